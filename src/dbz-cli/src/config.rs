@@ -0,0 +1,140 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+
+use crate::{Args, CharFormat, MapSType, OutputEncoding, Preset};
+
+/// Default options for `dbz` read from a TOML file. Options passed on the command
+/// line take precedence.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The encoding to use when neither `--json` nor `--csv` is passed and it can't
+    /// be inferred from the output file.
+    pub encoding: Option<OutputEncoding>,
+    pub pretty_json: Option<bool>,
+    pub json_array: Option<bool>,
+    pub map_stype: Option<MapSType>,
+    pub char_format: Option<CharFormat>,
+    pub fields: Option<Vec<String>>,
+    pub preset: Option<Preset>,
+    pub force: Option<bool>,
+    /// A template for the directory to write output files to when `--output` isn't
+    /// passed.
+    pub output_dir: Option<String>,
+}
+
+impl Config {
+    /// Loads the config from `path` if it's `Some`, otherwise from the default config
+    /// path if a file exists there.
+    ///
+    /// # Errors
+    /// This function returns an error if the file can't be read or isn't a valid config.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Unable to read config file '{}'", path.display()))?;
+        toml::from_str(&contents)
+            .map_err(|e| anyhow!(e))
+            .with_context(|| format!("Invalid config file '{}'", path.display()))
+    }
+}
+
+/// Returns `$XDG_CONFIG_HOME/dbz/config.toml`, falling back to
+/// `~/.config/dbz/config.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("dbz").join("config.toml"))
+}
+
+impl Args {
+    /// Fills in options that weren't passed on the command line from `config`.
+    pub fn apply_config(&mut self, config: Config) {
+        if !self.json && !self.csv && self.output.is_none() {
+            match config.encoding {
+                Some(OutputEncoding::Csv) => self.csv = true,
+                Some(OutputEncoding::Json) => self.json = true,
+                Some(OutputEncoding::Infer) | None => {}
+            }
+        }
+        self.should_pretty_print |= config.pretty_json.unwrap_or_default();
+        // `--csv` conflicts with `--json-array`
+        self.should_output_array |= !self.csv && config.json_array.unwrap_or_default();
+        self.force |= config.force.unwrap_or_default();
+        self.map_stype = self.map_stype.or(config.map_stype);
+        self.char_format = self.char_format.or(config.char_format);
+        if self.fields.is_none() && self.preset.is_none() {
+            self.fields = config.fields;
+            self.preset = config.preset;
+        }
+        self.output_dir = self.output_dir.take().or(config.output_dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config: Config = toml::from_str(
+            r#"
+encoding = "json"
+pretty_json = true
+map_stype = "native"
+char_format = "char"
+preset = "trade-tape"
+output_dir = "out/{dataset}/{schema}"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                encoding: Some(OutputEncoding::Json),
+                pretty_json: Some(true),
+                map_stype: Some(MapSType::Native),
+                char_format: Some(CharFormat::Char),
+                preset: Some(Preset::TradeTape),
+                output_dir: Some("out/{dataset}/{schema}".to_owned()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        assert!(toml::from_str::<Config>("compression = 3").is_err());
+    }
+
+    #[test]
+    fn test_args_take_precedence() {
+        let mut args = Args::parse_from(["dbz", "a.dbz", "--csv", "--fields", "price"]);
+        args.apply_config(Config {
+            encoding: Some(OutputEncoding::Json),
+            json_array: Some(true),
+            preset: Some(Preset::Quote),
+            ..Default::default()
+        });
+        assert!(args.csv);
+        assert!(!args.json);
+        assert!(!args.should_output_array);
+        assert_eq!(args.fields, Some(vec!["price".to_owned()]));
+        assert_eq!(args.preset, None);
+    }
+}
@@ -0,0 +1,46 @@
+use std::{io, path::PathBuf};
+
+use dbz_lib::{run_conformance_suite, suite_cases};
+
+/// Arguments of the `conformance` subcommand.
+#[derive(Debug, clap::Args)]
+pub struct ConformanceArgs {
+    #[clap(
+        help = "A conformance suite directory of fixture DBZ files and their expected output, like tests/conformance",
+        value_name = "DIR"
+    )]
+    pub dir: PathBuf,
+    #[clap(
+        long,
+        help = "Overwrite the expected output with this version's decoding of the fixtures instead of checking it"
+    )]
+    pub update: bool,
+}
+
+/// Runs the conformance suite in `args.dir` and writes a pass/fail line per case to
+/// `out`, or updates its expected output if `args.update` is set. Returns whether
+/// every case passed.
+pub fn write_conformance_suite(
+    args: &ConformanceArgs,
+    mut out: impl io::Write,
+) -> anyhow::Result<bool> {
+    if args.update {
+        for case in suite_cases(&args.dir)? {
+            case.write_expected()?;
+            writeln!(out, "UPDATED {}", case.name)?;
+        }
+        out.flush()?;
+        return Ok(true);
+    }
+    let results = run_conformance_suite(&args.dir)?;
+    let pass_count = results.iter().filter(|result| result.is_pass()).count();
+    for result in results.iter() {
+        match &result.failure {
+            None => writeln!(out, "PASS {}", result.name)?,
+            Some(failure) => writeln!(out, "FAIL {}: {failure}", result.name)?,
+        }
+    }
+    writeln!(out, "{pass_count} of {} cases passed", results.len())?;
+    out.flush()?;
+    Ok(pass_count == results.len())
+}
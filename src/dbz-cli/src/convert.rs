@@ -0,0 +1,131 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use dbz_lib::{Dbz, OutputEncoding, OutputFile, Schema, TextRecordReader, WriterOptions};
+use flate2::read::GzDecoder;
+
+use crate::{open_output_file, Output, OutputCompression};
+
+/// Arguments of the `convert` subcommand.
+#[derive(Debug, clap::Args)]
+pub struct ConvertArgs {
+    #[clap(
+        help = "The file to convert: DBZ, or CSV or JSON records of --schema as output by dbz",
+        value_name = "INPUT"
+    )]
+    pub input: PathBuf,
+    #[clap(
+        help = "The file to write, whose format is inferred from its extension: .dbz, .csv, or .json. CSV and JSON files ending in .zst or .gz are compressed",
+        value_name = "OUTPUT"
+    )]
+    pub output: PathBuf,
+    #[clap(
+        long,
+        value_name = "SCHEMA",
+        help = "The schema of the records of a CSV or JSON INPUT, e.g. trades"
+    )]
+    pub schema: Option<Schema>,
+    #[clap(
+        long,
+        value_name = "DATASET",
+        default_value = "",
+        help = "The dataset of the metadata when converting CSV or JSON to DBZ, e.g. GLBX.MDP3"
+    )]
+    pub dataset: String,
+    #[clap(
+        short,
+        long,
+        help = "Allow overwriting of existing files, such as the output file"
+    )]
+    pub force: bool,
+}
+
+/// A file format `convert` reads and writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Dbz,
+    Csv,
+    Json,
+}
+
+impl Format {
+    /// Infers the format of `path` from its extension, ignoring any compression's.
+    fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let stem = match OutputCompression::from_path(path) {
+            OutputCompression::None => path,
+            _ => Path::new(path.file_stem().unwrap_or_default()),
+        };
+        match stem.extension() {
+            Some(ext) if ext == "dbz" => Ok(Format::Dbz),
+            Some(ext) if ext == "csv" => Ok(Format::Csv),
+            Some(ext) if ext == "json" => Ok(Format::Json),
+            _ => Err(anyhow!(
+                "Unable to infer the format of '{}' from its extension",
+                path.display()
+            )),
+        }
+    }
+}
+
+/// Converts `args.input` to the format of `args.output`: DBZ to CSV or JSON, CSV or
+/// JSON to DBZ, or DBZ to DBZ, re-encoding the records.
+pub fn write_convert(args: &ConvertArgs) -> anyhow::Result<()> {
+    let input_format = Format::from_path(&args.input)?;
+    let output_format = Format::from_path(&args.output)?;
+    if input_format == Format::Dbz {
+        if args.schema.is_some() {
+            return Err(anyhow!("--schema only applies to CSV or JSON input"));
+        }
+        let dbz = Dbz::from_file(&args.input)?;
+        let file = open_output_file(&args.output, args.force)?;
+        let encoding = match output_format {
+            Format::Dbz => return dbz.write_dbz_to(BufWriter::new(file)).map(drop),
+            Format::Csv => OutputEncoding::Csv,
+            Format::Json => OutputEncoding::Json {
+                should_pretty_print: false,
+                should_output_array: false,
+                should_output_iso_timestamps: false,
+                should_output_decimal_prices: false,
+            },
+        };
+        let file = OutputFile::new(file, &WriterOptions::default());
+        let mut output = Output::from_file(file, OutputCompression::from_path(&args.output))?;
+        dbz.write_to(&mut output, encoding)?;
+        return Ok(output.finish()?);
+    }
+    if output_format != Format::Dbz {
+        return Err(anyhow!(
+            "Converting between CSV and JSON isn't supported, convert to DBZ instead"
+        ));
+    }
+    let schema = args
+        .schema
+        .ok_or_else(|| anyhow!("--schema is required to convert CSV or JSON"))?;
+    let input = open_text_input(&args.input)?;
+    let records = if input_format == Format::Csv {
+        TextRecordReader::csv(input, schema)?
+    } else {
+        TextRecordReader::json(input, schema)?
+    };
+    let file = open_output_file(&args.output, args.force)?;
+    records
+        .write_dbz_to(BufWriter::new(file), &args.dataset)
+        .with_context(|| format!("Failed to convert '{}'", args.input.display()))?;
+    Ok(())
+}
+
+/// Opens the CSV or JSON file at `path`, decompressing it according to its extension.
+fn open_text_input(path: &Path) -> anyhow::Result<Box<dyn io::Read>> {
+    let file = File::open(path)
+        .with_context(|| format!("Unable to open input file '{}'", path.display()))?;
+    let reader = BufReader::new(file);
+    Ok(match OutputCompression::from_path(path) {
+        OutputCompression::None => Box::new(reader),
+        OutputCompression::Zstd => Box::new(zstd::Decoder::with_buffer(reader)?),
+        OutputCompression::Gzip => Box::new(GzDecoder::new(reader)),
+    })
+}
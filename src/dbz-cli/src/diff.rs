@@ -0,0 +1,72 @@
+use std::{io, path::PathBuf};
+
+use dbz_lib::{Dbz, RecordComparator, RecordDiff};
+
+/// Arguments of the `diff` subcommand.
+#[derive(Debug, clap::Args)]
+pub struct DiffArgs {
+    #[clap(help = "The DBZ file to compare against", value_name = "LEFT")]
+    pub left: PathBuf,
+    #[clap(help = "The DBZ file to compare", value_name = "RIGHT")]
+    pub right: PathBuf,
+    #[clap(
+        long,
+        value_name = "FIELD",
+        value_delimiter = ',',
+        help = "Don't compare the comma-separated FIELDs, e.g. ts_in_delta,sequence. Book level fields are suffixed with their index like bid_px_00"
+    )]
+    pub ignore: Vec<String>,
+    #[clap(long, help = "Output each difference as a JSON object")]
+    pub json: bool,
+}
+
+/// Compares the records of the files in `args` in order and writes the differences
+/// to `out`. Returns whether the records matched.
+pub fn write_diff(args: &DiffArgs, mut out: impl io::Write) -> anyhow::Result<bool> {
+    let comparator = args
+        .ignore
+        .iter()
+        .fold(RecordComparator::new(), |comparator, field| {
+            comparator.ignore(field)
+        });
+    let left = Dbz::from_file(&args.left)?.into_record_iter()?;
+    let right = Dbz::from_file(&args.right)?.into_record_iter()?;
+    let mut is_match = true;
+    for diff in comparator.diff_iters(left, right) {
+        let diff = diff?;
+        is_match = false;
+        if args.json {
+            serde_json::to_writer(&mut out, &diff)?;
+            writeln!(out)?;
+        } else {
+            write_record_diff(&diff, args, &mut out)?;
+        }
+    }
+    if is_match && !args.json {
+        writeln!(out, "No differences found")?;
+    }
+    out.flush()?;
+    Ok(is_match)
+}
+
+fn write_record_diff(
+    diff: &RecordDiff,
+    args: &DiffArgs,
+    out: &mut impl io::Write,
+) -> io::Result<()> {
+    match diff {
+        RecordDiff::Changed { index, fields } => {
+            writeln!(out, "record {index}:")?;
+            for field in fields {
+                writeln!(out, "  {}: {} != {}", field.field, field.left, field.right)?;
+            }
+            Ok(())
+        }
+        RecordDiff::LeftOnly { index } => {
+            writeln!(out, "record {index}: only in {}", args.left.display())
+        }
+        RecordDiff::RightOnly { index } => {
+            writeln!(out, "record {index}: only in {}", args.right.display())
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use dbz_lib::{Dbz, Metadata, Schema};
+
+/// Arguments of the `doctor` subcommand.
+#[derive(Debug, clap::Args)]
+pub struct DoctorArgs {
+    #[clap(help = "The DBZ file to diagnose", value_name = "FILE")]
+    pub input: PathBuf,
+}
+
+/// The outcome of one of the checks of `dbz doctor`.
+struct Check {
+    name: &'static str,
+    /// A summary of what was found if the check passed, otherwise the problem and
+    /// a suggested fix.
+    result: Result<String, Problem>,
+}
+
+struct Problem {
+    message: String,
+    fix: &'static str,
+}
+
+impl Check {
+    fn pass(name: &'static str, summary: String) -> Self {
+        Self {
+            name,
+            result: Ok(summary),
+        }
+    }
+
+    fn fail(name: &'static str, message: String, fix: &'static str) -> Self {
+        Self {
+            name,
+            result: Err(Problem { message, fix }),
+        }
+    }
+}
+
+const REDOWNLOAD_FIX: &str = "The file is likely truncated or corrupted. Download it again";
+const REENCODE_FIX: &str =
+    "Re-encode the records with `DbzWriter`, which fills in the metadata from the records";
+
+/// Runs the diagnostic checks on `args.input` and writes a pass/fail report to
+/// `out`. Returns whether every check passed.
+pub fn write_doctor(args: &DoctorArgs, mut out: impl io::Write) -> anyhow::Result<bool> {
+    let checks = diagnose(&args.input);
+    let pass_count = checks.iter().filter(|check| check.result.is_ok()).count();
+    for check in checks.iter() {
+        match &check.result {
+            Ok(summary) => writeln!(out, "PASS {}: {summary}", check.name)?,
+            Err(problem) => {
+                writeln!(out, "FAIL {}: {}", check.name, problem.message)?;
+                writeln!(out, "  fix: {}", problem.fix)?;
+            }
+        }
+    }
+    writeln!(out, "{pass_count} of {} checks passed", checks.len())?;
+    out.flush()?;
+    Ok(pass_count == checks.len())
+}
+
+fn diagnose(path: &Path) -> Vec<Check> {
+    let metadata = match Metadata::from_file(path) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            // nothing else can be checked without the metadata
+            return vec![Check::fail(
+                "metadata",
+                format!("couldn't read the metadata: {e:#}"),
+                "Check that this is a DBZ file. If it is, it's likely truncated or corrupted. Download it again",
+            )];
+        }
+    };
+    let mut checks = vec![
+        Check::pass(
+            "metadata",
+            format!(
+                "version {}, dataset {}, schema {}",
+                metadata.version, metadata.dataset, metadata.schema
+            ),
+        ),
+        check_metadata_values(&metadata),
+    ];
+    let records = RecordSummary::decode(path);
+    checks.push(check_checksum(path, &records));
+    checks.push(match &records.error {
+        None => Check::pass("records", records.describe()),
+        Some(e) => Check::fail(
+            "records",
+            format!("failed to decode record {}: {e}", records.count),
+            REDOWNLOAD_FIX,
+        ),
+    });
+    // the remaining checks compare the records to the metadata
+    if records.error.is_none() {
+        checks.push(check_record_count(&metadata, &records));
+        checks.push(check_record_range(&metadata, &records));
+    }
+    checks
+}
+
+fn check_metadata_values(metadata: &Metadata) -> Check {
+    const NAME: &str = "metadata values";
+    if metadata.schema == Schema::Statistics {
+        Check::fail(
+            NAME,
+            format!("records of schema {} can't be decoded", metadata.schema),
+            "Upgrade dbz to a version that supports the schema",
+        )
+    } else if metadata.start > metadata.end {
+        Check::fail(
+            NAME,
+            format!("start {} is after end {}", metadata.start, metadata.end),
+            REENCODE_FIX,
+        )
+    } else if let Some(mapping) = metadata.mappings.iter().find(|mapping| {
+        mapping
+            .intervals
+            .iter()
+            .any(|interval| interval.start_date > interval.end_date)
+    }) {
+        Check::fail(
+            NAME,
+            format!(
+                "symbol mapping of {} has an interval that ends before it starts",
+                mapping.native
+            ),
+            "Regenerate the file's symbology from the original request",
+        )
+    } else {
+        Check::pass(
+            NAME,
+            format!(
+                "{} to {}, {} symbol mappings",
+                metadata.start,
+                metadata.end,
+                metadata.mappings.len()
+            ),
+        )
+    }
+}
+
+/// Returns the check of the Zstd content checksum of the records, which is verified
+/// while decoding them.
+fn check_checksum(path: &Path, records: &RecordSummary) -> Check {
+    const NAME: &str = "checksum";
+    match has_content_checksum(path) {
+        Ok(false) => Check::pass(NAME, "no checksum to verify".to_owned()),
+        Ok(true) => match &records.error {
+            None => Check::pass(NAME, "verified".to_owned()),
+            Some(e) if e.contains("checksum") => Check::fail(
+                NAME,
+                "the records don't match their checksum".to_owned(),
+                REDOWNLOAD_FIX,
+            ),
+            Some(_) => Check::fail(
+                NAME,
+                "couldn't be verified because the records couldn't be decoded".to_owned(),
+                REDOWNLOAD_FIX,
+            ),
+        },
+        Err(e) => Check::fail(
+            NAME,
+            format!("couldn't read the Zstd frame header: {e}"),
+            REDOWNLOAD_FIX,
+        ),
+    }
+}
+
+/// Returns whether the Zstd frame of the records, which follows the metadata's
+/// skippable frame, includes a content checksum.
+fn has_content_checksum(path: &Path) -> io::Result<bool> {
+    /// Bit of the frame header descriptor set when the frame has a checksum
+    const CONTENT_CHECKSUM_FLAG: u8 = 1 << 2;
+
+    let mut file = File::open(path)?;
+    let mut buffer = [0; 4];
+    file.seek(SeekFrom::Start(4))?;
+    file.read_exact(&mut buffer)?;
+    let metadata_size = u32::from_le_bytes(buffer);
+    // skip the metadata and the magic number of the records' frame
+    file.seek(SeekFrom::Start(8 + metadata_size as u64 + 4))?;
+    let mut descriptor = [0; 1];
+    file.read_exact(&mut descriptor)?;
+    Ok(descriptor[0] & CONTENT_CHECKSUM_FLAG != 0)
+}
+
+fn check_record_count(metadata: &Metadata, records: &RecordSummary) -> Check {
+    const NAME: &str = "record count";
+    if metadata.record_count == records.count {
+        Check::pass(NAME, format!("matches the metadata's {}", records.count))
+    } else {
+        Check::fail(
+            NAME,
+            format!(
+                "decoded {} records but the metadata has {}",
+                records.count, metadata.record_count
+            ),
+            REENCODE_FIX,
+        )
+    }
+}
+
+fn check_record_range(metadata: &Metadata, records: &RecordSummary) -> Check {
+    const NAME: &str = "record range";
+    let Some((first, last)) = records.ts_event_bounds else {
+        return Check::pass(NAME, "no records".to_owned());
+    };
+    if first < metadata.start || last >= metadata.end {
+        Check::fail(
+            NAME,
+            format!(
+                "records from {first} to {last} extend past the metadata's {} to {}",
+                metadata.start, metadata.end
+            ),
+            REENCODE_FIX,
+        )
+    } else {
+        Check::pass(NAME, "within the metadata's range".to_owned())
+    }
+}
+
+/// What was found decoding the records of a file.
+#[derive(Default)]
+struct RecordSummary {
+    /// The number of records decoded before any error.
+    count: u64,
+    /// The `ts_event` of the first and last records.
+    first_last: Option<(u64, u64)>,
+    /// The lowest and highest `ts_event` of the records.
+    ts_event_bounds: Option<(u64, u64)>,
+    error: Option<String>,
+}
+
+impl RecordSummary {
+    fn decode(path: &Path) -> Self {
+        let mut summary = Self::default();
+        let records = match Dbz::from_file(path).and_then(Dbz::into_record_iter) {
+            Ok(records) => records,
+            Err(e) => {
+                summary.error = Some(format!("{e:#}"));
+                return summary;
+            }
+        };
+        for record in records {
+            let ts_event = match record {
+                Ok(record) => record.header().ts_event,
+                Err(e) => {
+                    summary.error = Some(format!("{e:#}"));
+                    break;
+                }
+            };
+            summary.count += 1;
+            summary.first_last = Some(match summary.first_last {
+                Some((first, _)) => (first, ts_event),
+                None => (ts_event, ts_event),
+            });
+            summary.ts_event_bounds = Some(match summary.ts_event_bounds {
+                Some((low, high)) => (low.min(ts_event), high.max(ts_event)),
+                None => (ts_event, ts_event),
+            });
+        }
+        summary
+    }
+
+    fn describe(&self) -> String {
+        match self.first_last {
+            Some((first, last)) => format!(
+                "decoded {} records, first ts_event {first}, last ts_event {last}",
+                self.count
+            ),
+            None => "decoded 0 records".to_owned(),
+        }
+    }
+}
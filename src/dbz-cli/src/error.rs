@@ -0,0 +1,172 @@
+use std::{io, path::PathBuf};
+
+use dbz_lib::Error;
+use serde::Serialize;
+
+/// A category of failure, included in `--json-errors` output so callers can tell
+/// failures apart without parsing the message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The command-line arguments or config file are invalid
+    InvalidArgument,
+    /// The input file doesn't exist
+    InputNotFound,
+    /// The input isn't a valid DBZ file
+    CorruptInput,
+    /// The requested output isn't available for the input's schema
+    UnsupportedSchema,
+    /// The output file exists and `--force` wasn't passed
+    OutputExists,
+    /// The output device ran out of space
+    DiskFull,
+    /// Any other I/O error
+    Io,
+    /// `dbz validate` or `dbz doctor` found problems with the input or `dbz diff`
+    /// found differences
+    ValidationFailed,
+}
+
+impl ErrorCode {
+    /// Returns the [`ErrorCode`] for an I/O error in the chain of `error`, if there
+    /// is one.
+    fn from_io(error: &anyhow::Error) -> Option<Self> {
+        let io_error = error.chain().find_map(|e| e.downcast_ref::<io::Error>())?;
+        Some(match io_error.kind() {
+            io::ErrorKind::NotFound => Self::InputNotFound,
+            io::ErrorKind::StorageFull => Self::DiskFull,
+            io::ErrorKind::AlreadyExists => Self::OutputExists,
+            _ => Self::Io,
+        })
+    }
+}
+
+/// An error from running `dbz` along with its category and the file it relates to.
+#[derive(Debug)]
+pub struct CliError {
+    pub code: ErrorCode,
+    pub file: Option<PathBuf>,
+    /// The offset in bytes of the record that couldn't be decoded in the decompressed
+    /// records of `file`, if known.
+    pub byte_offset: Option<u64>,
+    /// The index of the record that couldn't be decoded, if known.
+    pub record_index: Option<u64>,
+    pub source: anyhow::Error,
+}
+
+impl CliError {
+    /// Creates an error, taking the position of the record that couldn't be decoded
+    /// from `source` if it has one.
+    pub fn new(code: ErrorCode, source: impl Into<anyhow::Error>) -> Self {
+        let source = source.into();
+        let (byte_offset, record_index) = match Error::find(&source) {
+            Some(Error::Decode {
+                byte_offset,
+                record_index,
+                ..
+            }) => (Some(*byte_offset), Some(*record_index)),
+            _ => (None, None),
+        };
+        Self {
+            code,
+            file: None,
+            byte_offset,
+            record_index,
+            source,
+        }
+    }
+
+    /// Creates an error for a failure to read the input, classifying it as
+    /// [`ErrorCode::CorruptInput`] unless it was caused by an I/O error other
+    /// than the input ending early.
+    pub fn reading(source: anyhow::Error) -> Self {
+        let code = match source
+            .chain()
+            .find_map(|e| e.downcast_ref::<io::Error>())
+            .map(io::Error::kind)
+        {
+            None | Some(io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData) => {
+                ErrorCode::CorruptInput
+            }
+            Some(_) => ErrorCode::from_io(&source).unwrap_or(ErrorCode::Io),
+        };
+        Self::new(code, source)
+    }
+
+    /// Creates an error for a failure to write the output, classifying it as
+    /// [`ErrorCode::CorruptInput`] if a record of the input couldn't be decoded, as an
+    /// I/O error if it was caused by one, and otherwise as `otherwise`.
+    pub fn writing(source: anyhow::Error, otherwise: ErrorCode) -> Self {
+        if let Some(Error::Decode { .. }) = Error::find(&source) {
+            return Self::new(ErrorCode::CorruptInput, source);
+        }
+        let code = match ErrorCode::from_io(&source) {
+            // the input is already open, so the output path must be missing
+            Some(ErrorCode::InputNotFound) => ErrorCode::Io,
+            Some(code) => code,
+            None => otherwise,
+        };
+        Self::new(code, source)
+    }
+
+    pub fn with_file(mut self, file: impl Into<PathBuf>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    /// Writes the error as a single-line JSON object to `writer`.
+    pub fn write_json(&self, mut writer: impl io::Write) -> io::Result<()> {
+        #[derive(Serialize)]
+        struct JsonError {
+            code: ErrorCode,
+            message: String,
+            file: Option<String>,
+            byte_offset: Option<u64>,
+            record_index: Option<u64>,
+        }
+        let json_error = JsonError {
+            code: self.code,
+            message: format!("{:#}", self.source),
+            file: self.file.as_ref().map(|f| f.display().to_string()),
+            byte_offset: self.byte_offset,
+            record_index: self.record_index,
+        };
+        serde_json::to_writer(&mut writer, &json_error)?;
+        writeln!(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    #[test]
+    fn test_write_json() {
+        let error = CliError::reading(
+            anyhow::Error::new(io::Error::from(io::ErrorKind::UnexpectedEof))
+                .context("Failed to read metadata prelude"),
+        )
+        .with_file("a.dbz");
+        let mut buffer = Vec::new();
+        error.write_json(&mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "{\"code\":\"corrupt_input\",\"message\":\"Failed to read metadata prelude: unexpected end of file\",\"file\":\"a.dbz\",\"byte_offset\":null,\"record_index\":null}\n"
+        );
+    }
+
+    #[test]
+    fn test_classify_write_errors() {
+        let disk_full = anyhow::Error::new(io::Error::from(io::ErrorKind::StorageFull));
+        assert_eq!(
+            CliError::writing(disk_full, ErrorCode::InvalidArgument).code,
+            ErrorCode::DiskFull
+        );
+        assert_eq!(
+            CliError::writing(anyhow!("Unknown column"), ErrorCode::InvalidArgument).code,
+            ErrorCode::InvalidArgument
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use std::{io, path::PathBuf};
+
+use dbz_lib::{Dbz, FieldMatcher};
+
+/// Arguments of the `grep` subcommand.
+#[derive(Debug, clap::Args)]
+#[clap(group(clap::ArgGroup::new("condition").required(true).multiple(true)))]
+pub struct GrepArgs {
+    #[clap(
+        help = "A DBZ file to search. Pass '-' to read from standard input",
+        value_name = "FILE"
+    )]
+    pub input: PathBuf,
+    #[clap(
+        long,
+        value_name = "NAME",
+        help = "The field to match, named as in the CSV output, e.g. order_id or bid_px_00"
+    )]
+    pub field: String,
+    #[clap(
+        long,
+        group = "condition",
+        value_name = "VALUE",
+        help = "Output the records whose field equals VALUE. Prices are in units of 1e-9 and timestamps in UNIX nanoseconds"
+    )]
+    pub eq: Option<String>,
+    #[clap(
+        long,
+        group = "condition",
+        value_name = "N",
+        allow_hyphen_values = true,
+        help = "Output the records whose numeric field is greater than or equal to N"
+    )]
+    pub ge: Option<i128>,
+    #[clap(
+        long,
+        group = "condition",
+        value_name = "N",
+        allow_hyphen_values = true,
+        help = "Output the records whose numeric field is less than or equal to N"
+    )]
+    pub le: Option<i128>,
+}
+
+impl GrepArgs {
+    /// Returns the condition on records described by the arguments.
+    pub fn matcher(&self) -> FieldMatcher {
+        let mut matcher = FieldMatcher::new(&self.field);
+        if let Some(eq) = &self.eq {
+            matcher = matcher.eq(eq);
+        }
+        if let Some(ge) = self.ge {
+            matcher = matcher.ge(ge);
+        }
+        if let Some(le) = self.le {
+            matcher = matcher.le(le);
+        }
+        matcher
+    }
+}
+
+/// Writes the records of `dbz` matching `args` to `out` as JSON with their indices.
+/// Returns the number of matching records.
+pub fn write_grep<R: io::BufRead>(
+    dbz: Dbz<R>,
+    args: &GrepArgs,
+    out: impl io::Write,
+) -> anyhow::Result<u64> {
+    dbz.write_matches_to(out, &args.matcher())
+}
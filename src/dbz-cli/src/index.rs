@@ -0,0 +1,38 @@
+use std::{io::BufWriter, path::PathBuf};
+
+use dbz_lib::RecordIndex;
+
+use crate::open_output_file;
+
+/// Arguments of the `index` subcommand.
+#[derive(Debug, clap::Args)]
+pub struct IndexArgs {
+    #[clap(help = "The DBZ file to index", value_name = "FILE")]
+    pub input: PathBuf,
+    #[clap(
+        short,
+        long,
+        value_name = "FILE",
+        help = "Saves the index to FILE instead of next to the DBZ file with an .idx extension added, where readers look for it"
+    )]
+    pub output: Option<PathBuf>,
+    #[clap(
+        short,
+        long,
+        help = "Allow overwriting of existing files, such as the output file"
+    )]
+    pub force: bool,
+}
+
+/// Builds the record index of `args.input` and saves it. Returns the number of
+/// records indexed.
+pub fn write_index(args: &IndexArgs) -> anyhow::Result<usize> {
+    let index = RecordIndex::build(&args.input)?;
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| RecordIndex::path_for(&args.input));
+    let file = open_output_file(&output, args.force)?;
+    index.encode(BufWriter::new(file))?;
+    Ok(index.len())
+}
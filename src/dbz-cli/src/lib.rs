@@ -1,13 +1,41 @@
 use std::{
     fs::File,
-    io::{self, BufWriter},
-    path::PathBuf,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use anyhow::{anyhow, Context};
-use clap::{ArgAction, Parser, ValueEnum};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use dbz_lib::{
+    AdjustmentTable, ColumnPreset, Dbz, FanoutOutput, Metadata, OutputFile, PipelineOptions, SType,
+    Schema, UndefPrice, WriterOptions,
+};
+use flate2::{write::GzEncoder, Compression};
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+pub mod config;
+pub mod conformance;
+pub mod convert;
+pub mod diff;
+pub mod doctor;
+pub mod error;
+pub mod grep;
+pub mod index;
+pub mod manifest;
+pub mod merge;
+pub mod metadata;
+pub mod sort;
+pub mod spec;
+pub mod split;
+pub mod stats;
+pub mod top;
+pub mod validate;
+pub mod verify;
 
-#[derive(Clone, Copy, Debug, ValueEnum)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum OutputEncoding {
     /// `dbz` will infer based on the extension of the specified output file
     Infer,
@@ -15,18 +43,115 @@ pub enum OutputEncoding {
     Json,
 }
 
+/// A symbology type product IDs can be mapped to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MapSType {
+    /// Add a `symbol` field with the native symbol of each record
+    Native,
+    /// Leave records keyed by product ID
+    #[clap(name = "product_id")]
+    ProductId,
+}
+
+/// How single-character fields like `action` and `side` are output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CharFormat {
+    /// The integer value of the character, e.g. 65
+    Int,
+    /// The character itself, e.g. A
+    Char,
+}
+
+impl From<CharFormat> for dbz_lib::CharFormat {
+    fn from(format: CharFormat) -> Self {
+        match format {
+            CharFormat::Int => dbz_lib::CharFormat::Int,
+            CharFormat::Char => dbz_lib::CharFormat::Char,
+        }
+    }
+}
+
+/// A curated selection of columns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// Timestamps, product ID, and the best bid and offer
+    Quote,
+    /// Timestamps, product ID, and the price, size, and side of each trade
+    TradeTape,
+    /// All fields except `rtype` and `publisher_id`
+    Research,
+}
+
+impl From<Preset> for ColumnPreset {
+    fn from(preset: Preset) -> Self {
+        match preset {
+            Preset::Quote => ColumnPreset::Quote,
+            Preset::TradeTape => ColumnPreset::TradeTape,
+            Preset::Research => ColumnPreset::Research,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Convert a DBZ file to CSV or JSON, or CSV or JSON records to DBZ
+    Convert(convert::ConvertArgs),
+    /// Output the metadata of a DBZ file as JSON without decoding its records
+    Metadata(metadata::MetadataArgs),
+    /// Summarize the records in a DBZ file
+    Stats(stats::StatsArgs),
+    /// Check the records in a DBZ file for problems
+    Validate(validate::ValidateArgs),
+    /// Find the most active products in a DBZ file
+    Top(top::TopArgs),
+    /// Build and read manifests of collections of DBZ files
+    Manifest(manifest::ManifestArgs),
+    /// Compare the records of two DBZ files
+    Diff(diff::DiffArgs),
+    /// Merge DBZ files of the same dataset and schema into one in timestamp order
+    Merge(merge::MergeArgs),
+    /// Sort the records of a DBZ file, including files larger than memory
+    Sort(sort::SortArgs),
+    /// Split a DBZ file into several files by day, hour, record count, or symbol
+    Split(split::SplitArgs),
+    /// Index the positions of the records in a DBZ file for random access
+    Index(index::IndexArgs),
+    /// Output the records of a DBZ file with a field of a given value as JSON
+    Grep(grep::GrepArgs),
+    /// Diagnose problems with a DBZ file
+    Doctor(doctor::DoctorArgs),
+    /// Check that this version decodes a conformance suite of fixture files as expected
+    Conformance(conformance::ConformanceArgs),
+    /// Output the byte-level layout of the DBZ format as JSON or a C header
+    Spec(spec::SpecArgs),
+    /// Check the records of a DBZ file against its checksum to detect truncation or
+    /// corruption
+    Verify(verify::VerifyArgs),
+}
+
 #[derive(Debug, Parser)]
-#[clap(version, about)]
+#[clap(
+    version,
+    about,
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true
+)]
 pub struct Args {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
     #[clap(
         help = "A DBZ file to convert to another encoding. Pass '-' to read from standard input",
-        value_name = "FILE"
+        value_name = "FILE",
+        required = true
     )]
-    pub input: PathBuf,
+    pub input: Option<PathBuf>,
     #[clap(
         short,
         long,
-        help = "Saves the result to FILE. If no path is specified, the output will be written to standard output",
+        help = "Saves the result to FILE. If no path is specified, the output will be written to standard output. FILE may contain {dataset}, {schema}, {date}, and {symbol}, which are replaced with values from the metadata",
         value_name = "FILE"
     )]
     pub output: Option<PathBuf>,
@@ -71,9 +196,210 @@ pub struct Args {
          help ="Make the JSON output easier to read with spacing and indentation"
     )]
     pub should_pretty_print: bool,
+    #[clap(
+        long = "json-array",
+        action = ArgAction::SetTrue,
+        default_value = "false",
+        conflicts_with = "csv",
+        help = "Output JSON as a single array instead of one object per line"
+    )]
+    pub should_output_array: bool,
+    #[clap(
+        long = "iso-timestamps",
+        action = ArgAction::SetTrue,
+        default_value = "false",
+        conflicts_with = "csv",
+        help = "Output timestamps in JSON as ISO 8601 strings instead of UNIX nanoseconds"
+    )]
+    pub should_output_iso_timestamps: bool,
+    #[clap(
+        long = "decimal-prices",
+        action = ArgAction::SetTrue,
+        default_value = "false",
+        conflicts_with = "csv",
+        help = "Output prices in JSON as decimal strings instead of integers in units of 1e-9, with the display factor and tick size annotated in the metadata, if any"
+    )]
+    pub should_output_decimal_prices: bool,
+    #[clap(
+        long = "map-stype",
+        value_name = "STYPE",
+        value_enum,
+        help = "Map product IDs to STYPE using the mappings in the metadata. Also rewrites the metadata's `stype_out` when used with --metadata"
+    )]
+    pub map_stype: Option<MapSType>,
+    #[clap(
+        long,
+        value_name = "FIELD",
+        value_delimiter = ',',
+        help = "Only output the comma-separated FIELDs, in the given order"
+    )]
+    pub fields: Option<Vec<String>>,
+    #[clap(
+        long,
+        value_enum,
+        conflicts_with = "fields",
+        help = "Only output a curated selection of fields for the file's schema"
+    )]
+    pub preset: Option<Preset>,
+    #[clap(
+        long = "product-id",
+        value_name = "ID",
+        value_delimiter = ',',
+        conflicts_with = "should-output-metadata",
+        help = "Only output the records of the comma-separated product IDs. Can be passed multiple times"
+    )]
+    pub product_ids: Vec<u32>,
+    #[clap(
+        long,
+        value_name = "N",
+        conflicts_with_all = &["should-output-metadata", "dry-run"],
+        help = "Skip the first N records, counting only records of the --product-id options"
+    )]
+    pub skip: Option<u64>,
+    #[clap(
+        short = 'n',
+        long,
+        value_name = "N",
+        conflicts_with_all = &["should-output-metadata", "dry-run"],
+        help = "Output at most N records, after any skipped with --skip. The rest of the file isn't decompressed"
+    )]
+    pub limit: Option<u64>,
+    #[clap(
+        long,
+        value_name = "N",
+        conflicts_with_all = &["should-output-metadata", "skip", "raw-body"],
+        help = "Output only the last N records of FILE, counting records of every product. Only the Zstd frames holding them are decompressed when FILE has several frames or an index"
+    )]
+    pub tail: Option<u64>,
+    #[clap(
+        long = "skip-unknown-rtypes",
+        action = ArgAction::SetTrue,
+        default_value = "false",
+        help = "Leave out records of a different type than the schema's, e.g. of a newer record type, instead of failing at the first one"
+    )]
+    pub should_skip_unknown_rtypes: bool,
+    #[clap(
+        long = "dry-run",
+        action = ArgAction::SetTrue,
+        default_value = "false",
+        conflicts_with = "should-output-metadata",
+        help = "Estimate the size of the output and how long it will take to write by converting a sample of the records, without writing anything"
+    )]
+    pub dry_run: bool,
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Output only the top N book levels of MBP records"
+    )]
+    pub levels: Option<usize>,
+    #[clap(
+        long,
+        value_name = "ADJUSTMENTS",
+        help = "Adjust prices and sizes with the multipliers in the CSV file ADJUSTMENTS, which has the columns symbol, start_date, end_date, price_multiplier, and size_multiplier"
+    )]
+    pub adjustments: Option<PathBuf>,
+    #[clap(
+        long = "raw-undef-prices",
+        action = ArgAction::SetTrue,
+        default_value = "false",
+        help = "Output undefined prices as their raw sentinel value instead of null in JSON and empty in CSV"
+    )]
+    pub should_output_raw_undef_prices: bool,
+    #[clap(
+        long = "no-header",
+        action = ArgAction::SetTrue,
+        default_value = "false",
+        conflicts_with = "json",
+        help = "Leave out the header row of CSV output"
+    )]
+    pub should_omit_csv_header: bool,
+    #[clap(
+        long = "char-format",
+        value_name = "FORMAT",
+        value_enum,
+        help = "How to output single-character fields like action and side [default: int]"
+    )]
+    pub char_format: Option<CharFormat>,
+    #[clap(
+        long = "buffer-size",
+        value_name = "BYTES",
+        help = "Buffer up to BYTES of output before writing it to the output file [default: 8192]"
+    )]
+    pub buffer_size: Option<usize>,
+    #[clap(
+        long = "flush-every",
+        value_name = "N",
+        help = "Flush the output after every N records to bound how much is lost if dbz is interrupted"
+    )]
+    pub flush_interval: Option<usize>,
+    #[clap(
+        long,
+        action = ArgAction::SetTrue,
+        default_value = "false",
+        help = "Sync the output file to disk before exiting. Has no effect when writing to standard output"
+    )]
+    pub fsync: bool,
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Encode CSV and JSON output on N threads, with reading and decoding the input on threads of their own. 0 converts on a single thread [default: the number of CPUs minus 2, at least 1, or 0 with a single CPU]"
+    )]
+    pub threads: Option<usize>,
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = &["should-output-metadata", "dry-run"],
+        value_parser = parse_tee,
+        help = "Also write the records to FILE in the same pass, in the encoding of its extension, .csv, .json, or .dbz. Append ?product_id=IDS&start=NANOS&end=NANOS to only write the records of the comma-separated product IDs or with a ts_event in the range to FILE. Can be passed multiple times"
+    )]
+    pub tee: Vec<TeeArg>,
+    #[clap(
+        long = "output-dir",
+        value_name = "DIR",
+        conflicts_with = "output",
+        help = "Saves the result to a file named after FILE in DIR. DIR may contain the same placeholders as --output"
+    )]
+    pub output_dir: Option<String>,
+    #[clap(
+        long,
+        value_name = "CONFIG",
+        help = "Read default options from CONFIG instead of $XDG_CONFIG_HOME/dbz/config.toml or ~/.config/dbz/config.toml"
+    )]
+    pub config: Option<PathBuf>,
+    #[clap(
+        long = "raw-body",
+        action = ArgAction::SetTrue,
+        default_value = "false",
+        requires = "schema",
+        conflicts_with = "should-output-metadata",
+        help = "Read FILE as only the Zstd-compressed records without the metadata, e.g. to recover the records of a partially written file. Requires --schema"
+    )]
+    pub raw_body: bool,
+    #[clap(
+        long,
+        value_name = "SCHEMA",
+        requires = "raw-body",
+        help = "The schema of the records of a --raw-body FILE, e.g. trades"
+    )]
+    pub schema: Option<Schema>,
+    #[clap(
+        long = "json-errors",
+        action = ArgAction::SetTrue,
+        global = true,
+        help = "Write errors to standard error as JSON objects with an error code, the file, and the byte offset and record index where known"
+    )]
+    pub json_errors: bool,
 }
 
 impl Args {
+    /// Returns the path of the input file, which is required unless a subcommand is
+    /// passed.
+    pub fn input(&self) -> &Path {
+        self.input
+            .as_deref()
+            .expect("clap requires FILE without a subcommand")
+    }
+
     pub fn output_encoding(&self) -> OutputEncoding {
         match (self.json, self.csv) {
             (false, false) => OutputEncoding::Infer,
@@ -82,48 +408,507 @@ impl Args {
             (true, true) => unreachable!("Invalid state that clap conflicts_with should prevent"),
         }
     }
+
+    /// Returns the options of the conversion pipeline.
+    pub fn pipeline_options(&self) -> PipelineOptions {
+        let default = PipelineOptions::default();
+        PipelineOptions {
+            encoder_threads: self.threads.unwrap_or(default.encoder_threads),
+            ..default
+        }
+    }
+
+    pub fn output_options(&self, schema: Schema) -> anyhow::Result<dbz_lib::OutputOptions> {
+        let columns = match self.preset {
+            Some(preset) => {
+                let mut columns =
+                    ColumnPreset::from(preset).columns_with_levels(schema, self.levels)?;
+                if self.map_stype == Some(MapSType::Native) {
+                    columns.push("symbol".to_owned());
+                }
+                Some(columns)
+            }
+            None => self.fields.clone(),
+        };
+        Ok(dbz_lib::OutputOptions {
+            map_stype: self.map_stype.map(|stype| match stype {
+                MapSType::Native => SType::Native,
+                MapSType::ProductId => SType::ProductId,
+            }),
+            columns,
+            levels: self.levels,
+            adjuster: match &self.adjustments {
+                Some(path) => {
+                    let file = File::open(path).with_context(|| {
+                        format!("Unable to open adjustments file '{}'", path.display())
+                    })?;
+                    let table =
+                        AdjustmentTable::from_csv(io::BufReader::new(file)).with_context(|| {
+                            format!("Invalid adjustments file '{}'", path.display())
+                        })?;
+                    Some(Arc::new(table))
+                }
+                None => None,
+            },
+            undef_price: if self.should_output_raw_undef_prices {
+                UndefPrice::Raw
+            } else {
+                UndefPrice::Null
+            },
+            char_format: self.char_format.map(Into::into).unwrap_or_default(),
+            should_omit_csv_header: self.should_omit_csv_header,
+            writer: self.writer_options(),
+        })
+    }
+
+    /// Returns the options of output files and DBZ tees.
+    pub fn writer_options(&self) -> WriterOptions {
+        let default = WriterOptions::default();
+        WriterOptions {
+            buffer_size: self.buffer_size.unwrap_or(default.buffer_size),
+            flush_interval: self.flush_interval,
+            should_sync_on_finish: self.fsync,
+            ..default
+        }
+    }
+}
+
+/// Replaces the placeholders in `--output` with values from `metadata`, see
+/// [`expand_path_template`].
+///
+/// # Errors
+/// This function returns an error if a placeholder can't be filled from `metadata`.
+pub fn resolve_output_template(args: &mut Args, metadata: &Metadata) -> anyhow::Result<()> {
+    if let Some(output) = args.output.as_ref() {
+        let output = output.to_str().ok_or_else(|| {
+            anyhow!(
+                "Output path '{}' isn't valid UTF-8",
+                output.to_string_lossy()
+            )
+        })?;
+        args.output = Some(PathBuf::from(expand_path_template(output, metadata)?));
+    }
+    Ok(())
+}
+
+/// Resolves the path of the output file from `--output-dir`, if passed, by
+/// substituting the placeholders and appending the input file name with the
+/// extension of `encoding`.
+///
+/// # Errors
+/// This function returns an error if the input is standard input, a placeholder
+/// can't be filled from `metadata`, or the output directory can't be created. The
+/// directory isn't created for dry runs.
+pub fn resolve_output_dir(
+    args: &mut Args,
+    encoding: dbz_lib::OutputEncoding,
+    metadata: &Metadata,
+) -> anyhow::Result<()> {
+    let output_dir = match args.output_dir.as_ref() {
+        Some(output_dir) => output_dir,
+        None => return Ok(()),
+    };
+    let dir = PathBuf::from(expand_path_template(output_dir, metadata)?);
+    let stem = match args.input().file_stem() {
+        Some(stem) if args.input().as_os_str() != "-" => stem,
+        _ => {
+            return Err(anyhow!(
+                "Unable to name output file when reading from standard input. Pass --output instead"
+            ))
+        }
+    };
+    let extension = match encoding {
+        dbz_lib::OutputEncoding::Csv => "csv",
+        dbz_lib::OutputEncoding::Json { .. } => "json",
+    };
+    if !args.dry_run {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Unable to create output directory '{}'", dir.display()))?;
+    }
+    let mut file_name = stem.to_owned();
+    file_name.push(".");
+    file_name.push(extension);
+    args.output = Some(dir.join(file_name));
+    Ok(())
+}
+
+/// Replaces the placeholders in `template` with values from `metadata`:
+/// - `{dataset}` with the dataset
+/// - `{schema}` with the schema
+/// - `{date}` with the UTC date of `start` like 2020-12-28
+/// - `{symbol}` with the only symbol of the file
+///
+/// Values are sanitized so they can't introduce path separators or characters that
+/// are invalid in file names.
+///
+/// # Errors
+/// This function returns an error if `template` contains `{symbol}` and the file
+/// doesn't have exactly one symbol, or `{date}` and `start` is out of range.
+pub fn expand_path_template(template: &str, metadata: &Metadata) -> anyhow::Result<String> {
+    let mut path = template
+        .replace("{dataset}", &sanitize_file_name(&metadata.dataset))
+        .replace("{schema}", metadata.schema.as_str());
+    if path.contains("{date}") {
+        let date = OffsetDateTime::from_unix_timestamp_nanos(metadata.start as i128)
+            .with_context(|| format!("Unable to fill {{date}} from start {}", metadata.start))?
+            .date();
+        path = path.replace("{date}", &date.to_string());
+    }
+    if path.contains("{symbol}") {
+        let symbol = match metadata.symbols.as_slice() {
+            [symbol] => symbol,
+            symbols => {
+                return Err(anyhow!(
+                    "Unable to fill {{symbol}} because the file has {} symbols instead of one",
+                    symbols.len()
+                ))
+            }
+        };
+        path = path.replace("{symbol}", &sanitize_file_name(symbol));
+    }
+    Ok(path)
+}
+
+/// Replaces the characters of `value` that aren't safe in a file name, such as path
+/// separators and spaces, with underscores. Values of only dots are also replaced so
+/// they can't refer to a parent directory.
+fn sanitize_file_name(value: &str) -> String {
+    let sanitized: String = value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '+' | '=') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if sanitized.chars().all(|c| c == '.') {
+        "_".repeat(sanitized.len().max(1))
+    } else {
+        sanitized
+    }
 }
 
 pub fn infer_encoding(args: &Args) -> anyhow::Result<dbz_lib::OutputEncoding> {
     match args.output_encoding() {
         OutputEncoding::Csv => Ok(dbz_lib::OutputEncoding::Csv),
-        OutputEncoding::Json => Ok(dbz_lib::OutputEncoding::Json {
-            should_pretty_print: args.should_pretty_print,
-        }),
-        OutputEncoding::Infer => match args.output.as_ref().and_then(|o| o.extension()) {
-            Some(ext) if ext == "csv" => Ok(dbz_lib::OutputEncoding::Csv),
-            Some(ext) if ext == "json" => Ok(dbz_lib::OutputEncoding::Json {
-                should_pretty_print: args.should_pretty_print,
-            }),
-            Some(ext) => Err(anyhow!(
-                "Unable to infer output encoding from output file with extension '{}'",
-                ext.to_string_lossy()
-            )),
-            None => Err(anyhow!(
-                "Unable to infer output encoding from output file without an extension"
+        OutputEncoding::Json => Ok(json_encoding(args)),
+        OutputEncoding::Infer => infer_encoding_from_path(args, args.output.as_deref()),
+    }
+}
+
+fn json_encoding(args: &Args) -> dbz_lib::OutputEncoding {
+    dbz_lib::OutputEncoding::Json {
+        should_pretty_print: args.should_pretty_print,
+        should_output_array: args.should_output_array,
+        should_output_iso_timestamps: args.should_output_iso_timestamps,
+        should_output_decimal_prices: args.should_output_decimal_prices,
+    }
+}
+
+/// Infers the encoding of the output file `path` from its extension.
+fn infer_encoding_from_path(
+    args: &Args,
+    path: Option<&Path>,
+) -> anyhow::Result<dbz_lib::OutputEncoding> {
+    // the encoding's extension precedes any compression's, e.g. `.csv.zst`
+    let path = path.map(|path| match OutputCompression::from_path(path) {
+        OutputCompression::None => path,
+        _ => Path::new(path.file_stem().unwrap_or_default()),
+    });
+    match path.and_then(|o| o.extension()) {
+        Some(ext) if ext == "csv" => Ok(dbz_lib::OutputEncoding::Csv),
+        Some(ext) if ext == "json" => Ok(json_encoding(args)),
+        Some(ext) => Err(anyhow!(
+            "Unable to infer output encoding from output file with extension '{}'",
+            ext.to_string_lossy()
+        )),
+        None => Err(anyhow!(
+            "Unable to infer output encoding from output file without an extension"
+        )),
+    }
+}
+
+/// The number of records converted to estimate the output of a dry run.
+pub const DRY_RUN_SAMPLE_SIZE: usize = 10_000;
+
+/// Writes a summary of the conversion `args` describe to `out` along with the
+/// estimated size and duration of the output, without writing any output.
+pub fn write_dry_run<R: io::BufRead>(
+    dbz: Dbz<R>,
+    args: &Args,
+    encoding: dbz_lib::OutputEncoding,
+    options: &dbz_lib::OutputOptions,
+    mut out: impl io::Write,
+) -> anyhow::Result<()> {
+    let schema = dbz.schema();
+    let estimate = dbz.estimate_output(encoding, options, DRY_RUN_SAMPLE_SIZE)?;
+    writeln!(
+        out,
+        "Input:     {} ({}, {} records)",
+        args.input().display(),
+        schema.as_str(),
+        estimate.record_count
+    )?;
+    match &args.output {
+        Some(output) if output.exists() && !args.force => writeln!(
+            out,
+            "Output:    {} (exists, pass --force to overwrite)",
+            output.display()
+        )?,
+        Some(output) => writeln!(out, "Output:    {}", output.display())?,
+        None => writeln!(out, "Output:    standard output")?,
+    }
+    let encoding_name = match encoding {
+        dbz_lib::OutputEncoding::Csv => "CSV",
+        dbz_lib::OutputEncoding::Json {
+            should_output_array: true,
+            ..
+        } => "JSON array",
+        dbz_lib::OutputEncoding::Json { .. } => "NDJSON",
+    };
+    writeln!(out, "Encoding:  {encoding_name}")?;
+    writeln!(
+        out,
+        "Sample:    {} records, {} in {:.3}s",
+        estimate.sample_record_count,
+        format_size(estimate.sample_size),
+        estimate.sample_duration.as_secs_f64()
+    )?;
+    writeln!(
+        out,
+        "Estimate:  {} records, {} in {:.3}s",
+        estimate.output_record_count,
+        format_size(estimate.size),
+        estimate.duration.as_secs_f64()
+    )?;
+    Ok(())
+}
+
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{size} {}", UNITS[unit])
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+/// Where the converted output is written.
+pub enum Output {
+    File(OutputFile),
+    Zstd(zstd::Encoder<'static, OutputFile>),
+    Gzip(GzEncoder<OutputFile>),
+    Stdout(io::Stdout),
+}
+
+/// The compression of an output file, inferred from its extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputCompression {
+    None,
+    /// `.zst` or `.zstd`
+    Zstd,
+    /// `.gz`
+    Gzip,
+}
+
+impl OutputCompression {
+    /// Infers the compression of the output file `path` from its extension.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext == "zst" || ext == "zstd" => Self::Zstd,
+            Some(ext) if ext == "gz" => Self::Gzip,
+            _ => Self::None,
+        }
+    }
+}
+
+impl Output {
+    /// Wraps `file` in an encoder for `compression`, if any.
+    pub fn from_file(file: OutputFile, compression: OutputCompression) -> io::Result<Self> {
+        Ok(match compression {
+            OutputCompression::None => Output::File(file),
+            OutputCompression::Zstd => Output::Zstd(zstd::Encoder::new(file, 0)?),
+            OutputCompression::Gzip => Output::Gzip(GzEncoder::new(file, Compression::default())),
+        })
+    }
+
+    /// Flushes any buffered output, ends the compressed stream of compressed files,
+    /// and, for files with `--fsync`, syncs them to disk.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Output::File(file) => file.finish().map(drop),
+            Output::Zstd(encoder) => encoder.finish()?.finish().map(drop),
+            Output::Gzip(encoder) => encoder.finish()?.finish().map(drop),
+            Output::Stdout(mut stdout) => match io::Write::flush(&mut stdout) {
+                // closed pipe, the reader doesn't want the rest of the output
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+                r => r,
+            },
+        }
+    }
+}
+
+impl io::Seek for Output {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        match self {
+            Output::File(file) => file.seek(pos),
+            Output::Zstd(_) | Output::Gzip(_) | Output::Stdout(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Can't seek compressed output or standard output",
             )),
-        },
+        }
+    }
+}
+
+impl io::Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::File(file) => file.write(buf),
+            Output::Zstd(encoder) => encoder.write(buf),
+            Output::Gzip(encoder) => encoder.write(buf),
+            Output::Stdout(stdout) => stdout.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::File(file) => file.flush(),
+            Output::Zstd(encoder) => encoder.flush(),
+            Output::Gzip(encoder) => encoder.flush(),
+            Output::Stdout(stdout) => stdout.flush(),
+        }
     }
 }
 
-pub fn output_from_args(args: &Args) -> anyhow::Result<Box<dyn io::Write>> {
+pub fn output_from_args(args: &Args) -> anyhow::Result<Output> {
     if let Some(output) = &args.output {
-        let output_file = open_output_file(output, args.force)?;
-        Ok(Box::new(BufWriter::new(output_file)))
+        output_file_from_args(args, output)
     } else {
-        Ok(Box::new(io::stdout().lock()))
+        Ok(Output::Stdout(io::stdout()))
     }
 }
 
+/// A `--tee` file and the filters of the records written to it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TeeArg {
+    pub path: PathBuf,
+    /// The product IDs of the records to write, or all if empty.
+    pub product_ids: Vec<u32>,
+    /// The UNIX nanosecond timestamp of the first `ts_event` to write.
+    pub start: Option<u64>,
+    /// The UNIX nanosecond timestamp of the exclusive end of the `ts_event`s to write.
+    pub end: Option<u64>,
+}
+
+impl TeeArg {
+    /// Applies the filters of the tee to `output`.
+    pub fn filter<'a>(&self, output: FanoutOutput<'a>) -> FanoutOutput<'a> {
+        let output = if self.product_ids.is_empty() {
+            output
+        } else {
+            output.filter_product_ids(self.product_ids.iter().copied())
+        };
+        if self.start.is_some() || self.end.is_some() {
+            output.filter_range(self.start.unwrap_or(0), self.end.unwrap_or(u64::MAX))
+        } else {
+            output
+        }
+    }
+}
+
+/// Parses a `--tee` file optionally followed by `?` and `&`-separated filters, e.g.
+/// `subset.dbz?product_id=5482,5483&start=1609160400000000000`.
+pub fn parse_tee(s: &str) -> anyhow::Result<TeeArg> {
+    let (path, filters) = s.split_once('?').unwrap_or((s, ""));
+    if path.is_empty() {
+        return Err(anyhow!("Tee '{s}' is missing a file"));
+    }
+    let mut tee = TeeArg {
+        path: PathBuf::from(path),
+        ..Default::default()
+    };
+    for filter in filters.split('&').filter(|filter| !filter.is_empty()) {
+        let (key, value) = filter
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Tee filter '{filter}' must be of the form KEY=VALUE"))?;
+        let parse_nanos = |value: &str| {
+            value.parse::<u64>().map_err(|_| {
+                anyhow!("Tee filter {key} must be a UNIX nanosecond timestamp, not '{value}'")
+            })
+        };
+        match key {
+            "product_id" => {
+                for id in value.split(',') {
+                    tee.product_ids.push(
+                        id.parse()
+                            .map_err(|_| anyhow!("Invalid product ID '{id}' in tee filter"))?,
+                    );
+                }
+            }
+            "start" => tee.start = Some(parse_nanos(value)?),
+            "end" => tee.end = Some(parse_nanos(value)?),
+            _ => {
+                return Err(anyhow!(
+                    "Unknown tee filter '{key}', expected one of: product_id, start, end"
+                ))
+            }
+        }
+    }
+    Ok(tee)
+}
+
+/// The encoding of a `--tee` file, inferred from its extension.
+#[derive(Clone, Copy, Debug)]
+pub enum TeeEncoding {
+    Text(dbz_lib::OutputEncoding),
+    /// `.dbz`, which can't be compressed further.
+    Dbz,
+}
+
+/// Opens the `--tee` files, each paired with the encoding of its extension.
+pub fn tees_from_args(args: &Args) -> anyhow::Result<Vec<(Output, TeeEncoding)>> {
+    args.tee
+        .iter()
+        .map(|tee| {
+            let path = &tee.path;
+            let encoding = if path.extension().is_some_and(|ext| ext == "dbz") {
+                TeeEncoding::Dbz
+            } else {
+                TeeEncoding::Text(
+                    infer_encoding_from_path(args, Some(path))
+                        .with_context(|| format!("Invalid tee file '{}'", path.display()))?,
+                )
+            };
+            Ok((output_file_from_args(args, path)?, encoding))
+        })
+        .collect()
+}
+
+/// Opens the output file `path`, compressed according to its extension.
+fn output_file_from_args(args: &Args, path: &PathBuf) -> anyhow::Result<Output> {
+    let file = OutputFile::new(open_output_file(path, args.force)?, &args.writer_options());
+    Ok(Output::from_file(file, OutputCompression::from_path(path))?)
+}
+
 fn open_output_file(path: &PathBuf, force: bool) -> anyhow::Result<File> {
     let mut options = File::options();
     options.write(true);
     if force {
-        options.create(true);
+        options.create(true).truncate(true);
     } else if path.exists() {
-        return Err(anyhow!(
-            "Output file exists. Pass --force flag to overwrite the existing file."
-        ));
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "Output file exists. Pass --force flag to overwrite the existing file.",
+        )
+        .into());
     } else {
         options.create_new(true);
     }
@@ -131,3 +916,61 @@ fn open_output_file(path: &PathBuf, force: bool) -> anyhow::Result<File> {
         .open(path)
         .with_context(|| format!("Unable to open output file '{}'", path.display()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("ESH1"), "ESH1");
+        assert_eq!(sanitize_file_name("ES.FUT"), "ES.FUT");
+        assert_eq!(sanitize_file_name("BRK/B"), "BRK_B");
+        assert_eq!(sanitize_file_name("ESH1 C4000"), "ESH1_C4000");
+        assert_eq!(sanitize_file_name(r"..\a:b*"), ".._a_b_");
+        assert_eq!(sanitize_file_name(".."), "__");
+        assert_eq!(sanitize_file_name(""), "_");
+    }
+
+    #[test]
+    fn test_expand_path_template() {
+        let mut metadata = Metadata::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        assert_eq!(
+            expand_path_template("{dataset}/{schema}-{date}-{symbol}.csv", &metadata).unwrap(),
+            "GLBX.MDP3/mbo-2020-12-28-ESH1.csv"
+        );
+        metadata.symbols.push("ESM1".to_owned());
+        assert_eq!(
+            expand_path_template("{date}.csv", &metadata).unwrap(),
+            "2020-12-28.csv"
+        );
+        assert!(expand_path_template("{symbol}.csv", &metadata).is_err());
+    }
+
+    #[test]
+    fn test_parse_tee() {
+        assert_eq!(
+            parse_tee("out.csv").unwrap(),
+            TeeArg {
+                path: PathBuf::from("out.csv"),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            parse_tee("subset.dbz?product_id=5482,5483&start=10&end=20").unwrap(),
+            TeeArg {
+                path: PathBuf::from("subset.dbz"),
+                product_ids: vec![5482, 5483],
+                start: Some(10),
+                end: Some(20),
+            }
+        );
+        assert_eq!(parse_tee("out.json?end=20").unwrap().end, Some(20));
+        assert!(parse_tee("?product_id=1").is_err());
+        assert!(parse_tee("out.csv?product_id=ESH1").is_err());
+        assert!(parse_tee("out.csv?start").is_err());
+        assert!(parse_tee("out.csv?symbol=ESH1").is_err());
+    }
+}
@@ -1,25 +1,508 @@
-use std::io;
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+    process::ExitCode,
+};
 
+use anyhow::anyhow;
 use clap::Parser;
-use dbz_cli::{infer_encoding, output_from_args, Args};
-use dbz_lib::Dbz;
+use dbz_cli::{
+    config::Config,
+    conformance::{write_conformance_suite, ConformanceArgs},
+    convert::{write_convert, ConvertArgs},
+    diff::{write_diff, DiffArgs},
+    doctor::{write_doctor, DoctorArgs},
+    error::{CliError, ErrorCode},
+    grep::{write_grep, GrepArgs},
+    index::{write_index, IndexArgs},
+    infer_encoding,
+    manifest::{write_manifest, write_plan, ManifestArgs, ManifestCommand},
+    merge::{write_merge, MergeArgs},
+    metadata::{write_metadata, MetadataArgs},
+    output_from_args, resolve_output_dir, resolve_output_template,
+    sort::{write_sort, SortArgs},
+    spec::SpecArgs,
+    split::{write_split, SplitArgs},
+    stats::{write_stats, StatsArgs},
+    tees_from_args,
+    top::{write_top, TopArgs},
+    validate::{write_conformance, write_continuity, write_integrity, write_timing, ValidateArgs},
+    verify::{write_verify, VerifyArgs},
+    write_dry_run, Args, Command, TeeEncoding,
+};
+use dbz_lib::{
+    ContinuityChecker, Dbz, DbzDataset, FanoutOutput, FormatSpec, IntegrityReport, Metadata,
+    UnknownRtypePolicy,
+};
 
-fn write_dbz<R: io::BufRead>(dbz: Dbz<R>, args: &Args) -> anyhow::Result<()> {
-    let writer = output_from_args(args)?;
-    let encoding = infer_encoding(args)?;
-    if args.should_output_metadata {
-        dbz.metadata().write_to(writer, encoding)?;
+fn write_dbz<R: io::BufRead + Send>(dbz: Dbz<R>, mut args: Args) -> Result<(), CliError> {
+    let dbz = if args.product_ids.is_empty() {
+        dbz
     } else {
-        dbz.write_to(writer, encoding)?;
+        dbz.filter_product_ids(args.product_ids.iter().copied())
+    };
+    let dbz = match args.skip {
+        Some(skip) => dbz.skip_records(skip),
+        None => dbz,
+    };
+    let dbz = match args.limit {
+        Some(limit) => dbz.take_records(limit),
+        None => dbz,
+    };
+    let dbz = if args.should_skip_unknown_rtypes {
+        dbz.with_unknown_rtype_policy(UnknownRtypePolicy::Skip)
+    } else {
+        dbz
+    };
+    let invalid_argument = |e| CliError::new(ErrorCode::InvalidArgument, e);
+    resolve_output_template(&mut args, dbz.metadata()).map_err(invalid_argument)?;
+    let encoding = infer_encoding(&args).map_err(invalid_argument)?;
+    resolve_output_dir(&mut args, encoding, dbz.metadata())
+        .map_err(|e| CliError::writing(e, ErrorCode::InvalidArgument))?;
+    let args = &args;
+    let options = args
+        .output_options(dbz.schema())
+        .map_err(|e| CliError::writing(e, ErrorCode::UnsupportedSchema).with_file(args.input()))?;
+    if args.dry_run {
+        return write_dry_run(dbz, args, encoding, &options, io::stdout().lock())
+            .map_err(|e| CliError::writing(e, ErrorCode::InvalidArgument).with_file(args.input()));
+    }
+    let output_error = |e| {
+        let error = CliError::writing(e, ErrorCode::InvalidArgument);
+        match &args.output {
+            Some(output) => error.with_file(output),
+            None => error,
+        }
+    };
+    let mut writer = output_from_args(args).map_err(output_error)?;
+    if !args.tee.is_empty() {
+        let mut tees = tees_from_args(args).map_err(invalid_argument)?;
+        let mut outputs = vec![FanoutOutput::text(&mut writer, encoding, options.clone())];
+        outputs.extend(
+            tees.iter_mut()
+                .zip(&args.tee)
+                .map(|((tee, tee_encoding), tee_arg)| {
+                    let output = match *tee_encoding {
+                        TeeEncoding::Text(tee_encoding) => {
+                            FanoutOutput::text(tee, tee_encoding, options.clone())
+                        }
+                        TeeEncoding::Dbz => FanoutOutput::dbz(tee, args.writer_options()),
+                    };
+                    tee_arg.filter(output)
+                }),
+        );
+        dbz.write_fanout(outputs).map_err(output_error)?;
+        for (tee, _) in tees {
+            tee.finish().map_err(|e| output_error(e.into()))?;
+        }
+    } else if args.should_output_metadata {
+        dbz.metadata()
+            .write_to_with_options(&mut writer, encoding, &options)
+            .map_err(output_error)?;
+    } else {
+        dbz.write_to_pipelined(&mut writer, encoding, &options, &args.pipeline_options())
+            .map_err(output_error)?;
+    }
+    writer.finish().map_err(|e| output_error(e.into()))
+}
+
+fn run(mut args: Args) -> Result<(), CliError> {
+    let config = Config::load(args.config.as_deref()).map_err(|e| {
+        let error = CliError::new(ErrorCode::InvalidArgument, e);
+        match &args.config {
+            Some(config) => error.with_file(config),
+            None => error,
+        }
+    })?;
+    args.apply_config(config);
+    match &args.command {
+        Some(Command::Convert(convert_args)) => return run_convert(convert_args),
+        Some(Command::Metadata(metadata_args)) => return run_metadata(metadata_args),
+        Some(Command::Stats(stats_args)) => return run_stats(stats_args),
+        Some(Command::Validate(validate_args)) => return run_validate(validate_args),
+        Some(Command::Top(top_args)) => return run_top(top_args),
+        Some(Command::Manifest(manifest_args)) => return run_manifest(manifest_args),
+        Some(Command::Diff(diff_args)) => return run_diff(diff_args),
+        Some(Command::Merge(merge_args)) => return run_merge(merge_args),
+        Some(Command::Sort(sort_args)) => return run_sort(sort_args),
+        Some(Command::Split(split_args)) => return run_split(split_args),
+        Some(Command::Index(index_args)) => return run_index(index_args),
+        Some(Command::Grep(grep_args)) => return run_grep(grep_args),
+        Some(Command::Doctor(doctor_args)) => return run_doctor(doctor_args),
+        Some(Command::Conformance(conformance_args)) => return run_conformance(conformance_args),
+        Some(Command::Spec(spec_args)) => return run_spec(spec_args),
+        Some(Command::Verify(verify_args)) => return run_verify(verify_args),
+        None => {}
+    }
+    if args.raw_body {
+        let schema = args.schema.expect("clap requires --schema with --raw-body");
+        if args.input().as_os_str() == "-" {
+            let dbz = Dbz::from_raw_body(BufReader::new(io::stdin()), schema);
+            write_dbz(dbz, args)
+        } else {
+            let file = File::open(args.input())
+                .map_err(|e| CliError::reading(e.into()).with_file(args.input()))?;
+            write_dbz(Dbz::from_raw_body(BufReader::new(file), schema), args)
+        }
+    } else if args.input().as_os_str() == "-" {
+        if args.tail.is_some() {
+            return Err(CliError::new(
+                ErrorCode::InvalidArgument,
+                anyhow!("--tail requires a file instead of standard input"),
+            ));
+        }
+        // the pipeline reads the input on another thread, which a locked stdin can't
+        // be sent to
+        let dbz = Dbz::new(BufReader::new(io::stdin())).map_err(CliError::reading)?;
+        write_dbz(dbz, args)
+    } else {
+        let dbz = match args.tail {
+            Some(count) => Dbz::from_file_tail(args.input(), count),
+            None => Dbz::from_file(args.input()),
+        }
+        .map_err(|e| CliError::reading(e).with_file(args.input()))?;
+        write_dbz(dbz, args)
     }
-    Ok(())
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+fn write_dbz_stats<R: io::BufRead>(dbz: Dbz<R>, args: &StatsArgs) -> Result<(), CliError> {
+    write_stats(dbz, args, io::stdout().lock())
+        .map_err(|e| CliError::writing(e, ErrorCode::UnsupportedSchema).with_file(&args.input))
+}
+
+fn run_metadata(args: &MetadataArgs) -> Result<(), CliError> {
+    // only the metadata is read, none of the records
+    let metadata = if args.input.as_os_str() == "-" {
+        Dbz::peek_metadata(&mut io::stdin().lock()).map_err(CliError::reading)?
+    } else {
+        Metadata::from_file(&args.input).map_err(|e| CliError::reading(e).with_file(&args.input))?
+    };
+    write_metadata(&metadata, io::stdout().lock()).map_err(|e| CliError::writing(e, ErrorCode::Io))
+}
+
+fn run_stats(args: &StatsArgs) -> Result<(), CliError> {
     if args.input.as_os_str() == "-" {
-        write_dbz(Dbz::new(io::stdin().lock())?, &args)
+        let dbz = Dbz::new(io::stdin().lock()).map_err(CliError::reading)?;
+        write_dbz_stats(dbz, args)
+    } else {
+        let dbz =
+            Dbz::from_file(&args.input).map_err(|e| CliError::reading(e).with_file(&args.input))?;
+        write_dbz_stats(dbz, args)
+    }
+}
+
+fn write_dbz_top<R: io::BufRead>(dbz: Dbz<R>, args: &TopArgs) -> Result<(), CliError> {
+    write_top(dbz, args, io::stdout().lock()).map_err(|e| {
+        let error = CliError::writing(e, ErrorCode::UnsupportedSchema);
+        match &args.output {
+            Some(output) => error.with_file(output),
+            None => error.with_file(&args.input),
+        }
+    })
+}
+
+fn run_top(args: &TopArgs) -> Result<(), CliError> {
+    if args.input.as_os_str() == "-" {
+        if args.output.is_some() {
+            return Err(CliError::new(
+                ErrorCode::InvalidArgument,
+                anyhow!("Can't save the records of standard input with --output"),
+            ));
+        }
+        let dbz = Dbz::new(io::stdin().lock()).map_err(CliError::reading)?;
+        write_dbz_top(dbz, args)
+    } else {
+        let dbz =
+            Dbz::from_file(&args.input).map_err(|e| CliError::reading(e).with_file(&args.input))?;
+        write_dbz_top(dbz, args)
+    }
+}
+
+fn write_dbz_grep<R: io::BufRead>(dbz: Dbz<R>, args: &GrepArgs) -> Result<(), CliError> {
+    write_grep(dbz, args, io::stdout().lock())
+        .map(|_| ())
+        .map_err(|e| CliError::writing(e, ErrorCode::InvalidArgument).with_file(&args.input))
+}
+
+fn run_grep(args: &GrepArgs) -> Result<(), CliError> {
+    if args.input.as_os_str() == "-" {
+        let dbz = Dbz::new(io::stdin().lock()).map_err(CliError::reading)?;
+        write_dbz_grep(dbz, args)
+    } else {
+        let dbz =
+            Dbz::from_file(&args.input).map_err(|e| CliError::reading(e).with_file(&args.input))?;
+        write_dbz_grep(dbz, args)
+    }
+}
+
+fn run_manifest(args: &ManifestArgs) -> Result<(), CliError> {
+    match &args.command {
+        // the error messages name the file that couldn't be read or written
+        ManifestCommand::Build(build_args) => {
+            write_manifest(build_args, io::stdout().lock()).map_err(CliError::reading)
+        }
+        ManifestCommand::Read(read_args) => {
+            let dataset = DbzDataset::from_manifest(&read_args.manifest)
+                .map_err(|e| CliError::reading(e).with_file(&read_args.manifest))?;
+            let files = read_args.plan(&dataset);
+            if read_args.should_verify {
+                for file in files.iter() {
+                    dataset.verify(file).map_err(|e| {
+                        CliError::new(ErrorCode::ValidationFailed, e).with_file(dataset.path(file))
+                    })?;
+                }
+            }
+            write_plan(&dataset, &files, io::stdout().lock())
+                .map_err(|e| CliError::writing(e, ErrorCode::Io))
+        }
+    }
+}
+
+fn run_diff(args: &DiffArgs) -> Result<(), CliError> {
+    // the error messages name the file that couldn't be read
+    let is_match = write_diff(args, io::stdout().lock()).map_err(CliError::reading)?;
+    if is_match {
+        Ok(())
+    } else {
+        Err(CliError::new(
+            ErrorCode::ValidationFailed,
+            anyhow!("Found differences"),
+        ))
+    }
+}
+
+fn run_merge(args: &MergeArgs) -> Result<(), CliError> {
+    // the error messages name the file that couldn't be read or written
+    write_merge(args).map(|_| ()).map_err(CliError::reading)
+}
+
+fn run_convert(args: &ConvertArgs) -> Result<(), CliError> {
+    // the error messages name the file that couldn't be read or written
+    write_convert(args).map_err(CliError::reading)
+}
+
+fn run_sort(args: &SortArgs) -> Result<(), CliError> {
+    // the error messages name the file that couldn't be read or written
+    write_sort(args).map(|_| ()).map_err(CliError::reading)
+}
+
+fn run_split(args: &SplitArgs) -> Result<(), CliError> {
+    // the error messages name the file that couldn't be read or written
+    write_split(args).map(|_| ()).map_err(CliError::reading)
+}
+
+fn run_index(args: &IndexArgs) -> Result<(), CliError> {
+    // the error messages name the file that couldn't be read or written
+    write_index(args).map(|_| ()).map_err(CliError::reading)
+}
+
+fn run_conformance(args: &ConformanceArgs) -> Result<(), CliError> {
+    let is_pass = write_conformance_suite(args, io::stdout().lock()).map_err(CliError::reading)?;
+    if is_pass {
+        Ok(())
+    } else {
+        Err(CliError::new(
+            ErrorCode::ValidationFailed,
+            anyhow!("Conformance cases failed"),
+        )
+        .with_file(&args.dir))
+    }
+}
+
+fn run_doctor(args: &DoctorArgs) -> Result<(), CliError> {
+    let is_healthy =
+        write_doctor(args, io::stdout().lock()).map_err(|e| CliError::writing(e, ErrorCode::Io))?;
+    if is_healthy {
+        Ok(())
+    } else {
+        Err(
+            CliError::new(ErrorCode::ValidationFailed, anyhow!("Found problems"))
+                .with_file(&args.input),
+        )
+    }
+}
+
+fn run_spec(args: &SpecArgs) -> Result<(), CliError> {
+    let spec = FormatSpec::new();
+    let stdout = io::stdout().lock();
+    if args.c_header {
+        spec.write_c_header(stdout)
     } else {
-        write_dbz(Dbz::from_file(&args.input)?, &args)
+        spec.write_json(stdout)
+    }
+    .map_err(|e| CliError::writing(e, ErrorCode::Io))
+}
+
+fn run_verify(args: &VerifyArgs) -> Result<(), CliError> {
+    let input = args.input.as_path();
+    let res = if input.as_os_str() == "-" {
+        let dbz = Dbz::new(io::stdin().lock()).map_err(CliError::reading)?;
+        write_verify(dbz, input, io::stdout().lock())
+    } else {
+        let dbz = Dbz::from_file(input).map_err(|e| CliError::reading(e).with_file(input))?;
+        write_verify(dbz, input, io::stdout().lock())
+    };
+    res.map_err(|e| CliError::reading(e).with_file(input))
+}
+
+fn validate_timing<R: io::BufRead>(
+    dbz: Dbz<R>,
+    input: &Path,
+    args: &ValidateArgs,
+) -> Result<bool, CliError> {
+    write_timing(dbz, input, args, io::stdout().lock())
+        .map_err(|e| CliError::writing(e, ErrorCode::UnsupportedSchema).with_file(input))
+}
+
+fn validate_conformance<R: io::BufRead>(
+    dbz: Dbz<R>,
+    input: &Path,
+    args: &ValidateArgs,
+) -> Result<bool, CliError> {
+    write_conformance(dbz, input, args, io::stdout().lock())
+        .map_err(|e| CliError::writing(e, ErrorCode::UnsupportedSchema).with_file(input))
+}
+
+fn run_validate(args: &ValidateArgs) -> Result<(), CliError> {
+    let mut continuity = None;
+    if args.should_check_continuity {
+        if args.inputs.len() < 2 {
+            return Err(CliError::new(
+                ErrorCode::InvalidArgument,
+                anyhow!("Checking continuity requires at least two files"),
+            ));
+        }
+        if args.inputs.iter().any(|input| input.as_os_str() == "-") {
+            return Err(CliError::new(
+                ErrorCode::InvalidArgument,
+                anyhow!("Can't check continuity of standard input"),
+            ));
+        }
+        continuity = Some(ContinuityChecker::new(args.gap_tolerance));
+    }
+    let record_check_count = [
+        args.should_check_timing,
+        args.should_check_conformance,
+        args.should_check_integrity,
+    ]
+    .into_iter()
+    .filter(|is_checked| *is_checked)
+    .count();
+    if record_check_count > 1 && args.inputs.iter().any(|input| input.as_os_str() == "-") {
+        return Err(CliError::new(
+            ErrorCode::InvalidArgument,
+            anyhow!("Can't run more than one of timing, conformance, and integrity checks on standard input"),
+        ));
+    }
+    let mut timing_failure = None;
+    let mut conformance_failure = None;
+    let mut integrity_failure = None;
+    for input in args.inputs.iter() {
+        if args.should_check_integrity {
+            let report = if input.as_os_str() == "-" {
+                IntegrityReport::from_reader(io::stdin().lock())
+            } else {
+                IntegrityReport::from_file(input)
+                    .map_err(|e| CliError::reading(e).with_file(input))?
+            };
+            let is_valid = write_integrity(&report, input, args, io::stdout().lock())
+                .map_err(|e| CliError::writing(e, ErrorCode::Io))?;
+            if !is_valid {
+                integrity_failure.get_or_insert(input);
+                // the other checks would stop at the first problem
+                continue;
+            }
+        }
+        if args.should_check_timing {
+            let is_valid = if input.as_os_str() == "-" {
+                let dbz = Dbz::new(io::stdin().lock()).map_err(CliError::reading)?;
+                validate_timing(dbz, input, args)?
+            } else {
+                let dbz =
+                    Dbz::from_file(input).map_err(|e| CliError::reading(e).with_file(input))?;
+                validate_timing(dbz, input, args)?
+            };
+            if !is_valid && timing_failure.is_none() {
+                timing_failure = Some(input);
+            }
+        }
+        if args.should_check_conformance {
+            let is_valid = if input.as_os_str() == "-" {
+                let dbz = Dbz::new(io::stdin().lock()).map_err(CliError::reading)?;
+                validate_conformance(dbz, input, args)?
+            } else {
+                let dbz =
+                    Dbz::from_file(input).map_err(|e| CliError::reading(e).with_file(input))?;
+                validate_conformance(dbz, input, args)?
+            };
+            if !is_valid && conformance_failure.is_none() {
+                conformance_failure = Some(input);
+            }
+        }
+        if let Some(checker) = continuity.as_mut() {
+            let dbz = Dbz::from_file(input).map_err(|e| CliError::reading(e).with_file(input))?;
+            checker
+                .add(dbz)
+                .map_err(|e| CliError::writing(e, ErrorCode::UnsupportedSchema).with_file(input))?;
+        }
+    }
+    let mut is_continuous = true;
+    if let Some(checker) = continuity {
+        let report = checker.report();
+        write_continuity(&report, args, io::stdout().lock())
+            .map_err(|e| CliError::writing(e, ErrorCode::Io))?;
+        is_continuous = report.is_clean();
+    }
+    if let Some(input) = integrity_failure {
+        Err(CliError::new(
+            ErrorCode::ValidationFailed,
+            anyhow!("Found integrity issues"),
+        )
+        .with_file(input))
+    } else if let Some(input) = timing_failure {
+        Err(
+            CliError::new(ErrorCode::ValidationFailed, anyhow!("Found timing issues"))
+                .with_file(input),
+        )
+    } else if let Some(input) = conformance_failure {
+        Err(CliError::new(
+            ErrorCode::ValidationFailed,
+            anyhow!("Found conformance issues"),
+        )
+        .with_file(input))
+    } else if !is_continuous {
+        Err(CliError::new(
+            ErrorCode::ValidationFailed,
+            anyhow!("Found continuity issues"),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        // clap errors are reported before `--json-errors` is known, so check for it
+        // in the raw arguments
+        Err(e) if e.use_stderr() && std::env::args_os().any(|arg| arg == "--json-errors") => {
+            let error = CliError::new(ErrorCode::InvalidArgument, e);
+            let _ = error.write_json(io::stderr().lock());
+            return ExitCode::from(2);
+        }
+        Err(e) => e.exit(),
+    };
+    let json_errors = args.json_errors;
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            if json_errors {
+                let _ = error.write_json(io::stderr().lock());
+            } else {
+                eprintln!("Error: {:?}", error.source);
+            }
+            ExitCode::FAILURE
+        }
     }
 }
@@ -0,0 +1,126 @@
+use std::{
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use clap::Subcommand;
+use dbz_lib::{DbzDataset, Manifest, ManifestFile};
+
+use crate::open_output_file;
+
+/// Arguments of the `manifest` subcommand.
+#[derive(Debug, clap::Args)]
+pub struct ManifestArgs {
+    #[clap(subcommand)]
+    pub command: ManifestCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ManifestCommand {
+    /// Build a manifest of DBZ files of the same dataset and schema
+    Build(BuildArgs),
+    /// List the files in a manifest with records in a time range
+    Read(ReadArgs),
+}
+
+/// Arguments of the `manifest build` subcommand.
+#[derive(Debug, clap::Args)]
+pub struct BuildArgs {
+    #[clap(
+        help = "The DBZ files to include in the manifest",
+        value_name = "FILE",
+        required = true
+    )]
+    pub inputs: Vec<PathBuf>,
+    #[clap(
+        short,
+        long,
+        value_name = "MANIFEST",
+        help = "Saves the manifest to MANIFEST. Paths of files in the same directory are stored relative to it. Without this option, the manifest is written to standard output"
+    )]
+    pub output: Option<PathBuf>,
+    #[clap(
+        short,
+        long,
+        help = "Allow overwriting of existing files, such as the output file"
+    )]
+    pub force: bool,
+}
+
+/// Arguments of the `manifest read` subcommand.
+#[derive(Debug, clap::Args)]
+pub struct ReadArgs {
+    #[clap(help = "The manifest to read", value_name = "MANIFEST")]
+    pub manifest: PathBuf,
+    #[clap(
+        long,
+        value_name = "NANOS",
+        default_value = "0",
+        help = "The UNIX nanosecond timestamp of the start of the time range"
+    )]
+    pub start: u64,
+    #[clap(
+        long,
+        value_name = "NANOS",
+        help = "The UNIX nanosecond timestamp of the exclusive end of the time range. Defaults to no end"
+    )]
+    pub end: Option<u64>,
+    #[clap(
+        long = "verify",
+        help = "Check the files in the time range against their digests in the manifest"
+    )]
+    pub should_verify: bool,
+}
+
+impl ReadArgs {
+    /// Returns the files of `dataset` in the time range of the arguments.
+    pub fn plan<'a>(&self, dataset: &'a DbzDataset) -> Vec<&'a ManifestFile> {
+        dataset.plan(self.start, self.end.unwrap_or(u64::MAX))
+    }
+}
+
+/// Builds a manifest of the files in `args` and writes it as JSON to the output file
+/// or, if there isn't one, to `stdout`.
+pub fn write_manifest(args: &BuildArgs, stdout: impl io::Write) -> anyhow::Result<()> {
+    let root = args
+        .output
+        .as_deref()
+        .and_then(Path::parent)
+        .unwrap_or_else(|| Path::new(""));
+    let manifest = Manifest::build(root, &args.inputs)?;
+    match &args.output {
+        Some(output) => {
+            let file = open_output_file(output, args.force)?;
+            manifest.write_json(BufWriter::new(file))
+        }
+        None => manifest.write_json(stdout),
+    }
+}
+
+/// Writes the resolved paths and time ranges of `files` from `dataset` to `out` as
+/// CSV.
+pub fn write_plan(
+    dataset: &DbzDataset,
+    files: &[&ManifestFile],
+    mut out: impl io::Write,
+) -> anyhow::Result<()> {
+    let res = (|| {
+        writeln!(out, "path,start,end,record_count")?;
+        for file in files {
+            writeln!(
+                out,
+                "{},{},{},{}",
+                dataset.path(file).display(),
+                file.start,
+                file.end,
+                file.record_count
+            )?;
+        }
+        out.flush()
+    })();
+    match res {
+        // closed pipe, should stop writing output
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        r => Ok(r?),
+    }
+}
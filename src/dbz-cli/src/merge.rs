@@ -0,0 +1,50 @@
+use std::{io::BufWriter, path::PathBuf};
+
+use dbz_lib::{MergeOptions, TieBreak};
+
+use crate::open_output_file;
+
+/// Arguments of the `merge` subcommand.
+#[derive(Debug, clap::Args)]
+pub struct MergeArgs {
+    #[clap(
+        help = "The DBZ files to merge. They must have the same dataset and schema",
+        value_name = "FILE",
+        required = true,
+        min_values = 2
+    )]
+    pub inputs: Vec<PathBuf>,
+    #[clap(
+        short,
+        long,
+        value_name = "FILE",
+        help = "Saves the merged records to FILE"
+    )]
+    pub output: PathBuf,
+    #[clap(
+        short,
+        long,
+        help = "Allow overwriting of existing files, such as the output file"
+    )]
+    pub force: bool,
+    #[clap(
+        long = "tie-break",
+        value_name = "TIE_BREAK",
+        default_value = "input",
+        help = "Order records with the same ts_event in different files by the order of the files, or canonically by product_id and then their bytes so the output doesn't depend on the order of the files"
+    )]
+    pub tie_break: TieBreak,
+}
+
+/// Merges the records of `args.inputs` in `ts_event` order into `args.output`.
+/// Returns the number of records written.
+pub fn write_merge(args: &MergeArgs) -> anyhow::Result<u64> {
+    let file = open_output_file(&args.output, args.force)?;
+    dbz_lib::merge_with_options(
+        &args.inputs,
+        BufWriter::new(file),
+        &MergeOptions {
+            tie_break: args.tie_break,
+        },
+    )
+}
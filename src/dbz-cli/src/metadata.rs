@@ -0,0 +1,27 @@
+use std::{io, path::PathBuf};
+
+use dbz_lib::{Metadata, OutputEncoding};
+
+/// Arguments of the `metadata` subcommand.
+#[derive(Debug, clap::Args)]
+pub struct MetadataArgs {
+    #[clap(
+        help = "The DBZ file whose metadata to output. Pass '-' to read from standard input",
+        value_name = "FILE"
+    )]
+    pub input: PathBuf,
+}
+
+/// Writes `metadata` to `out` as pretty-printed JSON, including its symbol mappings
+/// and record count.
+pub fn write_metadata(metadata: &Metadata, out: impl io::Write) -> anyhow::Result<()> {
+    metadata.write_to(
+        out,
+        OutputEncoding::Json {
+            should_pretty_print: true,
+            should_output_array: false,
+            should_output_iso_timestamps: false,
+            should_output_decimal_prices: false,
+        },
+    )
+}
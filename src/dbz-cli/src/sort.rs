@@ -0,0 +1,66 @@
+use std::{io::BufWriter, path::PathBuf};
+
+use dbz_lib::{Dbz, RecordOrder, SortOptions, TieBreak};
+
+use crate::open_output_file;
+
+/// Arguments of the `sort` subcommand.
+#[derive(Debug, clap::Args)]
+pub struct SortArgs {
+    #[clap(help = "The DBZ file to sort", value_name = "FILE")]
+    pub input: PathBuf,
+    #[clap(
+        short,
+        long,
+        value_name = "FILE",
+        help = "Saves the sorted records to FILE"
+    )]
+    pub output: PathBuf,
+    #[clap(
+        short,
+        long,
+        help = "Allow overwriting of existing files, such as the output file"
+    )]
+    pub force: bool,
+    #[clap(
+        long,
+        value_name = "ORDER",
+        default_value = "ts_event",
+        help = "Sort the records by ts_event, ts_recv, or ts_event_sequence"
+    )]
+    pub order: RecordOrder,
+    #[clap(
+        long = "tie-break",
+        value_name = "TIE_BREAK",
+        default_value = "input",
+        help = "Order records that compare equal by their order in the input, or canonically by product_id and then their bytes so the output doesn't depend on the input order"
+    )]
+    pub tie_break: TieBreak,
+    #[clap(
+        long = "max-memory",
+        value_name = "BYTES",
+        help = "Hold at most BYTES of records in memory, sorting larger files in runs written to temporary files [default: 1 GiB]"
+    )]
+    pub max_memory: Option<usize>,
+    #[clap(
+        long = "temp-dir",
+        value_name = "DIR",
+        help = "Write the temporary files of large sorts to DIR instead of the system's temporary directory"
+    )]
+    pub temp_dir: Option<PathBuf>,
+}
+
+/// Sorts the records of `args.input` into `args.output`. Returns the number of
+/// records written.
+pub fn write_sort(args: &SortArgs) -> anyhow::Result<u64> {
+    let default = SortOptions::default();
+    let options = SortOptions {
+        order: args.order,
+        tie_break: args.tie_break,
+        max_memory: args.max_memory.unwrap_or(default.max_memory),
+        temp_dir: args.temp_dir.clone(),
+    };
+    let dbz = Dbz::from_file(&args.input)?;
+    let file = open_output_file(&args.output, args.force)?;
+    dbz.write_sorted_to(BufWriter::new(file), &options)
+}
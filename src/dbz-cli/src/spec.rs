@@ -0,0 +1,9 @@
+/// Arguments of the `spec` subcommand.
+#[derive(Debug, clap::Args)]
+pub struct SpecArgs {
+    #[clap(
+        long,
+        help = "Output a C header of the record structs with static assertions of their layout instead of JSON"
+    )]
+    pub c_header: bool,
+}
@@ -0,0 +1,120 @@
+use std::{io::BufWriter, num::NonZeroUsize, path::PathBuf, thread};
+
+use anyhow::anyhow;
+use clap::{ArgAction, ValueEnum};
+use dbz_lib::{Metadata, SplitBy};
+
+use crate::{expand_path_template, open_output_file};
+
+/// Arguments of the `split` subcommand.
+#[derive(Debug, clap::Args)]
+pub struct SplitArgs {
+    #[clap(help = "The DBZ file to split", value_name = "FILE")]
+    pub input: PathBuf,
+    #[clap(
+        short,
+        long,
+        value_name = "TEMPLATE",
+        help = "Saves each part to the path TEMPLATE. {index} is replaced with the number of the part starting from 0, and {dataset}, {schema}, {date}, and {symbol} are replaced as for --output-dir. With --by-symbol, TEMPLATE must contain {symbol}, which is the symbol of each part"
+    )]
+    pub output: String,
+    #[clap(
+        long,
+        value_enum,
+        required_unless_present_any = &["records", "by-symbol"],
+        conflicts_with_all = &["records", "by-symbol"],
+        help = "Split the records by the UTC day or hour of their ts_event"
+    )]
+    pub by: Option<Interval>,
+    #[clap(
+        long,
+        value_name = "N",
+        conflicts_with = "by-symbol",
+        help = "Split the records into parts of at most N records"
+    )]
+    pub records: Option<u64>,
+    #[clap(
+        long = "by-symbol",
+        action = ArgAction::SetTrue,
+        default_value = "false",
+        help = "Split the records into one part per symbol, resolved from the mappings in the metadata. Records of unmapped products are split by product ID. Only the frames with records of each symbol are decoded when FILE has an index"
+    )]
+    pub by_symbol: bool,
+    #[clap(
+        long,
+        value_name = "N",
+        requires = "by-symbol",
+        help = "Split by symbol on N threads [default: the number of CPUs]"
+    )]
+    pub threads: Option<NonZeroUsize>,
+    #[clap(
+        short,
+        long,
+        help = "Allow overwriting of existing files, such as the output files"
+    )]
+    pub force: bool,
+}
+
+/// A span of time to split the records by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Interval {
+    /// The UTC day
+    Day,
+    /// The UTC hour
+    Hour,
+}
+
+/// Splits the records of `args.input` into files named after `args.output`. Returns
+/// the metadata of each part.
+pub fn write_split(args: &SplitArgs) -> anyhow::Result<Vec<Metadata>> {
+    if args.by_symbol {
+        return write_split_by_symbol(args);
+    }
+    let by = match (args.by, args.records) {
+        (Some(Interval::Day), _) => SplitBy::Day,
+        (Some(Interval::Hour), _) => SplitBy::Hour,
+        (None, Some(records)) => SplitBy::RecordCount(records),
+        (None, None) => unreachable!("clap requires --by, --records, or --by-symbol"),
+    };
+    // otherwise every part would be written to the same file
+    let has_distinct_names =
+        args.output.contains("{index}") || (by == SplitBy::Day && args.output.contains("{date}"));
+    if !has_distinct_names {
+        return Err(anyhow!(
+            "Output template '{}' must contain {{index}}, or {{date}} when splitting by day, to name each part differently",
+            args.output
+        ));
+    }
+    let mut index = 0;
+    dbz_lib::split(&args.input, by, |metadata| {
+        let path =
+            expand_path_template(&args.output, metadata)?.replace("{index}", &index.to_string());
+        index += 1;
+        Ok(BufWriter::new(open_output_file(
+            &PathBuf::from(path),
+            args.force,
+        )?))
+    })
+}
+
+/// Splits the records of `args.input` into one file per symbol named after
+/// `args.output`. Returns the metadata of each part.
+fn write_split_by_symbol(args: &SplitArgs) -> anyhow::Result<Vec<Metadata>> {
+    if !args.output.contains("{symbol}") {
+        return Err(anyhow!(
+            "Output template '{}' must contain {{symbol}} when splitting by symbol, to name each part differently",
+            args.output
+        ));
+    }
+    let threads = args
+        .threads
+        .or_else(|| thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get);
+    dbz_lib::split_by_symbol(&args.input, threads, |metadata| {
+        let path = expand_path_template(&args.output, metadata)?;
+        Ok(BufWriter::new(open_output_file(
+            &PathBuf::from(path),
+            args.force,
+        )?))
+    })
+}
@@ -0,0 +1,198 @@
+use std::{io, path::PathBuf, time::Duration};
+
+use anyhow::anyhow;
+use dbz_lib::{DataSummary, Dbz, Histogram, MessageRates};
+
+/// Arguments of the `stats` subcommand.
+#[derive(Debug, clap::Args)]
+#[clap(group(clap::ArgGroup::new("statistic").required(true)))]
+pub struct StatsArgs {
+    #[clap(
+        help = "A DBZ file to summarize. Pass '-' to read from standard input",
+        value_name = "FILE"
+    )]
+    pub input: PathBuf,
+    #[clap(
+        long,
+        group = "statistic",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        help = "Output the number of records in each DURATION-long bucket of ts_event as CSV, e.g. 1m or 1h"
+    )]
+    pub histogram: Option<Duration>,
+    #[clap(
+        long = "by-product-id",
+        requires = "histogram",
+        conflicts_with = "rates",
+        help = "Count the records of each product ID separately"
+    )]
+    pub should_count_by_product_id: bool,
+    #[clap(
+        long,
+        group = "statistic",
+        value_name = "WINDOW",
+        value_parser = parse_duration,
+        help = "Output percentiles of the number of records per WINDOW-long period of ts_event for each product ID, e.g. 1s"
+    )]
+    pub rates: Option<Duration>,
+    #[clap(
+        long,
+        requires = "rates",
+        conflicts_with = "histogram",
+        value_name = "COUNT",
+        help = "Flag windows with at least COUNT records as bursts"
+    )]
+    pub burst_threshold: Option<u64>,
+    #[clap(
+        long,
+        group = "statistic",
+        help = "Output the number of records, the first and last ts_event, the number of product IDs, the traded volume, and any gaps in sequence numbers"
+    )]
+    pub summary: bool,
+    #[clap(
+        long,
+        conflicts_with = "histogram",
+        help = "Output the rates or summary as JSON, including the start of each burst"
+    )]
+    pub json: bool,
+}
+
+/// Parses a duration made of an integer and a unit: `ns`, `us`, `ms`, `s`, `m`, `h`,
+/// or `d`.
+pub fn parse_duration(s: &str) -> anyhow::Result<Duration> {
+    let unit_start = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| anyhow!("Duration '{s}' is missing a unit"))?;
+    let (value, unit) = s.split_at(unit_start);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| anyhow!("Duration '{s}' must start with an integer"))?;
+    let nanos_per_unit = match unit {
+        "ns" => 1,
+        "us" => 1_000,
+        "ms" => 1_000_000,
+        "s" => 1_000_000_000,
+        "m" => 60 * 1_000_000_000,
+        "h" => 60 * 60 * 1_000_000_000,
+        "d" => 24 * 60 * 60 * 1_000_000_000,
+        _ => {
+            return Err(anyhow!(
+                "Unknown duration unit '{unit}', expected one of: ns, us, ms, s, m, h, d"
+            ))
+        }
+    };
+    let nanos = value
+        .checked_mul(nanos_per_unit)
+        .ok_or_else(|| anyhow!("Duration '{s}' is too large"))?;
+    Ok(Duration::from_nanos(nanos))
+}
+
+/// Writes the statistics `args` describe about `dbz` to `out`.
+pub fn write_stats<R: io::BufRead>(
+    dbz: Dbz<R>,
+    args: &StatsArgs,
+    mut out: impl io::Write,
+) -> anyhow::Result<()> {
+    let res = if args.summary {
+        let summary = dbz.summary()?;
+        write_summary(&summary, args.json, &mut out)
+    } else if let Some(window) = args.rates {
+        let rates = dbz.message_rates(window, args.burst_threshold)?;
+        write_rates(&rates, args.json, &mut out)
+    } else {
+        let bucket_duration = match args.histogram {
+            Some(bucket_duration) => bucket_duration,
+            None => return Err(anyhow!("Pass --histogram to count records by time")),
+        };
+        let histogram = dbz.histogram(bucket_duration, args.should_count_by_product_id)?;
+        write_histogram(&histogram, args.should_count_by_product_id, &mut out)
+    };
+    match res {
+        // closed pipe, should stop writing output
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        r => Ok(r?),
+    }
+}
+
+fn write_histogram(
+    histogram: &Histogram,
+    should_count_by_product_id: bool,
+    out: &mut impl io::Write,
+) -> io::Result<()> {
+    if should_count_by_product_id {
+        writeln!(out, "ts_start,product_id,count")?;
+    } else {
+        writeln!(out, "ts_start,count")?;
+    }
+    for bucket in histogram.buckets.iter() {
+        match bucket.product_id {
+            Some(product_id) => writeln!(out, "{},{product_id},{}", bucket.start, bucket.count)?,
+            None => writeln!(out, "{},{}", bucket.start, bucket.count)?,
+        }
+    }
+    out.flush()
+}
+
+fn write_summary(summary: &DataSummary, json: bool, out: &mut impl io::Write) -> io::Result<()> {
+    if json {
+        serde_json::to_writer(&mut *out, summary)?;
+        writeln!(out)?;
+        return out.flush();
+    }
+    let ts_event = |ts: Option<u64>| ts.map_or_else(|| "none".to_owned(), |ts| ts.to_string());
+    writeln!(out, "records:         {}", summary.record_count)?;
+    writeln!(out, "first ts_event:  {}", ts_event(summary.first_ts_event))?;
+    writeln!(out, "last ts_event:   {}", ts_event(summary.last_ts_event))?;
+    writeln!(out, "product IDs:     {}", summary.product_id_count)?;
+    writeln!(out, "volume:          {}", summary.volume)?;
+    writeln!(out, "sequence gaps:   {}", summary.sequence_gaps.len())?;
+    for gap in summary.sequence_gaps.iter() {
+        writeln!(
+            out,
+            "  record {}: publisher {} sequence jumps from {} to {}",
+            gap.record_index, gap.publisher_id, gap.last_sequence, gap.sequence
+        )?;
+    }
+    out.flush()
+}
+
+fn write_rates(rates: &MessageRates, json: bool, out: &mut impl io::Write) -> io::Result<()> {
+    if json {
+        for product in rates.products.iter() {
+            serde_json::to_writer(&mut *out, product)?;
+            writeln!(out)?;
+        }
+    } else {
+        writeln!(out, "product_id,record_count,p50,p90,p99,max,bursts")?;
+        for product in rates.products.iter() {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{}",
+                product.product_id,
+                product.record_count,
+                product.p50,
+                product.p90,
+                product.p99,
+                product.max,
+                product.bursts.len()
+            )?;
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("1m").unwrap(), Duration::from_secs(60));
+        assert_eq!(parse_duration("15s").unwrap(), Duration::from_secs(15));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(parse_duration("100ms").unwrap(), Duration::from_millis(100));
+        assert!(parse_duration("10").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("1w").is_err());
+    }
+}
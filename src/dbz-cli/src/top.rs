@@ -0,0 +1,139 @@
+use std::{
+    collections::HashSet,
+    io::{self, BufWriter},
+    path::PathBuf,
+};
+
+use clap::ValueEnum;
+use dbz_lib::{Dbz, ProductActivity, SymbolMap};
+
+use crate::open_output_file;
+
+/// Arguments of the `top` subcommand.
+#[derive(Debug, clap::Args)]
+pub struct TopArgs {
+    #[clap(
+        help = "A DBZ file to rank the products of. Pass '-' to read from standard input",
+        value_name = "FILE"
+    )]
+    pub input: PathBuf,
+    #[clap(
+        long,
+        value_enum,
+        default_value = "messages",
+        help = "How to measure activity"
+    )]
+    pub by: Activity,
+    #[clap(
+        short = 'n',
+        long = "count",
+        value_name = "N",
+        default_value = "20",
+        help = "The number of products to output"
+    )]
+    pub count: usize,
+    #[clap(
+        short,
+        long,
+        value_name = "FILE",
+        help = "Also save the records of the most active products to FILE as DBZ"
+    )]
+    pub output: Option<PathBuf>,
+    #[clap(
+        short,
+        long,
+        help = "Allow overwriting of existing files, such as the output file"
+    )]
+    pub force: bool,
+}
+
+/// A measure of how active a product is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Activity {
+    /// The traded volume
+    Volume,
+    /// The number of records
+    Messages,
+}
+
+/// Returns the `count` most active products in `activity` as measured `by`, most
+/// active first.
+pub fn most_active(
+    mut activity: Vec<ProductActivity>,
+    by: Activity,
+    count: usize,
+) -> Vec<ProductActivity> {
+    let measure = |a: &ProductActivity| match by {
+        Activity::Volume => a.volume,
+        Activity::Messages => a.record_count,
+    };
+    // ties go to the lower product ID, so the output is stable
+    activity.sort_by(|a, b| {
+        measure(b)
+            .cmp(&measure(a))
+            .then(a.product_id.cmp(&b.product_id))
+    });
+    activity.truncate(count);
+    activity
+}
+
+/// Writes the most active products in `dbz` as `args` describe to `out` as CSV, and
+/// their records to `args.output` if set. Writing the records reads the input a
+/// second time.
+pub fn write_top<R: io::BufRead>(
+    dbz: Dbz<R>,
+    args: &TopArgs,
+    mut out: impl io::Write,
+) -> anyhow::Result<()> {
+    // symbols are a nice-to-have, so files that can't be mapped still get a report
+    let symbol_map = SymbolMap::from_metadata(dbz.metadata()).ok();
+    let top = most_active(dbz.product_activity()?, args.by, args.count);
+    writeln!(out, "product_id,symbol,record_count,volume")?;
+    for activity in top.iter() {
+        let symbol = symbol_map
+            .as_ref()
+            .and_then(|m| m.get_for_ts(activity.product_id, activity.first_ts_event))
+            .unwrap_or_default();
+        writeln!(
+            out,
+            "{},{symbol},{},{}",
+            activity.product_id, activity.record_count, activity.volume
+        )?;
+    }
+    out.flush()?;
+    if let Some(output) = &args.output {
+        let product_ids: HashSet<u32> = top.iter().map(|a| a.product_id).collect();
+        let writer = BufWriter::new(open_output_file(output, args.force)?);
+        Dbz::from_file(&args.input)?.write_products_to(writer, &product_ids)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(product_id: u32, record_count: u64, volume: u64) -> ProductActivity {
+        ProductActivity {
+            product_id,
+            record_count,
+            volume,
+            first_ts_event: 0,
+        }
+    }
+
+    #[test]
+    fn test_most_active() {
+        let all = vec![activity(1, 10, 5), activity(2, 3, 50), activity(3, 10, 7)];
+        let ids = |top: Vec<ProductActivity>| top.iter().map(|a| a.product_id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(most_active(all.clone(), Activity::Messages, 2)),
+            vec![1, 3]
+        );
+        assert_eq!(
+            ids(most_active(all.clone(), Activity::Volume, 5)),
+            vec![2, 3, 1]
+        );
+        assert!(most_active(all, Activity::Volume, 0).is_empty());
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use dbz_lib::{
+    ConformanceReport, ContinuityIssue, ContinuityReport, Dbz, IntegrityReport, TimingIssues,
+    TimingReport,
+};
+
+use crate::stats::parse_duration;
+
+/// Arguments of the `validate` subcommand.
+#[derive(Debug, clap::Args)]
+#[clap(group(clap::ArgGroup::new("check").required(true).multiple(true)))]
+pub struct ValidateArgs {
+    #[clap(
+        help = "The DBZ files to validate, in order. Pass '-' to read from standard input",
+        value_name = "FILE",
+        required = true
+    )]
+    pub inputs: Vec<PathBuf>,
+    #[clap(
+        long = "timing",
+        group = "check",
+        help = "Check for records received before their ts_event, ts_in_delta outliers, and out-of-order ts_event runs"
+    )]
+    pub should_check_timing: bool,
+    #[clap(
+        long = "continuity",
+        group = "check",
+        help = "Check that each file picks up where the previous one left off, without time gaps or overlaps and with consecutive sequence numbers"
+    )]
+    pub should_check_continuity: bool,
+    #[clap(
+        long = "conformance",
+        group = "check",
+        help = "Check that the records match the schema, e.g. that every TBBO record is a trade"
+    )]
+    pub should_check_conformance: bool,
+    #[clap(
+        long = "integrity",
+        group = "check",
+        help = "Decode the metadata and every record, listing every problem found with its position instead of stopping at the first. Other checks are skipped for files with problems"
+    )]
+    pub should_check_integrity: bool,
+    #[clap(
+        long = "ts-in-delta-threshold",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        default_value = "1ms",
+        help = "Report records with a ts_in_delta greater than DURATION as outliers"
+    )]
+    pub ts_in_delta_threshold: Duration,
+    #[clap(
+        long = "gap-tolerance",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        default_value = "0s",
+        help = "Allow gaps of up to DURATION between the end of one file and the start of the next"
+    )]
+    pub gap_tolerance: Duration,
+    #[clap(long, help = "Output the report as JSON")]
+    pub json: bool,
+}
+
+/// Checks the timing of `dbz`, read from `input`, and writes the report to `out`.
+/// Returns whether the data passed validation.
+pub fn write_timing<R: io::BufRead>(
+    dbz: Dbz<R>,
+    input: &Path,
+    args: &ValidateArgs,
+    mut out: impl io::Write,
+) -> anyhow::Result<bool> {
+    let report = dbz.timing_report(args.ts_in_delta_threshold)?;
+    if args.json {
+        serde_json::to_writer(&mut out, &report)?;
+        writeln!(out)?;
+    } else {
+        if args.inputs.len() > 1 {
+            writeln!(out, "{}:", input.display())?;
+        }
+        write_timing_report(&report, args.ts_in_delta_threshold, &mut out)?;
+    }
+    out.flush()?;
+    Ok(report.is_clean())
+}
+
+/// Checks that the records of `dbz`, read from `input`, conform to its schema and
+/// writes the report to `out`. Returns whether the data passed validation.
+pub fn write_conformance<R: io::BufRead>(
+    dbz: Dbz<R>,
+    input: &Path,
+    args: &ValidateArgs,
+    mut out: impl io::Write,
+) -> anyhow::Result<bool> {
+    let report = dbz.conformance_report()?;
+    if args.json {
+        serde_json::to_writer(&mut out, &report)?;
+        writeln!(out)?;
+    } else {
+        if args.inputs.len() > 1 {
+            writeln!(out, "{}:", input.display())?;
+        }
+        write_conformance_report(&report, &mut out)?;
+    }
+    out.flush()?;
+    Ok(report.is_clean())
+}
+
+/// Writes `report`, the result of checking the integrity of `input`, to `out`.
+/// Returns whether the data passed validation.
+pub fn write_integrity(
+    report: &IntegrityReport,
+    input: &Path,
+    args: &ValidateArgs,
+    mut out: impl io::Write,
+) -> anyhow::Result<bool> {
+    if args.json {
+        serde_json::to_writer(&mut out, report)?;
+        writeln!(out)?;
+    } else {
+        if args.inputs.len() > 1 {
+            writeln!(out, "{}:", input.display())?;
+        }
+        write_integrity_report(report, &mut out)?;
+    }
+    out.flush()?;
+    Ok(report.is_clean())
+}
+
+fn write_integrity_report(report: &IntegrityReport, out: &mut impl io::Write) -> io::Result<()> {
+    let schema = report.schema.map_or("unknown", |schema| schema.as_str());
+    writeln!(out, "schema {schema}: {} records", report.record_count)?;
+    for problem in report.problems.iter() {
+        match (problem.record_index, problem.byte_offset) {
+            (Some(record_index), _) => {
+                writeln!(out, "  record {record_index}: {}", problem.message)?
+            }
+            (None, Some(byte_offset)) => {
+                writeln!(out, "  byte {byte_offset}: {}", problem.message)?
+            }
+            (None, None) => writeln!(out, "  {}", problem.message)?,
+        }
+    }
+    let unlisted_count = report.problem_count as usize - report.problems.len();
+    if unlisted_count > 0 {
+        writeln!(out, "  ... and {unlisted_count} more problems")?;
+    }
+    if report.is_clean() {
+        writeln!(out, "No integrity issues found")?;
+    }
+    Ok(())
+}
+
+fn write_conformance_report(
+    report: &ConformanceReport,
+    out: &mut impl io::Write,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "schema {}: {} records",
+        report.schema, report.record_count
+    )?;
+    if report.record_count != report.expected_record_count {
+        writeln!(
+            out,
+            "  expected {} records from the metadata",
+            report.expected_record_count
+        )?;
+    }
+    if report.non_trades.count > 0 {
+        write_issues(out, "non-trade records", &report.non_trades)?;
+    }
+    if report.is_clean() {
+        writeln!(out, "No conformance issues found")?;
+    }
+    Ok(())
+}
+
+fn write_issues(
+    out: &mut impl io::Write,
+    description: &str,
+    issues: &TimingIssues,
+) -> io::Result<()> {
+    write!(out, "  {description}: {}", issues.count)?;
+    if !issues.record_indices.is_empty() {
+        let indices: Vec<_> = issues
+            .record_indices
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        write!(out, " (records {}", indices.join(", "))?;
+        if issues.count as usize > indices.len() {
+            write!(out, ", ...")?;
+        }
+        write!(out, ")")?;
+    }
+    writeln!(out)
+}
+
+fn write_timing_report(
+    report: &TimingReport,
+    ts_in_delta_threshold: Duration,
+    out: &mut impl io::Write,
+) -> io::Result<()> {
+    for timing in report.publishers.iter() {
+        writeln!(
+            out,
+            "publisher_id {}: {} records",
+            timing.publisher_id, timing.record_count
+        )?;
+        write_issues(out, "ts_recv before ts_event", &timing.recv_before_event)?;
+        write_issues(
+            out,
+            &format!("ts_in_delta over {}ns", ts_in_delta_threshold.as_nanos()),
+            &timing.ts_in_delta_outliers,
+        )?;
+        write_issues(out, "out-of-order ts_event runs", &timing.out_of_order_runs)?;
+        if let Some(max_ts_in_delta) = timing.max_ts_in_delta {
+            writeln!(out, "  max ts_in_delta: {max_ts_in_delta}ns")?;
+        }
+    }
+    if report.is_clean() {
+        writeln!(out, "No timing issues found")?;
+    }
+    Ok(())
+}
+
+/// Writes `report`, the result of checking the continuity of `args.inputs`, to `out`.
+pub fn write_continuity(
+    report: &ContinuityReport,
+    args: &ValidateArgs,
+    mut out: impl io::Write,
+) -> anyhow::Result<()> {
+    if args.json {
+        serde_json::to_writer(&mut out, report)?;
+        writeln!(out)?;
+    } else {
+        write_continuity_report(report, &args.inputs, &mut out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn write_continuity_report(
+    report: &ContinuityReport,
+    inputs: &[PathBuf],
+    out: &mut impl io::Write,
+) -> io::Result<()> {
+    for boundary in report.boundaries.iter() {
+        writeln!(
+            out,
+            "{} -> {}:",
+            inputs[boundary.index - 1].display(),
+            inputs[boundary.index].display()
+        )?;
+        for issue in boundary.issues.iter() {
+            match issue {
+                ContinuityIssue::SchemaMismatch { previous, next } => {
+                    writeln!(out, "  schema changes from {previous} to {next}")?
+                }
+                ContinuityIssue::DatasetMismatch { previous, next } => {
+                    writeln!(out, "  dataset changes from {previous} to {next}")?
+                }
+                ContinuityIssue::Gap { end, start } => writeln!(
+                    out,
+                    "  gap of {}ns between end {end} and start {start}",
+                    start - end
+                )?,
+                ContinuityIssue::Overlap { end, start } => writeln!(
+                    out,
+                    "  overlap of {}ns between end {end} and start {start}",
+                    end - start
+                )?,
+                ContinuityIssue::RecordOverlap {
+                    last_ts_event,
+                    first_ts_event,
+                } => writeln!(
+                    out,
+                    "  first ts_event {first_ts_event} is before last ts_event {last_ts_event}"
+                )?,
+                ContinuityIssue::SequenceBreak {
+                    publisher_id,
+                    last_sequence,
+                    first_sequence,
+                } => writeln!(
+                    out,
+                    "  publisher_id {publisher_id}: sequence jumps from {last_sequence} to {first_sequence}"
+                )?,
+            }
+        }
+    }
+    if report.is_clean() {
+        writeln!(out, "No continuity issues found")?;
+    }
+    Ok(())
+}
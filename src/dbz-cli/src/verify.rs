@@ -0,0 +1,32 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use dbz_lib::Dbz;
+
+/// Arguments of the `verify` subcommand.
+#[derive(Debug, clap::Args)]
+pub struct VerifyArgs {
+    #[clap(
+        help = "The DBZ file to verify. Pass '-' to read from standard input",
+        value_name = "FILE"
+    )]
+    pub input: PathBuf,
+}
+
+/// Verifies the records of `dbz`, read from `input`, against its checksum footer and
+/// writes the outcome to `out`. Files without a checksum pass.
+pub fn write_verify<R: io::BufRead>(
+    dbz: Dbz<R>,
+    input: &Path,
+    mut out: impl io::Write,
+) -> anyhow::Result<()> {
+    if dbz.verify()? {
+        writeln!(out, "{}: checksum OK", input.display())?;
+    } else {
+        writeln!(out, "{}: no checksum to verify", input.display())?;
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -1,8 +1,11 @@
 use std::fs;
-use std::io::Read;
+use std::io::{Read, Write};
 
 use assert_cmd::Command;
-use predicates::str::{contains, ends_with, is_empty, starts_with};
+use predicates::{
+    prelude::PredicateBooleanExt,
+    str::{contains, ends_with, is_empty, starts_with},
+};
 use tempfile::{tempdir, NamedTempFile};
 
 fn cmd() -> Command {
@@ -17,7 +20,7 @@ fn write_json_to_path() {
     let output_dir = tempdir().unwrap();
     let output_path = format!("{}/a.json", output_dir.path().to_string_lossy());
     cmd()
-        .args(&[
+        .args([
             &format!("{DBZ_PATH}/test_data.mbp-1.dbz"),
             "--output",
             &output_path,
@@ -38,7 +41,7 @@ fn write_json_to_path() {
 #[test]
 fn write_to_stdout() {
     cmd()
-        .args(&[&format!("{DBZ_PATH}/test_data.mbo.dbz"), "--csv"])
+        .args([&format!("{DBZ_PATH}/test_data.mbo.dbz"), "--csv"])
         .assert()
         .success()
         .stdout(contains("channel_id"));
@@ -47,7 +50,7 @@ fn write_to_stdout() {
 #[test]
 fn write_to_nonexistent_path() {
     cmd()
-        .args(&[
+        .args([
             &format!("{DBZ_PATH}/test_data.mbo.dbz"),
             "--output",
             "./a/b/c/d/e",
@@ -58,6 +61,518 @@ fn write_to_nonexistent_path() {
         .stderr(contains("Unable to open output file './a/b/c/d/e'"));
 }
 
+#[test]
+fn write_with_flush_and_fsync() {
+    let output_dir = tempdir().unwrap();
+    let output_path = output_dir.path().join("trades.csv");
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--output",
+            output_path.to_str().unwrap(),
+            "--csv",
+            "--buffer-size",
+            "16",
+            "--flush-every",
+            "1",
+            "--fsync",
+        ])
+        .assert()
+        .success();
+    let expected = cmd()
+        .args([&format!("{DBZ_PATH}/test_data.trades.dbz"), "--csv"])
+        .output()
+        .unwrap()
+        .stdout;
+    assert_eq!(fs::read(output_path).unwrap(), expected);
+}
+
+#[test]
+fn write_with_tee() {
+    let output_dir = tempdir().unwrap();
+    let tee_path = output_dir.path().join("mbp-10.csv");
+    let json = cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbp-10.dbz"),
+            "--json",
+            "--tee",
+            tee_path.to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert!(json.status.success());
+    let expected_json = cmd()
+        .args([&format!("{DBZ_PATH}/test_data.mbp-10.dbz"), "--json"])
+        .output()
+        .unwrap()
+        .stdout;
+    assert_eq!(json.stdout, expected_json);
+    let expected_csv = cmd()
+        .args([&format!("{DBZ_PATH}/test_data.mbp-10.dbz"), "--csv"])
+        .output()
+        .unwrap()
+        .stdout;
+    assert_eq!(fs::read(tee_path).unwrap(), expected_csv);
+}
+
+#[test]
+fn write_compressed_output() {
+    let output_dir = tempdir().unwrap();
+    let csv_path = output_dir.path().join("trades.csv.zst");
+    let json_path = output_dir.path().join("trades.json.gz");
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--output",
+            csv_path.to_str().unwrap(),
+            "--tee",
+            json_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+    let expected_csv = cmd()
+        .args([&format!("{DBZ_PATH}/test_data.trades.dbz"), "--csv"])
+        .output()
+        .unwrap()
+        .stdout;
+    let csv = zstd::decode_all(fs::File::open(csv_path).unwrap()).unwrap();
+    assert_eq!(csv, expected_csv);
+    let expected_json = cmd()
+        .args([&format!("{DBZ_PATH}/test_data.trades.dbz"), "--json"])
+        .output()
+        .unwrap()
+        .stdout;
+    let mut json = Vec::new();
+    flate2::read::GzDecoder::new(fs::File::open(json_path).unwrap())
+        .read_to_end(&mut json)
+        .unwrap();
+    assert_eq!(json, expected_json);
+}
+
+#[test]
+fn tee_filtered_dbz() {
+    let output_dir = tempdir().unwrap();
+    let tee_path = output_dir.path().join("subset.dbz");
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "--csv",
+            "--tee",
+            &format!("{}?product_id=5482", tee_path.to_str().unwrap()),
+        ])
+        .assert()
+        .success();
+    let expected_csv = cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "--csv",
+            "--product-id",
+            "5482",
+        ])
+        .output()
+        .unwrap()
+        .stdout;
+    let csv = cmd()
+        .args([tee_path.to_str().unwrap(), "--csv"])
+        .output()
+        .unwrap()
+        .stdout;
+    assert_eq!(csv, expected_csv);
+}
+
+#[test]
+fn tee_filtered_range() {
+    const SECOND_TS_EVENT: u64 = 1609160400107665963;
+    let output_dir = tempdir().unwrap();
+    let tee_path = output_dir.path().join("trades.csv");
+    let json = cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--json",
+            "--tee",
+            &format!("{}?start={SECOND_TS_EVENT}", tee_path.to_str().unwrap()),
+        ])
+        .output()
+        .unwrap();
+    assert!(json.status.success());
+    // the filter only applies to the tee
+    assert_eq!(String::from_utf8(json.stdout).unwrap().lines().count(), 2);
+    let csv = fs::read_to_string(tee_path).unwrap();
+    assert_eq!(csv.lines().count(), 2);
+    assert!(csv.contains(&SECOND_TS_EVENT.to_string()));
+}
+
+#[test]
+fn tee_invalid_filter() {
+    let output_dir = tempdir().unwrap();
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--csv",
+            "--tee",
+            &format!(
+                "{}?symbol=ESH1",
+                output_dir.path().join("trades.csv").to_str().unwrap()
+            ),
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("Unknown tee filter 'symbol'"));
+}
+
+#[test]
+fn tee_without_extension() {
+    let output_dir = tempdir().unwrap();
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--csv",
+            "--tee",
+            output_dir.path().join("trades").to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("Unable to infer output encoding"));
+}
+
+#[test]
+fn output_path_template() {
+    let output_dir = tempdir().unwrap();
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--output",
+            output_dir
+                .path()
+                .join("{schema}-{date}-{symbol}.csv")
+                .to_str()
+                .unwrap(),
+        ])
+        .assert()
+        .success();
+    let contents =
+        fs::read_to_string(output_dir.path().join("trades-2020-12-28-ESH1.csv")).unwrap();
+    assert!(contents.starts_with("rtype,"));
+}
+
+#[test]
+fn grep_by_order_id() {
+    cmd()
+        .args([
+            "grep",
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "--field",
+            "order_id",
+            "--eq",
+            "647784973631",
+        ])
+        .assert()
+        .success()
+        .stdout(starts_with("{\"index\":1,\"record\":{").and(contains("\n").count(1)));
+}
+
+#[test]
+fn grep_unknown_field() {
+    cmd()
+        .args([
+            "grep",
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--field",
+            "order_id",
+            "--ge",
+            "0",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("Unknown field 'order_id' for schema trades"));
+}
+
+#[test]
+fn threads_dont_change_output() {
+    let input = format!("{DBZ_PATH}/test_data.mbp-10.dbz");
+    let expected = cmd()
+        .args([&input, "--csv", "--threads", "0"])
+        .ok()
+        .unwrap()
+        .stdout;
+    cmd()
+        .args([&input, "--csv", "--threads", "3"])
+        .assert()
+        .success()
+        .stdout(String::from_utf8(expected).unwrap());
+}
+
+#[test]
+fn merge_files() {
+    let output_dir = tempdir().unwrap();
+    let output_path = output_dir.path().join("merged.dbz");
+    let input = format!("{DBZ_PATH}/test_data.trades.dbz");
+    cmd()
+        .args(["merge", &input, &input, "-o", output_path.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(is_empty());
+    cmd()
+        .args([output_path.to_str().unwrap(), "--csv", "--no-header"])
+        .assert()
+        .success()
+        .stdout(predicates::function::function(|out: &str| {
+            out.lines().count() == 4
+        }));
+}
+
+#[test]
+fn merge_different_schemas() {
+    let output_dir = tempdir().unwrap();
+    cmd()
+        .args([
+            "merge",
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "-o",
+            output_dir.path().join("merged.dbz").to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("schema mbo doesn't match trades"));
+}
+
+#[test]
+fn sort_canonical_tie_break() {
+    let output_dir = tempdir().unwrap();
+    let input = format!("{DBZ_PATH}/test_data.mbp-10.dbz");
+    let in_memory = output_dir.path().join("in-memory.dbz");
+    let in_runs = output_dir.path().join("in-runs.dbz");
+    for (output_path, max_memory) in [(&in_memory, "1000000"), (&in_runs, "1")] {
+        cmd()
+            .args([
+                "sort",
+                &input,
+                "-o",
+                output_path.to_str().unwrap(),
+                "--tie-break",
+                "canonical",
+                "--max-memory",
+                max_memory,
+                "--temp-dir",
+                output_dir.path().to_str().unwrap(),
+            ])
+            .assert()
+            .success();
+    }
+    assert_eq!(fs::read(in_memory).unwrap(), fs::read(in_runs).unwrap());
+}
+
+#[test]
+fn sort_in_runs() {
+    let output_dir = tempdir().unwrap();
+    let output_path = output_dir.path().join("sorted.dbz");
+    let input = format!("{DBZ_PATH}/test_data.mbp-10.dbz");
+    cmd()
+        .args([
+            "sort",
+            &input,
+            "-o",
+            output_path.to_str().unwrap(),
+            "--max-memory",
+            "1",
+            "--temp-dir",
+            output_dir.path().to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(is_empty());
+    // only the output is left
+    assert_eq!(fs::read_dir(output_dir.path()).unwrap().count(), 1);
+    let expected = cmd().args([&input, "--json"]).ok().unwrap().stdout;
+    cmd()
+        .args([output_path.to_str().unwrap(), "--json"])
+        .assert()
+        .success()
+        .stdout(String::from_utf8(expected).unwrap());
+}
+
+#[test]
+fn split_by_record_count() {
+    let output_dir = tempdir().unwrap();
+    let template = output_dir.path().join("{schema}-{index}.dbz");
+    let input = format!("{DBZ_PATH}/test_data.mbo.dbz");
+    cmd()
+        .args([
+            "split",
+            &input,
+            "-o",
+            template.to_str().unwrap(),
+            "--records",
+            "1",
+        ])
+        .assert()
+        .success()
+        .stdout(is_empty());
+    let expected = cmd().args([&input, "--csv"]).ok().unwrap().stdout;
+    let expected = String::from_utf8(expected).unwrap();
+    let mut expected_lines = expected.lines();
+    let header = expected_lines.next().unwrap();
+    for (i, line) in expected_lines.enumerate() {
+        let part = output_dir.path().join(format!("mbo-{i}.dbz"));
+        cmd()
+            .args([part.to_str().unwrap(), "--csv"])
+            .assert()
+            .success()
+            .stdout(format!("{header}\n{line}\n"));
+    }
+    assert_eq!(fs::read_dir(output_dir.path()).unwrap().count(), 2);
+}
+
+#[test]
+fn split_requires_distinct_names() {
+    let output_dir = tempdir().unwrap();
+    cmd()
+        .args([
+            "split",
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "-o",
+            output_dir.path().join("part.dbz").to_str().unwrap(),
+            "--by",
+            "hour",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("must contain {index}"));
+}
+
+#[test]
+fn split_by_symbol() {
+    let output_dir = tempdir().unwrap();
+    let template = output_dir.path().join("{symbol}.dbz");
+    let input = format!("{DBZ_PATH}/test_data.mbo.dbz");
+    cmd()
+        .args([
+            "split",
+            &input,
+            "-o",
+            template.to_str().unwrap(),
+            "--by-symbol",
+            "--threads",
+            "2",
+        ])
+        .assert()
+        .success()
+        .stdout(is_empty());
+    let expected = cmd().args([&input, "--csv"]).ok().unwrap().stdout;
+    cmd()
+        .args([
+            output_dir.path().join("ESH1.dbz").to_str().unwrap(),
+            "--csv",
+        ])
+        .assert()
+        .success()
+        .stdout(String::from_utf8(expected).unwrap());
+    assert_eq!(fs::read_dir(output_dir.path()).unwrap().count(), 1);
+    cmd()
+        .args([
+            "split",
+            &input,
+            "-o",
+            output_dir.path().join("part.dbz").to_str().unwrap(),
+            "--by-symbol",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("must contain {symbol}"));
+}
+
+#[test]
+fn index_next_to_file() {
+    let output_dir = tempdir().unwrap();
+    let input = output_dir.path().join("trades.dbz");
+    fs::copy(format!("{DBZ_PATH}/test_data.trades.dbz"), &input).unwrap();
+    cmd()
+        .args(["index", input.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(is_empty());
+    let index = dbz_lib::RecordIndex::from_file(output_dir.path().join("trades.dbz.idx")).unwrap();
+    assert_eq!(index.len(), 2);
+    // doesn't overwrite without --force
+    cmd()
+        .args(["index", input.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(contains("Output file exists"));
+}
+
+#[test]
+fn conformance_suite_passes() {
+    cmd()
+        .args([
+            "conformance",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/conformance"),
+        ])
+        .assert()
+        .success()
+        .stdout(contains("PASS trades").and(ends_with("9 of 9 cases passed\n")));
+}
+
+#[test]
+fn build_and_read_manifest() {
+    let output_dir = tempdir().unwrap();
+    let manifest_path = output_dir.path().join("manifest.json");
+    cmd()
+        .args([
+            "manifest",
+            "build",
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--output",
+            manifest_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(is_empty());
+    assert!(fs::read_to_string(&manifest_path)
+        .unwrap()
+        .contains("\"schema\": \"trades\""));
+    cmd()
+        .args([
+            "manifest",
+            "read",
+            manifest_path.to_str().unwrap(),
+            "--start",
+            "1609200000000000000",
+        ])
+        .assert()
+        .success()
+        .stdout("path,start,end,record_count\n");
+    cmd()
+        .args([
+            "manifest",
+            "read",
+            manifest_path.to_str().unwrap(),
+            "--end",
+            "1609200000000000000",
+            "--verify",
+        ])
+        .assert()
+        .success()
+        .stdout(contains(
+            "test_data.trades.dbz,1609160400000000000,1609200000000000000,2\n",
+        ));
+}
+
+#[test]
+fn build_manifest_of_mixed_schemas() {
+    cmd()
+        .args([
+            "manifest",
+            "build",
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("expected dataset GLBX.MDP3 and schema trades"));
+}
+
 #[test]
 fn read_from_nonexistent_path() {
     let input_file = NamedTempFile::new().unwrap();
@@ -66,7 +581,7 @@ fn read_from_nonexistent_path() {
     input_file.close().unwrap();
     let output_file = NamedTempFile::new().unwrap();
     cmd()
-        .args(&[
+        .args([
             &input_path.to_string_lossy(),
             "--output",
             &output_file.path().to_string_lossy(),
@@ -82,7 +597,7 @@ fn write_csv() {
     let output_dir = tempdir().unwrap();
     let output_path = format!("{}/a.json", output_dir.path().to_string_lossy());
     cmd()
-        .args(&[
+        .args([
             &format!("{DBZ_PATH}/test_data.mbp-1.dbz"),
             "--output",
             &output_path,
@@ -107,7 +622,7 @@ fn encoding_overrides_extension() {
     // output file extension is csv, but the encoding argument is json
     let output_path = format!("{}/a.csv", output_dir.path().to_string_lossy());
     cmd()
-        .args(&[
+        .args([
             &format!("{DBZ_PATH}/test_data.mbp-1.dbz"),
             "--output",
             &output_path,
@@ -130,7 +645,7 @@ fn bad_infer() {
     let output_dir = tempdir().unwrap();
     let output_path = format!("{}/a.yaml", output_dir.path().to_string_lossy());
     cmd()
-        .args(&[
+        .args([
             &format!("{DBZ_PATH}/test_data.mbo.dbz"),
             "--output",
             &output_path,
@@ -147,7 +662,7 @@ fn no_extension_infer() {
     let output_dir = tempdir().unwrap();
     let output_path = format!("{}/a", output_dir.path().to_string_lossy());
     cmd()
-        .args(&[
+        .args([
             &format!("{DBZ_PATH}/test_data.mbo.dbz"),
             "--output",
             &output_path,
@@ -164,7 +679,7 @@ fn overwrite_fails() {
     let output_file = NamedTempFile::new().unwrap();
 
     cmd()
-        .args(&[
+        .args([
             &format!("{DBZ_PATH}/test_data.mbo.dbz"),
             "--output",
             &output_file.path().to_string_lossy(),
@@ -179,7 +694,7 @@ fn overwrite_fails() {
 fn force_overwrite() {
     let output_file = NamedTempFile::new().unwrap();
     cmd()
-        .args(&[
+        .args([
             &format!("{DBZ_PATH}/test_data.mbo.dbz"),
             "--output",
             &output_file.path().to_string_lossy(),
@@ -196,7 +711,7 @@ fn force_overwrite() {
 #[test]
 fn cant_specify_json_and_csv() {
     cmd()
-        .args(&[&format!("{DBZ_PATH}/test_data.mbo.dbz"), "--json", "--csv"])
+        .args([&format!("{DBZ_PATH}/test_data.mbo.dbz"), "--json", "--csv"])
         .assert()
         .failure()
         .stderr(contains("cannot be used with"));
@@ -205,7 +720,7 @@ fn cant_specify_json_and_csv() {
 #[test]
 fn metadata() {
     cmd()
-        .args(&[&format!("{DBZ_PATH}/test_data.ohlcv-1m.dbz"), "-J", "-m"])
+        .args([&format!("{DBZ_PATH}/test_data.ohlcv-1m.dbz"), "-J", "-m"])
         .assert()
         .success()
         .stdout(starts_with("{"))
@@ -213,10 +728,45 @@ fn metadata() {
         .stderr(is_empty());
 }
 
+#[test]
+fn metadata_subcommand() {
+    cmd()
+        .args(["metadata", &format!("{DBZ_PATH}/test_data.mbo.dbz")])
+        .assert()
+        .success()
+        .stdout(starts_with("{\n    \"version\": 1,"))
+        .stdout(contains("\"schema\": \"mbo\""))
+        .stdout(contains("\"record_count\": 2"))
+        .stdout(contains("\"mappings\": ["))
+        .stderr(is_empty());
+}
+
+#[test]
+fn metadata_subcommand_stdin() {
+    // truncated records don't matter since they aren't decoded
+    let input = fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+    cmd()
+        .args(["metadata", "-"])
+        .write_stdin(&input[..input.len() - 10])
+        .assert()
+        .success()
+        .stdout(contains("\"schema\": \"mbo\""));
+}
+
+#[test]
+fn metadata_subcommand_missing_file() {
+    cmd()
+        .args(["metadata", "nonexistent.dbz"])
+        .assert()
+        .failure()
+        .stdout(is_empty())
+        .stderr(contains("nonexistent.dbz"));
+}
+
 #[test]
 fn no_csv_metadata() {
     cmd()
-        .args(&[&format!("{DBZ_PATH}/test_data.ohlcv-1m.dbz"), "--csv", "-m"])
+        .args([&format!("{DBZ_PATH}/test_data.ohlcv-1m.dbz"), "--csv", "-m"])
         .assert()
         .failure()
         .stdout(is_empty())
@@ -226,7 +776,7 @@ fn no_csv_metadata() {
 #[test]
 fn pretty_print_data() {
     cmd()
-        .args(&[
+        .args([
             &format!("{DBZ_PATH}/test_data.mbo.dbz"),
             "--json",
             "--pretty-json",
@@ -241,7 +791,7 @@ fn pretty_print_data() {
 #[test]
 fn pretty_print_data_metadata() {
     cmd()
-        .args(&[
+        .args([
             &format!("{DBZ_PATH}/test_data.mbo.dbz"),
             "-J",
             "--metadata",
@@ -252,24 +802,1245 @@ fn pretty_print_data_metadata() {
 }
 
 #[test]
-fn read_from_stdin() {
-    let path = format!("{DBZ_PATH}/test_data.mbp-10.dbz");
-    let read_from_stdin_output = cmd()
-        .args(&[
-            "-", // STDIN
+fn map_stype_native_csv() {
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "--csv",
+            "--map-stype",
+            "native",
+        ])
+        .assert()
+        .success()
+        .stdout(contains(",sequence,symbol\n"))
+        .stdout(contains(",ESH1\n"))
+        .stderr(is_empty());
+}
+
+#[test]
+fn map_stype_native_json() {
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
             "--json",
+            "--map-stype",
+            "native",
         ])
-        // Pipe input from file
-        .pipe_stdin(&path)
+        .assert()
+        .success()
+        .stdout(contains(r#","symbol":"ESH1"}"#))
+        .stderr(is_empty());
+}
+
+#[test]
+fn map_stype_native_metadata() {
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "--json",
+            "--metadata",
+            "--map-stype",
+            "native",
+        ])
+        .assert()
+        .success()
+        .stdout(contains(r#""stype_out":"native""#))
+        .stdout(contains(r#""symbol":"ESH1""#))
+        .stderr(is_empty());
+}
+
+#[test]
+fn map_stype_product_id_is_unchanged() {
+    let path = format!("{DBZ_PATH}/test_data.mbo.dbz");
+    let mapped_output = cmd()
+        .args([&path, "--json", "--map-stype", "product_id"])
+        .ok()
+        .unwrap();
+    let output = cmd().args([&path, "--json"]).ok().unwrap();
+    assert_eq!(mapped_output.stdout, output.stdout);
+}
+
+#[test]
+fn select_fields() {
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbp-1.dbz"),
+            "--csv",
+            "--fields",
+            "ts_event,price,size",
+        ])
+        .assert()
+        .success()
+        .stdout(starts_with("ts_event,price,size\n"))
+        .stderr(is_empty());
+}
+
+#[test]
+fn filter_product_ids() {
+    let output = cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "--csv",
+            "--product-id",
+            "5482",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 3);
+    let output = cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "--csv",
+            "--product-id",
+            "1,2",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    // only the header
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 1);
+}
+
+#[test]
+fn skip_and_limit() {
+    let output = cmd()
+        .args([&format!("{DBZ_PATH}/test_data.mbo.dbz"), "--json"])
+        .output()
+        .unwrap();
+    let all = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<_> = all.lines().collect();
+    assert_eq!(lines.len(), 2);
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "--json",
+            "-n",
+            "1",
+        ])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", lines[0]));
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "--json",
+            "--skip",
+            "1",
+            "--limit",
+            "5",
+        ])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", lines[1]));
+}
+
+#[test]
+fn tail() {
+    let output = cmd()
+        .args([&format!("{DBZ_PATH}/test_data.mbo.dbz"), "--json"])
+        .output()
+        .unwrap();
+    let all = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<_> = all.lines().collect();
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "--json",
+            "--tail",
+            "1",
+        ])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", lines[1]));
+    cmd()
+        .args(["-", "--json", "--tail", "1"])
+        .write_stdin(fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap())
+        .assert()
+        .failure()
+        .stderr(contains("standard input"));
+}
+
+#[test]
+fn limit_stops_decoding() {
+    let input = fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+    // the records after the limit aren't read, so truncation past it goes unnoticed
+    cmd()
+        .args(["-", "--json", "--limit", "0"])
+        .write_stdin(&input[..input.len() - 5])
+        .assert()
+        .success()
+        .stdout(is_empty());
+}
+
+#[test]
+fn truncated_records_fail() {
+    let input = fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+    let output_dir = tempdir().unwrap();
+    let truncated_path = output_dir.path().join("mbo.dbz");
+    fs::write(&truncated_path, &input[..input.len() - 20]).unwrap();
+    let truncated_path = truncated_path.to_str().unwrap();
+    let csv_path = output_dir.path().join("mbo.csv");
+    for args in [
+        vec![truncated_path, "--csv", "--threads", "0"],
+        vec![truncated_path, "--csv", "--threads", "2"],
+        vec!["convert", truncated_path, csv_path.to_str().unwrap()],
+        vec!["stats", truncated_path, "--summary"],
+        vec!["top", truncated_path],
+        vec!["grep", truncated_path, "--field", "order_id", "--eq", "0"],
+    ] {
+        cmd()
+            .args(&args)
+            .assert()
+            .failure()
+            .stderr(contains("Failed to read record"));
+    }
+}
+
+#[test]
+fn skip_unknown_rtypes() {
+    let input = fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+    // the metadata's length follows the 4-byte Zstd skippable frame magic
+    let metadata_len = 8 + u32::from_le_bytes(input[4..8].try_into().unwrap()) as usize;
+    let mut records = zstd::decode_all(&input[metadata_len..]).unwrap();
+    // the rtype follows the length in the header
+    records[1] = 0xEE;
+    let mut modified = input[..metadata_len].to_vec();
+    modified.extend(zstd::encode_all(records.as_slice(), 0).unwrap());
+    let output = cmd()
+        .args([&format!("{DBZ_PATH}/test_data.mbo.dbz"), "--json"])
+        .output()
+        .unwrap();
+    let all = String::from_utf8(output.stdout).unwrap();
+    let second = all.lines().nth(1).unwrap();
+    // by default, the output fails at the record
+    cmd()
+        .args(["-", "--json"])
+        .write_stdin(modified.clone())
+        .assert()
+        .failure()
+        .stdout(is_empty())
+        .stderr(contains("Record 0 has rtype 0xee"));
+    cmd()
+        .args(["-", "--json", "--skip-unknown-rtypes"])
+        .write_stdin(modified)
+        .assert()
+        .success()
+        .stdout(format!("{second}\n"));
+}
+
+#[test]
+fn convert_round_trip() {
+    let output_dir = tempdir().unwrap();
+    let csv_path = output_dir.path().join("mbp-10.csv.zst");
+    let dbz_path = output_dir.path().join("mbp-10.dbz");
+    cmd()
+        .args([
+            "convert",
+            &format!("{DBZ_PATH}/test_data.mbp-10.dbz"),
+            csv_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+    cmd()
+        .args([
+            "convert",
+            csv_path.to_str().unwrap(),
+            dbz_path.to_str().unwrap(),
+            "--schema",
+            "mbp-10",
+            "--dataset",
+            "GLBX.MDP3",
+        ])
+        .assert()
+        .success();
+    let expected = cmd()
+        .args([&format!("{DBZ_PATH}/test_data.mbp-10.dbz"), "--json"])
+        .output()
+        .unwrap()
+        .stdout;
+    cmd()
+        .args([dbz_path.to_str().unwrap(), "--json"])
+        .assert()
+        .success()
+        .stdout(String::from_utf8(expected).unwrap());
+}
+
+#[test]
+fn convert_json_to_dbz() {
+    let output_dir = tempdir().unwrap();
+    let json_path = output_dir.path().join("trades.json");
+    let dbz_path = output_dir.path().join("trades.dbz");
+    cmd()
+        .args([
+            "convert",
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            json_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+    // the schema of text input must be passed
+    cmd()
+        .args([
+            "convert",
+            json_path.to_str().unwrap(),
+            dbz_path.to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("--schema is required"));
+    cmd()
+        .args([
+            "convert",
+            json_path.to_str().unwrap(),
+            dbz_path.to_str().unwrap(),
+            "--schema",
+            "trades",
+        ])
+        .assert()
+        .success();
+    cmd()
+        .args(["metadata", dbz_path.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(contains("\"record_count\": 2"));
+    cmd()
+        .args([
+            "convert",
+            json_path.to_str().unwrap(),
+            output_dir.path().join("trades.csv").to_str().unwrap(),
+            "--schema",
+            "trades",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("Converting between CSV and JSON isn't supported"));
+}
+
+#[test]
+fn csv_without_header() {
+    let output = cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbp-1.dbz"),
+            "--csv",
+            "--no-header",
+            "--fields",
+            "ts_event,price,size",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 2);
+    assert!(stdout.lines().all(|line| line.split(',').count() == 3));
+    assert!(!stdout.starts_with("ts_event"));
+}
+
+#[test]
+fn json_iso_timestamps_and_decimal_prices() {
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--json",
+            "--iso-timestamps",
+            "--decimal-prices",
+            "--fields",
+            "ts_event,price",
+        ])
+        .assert()
+        .success()
+        .stdout(starts_with(
+            "{\"ts_event\":\"2020-12-28T13:00:00.098821953Z\",\"price\":\"3720.250000000\"}\n",
+        ));
+}
+
+#[test]
+fn select_unknown_field() {
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--json",
+            "--fields",
+            "ts_event,bid_px_00",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("Unknown column 'bid_px_00'"));
+}
+
+#[test]
+fn quote_preset() {
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbp-10.dbz"),
+            "--csv",
+            "--preset",
+            "quote",
+            "--map-stype",
+            "native",
+        ])
+        .assert()
+        .success()
+        .stdout(starts_with(
+            "ts_recv,ts_event,product_id,bid_px_00,bid_sz_00,ask_px_00,ask_sz_00,symbol\n",
+        ))
+        .stderr(is_empty());
+}
+
+#[test]
+fn char_format() {
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--json",
+            "--char-format",
+            "char",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("\"action\":\"T\""));
+    cmd()
+        .args([&format!("{DBZ_PATH}/test_data.trades.dbz"), "--csv"])
+        .assert()
+        .success()
+        .stdout(contains(",84,"));
+}
+
+#[test]
+fn levels() {
+    let output = cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbp-10.dbz"),
+            "--csv",
+            "--levels",
+            "3",
+        ])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    let header = output.lines().next().unwrap();
+    assert!(header.ends_with(",bid_ct_02,ask_ct_02"), "{header}");
+    for line in output.lines() {
+        assert_eq!(line.split(',').count(), 13 + 3 * 6);
+    }
+}
+
+#[test]
+fn research_preset_with_levels() {
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbp-10.dbz"),
+            "--json",
+            "--preset",
+            "research",
+            "--levels",
+            "1",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("\"ask_ct_00\""))
+        .stdout(contains("bid_px_01").not());
+}
+
+#[test]
+fn adjustments() {
+    let adjustments_file = NamedTempFile::new().unwrap();
+    fs::write(
+        adjustments_file.path(),
+        "symbol,start_date,end_date,price_multiplier,size_multiplier\nESH1,2020-12-01,2021-01-01,0.5,2\n",
+    )
+    .unwrap();
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--json",
+            "--adjustments",
+            adjustments_file.path().to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(contains("\"price\":1860125000000,\"size\":10,"));
+}
+
+#[test]
+fn unavailable_preset() {
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "--csv",
+            "--preset",
+            "trade-tape",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("preset isn't available for schema mbo"));
+}
+
+#[test]
+fn cant_specify_fields_and_preset() {
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--csv",
+            "--fields",
+            "price",
+            "--preset",
+            "trade-tape",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("cannot be used with"));
+}
+
+#[test]
+fn json_array() {
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "--json",
+            "--json-array",
+        ])
+        .assert()
+        .success()
+        .stdout(starts_with("[{"))
+        .stdout(contains("},{"))
+        .stdout(ends_with("}]\n"))
+        .stderr(is_empty());
+}
+
+#[test]
+fn cant_specify_json_array_and_csv() {
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "--csv",
+            "--json-array",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("cannot be used with"));
+}
+
+#[test]
+fn dry_run() {
+    let output_dir = tempdir().unwrap();
+    let output_path = format!("{}/a.csv", output_dir.path().to_string_lossy());
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.mbp-10.dbz"),
+            "--output",
+            &output_path,
+            "--dry-run",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("(mbp-10, 2 records)"))
+        .stdout(contains("Encoding:  CSV"))
+        .stdout(contains("Estimate:  2 records"))
+        .stderr(is_empty());
+    assert!(!std::path::Path::new(&output_path).exists());
+}
+
+#[test]
+fn dry_run_filtered() {
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.ohlcv-1m.dbz"),
+            "--csv",
+            "--tail",
+            "1",
+            "--dry-run",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("Sample:    1 records"))
+        .stdout(contains("Estimate:  1 records"));
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.ohlcv-1m.dbz"),
+            "--csv",
+            "--product-id",
+            "1",
+            "--dry-run",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("Sample:    0 records"))
+        .stdout(contains("Estimate:  0 records"));
+}
+
+#[test]
+fn dry_run_truncated() {
+    let input = fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+    // the sample can't be decoded, so there's nothing to estimate from
+    cmd()
+        .args(["-", "--csv", "--dry-run", "--json-errors"])
+        .write_stdin(&input[..input.len() - 20])
+        .assert()
+        .failure()
+        .stdout(is_empty())
+        .stderr(starts_with("{\"code\":\"corrupt_input\""))
+        .stderr(contains("Failed to read record 0"));
+}
+
+#[test]
+fn dry_run_existing_output() {
+    let output_file = NamedTempFile::new().unwrap();
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--output",
+            output_file.path().to_str().unwrap(),
+            "--json",
+            "--dry-run",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("exists, pass --force to overwrite"))
+        .stdout(contains("Encoding:  NDJSON"));
+    assert_eq!(fs::metadata(output_file.path()).unwrap().len(), 0);
+}
+
+#[test]
+fn config_file_defaults() {
+    let config_dir = tempdir().unwrap();
+    fs::create_dir(config_dir.path().join("dbz")).unwrap();
+    fs::write(
+        config_dir.path().join("dbz/config.toml"),
+        "encoding = \"csv\"\nfields = [\"ts_event\", \"price\"]\n",
+    )
+    .unwrap();
+    cmd()
+        .env("XDG_CONFIG_HOME", config_dir.path())
+        .arg(format!("{DBZ_PATH}/test_data.trades.dbz"))
+        .assert()
+        .success()
+        .stdout(starts_with("ts_event,price\n"));
+    // options on the command line take precedence
+    cmd()
+        .env("XDG_CONFIG_HOME", config_dir.path())
+        .args([&format!("{DBZ_PATH}/test_data.trades.dbz"), "--json"])
+        .assert()
+        .success()
+        .stdout(starts_with("{\"ts_event\":"));
+}
+
+#[test]
+fn config_file_output_dir() {
+    let output_dir = tempdir().unwrap();
+    let config_file = NamedTempFile::new().unwrap();
+    fs::write(
+        config_file.path(),
+        format!(
+            "encoding = \"json\"\noutput_dir = \"{}/{{dataset}}/{{schema}}\"\n",
+            output_dir.path().to_str().unwrap()
+        ),
+    )
+    .unwrap();
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--config",
+            config_file.path().to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(is_empty());
+    let contents = fs::read_to_string(
+        output_dir
+            .path()
+            .join("GLBX.MDP3/trades/test_data.trades.json"),
+    )
+    .unwrap();
+    assert_eq!(contents.lines().count(), 2);
+}
+
+#[test]
+fn invalid_config_file() {
+    let config_file = NamedTempFile::new().unwrap();
+    fs::write(config_file.path(), "threads = 4\n").unwrap();
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--json",
+            "--config",
+            config_file.path().to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("Invalid config file"));
+}
+
+#[test]
+fn missing_config_file() {
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--json",
+            "--config",
+            "/nonexistent/config.toml",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("Unable to read config file"));
+}
+
+#[test]
+fn json_errors_corrupt_input() {
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/../../README.md"),
+            "--json",
+            "--json-errors",
+        ])
+        .assert()
+        .failure()
+        .stderr(starts_with("{\"code\":\"corrupt_input\",\"message\":"))
+        .stderr(contains("\"file\":"))
+        .stderr(contains("\"record_index\":null"));
+}
+
+#[test]
+fn json_errors_corrupt_record() {
+    let input = fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+    // the metadata's length follows the 4-byte Zstd skippable frame magic
+    let metadata_len = 8 + u32::from_le_bytes(input[4..8].try_into().unwrap()) as usize;
+    let mut records = zstd::decode_all(&input[metadata_len..]).unwrap();
+    // the rtype of the second record follows the length in its header
+    let record_len = records.len() / 2;
+    records[record_len + 1] = 0xEE;
+    let mut modified = input[..metadata_len].to_vec();
+    modified.extend(zstd::encode_all(records.as_slice(), 0).unwrap());
+    cmd()
+        .args(["-", "--json", "--json-errors"])
+        .write_stdin(modified)
+        .assert()
+        .failure()
+        .stderr(starts_with(
+            "{\"code\":\"corrupt_input\",\"message\":\"Record 1 has rtype 0xee",
+        ))
+        .stderr(contains(format!(
+            "\"byte_offset\":{record_len},\"record_index\":1}}"
+        )));
+}
+
+#[test]
+fn json_errors_output_exists() {
+    let output_file = NamedTempFile::new().unwrap();
+    cmd()
+        .args([
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--output",
+            output_file.path().to_str().unwrap(),
+            "--json",
+            "--json-errors",
+        ])
+        .assert()
+        .failure()
+        .stderr(starts_with("{\"code\":\"output_exists\""));
+}
+
+#[test]
+fn json_errors_invalid_argument() {
+    cmd()
+        .args(["--json-errors", "--json", "--csv", "a.dbz"])
+        .assert()
+        .code(2)
+        .stderr(starts_with("{\"code\":\"invalid_argument\""));
+}
+
+#[test]
+fn stats_histogram() {
+    cmd()
+        .args([
+            "stats",
+            &format!("{DBZ_PATH}/test_data.mbp-1.dbz"),
+            "--histogram",
+            "1h",
+        ])
+        .assert()
+        .success()
+        .stdout(starts_with(
+            "ts_start,count\n1609160400000000000,2\n1609164000000000000,0\n",
+        ))
+        .stderr(is_empty());
+}
+
+#[test]
+fn stats_histogram_by_product_id() {
+    cmd()
+        .args([
+            "stats",
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--histogram",
+            "1d",
+            "--by-product-id",
+        ])
+        .assert()
+        .success()
+        .stdout("ts_start,product_id,count\n1609113600000000000,5482,2\n");
+}
+
+#[test]
+fn stats_rates() {
+    cmd()
+        .args([
+            "stats",
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--rates",
+            "1s",
+        ])
+        .assert()
+        .success()
+        .stdout("product_id,record_count,p50,p90,p99,max,bursts\n5482,2,0,0,0,2,0\n");
+}
+
+#[test]
+fn stats_rates_json_bursts() {
+    cmd()
+        .args([
+            "stats",
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--rates",
+            "1d",
+            "--burst-threshold",
+            "2",
+            "--json",
+        ])
+        .assert()
+        .success()
+        .stdout(contains(
+            "\"bursts\":[{\"start\":1609113600000000000,\"count\":2}]",
+        ));
+}
+
+#[test]
+fn stats_rates_conflicts_with_histogram_args() {
+    cmd()
+        .args([
+            "stats",
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--rates",
+            "1s",
+            "--by-product-id",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("cannot be used with"));
+}
+
+#[test]
+fn stats_summary() {
+    cmd()
+        .args([
+            "stats",
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--summary",
+        ])
+        .assert()
+        .success()
+        .stdout(starts_with(
+            "records:         2\nfirst ts_event:  1609160400098821953\n",
+        ))
+        .stdout(contains(
+            "sequence gaps:   1\n  record 1: publisher 1 sequence jumps from 1170380 to 1170414\n",
+        ));
+    cmd()
+        .args([
+            "stats",
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--summary",
+            "--json",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("\"product_id_count\":1,\"volume\":26,"));
+}
+
+#[test]
+fn stats_invalid_duration() {
+    cmd()
+        .args([
+            "stats",
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--histogram",
+            "1w",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("Unknown duration unit 'w'"));
+}
+
+#[test]
+fn diff_same_file() {
+    let input = format!("{DBZ_PATH}/test_data.trades.dbz");
+    cmd()
+        .args(["diff", &input, &input, "--ignore", "ts_in_delta,sequence"])
+        .assert()
+        .success()
+        .stdout("No differences found\n");
+}
+
+#[test]
+fn diff_different_files() {
+    let left = format!("{DBZ_PATH}/test_data.trades.dbz");
+    let right = format!("{DBZ_PATH}/test_data.mbo.dbz");
+    cmd()
+        .args(["diff", &left, &right])
+        .assert()
+        .failure()
+        .stdout(starts_with("record 0:\n  rtype: "))
+        .stderr(contains("Found differences"));
+    cmd()
+        .args(["diff", &left, &right, "--json"])
+        .assert()
+        .failure()
+        .stdout(starts_with(
+            "{\"kind\":\"changed\",\"index\":0,\"fields\":[{\"field\":\"rtype\"",
+        ));
+}
+
+#[test]
+fn doctor_healthy_file() {
+    cmd()
+        .args(["doctor", &format!("{DBZ_PATH}/test_data.mbo.dbz")])
+        .assert()
+        .success()
+        .stdout(starts_with("PASS metadata: version 1"))
+        .stdout(contains("PASS records: decoded 2 records"))
+        .stdout(ends_with("6 of 6 checks passed\n"));
+}
+
+#[test]
+fn doctor_truncated_file() {
+    let input = fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+    let mut truncated = NamedTempFile::new().unwrap();
+    truncated.write_all(&input[..input.len() - 10]).unwrap();
+    cmd()
+        .args(["doctor", &truncated.path().to_string_lossy()])
+        .assert()
+        .failure()
+        .stdout(contains("FAIL records: "))
+        .stdout(contains("  fix: The file is likely truncated"))
+        .stderr(contains("Found problems"));
+}
+
+#[test]
+fn doctor_not_dbz() {
+    cmd()
+        .args([
+            "doctor",
+            &format!("{}/Cargo.toml", env!("CARGO_MANIFEST_DIR")),
+        ])
+        .assert()
+        .failure()
+        .stdout(starts_with("FAIL metadata: couldn't read the metadata"))
+        .stdout(ends_with("0 of 1 checks passed\n"));
+}
+
+#[test]
+fn spec() {
+    cmd()
+        .arg("spec")
+        .assert()
+        .success()
+        .stdout(starts_with("{\n  \"version\": 1,"))
+        .stdout(contains("\"name\": \"TickMsg\""));
+}
+
+#[test]
+fn spec_c_header() {
+    cmd()
+        .args(["spec", "--c-header"])
+        .assert()
+        .success()
+        .stdout(starts_with("/* Record layouts of DBZ version 1"))
+        .stdout(contains("typedef struct TickMsg {"))
+        .stdout(ends_with("#endif /* DBZ_V1_H */\n"));
+}
+
+#[test]
+fn validate_timing() {
+    cmd()
+        .args([
+            "validate",
+            &format!("{DBZ_PATH}/test_data.mbp-10.dbz"),
+            "--timing",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("publisher_id 1: 2 records"))
+        .stdout(ends_with("No timing issues found\n"));
+}
+
+#[test]
+fn validate_timing_outliers() {
+    cmd()
+        .args([
+            "validate",
+            &format!("{DBZ_PATH}/test_data.mbp-10.dbz"),
+            "--timing",
+            "--ts-in-delta-threshold",
+            "1us",
+            "--json",
+            "--json-errors",
+        ])
+        .assert()
+        .failure()
+        .stdout(contains(
+            "\"ts_in_delta_outliers\":{\"count\":2,\"record_indices\":[0,1]}",
+        ))
+        .stderr(starts_with("{\"code\":\"validation_failed\""));
+}
+
+#[test]
+fn validate_continuity() {
+    let input = format!("{DBZ_PATH}/test_data.trades.dbz");
+    cmd()
+        .args(["validate", &input, &input, "--continuity"])
+        .assert()
+        .failure()
+        .stdout(contains(format!("{input} -> {input}:")))
+        .stdout(contains("  overlap of 39600000000000ns"))
+        .stdout(contains(
+            "  publisher_id 1: sequence jumps from 1170414 to 1170380",
+        ))
+        .stderr(contains("Found continuity issues"));
+}
+
+#[test]
+fn validate_continuity_json() {
+    cmd()
+        .args([
+            "validate",
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "--continuity",
+            "--json",
+        ])
+        .assert()
+        .failure()
+        .stdout(starts_with(
+            "{\"boundaries\":[{\"index\":1,\"issues\":[{\"kind\":\"schema_mismatch\",\"previous\":\"trades\",\"next\":\"mbo\"}",
+        ));
+}
+
+#[test]
+fn validate_continuity_requires_multiple_files() {
+    cmd()
+        .args([
+            "validate",
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--continuity",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("requires at least two files"));
+}
+
+#[test]
+fn validate_conformance() {
+    cmd()
+        .args([
+            "validate",
+            &format!("{DBZ_PATH}/test_data.tbbo.dbz"),
+            "--conformance",
+        ])
+        .assert()
+        .success()
+        .stdout("schema tbbo: 2 records\nNo conformance issues found\n");
+}
+
+#[test]
+fn validate_conformance_json() {
+    cmd()
+        .args([
+            "validate",
+            &format!("{DBZ_PATH}/test_data.mbp-1.dbz"),
+            "--conformance",
+            "--json",
+        ])
+        .assert()
+        .success()
+        .stdout(starts_with(
+            "{\"schema\":\"mbp-1\",\"record_count\":2,\"expected_record_count\":2,",
+        ));
+}
+
+#[test]
+fn validate_integrity() {
+    cmd()
+        .args([
+            "validate",
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            "--integrity",
+        ])
+        .assert()
+        .success()
+        .stdout("schema mbo: 2 records\nNo integrity issues found\n");
+}
+
+#[test]
+fn validate_integrity_lists_every_problem() {
+    let mut input = fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+    // invalidate both the schema and the compression in the fixed-length metadata
+    input[28] = 0xFF;
+    input[62] = 0xFF;
+    let mut corrupt_file = NamedTempFile::new().unwrap();
+    corrupt_file.write_all(&input).unwrap();
+    cmd()
+        .args([
+            "validate",
+            &format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            corrupt_file.path().to_str().unwrap(),
+            "--integrity",
+            "--timing",
+        ])
+        .assert()
+        .failure()
+        .stdout(contains("No integrity issues found"))
+        .stdout(contains("  byte 28: Failed to read schema"))
+        .stdout(contains("  byte 62: Failed to parse compression"))
+        .stderr(contains("Found integrity issues"));
+}
+
+#[test]
+fn validate_integrity_json() {
+    let input = fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+    cmd()
+        .args(["validate", "-", "--integrity", "--json"])
+        .write_stdin(&input[..input.len() - 5])
+        .assert()
+        .failure()
+        .stdout(contains("\"record_index\":0,"));
+}
+
+#[test]
+fn top_by_volume() {
+    cmd()
+        .args([
+            "top",
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--by",
+            "volume",
+            "-n",
+            "5",
+        ])
+        .assert()
+        .success()
+        .stdout("product_id,symbol,record_count,volume\n5482,ESH1,2,26\n");
+}
+
+#[test]
+fn top_with_output() {
+    let output_dir = tempdir().unwrap();
+    let output_path = format!("{}/top.dbz", output_dir.path().to_string_lossy());
+    cmd()
+        .args([
+            "top",
+            &format!("{DBZ_PATH}/test_data.mbp-1.dbz"),
+            "--output",
+            &output_path,
+        ])
+        .assert()
+        .success()
+        .stdout(contains("5482,ESH1,2,"));
+    cmd()
+        .args([&output_path, "--json"])
+        .assert()
+        .success()
+        .stdout(contains("\"product_id\":5482").count(2));
+}
+
+#[test]
+fn top_stdin_with_output() {
+    let mut input = Vec::new();
+    fs::File::open(format!("{DBZ_PATH}/test_data.trades.dbz"))
+        .unwrap()
+        .read_to_end(&mut input)
+        .unwrap();
+    cmd()
+        .args(["top", "-", "--output", "top.dbz"])
+        .write_stdin(input)
+        .assert()
+        .failure()
+        .stderr(contains("Can't save the records of standard input"));
+}
+
+#[test]
+fn cant_pass_conversion_args_with_subcommand() {
+    cmd()
+        .args([
+            "--json",
+            "stats",
+            &format!("{DBZ_PATH}/test_data.trades.dbz"),
+            "--histogram",
+            "1d",
+        ])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn read_from_stdin() {
+    let path = format!("{DBZ_PATH}/test_data.mbp-10.dbz");
+    let read_from_stdin_output = cmd()
+        .args([
+            "-", // STDIN
+            "--json",
+        ])
+        // Pipe input from file
+        .pipe_stdin(&path)
         .unwrap()
         .ok()
         .unwrap();
-    let read_from_file_output = cmd().args(&[&path, "--json"]).ok().unwrap();
+    let read_from_file_output = cmd().args([&path, "--json"]).ok().unwrap();
     assert_eq!(read_from_stdin_output.stdout, read_from_file_output.stdout);
     assert!(read_from_stdin_output.stderr.is_empty());
     assert!(read_from_file_output.stderr.is_empty());
 }
 
+#[test]
+fn read_raw_body() {
+    let path = format!("{DBZ_PATH}/test_data.trades.dbz");
+    let input = fs::read(&path).unwrap();
+    // the metadata is a skippable frame whose size follows its magic number
+    let metadata_size = u32::from_le_bytes(input[4..8].try_into().unwrap()) as usize;
+    let mut body = NamedTempFile::new().unwrap();
+    body.write_all(&input[8 + metadata_size..]).unwrap();
+    let expected = cmd().args([&path, "--json"]).ok().unwrap();
+    cmd()
+        .args([
+            &body.path().to_string_lossy(),
+            "--raw-body",
+            "--schema",
+            "trades",
+            "--json",
+        ])
+        .assert()
+        .success()
+        .stdout(String::from_utf8(expected.stdout).unwrap());
+    cmd()
+        .args(["-", "--raw-body", "--schema", "trades", "--json"])
+        .pipe_stdin(body.path())
+        .unwrap()
+        .assert()
+        .success()
+        .stdout(contains("\"product_id\":"));
+}
+
+#[test]
+fn raw_body_requires_schema() {
+    cmd()
+        .args([&format!("{DBZ_PATH}/test_data.trades.dbz"), "--raw-body"])
+        .assert()
+        .failure()
+        .stderr(contains("--schema"));
+}
+
 #[test]
 fn help() {
     cmd()
@@ -287,3 +2058,50 @@ fn version() {
         .success()
         .stdout(contains(env!("CARGO_PKG_VERSION")));
 }
+
+/// Re-encodes the test trades to a file with a checksum footer.
+fn write_checksummed_trades() -> NamedTempFile {
+    let dbz = dbz_lib::Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+    let options = dbz_lib::WriterOptions {
+        should_write_checksum: true,
+        ..Default::default()
+    };
+    let file = NamedTempFile::new().unwrap();
+    let mut writer =
+        dbz_lib::DbzWriter::with_options(file.reopen().unwrap(), dbz.metadata(), &options).unwrap();
+    for record in dbz.into_record_iter().unwrap() {
+        match record.unwrap() {
+            dbz_lib::Record::Trades(trade) => writer.write_record(&trade).unwrap(),
+            record => panic!("Unexpected record {record:?}"),
+        }
+    }
+    writer.finish().unwrap();
+    file
+}
+
+#[test]
+fn verify_checksum() {
+    let file = write_checksummed_trades();
+    cmd()
+        .args(["verify", file.path().to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(ends_with(": checksum OK\n"));
+    cmd()
+        .args(["verify", &format!("{DBZ_PATH}/test_data.trades.dbz")])
+        .assert()
+        .success()
+        .stdout(ends_with(": no checksum to verify\n"));
+}
+
+#[test]
+fn verify_truncated() {
+    let input = fs::read(write_checksummed_trades().path()).unwrap();
+    cmd()
+        .args(["verify", "-", "--json-errors"])
+        .write_stdin(&input[..input.len() - 5])
+        .assert()
+        .failure()
+        .stderr(contains("\"code\":\"corrupt_input\""))
+        .stderr(contains("Missing checksum footer"));
+}
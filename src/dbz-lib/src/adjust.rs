@@ -0,0 +1,245 @@
+//! Adjustments of prices and sizes for corporate actions and futures rolls.
+use std::{collections::HashMap, fmt, io};
+
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use time::Date;
+
+/// Multipliers applied to the prices and sizes of records.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Adjustment {
+    /// The multiplier applied to every price field.
+    pub price_multiplier: f64,
+    /// The multiplier applied to every size field, such as `size`, `bid_sz`, and
+    /// `volume`. Adjusted sizes are rounded to the nearest integer.
+    pub size_multiplier: f64,
+}
+
+/// Supplies the [`Adjustment`] to apply to records of a symbol on a date. Set
+/// [`OutputOptions::adjuster`](crate::OutputOptions::adjuster) to apply one when
+/// writing records to any [`OutputEncoding`](crate::OutputEncoding).
+pub trait Adjuster: fmt::Debug + Send + Sync {
+    /// Returns the adjustment for records of the native `symbol` with a `ts_event`
+    /// on the UTC `date`, or `None` to leave them unchanged.
+    fn adjustment(&self, symbol: &str, date: Date) -> Option<Adjustment>;
+}
+
+/// An [`Adjuster`] backed by a table of adjustments for date ranges of each symbol.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdjustmentTable {
+    entries: HashMap<String, Vec<AdjustmentEntry>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct AdjustmentEntry {
+    start_date: Date,
+    end_date: Date,
+    adjustment: Adjustment,
+}
+
+#[derive(Deserialize)]
+struct AdjustmentRow {
+    symbol: String,
+    start_date: String,
+    end_date: String,
+    price_multiplier: f64,
+    size_multiplier: f64,
+}
+
+impl AdjustmentTable {
+    /// Adds `adjustment` for `symbol` from `start_date` until, but excluding,
+    /// `end_date`. When ranges overlap, the adjustment added first takes precedence.
+    pub fn insert(
+        &mut self,
+        symbol: impl Into<String>,
+        start_date: Date,
+        end_date: Date,
+        adjustment: Adjustment,
+    ) {
+        self.entries
+            .entry(symbol.into())
+            .or_default()
+            .push(AdjustmentEntry {
+                start_date,
+                end_date,
+                adjustment,
+            });
+    }
+
+    /// Reads a table from CSV with the columns `symbol`, `start_date`, `end_date`,
+    /// `price_multiplier`, and `size_multiplier`. Dates are formatted as
+    /// `YYYY-MM-DD` and `end_date` is exclusive.
+    ///
+    /// # Errors
+    /// This function returns an error if `reader` can't be read or doesn't contain a
+    /// valid table.
+    pub fn from_csv(reader: impl io::Read) -> anyhow::Result<Self> {
+        let mut res = Self::default();
+        let mut csv_reader = csv::Reader::from_reader(reader);
+        for (i, row) in csv_reader.deserialize().enumerate() {
+            let row: AdjustmentRow =
+                row.with_context(|| format!("Failed to read adjustment at row {i}"))?;
+            let start_date = parse_date(&row.start_date)
+                .with_context(|| format!("Invalid start_date at row {i}"))?;
+            let end_date = parse_date(&row.end_date)
+                .with_context(|| format!("Invalid end_date at row {i}"))?;
+            res.insert(
+                row.symbol,
+                start_date,
+                end_date,
+                Adjustment {
+                    price_multiplier: row.price_multiplier,
+                    size_multiplier: row.size_multiplier,
+                },
+            );
+        }
+        Ok(res)
+    }
+}
+
+impl Adjuster for AdjustmentTable {
+    fn adjustment(&self, symbol: &str, date: Date) -> Option<Adjustment> {
+        self.entries.get(symbol).and_then(|entries| {
+            entries
+                .iter()
+                .find(|e| e.start_date <= date && date < e.end_date)
+                .map(|e| e.adjustment)
+        })
+    }
+}
+
+/// Parses a date formatted as `YYYY-MM-DD`.
+pub(crate) fn parse_date(s: &str) -> anyhow::Result<Date> {
+    let mut parts = s.splitn(3, '-');
+    let mut next_part = |name| {
+        parts
+            .next()
+            .ok_or_else(|| anyhow!("Date '{s}' is missing the {name}, expected YYYY-MM-DD"))
+    };
+    let year = next_part("year")?.parse()?;
+    let month: u8 = next_part("month")?.parse()?;
+    let day = next_part("day")?.parse()?;
+    Date::from_calendar_date(year, month.try_into()?, day)
+        .with_context(|| format!("Couldn't convert {s} to a valid date"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{Dbz, OutputEncoding, OutputOptions};
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    fn date(year: i32, month: u8, day: u8) -> Date {
+        Date::from_calendar_date(year, month.try_into().unwrap(), day).unwrap()
+    }
+
+    #[test]
+    fn test_from_csv() {
+        let table = AdjustmentTable::from_csv(
+            "symbol,start_date,end_date,price_multiplier,size_multiplier\n\
+             ESH1,2020-12-01,2020-12-29,0.5,2\n\
+             ESH1,2020-12-29,2021-01-01,0.25,4\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let half = Adjustment {
+            price_multiplier: 0.5,
+            size_multiplier: 2.0,
+        };
+        assert_eq!(table.adjustment("ESH1", date(2020, 12, 1)), Some(half));
+        assert_eq!(table.adjustment("ESH1", date(2020, 12, 28)), Some(half));
+        assert_eq!(
+            table
+                .adjustment("ESH1", date(2020, 12, 29))
+                .unwrap()
+                .size_multiplier,
+            4.0
+        );
+        assert_eq!(table.adjustment("ESH1", date(2021, 1, 1)), None);
+        assert_eq!(table.adjustment("ESM1", date(2020, 12, 1)), None);
+    }
+
+    fn write_adjusted_csv(schema: &str, columns: &[&str], table: AdjustmentTable) -> String {
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.{schema}.dbz")).unwrap();
+        let mut buffer = Vec::new();
+        target
+            .write_to_with_options(
+                &mut buffer,
+                OutputEncoding::Csv,
+                &OutputOptions {
+                    columns: Some(columns.iter().map(|c| c.to_string()).collect()),
+                    adjuster: Some(Arc::new(table)),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_adjust_trades() {
+        let mut table = AdjustmentTable::default();
+        table.insert(
+            "ESH1",
+            date(2020, 12, 28),
+            date(2020, 12, 29),
+            Adjustment {
+                price_multiplier: 0.5,
+                size_multiplier: 3.0,
+            },
+        );
+        assert_eq!(
+            write_adjusted_csv("trades", &["price", "size"], table),
+            "price,size\n1860125000000,15\n1860125000000,63\n"
+        );
+    }
+
+    #[test]
+    fn test_adjust_ohlcv_volume() {
+        let mut table = AdjustmentTable::default();
+        table.insert(
+            "ESH1",
+            date(2020, 12, 28),
+            date(2020, 12, 29),
+            Adjustment {
+                price_multiplier: 2.0,
+                size_multiplier: 0.5,
+            },
+        );
+        assert_eq!(
+            write_adjusted_csv("ohlcv-1m", &["open", "volume"], table),
+            "open,volume\n744050000000000,177\n744200000000000,76\n"
+        );
+    }
+
+    #[test]
+    fn test_unmatched_records_are_unchanged() {
+        let mut table = AdjustmentTable::default();
+        table.insert(
+            "ESM1",
+            date(2020, 12, 28),
+            date(2020, 12, 29),
+            Adjustment {
+                price_multiplier: 0.5,
+                size_multiplier: 3.0,
+            },
+        );
+        assert_eq!(
+            write_adjusted_csv("trades", &["price", "size"], table),
+            "price,size\n3720250000000,5\n3720250000000,21\n"
+        );
+    }
+
+    #[test]
+    fn test_from_csv_invalid_date() {
+        let res = AdjustmentTable::from_csv(
+            "symbol,start_date,end_date,price_multiplier,size_multiplier\n\
+             ESH1,2020-13-01,2020-12-29,0.5,2\n"
+                .as_bytes(),
+        );
+        assert!(matches!(res, Err(e) if e.to_string().contains("Invalid start_date at row 0")));
+    }
+}
@@ -0,0 +1,185 @@
+//! Reading DBZ data asynchronously with tokio, e.g. streamed from object storage,
+//! without blocking a runtime thread.
+use anyhow::{anyhow, Context};
+use async_compression::tokio::bufread::ZstdDecoder;
+use databento_defs::enums::Schema;
+use tokio::io::{self, AsyncBufRead, AsyncRead, AsyncReadExt};
+
+use crate::{record::record_size, Metadata, Record, SizeHintPolicy};
+
+/// An asynchronous reader of DBZ data, the counterpart of [`Dbz`](crate::Dbz) for
+/// readers implementing [`AsyncBufRead`]. Records are decompressed and decoded lazily
+/// with [`AsyncDbz::next_record`].
+pub struct AsyncDbz<R: AsyncBufRead + Unpin> {
+    metadata: Metadata,
+    decoder: ZstdDecoder<R>,
+    size_hint_policy: SizeHintPolicy,
+    /// Reusable buffer sized to the record type of the schema.
+    buffer: Vec<u8>,
+    /// The number of records read.
+    i: u64,
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncDbz<R> {
+    /// Creates a new [`AsyncDbz`] from `reader`, reading the metadata.
+    ///
+    /// # Errors
+    /// This function will return an error if it is unable to parse the metadata in
+    /// `reader` or the schema is [`Schema::Statistics`].
+    pub async fn new(mut reader: R) -> anyhow::Result<Self> {
+        let mut prelude = [0u8; Metadata::PRELUDE_LEN];
+        reader
+            .read_exact(&mut prelude)
+            .await
+            .context("Failed to read metadata prelude")?;
+        let mut metadata_buffer = vec![0u8; Metadata::frame_size(&prelude)?];
+        reader
+            .read_exact(&mut metadata_buffer)
+            .await
+            .context("Failed to read metadata")?;
+        let metadata = Metadata::decode(metadata_buffer)?;
+        let record_size = record_size(metadata.schema)
+            .ok_or_else(|| anyhow!("Not implemented for schema {}", metadata.schema.as_str()))?;
+        let mut decoder = ZstdDecoder::new(reader);
+        decoder.multiple_members(true);
+        Ok(Self {
+            metadata,
+            decoder,
+            size_hint_policy: SizeHintPolicy::default(),
+            buffer: vec![0; record_size],
+            i: 0,
+        })
+    }
+
+    /// Returns the [`Schema`] of the DBZ data.
+    pub fn schema(&self) -> Schema {
+        self.metadata.schema
+    }
+
+    /// Returns a reference to the metadata read from the DBZ data.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Sets how far decoding trusts the `record_count` in the metadata, see
+    /// [`DbzStreamIter::with_size_hint_policy`](crate::DbzStreamIter::with_size_hint_policy).
+    /// When trusted, no more than `record_count` records are read.
+    pub fn with_size_hint_policy(mut self, size_hint_policy: SizeHintPolicy) -> Self {
+        self.size_hint_policy = size_hint_policy;
+        self
+    }
+
+    /// Reads the next record, or returns `None` once the records are exhausted.
+    ///
+    /// # Errors
+    /// This function returns an error if there's an issue reading or decompressing the
+    /// data, a record is truncated or of a different type than the schema's, or, when
+    /// trusting `record_count`, the data ends before it.
+    pub async fn next_record(&mut self) -> anyhow::Result<Option<Record>> {
+        let is_trusted = self.size_hint_policy == SizeHintPolicy::Trust;
+        if is_trusted && self.i >= self.metadata.record_count {
+            return Ok(None);
+        }
+        let is_read = read_record(&mut self.decoder, &mut self.buffer)
+            .await
+            .with_context(|| format!("Failed to read record {} from DBZ decoder", self.i))?;
+        if !is_read {
+            return if is_trusted {
+                Err(anyhow!(
+                    "DBZ data ended after {} records, expected {}",
+                    self.i,
+                    self.metadata.record_count
+                ))
+            } else {
+                Ok(None)
+            };
+        }
+        let record = Record::from_bytes(self.metadata.schema, &self.buffer).ok_or_else(|| {
+            anyhow!(
+                "Record {} has rtype {:#04x}, which doesn't match schema {}",
+                self.i,
+                // the rtype follows the length in the header
+                self.buffer[1],
+                self.metadata.schema.as_str()
+            )
+        })?;
+        self.i += 1;
+        Ok(Some(record))
+    }
+}
+
+/// Reads a whole record into `buffer`. Returns `false` if the data ended before the
+/// start of the record.
+async fn read_record(reader: &mut (impl AsyncRead + Unpin), buffer: &mut [u8]) -> io::Result<bool> {
+    let mut pos = 0;
+    while pos < buffer.len() {
+        match reader.read(&mut buffer[pos..]).await? {
+            0 if pos == 0 => return Ok(false),
+            0 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("record truncated after {pos} of {} bytes", buffer.len()),
+                ))
+            }
+            n => pos += n,
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::BufReader;
+
+    use super::*;
+    use crate::Dbz;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    async fn read_all(schema: &str) -> (Metadata, Vec<Record>) {
+        let file = tokio::fs::File::open(format!("{DBZ_PATH}/test_data.{schema}.dbz"))
+            .await
+            .unwrap();
+        let mut target = AsyncDbz::new(BufReader::new(file)).await.unwrap();
+        let mut records = Vec::new();
+        while let Some(record) = target.next_record().await.unwrap() {
+            records.push(record);
+        }
+        (target.metadata().clone(), records)
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn test_matches_sync_reader() {
+        for schema in ["mbo", "mbp-1", "mbp-10", "ohlcv-1s", "tbbo", "trades"] {
+            let (metadata, records) = block_on(read_all(schema));
+            let expected = Dbz::from_file(format!("{DBZ_PATH}/test_data.{schema}.dbz")).unwrap();
+            assert_eq!(&metadata, expected.metadata());
+            let expected_records = expected
+                .into_record_iter()
+                .unwrap()
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(records, expected_records, "{schema}");
+        }
+    }
+
+    #[test]
+    fn test_truncated() {
+        let bytes = std::fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        let truncated = &bytes[..bytes.len() - 10];
+        let res = block_on(async {
+            let mut target = AsyncDbz::new(truncated).await.unwrap();
+            while target.next_record().await?.is_some() {}
+            Ok::<_, anyhow::Error>(())
+        });
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,425 @@
+//! Building market-by-price order books from MBO data.
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+    mem,
+};
+
+use anyhow::anyhow;
+use databento_defs::{
+    enums::Schema,
+    record::{BidAskPair, ConstTypeId, Mbp10Msg, RecordHeader, TickMsg},
+};
+use streaming_iterator::StreamingIterator;
+
+use crate::{
+    transform::to_mbp1, write::dbz::new_manual_encoder, Dbz, Metadata, Record, WriterOptions,
+    UNDEF_PRICE,
+};
+
+/// The number of book levels in an MBP-10 record.
+const MBP10_DEPTH: usize = 10;
+
+/// Replays MBO records into an order book for each product and produces the MBP-10
+/// record each one results in, for converting MBO data to MBP data locally.
+///
+/// Adds, cancels, and modifies that change one of the top 10 price levels of their
+/// side produce a record with the `depth` of the level. Trades produce a record with
+/// a `depth` of 0 without changing the book, like in Databento's MBP data, and clears
+/// empty the book of the product. Fills and other actions don't change the book.
+///
+/// The books start empty, so the MBO data should start with a snapshot of the book or
+/// at the start of the session.
+#[derive(Debug, Default)]
+pub struct BookBuilder {
+    books: HashMap<u32, Book>,
+}
+
+impl BookBuilder {
+    /// Creates a new [`BookBuilder`] with empty books.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies `tick` to the book of its product. Returns the resulting MBP-10 record,
+    /// or `None` if `tick` doesn't change the top 10 levels of the book and isn't a
+    /// trade. Cancels and modifies of unknown orders are ignored, except modifies are
+    /// treated as adds.
+    pub fn apply(&mut self, tick: &TickMsg) -> Option<Mbp10Msg> {
+        let book = self.books.entry(tick.hd.product_id).or_default();
+        let is_bid = match tick.side as u8 {
+            b'B' => Some(true),
+            b'A' => Some(false),
+            _ => None,
+        };
+        let depth = match (tick.action as u8, is_bid) {
+            (b'T', _) => 0,
+            (b'R', _) => {
+                *book = Book::default();
+                0
+            }
+            (b'A', Some(is_bid)) => {
+                book.remove(tick.order_id, u32::MAX);
+                book.add(tick.order_id, is_bid, tick.price, tick.size)
+            }
+            (b'C', Some(_)) => {
+                let (is_bid, price) = book.remove(tick.order_id, tick.size)?;
+                book.depth(is_bid, price)
+            }
+            (b'M', Some(is_bid)) => {
+                let old_depth = book
+                    .remove(tick.order_id, u32::MAX)
+                    .map(|(was_bid, price)| (was_bid, book.depth(was_bid, price)));
+                let depth = book.add(tick.order_id, is_bid, tick.price, tick.size);
+                match old_depth {
+                    Some((was_bid, old_depth)) if was_bid == is_bid => depth.min(old_depth),
+                    _ => depth,
+                }
+            }
+            _ => return None,
+        };
+        if depth >= MBP10_DEPTH {
+            return None;
+        }
+        Some(Mbp10Msg {
+            hd: RecordHeader {
+                length: (mem::size_of::<Mbp10Msg>() / 4) as u8,
+                rtype: Mbp10Msg::TYPE_ID,
+                ..tick.hd.clone()
+            },
+            price: tick.price,
+            size: tick.size,
+            action: tick.action,
+            side: tick.side,
+            flags: tick.flags,
+            depth: depth as u8,
+            ts_recv: tick.ts_recv,
+            ts_in_delta: tick.ts_in_delta,
+            sequence: tick.sequence,
+            booklevel: book.top_levels(),
+        })
+    }
+
+    /// Returns the top 10 levels of the book of `product_id`, or `None` if no records
+    /// of it have been applied.
+    pub fn top_levels(&self, product_id: u32) -> Option<[BidAskPair; MBP10_DEPTH]> {
+        self.books.get(&product_id).map(Book::top_levels)
+    }
+}
+
+/// The order book of one product.
+#[derive(Debug, Default)]
+struct Book {
+    orders: HashMap<u64, Order>,
+    bids: BTreeMap<i64, Level>,
+    asks: BTreeMap<i64, Level>,
+}
+
+#[derive(Debug)]
+struct Order {
+    is_bid: bool,
+    price: i64,
+    size: u32,
+}
+
+/// The aggregate of the orders at a price.
+#[derive(Debug, Default)]
+struct Level {
+    size: u32,
+    count: u32,
+}
+
+impl Book {
+    /// Adds an order and returns the depth of its level.
+    fn add(&mut self, order_id: u64, is_bid: bool, price: i64, size: u32) -> usize {
+        let levels = if is_bid {
+            &mut self.bids
+        } else {
+            &mut self.asks
+        };
+        let level = levels.entry(price).or_default();
+        level.size = level.size.saturating_add(size);
+        level.count += 1;
+        self.orders.insert(
+            order_id,
+            Order {
+                is_bid,
+                price,
+                size,
+            },
+        );
+        self.depth(is_bid, price)
+    }
+
+    /// Removes `size` from an order, removing the order once none is left. Returns
+    /// the side and price of the order, or `None` if it's unknown.
+    fn remove(&mut self, order_id: u64, size: u32) -> Option<(bool, i64)> {
+        let order = self.orders.get_mut(&order_id)?;
+        let (is_bid, price) = (order.is_bid, order.price);
+        let removed = size.min(order.size);
+        order.size -= removed;
+        let is_done = order.size == 0;
+        if is_done {
+            self.orders.remove(&order_id);
+        }
+        let levels = if is_bid {
+            &mut self.bids
+        } else {
+            &mut self.asks
+        };
+        if let Some(level) = levels.get_mut(&price) {
+            level.size = level.size.saturating_sub(removed);
+            if is_done {
+                level.count = level.count.saturating_sub(1);
+            }
+            if level.count == 0 {
+                levels.remove(&price);
+            }
+        }
+        Some((is_bid, price))
+    }
+
+    /// Returns the number of levels on the side better than `price`, up to
+    /// [`MBP10_DEPTH`].
+    fn depth(&self, is_bid: bool, price: i64) -> usize {
+        if is_bid {
+            self.bids
+                .range(price.saturating_add(1)..)
+                .take(MBP10_DEPTH)
+                .count()
+        } else {
+            self.asks.range(..price).take(MBP10_DEPTH).count()
+        }
+    }
+
+    fn top_levels(&self) -> [BidAskPair; MBP10_DEPTH] {
+        let mut levels: [BidAskPair; MBP10_DEPTH] = std::array::from_fn(|_| BidAskPair {
+            bid_px: UNDEF_PRICE,
+            ask_px: UNDEF_PRICE,
+            bid_sz: 0,
+            ask_sz: 0,
+            bid_ct: 0,
+            ask_ct: 0,
+        });
+        for (level, (price, bid)) in levels.iter_mut().zip(self.bids.iter().rev()) {
+            level.bid_px = *price;
+            level.bid_sz = bid.size;
+            level.bid_ct = bid.count;
+        }
+        for (level, (price, ask)) in levels.iter_mut().zip(self.asks.iter()) {
+            level.ask_px = *price;
+            level.ask_sz = ask.size;
+            level.ask_ct = ask.count;
+        }
+        levels
+    }
+}
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Builds the order books of MBO data with a [`BookBuilder`] and writes the
+    /// resulting records of `schema`, either [`Schema::Mbp1`] or [`Schema::Mbp10`], in
+    /// the DBZ format to `writer`. For MBP-1, only records that changed the top of the
+    /// book and trades are kept. Returns the number of records written.
+    ///
+    /// The metadata's `schema` and `record_count` are rewritten to match.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] isn't [`Schema::Mbo`] or
+    /// `schema` isn't [`Schema::Mbp1`] or [`Schema::Mbp10`]. It will also return an
+    /// error if there's an issue reading the records or writing the output to
+    /// `writer`.
+    pub fn write_mbp_to(
+        self,
+        mut writer: impl io::Write + io::Seek,
+        schema: Schema,
+    ) -> anyhow::Result<u64> {
+        if self.schema() != Schema::Mbo {
+            return Err(anyhow!(
+                "Can only build books from mbo, found {}",
+                self.schema().as_str()
+            ));
+        }
+        if !matches!(schema, Schema::Mbp1 | Schema::Mbp10) {
+            return Err(anyhow!(
+                "Can only build mbp-1 or mbp-10 books, found {}",
+                schema.as_str()
+            ));
+        }
+        let mut metadata = self.metadata().clone();
+        metadata.schema = schema;
+        metadata.encode(&mut writer)?;
+        let mut encoder = new_manual_encoder(&mut writer, &WriterOptions::default())?;
+        let mut builder = BookBuilder::new();
+        let mut record_count = 0;
+        let mut ticks = self.try_into_iter::<TickMsg>()?;
+        while let Some(tick) = ticks.next() {
+            let Some(mbp10) = builder.apply(tick) else {
+                continue;
+            };
+            let record = if schema == Schema::Mbp1 {
+                if mbp10.depth != 0 {
+                    continue;
+                }
+                Record::Mbp1(to_mbp1(&mbp10))
+            } else {
+                Record::Mbp10(mbp10)
+            };
+            encoder.write_all(record.as_bytes())?;
+            record_count += 1;
+        }
+        if let Some(error) = ticks.error() {
+            return Err(anyhow!("{error:#}"));
+        }
+        encoder.finish()?;
+        Metadata::update_encoded(
+            &mut writer,
+            metadata.start,
+            metadata.end,
+            metadata.limit,
+            record_count,
+        )?;
+        writer.flush()?;
+        Ok(record_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    use databento_defs::record::Mbp1Msg;
+
+    use super::*;
+    use crate::{write::test_data::RECORD_HEADER, DbzWriter};
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    fn tick(action: u8, side: u8, order_id: u64, price: i64, size: u32) -> TickMsg {
+        TickMsg {
+            hd: RecordHeader {
+                length: (mem::size_of::<TickMsg>() / 4) as u8,
+                rtype: TickMsg::TYPE_ID,
+                ..RECORD_HEADER
+            },
+            order_id,
+            price,
+            size,
+            flags: 0,
+            channel_id: 0,
+            action: action as i8,
+            side: side as i8,
+            ts_recv: RECORD_HEADER.ts_event,
+            ts_in_delta: 0,
+            sequence: order_id as u32,
+        }
+    }
+
+    #[test]
+    fn test_book_builder() {
+        let mut target = BookBuilder::new();
+        let top = target.apply(&tick(b'A', b'B', 1, 100, 5)).unwrap();
+        assert_eq!(top.depth, 0);
+        assert_eq!(top.hd.rtype, Mbp10Msg::TYPE_ID);
+        assert_eq!((top.booklevel[0].bid_px, top.booklevel[0].bid_sz), (100, 5));
+        assert_eq!(top.booklevel[0].ask_px, UNDEF_PRICE);
+        // a worse bid is the second level
+        assert_eq!(target.apply(&tick(b'A', b'B', 2, 99, 3)).unwrap().depth, 1);
+        let joined = target.apply(&tick(b'A', b'B', 3, 100, 2)).unwrap();
+        assert_eq!(
+            (joined.booklevel[0].bid_sz, joined.booklevel[0].bid_ct),
+            (7, 2)
+        );
+        assert_eq!(target.apply(&tick(b'A', b'A', 4, 110, 4)).unwrap().depth, 0);
+        // a partial cancel
+        let partial = target.apply(&tick(b'C', b'B', 1, 100, 1)).unwrap();
+        assert_eq!(
+            (partial.booklevel[0].bid_sz, partial.booklevel[0].bid_ct),
+            (6, 2)
+        );
+        // a trade doesn't change the book
+        let trade = target.apply(&tick(b'T', b'N', 0, 110, 1)).unwrap();
+        assert_eq!(trade.depth, 0);
+        assert_eq!(trade.booklevel, partial.booklevel);
+        // fills and cancels of unknown orders are ignored
+        assert!(target.apply(&tick(b'F', b'A', 4, 110, 1)).is_none());
+        assert!(target.apply(&tick(b'C', b'A', 5, 110, 1)).is_none());
+        // modifying the second level to a new best bid
+        let modified = target.apply(&tick(b'M', b'B', 2, 105, 3)).unwrap();
+        assert_eq!(modified.depth, 0);
+        assert_eq!(modified.booklevel[0].bid_px, 105);
+        assert_eq!(modified.booklevel[1].bid_px, 100);
+        assert_eq!(modified.booklevel[2].bid_px, UNDEF_PRICE);
+        target.apply(&tick(b'C', b'B', 1, 100, 4)).unwrap();
+        let levels = target.top_levels(RECORD_HEADER.product_id).unwrap();
+        assert_eq!((levels[1].bid_sz, levels[1].bid_ct), (2, 1));
+        target.apply(&tick(b'R', b'N', 0, 0, 0)).unwrap();
+        let levels = target.top_levels(RECORD_HEADER.product_id).unwrap();
+        assert_eq!(levels[0].bid_px, UNDEF_PRICE);
+        assert!(target.top_levels(RECORD_HEADER.product_id + 1).is_none());
+    }
+
+    #[test]
+    fn test_book_builder_beyond_depth() {
+        let mut target = BookBuilder::new();
+        for i in 0..MBP10_DEPTH as u64 {
+            target
+                .apply(&tick(b'A', b'A', i, 100 + i as i64, 1))
+                .unwrap();
+        }
+        // an 11th level isn't in MBP-10
+        assert!(target.apply(&tick(b'A', b'A', 10, 200, 1)).is_none());
+        assert!(target.apply(&tick(b'C', b'A', 10, 200, 1)).is_none());
+    }
+
+    #[test]
+    fn test_write_mbp_to() {
+        let metadata = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz"))
+            .unwrap()
+            .metadata()
+            .clone();
+        let mut writer = DbzWriter::new(Cursor::new(Vec::new()), &metadata).unwrap();
+        for tick in [
+            tick(b'A', b'B', 1, 100, 5),
+            tick(b'A', b'B', 2, 99, 3),
+            tick(b'T', b'N', 0, 100, 1),
+        ] {
+            writer.write_record(&tick).unwrap();
+        }
+        let mut source = writer.finish().unwrap();
+        source.seek(SeekFrom::Start(0)).unwrap();
+        let mut output = Cursor::new(Vec::new());
+        let record_count = Dbz::new(source.clone())
+            .unwrap()
+            .write_mbp_to(&mut output, Schema::Mbp1)
+            .unwrap();
+        // the second add is below the top of the book
+        assert_eq!(record_count, 2);
+        output.seek(SeekFrom::Start(0)).unwrap();
+        let target = Dbz::new(output).unwrap();
+        assert_eq!(target.schema(), Schema::Mbp1);
+        assert_eq!(target.metadata().record_count, 2);
+        let records = target
+            .try_into_fallible_iter::<Mbp1Msg>()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(records[1].action, b'T' as i8);
+        assert_eq!(records[1].booklevel[0].bid_sz, 5);
+        let mut output = Cursor::new(Vec::new());
+        let record_count = Dbz::new(source)
+            .unwrap()
+            .write_mbp_to(&mut output, Schema::Mbp10)
+            .unwrap();
+        assert_eq!(record_count, 3);
+    }
+
+    #[test]
+    fn test_write_mbp_to_requires_mbo() {
+        let source = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let res = source.write_mbp_to(Cursor::new(Vec::new()), Schema::Mbp10);
+        assert!(matches!(res, Err(e) if e.to_string().contains("Can only build books from mbo")));
+        let source = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        let res = source.write_mbp_to(Cursor::new(Vec::new()), Schema::Tbbo);
+        assert!(matches!(res, Err(e) if e.to_string().contains("Can only build mbp-1 or mbp-10")));
+    }
+}
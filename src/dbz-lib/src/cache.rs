@@ -0,0 +1,229 @@
+//! Caching of DBZ metadata for services that repeatedly open the same files.
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{Dbz, Metadata};
+
+/// A cache of the decoded metadata of DBZ files and the offset of their records, so
+/// opening a file again skips reading and decompressing its metadata. Entries are
+/// keyed by path and invalidated when the file's modification time or size changes.
+///
+/// The cache can be shared between threads. Once it holds `capacity` files, the
+/// least recently opened file is evicted.
+#[derive(Debug)]
+pub struct MetadataCache {
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<PathBuf, CacheEntry>,
+    /// Incremented on every access for finding the least recently used entry.
+    clock: u64,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    modified: SystemTime,
+    len: u64,
+    metadata: Metadata,
+    /// The position of the first record, right after the metadata frame.
+    records_offset: u64,
+    last_used: u64,
+}
+
+impl MetadataCache {
+    /// Creates an empty cache holding the metadata of at most `capacity` files.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Opens the DBZ file at `path`, reusing its cached metadata if the file hasn't
+    /// changed since it was last opened. Otherwise the metadata is read from the file
+    /// and cached.
+    ///
+    /// # Errors
+    /// This function returns an error if the file can't be opened or seeked, or if
+    /// its metadata is invalid.
+    pub fn open(&self, path: impl AsRef<Path>) -> anyhow::Result<Dbz<BufReader<File>>> {
+        let path = path.as_ref();
+        let mut file = File::open(path)
+            .with_context(|| format!("Error opening dbz file at path '{}'", path.display()))?;
+        let file_metadata = file.metadata()?;
+        let modified = file_metadata.modified()?;
+        let len = file_metadata.len();
+        {
+            let mut state = self.lock()?;
+            state.clock += 1;
+            let clock = state.clock;
+            if let Some(entry) = state.entries.get_mut(path) {
+                if entry.modified == modified && entry.len == len {
+                    entry.last_used = clock;
+                    let metadata = entry.metadata.clone();
+                    let records_offset = entry.records_offset;
+                    // don't hold the lock while seeking
+                    drop(state);
+                    file.seek(SeekFrom::Start(records_offset))?;
+                    return Ok(Dbz::from_parts(BufReader::new(file), metadata));
+                }
+            }
+        }
+        let mut reader = BufReader::new(file);
+        let metadata = Metadata::read(&mut reader)?;
+        let records_offset = reader.stream_position()?;
+        let mut state = self.lock()?;
+        state.clock += 1;
+        let last_used = state.clock;
+        if !state.entries.contains_key(path) && state.entries.len() >= self.capacity {
+            state.evict();
+        }
+        if self.capacity > 0 {
+            state.entries.insert(
+                path.to_owned(),
+                CacheEntry {
+                    modified,
+                    len,
+                    metadata: metadata.clone(),
+                    records_offset,
+                    last_used,
+                },
+            );
+        }
+        Ok(Dbz::from_parts(reader, metadata))
+    }
+
+    /// Removes the cached metadata of the file at `path`, if any.
+    pub fn invalidate(&self, path: impl AsRef<Path>) {
+        if let Ok(mut state) = self.lock() {
+            state.entries.remove(path.as_ref());
+        }
+    }
+
+    /// Removes all cached metadata.
+    pub fn clear(&self) {
+        if let Ok(mut state) = self.lock() {
+            state.entries.clear();
+        }
+    }
+
+    /// Returns the number of files with cached metadata.
+    pub fn len(&self) -> usize {
+        self.lock().map_or(0, |state| state.entries.len())
+    }
+
+    /// Returns `true` if no metadata is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> anyhow::Result<MutexGuard<'_, CacheState>> {
+        self.state
+            .lock()
+            .map_err(|_| anyhow!("Metadata cache lock was poisoned"))
+    }
+}
+
+impl CacheState {
+    fn evict(&mut self) {
+        if let Some(path) = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(path, _)| path.clone())
+        {
+            self.entries.remove(&path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write};
+
+    use databento_defs::{enums::Schema, record::OhlcvMsg};
+    use streaming_iterator::StreamingIterator;
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    fn copy_test_data(dir: &TempDir, schema: &str, name: &str) -> PathBuf {
+        let path = dir.path().join(format!("{name}.{schema}.dbz"));
+        fs::copy(format!("{DBZ_PATH}/test_data.{schema}.dbz"), &path).unwrap();
+        path
+    }
+
+    fn volumes(dbz: Dbz<BufReader<File>>) -> Vec<u64> {
+        let mut iter = dbz.try_into_iter::<OhlcvMsg>().unwrap();
+        let mut volumes = Vec::new();
+        while let Some(record) = iter.next() {
+            volumes.push(record.volume);
+        }
+        volumes
+    }
+
+    #[test]
+    fn test_open_reuses_metadata() {
+        let dir = tempdir().unwrap();
+        let path = copy_test_data(&dir, "ohlcv-1m", "reuse");
+        let target = MetadataCache::new(4);
+        let dbz = target.open(&path).unwrap();
+        assert_eq!(target.len(), 1);
+        let expected_metadata = dbz.metadata().clone();
+        assert_eq!(volumes(dbz), vec![353, 152]);
+        // change the dataset in the file without changing its size or modification time
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        let mut file = File::options().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(12)).unwrap();
+        file.write_all(b"XXXX").unwrap();
+        file.set_modified(modified).unwrap();
+        drop(file);
+        let dbz = target.open(&path).unwrap();
+        assert_eq!(dbz.metadata(), &expected_metadata);
+        assert_eq!(volumes(dbz), vec![353, 152]);
+    }
+
+    #[test]
+    fn test_open_rereads_changed_file() {
+        let dir = tempdir().unwrap();
+        let path = copy_test_data(&dir, "ohlcv-1m", "changed");
+        let target = MetadataCache::new(4);
+        assert_eq!(target.open(&path).unwrap().schema(), Schema::Ohlcv1M);
+        fs::copy(format!("{DBZ_PATH}/test_data.trades.dbz"), &path).unwrap();
+        assert_eq!(target.open(&path).unwrap().schema(), Schema::Trades);
+        assert_eq!(target.len(), 1);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let dir = tempdir().unwrap();
+        let paths = ["lru1", "lru2", "lru3"].map(|name| copy_test_data(&dir, "trades", name));
+        let target = MetadataCache::new(2);
+        target.open(&paths[0]).unwrap();
+        target.open(&paths[1]).unwrap();
+        target.open(&paths[0]).unwrap();
+        target.open(&paths[2]).unwrap();
+        assert_eq!(target.len(), 2);
+        let state = target.lock().unwrap();
+        assert!(state.entries.contains_key(&paths[0]));
+        assert!(!state.entries.contains_key(&paths[1]));
+        drop(state);
+        target.invalidate(&paths[0]);
+        assert_eq!(target.len(), 1);
+        target.clear();
+        assert!(target.is_empty());
+    }
+}
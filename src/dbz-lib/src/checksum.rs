@@ -0,0 +1,200 @@
+//! An optional CRC32 footer of the records of a DBZ file, so truncated or corrupted
+//! files can be detected instead of silently decoding fewer records.
+use std::io::{self, SeekFrom};
+
+use anyhow::Context;
+use crc32fast::Hasher;
+
+use crate::{read::FromLittleEndianSlice, Dbz, Error, Metadata};
+
+/// The annotation marking a file as ending with a checksum footer, with the name of
+/// the checksum algorithm as its value. It's in the metadata so a file truncated
+/// before its footer can be told apart from one without a footer.
+pub(crate) const CHECKSUM_KEY: &str = "checksum";
+pub(crate) const CHECKSUM_ALGORITHM: &str = "crc32";
+/// The magic number of the footer, a Zstd skippable frame that decoders skip like
+/// the metadata.
+const FOOTER_MAGIC: u32 = Metadata::ZSTD_MAGIC_RANGE.start + 1;
+/// The length of the footer: its magic number, its frame size, and the CRC32 of the
+/// compressed records before it.
+pub(crate) const FOOTER_LEN: usize = 12;
+
+/// Writes to the inner writer while computing the checksum of everything written,
+/// then appends the footer on [`ChecksumWriter::finish`].
+pub(crate) struct ChecksumWriter<W> {
+    inner: W,
+    /// `None` if no footer is written.
+    hasher: Option<Hasher>,
+}
+
+impl<W: io::Write> ChecksumWriter<W> {
+    pub fn new(inner: W, should_write_footer: bool) -> Self {
+        Self {
+            inner,
+            hasher: should_write_footer.then(Hasher::new),
+        }
+    }
+
+    /// Writes the footer, if any, and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(hasher) = self.hasher.take() {
+            self.inner.write_all(&encode_footer(hasher.finalize()))?;
+        }
+        Ok(self.inner)
+    }
+}
+
+impl<W: io::Write> io::Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: io::Seek> io::Seek for ChecksumWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+fn encode_footer(checksum: u32) -> [u8; FOOTER_LEN] {
+    let mut footer = [0; FOOTER_LEN];
+    footer[..4].copy_from_slice(FOOTER_MAGIC.to_le_bytes().as_slice());
+    footer[4..8].copy_from_slice(4u32.to_le_bytes().as_slice());
+    footer[8..].copy_from_slice(checksum.to_le_bytes().as_slice());
+    footer
+}
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Returns whether the metadata says the records end with a checksum footer,
+    /// written by [`DbzWriter`](crate::DbzWriter) with
+    /// [`WriterOptions::should_write_checksum`](crate::WriterOptions::should_write_checksum).
+    pub fn has_checksum(&self) -> bool {
+        self.metadata()
+            .annotations
+            .get(CHECKSUM_KEY)
+            .is_some_and(|algorithm| algorithm == CHECKSUM_ALGORITHM)
+    }
+
+    /// Reads the rest of the records and checks them against the checksum footer at
+    /// the end of the file. Returns `false` without reading the records if the file
+    /// has no checksum, see [`Dbz::has_checksum`]. The [`Dbz`] must be positioned at
+    /// the start of the records, as it is after [`Dbz::new`] or [`Dbz::from_file`].
+    ///
+    /// # Errors
+    /// This function returns an [`Error::ChecksumMismatch`] if the records don't match
+    /// the checksum, and an [`Error::MissingChecksum`] if the footer is missing, e.g.
+    /// because the file was truncated. It will also return an error if there's an
+    /// issue reading the records.
+    pub fn verify(self) -> anyhow::Result<bool> {
+        if !self.has_checksum() {
+            return Ok(false);
+        }
+        let (_, mut reader) = self.replace_reader(io::empty());
+        let mut hasher = Hasher::new();
+        // the last bytes read, held back until it's known they're not the footer
+        let mut tail = Vec::with_capacity(FOOTER_LEN);
+        loop {
+            let buffer = reader
+                .fill_buf()
+                .with_context(|| "Failed to read records to verify")?;
+            if buffer.is_empty() {
+                break;
+            }
+            let len = buffer.len();
+            tail.extend_from_slice(buffer);
+            reader.consume(len);
+            let hashed_len = tail.len().saturating_sub(FOOTER_LEN);
+            hasher.update(&tail[..hashed_len]);
+            tail.drain(..hashed_len);
+        }
+        if tail.len() < FOOTER_LEN
+            || u32::from_le_slice(&tail) != FOOTER_MAGIC
+            || u32::from_le_slice(&tail[4..]) != 4
+        {
+            return Err(Error::MissingChecksum.into());
+        }
+        let expected = u32::from_le_slice(&tail[8..]);
+        let actual = hasher.finalize();
+        if expected != actual {
+            return Err(Error::ChecksumMismatch { expected, actual }.into());
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use databento_defs::record::TradeMsg;
+    use streaming_iterator::StreamingIterator;
+
+    use super::*;
+    use crate::{DbzWriter, RecordIndex, WriterOptions};
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    fn write_trades(should_write_checksum: bool) -> Vec<u8> {
+        let dbz = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let metadata = dbz.metadata().clone();
+        let options = WriterOptions {
+            should_write_checksum,
+            frame_record_count: Some(1),
+            ..Default::default()
+        };
+        let mut writer =
+            DbzWriter::with_options(Cursor::new(Vec::new()), &metadata, &options).unwrap();
+        let mut records = dbz.try_into_iter::<TradeMsg>().unwrap();
+        while let Some(record) = records.next() {
+            writer.write_record(record).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_verify() {
+        let bytes = write_trades(true);
+        let dbz = Dbz::new(bytes.as_slice()).unwrap();
+        assert!(dbz.has_checksum());
+        assert!(dbz.verify().unwrap());
+        // the footer is skipped when decoding
+        let records = Dbz::new(bytes.as_slice())
+            .unwrap()
+            .try_into_iter::<TradeMsg>()
+            .unwrap();
+        assert_eq!(records.count(), 2);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checksum.dbz");
+        std::fs::write(&path, &bytes).unwrap();
+        // one frame per record and none for the footer
+        assert_eq!(RecordIndex::build(&path).unwrap().frames().len(), 2);
+        let unchecked = write_trades(false);
+        assert!(!Dbz::new(unchecked.as_slice()).unwrap().verify().unwrap());
+    }
+
+    #[test]
+    fn test_verify_corrupt() {
+        let bytes = write_trades(true);
+        // truncated after the records and within the footer
+        for len in [bytes.len() - FOOTER_LEN, bytes.len() - 1] {
+            let error = Dbz::new(&bytes[..len]).unwrap().verify().unwrap_err();
+            assert!(matches!(Error::find(&error), Some(Error::MissingChecksum)));
+        }
+        let mut corrupt = bytes.clone();
+        let i = corrupt.len() - FOOTER_LEN - 1;
+        corrupt[i] ^= 0xFF;
+        let error = Dbz::new(corrupt.as_slice()).unwrap().verify().unwrap_err();
+        assert!(matches!(
+            Error::find(&error),
+            Some(Error::ChecksumMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,131 @@
+//! Checks that records conform to the semantics of their schema.
+use std::{convert::Infallible, io};
+
+use databento_defs::enums::Schema;
+use serde::Serialize;
+
+use crate::{
+    fields::{FieldValue, FieldVisitor, RecordHandler, Scope, VisitFields},
+    Dbz, TimingIssues,
+};
+
+/// Records that don't conform to their schema, returned by
+/// [`Dbz::conformance_report`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ConformanceReport {
+    /// The schema the records were checked against.
+    pub schema: Schema,
+    /// The number of records read.
+    pub record_count: u64,
+    /// The number of records according to the metadata. Differs from `record_count`
+    /// when the data ends early or contains a record of a different type than the
+    /// schema's.
+    pub expected_record_count: u64,
+    /// TBBO records that aren't trades. Every TBBO record should be a trade with the
+    /// quote from before it.
+    pub non_trades: TimingIssues,
+}
+
+impl ConformanceReport {
+    /// Returns `true` if no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.record_count == self.expected_record_count && self.non_trades.count == 0
+    }
+}
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Checks that the records conform to [`Dbz::schema()`] beyond sharing its record
+    /// type. TBBO and MBP-1 share a record type, but every TBBO record must be a
+    /// trade.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`]. It will also return an error if there's an issue
+    /// reading the records.
+    pub fn conformance_report(self) -> anyhow::Result<ConformanceReport> {
+        let mut checker = ConformanceChecker {
+            report: ConformanceReport {
+                schema: self.schema(),
+                record_count: 0,
+                expected_record_count: self.metadata().record_count,
+                non_trades: TimingIssues::default(),
+            },
+        };
+        self.handle_records(&mut checker)?;
+        Ok(checker.report)
+    }
+}
+
+struct ConformanceChecker {
+    report: ConformanceReport,
+}
+
+impl RecordHandler for ConformanceChecker {
+    fn handle<T: VisitFields>(&mut self, record: &T) {
+        let index = self.report.record_count;
+        self.report.record_count += 1;
+        if self.report.schema == Schema::Tbbo {
+            let mut action = RecordAction(None);
+            // `RecordAction` never fails
+            let _ = record.visit_fields(&mut action);
+            if action.0 != Some(b'T' as i8) {
+                self.report.non_trades.add(index);
+            }
+        }
+    }
+}
+
+/// Picks out the `action` of a record, if it has one.
+struct RecordAction(Option<i8>);
+
+impl FieldVisitor for RecordAction {
+    type Error = Infallible;
+
+    fn visit(
+        &mut self,
+        scope: Scope,
+        name: &'static str,
+        value: FieldValue,
+    ) -> Result<(), Infallible> {
+        if let (Scope::Body, "action", FieldValue::Char(action)) = (scope, name, value) {
+            self.0 = Some(action);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    use databento_defs::record::Mbp1Msg;
+
+    use super::*;
+    use crate::write_dbz_stream;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    #[test]
+    fn test_tbbo_conforms() {
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.tbbo.dbz")).unwrap();
+        let report = target.conformance_report().unwrap();
+        assert_eq!(report.record_count, 2);
+        assert!(report.is_clean());
+    }
+
+    #[test]
+    fn test_mbp1_as_tbbo() {
+        // relabel MBP-1 data, which includes quotes, as TBBO
+        let source = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbp-1.dbz")).unwrap();
+        let mut metadata = source.metadata().clone();
+        metadata.schema = Schema::Tbbo;
+        let mut buffer = Cursor::new(Vec::new());
+        metadata.encode(&mut buffer).unwrap();
+        write_dbz_stream(&mut buffer, source.try_into_iter::<Mbp1Msg>().unwrap()).unwrap();
+        buffer.seek(SeekFrom::Start(0)).unwrap();
+        let report = Dbz::new(buffer).unwrap().conformance_report().unwrap();
+        assert!(!report.is_clean());
+        assert_eq!(report.non_trades.count, 2);
+        assert_eq!(report.non_trades.record_indices, vec![0, 1]);
+    }
+}
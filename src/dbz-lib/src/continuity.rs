@@ -0,0 +1,340 @@
+//! Checks that consecutive DBZ files, such as daily files, fit together.
+use std::{collections::BTreeMap, convert::Infallible, io, time::Duration};
+
+use databento_defs::enums::Schema;
+use serde::Serialize;
+
+use crate::{
+    fields::{FieldValue, FieldVisitor, RecordHandler, Scope, VisitFields},
+    Dbz,
+};
+
+/// Checks a sequence of DBZ files for gaps and overlaps between consecutive files.
+/// Add the files in order with [`ContinuityChecker::add`].
+#[derive(Clone, Debug)]
+pub struct ContinuityChecker {
+    tolerance: u64,
+    summaries: Vec<FileSummary>,
+}
+
+/// The problems found between consecutive files by a [`ContinuityChecker`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ContinuityReport {
+    /// The boundaries between files with problems, in order.
+    pub boundaries: Vec<FileBoundary>,
+}
+
+/// The problems found at the boundary between two consecutive files.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FileBoundary {
+    /// The index of the later file in the order they were added. The earlier file's
+    /// index is one less.
+    pub index: usize,
+    /// The problems between the two files.
+    pub issues: Vec<ContinuityIssue>,
+}
+
+/// A problem between two consecutive files.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ContinuityIssue {
+    /// The files have different schemas.
+    SchemaMismatch {
+        /// The schema of the earlier file.
+        previous: Schema,
+        /// The schema of the later file.
+        next: Schema,
+    },
+    /// The files are from different datasets.
+    DatasetMismatch {
+        /// The dataset of the earlier file.
+        previous: String,
+        /// The dataset of the later file.
+        next: String,
+    },
+    /// The later file starts more than the tolerance after the earlier file ends.
+    /// Uses the metadata's `start` and `end` if set, otherwise the first and last
+    /// `ts_event`.
+    Gap {
+        /// The end of the earlier file in UNIX epoch nanoseconds.
+        end: u64,
+        /// The start of the later file in UNIX epoch nanoseconds.
+        start: u64,
+    },
+    /// The later file starts before the earlier file ends. Like [`Self::Gap`], uses the
+    /// metadata's `start` and `end` if set.
+    Overlap {
+        /// The end of the earlier file in UNIX epoch nanoseconds.
+        end: u64,
+        /// The start of the later file in UNIX epoch nanoseconds.
+        start: u64,
+    },
+    /// The first record of the later file has a `ts_event` before the last record of
+    /// the earlier file.
+    RecordOverlap {
+        /// The `ts_event` of the last record of the earlier file.
+        last_ts_event: u64,
+        /// The `ts_event` of the first record of the later file.
+        first_ts_event: u64,
+    },
+    /// The first `sequence` of a publisher in the later file doesn't follow the last
+    /// `sequence` of the publisher in the earlier file.
+    SequenceBreak {
+        /// The publisher whose sequence numbers don't follow.
+        publisher_id: u16,
+        /// The last `sequence` of the publisher in the earlier file.
+        last_sequence: u32,
+        /// The first `sequence` of the publisher in the later file.
+        first_sequence: u32,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct FileSummary {
+    dataset: String,
+    schema: Schema,
+    start: u64,
+    end: u64,
+    first_ts_event: Option<u64>,
+    last_ts_event: Option<u64>,
+    /// The first and last sequence of each publisher.
+    sequences: BTreeMap<u16, (u32, u32)>,
+}
+
+impl ContinuityChecker {
+    /// Creates a checker that allows gaps of up to `tolerance` between files.
+    pub fn new(tolerance: Duration) -> Self {
+        Self {
+            tolerance: tolerance.as_nanos() as u64,
+            summaries: Vec::new(),
+        }
+    }
+
+    /// Reads the records of `dbz`, the file following the previously-added ones.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`]. It will also return an error if there's an issue
+    /// reading the records.
+    pub fn add<R: io::BufRead>(&mut self, dbz: Dbz<R>) -> anyhow::Result<()> {
+        let metadata = dbz.metadata();
+        let mut summary = FileSummary {
+            dataset: metadata.dataset.clone(),
+            schema: metadata.schema,
+            start: metadata.start,
+            end: metadata.end,
+            first_ts_event: None,
+            last_ts_event: None,
+            sequences: BTreeMap::new(),
+        };
+        dbz.handle_records(&mut summary)?;
+        self.summaries.push(summary);
+        Ok(())
+    }
+
+    /// Returns the problems found between the files added so far.
+    pub fn report(&self) -> ContinuityReport {
+        ContinuityReport {
+            boundaries: self
+                .summaries
+                .windows(2)
+                .enumerate()
+                .filter_map(|(i, pair)| {
+                    let issues = self.check(&pair[0], &pair[1]);
+                    (!issues.is_empty()).then_some(FileBoundary {
+                        index: i + 1,
+                        issues,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    fn check(&self, previous: &FileSummary, next: &FileSummary) -> Vec<ContinuityIssue> {
+        let mut issues = Vec::new();
+        if previous.schema != next.schema {
+            issues.push(ContinuityIssue::SchemaMismatch {
+                previous: previous.schema,
+                next: next.schema,
+            });
+        }
+        if previous.dataset != next.dataset {
+            issues.push(ContinuityIssue::DatasetMismatch {
+                previous: previous.dataset.clone(),
+                next: next.dataset.clone(),
+            });
+        }
+        let end = previous.range().map(|(_, end)| end);
+        let start = next.range().map(|(start, _)| start);
+        if let Some((end, start)) = end.zip(start) {
+            if start < end {
+                issues.push(ContinuityIssue::Overlap { end, start });
+            } else if start - end > self.tolerance {
+                issues.push(ContinuityIssue::Gap { end, start });
+            }
+        }
+        if let Some((last_ts_event, first_ts_event)) =
+            previous.last_ts_event.zip(next.first_ts_event)
+        {
+            if first_ts_event < last_ts_event {
+                issues.push(ContinuityIssue::RecordOverlap {
+                    last_ts_event,
+                    first_ts_event,
+                });
+            }
+        }
+        for (publisher_id, (_, last_sequence)) in previous.sequences.iter() {
+            if let Some((first_sequence, _)) = next.sequences.get(publisher_id) {
+                if *first_sequence != last_sequence.wrapping_add(1) {
+                    issues.push(ContinuityIssue::SequenceBreak {
+                        publisher_id: *publisher_id,
+                        last_sequence: *last_sequence,
+                        first_sequence: *first_sequence,
+                    });
+                }
+            }
+        }
+        issues
+    }
+}
+
+impl ContinuityReport {
+    /// Returns `true` if no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.boundaries.is_empty()
+    }
+}
+
+impl FileSummary {
+    /// Returns the time range covered by the file: the metadata's `start` and `end`
+    /// if set, otherwise the first and last `ts_event`.
+    fn range(&self) -> Option<(u64, u64)> {
+        if self.end > self.start {
+            Some((self.start, self.end))
+        } else {
+            self.first_ts_event.zip(self.last_ts_event)
+        }
+    }
+}
+
+impl RecordHandler for FileSummary {
+    fn handle<T: VisitFields>(&mut self, record: &T) {
+        let hd = record.header();
+        self.first_ts_event.get_or_insert(hd.ts_event);
+        self.last_ts_event = Some(hd.ts_event);
+        let mut sequence = RecordSequence(None);
+        // `RecordSequence` never fails
+        let _ = record.visit_fields(&mut sequence);
+        if let Some(sequence) = sequence.0 {
+            self.sequences
+                .entry(hd.publisher_id)
+                .and_modify(|(_, last)| *last = sequence)
+                .or_insert((sequence, sequence));
+        }
+    }
+}
+
+/// Picks out the `sequence` of a record, if it has one.
+pub(crate) struct RecordSequence(pub Option<u32>);
+
+impl FieldVisitor for RecordSequence {
+    type Error = Infallible;
+
+    fn visit(
+        &mut self,
+        scope: Scope,
+        name: &'static str,
+        value: FieldValue,
+    ) -> Result<(), Infallible> {
+        if let (Scope::Body, "sequence", FieldValue::U32(sequence)) = (scope, name, value) {
+            self.0 = Some(sequence);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    fn summary(start: u64, end: u64, first_sequence: u32, last_sequence: u32) -> FileSummary {
+        FileSummary {
+            dataset: "GLBX.MDP3".to_owned(),
+            schema: Schema::Trades,
+            start,
+            end,
+            first_ts_event: Some(start),
+            last_ts_event: Some(end - 1),
+            sequences: BTreeMap::from([(1, (first_sequence, last_sequence))]),
+        }
+    }
+
+    #[test]
+    fn test_abutting_files() {
+        let mut target = ContinuityChecker::new(Duration::ZERO);
+        target.summaries = vec![summary(0, 100, 1, 10), summary(100, 200, 11, 20)];
+        assert!(target.report().is_clean());
+    }
+
+    #[test]
+    fn test_gap_and_sequence_break() {
+        let mut target = ContinuityChecker::new(Duration::from_nanos(5));
+        target.summaries = vec![
+            summary(0, 100, 1, 10),
+            summary(105, 200, 11, 20),
+            summary(210, 300, 25, 30),
+        ];
+        assert_eq!(
+            target.report().boundaries,
+            vec![FileBoundary {
+                index: 2,
+                issues: vec![
+                    ContinuityIssue::Gap {
+                        end: 200,
+                        start: 210
+                    },
+                    ContinuityIssue::SequenceBreak {
+                        publisher_id: 1,
+                        last_sequence: 20,
+                        first_sequence: 25
+                    }
+                ]
+            }]
+        );
+    }
+
+    #[test]
+    fn test_same_file_twice_overlaps() {
+        let mut target = ContinuityChecker::new(Duration::ZERO);
+        for _ in 0..2 {
+            target
+                .add(Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap())
+                .unwrap();
+        }
+        let report = target.report();
+        assert_eq!(report.boundaries.len(), 1);
+        let issues = &report.boundaries[0].issues;
+        assert!(matches!(issues[0], ContinuityIssue::Overlap { .. }));
+        assert!(matches!(issues[1], ContinuityIssue::RecordOverlap { .. }));
+        assert!(matches!(issues[2], ContinuityIssue::SequenceBreak { .. }));
+    }
+
+    #[test]
+    fn test_schema_mismatch() {
+        let mut target = ContinuityChecker::new(Duration::ZERO);
+        for schema in ["trades", "mbo"] {
+            target
+                .add(Dbz::from_file(format!("{DBZ_PATH}/test_data.{schema}.dbz")).unwrap())
+                .unwrap();
+        }
+        assert_eq!(
+            target.report().boundaries[0].issues[0],
+            ContinuityIssue::SchemaMismatch {
+                previous: Schema::Trades,
+                next: Schema::Mbo
+            }
+        );
+    }
+}
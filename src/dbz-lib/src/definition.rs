@@ -0,0 +1,233 @@
+//! Schema definitions embedded in the metadata, which describe the layout of the
+//! records of a DBZ file so they can be decoded without knowing their type.
+use anyhow::{anyhow, Context};
+use databento_defs::enums::Schema;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    read::FromLittleEndianSlice,
+    spec::{parse_type_name, FieldSpec, StructSpec},
+    FormatSpec,
+};
+
+/// The layout of the records of a DBZ file, stored in the metadata to make the file
+/// self-describing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SchemaDefinition {
+    /// The `rtype` of the records.
+    pub rtype: u8,
+    /// The size of each record in bytes.
+    pub record_size: u16,
+    /// The primitive fields of the records, with the fields of nested structs and
+    /// arrays of structs flattened into names like `hd.ts_event` and
+    /// `booklevel.0.bid_px`.
+    pub fields: Vec<FieldDefinition>,
+}
+
+/// A primitive field of a record.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldDefinition {
+    /// The name of the field, with the names of any enclosing structs as a prefix
+    /// like `hd.ts_event`.
+    pub name: String,
+    /// The offset in bytes from the start of the record.
+    pub offset: u16,
+    /// The size of the field in bytes.
+    pub size: u16,
+    /// The Rust type of the field like `u64` or `[i8; 22]`, with `c_char` as `i8`.
+    #[serde(rename = "type")]
+    pub type_name: String,
+}
+
+impl SchemaDefinition {
+    /// Returns the definition of the records of `schema` in the current DBZ version.
+    ///
+    /// # Errors
+    /// This function returns an error if records of `schema` can't be decoded.
+    pub fn for_schema(schema: Schema) -> anyhow::Result<Self> {
+        let spec = FormatSpec::new();
+        let (record, rtype) = spec
+            .schemas
+            .iter()
+            .find(|spec| spec.value == schema as u16)
+            .and_then(|spec| Some((spec.record?, spec.rtype?)))
+            .ok_or_else(|| anyhow!("Records of schema {} can't be defined", schema.as_str()))?;
+        let record = find_struct(&spec.structs, record)?;
+        let mut fields = Vec::new();
+        flatten_fields(&spec.structs, record, "", 0, &mut fields)?;
+        Ok(Self {
+            rtype,
+            record_size: u16::try_from(record.size)?,
+            fields,
+        })
+    }
+
+    /// Decodes the fields of the record in `bytes` into a JSON object keyed by field
+    /// name. Integers are decoded as numbers, arrays of `i8` as null-terminated
+    /// strings, and other arrays as arrays of numbers.
+    ///
+    /// # Errors
+    /// This function returns an error if `bytes` is shorter than `record_size` or a
+    /// field has a type that can't be decoded.
+    pub fn decode_record(&self, bytes: &[u8]) -> anyhow::Result<Map<String, Value>> {
+        if bytes.len() < self.record_size as usize {
+            return Err(anyhow!(
+                "Record of {} bytes is shorter than the defined size of {} bytes",
+                bytes.len(),
+                self.record_size
+            ));
+        }
+        self.fields
+            .iter()
+            .map(|field| {
+                let start = field.offset as usize;
+                let value =
+                    decode_field(&field.type_name, &bytes[start..start + field.size as usize])
+                        .with_context(|| format!("Failed to decode field '{}'", field.name))?;
+                Ok((field.name.clone(), value))
+            })
+            .collect()
+    }
+
+    /// Checks that each field lies within the record.
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        for field in self.fields.iter() {
+            if field.offset as usize + field.size as usize > self.record_size as usize {
+                return Err(anyhow!(
+                    "Field '{}' at offset {} of {} bytes exceeds the record size of {}",
+                    field.name,
+                    field.offset,
+                    field.size,
+                    self.record_size
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn find_struct<'a>(structs: &'a [StructSpec], name: &str) -> anyhow::Result<&'a StructSpec> {
+    structs
+        .iter()
+        .find(|spec| spec.name == name)
+        .ok_or_else(|| anyhow!("Missing layout of struct {name}"))
+}
+
+/// Adds the primitive fields of `spec` at `base_offset` to `fields`, recursing into
+/// nested structs.
+fn flatten_fields(
+    structs: &[StructSpec],
+    spec: &StructSpec,
+    prefix: &str,
+    base_offset: usize,
+    fields: &mut Vec<FieldDefinition>,
+) -> anyhow::Result<()> {
+    for FieldSpec {
+        name,
+        offset,
+        size,
+        type_name,
+    } in spec.fields.iter()
+    {
+        // skip padding like `_dummy`
+        if *size == 0 || name.starts_with('_') {
+            continue;
+        }
+        let offset = base_offset + offset;
+        let (element, len) = parse_type_name(type_name);
+        match (structs.iter().find(|spec| spec.name == element), len) {
+            (Some(nested), None) => {
+                flatten_fields(structs, nested, &format!("{prefix}{name}."), offset, fields)?
+            }
+            (Some(nested), Some(len)) => {
+                for i in 0..len {
+                    flatten_fields(
+                        structs,
+                        nested,
+                        &format!("{prefix}{name}.{i}."),
+                        offset + i * nested.size,
+                        fields,
+                    )?;
+                }
+            }
+            (None, _) => fields.push(FieldDefinition {
+                name: format!("{prefix}{name}"),
+                offset: u16::try_from(offset)?,
+                size: u16::try_from(*size)?,
+                type_name: type_name.clone(),
+            }),
+        }
+    }
+    Ok(())
+}
+
+fn decode_field(type_name: &str, bytes: &[u8]) -> anyhow::Result<Value> {
+    Ok(match parse_type_name(type_name) {
+        ("i8", Some(_)) => {
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            Value::String(
+                std::str::from_utf8(&bytes[..end])
+                    .with_context(|| "String isn't valid UTF-8")?
+                    .to_owned(),
+            )
+        }
+        (element, Some(len)) if len > 0 => {
+            let size = bytes.len() / len;
+            Value::Array(
+                bytes
+                    .chunks_exact(size)
+                    .map(|chunk| decode_field(element, chunk))
+                    .collect::<anyhow::Result<_>>()?,
+            )
+        }
+        (_, Some(_)) => Value::Array(Vec::new()),
+        ("u8", None) => bytes[0].into(),
+        ("u16", None) => u16::from_le_slice(bytes).into(),
+        ("u32", None) => u32::from_le_slice(bytes).into(),
+        ("u64", None) => u64::from_le_slice(bytes).into(),
+        ("i8", None) => (bytes[0] as i8).into(),
+        ("i16", None) => (u16::from_le_slice(bytes) as i16).into(),
+        ("i32", None) => i32::from_le_slice(bytes).into(),
+        ("i64", None) => i64::from_le_slice(bytes).into(),
+        (type_name, None) => return Err(anyhow!("Can't decode fields of type {type_name}")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use databento_defs::record::{Mbp10Msg, TradeMsg};
+    use streaming_iterator::StreamingIterator;
+
+    use super::*;
+    use crate::{write::dbz::as_u8_slice, Dbz};
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    #[test]
+    fn test_for_schema() {
+        let target = SchemaDefinition::for_schema(Schema::Mbp10).unwrap();
+        assert_eq!(target.record_size as usize, std::mem::size_of::<Mbp10Msg>());
+        assert_eq!(target.fields[0].name, "hd.length");
+        let last = target.fields.last().unwrap();
+        assert_eq!(last.name, "booklevel.9.ask_ct");
+        assert_eq!(last.offset + last.size, target.record_size);
+        target.validate().unwrap();
+        assert!(SchemaDefinition::for_schema(Schema::Statistics).is_err());
+    }
+
+    #[test]
+    fn test_decode_record() {
+        let target = SchemaDefinition::for_schema(Schema::Trades).unwrap();
+        let dbz = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let mut iter = dbz.try_into_iter::<TradeMsg>().unwrap();
+        let record = iter.next().unwrap();
+        let bytes = unsafe { as_u8_slice(record) };
+        let fields = target.decode_record(bytes).unwrap();
+        assert_eq!(fields["hd.rtype"], record.hd.rtype);
+        assert_eq!(fields["hd.ts_event"], record.hd.ts_event);
+        assert_eq!(fields["price"], record.price);
+        assert_eq!(fields["side"], record.side);
+        assert!(target.decode_record(&bytes[1..]).is_err());
+    }
+}
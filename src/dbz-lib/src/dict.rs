@@ -0,0 +1,104 @@
+//! Training of Zstd dictionaries for compressing many small DBZ files.
+use std::io;
+
+use anyhow::Context;
+
+use crate::Dbz;
+
+/// Trains a Zstd dictionary of at most `max_size` bytes from the records of
+/// `samples`, each of which is one sample. Compressing with a dictionary, see
+/// [`WriterOptions::dictionary`](crate::WriterOptions::dictionary), greatly improves
+/// the compression of files of only a few KB, like per-symbol daily files, because
+/// Zstd doesn't need to learn the common structure of the records anew in each file.
+///
+/// The samples should be representative of the files that will be compressed, e.g.
+/// a few hundred existing files of the same schema. Zstd recommends a `max_size` of
+/// around 100 KB.
+///
+/// # Errors
+/// This function returns an error if the records of a sample can't be decoded or
+/// Zstd can't train a dictionary from the samples, e.g. because there are too few.
+pub fn train_dict<R: io::BufRead>(
+    samples: impl IntoIterator<Item = Dbz<R>>,
+    max_size: usize,
+) -> anyhow::Result<Vec<u8>> {
+    let mut payloads = Vec::new();
+    for (i, dbz) in samples.into_iter().enumerate() {
+        let mut payload = Vec::new();
+        for record in dbz.into_record_iter()? {
+            let record = record.with_context(|| format!("Failed to decode sample {i}"))?;
+            payload.extend_from_slice(record.as_bytes());
+        }
+        payloads.push(payload);
+    }
+    zstd::dict::from_samples(&payloads, max_size).with_context(|| {
+        format!(
+            "Failed to train a dictionary from {} samples",
+            payloads.len()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::BufReader};
+
+    use databento_defs::record::Mbp10Msg;
+
+    use super::*;
+    use crate::{DbzWriter, WriterOptions};
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    fn samples() -> impl Iterator<Item = Dbz<BufReader<File>>> {
+        ["mbo", "mbp-1", "mbp-10", "tbbo", "trades"]
+            .into_iter()
+            .cycle()
+            .take(100)
+            .map(|schema| Dbz::from_file(format!("{DBZ_PATH}/test_data.{schema}.dbz")).unwrap())
+    }
+
+    #[test]
+    fn test_train_dict_round_trip() {
+        let dictionary = train_dict(samples(), 4096).unwrap();
+        assert!(!dictionary.is_empty());
+        assert!(dictionary.len() <= 4096);
+        let dbz = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbp-10.dbz")).unwrap();
+        let metadata = dbz.metadata().clone();
+        let records = dbz
+            .try_into_fallible_iter::<Mbp10Msg>()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        let options = WriterOptions {
+            dictionary: Some(dictionary.clone().into()),
+            ..Default::default()
+        };
+        let mut target =
+            DbzWriter::with_options(io::Cursor::new(Vec::new()), &metadata, &options).unwrap();
+        for record in records.iter() {
+            target.write_record(record).unwrap();
+        }
+        let buffer = target.finish().unwrap().into_inner();
+        let res_records = Dbz::new(buffer.as_slice())
+            .unwrap()
+            .with_dictionary(dictionary)
+            .try_into_fallible_iter::<Mbp10Msg>()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(res_records, records);
+        // the records can't be decoded without the dictionary
+        assert!(Dbz::new(buffer.as_slice())
+            .unwrap()
+            .try_into_fallible_iter::<Mbp10Msg>()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_train_dict_too_few_samples() {
+        assert!(train_dict(samples().take(1), 4096).is_err());
+    }
+}
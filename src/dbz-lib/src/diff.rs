@@ -0,0 +1,303 @@
+//! Comparing records exactly or ignoring fields that are expected to differ, e.g.
+//! between an original recording and a re-recording of the same data.
+use std::{collections::HashSet, mem};
+
+use serde::Serialize;
+
+use crate::{
+    fields::{FieldValue, FieldVisitor, Scope, VisitFields},
+    Record,
+};
+
+/// A field whose value differs between two records.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FieldDiff {
+    /// The flattened name of the field, with book level fields suffixed by their
+    /// zero-padded index, e.g. `bid_px_00`.
+    pub field: String,
+    /// The value in the left record.
+    pub left: String,
+    /// The value in the right record.
+    pub right: String,
+}
+
+/// A difference between two sequences of records.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecordDiff {
+    /// The records at `index` differ in `fields`.
+    Changed {
+        /// The index of the records in both sequences.
+        index: usize,
+        /// The fields that differ, in the order of the record.
+        fields: Vec<FieldDiff>,
+    },
+    /// Only the left sequence has a record at `index`.
+    LeftOnly {
+        /// The index of the record in the left sequence.
+        index: usize,
+    },
+    /// Only the right sequence has a record at `index`.
+    RightOnly {
+        /// The index of the record in the right sequence.
+        index: usize,
+    },
+}
+
+/// Compares records field by field, skipping the ignored fields. Without ignored
+/// fields, two records are equal exactly when they're `==`.
+#[derive(Clone, Debug, Default)]
+pub struct RecordComparator {
+    ignored: HashSet<String>,
+}
+
+impl RecordComparator {
+    /// Creates a comparator that compares every field.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ignores the field named `field` when comparing. Book level fields are named
+    /// with their zero-padded index like in CSV output, e.g. `bid_px_00`.
+    pub fn ignore(mut self, field: impl Into<String>) -> Self {
+        self.ignored.insert(field.into());
+        self
+    }
+
+    /// Returns `true` if `left` and `right` are equal apart from the ignored fields.
+    pub fn matches(&self, left: &Record, right: &Record) -> bool {
+        left == right || self.diff(left, right).is_empty()
+    }
+
+    /// Returns the fields that differ between `left` and `right`, other than the
+    /// ignored fields. Records of different types only differ in their `rtype`.
+    pub fn diff(&self, left: &Record, right: &Record) -> Vec<FieldDiff> {
+        if left == right {
+            return Vec::new();
+        }
+        if mem::discriminant(left) != mem::discriminant(right) {
+            return vec![FieldDiff {
+                field: "rtype".to_owned(),
+                left: left.header().rtype.to_string(),
+                right: right.header().rtype.to_string(),
+            }];
+        }
+        let left_fields = self.fields(left);
+        let right_fields = self.fields(right);
+        left_fields
+            .into_iter()
+            .zip(right_fields)
+            .filter(|((_, left), (_, right))| left != right)
+            .map(|((field, left), (_, right))| FieldDiff { field, left, right })
+            .collect()
+    }
+
+    /// Compares `left` and `right` record by record, returning an iterator over the
+    /// differences in order. Iteration stops at the first error from either side.
+    pub fn diff_iters<L, R>(&self, left: L, right: R) -> RecordDiffIter<L, R>
+    where
+        L: Iterator<Item = anyhow::Result<Record>>,
+        R: Iterator<Item = anyhow::Result<Record>>,
+    {
+        RecordDiffIter {
+            comparator: self.clone(),
+            left,
+            right,
+            index: 0,
+            is_done: false,
+        }
+    }
+
+    /// Returns the flattened names and formatted values of the fields of `record`
+    /// that aren't ignored.
+    fn fields(&self, record: &Record) -> Vec<(String, String)> {
+        fn collect<T: VisitFields>(record: &T, ignored: &HashSet<String>) -> Vec<(String, String)> {
+            let mut collector = FieldCollector {
+                ignored,
+                fields: Vec::with_capacity(T::HEADERS.len()),
+            };
+            // collecting can't fail
+            let _ = record.visit_fields(&mut collector);
+            collector.fields
+        }
+
+        match record {
+            Record::Mbo(rec) => collect(rec, &self.ignored),
+            Record::Mbp1(rec) | Record::Tbbo(rec) => collect(rec, &self.ignored),
+            Record::Mbp10(rec) => collect(rec, &self.ignored),
+            Record::Trades(rec) => collect(rec, &self.ignored),
+            Record::Ohlcv(rec) => collect(rec, &self.ignored),
+            Record::Definition(rec) => collect(rec, &self.ignored),
+            Record::Status(rec) => collect(rec, &self.ignored),
+        }
+    }
+}
+
+struct FieldCollector<'a> {
+    ignored: &'a HashSet<String>,
+    fields: Vec<(String, String)>,
+}
+
+impl FieldVisitor for FieldCollector<'_> {
+    type Error = ();
+
+    fn visit(&mut self, scope: Scope, name: &'static str, value: FieldValue) -> Result<(), ()> {
+        let name = match scope {
+            Scope::Level(i) => format!("{name}_{i:02}"),
+            Scope::Body | Scope::Header => name.to_owned(),
+        };
+        if !self.ignored.contains(&name) {
+            self.fields.push((name, format_value(value)));
+        }
+        Ok(())
+    }
+}
+
+fn format_value(value: FieldValue) -> String {
+    match value {
+        FieldValue::I8(v) => v.to_string(),
+        FieldValue::U8(v) => v.to_string(),
+        FieldValue::I16(v) => v.to_string(),
+        FieldValue::U16(v) => v.to_string(),
+        FieldValue::I32(v) => v.to_string(),
+        FieldValue::U32(v) => v.to_string(),
+        FieldValue::I64(v) | FieldValue::Price(v) => v.to_string(),
+        FieldValue::U64(v) | FieldValue::Timestamp(v) => v.to_string(),
+        FieldValue::Char(c) => (c as u8 as char).to_string(),
+        FieldValue::CStr(_) | FieldValue::Str(_) => value.as_str().unwrap_or_default().to_owned(),
+        FieldValue::Null => "null".to_owned(),
+    }
+}
+
+/// An iterator over the differences between two sequences of records. This struct
+/// is created by the [`RecordComparator::diff_iters`] method.
+pub struct RecordDiffIter<L, R> {
+    comparator: RecordComparator,
+    left: L,
+    right: R,
+    /// The index of the next pair of records.
+    index: usize,
+    is_done: bool,
+}
+
+impl<L, R> Iterator for RecordDiffIter<L, R>
+where
+    L: Iterator<Item = anyhow::Result<Record>>,
+    R: Iterator<Item = anyhow::Result<Record>>,
+{
+    type Item = anyhow::Result<RecordDiff>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.is_done {
+            let index = self.index;
+            self.index += 1;
+            let (left, right) = match (self.left.next(), self.right.next()) {
+                (None, None) => {
+                    self.is_done = true;
+                    return None;
+                }
+                (Some(Err(e)), _) | (_, Some(Err(e))) => {
+                    self.is_done = true;
+                    return Some(Err(e));
+                }
+                (Some(Ok(_)), None) => return Some(Ok(RecordDiff::LeftOnly { index })),
+                (None, Some(Ok(_))) => return Some(Ok(RecordDiff::RightOnly { index })),
+                (Some(Ok(left)), Some(Ok(right))) => (left, right),
+            };
+            let fields = self.comparator.diff(&left, &right);
+            if !fields.is_empty() {
+                return Some(Ok(RecordDiff::Changed { index, fields }));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Dbz;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    fn records(schema: &str) -> Vec<Record> {
+        Dbz::from_file(format!("{DBZ_PATH}/test_data.{schema}.dbz"))
+            .unwrap()
+            .into_record_iter()
+            .unwrap()
+            .collect::<anyhow::Result<_>>()
+            .unwrap()
+    }
+
+    fn with_sequence(record: &Record, sequence: u32) -> Record {
+        let mut record = record.clone();
+        match &mut record {
+            Record::Trades(rec) => rec.sequence = sequence,
+            _ => unreachable!(),
+        }
+        record
+    }
+
+    #[test]
+    fn test_diff_exact() {
+        let trades = records("trades");
+        let target = RecordComparator::new();
+        assert!(target.matches(&trades[0], &trades[0]));
+        let changed = with_sequence(&trades[0], 7);
+        assert!(!target.matches(&trades[0], &changed));
+        let diff = target.diff(&trades[0], &changed);
+        assert_eq!(diff.len(), 1);
+        assert_eq!(diff[0].field, "sequence");
+        assert_eq!(diff[0].right, "7");
+    }
+
+    #[test]
+    fn test_diff_ignores_fields() {
+        let trades = records("trades");
+        let target = RecordComparator::new().ignore("sequence");
+        assert!(target.matches(&trades[0], &with_sequence(&trades[0], 7)));
+        // other fields are still compared
+        assert!(!target.matches(&trades[0], &trades[1]));
+        assert!(target
+            .diff(&trades[0], &trades[1])
+            .iter()
+            .all(|diff| diff.field != "sequence"));
+    }
+
+    #[test]
+    fn test_diff_different_record_types() {
+        let target = RecordComparator::new();
+        let diff = target.diff(&records("trades")[0], &records("mbo")[0]);
+        assert_eq!(diff.len(), 1);
+        assert_eq!(diff[0].field, "rtype");
+    }
+
+    #[test]
+    fn test_diff_iters() {
+        let trades = records("trades");
+        let right = vec![
+            trades[0].clone(),
+            with_sequence(&trades[1], 7),
+            trades[1].clone(),
+        ];
+        let target = RecordComparator::new();
+        let diffs = target
+            .diff_iters(
+                trades.clone().into_iter().map(Ok),
+                right.clone().into_iter().map(Ok),
+            )
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(diffs.len(), 2);
+        assert!(matches!(&diffs[0], RecordDiff::Changed { index: 1, fields } if fields.len() == 1));
+        assert_eq!(diffs[1], RecordDiff::RightOnly { index: 2 });
+        let diffs = target
+            .diff_iters(
+                right.into_iter().take(1).map(Ok),
+                trades.into_iter().map(Ok),
+            )
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(diffs, vec![RecordDiff::RightOnly { index: 1 }]);
+    }
+}
@@ -0,0 +1,230 @@
+//! Hints for displaying the fixed-precision prices of a dataset, stored in the
+//! metadata's annotations.
+use std::io;
+
+use anyhow::{anyhow, Context};
+
+use crate::Metadata;
+
+/// The number of decimal places of fixed-precision prices, which are in units of 1e-9.
+const PRICE_DECIMALS: i32 = 9;
+
+/// How to display the prices of a DBZ file as decimals, read from the
+/// [`PriceDisplay::FACTOR_KEY`] and [`PriceDisplay::TICK_SIZE_KEY`] annotations of its
+/// metadata. Without them, prices are displayed with nine decimal places.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PriceDisplay {
+    /// The power of ten prices are multiplied by for display, e.g. -2 for a display
+    /// factor of 0.01.
+    pub factor_exponent: i8,
+    /// The number of decimal places to display after applying the display factor,
+    /// which is the number of decimal places of the tick size. `None` displays all
+    /// nine decimal places of the fixed-precision prices, shifted by the display
+    /// factor.
+    pub decimals: Option<u8>,
+}
+
+impl PriceDisplay {
+    /// The annotation holding the display factor of the prices as a decimal power of
+    /// ten, e.g. `0.01` or `100`.
+    pub const FACTOR_KEY: &'static str = "price_display_factor";
+    /// The annotation holding the tick size of the prices as a decimal after applying
+    /// the display factor, e.g. `0.25`.
+    pub const TICK_SIZE_KEY: &'static str = "tick_size";
+
+    /// Reads the display hints from the annotations of `metadata`.
+    ///
+    /// # Errors
+    /// This function returns an error if the display factor isn't a power of ten
+    /// between 1e-9 and 1e9 or the tick size isn't a positive decimal with at most 18
+    /// decimal places.
+    pub fn from_metadata(metadata: &Metadata) -> anyhow::Result<Self> {
+        let factor_exponent = match metadata.annotations.get(Self::FACTOR_KEY) {
+            Some(factor) => parse_power_of_ten(factor)
+                .filter(|exponent| exponent.abs() <= PRICE_DECIMALS as i8)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Invalid {} '{factor}', expected a power of ten from 0.000000001 to 1000000000",
+                        Self::FACTOR_KEY
+                    )
+                })?,
+            None => 0,
+        };
+        let decimals = metadata
+            .annotations
+            .get(Self::TICK_SIZE_KEY)
+            .map(|tick_size| {
+                decimal_places(tick_size)
+                    .with_context(|| format!("Invalid {} '{tick_size}'", Self::TICK_SIZE_KEY))
+            })
+            .transpose()?;
+        Ok(Self {
+            factor_exponent,
+            decimals,
+        })
+    }
+
+    /// Sets the annotations of `metadata` for displaying prices with `factor` and
+    /// `tick_size`, both decimals.
+    ///
+    /// # Errors
+    /// This function returns an error under the same conditions as
+    /// [`PriceDisplay::from_metadata`], in which case `metadata` is unchanged.
+    pub fn annotate(metadata: &mut Metadata, factor: &str, tick_size: &str) -> anyhow::Result<()> {
+        let mut annotated = metadata.clone();
+        annotated
+            .annotations
+            .insert(Self::FACTOR_KEY.to_owned(), factor.to_owned());
+        annotated
+            .annotations
+            .insert(Self::TICK_SIZE_KEY.to_owned(), tick_size.to_owned());
+        Self::from_metadata(&annotated)?;
+        *metadata = annotated;
+        Ok(())
+    }
+
+    /// Writes the fixed-precision `price`, in units of 1e-9, as a decimal number with
+    /// the display factor applied, rounding half away from zero to
+    /// [`PriceDisplay::decimals`] decimal places.
+    ///
+    /// # Errors
+    /// This function returns an error if there's an issue writing to `writer`.
+    pub fn write<W: io::Write + ?Sized>(&self, writer: &mut W, price: i64) -> io::Result<()> {
+        // i128 fits every price shifted by up to 18 decimal places
+        let mut abs = i128::from(price).unsigned_abs();
+        let mut decimals = (PRICE_DECIMALS - i32::from(self.factor_exponent)) as u32;
+        match self.decimals.map(u32::from) {
+            Some(target) if target < decimals => {
+                let divisor = 10u128.pow(decimals - target);
+                abs = (abs + divisor / 2) / divisor;
+                decimals = target;
+            }
+            Some(target) if target > decimals => {
+                abs *= 10u128.pow(target - decimals);
+                decimals = target;
+            }
+            _ => {}
+        }
+        let sign = if price < 0 && abs > 0 { "-" } else { "" };
+        let scale = 10u128.pow(decimals);
+        if decimals == 0 {
+            write!(writer, "{sign}{abs}")
+        } else {
+            write!(
+                writer,
+                "{sign}{}.{:0width$}",
+                abs / scale,
+                abs % scale,
+                width = decimals as usize
+            )
+        }
+    }
+}
+
+/// Returns the exponent of `value` if it's a decimal power of ten like `100` or
+/// `0.01`.
+fn parse_power_of_ten(value: &str) -> Option<i8> {
+    let (int, frac) = value.split_once('.').unwrap_or((value, ""));
+    let frac = frac.trim_end_matches('0');
+    if frac.is_empty() {
+        let zeros = int.strip_prefix('1')?;
+        (zeros.len() < 128 && zeros.bytes().all(|b| b == b'0')).then_some(zeros.len() as i8)
+    } else {
+        let zeros = frac.strip_suffix('1')?;
+        (int.bytes().all(|b| b == b'0') && zeros.len() < 128 && zeros.bytes().all(|b| b == b'0'))
+            .then(|| -(zeros.len() as i8) - 1)
+    }
+}
+
+/// Returns the number of significant decimal places of the positive decimal `value`.
+fn decimal_places(value: &str) -> anyhow::Result<u8> {
+    let (int, frac) = value.split_once('.').unwrap_or((value, ""));
+    let frac = frac.trim_end_matches('0');
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if int.is_empty() && frac.is_empty() || !is_digits(int) || !is_digits(frac) {
+        return Err(anyhow!("Expected a decimal number"));
+    }
+    if int.bytes().chain(frac.bytes()).all(|b| b == b'0') {
+        return Err(anyhow!("Expected a positive number"));
+    }
+    if frac.len() > 2 * PRICE_DECIMALS as usize {
+        return Err(anyhow!(
+            "Expected at most {} decimal places",
+            2 * PRICE_DECIMALS
+        ));
+    }
+    Ok(frac.len() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use databento_defs::enums::Schema;
+
+    use super::*;
+
+    fn format(display: PriceDisplay, price: i64) -> String {
+        let mut buffer = Vec::new();
+        display.write(&mut buffer, price).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_write() {
+        let default = PriceDisplay::default();
+        assert_eq!(format(default, 5_500_250_000_000), "5500.250000000");
+        assert_eq!(format(default, i64::MIN), "-9223372036.854775808");
+        let ticks = PriceDisplay {
+            factor_exponent: 0,
+            decimals: Some(2),
+        };
+        assert_eq!(format(ticks, 5_500_250_000_000), "5500.25");
+        // rounds half away from zero
+        assert_eq!(format(ticks, -1_005_000_000), "-1.01");
+        assert_eq!(format(ticks, -4_000_000), "0.00");
+        let cents = PriceDisplay {
+            factor_exponent: 2,
+            decimals: Some(0),
+        };
+        assert_eq!(format(cents, 1_230_000_000), "123");
+        let shifted = PriceDisplay {
+            factor_exponent: -9,
+            decimals: None,
+        };
+        assert_eq!(format(shifted, 1), "0.000000000000000001");
+        let padded = PriceDisplay {
+            factor_exponent: 9,
+            decimals: Some(1),
+        };
+        assert_eq!(format(padded, 2), "2.0");
+    }
+
+    #[test]
+    fn test_from_metadata() {
+        let mut metadata = Metadata::placeholder(Schema::Trades);
+        assert_eq!(
+            PriceDisplay::from_metadata(&metadata).unwrap(),
+            PriceDisplay::default()
+        );
+        PriceDisplay::annotate(&mut metadata, "0.01", "0.25").unwrap();
+        assert_eq!(
+            PriceDisplay::from_metadata(&metadata).unwrap(),
+            PriceDisplay {
+                factor_exponent: -2,
+                decimals: Some(2),
+            }
+        );
+        PriceDisplay::annotate(&mut metadata, "100", "1.0").unwrap();
+        assert_eq!(
+            PriceDisplay::from_metadata(&metadata).unwrap(),
+            PriceDisplay {
+                factor_exponent: 2,
+                decimals: Some(0),
+            }
+        );
+        for (factor, tick_size) in [("0.5", "1"), ("1e10", "1"), ("1", "0"), ("1", "-1")] {
+            assert!(PriceDisplay::annotate(&mut metadata, factor, tick_size).is_err());
+        }
+        // unchanged by the failures
+        assert_eq!(metadata.annotations[PriceDisplay::FACTOR_KEY], "100");
+    }
+}
@@ -0,0 +1,110 @@
+//! The kinds of errors from reading DBZ that callers may need to tell apart.
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::write::dbz::SCHEMA_VERSION;
+
+/// A kind of failure reading DBZ. Functions return it inside an [`anyhow::Error`],
+/// often with added context, so use [`Error::find`] to tell a corrupt file from an
+/// I/O failure instead of matching on the message.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The file at `path` couldn't be opened, or reading the DBZ data failed.
+    #[error("{}", io_message(path.as_deref()))]
+    Io {
+        /// The path of the file if it couldn't be opened, or `None` if reading from
+        /// an open file or other reader failed.
+        path: Option<PathBuf>,
+        /// The cause.
+        #[source]
+        source: io::Error,
+    },
+    /// The metadata is corrupt or isn't DBZ metadata.
+    #[error("Invalid metadata: {0}")]
+    InvalidMetadata(String),
+    /// The file was encoded in a newer version of DBZ than this version of dbz reads.
+    #[error(
+        "Can't read newer version of DBZ: version {version}, expected at most {SCHEMA_VERSION}"
+    )]
+    UnsupportedVersion {
+        /// The version of the file.
+        version: u8,
+    },
+    /// A record couldn't be decoded, e.g. because it has the wrong rtype or the
+    /// records ended early.
+    #[error("{message}")]
+    Decode {
+        /// A description of the problem.
+        message: String,
+        /// The index of the record.
+        record_index: u64,
+        /// The offset of the start of the record in the decompressed records.
+        byte_offset: u64,
+    },
+    /// The records don't match the checksum in the footer of the file.
+    #[error("Checksum mismatch: expected {expected:#010x}, found {actual:#010x}")]
+    ChecksumMismatch {
+        /// The checksum in the footer.
+        expected: u32,
+        /// The checksum of the records.
+        actual: u32,
+    },
+    /// The metadata says the file ends with a checksum footer but it doesn't, e.g.
+    /// because the file was truncated.
+    #[error("Missing checksum footer, the file may be truncated")]
+    MissingChecksum,
+}
+
+impl Error {
+    /// Returns the [`Error`] that caused `error`, if any.
+    pub fn find(error: &anyhow::Error) -> Option<&Self> {
+        error
+            .downcast_ref()
+            .or_else(|| error.chain().find_map(|e| e.downcast_ref()))
+    }
+
+    /// Opens the file at `path`.
+    pub(crate) fn open(path: impl Into<PathBuf>) -> Result<std::fs::File, Self> {
+        let path = path.into();
+        std::fs::File::open(&path).map_err(|source| Self::Io {
+            path: Some(path),
+            source,
+        })
+    }
+}
+
+fn io_message(path: Option<&Path>) -> String {
+    match path {
+        Some(path) => format!("Error opening dbz file at path '{}'", path.display()),
+        None => "Error reading dbz data".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let error = anyhow::Error::new(Error::UnsupportedVersion { version: 2 })
+            .context("Failed to read metadata");
+        assert!(matches!(
+            Error::find(&error),
+            Some(Error::UnsupportedVersion { version: 2 })
+        ));
+        let error = Error::open("does/not/exist.dbz")
+            .context("Failed to query")
+            .unwrap_err();
+        assert!(matches!(Error::find(&error), Some(Error::Io { .. })));
+        // the I/O error is still in the chain
+        assert!(error
+            .chain()
+            .any(|e| e.downcast_ref::<io::Error>().is_some()));
+        assert!(Error::find(&anyhow::anyhow!("other")).is_none());
+    }
+}
@@ -0,0 +1,675 @@
+//! Field-by-field access to records, shared by the text encoders so each
+//! record type's layout only needs to be described once.
+use std::{fmt, io, os::raw::c_char, slice};
+
+use anyhow::anyhow;
+use databento_defs::{
+    enums::Schema,
+    record::{
+        BidAskPair, ConstTypeId, Mbp10Msg, Mbp1Msg, OhlcvMsg, RecordHeader, SecurityUpdateAction,
+        StatusMsg, SymDefMsg, TickMsg, TradeMsg,
+    },
+};
+use streaming_iterator::StreamingIterator;
+
+use crate::{Dbz, DbzStreamIter};
+
+/// Where a field is nested in the JSON representation of a record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// A top-level field of the record.
+    Body,
+    /// A field of the [`RecordHeader`], nested under `hd`.
+    Header,
+    /// A field of a book level, nested under `booklevel` at the given index.
+    Level(usize),
+}
+
+/// The value of a single record field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldValue<'a> {
+    I8(i8),
+    U8(u8),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    /// A UNIX timestamp in nanoseconds.
+    Timestamp(u64),
+    /// A fixed-precision price where every 1 unit corresponds to 1e-9.
+    Price(i64),
+    /// A single ASCII character such as `action` or `side`. It's an `i8` rather than
+    /// a `c_char`, which is `u8` on some targets such as aarch64 Linux, so it's
+    /// output the same on every target.
+    Char(i8),
+    /// A fixed-length, null-terminated string.
+    CStr(&'a [c_char]),
+    Str(&'a str),
+    /// The absence of a value.
+    Null,
+}
+
+impl<'a> FieldValue<'a> {
+    /// Returns the string contents of a [`FieldValue::CStr`] or [`FieldValue::Str`].
+    pub fn as_str(&self) -> Option<&'a str> {
+        match *self {
+            FieldValue::CStr(chars) => Some(c_chars_to_str(chars)),
+            FieldValue::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// Returns a [`FieldValue::Char`] of `c`.
+// the cast is only unnecessary on targets where `c_char` is `i8`
+#[allow(clippy::unnecessary_cast)]
+fn c_char_value(c: c_char) -> FieldValue<'static> {
+    FieldValue::Char(c as i8)
+}
+
+fn c_chars_to_str(chars: &[c_char]) -> &str {
+    // Safety: `c_char` has the same size and alignment as `u8`
+    let bytes = unsafe { slice::from_raw_parts(chars.as_ptr() as *const u8, chars.len()) };
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[..len]).unwrap_or("<invalid UTF-8>")
+}
+
+/// Receives each field of a record in order.
+pub trait FieldVisitor {
+    type Error;
+
+    fn visit(
+        &mut self,
+        scope: Scope,
+        name: &'static str,
+        value: FieldValue,
+    ) -> Result<(), Self::Error>;
+}
+
+/// The value of a price that isn't defined, e.g. the price of an empty book level.
+pub const UNDEF_PRICE: i64 = i64::MAX;
+
+/// The number of fields visited for each book level.
+pub const LEVEL_FIELD_COUNT: usize = 6;
+
+/// A record type whose fields can be visited one at a time.
+pub trait VisitFields: ConstTypeId + fmt::Debug {
+    /// The flattened names of the fields in the order they're visited, with book
+    /// level fields suffixed by their zero-padded index.
+    const HEADERS: &'static [&'static str];
+    /// The number of book levels, whose fields are always visited last.
+    const LEVEL_COUNT: usize = 0;
+
+    /// Returns the header common to all records.
+    fn header(&self) -> &RecordHeader;
+
+    /// Calls `visitor` with every field of the record in the same order as
+    /// [`VisitFields::HEADERS`].
+    fn visit_fields<V: FieldVisitor>(&self, visitor: &mut V) -> Result<(), V::Error>;
+}
+
+fn visit_header<V: FieldVisitor>(hd: &RecordHeader, visitor: &mut V) -> Result<(), V::Error> {
+    visitor.visit(Scope::Header, "rtype", FieldValue::U8(hd.rtype))?;
+    visitor.visit(
+        Scope::Header,
+        "publisher_id",
+        FieldValue::U16(hd.publisher_id),
+    )?;
+    visitor.visit(Scope::Header, "product_id", FieldValue::U32(hd.product_id))?;
+    visitor.visit(
+        Scope::Header,
+        "ts_event",
+        FieldValue::Timestamp(hd.ts_event),
+    )
+}
+
+fn visit_levels<V: FieldVisitor>(levels: &[BidAskPair], visitor: &mut V) -> Result<(), V::Error> {
+    for (i, level) in levels.iter().enumerate() {
+        let scope = Scope::Level(i);
+        visitor.visit(scope, "bid_px", FieldValue::Price(level.bid_px))?;
+        visitor.visit(scope, "ask_px", FieldValue::Price(level.ask_px))?;
+        visitor.visit(scope, "bid_sz", FieldValue::U32(level.bid_sz))?;
+        visitor.visit(scope, "ask_sz", FieldValue::U32(level.ask_sz))?;
+        visitor.visit(scope, "bid_ct", FieldValue::U32(level.bid_ct))?;
+        visitor.visit(scope, "ask_ct", FieldValue::U32(level.ask_ct))?;
+    }
+    Ok(())
+}
+
+impl VisitFields for TickMsg {
+    const HEADERS: &'static [&'static str] = &[
+        "rtype",
+        "publisher_id",
+        "product_id",
+        "ts_event",
+        "order_id",
+        "price",
+        "size",
+        "flags",
+        "channel_id",
+        "action",
+        "side",
+        "ts_recv",
+        "ts_in_delta",
+        "sequence",
+    ];
+
+    fn header(&self) -> &RecordHeader {
+        &self.hd
+    }
+
+    fn visit_fields<V: FieldVisitor>(&self, visitor: &mut V) -> Result<(), V::Error> {
+        use FieldValue::*;
+
+        visit_header(&self.hd, visitor)?;
+        visitor.visit(Scope::Body, "order_id", U64(self.order_id))?;
+        visitor.visit(Scope::Body, "price", Price(self.price))?;
+        visitor.visit(Scope::Body, "size", U32(self.size))?;
+        visitor.visit(Scope::Body, "flags", I8(self.flags))?;
+        visitor.visit(Scope::Body, "channel_id", U8(self.channel_id))?;
+        visitor.visit(Scope::Body, "action", c_char_value(self.action))?;
+        visitor.visit(Scope::Body, "side", c_char_value(self.side))?;
+        visitor.visit(Scope::Body, "ts_recv", Timestamp(self.ts_recv))?;
+        visitor.visit(Scope::Body, "ts_in_delta", I32(self.ts_in_delta))?;
+        visitor.visit(Scope::Body, "sequence", U32(self.sequence))
+    }
+}
+
+/// Visits the fields common to [`TradeMsg`], [`Mbp1Msg`], and [`Mbp10Msg`].
+macro_rules! visit_mbp_fields {
+    ($rec:expr, $visitor:expr) => {{
+        use FieldValue::*;
+
+        visit_header(&$rec.hd, $visitor)?;
+        $visitor.visit(Scope::Body, "price", Price($rec.price))?;
+        $visitor.visit(Scope::Body, "size", U32($rec.size))?;
+        $visitor.visit(Scope::Body, "action", c_char_value($rec.action))?;
+        $visitor.visit(Scope::Body, "side", c_char_value($rec.side))?;
+        $visitor.visit(Scope::Body, "flags", I8($rec.flags))?;
+        $visitor.visit(Scope::Body, "depth", U8($rec.depth))?;
+        $visitor.visit(Scope::Body, "ts_recv", Timestamp($rec.ts_recv))?;
+        $visitor.visit(Scope::Body, "ts_in_delta", I32($rec.ts_in_delta))?;
+        $visitor.visit(Scope::Body, "sequence", U32($rec.sequence))?;
+        visit_levels(&$rec.booklevel, $visitor)
+    }};
+}
+
+impl VisitFields for TradeMsg {
+    const HEADERS: &'static [&'static str] = &[
+        "rtype",
+        "publisher_id",
+        "product_id",
+        "ts_event",
+        "price",
+        "size",
+        "action",
+        "side",
+        "flags",
+        "depth",
+        "ts_recv",
+        "ts_in_delta",
+        "sequence",
+    ];
+
+    fn header(&self) -> &RecordHeader {
+        &self.hd
+    }
+
+    fn visit_fields<V: FieldVisitor>(&self, visitor: &mut V) -> Result<(), V::Error> {
+        visit_mbp_fields!(self, visitor)
+    }
+}
+
+impl VisitFields for Mbp1Msg {
+    const LEVEL_COUNT: usize = 1;
+    const HEADERS: &'static [&'static str] = &[
+        "rtype",
+        "publisher_id",
+        "product_id",
+        "ts_event",
+        "price",
+        "size",
+        "action",
+        "side",
+        "flags",
+        "depth",
+        "ts_recv",
+        "ts_in_delta",
+        "sequence",
+        "bid_px_00",
+        "ask_px_00",
+        "bid_sz_00",
+        "ask_sz_00",
+        "bid_ct_00",
+        "ask_ct_00",
+    ];
+
+    fn header(&self) -> &RecordHeader {
+        &self.hd
+    }
+
+    fn visit_fields<V: FieldVisitor>(&self, visitor: &mut V) -> Result<(), V::Error> {
+        visit_mbp_fields!(self, visitor)
+    }
+}
+
+impl VisitFields for Mbp10Msg {
+    const LEVEL_COUNT: usize = 10;
+    const HEADERS: &'static [&'static str] = &[
+        "rtype",
+        "publisher_id",
+        "product_id",
+        "ts_event",
+        "price",
+        "size",
+        "action",
+        "side",
+        "flags",
+        "depth",
+        "ts_recv",
+        "ts_in_delta",
+        "sequence",
+        "bid_px_00",
+        "ask_px_00",
+        "bid_sz_00",
+        "ask_sz_00",
+        "bid_ct_00",
+        "ask_ct_00",
+        "bid_px_01",
+        "ask_px_01",
+        "bid_sz_01",
+        "ask_sz_01",
+        "bid_ct_01",
+        "ask_ct_01",
+        "bid_px_02",
+        "ask_px_02",
+        "bid_sz_02",
+        "ask_sz_02",
+        "bid_ct_02",
+        "ask_ct_02",
+        "bid_px_03",
+        "ask_px_03",
+        "bid_sz_03",
+        "ask_sz_03",
+        "bid_ct_03",
+        "ask_ct_03",
+        "bid_px_04",
+        "ask_px_04",
+        "bid_sz_04",
+        "ask_sz_04",
+        "bid_ct_04",
+        "ask_ct_04",
+        "bid_px_05",
+        "ask_px_05",
+        "bid_sz_05",
+        "ask_sz_05",
+        "bid_ct_05",
+        "ask_ct_05",
+        "bid_px_06",
+        "ask_px_06",
+        "bid_sz_06",
+        "ask_sz_06",
+        "bid_ct_06",
+        "ask_ct_06",
+        "bid_px_07",
+        "ask_px_07",
+        "bid_sz_07",
+        "ask_sz_07",
+        "bid_ct_07",
+        "ask_ct_07",
+        "bid_px_08",
+        "ask_px_08",
+        "bid_sz_08",
+        "ask_sz_08",
+        "bid_ct_08",
+        "ask_ct_08",
+        "bid_px_09",
+        "ask_px_09",
+        "bid_sz_09",
+        "ask_sz_09",
+        "bid_ct_09",
+        "ask_ct_09",
+    ];
+
+    fn header(&self) -> &RecordHeader {
+        &self.hd
+    }
+
+    fn visit_fields<V: FieldVisitor>(&self, visitor: &mut V) -> Result<(), V::Error> {
+        visit_mbp_fields!(self, visitor)
+    }
+}
+
+impl VisitFields for OhlcvMsg {
+    const HEADERS: &'static [&'static str] = &[
+        "rtype",
+        "publisher_id",
+        "product_id",
+        "ts_event",
+        "open",
+        "high",
+        "low",
+        "close",
+        "volume",
+    ];
+
+    fn header(&self) -> &RecordHeader {
+        &self.hd
+    }
+
+    fn visit_fields<V: FieldVisitor>(&self, visitor: &mut V) -> Result<(), V::Error> {
+        use FieldValue::*;
+
+        visit_header(&self.hd, visitor)?;
+        visitor.visit(Scope::Body, "open", Price(self.open))?;
+        visitor.visit(Scope::Body, "high", Price(self.high))?;
+        visitor.visit(Scope::Body, "low", Price(self.low))?;
+        visitor.visit(Scope::Body, "close", Price(self.close))?;
+        visitor.visit(Scope::Body, "volume", U64(self.volume))
+    }
+}
+
+impl VisitFields for StatusMsg {
+    const HEADERS: &'static [&'static str] = &[
+        "rtype",
+        "publisher_id",
+        "product_id",
+        "ts_event",
+        "ts_recv",
+        "group",
+        "trading_status",
+        "halt_reason",
+        "trading_event",
+    ];
+
+    fn header(&self) -> &RecordHeader {
+        &self.hd
+    }
+
+    fn visit_fields<V: FieldVisitor>(&self, visitor: &mut V) -> Result<(), V::Error> {
+        use FieldValue::*;
+
+        visit_header(&self.hd, visitor)?;
+        visitor.visit(Scope::Body, "ts_recv", Timestamp(self.ts_recv))?;
+        visitor.visit(Scope::Body, "group", CStr(&self.group))?;
+        visitor.visit(Scope::Body, "trading_status", U8(self.trading_status))?;
+        visitor.visit(Scope::Body, "halt_reason", U8(self.halt_reason))?;
+        visitor.visit(Scope::Body, "trading_event", U8(self.trading_event))
+    }
+}
+
+impl VisitFields for SymDefMsg {
+    const HEADERS: &'static [&'static str] = &[
+        "rtype",
+        "publisher_id",
+        "product_id",
+        "ts_event",
+        "ts_recv",
+        "min_price_increment",
+        "display_factor",
+        "expiration",
+        "activation",
+        "high_limit_price",
+        "low_limit_price",
+        "max_price_variation",
+        "trading_reference_price",
+        "unit_of_measure_qty",
+        "min_price_increment_amount",
+        "price_ratio",
+        "inst_attrib_value",
+        "underlying_id",
+        "cleared_volume",
+        "market_depth_implied",
+        "market_depth",
+        "market_segment_id",
+        "max_trade_vol",
+        "min_lot_size",
+        "min_lot_size_block",
+        "min_lot_size_round_lot",
+        "min_trade_vol",
+        "open_interest_qty",
+        "contract_multiplier",
+        "decay_quantity",
+        "original_contract_size",
+        "related_security_id",
+        "trading_reference_date",
+        "appl_id",
+        "maturity_month_year",
+        "decay_start_date",
+        "chan",
+        "currency",
+        "settl_currency",
+        "secsubtype",
+        "symbol",
+        "group",
+        "exchange",
+        "asset",
+        "cfi",
+        "security_type",
+        "unit_of_measure",
+        "underlying",
+        "related",
+        "match_algorithm",
+        "md_security_trading_status",
+        "main_fraction",
+        "price_display_format",
+        "settl_price_type",
+        "sub_fraction",
+        "underlying_product",
+        "security_update_action",
+        "maturity_month_month",
+        "maturity_month_day",
+        "maturity_month_week",
+        "user_defined_instrument",
+        "contract_multiplier_unit",
+        "flow_schedule_type",
+        "tick_rule",
+    ];
+
+    fn header(&self) -> &RecordHeader {
+        &self.hd
+    }
+
+    fn visit_fields<V: FieldVisitor>(&self, visitor: &mut V) -> Result<(), V::Error> {
+        use FieldValue::*;
+
+        visit_header(&self.hd, visitor)?;
+        let mut visit = |name, value| visitor.visit(Scope::Body, name, value);
+        visit("ts_recv", Timestamp(self.ts_recv))?;
+        visit("min_price_increment", Price(self.min_price_increment))?;
+        visit("display_factor", I64(self.display_factor))?;
+        visit("expiration", Timestamp(self.expiration))?;
+        visit("activation", Timestamp(self.activation))?;
+        visit("high_limit_price", Price(self.high_limit_price))?;
+        visit("low_limit_price", Price(self.low_limit_price))?;
+        visit("max_price_variation", Price(self.max_price_variation))?;
+        visit(
+            "trading_reference_price",
+            Price(self.trading_reference_price),
+        )?;
+        visit("unit_of_measure_qty", I64(self.unit_of_measure_qty))?;
+        visit(
+            "min_price_increment_amount",
+            Price(self.min_price_increment_amount),
+        )?;
+        visit("price_ratio", I64(self.price_ratio))?;
+        visit("inst_attrib_value", I32(self.inst_attrib_value))?;
+        visit("underlying_id", U32(self.underlying_id))?;
+        visit("cleared_volume", I32(self.cleared_volume))?;
+        visit("market_depth_implied", I32(self.market_depth_implied))?;
+        visit("market_depth", I32(self.market_depth))?;
+        visit("market_segment_id", U32(self.market_segment_id))?;
+        visit("max_trade_vol", U32(self.max_trade_vol))?;
+        visit("min_lot_size", I32(self.min_lot_size))?;
+        visit("min_lot_size_block", I32(self.min_lot_size_block))?;
+        visit("min_lot_size_round_lot", I32(self.min_lot_size_round_lot))?;
+        visit("min_trade_vol", U32(self.min_trade_vol))?;
+        visit("open_interest_qty", I32(self.open_interest_qty))?;
+        visit("contract_multiplier", I32(self.contract_multiplier))?;
+        visit("decay_quantity", I32(self.decay_quantity))?;
+        visit("original_contract_size", I32(self.original_contract_size))?;
+        visit("related_security_id", U32(self.related_security_id))?;
+        visit("trading_reference_date", U16(self.trading_reference_date))?;
+        visit("appl_id", I16(self.appl_id))?;
+        visit("maturity_month_year", U16(self.maturity_month_year))?;
+        visit("decay_start_date", U16(self.decay_start_date))?;
+        visit("chan", U16(self.chan))?;
+        visit("currency", CStr(&self.currency))?;
+        visit("settl_currency", CStr(&self.settl_currency))?;
+        visit("secsubtype", CStr(&self.secsubtype))?;
+        visit("symbol", CStr(&self.symbol))?;
+        visit("group", CStr(&self.group))?;
+        visit("exchange", CStr(&self.exchange))?;
+        visit("asset", CStr(&self.asset))?;
+        visit("cfi", CStr(&self.cfi))?;
+        visit("security_type", CStr(&self.security_type))?;
+        visit("unit_of_measure", CStr(&self.unit_of_measure))?;
+        visit("underlying", CStr(&self.underlying))?;
+        visit("related", CStr(&self.related))?;
+        visit("match_algorithm", c_char_value(self.match_algorithm))?;
+        visit(
+            "md_security_trading_status",
+            U8(self.md_security_trading_status),
+        )?;
+        visit("main_fraction", U8(self.main_fraction))?;
+        visit("price_display_format", U8(self.price_display_format))?;
+        visit("settl_price_type", U8(self.settl_price_type))?;
+        visit("sub_fraction", U8(self.sub_fraction))?;
+        visit("underlying_product", U8(self.underlying_product))?;
+        visit(
+            "security_update_action",
+            Str(security_update_action_str(self.security_update_action)),
+        )?;
+        visit("maturity_month_month", U8(self.maturity_month_month))?;
+        visit("maturity_month_day", U8(self.maturity_month_day))?;
+        visit("maturity_month_week", U8(self.maturity_month_week))?;
+        visit(
+            "user_defined_instrument",
+            c_char_value(self.user_defined_instrument),
+        )?;
+        visit(
+            "contract_multiplier_unit",
+            I8(self.contract_multiplier_unit),
+        )?;
+        visit("flow_schedule_type", I8(self.flow_schedule_type))?;
+        visit("tick_rule", U8(self.tick_rule))
+    }
+}
+
+fn security_update_action_str(action: SecurityUpdateAction) -> &'static str {
+    match action {
+        SecurityUpdateAction::Add => "Add",
+        SecurityUpdateAction::Modify => "Modify",
+        SecurityUpdateAction::Delete => "Delete",
+        SecurityUpdateAction::Invalid => "Invalid",
+    }
+}
+
+/// Receives each record of a [`Dbz`] regardless of its type. See
+/// [`Dbz::handle_records`].
+pub(crate) trait RecordHandler {
+    fn handle<T: VisitFields>(&mut self, record: &T);
+}
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Decodes each record with the record type of [`Dbz::schema()`] and passes it to
+    /// `handler`.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`]. It will also return an error if there's an issue
+    /// reading the records.
+    pub(crate) fn handle_records(self, handler: &mut impl RecordHandler) -> anyhow::Result<()> {
+        fn handle_all<R: io::BufRead, T: VisitFields>(
+            mut iter: DbzStreamIter<R, T>,
+            handler: &mut impl RecordHandler,
+        ) -> anyhow::Result<()> {
+            while let Some(record) = iter.next() {
+                handler.handle(record);
+            }
+            iter.take_error().map_or(Ok(()), Err)
+        }
+
+        match self.schema() {
+            Schema::Mbo => handle_all(self.try_into_iter::<TickMsg>()?, handler),
+            Schema::Mbp1 | Schema::Tbbo => handle_all(self.try_into_iter::<Mbp1Msg>()?, handler),
+            Schema::Mbp10 => handle_all(self.try_into_iter::<Mbp10Msg>()?, handler),
+            Schema::Trades => handle_all(self.try_into_iter::<TradeMsg>()?, handler),
+            Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D => {
+                handle_all(self.try_into_iter::<OhlcvMsg>()?, handler)
+            }
+            Schema::Definition => handle_all(self.try_into_iter::<SymDefMsg>()?, handler),
+            Schema::Statistics => Err(anyhow!("Not implemented for schema Statistics")),
+            Schema::Status => handle_all(self.try_into_iter::<StatusMsg>()?, handler),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::write::test_data::{BID_ASK, RECORD_HEADER};
+
+    /// Collects the flattened name of every visited field.
+    #[derive(Default)]
+    struct NameCollector(Vec<String>);
+
+    impl FieldVisitor for NameCollector {
+        type Error = ();
+
+        fn visit(&mut self, scope: Scope, name: &'static str, _: FieldValue) -> Result<(), ()> {
+            self.0.push(match scope {
+                Scope::Level(i) => format!("{name}_{i:02}"),
+                Scope::Body | Scope::Header => name.to_owned(),
+            });
+            Ok(())
+        }
+    }
+
+    fn visited_names<T: VisitFields>(rec: &T) -> Vec<String> {
+        let mut collector = NameCollector::default();
+        rec.visit_fields(&mut collector).unwrap();
+        collector.0
+    }
+
+    #[test]
+    fn test_mbp10_visits_headers_in_order() {
+        let rec = Mbp10Msg {
+            hd: RECORD_HEADER,
+            price: 5500,
+            size: 3,
+            action: 'B' as c_char,
+            side: 'A' as c_char,
+            flags: -128,
+            depth: 9,
+            ts_recv: 1658441891000000000,
+            ts_in_delta: 22_000,
+            sequence: 1_002_375,
+            booklevel: [BID_ASK; 10],
+        };
+        assert_eq!(visited_names(&rec), Mbp10Msg::HEADERS);
+    }
+
+    #[test]
+    fn test_c_chars_to_str() {
+        let mut chars = [0 as c_char; 8];
+        for (c, b) in chars.iter_mut().zip(b"ESH1") {
+            *c = *b as c_char;
+        }
+        assert_eq!(FieldValue::CStr(&chars).as_str(), Some("ESH1"));
+        assert_eq!(FieldValue::CStr(&[]).as_str(), Some(""));
+        assert_eq!(FieldValue::U8(1).as_str(), None);
+    }
+
+    #[test]
+    fn test_c_char_value_is_signed() {
+        assert!(matches!(c_char_value('B' as c_char), FieldValue::Char(66)));
+        // the same on targets where `c_char` is `u8`
+        assert!(matches!(
+            c_char_value(0xC3_u8 as c_char),
+            FieldValue::Char(-61)
+        ));
+    }
+}
@@ -0,0 +1,294 @@
+//! Searching the records of a DBZ file for those with a field of a given value, e.g.
+//! to find every record of an order.
+use std::io;
+
+use anyhow::{anyhow, Context};
+use databento_defs::{
+    enums::Schema,
+    record::{Mbp10Msg, Mbp1Msg, OhlcvMsg, StatusMsg, SymDefMsg, TbboMsg, TickMsg, TradeMsg},
+};
+use streaming_iterator::StreamingIterator;
+
+use crate::{
+    fields::{FieldValue, FieldVisitor, Scope, VisitFields},
+    write::write_json_object,
+    Dbz,
+};
+
+/// A condition on the value of one field of a record. Fields are named as in the CSV
+/// output, so book level fields are suffixed by their zero-padded index, e.g.
+/// `bid_px_00`. Conditions are combined, e.g. [`FieldMatcher::ge`] and
+/// [`FieldMatcher::le`] for a range.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldMatcher {
+    field: String,
+    eq: Option<String>,
+    ge: Option<i128>,
+    le: Option<i128>,
+}
+
+impl FieldMatcher {
+    /// Creates a new [`FieldMatcher`] of `field` that matches every value.
+    pub fn new(field: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            eq: None,
+            ge: None,
+            le: None,
+        }
+    }
+
+    /// Matches only values equal to `value`. Numeric fields, including prices in units
+    /// of 1e-9 and timestamps in UNIX nanoseconds, are compared as integers, string
+    /// fields as text, and character fields as either the character or its code.
+    pub fn eq(mut self, value: impl Into<String>) -> Self {
+        self.eq = Some(value.into());
+        self
+    }
+
+    /// Matches only values greater than or equal to `value`. Only numeric fields can be
+    /// compared.
+    pub fn ge(mut self, value: i128) -> Self {
+        self.ge = Some(value);
+        self
+    }
+
+    /// Matches only values less than or equal to `value`. Only numeric fields can be
+    /// compared.
+    pub fn le(mut self, value: i128) -> Self {
+        self.le = Some(value);
+        self
+    }
+
+    /// Returns the name of the field.
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    fn matches_value(&self, value: FieldValue) -> anyhow::Result<bool> {
+        let number = match value {
+            FieldValue::I8(v) => Some(v as i128),
+            FieldValue::U8(v) => Some(v as i128),
+            FieldValue::I16(v) => Some(v as i128),
+            FieldValue::U16(v) => Some(v as i128),
+            FieldValue::I32(v) => Some(v as i128),
+            FieldValue::U32(v) => Some(v as i128),
+            FieldValue::I64(v) | FieldValue::Price(v) => Some(v as i128),
+            FieldValue::U64(v) | FieldValue::Timestamp(v) => Some(v as i128),
+            FieldValue::Char(_) | FieldValue::CStr(_) | FieldValue::Str(_) | FieldValue::Null => {
+                None
+            }
+        };
+        if let Some(eq) = &self.eq {
+            let is_eq = match (number, value) {
+                (Some(number), _) => {
+                    eq.parse::<i128>().map_err(|_| {
+                        anyhow!(
+                            "Field '{}' is numeric, so it can only equal an integer, not '{eq}'",
+                            self.field
+                        )
+                    })? == number
+                }
+                // characters are output as their code by default
+                (None, FieldValue::Char(c)) => {
+                    eq.as_bytes() == [c as u8] || eq.parse::<u8>() == Ok(c as u8)
+                }
+                (None, _) => value.as_str() == Some(eq.as_str()),
+            };
+            if !is_eq {
+                return Ok(false);
+            }
+        }
+        if self.ge.is_none() && self.le.is_none() {
+            return Ok(true);
+        }
+        let number = number.ok_or_else(|| {
+            anyhow!(
+                "Field '{}' isn't numeric, so it can't be compared to a bound",
+                self.field
+            )
+        })?;
+        Ok(self.ge.is_none_or(|ge| number >= ge) && self.le.is_none_or(|le| number <= le))
+    }
+}
+
+/// Finds the field of a [`FieldMatcher`] while visiting a record and checks its value.
+struct MatchVisitor<'a> {
+    matcher: &'a FieldMatcher,
+    is_match: bool,
+}
+
+impl FieldVisitor for MatchVisitor<'_> {
+    type Error = anyhow::Error;
+
+    fn visit(&mut self, scope: Scope, name: &'static str, value: FieldValue) -> anyhow::Result<()> {
+        let is_field = match scope {
+            Scope::Level(i) => {
+                self.matcher
+                    .field
+                    .strip_prefix(name)
+                    .and_then(|s| s.strip_prefix('_'))
+                    == Some(format!("{i:02}").as_str())
+            }
+            Scope::Body | Scope::Header => self.matcher.field == name,
+        };
+        if is_field {
+            self.is_match = self.matcher.matches_value(value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Writes the records matching `matcher` to `writer` as NDJSON, one object per
+    /// line with the `index` of the record in the DBZ and the `record` as in the
+    /// default JSON output. Returns the number of records that matched.
+    ///
+    /// # Errors
+    /// This function returns an error if the records of [`Dbz::schema()`] have no field
+    /// named like `matcher`'s, the value of `matcher` can't be compared to the field,
+    /// or the schema is [`Schema::Statistics`]. It will also return an error if
+    /// there's an issue writing to `writer`.
+    pub fn write_matches_to(
+        self,
+        writer: impl io::Write,
+        matcher: &FieldMatcher,
+    ) -> anyhow::Result<u64> {
+        match self.schema() {
+            Schema::Mbo => self.write_matches_of::<TickMsg>(writer, matcher),
+            Schema::Mbp1 => self.write_matches_of::<Mbp1Msg>(writer, matcher),
+            Schema::Mbp10 => self.write_matches_of::<Mbp10Msg>(writer, matcher),
+            Schema::Tbbo => self.write_matches_of::<TbboMsg>(writer, matcher),
+            Schema::Trades => self.write_matches_of::<TradeMsg>(writer, matcher),
+            Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D => {
+                self.write_matches_of::<OhlcvMsg>(writer, matcher)
+            }
+            Schema::Definition => self.write_matches_of::<SymDefMsg>(writer, matcher),
+            Schema::Statistics => Err(anyhow!("Not implemented for schema Statistics")),
+            Schema::Status => self.write_matches_of::<StatusMsg>(writer, matcher),
+        }
+    }
+
+    fn write_matches_of<T: VisitFields>(
+        self,
+        mut writer: impl io::Write,
+        matcher: &FieldMatcher,
+    ) -> anyhow::Result<u64> {
+        if !T::HEADERS.contains(&matcher.field.as_str()) {
+            return Err(anyhow!(
+                "Unknown field '{}' for schema {}, expected one of: {}",
+                matcher.field,
+                self.schema(),
+                T::HEADERS.join(", ")
+            ));
+        }
+        let mut records = self.try_into_iter::<T>()?;
+        let mut index = 0;
+        let mut match_count = 0;
+        while let Some(record) = records.next() {
+            let mut visitor = MatchVisitor {
+                matcher,
+                is_match: false,
+            };
+            record
+                .visit_fields(&mut visitor)
+                .with_context(|| format!("Failed to match record {index}"))?;
+            if visitor.is_match {
+                match write_match(&mut writer, index, record) {
+                    // broken output, likely a closed pipe
+                    Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(match_count),
+                    r => r,
+                }
+                .with_context(|| format!("Failed to serialize {record:#?}"))?;
+                match_count += 1;
+            }
+            index += 1;
+        }
+        if let Some(error) = records.take_error() {
+            return Err(error);
+        }
+        writer.flush()?;
+        Ok(match_count)
+    }
+}
+
+fn write_match<T: VisitFields>(
+    writer: &mut impl io::Write,
+    index: u64,
+    record: &T,
+) -> io::Result<()> {
+    write!(writer, "{{\"index\":{index},\"record\":")?;
+    write_json_object(writer, record)?;
+    writer.write_all(b"}\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    fn matches(schema: &str, matcher: &FieldMatcher) -> anyhow::Result<Vec<serde_json::Value>> {
+        let mut output = Vec::new();
+        let count = Dbz::from_file(format!("{DBZ_PATH}/test_data.{schema}.dbz"))?
+            .write_matches_to(&mut output, matcher)?;
+        let lines: Vec<serde_json::Value> = String::from_utf8(output)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(lines.len() as u64, count);
+        Ok(lines)
+    }
+
+    #[test]
+    fn test_eq() {
+        let all = matches("mbo", &FieldMatcher::new("rtype")).unwrap();
+        assert_eq!(all.len(), 2);
+        let order_id = all[1]["record"]["order_id"].as_u64().unwrap();
+        let res = matches(
+            "mbo",
+            &FieldMatcher::new("order_id").eq(order_id.to_string()),
+        )
+        .unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0]["index"], 1);
+        assert_eq!(res[0]["record"], all[1]["record"]);
+        assert_eq!(all[0]["record"]["action"], b'C');
+        for action in ["C", "67"] {
+            let res = matches("mbo", &FieldMatcher::new("action").eq(action)).unwrap();
+            assert_eq!(res.len(), 2);
+        }
+        assert!(matches("mbo", &FieldMatcher::new("action").eq("T"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_range() {
+        let all = matches("mbp-10", &FieldMatcher::new("bid_px_00")).unwrap();
+        let bid_px = all[0]["record"]["booklevel"][0]["bid_px"].as_i64().unwrap() as i128;
+        let res = matches(
+            "mbp-10",
+            &FieldMatcher::new("bid_px_00").ge(bid_px).le(bid_px),
+        )
+        .unwrap();
+        assert!(!res.is_empty());
+        assert!(res
+            .iter()
+            .all(|m| m["record"]["booklevel"][0]["bid_px"]
+                == all[0]["record"]["booklevel"][0]["bid_px"]));
+        assert!(
+            matches("mbp-10", &FieldMatcher::new("bid_px_00").ge(i128::MAX))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let err = matches("trades", &FieldMatcher::new("order_id").eq("1")).unwrap_err();
+        assert!(err.to_string().starts_with("Unknown field 'order_id'"));
+        assert!(matches("trades", &FieldMatcher::new("price").eq("1.5")).is_err());
+        assert!(matches("trades", &FieldMatcher::new("action").ge(0)).is_err());
+    }
+}
@@ -0,0 +1,525 @@
+//! External indexes of the positions of records in DBZ files, for random access to
+//! files without rewriting them.
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Seek},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use zstd::Decoder;
+
+use crate::{
+    read::{read_record, FromLittleEndianSlice},
+    record::record_size,
+    Dbz, Metadata, Record,
+};
+
+/// The magic bytes at the start of an index file.
+const INDEX_MAGIC: &[u8; 4] = b"DBZI";
+/// The version of the index format. Version 2 added the `ts_event` of each frame and
+/// version 3 replaced the offset of each record with the record count.
+const INDEX_VERSION: u8 = 3;
+/// The offset of `ts_event` in the record header.
+const TS_EVENT_OFFSET: usize = 8;
+
+/// An index of the positions of the records in a DBZ file, saved to a separate file
+/// so existing files can be read from any record without being rewritten. Built with
+/// [`RecordIndex::build`] or [`DbzWriter::finish_with_index`](crate::DbzWriter::finish_with_index)
+/// and used by [`Dbz::from_file_at_record`] and [`Dbz::seek_to_ts`].
+///
+/// The index holds the number of records and, for each Zstd frame of records, its
+/// offset in the file, the decompressed offset it starts at, and the `ts_event` of
+/// its first record, so decoding can start from the frame containing a record. The
+/// records of a schema all have the same size, so the decompressed offset of each
+/// record follows from its position. Files have a single frame of records
+/// unless they were written with
+/// [`WriterOptions::frame_record_count`](crate::WriterOptions::frame_record_count).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordIndex {
+    /// The size of the indexed file, for detecting an index that's out of date.
+    dbz_len: u64,
+    frames: Vec<FrameOffset>,
+    record_count: u64,
+}
+
+/// The position of a Zstd frame of records in a DBZ file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameOffset {
+    /// The offset of the frame from the start of the file.
+    pub compressed: u64,
+    /// The offset in the decompressed records where the frame starts.
+    pub decompressed: u64,
+    /// The `ts_event` of the first record in the frame, or `u64::MAX` if it has no
+    /// records.
+    pub ts_event: u64,
+}
+
+impl RecordIndex {
+    pub(crate) fn new(dbz_len: u64, frames: Vec<FrameOffset>, record_count: u64) -> Self {
+        Self {
+            dbz_len,
+            frames,
+            record_count,
+        }
+    }
+
+    /// Builds the index of the DBZ file at `path` by decoding all of its records.
+    ///
+    /// # Errors
+    /// This function returns an error if `path` can't be read or one of its records
+    /// can't be decoded.
+    pub fn build(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let mut file = File::open(path)
+            .with_context(|| format!("Error opening dbz file at path '{}'", path.display()))?;
+        let dbz_len = file.metadata()?.len();
+        // reading the metadata from the unbuffered file leaves it at the records
+        let metadata = Metadata::read(&mut file)?;
+        let record_size = record_size(metadata.schema)
+            .ok_or_else(|| anyhow!("Not implemented for schema {}", metadata.schema.as_str()))?;
+        let mut reader = BufReader::new(file);
+        let mut frames = Vec::new();
+        let mut record_count = 0;
+        let mut buffer = vec![0; record_size];
+        // an empty body still has a frame to start decoding from
+        while frames.is_empty() || !reader.fill_buf()?.is_empty() {
+            let mut frame = FrameOffset {
+                compressed: reader.stream_position()?,
+                decompressed: record_count * record_size as u64,
+                ts_event: u64::MAX,
+            };
+            // the decoder only consumes the bytes of the frame from `reader`
+            let mut decoder = Decoder::with_buffer(&mut reader)?.single_frame();
+            while read_record(&mut decoder, &mut buffer)
+                .with_context(|| format!("Failed to decode record {record_count}"))?
+            {
+                Record::from_bytes(metadata.schema, &buffer).ok_or_else(|| {
+                    anyhow!(
+                        "Record {record_count} has rtype {:#04x}, which doesn't match schema {}",
+                        // the rtype follows the length in the header
+                        buffer[1],
+                        metadata.schema.as_str()
+                    )
+                })?;
+                if frame.decompressed == record_count * record_size as u64 {
+                    frame.ts_event = u64::from_le_slice(&buffer[TS_EVENT_OFFSET..]);
+                }
+                record_count += 1;
+            }
+            // skippable frames like a checksum footer have no records
+            if frames.is_empty() || frame.decompressed < record_count * record_size as u64 {
+                frames.push(frame);
+            }
+        }
+        Ok(Self::new(dbz_len, frames, record_count))
+    }
+
+    /// Returns the conventional path of the index of the DBZ file at `dbz_path`, which
+    /// appends `.idx` to it, like `trades.dbz.idx`. [`Dbz::from_file_at_record`] looks
+    /// for the index there.
+    pub fn path_for(dbz_path: impl AsRef<Path>) -> PathBuf {
+        let mut path = dbz_path.as_ref().as_os_str().to_owned();
+        path.push(".idx");
+        PathBuf::from(path)
+    }
+
+    /// Reads an index previously saved with [`RecordIndex::encode`] from the file at
+    /// `path`.
+    ///
+    /// # Errors
+    /// This function returns an error if `path` can't be read or isn't an index.
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("Error opening index file at path '{}'", path.display()))?;
+        Self::decode(BufReader::new(file))
+            .with_context(|| format!("Invalid index file '{}'", path.display()))
+    }
+
+    /// Writes the index to `writer` in a compact binary format of little-endian
+    /// integers.
+    ///
+    /// # Errors
+    /// This function returns an error if there's an issue writing to `writer`.
+    pub fn encode(&self, mut writer: impl io::Write) -> anyhow::Result<()> {
+        writer.write_all(INDEX_MAGIC)?;
+        writer.write_all(&[INDEX_VERSION])?;
+        writer.write_all(&self.dbz_len.to_le_bytes())?;
+        writer.write_all(&(self.frames.len() as u64).to_le_bytes())?;
+        for frame in self.frames.iter() {
+            writer.write_all(&frame.compressed.to_le_bytes())?;
+            writer.write_all(&frame.decompressed.to_le_bytes())?;
+            writer.write_all(&frame.ts_event.to_le_bytes())?;
+        }
+        writer.write_all(&self.record_count.to_le_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads an index in the format written by [`RecordIndex::encode`] from `reader`.
+    ///
+    /// # Errors
+    /// This function returns an error if `reader` doesn't contain an index of a
+    /// supported version or it's truncated.
+    pub fn decode(mut reader: impl io::Read) -> anyhow::Result<Self> {
+        let mut prelude = [0u8; INDEX_MAGIC.len() + 1];
+        reader
+            .read_exact(&mut prelude)
+            .context("Failed to read index prelude")?;
+        if &prelude[..INDEX_MAGIC.len()] != INDEX_MAGIC {
+            return Err(anyhow!("No index magic bytes"));
+        }
+        let version = prelude[INDEX_MAGIC.len()];
+        if version != INDEX_VERSION {
+            return Err(anyhow!(
+                "Unsupported index version {version}. Rebuild it with `dbz index`"
+            ));
+        }
+        let dbz_len = read_u64(&mut reader)?;
+        // the counts aren't trusted for preallocating, in case the file is corrupt
+        let frame_count = read_u64(&mut reader)?;
+        let mut frames = Vec::new();
+        for _ in 0..frame_count {
+            frames.push(FrameOffset {
+                compressed: read_u64(&mut reader)?,
+                decompressed: read_u64(&mut reader)?,
+                ts_event: read_u64(&mut reader)?,
+            });
+        }
+        let record_count = read_u64(&mut reader)?;
+        Ok(Self {
+            dbz_len,
+            frames,
+            record_count,
+        })
+    }
+
+    /// Returns the number of records in the index.
+    pub fn len(&self) -> usize {
+        self.record_count as usize
+    }
+
+    /// Returns `true` if the indexed file has no records.
+    pub fn is_empty(&self) -> bool {
+        self.record_count == 0
+    }
+
+    /// Returns the Zstd frames of records in the indexed file.
+    pub fn frames(&self) -> &[FrameOffset] {
+        &self.frames
+    }
+
+    /// Returns the frame containing the decompressed `offset` and the number of
+    /// decompressed bytes to skip from its start.
+    fn locate(&self, offset: u64) -> Option<(FrameOffset, u64)> {
+        let frame = self
+            .frames
+            .iter()
+            .rev()
+            .find(|frame| frame.decompressed <= offset)?;
+        Some((*frame, offset - frame.decompressed))
+    }
+}
+
+fn read_u64(reader: &mut impl io::Read) -> anyhow::Result<u64> {
+    let mut buffer = [0u8; 8];
+    reader
+        .read_exact(&mut buffer)
+        .context("Index file truncated")?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+impl Dbz<BufReader<File>> {
+    /// Creates a new [`Dbz`] from the file at `path` whose records start at record
+    /// `record`, e.g. to resume processing a large file or read a page of its records.
+    /// The metadata is unchanged.
+    ///
+    /// When the index at [`RecordIndex::path_for`] exists, decoding starts from the
+    /// frame containing the record. Otherwise, the records before it are decompressed
+    /// and discarded without being decoded.
+    ///
+    /// # Errors
+    /// This function returns an error if `path` can't be read, the index exists but
+    /// is invalid or doesn't match the file, or the file has fewer than `record`
+    /// records. It will also return an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`](databento_defs::enums::Schema::Statistics).
+    pub fn from_file_at_record(path: impl AsRef<Path>, record: usize) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let mut file = File::open(path)
+            .with_context(|| format!("Error opening dbz file at path '{}'", path.display()))?;
+        let metadata = Metadata::read(&mut file)?;
+        let record_size = record_size(metadata.schema)
+            .ok_or_else(|| anyhow!("Not implemented for schema {}", metadata.schema.as_str()))?;
+        let index_path = RecordIndex::path_for(path);
+        let (bytes_before, skip_bytes) = if index_path.exists() {
+            let index = RecordIndex::from_file(&index_path)?;
+            if index.dbz_len != file.metadata()?.len() {
+                return Err(anyhow!(
+                    "Index '{}' doesn't match '{}'. Rebuild it with `dbz index`",
+                    index_path.display(),
+                    path.display()
+                ));
+            }
+            // starting right after the last record yields no records
+            if record > index.len() {
+                return Err(anyhow!(
+                    "Can't start at record {record} of '{}' with {} records",
+                    path.display(),
+                    index.len()
+                ));
+            }
+            let (frame, skip_bytes) = index
+                .locate((record * record_size) as u64)
+                .ok_or_else(|| anyhow!("Index '{}' has no frames", index_path.display()))?;
+            file.seek(io::SeekFrom::Start(frame.compressed))?;
+            (frame.decompressed, skip_bytes)
+        } else {
+            (0, (record * record_size) as u64)
+        };
+        Ok(Self::from_parts(BufReader::new(file), metadata)
+            .with_records_before(bytes_before / record_size as u64)
+            .with_skip_bytes(skip_bytes))
+    }
+}
+
+impl<R: io::BufRead + io::Seek> Dbz<R> {
+    /// Moves to the first record with a `ts_event` at or after `ts`, so iterating
+    /// starts from it. Decoding starts from the Zstd frame that `index` locates `ts`
+    /// in, so only the records of that frame before `ts` are decompressed and
+    /// skipped. Assumes the records are in `ts_event` order, like those of DBZ files
+    /// from Databento. Must be called before iterating.
+    ///
+    /// # Errors
+    /// This function returns an error if `index` doesn't match the data or there's an
+    /// issue seeking or decompressing the data. It will also return an error if
+    /// [`Dbz::schema()`] is
+    /// [`Schema::Statistics`](databento_defs::enums::Schema::Statistics).
+    pub fn seek_to_ts(self, index: &RecordIndex, ts: u64) -> anyhow::Result<Self> {
+        let record_size = record_size(self.schema())
+            .ok_or_else(|| anyhow!("Not implemented for schema {}", self.schema().as_str()))?;
+        let (dbz, mut reader) = self.replace_reader(io::empty());
+        let dbz_len = reader.seek(io::SeekFrom::End(0))?;
+        if dbz_len != index.dbz_len {
+            return Err(anyhow!(
+                "Index of {} bytes of DBZ data doesn't match the {dbz_len} bytes read. \
+                Rebuild it with `dbz index`",
+                index.dbz_len
+            ));
+        }
+        // the last frame starting before `ts` may still contain records at or after it
+        let frame = index
+            .frames
+            .iter()
+            .rev()
+            .find(|frame| frame.ts_event < ts)
+            .or_else(|| index.frames.first())
+            .copied()
+            .ok_or_else(|| anyhow!("Index has no frames"))?;
+        reader.seek(io::SeekFrom::Start(frame.compressed))?;
+        let mut skip_bytes = 0;
+        {
+            let mut decoder = match dbz.dictionary() {
+                Some(dictionary) => Decoder::with_dictionary(&mut reader, dictionary)?,
+                None => Decoder::with_buffer(&mut reader)?,
+            }
+            .single_frame();
+            let mut buffer = vec![0; record_size];
+            while read_record(&mut decoder, &mut buffer)
+                .context("Failed to decode the frame containing the timestamp")?
+            {
+                if u64::from_le_slice(&buffer[TS_EVENT_OFFSET..]) >= ts {
+                    break;
+                }
+                skip_bytes += record_size as u64;
+            }
+        }
+        reader.seek(io::SeekFrom::Start(frame.compressed))?;
+        Ok(dbz
+            .replace_reader(reader)
+            .0
+            .with_records_before(frame.decompressed / record_size as u64)
+            .with_skip_bytes(skip_bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::BufWriter};
+
+    use databento_defs::record::TradeMsg;
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+    use crate::{DbzWriter, WriterOptions};
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    /// Copies the test data of `schema` to `dir`, so an index can be saved next to it.
+    fn copy_test_data(dir: &TempDir, schema: &str) -> PathBuf {
+        let path = dir.path().join(format!("test_data.{schema}.dbz"));
+        fs::copy(format!("{DBZ_PATH}/test_data.{schema}.dbz"), &path).unwrap();
+        path
+    }
+
+    fn records(dbz: Dbz<BufReader<File>>) -> Vec<Record> {
+        dbz.into_record_iter()
+            .unwrap()
+            .collect::<anyhow::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_build_and_decode() {
+        let path = format!("{DBZ_PATH}/test_data.mbp-10.dbz");
+        let index = RecordIndex::build(&path).unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.frames().len(), 1);
+        let mut buffer = Vec::new();
+        index.encode(&mut buffer).unwrap();
+        // the prelude, file size, frame count, one frame, and record count
+        assert_eq!(buffer.len(), 5 + 8 + 8 + 3 * 8 + 8);
+        assert_eq!(RecordIndex::decode(buffer.as_slice()).unwrap(), index);
+        // truncated
+        assert!(RecordIndex::decode(&buffer[..buffer.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_from_file_at_record() {
+        let dir = tempdir().unwrap();
+        let path = copy_test_data(&dir, "mbo");
+        let expected = records(Dbz::from_file(&path).unwrap());
+        // without an index
+        assert_eq!(
+            records(Dbz::from_file_at_record(&path, 1).unwrap()),
+            &expected[1..]
+        );
+        let index_path = RecordIndex::path_for(&path);
+        RecordIndex::build(&path)
+            .unwrap()
+            .encode(File::create(&index_path).unwrap())
+            .unwrap();
+        let res = [0, 1, 2].map(|i| records(Dbz::from_file_at_record(&path, i).unwrap()));
+        let past_end = Dbz::from_file_at_record(&path, 3);
+        assert_eq!(res[0], expected);
+        assert_eq!(res[1], &expected[1..]);
+        assert!(res[2].is_empty());
+        assert!(past_end.is_err());
+    }
+
+    #[test]
+    fn test_from_file_at_record_stale_index() {
+        let dir = tempdir().unwrap();
+        let path = copy_test_data(&dir, "trades");
+        let index_path = RecordIndex::path_for(&path);
+        RecordIndex::build(&path)
+            .unwrap()
+            .encode(File::create(&index_path).unwrap())
+            .unwrap();
+        fs::copy(format!("{DBZ_PATH}/test_data.mbp-10.dbz"), &path).unwrap();
+        let res = Dbz::from_file_at_record(&path, 1);
+        assert!(format!("{:#}", res.unwrap_err()).contains("doesn't match"));
+    }
+
+    /// Writes 10 trades in frames of 3 records to a file in `dir`, with pairs of
+    /// records sharing a `ts_event`. Returns the path, the index returned by the
+    /// writer, and the records.
+    fn write_framed_trades(dir: &TempDir) -> (PathBuf, RecordIndex, Vec<TradeMsg>) {
+        let dbz = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let metadata = dbz.metadata().clone();
+        let first = dbz
+            .try_into_fallible_iter::<TradeMsg>()
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        let records = (0..10)
+            .map(|i| {
+                let mut record = first.clone();
+                record.hd.ts_event += (i / 2) * 10;
+                record.sequence = i as u32;
+                record
+            })
+            .collect::<Vec<_>>();
+        let path = dir.path().join("framed.trades.dbz");
+        let options = WriterOptions {
+            frame_record_count: Some(3),
+            ..Default::default()
+        };
+        let mut writer = DbzWriter::with_options(
+            BufWriter::new(File::create(&path).unwrap()),
+            &metadata,
+            &options,
+        )
+        .unwrap();
+        for record in records.iter() {
+            writer.write_record(record).unwrap();
+        }
+        let (_, index) = writer.finish_with_index().unwrap();
+        (path, index, records)
+    }
+
+    #[test]
+    fn test_writer_index_matches_build() {
+        let dir = tempdir().unwrap();
+        let (path, index, records) = write_framed_trades(&dir);
+        let built = RecordIndex::build(&path);
+        let read = Dbz::from_file(&path)
+            .unwrap()
+            .try_into_fallible_iter::<TradeMsg>()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>();
+        assert_eq!(built.unwrap(), index);
+        assert_eq!(read.unwrap(), records);
+        assert_eq!(index.len(), 10);
+        let frames = index.frames();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(
+            frames[1].decompressed,
+            3 * std::mem::size_of::<TradeMsg>() as u64
+        );
+        assert_eq!(frames[1].ts_event, records[3].hd.ts_event);
+        assert!(frames.windows(2).all(|w| w[0].compressed < w[1].compressed));
+    }
+
+    #[test]
+    fn test_seek_to_ts() {
+        let dir = tempdir().unwrap();
+        let (path, index, records) = write_framed_trades(&dir);
+        let first_ts = records[0].hd.ts_event;
+        // before, at the start of, in the middle of, and past the end of the frames
+        let res = [0, first_ts, first_ts + 10, first_ts + 25, first_ts + 100].map(|ts| {
+            Dbz::from_file(&path)
+                .unwrap()
+                .seek_to_ts(&index, ts)
+                .unwrap()
+                .try_into_fallible_iter::<TradeMsg>()
+                .unwrap()
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap()
+        });
+        let stale = RecordIndex::build(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let stale_res = Dbz::from_file(&path).unwrap().seek_to_ts(&stale, first_ts);
+        assert_eq!(res[0], records);
+        assert_eq!(res[1], records);
+        // the record with the timestamp that starts the second frame is in the first
+        assert_eq!(res[2], &records[2..]);
+        assert_eq!(res[3], &records[6..]);
+        assert!(res[4].is_empty());
+        assert!(format!("{:#}", stale_res.unwrap_err()).contains("doesn't match"));
+    }
+
+    #[test]
+    fn test_from_file_at_record_framed() {
+        let dir = tempdir().unwrap();
+        let (path, index, records) = write_framed_trades(&dir);
+        let index_path = RecordIndex::path_for(&path);
+        index.encode(File::create(&index_path).unwrap()).unwrap();
+        let res = Dbz::from_file_at_record(&path, 7)
+            .unwrap()
+            .try_into_fallible_iter::<TradeMsg>()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>();
+        assert_eq!(res.unwrap(), &records[7..]);
+    }
+}
@@ -0,0 +1,277 @@
+//! Checks that DBZ data decodes, reporting every problem instead of only the first.
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context};
+use databento_defs::enums::Schema;
+use serde::Serialize;
+use zstd::Decoder;
+
+use crate::{
+    read::read_record,
+    record::{record_size, Record},
+    Metadata,
+};
+
+/// The problems found decoding DBZ data, returned by [`IntegrityReport::from_file`]
+/// and [`IntegrityReport::from_reader`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct IntegrityReport {
+    /// The schema from the metadata, if it could be decoded.
+    pub schema: Option<Schema>,
+    /// The number of whole records read, including those with problems.
+    pub record_count: u64,
+    /// The number of records according to the metadata, if it could be decoded.
+    pub expected_record_count: Option<u64>,
+    /// The number of problems found, which may be more than are listed in
+    /// `problems`.
+    pub problem_count: u64,
+    /// Up to [`IntegrityReport::MAX_PROBLEMS`] problems, in the order they were
+    /// found.
+    pub problems: Vec<IntegrityProblem>,
+}
+
+/// A problem decoding DBZ data and where it was found.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct IntegrityProblem {
+    /// The offset of the problem in the file for problems in the metadata, or in the
+    /// decompressed records for problems in a record. `None` when it isn't known.
+    pub byte_offset: Option<u64>,
+    /// The index of the record with the problem, if it's in a record.
+    pub record_index: Option<u64>,
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl IntegrityReport {
+    /// The maximum number of problems listed.
+    pub const MAX_PROBLEMS: usize = 100;
+
+    /// Checks the DBZ file at `path`, see [`IntegrityReport::from_reader`].
+    ///
+    /// # Errors
+    /// This function returns an error if it's unable to open the file at `path`.
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = File::open(path.as_ref()).with_context(|| {
+            format!(
+                "Error opening dbz file at path '{}'",
+                path.as_ref().display()
+            )
+        })?;
+        Ok(Self::from_reader(BufReader::new(file)))
+    }
+
+    /// Decodes the metadata and every record in `reader`, continuing past problems
+    /// where the position of the following data is still known: each field of the
+    /// fixed-length metadata and each record is checked even if an earlier one is
+    /// invalid. Checking stops at problems that make the rest of the data unreadable,
+    /// such as an invalid Zstd frame or a truncated record.
+    ///
+    /// Records compressed with a dictionary can't be checked.
+    pub fn from_reader(mut reader: impl io::BufRead) -> Self {
+        let mut report = Self::default();
+        let mut prelude = [0; Metadata::PRELUDE_LEN];
+        if let Err(e) = reader.read_exact(&mut prelude) {
+            report.add(
+                Some(0),
+                None,
+                anyhow::Error::new(e).context("Failed to read metadata prelude"),
+            );
+            return report;
+        }
+        let frame_size = match Metadata::frame_size(&prelude) {
+            Ok(frame_size) => frame_size,
+            Err(e) => {
+                report.add(Some(0), None, e);
+                return report;
+            }
+        };
+        let mut metadata_buffer = vec![0; frame_size];
+        if let Err(e) = reader.read_exact(&mut metadata_buffer) {
+            let e = anyhow::Error::new(e).context("Failed to read metadata");
+            report.add(Some(Metadata::PRELUDE_LEN as u64), None, e);
+            return report;
+        }
+        let mut problems = Vec::new();
+        let metadata = Metadata::decode_checked(&metadata_buffer, &mut problems);
+        for (pos, e) in problems {
+            report.add(Some((Metadata::PRELUDE_LEN + pos) as u64), None, e);
+        }
+        let Some(metadata) = metadata else {
+            return report;
+        };
+        report.schema = Some(metadata.schema);
+        report.expected_record_count = Some(metadata.record_count);
+        report.check_records(reader, &metadata);
+        report
+    }
+
+    /// Returns `true` if no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.problem_count == 0
+    }
+
+    fn check_records(&mut self, reader: impl io::BufRead, metadata: &Metadata) {
+        let schema = metadata.schema;
+        let Some(record_size) = record_size(schema) else {
+            let e = anyhow!("Not implemented for schema {}", schema.as_str());
+            return self.add(None, None, e);
+        };
+        let mut decoder = match Decoder::with_buffer(reader) {
+            Ok(decoder) => decoder,
+            Err(e) => return self.add(Some(0), None, anyhow::Error::new(e)),
+        };
+        let mut buffer = vec![0; record_size];
+        loop {
+            let index = self.record_count;
+            let byte_offset = Some(index * record_size as u64);
+            match read_record(&mut decoder, &mut buffer) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => {
+                    let e = anyhow::Error::new(e).context(format!("Failed to read record {index}"));
+                    return self.add(byte_offset, Some(index), e);
+                }
+            }
+            self.record_count += 1;
+            if Record::from_bytes(schema, &buffer).is_none() {
+                // the rtype follows the length in the header
+                let e = anyhow!(
+                    "Record {index} has rtype {:#04x}, which doesn't match schema {}",
+                    buffer[1],
+                    schema.as_str()
+                );
+                self.add(byte_offset, Some(index), e);
+            }
+        }
+        if self.record_count != metadata.record_count {
+            let e = anyhow!(
+                "DBZ data has {} records, expected {} from the metadata",
+                self.record_count,
+                metadata.record_count
+            );
+            self.add(None, None, e);
+        }
+    }
+
+    fn add(&mut self, byte_offset: Option<u64>, record_index: Option<u64>, error: anyhow::Error) {
+        self.problem_count += 1;
+        if self.problems.len() < Self::MAX_PROBLEMS {
+            self.problems.push(IntegrityProblem {
+                byte_offset,
+                record_index,
+                message: format!("{error:#}"),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use databento_defs::record::TickMsg;
+
+    use super::*;
+    use crate::{write::dbz::SCHEMA_VERSION, Dbz};
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    /// Returns the test data of `schema` with its records decompressed, so they can be
+    /// modified and recompressed.
+    fn split_test_data(schema: &str) -> (Vec<u8>, Vec<u8>) {
+        let bytes = std::fs::read(format!("{DBZ_PATH}/test_data.{schema}.dbz")).unwrap();
+        let mut prelude = [0; Metadata::PRELUDE_LEN];
+        prelude.copy_from_slice(&bytes[..Metadata::PRELUDE_LEN]);
+        let metadata_len = Metadata::PRELUDE_LEN + Metadata::frame_size(&prelude).unwrap();
+        let records = zstd::decode_all(&bytes[metadata_len..]).unwrap();
+        (bytes[..metadata_len].to_vec(), records)
+    }
+
+    fn join(metadata: &[u8], records: &[u8]) -> Vec<u8> {
+        let mut res = metadata.to_vec();
+        res.extend(zstd::encode_all(records, 0).unwrap());
+        res
+    }
+
+    #[test]
+    fn test_clean() {
+        for schema in ["mbo", "mbp-1", "mbp-10", "ohlcv-1s", "tbbo", "trades"] {
+            let report =
+                IntegrityReport::from_file(format!("{DBZ_PATH}/test_data.{schema}.dbz")).unwrap();
+            assert!(report.is_clean(), "{schema}: {:?}", report.problems);
+            assert_eq!(report.record_count, 2);
+            assert_eq!(report.expected_record_count, Some(2));
+        }
+    }
+
+    #[test]
+    fn test_metadata_problems() {
+        let (mut metadata, records) = split_test_data("mbo");
+        let schema_offset =
+            Metadata::PRELUDE_LEN + Metadata::VERSION_CSTR_LEN + Metadata::DATASET_CSTR_LEN;
+        // the version, an invalid schema, and an invalid compression that follows it
+        metadata[Metadata::PRELUDE_LEN + 3] = SCHEMA_VERSION + 1;
+        metadata[schema_offset] = 0xFF;
+        let compression_offset = schema_offset + 2 + 4 * 8;
+        metadata[compression_offset] = 0xFF;
+        let report = IntegrityReport::from_reader(Cursor::new(join(&metadata, &records)));
+        assert_eq!(report.problem_count, 3);
+        let offsets = report
+            .problems
+            .iter()
+            .map(|problem| problem.byte_offset.unwrap() as usize)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            offsets,
+            vec![Metadata::PRELUDE_LEN + 3, schema_offset, compression_offset]
+        );
+        assert!(report.problems[1].message.contains("schema"));
+        // records can't be checked without the schema
+        assert_eq!(report.schema, None);
+        // decoding normally stops at the first problem
+        let error = Dbz::new(Cursor::new(join(&metadata, &records))).unwrap_err();
+        assert_eq!(error.to_string(), report.problems[0].message);
+    }
+
+    #[test]
+    fn test_record_problems() {
+        let (metadata, mut records) = split_test_data("mbo");
+        let record_size = std::mem::size_of::<TickMsg>();
+        // corrupt the rtype of both records and truncate a third
+        records[1] = 0xFF;
+        records[record_size + 1] = 0xFF;
+        records.extend_from_within(..10);
+        let report = IntegrityReport::from_reader(Cursor::new(join(&metadata, &records)));
+        assert_eq!(report.schema, Some(Schema::Mbo));
+        assert_eq!(report.record_count, 2);
+        let positions = report
+            .problems
+            .iter()
+            .map(|problem| (problem.byte_offset, problem.record_index))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            vec![
+                (Some(0), Some(0)),
+                (Some(record_size as u64), Some(1)),
+                (Some(2 * record_size as u64), Some(2)),
+            ]
+        );
+        assert!(report.problems[2].message.contains("truncated"));
+    }
+
+    #[test]
+    fn test_missing_records() {
+        let (metadata, records) = split_test_data("mbo");
+        let record_size = std::mem::size_of::<TickMsg>();
+        let report =
+            IntegrityReport::from_reader(Cursor::new(join(&metadata, &records[..record_size])));
+        assert_eq!(report.problem_count, 1);
+        assert_eq!(report.problems[0].record_index, None);
+        assert!(report.problems[0].message.contains("expected 2"));
+    }
+}
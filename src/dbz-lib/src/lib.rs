@@ -1,15 +1,96 @@
 //! A crate for reading DBZ files and converting them to other [OutputEncoding]s.
-#[deny(missing_docs)]
-#[deny(rustdoc::broken_intra_doc_links)]
-#[deny(clippy::missing_errors_doc)]
+#![deny(missing_docs)]
+#![deny(rustdoc::broken_intra_doc_links)]
+#![deny(clippy::missing_errors_doc)]
+
+mod adjust;
+mod book;
+mod cache;
+mod checksum;
+mod conformance;
+mod continuity;
+mod definition;
+mod dict;
+mod diff;
+mod display;
+mod error;
+mod fields;
+mod grep;
+mod index;
+mod integrity;
+mod manifest;
+mod merge;
+#[cfg(feature = "rayon")]
+mod par;
+mod parse;
+mod query;
 mod read;
+mod record;
+mod sample;
+mod sort;
+mod spec;
+mod split;
+mod stats;
+mod stream;
+mod suite;
+mod symbology;
+mod tail;
+mod timing;
+mod transform;
 mod write;
 
+#[cfg(feature = "tokio")]
+pub mod r#async;
 #[cfg(any(feature = "python", feature = "python-test"))]
 pub mod python;
 
-pub use crate::read::{Dbz, DbzStreamIter, MappingInterval, Metadata, SymbolMapping};
+pub use databento_defs::enums::{Compression, SType, Schema};
+
+pub use crate::adjust::{Adjuster, Adjustment, AdjustmentTable};
+pub use crate::book::BookBuilder;
+pub use crate::cache::MetadataCache;
+pub use crate::conformance::ConformanceReport;
+pub use crate::continuity::{ContinuityChecker, ContinuityIssue, ContinuityReport, FileBoundary};
+pub use crate::definition::{FieldDefinition, SchemaDefinition};
+pub use crate::dict::train_dict;
+pub use crate::diff::{FieldDiff, RecordComparator, RecordDiff, RecordDiffIter};
+pub use crate::display::PriceDisplay;
+pub use crate::error::Error;
+pub use crate::fields::UNDEF_PRICE;
+pub use crate::grep::FieldMatcher;
+pub use crate::index::{FrameOffset, RecordIndex};
+pub use crate::integrity::{IntegrityProblem, IntegrityReport};
+pub use crate::manifest::{DbzDataset, Manifest, ManifestFile};
+pub use crate::merge::{merge, merge_with_options, MergeOptions};
+#[cfg(feature = "rayon")]
+pub use crate::par::DbzParIter;
+pub use crate::parse::{encode_from_csv, encode_from_json, TextRecordReader};
+pub use crate::query::{DbzRangeIter, RangeOptions, RecordOrder, TieBreak};
+pub use crate::read::{
+    Dbz, DbzFallibleIter, DbzStreamIter, DecodeStats, MappingInterval, Metadata, SizeHintPolicy,
+    SymbolMapping, UnknownRtypePolicy,
+};
+pub use crate::record::{DbzJsonIter, DbzRecordIter, Record};
+pub use crate::sample::SampleOptions;
+pub use crate::sort::SortOptions;
+pub use crate::spec::{
+    EnumValueSpec, FieldSpec, FormatSpec, MetadataSpec, SchemaSpec, StructSpec, VariableFieldSpec,
+};
+pub use crate::split::{plan_symbol_split, split, split_by_symbol, SplitBy, SymbolPartition};
+pub use crate::stats::{
+    Burst, DataSummary, Histogram, HistogramBucket, MessageRates, ProductActivity, ProductRates,
+    SequenceGap,
+};
+pub use crate::stream::DbzStreamDecoder;
+pub use crate::suite::{run_conformance_suite, suite_cases, CaseResult, SuiteCase};
+pub use crate::symbology::{mappings_from_symbology_json, SymbolMap, SymbolResolver};
+pub use crate::timing::{PublisherTiming, TimingIssues, TimingReport};
+pub use crate::transform::Retimestamp;
 pub use crate::write::{
-    dbz::{write_dbz, write_dbz_stream},
-    OutputEncoding,
+    dbz::{
+        write_dbz, write_dbz_stream, write_dbz_stream_with_options, write_dbz_with_options,
+        DbzWriter,
+    },
+    CharFormat, ColumnPreset, FanoutOutput, OutputEncoding, OutputEstimate, OutputFile,
+    OutputOptions, PipelineOptions, RotatingDbzWriter, RotationOptions, UndefPrice, WriterOptions,
 };
@@ -0,0 +1,334 @@
+//! Manifests describing collections of DBZ files, such as a directory of daily files.
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use databento_defs::enums::Schema;
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{Dbz, Metadata};
+
+/// A description of a collection of DBZ files of the same dataset and schema, for
+/// finding the files with records in a time range without opening every file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The version of the manifest format.
+    pub version: u8,
+    /// The dataset of all the files.
+    pub dataset: String,
+    /// The schema of all the files.
+    #[serde(deserialize_with = "deserialize_schema")]
+    pub schema: Schema,
+    /// The files, ordered by `start`.
+    pub files: Vec<ManifestFile>,
+}
+
+/// A DBZ file in a [`Manifest`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// The path of the file. Relative paths are relative to the directory of the
+    /// manifest.
+    pub path: PathBuf,
+    /// The UNIX nanosecond timestamp of the start of the file's time range.
+    pub start: u64,
+    /// The UNIX nanosecond timestamp of the end of the file's time range.
+    pub end: u64,
+    /// The number of records in the file.
+    pub record_count: u64,
+    /// The hex-encoded SHA-256 digest of the file.
+    pub sha256: String,
+}
+
+impl Manifest {
+    /// The current version of the manifest format.
+    pub const VERSION: u8 = 1;
+
+    /// Builds a manifest of the DBZ files at `paths` from their metadata. The paths
+    /// of files within `root` are stored relative to it.
+    ///
+    /// # Errors
+    /// This function returns an error if `paths` is empty, if any of the files can't
+    /// be read, or if the files don't all have the same dataset and schema.
+    pub fn build(root: &Path, paths: &[PathBuf]) -> anyhow::Result<Self> {
+        let mut manifest: Option<Manifest> = None;
+        for path in paths {
+            let metadata = Metadata::from_file(path)?;
+            let file = ManifestFile {
+                path: path.strip_prefix(root).unwrap_or(path).to_owned(),
+                start: metadata.start,
+                end: metadata.end,
+                record_count: metadata.record_count,
+                sha256: file_digest(path)?,
+            };
+            match &mut manifest {
+                Some(manifest) => {
+                    if metadata.dataset != manifest.dataset || metadata.schema != manifest.schema {
+                        return Err(anyhow!(
+                            "'{}' has dataset {} and schema {}, expected dataset {} and schema {}",
+                            path.display(),
+                            metadata.dataset,
+                            metadata.schema,
+                            manifest.dataset,
+                            manifest.schema
+                        ));
+                    }
+                    manifest.files.push(file);
+                }
+                None => {
+                    manifest = Some(Manifest {
+                        version: Self::VERSION,
+                        dataset: metadata.dataset.clone(),
+                        schema: metadata.schema,
+                        files: vec![file],
+                    })
+                }
+            }
+        }
+        let mut manifest = manifest.ok_or_else(|| anyhow!("No files to build a manifest of"))?;
+        manifest.files.sort_by_key(|file| (file.start, file.end));
+        Ok(manifest)
+    }
+
+    /// Reads a JSON manifest from `reader`.
+    ///
+    /// # Errors
+    /// This function returns an error if the manifest is invalid JSON or is of a newer
+    /// version.
+    pub fn from_json(reader: impl io::Read) -> anyhow::Result<Self> {
+        let manifest: Manifest =
+            serde_json::from_reader(reader).with_context(|| "Invalid manifest")?;
+        if manifest.version > Self::VERSION {
+            return Err(anyhow!(
+                "Can't read manifest version {}, the latest supported version is {}",
+                manifest.version,
+                Self::VERSION
+            ));
+        }
+        Ok(manifest)
+    }
+
+    /// Writes the manifest to `writer` as pretty-printed JSON.
+    ///
+    /// # Errors
+    /// This function returns an error if there's an issue writing to `writer`.
+    pub fn write_json(&self, mut writer: impl io::Write) -> anyhow::Result<()> {
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+fn deserialize_schema<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Schema, D::Error> {
+    let schema = String::deserialize(deserializer)?;
+    schema
+        .parse()
+        .map_err(|_| serde::de::Error::custom(format!("unknown schema '{schema}'")))
+}
+
+/// Returns the hex-encoded SHA-256 digest of the file at `path`.
+fn file_digest(path: &Path) -> anyhow::Result<String> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Error opening dbz file at path '{}'", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// A collection of DBZ files described by a [`Manifest`].
+#[derive(Clone, Debug)]
+pub struct DbzDataset {
+    manifest: Manifest,
+    root: PathBuf,
+}
+
+impl DbzDataset {
+    /// Reads the JSON manifest at `path`. The relative paths of its files are resolved
+    /// against the manifest's directory.
+    ///
+    /// # Errors
+    /// This function returns an error if the manifest can't be read or is invalid.
+    pub fn from_manifest(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("Error opening manifest at path '{}'", path.display()))?;
+        let manifest = Manifest::from_json(BufReader::new(file))
+            .with_context(|| format!("Error reading manifest at path '{}'", path.display()))?;
+        let root = path.parent().unwrap_or_else(|| Path::new("")).to_owned();
+        Ok(Self::new(manifest, root))
+    }
+
+    /// Creates a dataset of the files in `manifest` with relative paths resolved
+    /// against `root`.
+    pub fn new(manifest: Manifest, root: PathBuf) -> Self {
+        Self { manifest, root }
+    }
+
+    /// Returns a reference to the manifest of the dataset.
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Returns the files whose time ranges overlap the range from `start` up to but
+    /// excluding `end`, in order.
+    pub fn plan(&self, start: u64, end: u64) -> Vec<&ManifestFile> {
+        self.manifest
+            .files
+            .iter()
+            .filter(|file| file.start < end && start < file.end)
+            .collect()
+    }
+
+    /// Returns the path of `file` resolved against the manifest's directory.
+    pub fn path(&self, file: &ManifestFile) -> PathBuf {
+        self.root.join(&file.path)
+    }
+
+    /// Opens `file` for reading.
+    ///
+    /// # Errors
+    /// This function returns an error if the file can't be opened or its metadata is
+    /// invalid.
+    pub fn open(&self, file: &ManifestFile) -> anyhow::Result<Dbz<BufReader<File>>> {
+        Dbz::from_file(self.path(file))
+    }
+
+    /// Checks that the contents of `file` match its digest in the manifest.
+    ///
+    /// # Errors
+    /// This function returns an error if the file can't be read or its digest doesn't
+    /// match.
+    pub fn verify(&self, file: &ManifestFile) -> anyhow::Result<()> {
+        let path = self.path(file);
+        let digest = file_digest(&path)?;
+        if digest != file.sha256 {
+            return Err(anyhow!(
+                "'{}' has SHA-256 digest {digest}, expected {}",
+                path.display(),
+                file.sha256
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{fs, io::Seek};
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+    const DAY: u64 = 86_400_000_000_000;
+
+    /// Writes a copy of the trades test data to `path` with a metadata time range from
+    /// `start` to `end`.
+    pub(crate) fn write_trades_with_range(path: &Path, start: u64, end: u64) {
+        let bytes = fs::read(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let mut reader = io::Cursor::new(&bytes);
+        let mut metadata = Metadata::read(&mut reader).unwrap();
+        let records_offset = reader.stream_position().unwrap() as usize;
+        metadata.start = start;
+        metadata.end = end;
+        let mut buffer = io::Cursor::new(Vec::new());
+        metadata.encode(&mut buffer).unwrap();
+        let mut contents = buffer.into_inner();
+        contents.extend_from_slice(&bytes[records_offset..]);
+        fs::write(path, contents).unwrap();
+    }
+
+    fn write_days(dir: &Path) -> Vec<PathBuf> {
+        // out of order to check sorting
+        [2, 0, 1]
+            .into_iter()
+            .map(|day| {
+                let path = dir.join(format!("day{day}.dbz"));
+                write_trades_with_range(&path, day * DAY, (day + 1) * DAY);
+                path
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_build_and_plan() {
+        let dir = tempdir().unwrap();
+        let dir = dir.path();
+        let paths = write_days(dir);
+        let manifest = Manifest::build(dir, &paths).unwrap();
+        assert_eq!(manifest.dataset, "GLBX.MDP3");
+        assert_eq!(manifest.schema, Schema::Trades);
+        let file_paths: Vec<_> = manifest.files.iter().map(|f| f.path.clone()).collect();
+        assert_eq!(
+            file_paths,
+            ["day0.dbz", "day1.dbz", "day2.dbz"].map(PathBuf::from)
+        );
+        assert_eq!(manifest.files[0].sha256.len(), 64);
+        let target = DbzDataset::new(manifest, dir.to_owned());
+        let plan = |start, end| -> Vec<_> {
+            target
+                .plan(start, end)
+                .into_iter()
+                .map(|file| file.start / DAY)
+                .collect()
+        };
+        assert_eq!(plan(0, 3 * DAY), vec![0, 1, 2]);
+        assert_eq!(plan(DAY, 2 * DAY), vec![1]);
+        assert_eq!(plan(DAY / 2, DAY + 1), vec![0, 1]);
+        assert!(plan(3 * DAY, 4 * DAY).is_empty());
+        let file = &target.manifest().files[1];
+        target.verify(file).unwrap();
+        assert_eq!(target.open(file).unwrap().metadata().start, DAY);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let dir = tempdir().unwrap();
+        let dir = dir.path();
+        let paths = write_days(dir);
+        let manifest = Manifest::build(dir, &paths).unwrap();
+        let manifest_path = dir.join("manifest.json");
+        manifest
+            .write_json(File::create(&manifest_path).unwrap())
+            .unwrap();
+        let json = fs::read_to_string(&manifest_path).unwrap();
+        assert!(json.contains("\"schema\": \"trades\""), "{json}");
+        let target = DbzDataset::from_manifest(&manifest_path).unwrap();
+        assert_eq!(target.manifest(), &manifest);
+        assert_eq!(target.path(&manifest.files[0]), dir.join("day0.dbz"));
+    }
+
+    #[test]
+    fn test_verify_modified_file() {
+        let dir = tempdir().unwrap();
+        let dir = dir.path();
+        let paths = write_days(dir);
+        let target = DbzDataset::new(Manifest::build(dir, &paths).unwrap(), dir.to_owned());
+        write_trades_with_range(&dir.join("day0.dbz"), 0, 2 * DAY);
+        let err = target.verify(&target.manifest().files[0]).unwrap_err();
+        assert!(err.to_string().contains("SHA-256"), "{err}");
+    }
+
+    #[test]
+    fn test_build_mixed_schemas() {
+        let paths = ["trades", "mbo"]
+            .map(|schema| PathBuf::from(format!("{DBZ_PATH}/test_data.{schema}.dbz")));
+        let err = Manifest::build(Path::new(DBZ_PATH), &paths).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("expected dataset GLBX.MDP3 and schema trades"));
+    }
+
+    #[test]
+    fn test_from_json_newer_version() {
+        let json = r#"{"version":2,"dataset":"GLBX.MDP3","schema":"trades","files":[]}"#;
+        assert!(Manifest::from_json(json.as_bytes()).is_err());
+        let json = r#"{"version":1,"dataset":"GLBX.MDP3","schema":"trade","files":[]}"#;
+        let err = Manifest::from_json(json.as_bytes()).unwrap_err();
+        assert!(format!("{err:#}").contains("unknown schema 'trade'"));
+    }
+}
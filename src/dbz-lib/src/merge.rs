@@ -0,0 +1,291 @@
+//! Merging of DBZ files of the same dataset and schema into one.
+use std::{collections::BTreeMap, io, path::Path};
+
+use anyhow::{anyhow, Context};
+use databento_defs::enums::Compression;
+
+use crate::{
+    query::{merge_annotations, merge_mapping, MAX_BATCH_SIZE},
+    write::dbz::{write_records, SCHEMA_VERSION},
+    Dbz, DbzRangeIter, Metadata, RecordOrder, TieBreak,
+};
+
+/// Options for [`merge_with_options`].
+#[derive(Clone, Debug, Default)]
+pub struct MergeOptions {
+    /// How records with the same `ts_event` in different files are ordered.
+    pub tie_break: TieBreak,
+}
+
+/// Merges the records of the DBZ files at `inputs` in `ts_event` order and writes
+/// them as DBZ to `output`, e.g. to combine files downloaded in several requests.
+/// Records with the same `ts_event` are kept in the order of `inputs` and, within a
+/// file, in the order of the file. Returns the number of records written.
+///
+/// The files must share a dataset, schema, and symbology types, and the records of
+/// each are expected to be in `ts_event` order. The metadata of the output combines
+/// the metadata of the inputs: the union of their symbols and symbol mappings, the
+/// earliest start and the latest end, and the total number of records.
+///
+/// # Errors
+/// This function returns an error if `inputs` is empty, any of the files can't be
+/// read, or their metadata doesn't match. It will also return an error if there's
+/// an issue writing to `output`.
+pub fn merge(
+    inputs: &[impl AsRef<Path>],
+    output: impl io::Write + io::Seek,
+) -> anyhow::Result<u64> {
+    merge_with_options(inputs, output, &MergeOptions::default())
+}
+
+/// Like [`merge`], but orders records with the same `ts_event` in different files by
+/// `options.tie_break` before the order of `inputs`.
+///
+/// # Errors
+/// This function returns an error for the same reasons as [`merge`].
+pub fn merge_with_options(
+    inputs: &[impl AsRef<Path>],
+    output: impl io::Write + io::Seek,
+    options: &MergeOptions,
+) -> anyhow::Result<u64> {
+    let dbzs = inputs
+        .iter()
+        .map(Dbz::from_file)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let metadata = merge_metadata(
+        inputs
+            .iter()
+            .zip(dbzs.iter())
+            .map(|(input, dbz)| (input.as_ref(), dbz.metadata())),
+    )?;
+    let records = dbzs
+        .into_iter()
+        .map(Dbz::into_record_iter)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let records = DbzRangeIter::new(
+        records,
+        RecordOrder::TsEvent,
+        options.tie_break,
+        MAX_BATCH_SIZE,
+        0,
+        u64::MAX,
+    )?;
+    write_records(output, &metadata, records)
+}
+
+/// Combines the metadata of the files to merge, checking that they can be merged.
+fn merge_metadata<'a>(
+    metadatas: impl Iterator<Item = (&'a Path, &'a Metadata)>,
+) -> anyhow::Result<Metadata> {
+    let mut res: Option<Metadata> = None;
+    let mut mappings = BTreeMap::new();
+    for (path, metadata) in metadatas {
+        for mapping in metadata.mappings.iter() {
+            merge_mapping(&mut mappings, mapping);
+        }
+        let Some(merged) = res.as_mut() else {
+            res = Some(Metadata {
+                version: SCHEMA_VERSION,
+                compression: Compression::ZStd,
+                record_count: 0,
+                mappings: Vec::new(),
+                ..metadata.clone()
+            });
+            continue;
+        };
+        check_matches(merged, metadata)
+            .with_context(|| format!("Can't merge '{}'", path.display()))?;
+        merged.start = merged.start.min(metadata.start);
+        merged.end = merged.end.max(metadata.end);
+        // a limit of 0 means there was no limit
+        merged.limit = if merged.limit == 0 || metadata.limit == 0 {
+            0
+        } else {
+            merged.limit + metadata.limit
+        };
+        extend_unique(&mut merged.symbols, &metadata.symbols);
+        extend_unique(&mut merged.partial, &metadata.partial);
+        extend_unique(&mut merged.not_found, &metadata.not_found);
+        merge_annotations(&mut merged.annotations, &metadata.annotations);
+    }
+    let mut res = res.ok_or_else(|| anyhow!("No files to merge"))?;
+    res.mappings = mappings.into_values().collect();
+    Ok(res)
+}
+
+fn check_matches(merged: &Metadata, metadata: &Metadata) -> anyhow::Result<()> {
+    if metadata.dataset != merged.dataset {
+        Err(anyhow!(
+            "dataset {} doesn't match {}",
+            metadata.dataset,
+            merged.dataset
+        ))
+    } else if metadata.schema != merged.schema {
+        Err(anyhow!(
+            "schema {} doesn't match {}",
+            metadata.schema,
+            merged.schema
+        ))
+    } else if (metadata.stype_in, metadata.stype_out) != (merged.stype_in, merged.stype_out) {
+        Err(anyhow!(
+            "symbology types {} to {} don't match {} to {}",
+            metadata.stype_in,
+            metadata.stype_out,
+            merged.stype_in,
+            merged.stype_out
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Appends the values of `other` that aren't already in `values`.
+fn extend_unique(values: &mut Vec<String>, other: &[String]) {
+    for value in other {
+        if !values.contains(value) {
+            values.push(value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        path::{Path, PathBuf},
+    };
+
+    use databento_defs::{enums::Schema, record::TradeMsg};
+    use streaming_iterator::StreamingIterator;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::DbzWriter;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    /// Writes the trades test data with every `ts_event` shifted by `offset` to a
+    /// temporary file.
+    fn write_shifted_trades(dir: &Path, offset: u64, symbol: &str) -> PathBuf {
+        let dbz = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let mut metadata = dbz.metadata().clone();
+        metadata.symbols = vec![symbol.to_owned()];
+        metadata.annotations = BTreeMap::from([
+            ("source".to_owned(), symbol.to_owned()),
+            (format!("notes-{symbol}"), "shifted".to_owned()),
+        ]);
+        let path = dir.join(format!("{symbol}.dbz"));
+        let mut writer = DbzWriter::new(File::create(&path).unwrap(), &metadata).unwrap();
+        let mut records = dbz.try_into_iter::<TradeMsg>().unwrap();
+        while let Some(record) = records.next() {
+            let mut record = record.clone();
+            record.hd.ts_event += offset;
+            writer.write_record(&record).unwrap();
+        }
+        writer.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_merge() {
+        let dir = tempdir().unwrap();
+        let left = write_shifted_trades(dir.path(), 0, "ESH1");
+        let right = write_shifted_trades(dir.path(), 1, "ESM1");
+        let mut buffer = io::Cursor::new(Vec::new());
+        let record_count = merge(&[&left, &right], &mut buffer).unwrap();
+        assert_eq!(record_count, 4);
+        buffer.set_position(0);
+        let res = Dbz::new(buffer).unwrap();
+        let metadata = res.metadata().clone();
+        assert_eq!(metadata.record_count, 4);
+        assert_eq!(metadata.symbols, vec!["ESH1", "ESM1"]);
+        // the first file's value wins
+        assert_eq!(metadata.annotations["source"], "ESH1");
+        assert!(metadata.annotations.contains_key("notes-ESH1"));
+        assert!(metadata.annotations.contains_key("notes-ESM1"));
+        let left_metadata = Metadata::from_file(&left).unwrap();
+        let right_metadata = Metadata::from_file(&right).unwrap();
+        assert_eq!(metadata.start, left_metadata.start);
+        assert_eq!(metadata.end, right_metadata.end);
+        let ts_events = res
+            .try_into_fallible_iter::<TradeMsg>()
+            .unwrap()
+            .map(|record| record.unwrap().hd.ts_event)
+            .collect::<Vec<_>>();
+        let mut sorted = ts_events.clone();
+        sorted.sort();
+        assert_eq!(ts_events, sorted);
+        assert_eq!(ts_events[1], ts_events[0] + 1);
+    }
+
+    /// Writes the trades test data with every `product_id` set to `product_id` to a
+    /// temporary file.
+    fn write_trades_of_product(dir: &Path, product_id: u32) -> PathBuf {
+        let dbz = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let path = dir.join(format!("product-{product_id}.dbz"));
+        let mut writer = DbzWriter::new(File::create(&path).unwrap(), dbz.metadata()).unwrap();
+        let mut records = dbz.try_into_iter::<TradeMsg>().unwrap();
+        while let Some(record) = records.next() {
+            let mut record = record.clone();
+            record.hd.product_id = product_id;
+            writer.write_record(&record).unwrap();
+        }
+        writer.finish().unwrap();
+        path
+    }
+
+    fn merged(inputs: &[&PathBuf], tie_break: TieBreak) -> Vec<u8> {
+        let mut buffer = io::Cursor::new(Vec::new());
+        merge_with_options(inputs, &mut buffer, &MergeOptions { tie_break }).unwrap();
+        buffer.into_inner()
+    }
+
+    fn product_ids(merged: Vec<u8>) -> Vec<u32> {
+        Dbz::new(io::Cursor::new(merged))
+            .unwrap()
+            .try_into_fallible_iter::<TradeMsg>()
+            .unwrap()
+            .map(|record| record.unwrap().hd.product_id)
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_tie_break() {
+        let dir = tempdir().unwrap();
+        let left = write_trades_of_product(dir.path(), 2);
+        let right = write_trades_of_product(dir.path(), 1);
+        let input_order = merged(&[&left, &right], TieBreak::InputOrder);
+        let reversed_input_order = merged(&[&right, &left], TieBreak::InputOrder);
+        let canonical = merged(&[&left, &right], TieBreak::Canonical);
+        let reversed_canonical = merged(&[&right, &left], TieBreak::Canonical);
+        assert_eq!(product_ids(input_order), vec![2, 1, 2, 1]);
+        assert_eq!(product_ids(reversed_input_order), vec![1, 2, 1, 2]);
+        // the output doesn't depend on the order of the inputs
+        assert_eq!(canonical, reversed_canonical);
+        assert_eq!(product_ids(canonical), vec![1, 2, 1, 2]);
+    }
+
+    #[test]
+    fn test_merge_mismatched_schema() {
+        let res = merge(
+            &[
+                format!("{DBZ_PATH}/test_data.trades.dbz"),
+                format!("{DBZ_PATH}/test_data.mbo.dbz"),
+            ],
+            io::Cursor::new(Vec::new()),
+        );
+        let message = format!("{:#}", res.unwrap_err());
+        assert!(message.contains("test_data.mbo.dbz"));
+        assert!(message.contains(&format!(
+            "schema {} doesn't match {}",
+            Schema::Mbo,
+            Schema::Trades
+        )));
+    }
+
+    #[test]
+    fn test_merge_no_inputs() {
+        let inputs: [&str; 0] = [];
+        assert!(merge(&inputs, io::Cursor::new(Vec::new())).is_err());
+    }
+}
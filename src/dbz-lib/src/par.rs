@@ -0,0 +1,306 @@
+//! Decoding the records of a DBZ file split across several Zstd frames on multiple
+//! threads.
+use std::{
+    io::{self, Read},
+    mem,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Context};
+use rayon::prelude::*;
+use zstd::{zstd_safe, Decoder};
+
+use crate::{read::RecordFilter, record::record_size, Metadata, Record};
+
+/// The minimum number of compressed bytes read at a time while looking for the end
+/// of a frame.
+const READ_CHUNK_LEN: usize = 1 << 20;
+
+/// An iterator over the records of a [`Dbz`](crate::Dbz) that decompresses batches of
+/// its Zstd frames in parallel. Records are returned in the order they were written,
+/// with the error that ended iteration, if any, as the last item. This struct is
+/// created by the [`Dbz::par_iter`](crate::Dbz::par_iter) method.
+pub struct DbzParIter<R: io::BufRead> {
+    reader: R,
+    metadata: Metadata,
+    filter: RecordFilter,
+    dictionary: Option<Arc<[u8]>>,
+    /// The size of the record type of the schema.
+    record_size: usize,
+    /// The number of decompressed bytes still to skip before the first record.
+    skip_bytes: u64,
+    /// Compressed bytes read that haven't been split into frames yet.
+    pending: Vec<u8>,
+    /// Whether `reader` has run out of bytes.
+    is_eof: bool,
+    /// The number of frames split off so far.
+    frame_count: usize,
+    /// Decompressed records of the current batch of frames, starting at `pos`. A
+    /// partial record at the end is completed by the next batch.
+    buffer: Vec<u8>,
+    pos: usize,
+    /// The number of records decoded, including those skipped by `filter`.
+    i: u64,
+    /// An error reading the frames, returned after the records of the frames before
+    /// it.
+    error: Option<anyhow::Error>,
+    is_done: bool,
+}
+
+impl<R: io::BufRead> DbzParIter<R> {
+    pub(crate) fn new(
+        reader: R,
+        metadata: Metadata,
+        filter: RecordFilter,
+        dictionary: Option<Arc<[u8]>>,
+        skip_bytes: u64,
+    ) -> anyhow::Result<Self> {
+        let record_size = record_size(metadata.schema)
+            .ok_or_else(|| anyhow!("Not implemented for schema {}", metadata.schema.as_str()))?;
+        Ok(Self {
+            reader,
+            metadata,
+            filter,
+            dictionary,
+            record_size,
+            skip_bytes,
+            pending: Vec::new(),
+            is_eof: false,
+            frame_count: 0,
+            buffer: Vec::new(),
+            pos: 0,
+            i: 0,
+            error: None,
+            is_done: false,
+        })
+    }
+
+    /// Returns the next complete frame, or `None` if the data ended after the last
+    /// one.
+    fn next_frame(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        loop {
+            if !self.pending.is_empty() {
+                if let Ok(frame_len) = zstd_safe::find_frame_compressed_size(&self.pending) {
+                    let rest = self.pending.split_off(frame_len);
+                    self.frame_count += 1;
+                    return Ok(Some(mem::replace(&mut self.pending, rest)));
+                }
+                if self.is_eof {
+                    return Err(anyhow!(
+                        "DBZ data ended in the middle of Zstd frame {} after {} bytes",
+                        self.frame_count,
+                        self.pending.len()
+                    ));
+                }
+            } else if self.is_eof {
+                return Ok(None);
+            }
+            // read at least as many bytes as are pending so that searching for the end
+            // of a large frame takes linear time
+            let len = self.pending.len().max(READ_CHUNK_LEN) as u64;
+            let read = (&mut self.reader)
+                .take(len)
+                .read_to_end(&mut self.pending)
+                .with_context(|| format!("Failed to read Zstd frame {}", self.frame_count))?;
+            self.is_eof = read == 0;
+        }
+    }
+
+    /// Decompresses the next batch of frames, one per thread, into `buffer`. Returns
+    /// `false` if there are no frames left.
+    fn decode_batch(&mut self) -> anyhow::Result<bool> {
+        let first_frame = self.frame_count;
+        let mut frames = Vec::new();
+        while frames.len() < rayon::current_num_threads() && self.error.is_none() {
+            match self.next_frame() {
+                Ok(Some(frame)) => frames.push(frame),
+                Ok(None) => break,
+                Err(e) => self.error = Some(e),
+            }
+        }
+        if frames.is_empty() {
+            return self.error.take().map_or(Ok(false), Err);
+        }
+        let dictionary = self.dictionary.as_deref();
+        let decompressed = frames
+            .par_iter()
+            .map(|frame| decompress_frame(frame, dictionary))
+            .collect::<Vec<_>>();
+        // keep any partial record from the previous batch
+        self.buffer.drain(..self.pos);
+        self.pos = 0;
+        for (i, frame) in decompressed.into_iter().enumerate() {
+            let frame =
+                frame.with_context(|| format!("Failed to decompress frame {}", first_frame + i))?;
+            self.buffer.extend_from_slice(&frame);
+        }
+        let skipped = self.buffer.len().min(self.skip_bytes as usize);
+        self.pos = skipped;
+        self.skip_bytes -= skipped as u64;
+        Ok(true)
+    }
+
+    /// Returns the next record matching the filter, or `None` if the data ended.
+    fn next_record(&mut self) -> anyhow::Result<Option<Record>> {
+        loop {
+            // stop before decompressing any more once the limit is reached
+            if self.filter.is_exhausted() {
+                return Ok(None);
+            }
+            let Some(bytes) = self.buffer.get(self.pos..self.pos + self.record_size) else {
+                if self.decode_batch()? {
+                    continue;
+                }
+                let partial_len = self.buffer.len() - self.pos;
+                if partial_len > 0 {
+                    return Err(anyhow!(
+                        "DBZ data ended in the middle of a record: {partial_len} of {} bytes",
+                        self.record_size
+                    ));
+                }
+                return Ok(None);
+            };
+            self.pos += self.record_size;
+            self.i += 1;
+            if self.filter.is_active() && !self.filter.matches(bytes) {
+                continue;
+            }
+            return Record::from_bytes(self.metadata.schema, bytes)
+                .map(Some)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Record {} has rtype {:#04x}, which doesn't match schema {}",
+                        self.i - 1,
+                        // the rtype follows the length in the header
+                        bytes[1],
+                        self.metadata.schema.as_str()
+                    )
+                });
+        }
+    }
+}
+
+impl<R: io::BufRead> Iterator for DbzParIter<R> {
+    type Item = anyhow::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_done {
+            return None;
+        }
+        let res = self.next_record().transpose();
+        self.is_done = !matches!(res, Some(Ok(_)));
+        res
+    }
+}
+
+/// Decompresses a single Zstd frame.
+fn decompress_frame(frame: &[u8], dictionary: Option<&[u8]>) -> anyhow::Result<Vec<u8>> {
+    let content_size = zstd_safe::get_frame_content_size(frame);
+    // the largest values indicate an unknown size or an error
+    let mut res = Vec::with_capacity(if content_size < u64::MAX - 1 {
+        content_size as usize
+    } else {
+        frame.len() * 4
+    });
+    let mut decoder = match dictionary {
+        Some(dictionary) => Decoder::with_dictionary(frame, dictionary)?,
+        None => Decoder::with_buffer(frame)?,
+    };
+    decoder.read_to_end(&mut res)?;
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Dbz;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    /// Re-encodes the records of the test data of `schema` with every `frame_len`
+    /// bytes of records in a separate frame. Returns the encoded file and its records.
+    fn encode_in_frames(schema: &str, frame_len: usize) -> (Vec<u8>, Vec<Record>) {
+        let path = format!("{DBZ_PATH}/test_data.{schema}.dbz");
+        let bytes = std::fs::read(&path).unwrap();
+        let mut prelude = [0; Metadata::PRELUDE_LEN];
+        prelude.copy_from_slice(&bytes[..Metadata::PRELUDE_LEN]);
+        let metadata_len = Metadata::PRELUDE_LEN + Metadata::frame_size(&prelude).unwrap();
+        let records = zstd::decode_all(&bytes[metadata_len..]).unwrap();
+        let mut res = bytes[..metadata_len].to_vec();
+        for chunk in records.chunks(frame_len) {
+            res.extend(zstd::encode_all(chunk, 0).unwrap());
+        }
+        let expected = Dbz::from_file(&path)
+            .unwrap()
+            .into_record_iter()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        (res, expected)
+    }
+
+    /// Decodes `bytes` with batches of several frames, regardless of the number of
+    /// cores.
+    fn par_records(bytes: &[u8]) -> anyhow::Result<Vec<Record>> {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap()
+            .install(|| Dbz::new(bytes).unwrap().par_iter().unwrap().collect())
+    }
+
+    #[test]
+    fn test_matches_sequential() {
+        for schema in ["mbo", "mbp-1", "mbp-10", "ohlcv-1s", "tbbo", "trades"] {
+            // frames that split records as well as frames of whole records
+            for frame_len in [7, 56, 1 << 20] {
+                let (bytes, expected) = encode_in_frames(schema, frame_len);
+                assert_eq!(
+                    par_records(&bytes).unwrap(),
+                    expected,
+                    "{schema} in frames of {frame_len}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        let (bytes, expected) = encode_in_frames("mbo", 56);
+        let Record::Mbo(last) = expected.last().unwrap() else {
+            panic!("Expected MBO record");
+        };
+        let res = Dbz::new(bytes.as_slice())
+            .unwrap()
+            .filter_range(last.hd.ts_event, u64::MAX)
+            .par_iter()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert!(!res.is_empty());
+        assert!(res
+            .iter()
+            .all(|record| record.header().ts_event >= last.hd.ts_event));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let (bytes, expected) = encode_in_frames("mbo", 56);
+        let res = par_records(&bytes[..bytes.len() - 3]);
+        assert!(format!("{:#}", res.unwrap_err()).contains("middle of Zstd frame"));
+        // the records of the complete frames are still returned first
+        let records = rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap()
+            .install(|| {
+                Dbz::new(&bytes[..bytes.len() - 3])
+                    .unwrap()
+                    .par_iter()
+                    .unwrap()
+                    .take_while(Result::is_ok)
+                    .count()
+            });
+        assert_eq!(records, expected.len() - 1);
+    }
+}
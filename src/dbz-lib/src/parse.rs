@@ -0,0 +1,605 @@
+//! Reading records back from the CSV and JSON written by the text encoders, so text
+//! can be converted to DBZ.
+use std::{collections::VecDeque, convert::Infallible, io, mem};
+
+use anyhow::{anyhow, Context};
+use databento_defs::{
+    enums::Schema,
+    record::{Mbp10Msg, Mbp1Msg, OhlcvMsg, StatusMsg, SymDefMsg, TickMsg, TradeMsg},
+};
+use serde_json::Value;
+
+use crate::{
+    fields::{FieldValue, FieldVisitor, Scope, VisitFields},
+    spec::StructSpec,
+    DbzWriter, FormatSpec, Metadata, Record,
+};
+
+/// An iterator over the records of `schema` in CSV or JSON as written by
+/// [`Dbz::write_to`](crate::Dbz::write_to) with the default options: prices as
+/// fixed-precision integers and timestamps as UNIX nanoseconds. Single-character
+/// fields like `action` may be either integers or characters. Fields that aren't part
+/// of the record type, like a `symbol` added by symbology mapping, are ignored.
+pub struct TextRecordReader<R: io::Read> {
+    schema: Schema,
+    fields: Vec<TextField>,
+    inner: TextInner<R>,
+    /// Reusable buffer sized to the record type of the schema.
+    buffer: Vec<u8>,
+    /// The number of records read.
+    i: u64,
+    is_done: bool,
+}
+
+enum TextInner<R: io::Read> {
+    Csv {
+        reader: csv::Reader<R>,
+        /// The column of each field.
+        columns: Vec<usize>,
+        row: csv::StringRecord,
+    },
+    Json {
+        values: serde_json::StreamDeserializer<'static, serde_json::de::IoRead<R>, Value>,
+        /// The remaining records of a JSON array.
+        pending: VecDeque<Value>,
+    },
+}
+
+/// Encodes the CSV records of `metadata.schema` read from `reader`, in the format
+/// read by [`TextRecordReader::csv`], as DBZ to `writer` with `metadata`. The `start`,
+/// `end`, and `record_count` of `metadata` are replaced with those of the records.
+/// Returns the number of records written.
+///
+/// # Errors
+/// This function returns an error under the same conditions as
+/// [`TextRecordReader::csv`], if a record can't be parsed, or if there's an issue
+/// writing to `writer`.
+pub fn encode_from_csv(
+    reader: impl io::Read,
+    metadata: &Metadata,
+    writer: impl io::Write + io::Seek,
+) -> anyhow::Result<u64> {
+    TextRecordReader::csv(reader, metadata.schema)?.encode(writer, metadata)
+}
+
+/// Encodes the JSON records of `metadata.schema` read from `reader`, in the format
+/// read by [`TextRecordReader::json`], as DBZ to `writer` with `metadata`. The
+/// `start`, `end`, and `record_count` of `metadata` are replaced with those of the
+/// records. Returns the number of records written.
+///
+/// # Errors
+/// This function returns an error under the same conditions as
+/// [`TextRecordReader::json`], if a record can't be parsed, or if there's an issue
+/// writing to `writer`.
+pub fn encode_from_json(
+    reader: impl io::Read,
+    metadata: &Metadata,
+    writer: impl io::Write + io::Seek,
+) -> anyhow::Result<u64> {
+    TextRecordReader::json(reader, metadata.schema)?.encode(writer, metadata)
+}
+
+/// A field of a record type and where it's encoded.
+#[derive(Debug)]
+struct TextField {
+    scope: Scope,
+    name: &'static str,
+    kind: FieldKind,
+    offset: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FieldKind {
+    Int { size: usize, is_signed: bool },
+    Char,
+    CStr(usize),
+}
+
+impl<R: io::Read> TextRecordReader<R> {
+    /// Creates a reader of the records of `schema` in CSV with a header row.
+    ///
+    /// # Errors
+    /// This function returns an error if the header row can't be read, is missing
+    /// a field of the record type of `schema`, or `schema` is
+    /// [`Schema::Statistics`].
+    pub fn csv(reader: R, schema: Schema) -> anyhow::Result<Self> {
+        let fields = text_fields(schema)?;
+        let mut reader = csv::Reader::from_reader(reader);
+        let headers = reader
+            .headers()
+            .with_context(|| "Failed to read CSV header row".to_owned())?;
+        let columns = fields
+            .iter()
+            .map(|field| {
+                let name = field.flat_name();
+                headers
+                    .iter()
+                    .position(|header| header == name)
+                    .ok_or_else(|| anyhow!("CSV is missing the {name} column"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let inner = TextInner::Csv {
+            reader,
+            columns,
+            row: csv::StringRecord::new(),
+        };
+        Ok(Self::new(schema, fields, inner))
+    }
+
+    /// Creates a reader of the records of `schema` in JSON, either one object per
+    /// line, pretty-printed, or in an array.
+    ///
+    /// # Errors
+    /// This function returns an error if `schema` is [`Schema::Statistics`].
+    pub fn json(reader: R, schema: Schema) -> anyhow::Result<Self> {
+        let inner = TextInner::Json {
+            values: serde_json::Deserializer::from_reader(reader).into_iter(),
+            pending: VecDeque::new(),
+        };
+        Ok(Self::new(schema, text_fields(schema)?, inner))
+    }
+
+    fn new(schema: Schema, fields: Vec<TextField>, inner: TextInner<R>) -> Self {
+        Self {
+            schema,
+            fields,
+            inner,
+            // Statistics, the only schema without a record type, is rejected before
+            buffer: vec![0; crate::record::record_size(schema).unwrap_or_default()],
+            i: 0,
+            is_done: false,
+        }
+    }
+
+    /// Encodes the records as DBZ to `writer` with metadata of `dataset`, the schema,
+    /// and the range of the records' `ts_event`s. Returns the number of records
+    /// written.
+    ///
+    /// # Errors
+    /// This function returns an error if a record can't be parsed or there's an
+    /// issue writing to `writer`.
+    pub fn write_dbz_to(
+        self,
+        writer: impl io::Write + io::Seek,
+        dataset: &str,
+    ) -> anyhow::Result<u64> {
+        let mut metadata = Metadata::placeholder(self.schema);
+        metadata.dataset = dataset.to_owned();
+        self.encode(writer, &metadata)
+    }
+
+    /// Encodes the records as DBZ to `writer` with `metadata`, whose `start`, `end`,
+    /// and `record_count` are replaced with those of the records.
+    fn encode(self, writer: impl io::Write + io::Seek, metadata: &Metadata) -> anyhow::Result<u64> {
+        let mut writer = DbzWriter::new(writer, metadata)?;
+        for record in self {
+            writer.write_record_bytes(record?.as_bytes())?;
+        }
+        let record_count = writer.record_count();
+        writer.finish()?;
+        Ok(record_count)
+    }
+
+    /// Reads the next record into `buffer`, returning `false` if there are none left.
+    fn read_next(&mut self) -> anyhow::Result<bool> {
+        self.buffer.fill(0);
+        match &mut self.inner {
+            TextInner::Csv {
+                reader,
+                columns,
+                row,
+            } => {
+                if !reader.read_record(row)? {
+                    return Ok(false);
+                }
+                for (field, &column) in self.fields.iter().zip(columns.iter()) {
+                    let value = row
+                        .get(column)
+                        .ok_or_else(|| anyhow!("Missing {} column", field.flat_name()))?;
+                    field.encode(value, &mut self.buffer)?;
+                }
+            }
+            TextInner::Json { values, pending } => {
+                let value = match pending.pop_front() {
+                    Some(value) => value,
+                    None => match values.next().transpose()? {
+                        Some(Value::Array(array)) => {
+                            pending.extend(array);
+                            return self.read_next();
+                        }
+                        Some(value) => value,
+                        None => return Ok(false),
+                    },
+                };
+                for field in self.fields.iter() {
+                    let value = field
+                        .json_value(&value)
+                        .ok_or_else(|| anyhow!("Missing {} field", field.json_path()))?;
+                    match value {
+                        Value::String(s) => field.encode(s, &mut self.buffer)?,
+                        Value::Number(n) => field.encode(&n.to_string(), &mut self.buffer)?,
+                        _ => {
+                            return Err(anyhow!("Invalid value {value} for {}", field.json_path()))
+                        }
+                    }
+                }
+            }
+        }
+        // the length in the header is in 32-bit words
+        self.buffer[0] = (self.buffer.len() / 4) as u8;
+        Ok(true)
+    }
+}
+
+impl<R: io::Read> Iterator for TextRecordReader<R> {
+    type Item = anyhow::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_done {
+            return None;
+        }
+        let res = match self.read_next() {
+            Ok(true) => Record::from_bytes(self.schema, &self.buffer).ok_or_else(|| {
+                anyhow!(
+                    "rtype {} doesn't match schema {}",
+                    // the rtype follows the length in the header
+                    self.buffer[1],
+                    self.schema.as_str()
+                )
+            }),
+            Ok(false) => {
+                self.is_done = true;
+                return None;
+            }
+            Err(e) => Err(e),
+        };
+        let i = self.i;
+        self.i += 1;
+        self.is_done = res.is_err();
+        Some(res.with_context(|| format!("Failed to parse record {i}")))
+    }
+}
+
+impl TextField {
+    /// Returns the name of the field as a CSV column.
+    fn flat_name(&self) -> String {
+        match self.scope {
+            Scope::Level(i) => format!("{}_{i:02}", self.name),
+            Scope::Body | Scope::Header => self.name.to_owned(),
+        }
+    }
+
+    /// Returns the path of the field in a JSON record, e.g. `hd.ts_event`.
+    fn json_path(&self) -> String {
+        match self.scope {
+            Scope::Body => self.name.to_owned(),
+            Scope::Header => format!("hd.{}", self.name),
+            Scope::Level(i) => format!("booklevel[{i}].{}", self.name),
+        }
+    }
+
+    fn json_value<'a>(&self, record: &'a Value) -> Option<&'a Value> {
+        match self.scope {
+            Scope::Body => record.get(self.name),
+            Scope::Header => record.get("hd")?.get(self.name),
+            Scope::Level(i) => record.get("booklevel")?.get(i)?.get(self.name),
+        }
+    }
+
+    /// Parses `value` and encodes it in `buffer`.
+    fn encode(&self, value: &str, buffer: &mut [u8]) -> anyhow::Result<()> {
+        let invalid = || anyhow!("Invalid value '{value}' for {}", self.flat_name());
+        match self.kind {
+            FieldKind::Int { size, is_signed } => {
+                let bytes = if is_signed {
+                    let n = value.parse::<i64>().map_err(|_| invalid())?;
+                    let min = i64::MIN >> (64 - size * 8);
+                    if n < min || n > !min {
+                        return Err(invalid());
+                    }
+                    n.to_le_bytes()
+                } else {
+                    let n = value.parse::<u64>().map_err(|_| invalid())?;
+                    if size < 8 && n >> (size * 8) != 0 {
+                        return Err(invalid());
+                    }
+                    n.to_le_bytes()
+                };
+                buffer[self.offset..self.offset + size].copy_from_slice(&bytes[..size]);
+            }
+            FieldKind::Char => {
+                let c = match value.parse::<i8>() {
+                    Ok(c) => c as u8,
+                    Err(_) if value.len() == 1 => value.as_bytes()[0],
+                    Err(_) => return Err(invalid()),
+                };
+                buffer[self.offset] = c;
+            }
+            FieldKind::CStr(len) => {
+                if value.len() > len {
+                    return Err(anyhow!(
+                        "'{value}' is longer than the {len} characters of {}",
+                        self.name
+                    ));
+                }
+                buffer[self.offset..self.offset + value.len()].copy_from_slice(value.as_bytes());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the fields of the record type of `schema` in the order they're visited.
+fn text_fields(schema: Schema) -> anyhow::Result<Vec<TextField>> {
+    match schema {
+        Schema::Mbo => text_fields_of::<TickMsg>(),
+        Schema::Mbp1 | Schema::Tbbo => text_fields_of::<Mbp1Msg>(),
+        Schema::Mbp10 => text_fields_of::<Mbp10Msg>(),
+        Schema::Trades => text_fields_of::<TradeMsg>(),
+        Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D => {
+            text_fields_of::<OhlcvMsg>()
+        }
+        Schema::Definition => text_fields_of::<SymDefMsg>(),
+        Schema::Statistics => Err(anyhow!("Not implemented for schema Statistics")),
+        Schema::Status => text_fields_of::<StatusMsg>(),
+    }
+}
+
+fn text_fields_of<T: VisitFields>() -> anyhow::Result<Vec<TextField>> {
+    // Safety: records are POD, so all zeroes is a valid value
+    let record: T = unsafe { mem::zeroed() };
+    let mut collector = FieldCollector(Vec::new());
+    // `FieldCollector` never fails
+    let _ = record.visit_fields(&mut collector);
+    let spec = FormatSpec::new();
+    let find_struct = |name: &str| {
+        spec.structs
+            .iter()
+            .find(|spec| spec.name == name)
+            .ok_or_else(|| anyhow!("No layout of {name}"))
+    };
+    let type_name = std::any::type_name::<T>();
+    let record_spec = find_struct(type_name.rsplit("::").next().unwrap_or(type_name))?;
+    let header_spec = find_struct("RecordHeader")?;
+    let level_spec = find_struct("BidAskPair")?;
+    collector
+        .0
+        .into_iter()
+        .map(|(scope, name, kind)| {
+            let offset = match scope {
+                Scope::Body => field_offset(record_spec, name)?,
+                Scope::Header => {
+                    field_offset(record_spec, "hd")? + field_offset(header_spec, name)?
+                }
+                Scope::Level(i) => {
+                    field_offset(record_spec, "booklevel")?
+                        + i * level_spec.size
+                        + field_offset(level_spec, name)?
+                }
+            };
+            Ok(TextField {
+                scope,
+                name,
+                kind,
+                offset,
+            })
+        })
+        .collect()
+}
+
+fn field_offset(spec: &StructSpec, name: &str) -> anyhow::Result<usize> {
+    spec.fields
+        .iter()
+        .find(|field| field.name == name)
+        .map(|field| field.offset)
+        .ok_or_else(|| anyhow!("{} has no field {name}", spec.name))
+}
+
+/// Collects the name and kind of every visited field.
+struct FieldCollector(Vec<(Scope, &'static str, FieldKind)>);
+
+impl FieldVisitor for FieldCollector {
+    type Error = Infallible;
+
+    fn visit(
+        &mut self,
+        scope: Scope,
+        name: &'static str,
+        value: FieldValue,
+    ) -> Result<(), Infallible> {
+        let int = |size, is_signed| FieldKind::Int { size, is_signed };
+        let kind = match value {
+            FieldValue::I8(_) => int(1, true),
+            FieldValue::U8(_) => int(1, false),
+            FieldValue::I16(_) => int(2, true),
+            FieldValue::U16(_) => int(2, false),
+            FieldValue::I32(_) => int(4, true),
+            FieldValue::U32(_) => int(4, false),
+            FieldValue::I64(_) | FieldValue::Price(_) => int(8, true),
+            FieldValue::U64(_) | FieldValue::Timestamp(_) => int(8, false),
+            FieldValue::Char(_) => FieldKind::Char,
+            FieldValue::CStr(chars) => FieldKind::CStr(mem::size_of_val(chars)),
+            // records only have fixed-size fields
+            FieldValue::Str(_) | FieldValue::Null => return Ok(()),
+        };
+        self.0.push((scope, name, kind));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{Dbz, OutputEncoding};
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    fn text_of(schema: &str, encoding: OutputEncoding) -> (Dbz<impl io::BufRead>, Vec<u8>) {
+        let path = format!("{DBZ_PATH}/test_data.{schema}.dbz");
+        let mut text = Vec::new();
+        Dbz::from_file(&path)
+            .unwrap()
+            .write_to(&mut text, encoding)
+            .unwrap();
+        (Dbz::from_file(&path).unwrap(), text)
+    }
+
+    fn json() -> OutputEncoding {
+        OutputEncoding::Json {
+            should_pretty_print: false,
+            should_output_array: false,
+            should_output_iso_timestamps: false,
+            should_output_decimal_prices: false,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for schema in ["mbo", "mbp-1", "mbp-10", "ohlcv-1s", "tbbo", "trades"] {
+            for encoding in [OutputEncoding::Csv, json()] {
+                let (dbz, text) = text_of(schema, encoding);
+                let expected = dbz
+                    .into_record_iter()
+                    .unwrap()
+                    .collect::<anyhow::Result<Vec<_>>>()
+                    .unwrap();
+                let reader = if matches!(encoding, OutputEncoding::Csv) {
+                    TextRecordReader::csv(text.as_slice(), dbz_schema(schema))
+                } else {
+                    TextRecordReader::json(text.as_slice(), dbz_schema(schema))
+                };
+                let records = reader.unwrap().collect::<anyhow::Result<Vec<_>>>().unwrap();
+                assert_eq!(records, expected, "{schema} {encoding:?}");
+            }
+        }
+    }
+
+    fn dbz_schema(schema: &str) -> Schema {
+        schema.parse().unwrap()
+    }
+
+    #[test]
+    fn test_json_array_and_chars() {
+        let (dbz, _) = text_of("mbo", json());
+        let expected = dbz
+            .into_record_iter()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        let (_, text) = text_of(
+            "mbo",
+            OutputEncoding::Json {
+                should_pretty_print: true,
+                should_output_array: true,
+                should_output_iso_timestamps: false,
+                should_output_decimal_prices: false,
+            },
+        );
+        let text = String::from_utf8(text)
+            .unwrap()
+            .replace("\"action\": 67", "\"action\": \"C\"");
+        let records = TextRecordReader::json(text.as_bytes(), Schema::Mbo)
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(records, expected);
+    }
+
+    #[test]
+    fn test_write_dbz_to() {
+        let (dbz, text) = text_of("trades", OutputEncoding::Csv);
+        let mut buffer = Cursor::new(Vec::new());
+        let record_count = TextRecordReader::csv(text.as_slice(), Schema::Trades)
+            .unwrap()
+            .write_dbz_to(&mut buffer, "GLBX.MDP3")
+            .unwrap();
+        assert_eq!(record_count, 2);
+        buffer.set_position(0);
+        let target = Dbz::new(buffer).unwrap();
+        assert_eq!(target.metadata().dataset, "GLBX.MDP3");
+        assert_eq!(target.metadata().record_count, 2);
+        assert!(target.metadata().start < target.metadata().end);
+        let expected = dbz
+            .into_record_iter()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        let records = target
+            .into_record_iter()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(records, expected);
+    }
+
+    #[test]
+    fn test_encode_from_text() {
+        for encoding in [OutputEncoding::Csv, json()] {
+            let (dbz, text) = text_of("mbp-1", encoding);
+            let metadata = dbz.metadata().clone();
+            let mut buffer = Cursor::new(Vec::new());
+            let record_count = if matches!(encoding, OutputEncoding::Csv) {
+                encode_from_csv(text.as_slice(), &metadata, &mut buffer)
+            } else {
+                encode_from_json(text.as_slice(), &metadata, &mut buffer)
+            }
+            .unwrap();
+            assert_eq!(record_count, 2);
+            buffer.set_position(0);
+            let target = Dbz::new(buffer).unwrap();
+            assert_eq!(target.metadata().symbols, metadata.symbols);
+            assert_eq!(target.metadata().mappings, metadata.mappings);
+            assert_eq!(target.metadata().record_count, 2);
+            let expected = dbz
+                .into_record_iter()
+                .unwrap()
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap();
+            let records = target
+                .into_record_iter()
+                .unwrap()
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(records, expected);
+        }
+    }
+
+    #[test]
+    fn test_json_missing_field() {
+        let mut records =
+            TextRecordReader::json(r#"{"hd": {"rtype": 0}}"#.as_bytes(), Schema::Trades).unwrap();
+        let error = records.next().unwrap().unwrap_err();
+        assert!(
+            format!("{error:#}").contains("Missing hd.publisher_id field"),
+            "{error:#}"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = TextRecordReader::csv("rtype,price\n".as_bytes(), Schema::Trades)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("publisher_id"), "{error}");
+        let (_, text) = text_of("trades", OutputEncoding::Csv);
+        let text = String::from_utf8(text)
+            .unwrap()
+            .replacen(",1,", ",70000,", 1);
+        let mut records = TextRecordReader::csv(text.as_bytes(), Schema::Trades).unwrap();
+        let error = records.next().unwrap().unwrap_err();
+        assert!(format!("{error:#}").contains("record 0"), "{error:#}");
+        assert!(records.next().is_none());
+        // the rtype of the records must match the schema
+        let (_, text) = text_of("trades", OutputEncoding::Csv);
+        let error = TextRecordReader::csv(text.as_slice(), Schema::Mbo)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("order_id"), "{error}");
+        let text = String::from_utf8(text).unwrap().replace("\n0,", "\n9,");
+        let mut records = TextRecordReader::csv(text.as_bytes(), Schema::Trades).unwrap();
+        let error = records.next().unwrap().unwrap_err();
+        assert!(format!("{error:#}").contains("doesn't match"), "{error:#}");
+    }
+}
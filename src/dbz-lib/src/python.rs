@@ -1,24 +1,30 @@
 //! Python wrappers around dbz_lib functions. These are implemented here instead of `dbz-python`
 //! to be able to implement `pyo3` traits for `dbz_lib` types.
 #![allow(clippy::borrow_deref_ref)]
+use std::collections::BTreeMap;
 use std::ffi::c_char;
 use std::mem;
 // in generated code from `pyfunction` macro and `&PyBytes`
 use std::{fmt, io, io::SeekFrom};
 
 use databento_defs::record::{
-    BidAskPair, Mbp10Msg, Mbp1Msg, OhlcvMsg, RecordHeader, TbboMsg, TickMsg, TradeMsg,
+    BidAskPair, Mbp10Msg, Mbp1Msg, OhlcvMsg, RecordHeader, SecurityUpdateAction, StatusMsg,
+    SymDefMsg, TbboMsg, TickMsg, TradeMsg,
 };
 use pyo3::exceptions::{PyKeyError, PyTypeError, PyValueError};
 use pyo3::prelude::*;
 use pyo3::types::{PyBytes, PyDate, PyDateAccess, PyDict};
+use streaming_iterator::StreamingIterator;
 use time::Date;
 
 use databento_defs::enums::{Compression, SType, Schema};
 use databento_defs::record::ConstTypeId;
 
 use crate::write::dbz::SCHEMA_VERSION;
-use crate::{write_dbz, MappingInterval, Metadata, SymbolMapping};
+use crate::{
+    mappings_from_symbology_json, write_dbz_stream, Dbz, DbzDataset, MappingInterval, Metadata,
+    RangeOptions, RecordOrder, SymbolMapping,
+};
 
 /// Decodes the given Python `bytes` to `Metadata`. Returns a Python `dict` with
 /// all the DBZ metadata.
@@ -31,6 +37,39 @@ pub fn decode_metadata(bytes: &PyBytes) -> PyResult<Metadata> {
     Metadata::read(&mut reader).map_err(to_val_err)
 }
 
+/// Converts `symbology`, a `dict` parsed from a `symbology.json` response of the
+/// Databento symbology API, to DBZ symbol mappings. Returns a Python `list` of
+/// mappings in the same format as the `mappings` of [`decode_metadata`].
+///
+/// # Errors
+/// This function returns an error if `symbology` can't be converted to JSON or isn't
+/// in the symbology format.
+#[pyfunction]
+pub fn mappings_from_symbology(py: Python<'_>, symbology: &PyDict) -> PyResult<PyObject> {
+    let json: String = py
+        .import("json")?
+        .call_method1("dumps", (symbology,))?
+        .extract()?;
+    let mappings = mappings_from_symbology_json(&json).map_err(to_val_err)?;
+    Ok(mappings.to_object(py))
+}
+
+/// Converts the symbol mappings of `metadata`, encoded DBZ metadata, to the
+/// `symbology.json` format of the Databento symbology API. Returns a Python `dict`.
+///
+/// # Errors
+/// This function returns an error if the metadata cannot be parsed from `metadata`.
+#[pyfunction]
+pub fn mappings_to_symbology(py: Python<'_>, metadata: &PyBytes) -> PyResult<PyObject> {
+    let mut reader = io::BufReader::new(metadata.as_bytes());
+    let metadata = Metadata::read(&mut reader).map_err(to_val_err)?;
+    let json = metadata.to_symbology_json();
+    Ok(py
+        .import("json")?
+        .call_method1("loads", (json,))?
+        .to_object(py))
+}
+
 /// Encodes the given metadata into the DBZ metadata binary format.
 /// Returns Python `bytes`.
 ///
@@ -55,6 +94,7 @@ pub fn encode_metadata(
     partial: Vec<String>,
     not_found: Vec<String>,
     mappings: Vec<SymbolMapping>,
+    annotations: Option<BTreeMap<String, String>>,
 ) -> PyResult<Py<PyBytes>> {
     let metadata = Metadata {
         version: SCHEMA_VERSION,
@@ -67,10 +107,12 @@ pub fn encode_metadata(
         compression: Compression::try_from(compression).map_err(to_val_err)?,
         stype_in: SType::try_from(stype_in).map_err(to_val_err)?,
         stype_out: SType::try_from(stype_out).map_err(to_val_err)?,
+        schema_definition: None,
         symbols,
         partial,
         not_found,
         mappings,
+        annotations: annotations.unwrap_or_default(),
     };
     let mut encoded = Vec::with_capacity(1024);
     let cursor = io::Cursor::new(&mut encoded);
@@ -79,6 +121,10 @@ pub fn encode_metadata(
 }
 
 /// Updates existing fields that have already been written to the given file.
+///
+/// # Errors
+/// This function returns an error if there's an issue seeking in or writing to
+/// `file`.
 #[pyfunction]
 pub fn update_encoded_metadata(
     _py: Python<'_>,
@@ -91,6 +137,8 @@ pub fn update_encoded_metadata(
     Metadata::update_encoded(file, start, end, limit.unwrap_or(0), record_count).map_err(to_val_err)
 }
 
+/// A Python file-like object, like an open file or `io.BytesIO`, that can be used
+/// as a Rust reader or writer.
 pub struct PyFileLike {
     inner: PyObject,
 }
@@ -101,22 +149,62 @@ pub struct PyFileLike {
 /// `records` is a list of **flat** dicts where the field names match the
 /// record type corresponding with `schema`. For `Mbp1` and `Mbp10` schemas, the
 /// `booklevel` fields should be suffixed with `_0{level}`, e.g. the first book
-/// level ask price should be under the key `"ask_px_00"`.
+/// level ask price should be under the key `"ask_px_00"`. String fields like
+/// `symbol` are `str`s and `security_update_action` is its name, e.g. `"Add"`, like
+/// in the text output formats. The `Statistics` schema isn't supported.
+///
+/// `schema` can be omitted, in which case it's taken from `record_class`, a record
+/// class like `TradeMsg` or the name of one, or if that's also omitted, inferred from
+/// the fields present in the first record. `dataset`, `records`, and `stype` are
+/// required.
+///
+/// Every dict is checked before anything is written. Records are then converted and
+/// encoded one at a time, so memory use doesn't grow with the number of records.
 ///
 /// # Errors
 /// This function returns an error if any of the enum arguments cannot be converted to
 /// their Rust equivalents. It will also return an error if there's an issue writing
-/// the encoded to bytes or an expected field is missing from one of the dicts.
-#[pyfunction]
+/// the encoded to bytes or an expected field is missing from one of the dicts, in
+/// which case nothing is written. If
+/// `schema` is `None`, it will also return an error if the schema can't be inferred
+/// or both `schema` and `record_class` are passed.
+// all arguments after one with a default need one, so the required ones are checked
+// below
+#[pyfunction(
+    file,
+    schema = "None",
+    dataset = "None",
+    records = "None",
+    stype = "None",
+    record_class = "None"
+)]
 pub fn write_dbz_file(
     _py: Python<'_>,
-    mut file: PyFileLike,
-    schema: &str,
-    dataset: String,
-    records: Vec<&PyDict>,
-    stype: &str,
+    file: PyFileLike,
+    schema: Option<&str>,
+    dataset: Option<String>,
+    records: Option<Vec<&PyDict>>,
+    stype: Option<&str>,
+    record_class: Option<&PyAny>,
 ) -> PyResult<()> {
-    let schema = schema.parse::<Schema>().map_err(to_val_err)?;
+    let missing = |name| {
+        PyTypeError::new_err(format!(
+            "write_dbz_file() missing required argument: '{name}'"
+        ))
+    };
+    let dataset = dataset.ok_or_else(|| missing("dataset"))?;
+    let records = records.ok_or_else(|| missing("records"))?;
+    let stype = stype.ok_or_else(|| missing("stype"))?;
+    let schema = match (schema, record_class) {
+        (Some(_), Some(_)) => {
+            return Err(PyValueError::new_err(
+                "Pass only one of `schema` and `record_class`",
+            ))
+        }
+        (Some(schema), None) => schema.parse::<Schema>().map_err(to_val_err)?,
+        (None, Some(record_class)) => schema_of_record_class(record_class)?,
+        (None, None) => infer_schema(records.first().copied())?,
+    };
     let stype = stype.parse::<SType>().map_err(to_val_err)?;
     let metadata = Metadata {
         version: SCHEMA_VERSION,
@@ -129,42 +217,291 @@ pub fn write_dbz_file(
         compression: Compression::None,
         stype_in: stype,
         stype_out: stype,
+        schema_definition: None,
         symbols: vec![],
         partial: vec![],
         not_found: vec![],
         mappings: vec![],
+        annotations: BTreeMap::new(),
     };
-    metadata.encode(&mut file).map_err(to_val_err)?;
     match schema {
-        Schema::Mbo => write_records_to_dbz::<TickMsg>(file, &records),
-        Schema::Mbp1 => write_records_to_dbz::<Mbp1Msg>(file, &records),
-        Schema::Mbp10 => write_records_to_dbz::<Mbp10Msg>(file, &records),
-        Schema::Tbbo => write_records_to_dbz::<TbboMsg>(file, &records),
-        Schema::Trades => write_records_to_dbz::<TradeMsg>(file, &records),
-        Schema::Ohlcv1S => write_records_to_dbz::<OhlcvMsg>(file, &records),
-        Schema::Ohlcv1M => write_records_to_dbz::<OhlcvMsg>(file, &records),
-        Schema::Ohlcv1H => write_records_to_dbz::<OhlcvMsg>(file, &records),
-        Schema::Ohlcv1D => write_records_to_dbz::<OhlcvMsg>(file, &records),
-        Schema::Definition | Schema::Statistics | Schema::Status => Err(PyValueError::new_err(
+        Schema::Mbo => write_records_to_dbz::<TickMsg>(file, &metadata, &records),
+        Schema::Mbp1 => write_records_to_dbz::<Mbp1Msg>(file, &metadata, &records),
+        Schema::Mbp10 => write_records_to_dbz::<Mbp10Msg>(file, &metadata, &records),
+        Schema::Tbbo => write_records_to_dbz::<TbboMsg>(file, &metadata, &records),
+        Schema::Trades => write_records_to_dbz::<TradeMsg>(file, &metadata, &records),
+        Schema::Ohlcv1S => write_records_to_dbz::<OhlcvMsg>(file, &metadata, &records),
+        Schema::Ohlcv1M => write_records_to_dbz::<OhlcvMsg>(file, &metadata, &records),
+        Schema::Ohlcv1H => write_records_to_dbz::<OhlcvMsg>(file, &metadata, &records),
+        Schema::Ohlcv1D => write_records_to_dbz::<OhlcvMsg>(file, &metadata, &records),
+        Schema::Definition => write_records_to_dbz::<SymDefMsg>(file, &metadata, &records),
+        Schema::Status => write_records_to_dbz::<StatusMsg>(file, &metadata, &records),
+        Schema::Statistics => Err(PyValueError::new_err(
             "Unsupported schema type for writing DBZ files",
         )),
     }
 }
 
+/// Checks each dict in `records` can be converted to the record type corresponding
+/// with `schema` without writing anything. Returns a Python `dict` report with the
+/// `schema`, the `record_count`, the `valid_count`, and a list of `errors` with one for
+/// every invalid field. Each error is a `dict` with the `index` of the record, the
+/// `field`, its `expected_type`, the Python `actual_type` of the value (`None` if
+/// missing), and a `message`.
+///
+/// Like [`write_dbz_file`], if `schema` is `None`, it's inferred from the fields
+/// present in the first record.
+///
+/// # Errors
+/// This function returns an error if `schema` is invalid, can't be inferred, or is a
+/// schema that's not supported for writing.
+#[pyfunction]
+pub fn validate_records(
+    py: Python<'_>,
+    schema: Option<&str>,
+    records: Vec<&PyDict>,
+) -> PyResult<PyObject> {
+    let schema = match schema {
+        Some(schema) => schema.parse::<Schema>().map_err(to_val_err)?,
+        None => infer_schema(records.first().copied())?,
+    };
+    let errors = match schema {
+        Schema::Mbo => validate_records_as::<TickMsg>(&records),
+        Schema::Mbp1 => validate_records_as::<Mbp1Msg>(&records),
+        Schema::Mbp10 => validate_records_as::<Mbp10Msg>(&records),
+        Schema::Tbbo => validate_records_as::<TbboMsg>(&records),
+        Schema::Trades => validate_records_as::<TradeMsg>(&records),
+        Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D => {
+            validate_records_as::<OhlcvMsg>(&records)
+        }
+        Schema::Definition => validate_records_as::<SymDefMsg>(&records),
+        Schema::Status => validate_records_as::<StatusMsg>(&records),
+        Schema::Statistics => {
+            return Err(PyValueError::new_err(
+                "Unsupported schema type for writing DBZ files",
+            ))
+        }
+    };
+    let report = PyDict::new(py);
+    report.set_item("schema", schema.as_str())?;
+    report.set_item("record_count", records.len())?;
+    report.set_item("valid_count", records.len() - errors.len())?;
+    report.set_item(
+        "errors",
+        errors
+            .into_iter()
+            .flat_map(|(i, errors)| errors.into_iter().map(move |e| e.into_py_dict(py, i)))
+            .collect::<PyResult<Vec<_>>>()?,
+    )?;
+    Ok(report.into_py(py))
+}
+
+/// Decodes the DBZ data in `bytes` and writes the records with a `product_id` in
+/// `product_ids` to `file` as DBZ with the same metadata, apart from the record count.
+/// Returns the number of records written.
+///
+/// # Errors
+/// This function returns an error if the DBZ data can't be decoded or there's an
+/// issue writing to `file`.
+#[pyfunction]
+pub fn filter_product_ids(
+    _py: Python<'_>,
+    file: PyFileLike,
+    bytes: &PyBytes,
+    product_ids: Vec<u32>,
+) -> PyResult<u64> {
+    Dbz::new(io::Cursor::new(bytes.as_bytes()))
+        .map_err(to_val_err)?
+        .filter_product_ids(product_ids)
+        .write_dbz_to(file)
+        .map_err(to_val_err)
+}
+
+/// Queries the local DBZ files listed in the manifest at `manifest` for the records of
+/// `schema` with a `ts_event` from `start` up to but excluding `end`, like the
+/// historical client's `timeseries.get_range`. The matching records are merged in
+/// `ts_event` order and written to `file` as DBZ, which can then be read like the
+/// response of a remote request. Returns the number of records written.
+///
+/// If `end` is `None`, there's no end to the time range. If `symbols` is `None` or
+/// empty, records of all symbols are returned. `order` is how records from different
+/// files are merged: `"ts_event"`, the default, `"ts_recv"`, or
+/// `"ts_event_sequence"`. `max_memory` is an upper bound in bytes on the memory used
+/// for merging the files.
+///
+/// # Errors
+/// This function returns an error if the manifest can't be read, `schema` or `order`
+/// is invalid, `schema` doesn't match the manifest, `max_memory` is too small, or any of the files can't be read. It will also
+/// return an error if there's an issue writing to `file`.
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+pub fn get_range(
+    _py: Python<'_>,
+    file: PyFileLike,
+    manifest: &str,
+    schema: &str,
+    start: u64,
+    end: Option<u64>,
+    symbols: Option<Vec<String>>,
+    order: Option<&str>,
+    max_memory: Option<usize>,
+) -> PyResult<u64> {
+    let dataset = DbzDataset::from_manifest(manifest).map_err(to_val_err)?;
+    let schema = schema.parse::<Schema>().map_err(to_val_err)?;
+    let options = RangeOptions {
+        order: match order {
+            Some(order) => order.parse::<RecordOrder>().map_err(to_val_err)?,
+            None => RecordOrder::default(),
+        },
+        max_memory,
+        ..Default::default()
+    };
+    let symbols = symbols.unwrap_or_default();
+    let symbols: Vec<&str> = symbols.iter().map(String::as_str).collect();
+    dataset
+        .write_range_dbz(
+            file,
+            schema,
+            &symbols,
+            start,
+            end.unwrap_or(u64::MAX),
+            &options,
+        )
+        .map_err(to_val_err)
+}
+
+/// Returns the index of each record that can't be converted to `T` with the errors
+/// of all its invalid fields.
+fn validate_records_as<T: FromPyDict>(records: &[&PyDict]) -> Vec<(usize, Vec<FieldError>)> {
+    records
+        .iter()
+        .enumerate()
+        .filter_map(|(i, dict)| T::from_py_dict(dict).err().map(|e| (i, e)))
+        .collect()
+}
+
+/// Infers the [`Schema`] of `record` based on the keys present. TBBO records share
+/// their fields with MBP-1, so they're inferred as MBP-1, and the OHLCV schemas
+/// can't be distinguished from one another, so they must always be passed explicitly.
+fn infer_schema(record: Option<&PyDict>) -> PyResult<Schema> {
+    let record = record.ok_or_else(|| {
+        PyValueError::new_err("Can't infer schema without any records; pass `schema` explicitly")
+    })?;
+    let has_key = |key: &str| record.contains(key).unwrap_or(false);
+    if has_key("order_id") {
+        Ok(Schema::Mbo)
+    } else if has_key("bid_px_01") {
+        Ok(Schema::Mbp10)
+    } else if has_key("bid_px_00") {
+        Ok(Schema::Mbp1)
+    } else if has_key("price") {
+        Ok(Schema::Trades)
+    } else if has_key("open") {
+        Err(PyValueError::new_err(
+            "Can't infer the interval of OHLCV records; pass `schema` explicitly",
+        ))
+    } else if has_key("security_update_action") {
+        Ok(Schema::Definition)
+    } else if has_key("trading_status") {
+        Ok(Schema::Status)
+    } else {
+        Err(PyValueError::new_err(
+            "Unable to infer schema from record fields; pass `schema` explicitly",
+        ))
+    }
+}
+
+/// Returns the [`Schema`] of `record_class`, a record class or the name of one, e.g.
+/// `TradeMsg` for [`Schema::Trades`]. Like in [`infer_schema`], `OhlcvMsg` is shared
+/// by all the OHLCV schemas, so their schema must be passed explicitly.
+fn schema_of_record_class(record_class: &PyAny) -> PyResult<Schema> {
+    let name: &str = match record_class.extract() {
+        Ok(name) => name,
+        Err(_) => record_class.getattr("__name__")?.extract()?,
+    };
+    match name {
+        "TickMsg" => Ok(Schema::Mbo),
+        "Mbp1Msg" => Ok(Schema::Mbp1),
+        "Mbp10Msg" => Ok(Schema::Mbp10),
+        "TbboMsg" => Ok(Schema::Tbbo),
+        "TradeMsg" => Ok(Schema::Trades),
+        "OhlcvMsg" => Err(PyValueError::new_err(
+            "Can't infer the interval of OHLCV records; pass `schema` explicitly",
+        )),
+        "SymDefMsg" => Ok(Schema::Definition),
+        "StatusMsg" => Ok(Schema::Status),
+        _ => Err(PyValueError::new_err(format!(
+            "Unknown record class '{name}', expected one of: TickMsg, Mbp1Msg, Mbp10Msg, \
+            TbboMsg, TradeMsg, SymDefMsg, StatusMsg"
+        ))),
+    }
+}
+
+/// Writes `metadata` and `records` to `file`. Every dict is checked before anything
+/// is written, so a failed write doesn't leave metadata claiming records that
+/// weren't written.
 #[allow(clippy::ptr_arg)]
 fn write_records_to_dbz<T: ConstTypeId + FromPyDict>(
-    file: PyFileLike,
+    mut file: PyFileLike,
+    metadata: &Metadata,
     records: &Vec<&PyDict>,
 ) -> PyResult<()> {
-    write_dbz(
-        file,
-        records
-            .iter()
-            .map(|dict| T::from_py_dict(dict))
-            .collect::<PyResult<Vec<T>>>()?
-            .iter(),
-    )
-    .map_err(to_val_err)
+    for (i, dict) in records.iter().enumerate() {
+        if let Err(mut errors) = T::from_py_dict(dict) {
+            return Err(errors.swap_remove(0).into_py_err(dict.py(), i));
+        }
+    }
+    metadata.encode(&mut file).map_err(to_val_err)?;
+    let mut stream = PyDictStream::<T>::new(records);
+    write_dbz_stream(file, &mut stream).map_err(to_val_err)?;
+    match stream.error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// A [`StreamingIterator`] that converts each dict to a record as it's advanced, so
+/// only one converted record is held in memory at a time. Iteration stops at the
+/// first dict that fails to convert and the error is stored in `error`.
+struct PyDictStream<'a, T> {
+    records: std::slice::Iter<'a, &'a PyDict>,
+    index: usize,
+    record: Option<T>,
+    error: Option<PyErr>,
+}
+
+impl<'a, T> PyDictStream<'a, T> {
+    fn new(records: &'a [&'a PyDict]) -> Self {
+        Self {
+            records: records.iter(),
+            index: 0,
+            record: None,
+            error: None,
+        }
+    }
+}
+
+impl<'a, T: FromPyDict> StreamingIterator for PyDictStream<'a, T> {
+    type Item = T;
+
+    fn advance(&mut self) {
+        self.record = match self.records.next() {
+            Some(dict) if self.error.is_none() => match T::from_py_dict(dict) {
+                Ok(record) => Some(record),
+                Err(mut errors) => {
+                    // writing stops at the first invalid field
+                    self.error = Some(errors.swap_remove(0).into_py_err(dict.py(), self.index));
+                    None
+                }
+            },
+            _ => None,
+        };
+        self.index += 1;
+    }
+
+    fn get(&self) -> Option<&Self::Item> {
+        self.record.as_ref()
+    }
 }
 
 impl<'source> FromPyObject<'source> for PyFileLike {
@@ -216,6 +553,8 @@ impl IntoPy<PyObject> for Metadata {
             .expect("set not_found");
         dict.set_item("mappings", self.mappings)
             .expect("set mappings");
+        dict.set_item("annotations", self.annotations)
+            .expect("set annotations");
         dict.into_py(py)
     }
 }
@@ -301,10 +640,10 @@ fn py_to_rs_io_err(e: PyErr) -> io::Error {
 
         match e_as_object.call_method(py, "__str__", (), None) {
             Ok(repr) => match repr.extract::<String>(py) {
-                Ok(s) => io::Error::new(io::ErrorKind::Other, s),
-                Err(_e) => io::Error::new(io::ErrorKind::Other, "An unknown error has occurred"),
+                Ok(s) => io::Error::other(s),
+                Err(_e) => io::Error::other("An unknown error has occurred"),
             },
-            Err(_) => io::Error::new(io::ErrorKind::Other, "Err doesn't have __str__"),
+            Err(_) => io::Error::other("Err doesn't have __str__"),
         }
     })
 }
@@ -338,8 +677,8 @@ impl io::Seek for PyFileLike {
         Python::with_gil(|py| {
             let (whence, offset) = match pos {
                 SeekFrom::Start(i) => (0, i as i64),
-                SeekFrom::Current(i) => (1, i as i64),
-                SeekFrom::End(i) => (2, i as i64),
+                SeekFrom::Current(i) => (1, i),
+                SeekFrom::End(i) => (2, i),
             };
 
             let new_position = self
@@ -353,135 +692,424 @@ impl io::Seek for PyFileLike {
 }
 
 trait FromPyDict: Sized {
-    fn from_py_dict(dict: &PyDict) -> PyResult<Self>;
+    /// Converts `dict` to a record, or returns the errors of every invalid field.
+    fn from_py_dict(dict: &PyDict) -> Result<Self, Vec<FieldError>>;
 }
 
-fn try_get_item<'a>(dict: &'a PyDict, key: &str) -> PyResult<&'a PyAny> {
-    dict.get_item(key)
-        .ok_or_else(|| PyKeyError::new_err(format!("Missing {key}")))
+/// An error converting a single field of a record `dict` to its Rust equivalent.
+#[derive(Debug)]
+struct FieldError {
+    key: String,
+    /// The name of the Rust type the field should be converted to.
+    expected_type: &'static str,
+    /// The Python type of the offending value. `None` if the key is missing.
+    actual_type: Option<String>,
+    /// The original conversion error, if any.
+    source: Option<PyErr>,
 }
 
-fn try_extract_item<'a, D>(dict: &'a PyDict, key: &str) -> PyResult<D>
+impl FieldError {
+    fn missing<D>(key: &str) -> Self {
+        Self {
+            key: key.to_owned(),
+            expected_type: std::any::type_name::<D>(),
+            actual_type: None,
+            source: None,
+        }
+    }
+
+    fn invalid<D>(key: &str, value: &PyAny, source: PyErr) -> Self {
+        Self {
+            key: key.to_owned(),
+            expected_type: std::any::type_name::<D>(),
+            actual_type: Some(
+                value
+                    .get_type()
+                    .name()
+                    .map(ToOwned::to_owned)
+                    .unwrap_or_else(|_| "<unknown>".to_owned()),
+            ),
+            source: Some(source),
+        }
+    }
+
+    /// Converts the error to a Python exception for the record at `index`. Missing
+    /// fields raise a `KeyError`, otherwise the type of the original conversion
+    /// error is preserved.
+    fn into_py_err(self, py: Python<'_>, index: usize) -> PyErr {
+        let msg = format!("record {index}: {self}");
+        match self.source {
+            Some(source) => PyErr::from_type(source.get_type(py), msg),
+            None => PyKeyError::new_err(msg),
+        }
+    }
+
+    fn into_py_dict(self, py: Python<'_>, index: usize) -> PyResult<&PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("index", index)?;
+        dict.set_item("field", &self.key)?;
+        dict.set_item("expected_type", self.expected_type)?;
+        dict.set_item("actual_type", &self.actual_type)?;
+        dict.set_item("message", self.to_string())?;
+        Ok(dict)
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.actual_type, &self.source) {
+            (Some(actual_type), Some(source)) => write!(
+                f,
+                "invalid value for field `{}`: expected `{}`, got Python `{actual_type}` ({source})",
+                self.key, self.expected_type
+            ),
+            _ => write!(
+                f,
+                "missing field `{}` of type `{}`",
+                self.key, self.expected_type
+            ),
+        }
+    }
+}
+
+fn try_extract_item<'a, D>(dict: &'a PyDict, key: &str) -> Result<D, FieldError>
 where
     D: FromPyObject<'a>,
 {
-    try_get_item(dict, key)?.extract::<D>()
+    let value = dict
+        .get_item(key)
+        .ok_or_else(|| FieldError::missing::<D>(key))?;
+    value
+        .extract::<D>()
+        .map_err(|e| FieldError::invalid::<D>(key, value, e))
+}
+
+/// Extracts a character field, which may be either its integer value or a
+/// one-character `str`, matching both text output formats.
+fn try_extract_char(dict: &PyDict, key: &str) -> Result<c_char, FieldError> {
+    let value = dict
+        .get_item(key)
+        .ok_or_else(|| FieldError::missing::<c_char>(key))?;
+    value
+        .extract::<c_char>()
+        .or_else(|e| match value.extract::<&str>().map(str::as_bytes) {
+            Ok(&[c]) if c.is_ascii() => Ok(c as c_char),
+            _ => Err(FieldError::invalid::<c_char>(key, value, e)),
+        })
+}
+
+/// Extracts a fixed-length C string field from a `str`. Strings shorter than `N`
+/// are padded with null bytes.
+fn try_extract_cstr<const N: usize>(dict: &PyDict, key: &str) -> Result<[c_char; N], FieldError> {
+    let value = dict
+        .get_item(key)
+        .ok_or_else(|| FieldError::missing::<[c_char; N]>(key))?;
+    let string = value
+        .extract::<&str>()
+        .map_err(|e| FieldError::invalid::<[c_char; N]>(key, value, e))?;
+    if !string.is_ascii() || string.len() > N {
+        return Err(FieldError::invalid::<[c_char; N]>(
+            key,
+            value,
+            PyValueError::new_err(format!(
+                "expected an ASCII string of at most {N} characters"
+            )),
+        ));
+    }
+    let mut chars = [0; N];
+    for (c, b) in chars.iter_mut().zip(string.bytes()) {
+        *c = b as c_char;
+    }
+    Ok(chars)
+}
+
+/// Extracts a `security_update_action`, which may be either its name like in the
+/// text output formats, e.g. `"Add"`, or its one-character code, e.g. `"A"`.
+fn try_extract_security_update_action(
+    dict: &PyDict,
+    key: &str,
+) -> Result<SecurityUpdateAction, FieldError> {
+    let value = dict
+        .get_item(key)
+        .ok_or_else(|| FieldError::missing::<SecurityUpdateAction>(key))?;
+    let action = value
+        .extract::<&str>()
+        .map_err(|e| FieldError::invalid::<SecurityUpdateAction>(key, value, e))?;
+    match action {
+        "Add" | "A" => Ok(SecurityUpdateAction::Add),
+        "Modify" | "M" => Ok(SecurityUpdateAction::Modify),
+        "Delete" | "D" => Ok(SecurityUpdateAction::Delete),
+        "Invalid" | "~" => Ok(SecurityUpdateAction::Invalid),
+        _ => Err(FieldError::invalid::<SecurityUpdateAction>(
+            key,
+            value,
+            PyValueError::new_err(format!("unknown security update action '{action}'")),
+        )),
+    }
+}
+
+/// Extracts the fields of a record `dict`, collecting the error of every invalid field
+/// instead of stopping at the first. Invalid fields are replaced with placeholders,
+/// so the record returned by [`FieldExtractor::finish`] is only used without errors.
+struct FieldExtractor<'a> {
+    dict: &'a PyDict,
+    errors: Vec<FieldError>,
+}
+
+impl<'a> FieldExtractor<'a> {
+    fn new(dict: &'a PyDict) -> Self {
+        Self {
+            dict,
+            errors: Vec::new(),
+        }
+    }
+
+    fn collect<D>(&mut self, res: Result<D, FieldError>, placeholder: D) -> D {
+        res.unwrap_or_else(|e| {
+            self.errors.push(e);
+            placeholder
+        })
+    }
+
+    fn item<D: FromPyObject<'a> + Default>(&mut self, key: &str) -> D {
+        let res = try_extract_item(self.dict, key);
+        self.collect(res, D::default())
+    }
+
+    fn char(&mut self, key: &str) -> c_char {
+        let res = try_extract_char(self.dict, key);
+        self.collect(res, 0)
+    }
+
+    fn cstr<const N: usize>(&mut self, key: &str) -> [c_char; N] {
+        let res = try_extract_cstr(self.dict, key);
+        self.collect(res, [0; N])
+    }
+
+    fn security_update_action(&mut self, key: &str) -> SecurityUpdateAction {
+        let res = try_extract_security_update_action(self.dict, key);
+        self.collect(res, SecurityUpdateAction::Invalid)
+    }
+
+    fn finish<T>(self, record: T) -> Result<T, Vec<FieldError>> {
+        if self.errors.is_empty() {
+            Ok(record)
+        } else {
+            Err(self.errors)
+        }
+    }
 }
 
-fn header_from_dict<T: ConstTypeId>(dict: &PyDict) -> PyResult<RecordHeader> {
-    Ok(RecordHeader {
+fn header_from_fields<T: ConstTypeId>(fields: &mut FieldExtractor) -> RecordHeader {
+    RecordHeader {
         length: (mem::size_of::<T>() / 4) as u8,
         rtype: T::TYPE_ID,
-        publisher_id: try_extract_item::<u16>(dict, "publisher_id")?,
-        product_id: try_extract_item::<u32>(dict, "product_id")?,
-        ts_event: try_extract_item::<u64>(dict, "ts_event")?,
-    })
+        publisher_id: fields.item::<u16>("publisher_id"),
+        product_id: fields.item::<u32>("product_id"),
+        ts_event: fields.item::<u64>("ts_event"),
+    }
 }
 
 impl FromPyDict for TickMsg {
-    fn from_py_dict(dict: &PyDict) -> PyResult<Self> {
-        Ok(Self {
-            hd: header_from_dict::<Self>(dict)?,
-            order_id: try_extract_item::<u64>(dict, "order_id")?,
-            price: try_extract_item::<i64>(dict, "price")?,
-            size: try_extract_item::<u32>(dict, "size")?,
-            flags: try_extract_item::<i8>(dict, "flags")?,
-            channel_id: try_extract_item::<u8>(dict, "channel_id")?,
-            action: try_extract_item::<c_char>(dict, "action")?,
-            side: try_extract_item::<c_char>(dict, "side")?,
-            ts_recv: try_extract_item::<u64>(dict, "ts_recv")?,
-            ts_in_delta: try_extract_item::<i32>(dict, "ts_in_delta")?,
-            sequence: try_extract_item::<u32>(dict, "sequence")?,
-        })
+    fn from_py_dict(dict: &PyDict) -> Result<Self, Vec<FieldError>> {
+        let mut fields = FieldExtractor::new(dict);
+        let record = Self {
+            hd: header_from_fields::<Self>(&mut fields),
+            order_id: fields.item::<u64>("order_id"),
+            price: fields.item::<i64>("price"),
+            size: fields.item::<u32>("size"),
+            flags: fields.item::<i8>("flags"),
+            channel_id: fields.item::<u8>("channel_id"),
+            action: fields.char("action"),
+            side: fields.char("side"),
+            ts_recv: fields.item::<u64>("ts_recv"),
+            ts_in_delta: fields.item::<i32>("ts_in_delta"),
+            sequence: fields.item::<u32>("sequence"),
+        };
+        fields.finish(record)
     }
 }
 
-fn ba_pair_from_dict<const LEVEL: u8>(dict: &PyDict) -> PyResult<BidAskPair> {
-    Ok(BidAskPair {
-        bid_px: try_extract_item::<i64>(dict, &format!("bid_px_0{LEVEL}"))?,
-        ask_px: try_extract_item::<i64>(dict, &format!("ask_px_0{LEVEL}"))?,
-        bid_sz: try_extract_item::<u32>(dict, &format!("bid_sz_0{LEVEL}"))?,
-        ask_sz: try_extract_item::<u32>(dict, &format!("ask_sz_0{LEVEL}"))?,
-        bid_ct: try_extract_item::<u32>(dict, &format!("bid_ct_0{LEVEL}"))?,
-        ask_ct: try_extract_item::<u32>(dict, &format!("ask_ct_0{LEVEL}"))?,
-    })
+fn ba_pair_from_fields<const LEVEL: u8>(fields: &mut FieldExtractor) -> BidAskPair {
+    BidAskPair {
+        bid_px: fields.item::<i64>(&format!("bid_px_0{LEVEL}")),
+        ask_px: fields.item::<i64>(&format!("ask_px_0{LEVEL}")),
+        bid_sz: fields.item::<u32>(&format!("bid_sz_0{LEVEL}")),
+        ask_sz: fields.item::<u32>(&format!("ask_sz_0{LEVEL}")),
+        bid_ct: fields.item::<u32>(&format!("bid_ct_0{LEVEL}")),
+        ask_ct: fields.item::<u32>(&format!("ask_ct_0{LEVEL}")),
+    }
 }
 
 impl FromPyDict for TradeMsg {
-    fn from_py_dict(dict: &PyDict) -> PyResult<Self> {
-        Ok(Self {
-            hd: header_from_dict::<Self>(dict)?,
-            price: try_extract_item::<i64>(dict, "price")?,
-            size: try_extract_item::<u32>(dict, "size")?,
-            action: try_extract_item::<c_char>(dict, "action")?,
-            side: try_extract_item::<c_char>(dict, "side")?,
-            flags: try_extract_item::<i8>(dict, "flags")?,
-            depth: try_extract_item::<u8>(dict, "depth")?,
-            ts_recv: try_extract_item::<u64>(dict, "ts_recv")?,
-            ts_in_delta: try_extract_item::<i32>(dict, "ts_in_delta")?,
-            sequence: try_extract_item::<u32>(dict, "sequence")?,
+    fn from_py_dict(dict: &PyDict) -> Result<Self, Vec<FieldError>> {
+        let mut fields = FieldExtractor::new(dict);
+        let record = Self {
+            hd: header_from_fields::<Self>(&mut fields),
+            price: fields.item::<i64>("price"),
+            size: fields.item::<u32>("size"),
+            action: fields.char("action"),
+            side: fields.char("side"),
+            flags: fields.item::<i8>("flags"),
+            depth: fields.item::<u8>("depth"),
+            ts_recv: fields.item::<u64>("ts_recv"),
+            ts_in_delta: fields.item::<i32>("ts_in_delta"),
+            sequence: fields.item::<u32>("sequence"),
             booklevel: [],
-        })
+        };
+        fields.finish(record)
     }
 }
 
 impl FromPyDict for Mbp1Msg {
-    fn from_py_dict(dict: &PyDict) -> PyResult<Self> {
-        Ok(Self {
-            hd: header_from_dict::<Self>(dict)?,
-            price: try_extract_item::<i64>(dict, "price")?,
-            size: try_extract_item::<u32>(dict, "size")?,
-            action: try_extract_item::<c_char>(dict, "action")?,
-            side: try_extract_item::<c_char>(dict, "side")?,
-            flags: try_extract_item::<i8>(dict, "flags")?,
-            depth: try_extract_item::<u8>(dict, "depth")?,
-            ts_recv: try_extract_item::<u64>(dict, "ts_recv")?,
-            ts_in_delta: try_extract_item::<i32>(dict, "ts_in_delta")?,
-            sequence: try_extract_item::<u32>(dict, "sequence")?,
-            booklevel: [ba_pair_from_dict::<0>(dict)?],
-        })
+    fn from_py_dict(dict: &PyDict) -> Result<Self, Vec<FieldError>> {
+        let mut fields = FieldExtractor::new(dict);
+        let record = Self {
+            hd: header_from_fields::<Self>(&mut fields),
+            price: fields.item::<i64>("price"),
+            size: fields.item::<u32>("size"),
+            action: fields.char("action"),
+            side: fields.char("side"),
+            flags: fields.item::<i8>("flags"),
+            depth: fields.item::<u8>("depth"),
+            ts_recv: fields.item::<u64>("ts_recv"),
+            ts_in_delta: fields.item::<i32>("ts_in_delta"),
+            sequence: fields.item::<u32>("sequence"),
+            booklevel: [ba_pair_from_fields::<0>(&mut fields)],
+        };
+        fields.finish(record)
     }
 }
 
 impl FromPyDict for Mbp10Msg {
-    fn from_py_dict(dict: &PyDict) -> PyResult<Self> {
-        Ok(Self {
-            hd: header_from_dict::<Self>(dict)?,
-            price: try_extract_item::<i64>(dict, "price")?,
-            size: try_extract_item::<u32>(dict, "size")?,
-            action: try_extract_item::<c_char>(dict, "action")?,
-            side: try_extract_item::<c_char>(dict, "side")?,
-            flags: try_extract_item::<i8>(dict, "flags")?,
-            depth: try_extract_item::<u8>(dict, "depth")?,
-            ts_recv: try_extract_item::<u64>(dict, "ts_recv")?,
-            ts_in_delta: try_extract_item::<i32>(dict, "ts_in_delta")?,
-            sequence: try_extract_item::<u32>(dict, "sequence")?,
+    fn from_py_dict(dict: &PyDict) -> Result<Self, Vec<FieldError>> {
+        let mut fields = FieldExtractor::new(dict);
+        let record = Self {
+            hd: header_from_fields::<Self>(&mut fields),
+            price: fields.item::<i64>("price"),
+            size: fields.item::<u32>("size"),
+            action: fields.char("action"),
+            side: fields.char("side"),
+            flags: fields.item::<i8>("flags"),
+            depth: fields.item::<u8>("depth"),
+            ts_recv: fields.item::<u64>("ts_recv"),
+            ts_in_delta: fields.item::<i32>("ts_in_delta"),
+            sequence: fields.item::<u32>("sequence"),
             booklevel: [
-                ba_pair_from_dict::<0>(dict)?,
-                ba_pair_from_dict::<1>(dict)?,
-                ba_pair_from_dict::<2>(dict)?,
-                ba_pair_from_dict::<3>(dict)?,
-                ba_pair_from_dict::<4>(dict)?,
-                ba_pair_from_dict::<5>(dict)?,
-                ba_pair_from_dict::<6>(dict)?,
-                ba_pair_from_dict::<7>(dict)?,
-                ba_pair_from_dict::<8>(dict)?,
-                ba_pair_from_dict::<9>(dict)?,
+                ba_pair_from_fields::<0>(&mut fields),
+                ba_pair_from_fields::<1>(&mut fields),
+                ba_pair_from_fields::<2>(&mut fields),
+                ba_pair_from_fields::<3>(&mut fields),
+                ba_pair_from_fields::<4>(&mut fields),
+                ba_pair_from_fields::<5>(&mut fields),
+                ba_pair_from_fields::<6>(&mut fields),
+                ba_pair_from_fields::<7>(&mut fields),
+                ba_pair_from_fields::<8>(&mut fields),
+                ba_pair_from_fields::<9>(&mut fields),
             ],
-        })
+        };
+        fields.finish(record)
     }
 }
 
 impl FromPyDict for OhlcvMsg {
-    fn from_py_dict(dict: &PyDict) -> PyResult<Self> {
-        Ok(Self {
-            hd: header_from_dict::<Self>(dict)?,
-            open: try_extract_item::<i64>(dict, "open")?,
-            high: try_extract_item::<i64>(dict, "high")?,
-            low: try_extract_item::<i64>(dict, "low")?,
-            close: try_extract_item::<i64>(dict, "close")?,
-            volume: try_extract_item::<u64>(dict, "volume")?,
-        })
+    fn from_py_dict(dict: &PyDict) -> Result<Self, Vec<FieldError>> {
+        let mut fields = FieldExtractor::new(dict);
+        let record = Self {
+            hd: header_from_fields::<Self>(&mut fields),
+            open: fields.item::<i64>("open"),
+            high: fields.item::<i64>("high"),
+            low: fields.item::<i64>("low"),
+            close: fields.item::<i64>("close"),
+            volume: fields.item::<u64>("volume"),
+        };
+        fields.finish(record)
+    }
+}
+
+impl FromPyDict for StatusMsg {
+    fn from_py_dict(dict: &PyDict) -> Result<Self, Vec<FieldError>> {
+        let mut fields = FieldExtractor::new(dict);
+        let record = Self {
+            hd: header_from_fields::<Self>(&mut fields),
+            ts_recv: fields.item::<u64>("ts_recv"),
+            group: fields.cstr("group"),
+            trading_status: fields.item::<u8>("trading_status"),
+            halt_reason: fields.item::<u8>("halt_reason"),
+            trading_event: fields.item::<u8>("trading_event"),
+        };
+        fields.finish(record)
+    }
+}
+
+impl FromPyDict for SymDefMsg {
+    fn from_py_dict(dict: &PyDict) -> Result<Self, Vec<FieldError>> {
+        let mut fields = FieldExtractor::new(dict);
+        let record = Self {
+            hd: header_from_fields::<Self>(&mut fields),
+            ts_recv: fields.item::<u64>("ts_recv"),
+            min_price_increment: fields.item::<i64>("min_price_increment"),
+            display_factor: fields.item::<i64>("display_factor"),
+            expiration: fields.item::<u64>("expiration"),
+            activation: fields.item::<u64>("activation"),
+            high_limit_price: fields.item::<i64>("high_limit_price"),
+            low_limit_price: fields.item::<i64>("low_limit_price"),
+            max_price_variation: fields.item::<i64>("max_price_variation"),
+            trading_reference_price: fields.item::<i64>("trading_reference_price"),
+            unit_of_measure_qty: fields.item::<i64>("unit_of_measure_qty"),
+            min_price_increment_amount: fields.item::<i64>("min_price_increment_amount"),
+            price_ratio: fields.item::<i64>("price_ratio"),
+            inst_attrib_value: fields.item::<i32>("inst_attrib_value"),
+            underlying_id: fields.item::<u32>("underlying_id"),
+            cleared_volume: fields.item::<i32>("cleared_volume"),
+            market_depth_implied: fields.item::<i32>("market_depth_implied"),
+            market_depth: fields.item::<i32>("market_depth"),
+            market_segment_id: fields.item::<u32>("market_segment_id"),
+            max_trade_vol: fields.item::<u32>("max_trade_vol"),
+            min_lot_size: fields.item::<i32>("min_lot_size"),
+            min_lot_size_block: fields.item::<i32>("min_lot_size_block"),
+            min_lot_size_round_lot: fields.item::<i32>("min_lot_size_round_lot"),
+            min_trade_vol: fields.item::<u32>("min_trade_vol"),
+            open_interest_qty: fields.item::<i32>("open_interest_qty"),
+            contract_multiplier: fields.item::<i32>("contract_multiplier"),
+            decay_quantity: fields.item::<i32>("decay_quantity"),
+            original_contract_size: fields.item::<i32>("original_contract_size"),
+            related_security_id: fields.item::<u32>("related_security_id"),
+            trading_reference_date: fields.item::<u16>("trading_reference_date"),
+            appl_id: fields.item::<i16>("appl_id"),
+            maturity_month_year: fields.item::<u16>("maturity_month_year"),
+            decay_start_date: fields.item::<u16>("decay_start_date"),
+            chan: fields.item::<u16>("chan"),
+            currency: fields.cstr("currency"),
+            settl_currency: fields.cstr("settl_currency"),
+            secsubtype: fields.cstr("secsubtype"),
+            symbol: fields.cstr("symbol"),
+            group: fields.cstr("group"),
+            exchange: fields.cstr("exchange"),
+            asset: fields.cstr("asset"),
+            cfi: fields.cstr("cfi"),
+            security_type: fields.cstr("security_type"),
+            unit_of_measure: fields.cstr("unit_of_measure"),
+            underlying: fields.cstr("underlying"),
+            related: fields.cstr("related"),
+            match_algorithm: fields.char("match_algorithm"),
+            md_security_trading_status: fields.item::<u8>("md_security_trading_status"),
+            main_fraction: fields.item::<u8>("main_fraction"),
+            price_display_format: fields.item::<u8>("price_display_format"),
+            settl_price_type: fields.item::<u8>("settl_price_type"),
+            sub_fraction: fields.item::<u8>("sub_fraction"),
+            underlying_product: fields.item::<u8>("underlying_product"),
+            security_update_action: fields.security_update_action("security_update_action"),
+            maturity_month_month: fields.item::<u8>("maturity_month_month"),
+            maturity_month_day: fields.item::<u8>("maturity_month_day"),
+            maturity_month_week: fields.item::<u8>("maturity_month_week"),
+            user_defined_instrument: fields.char("user_defined_instrument"),
+            contract_multiplier_unit: fields.item::<i8>("contract_multiplier_unit"),
+            flow_schedule_type: fields.item::<i8>("flow_schedule_type"),
+            tick_rule: fields.item::<u8>("tick_rule"),
+            _dummy: [0; 3],
+        };
+        fields.finish(record)
     }
 }
 
@@ -493,7 +1121,7 @@ mod tests {
     use streaming_iterator::StreamingIterator;
 
     use super::*;
-    use crate::{Dbz, OutputEncoding};
+    use crate::{manifest::tests::write_trades_with_range, Manifest, OutputEncoding};
 
     const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
 
@@ -563,7 +1191,10 @@ mod tests {
                 }
                 .unwrap();
             }
-            serde_json::Value::String(s) if key.starts_with("ts_") => {
+            // timestamps are output as strings
+            serde_json::Value::String(s)
+                if key.starts_with("ts_") || matches!(key, "expiration" | "activation") =>
+            {
                 dict.set_item(key, s.parse::<u64>().unwrap()).unwrap();
             }
             serde_json::Value::String(s) => {
@@ -597,9 +1228,635 @@ mod tests {
         res
     }
 
+    /// Reads the test data for `schema` into generic serde JSON objects.
+    fn read_json_records(schema: Schema) -> Vec<JsonObj> {
+        let input =
+            Dbz::from_file(format!("{DBZ_PATH}/test_data.{}.dbz", schema.as_str())).unwrap();
+        let mut writer = Cursor::new(Vec::new());
+        input
+            .write_to(
+                &mut writer,
+                OutputEncoding::Json {
+                    should_pretty_print: false,
+                    should_output_array: false,
+                    should_output_iso_timestamps: false,
+                    should_output_decimal_prices: false,
+                },
+            )
+            .unwrap();
+        let json_input = String::from_utf8(writer.into_inner()).unwrap();
+        serde_json::Deserializer::from_str(&json_input)
+            .into_iter()
+            .collect::<serde_json::Result<Vec<JsonObj>>>()
+            .unwrap()
+    }
+
     const DATASET: &str = "GLBX.MDP3";
     const STYPE: SType = SType::ProductId;
 
+    #[test]
+    fn test_symbology_round_trip() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let metadata = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz"))
+                .unwrap()
+                .metadata()
+                .clone();
+            let mut encoded = Vec::new();
+            metadata.encode(Cursor::new(&mut encoded)).unwrap();
+            let symbology = mappings_to_symbology(py, PyBytes::new(py, &encoded)).unwrap();
+            let symbology: &PyDict = symbology.extract(py).unwrap();
+            assert_eq!(
+                symbology
+                    .get_item("stype_out")
+                    .unwrap()
+                    .extract::<String>()
+                    .unwrap(),
+                "product_id"
+            );
+            let mappings = mappings_from_symbology(py, symbology).unwrap();
+            let mappings: &PyAny = mappings.as_ref(py);
+            assert_eq!(mappings.len().unwrap(), metadata.mappings.len());
+            assert_eq!(
+                mappings
+                    .get_item(0)
+                    .unwrap()
+                    .get_item("native")
+                    .unwrap()
+                    .extract::<String>()
+                    .unwrap(),
+                metadata.mappings[0].native
+            );
+        });
+    }
+
+    #[test]
+    fn test_mappings_from_symbology_invalid() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let symbology = PyDict::new(py);
+            symbology.set_item("symbols", vec!["ESH1"]).unwrap();
+            assert!(mappings_from_symbology(py, symbology).is_err());
+        });
+    }
+
+    #[test]
+    fn test_infer_schema() {
+        pyo3::prepare_freethreaded_python();
+        for (schema, exp) in [
+            (Schema::Mbo, Some(Schema::Mbo)),
+            (Schema::Mbp1, Some(Schema::Mbp1)),
+            (Schema::Mbp10, Some(Schema::Mbp10)),
+            // TBBO and MBP-1 share a record type
+            (Schema::Tbbo, Some(Schema::Mbp1)),
+            (Schema::Trades, Some(Schema::Trades)),
+            (Schema::Ohlcv1H, None),
+        ] {
+            let json_recs = read_json_records(schema);
+            Python::with_gil(|py| {
+                let res = infer_schema(Some(json_to_py_dict(py, &json_recs[0])));
+                match exp {
+                    Some(exp) => assert_eq!(res.unwrap(), exp),
+                    None => assert!(res.is_err()),
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn test_infer_schema_no_records() {
+        pyo3::prepare_freethreaded_python();
+        let res = infer_schema(None);
+        assert!(matches!(res, Err(e) if e.to_string().contains("without any records")));
+    }
+
+    fn write_trades_from_python(
+        modify: impl Fn(Python<'_>, usize, &PyDict),
+    ) -> PyResult<Arc<Mutex<Cursor<Vec<u8>>>>> {
+        pyo3::prepare_freethreaded_python();
+        let json_recs = read_json_records(Schema::Trades);
+        Python::with_gil(|py| {
+            let recs: Vec<_> = json_recs
+                .iter()
+                .enumerate()
+                .map(|(i, json_rec)| {
+                    let rec = json_to_py_dict(py, json_rec);
+                    modify(py, i, rec);
+                    rec
+                })
+                .collect();
+            let mock_file = MockPyFile::new();
+            let output_buf = mock_file.inner();
+            let mock_file = Py::new(py, mock_file).unwrap().into_py(py);
+            write_dbz_file(
+                py,
+                mock_file.extract(py).unwrap(),
+                Some(Schema::Trades.as_str()),
+                Some(DATASET.to_owned()),
+                Some(recs),
+                Some(STYPE.as_str()),
+                None,
+            )?;
+            Ok(output_buf)
+        })
+    }
+
+    #[test]
+    fn test_write_missing_field_error() {
+        let res = write_trades_from_python(|_py, i, rec| {
+            if i == 1 {
+                rec.del_item("ts_recv").unwrap();
+            }
+        });
+        Python::with_gil(|py| {
+            let err = res.unwrap_err();
+            assert!(err.is_instance_of::<PyKeyError>(py));
+            let msg = err.value(py).to_string();
+            assert!(msg.contains("record 1"), "{msg}");
+            assert!(
+                msg.contains("missing field `ts_recv` of type `u64`"),
+                "{msg}"
+            );
+        });
+    }
+
+    #[test]
+    fn test_write_invalid_field_error() {
+        let res = write_trades_from_python(|_py, i, rec| {
+            if i == 1 {
+                rec.set_item("size", "ten").unwrap();
+            }
+        });
+        Python::with_gil(|py| {
+            let err = res.unwrap_err();
+            assert!(err.is_instance_of::<PyTypeError>(py));
+            let msg = err.value(py).to_string();
+            assert!(msg.contains("record 1"), "{msg}");
+            assert!(
+                msg.contains("invalid value for field `size`: expected `u32`, got Python `str`"),
+                "{msg}"
+            );
+        });
+    }
+
+    #[test]
+    fn test_failed_write_leaves_file_empty() {
+        pyo3::prepare_freethreaded_python();
+        let json_recs = read_json_records(Schema::Trades);
+        Python::with_gil(|py| {
+            let recs: Vec<_> = json_recs
+                .iter()
+                .map(|json_rec| json_to_py_dict(py, json_rec))
+                .collect();
+            recs[1].del_item("ts_recv").unwrap();
+            let mock_file = MockPyFile::new();
+            let output_buf = mock_file.inner();
+            let mock_file = Py::new(py, mock_file).unwrap().into_py(py);
+            let err = write_dbz_file(
+                py,
+                mock_file.extract(py).unwrap(),
+                Some(Schema::Trades.as_str()),
+                Some(DATASET.to_owned()),
+                Some(recs),
+                Some(STYPE.as_str()),
+                None,
+            )
+            .unwrap_err();
+            assert!(err.value(py).to_string().contains("record 1"), "{err}");
+            // neither the metadata nor the valid first record are written
+            assert!(output_buf.lock().unwrap().get_ref().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_write_char_fields_as_str() {
+        let output_buf = write_trades_from_python(|_py, _i, rec| {
+            rec.set_item("side", "A").unwrap();
+        })
+        .unwrap();
+        let output_buf = output_buf.lock().unwrap().get_ref().clone();
+        let dbz = Dbz::new(Cursor::new(output_buf)).unwrap();
+        let mut iter = dbz.try_into_iter::<TradeMsg>().unwrap();
+        assert_eq!(iter.next().unwrap().side, 'A' as c_char);
+
+        let res = write_trades_from_python(|_py, _i, rec| {
+            rec.set_item("side", "AB").unwrap();
+        });
+        Python::with_gil(|py| {
+            assert!(res.unwrap_err().is_instance_of::<PyTypeError>(py));
+        });
+    }
+
+    #[test]
+    fn test_py_dict_stream_stops_at_error() {
+        pyo3::prepare_freethreaded_python();
+        let json_recs = read_json_records(Schema::Trades);
+        Python::with_gil(|py| {
+            let mut recs: Vec<_> = json_recs
+                .iter()
+                .map(|json_rec| json_to_py_dict(py, json_rec))
+                .collect();
+            recs.insert(1, PyDict::new(py));
+            let mut stream = PyDictStream::<TradeMsg>::new(&recs);
+            assert!(stream.next().is_some());
+            assert!(stream.next().is_none());
+            assert!(stream.next().is_none());
+            let msg = stream.error.unwrap().value(py).to_string();
+            assert!(msg.contains("record 1"), "{msg}");
+        });
+    }
+
+    #[test]
+    fn test_validate_records() {
+        pyo3::prepare_freethreaded_python();
+        let json_recs = read_json_records(Schema::Mbp1);
+        Python::with_gil(|py| {
+            let recs: Vec<_> = json_recs
+                .iter()
+                .map(|json_rec| json_to_py_dict(py, json_rec))
+                .collect();
+            recs[0].del_item("ask_ct_00").unwrap();
+            recs[0].set_item("price", "abc").unwrap();
+            let report = validate_records(py, Some(Schema::Mbp1.as_str()), recs).unwrap();
+            let report: &PyDict = report.extract(py).unwrap();
+            let get = |key| report.get_item(key).unwrap();
+            assert_eq!(get("schema").extract::<String>().unwrap(), "mbp-1");
+            assert_eq!(
+                get("record_count").extract::<usize>().unwrap(),
+                json_recs.len()
+            );
+            assert_eq!(
+                get("valid_count").extract::<usize>().unwrap(),
+                json_recs.len() - 1
+            );
+            // every invalid field of the record is reported
+            let errors: Vec<&PyDict> = get("errors").extract().unwrap();
+            assert_eq!(errors.len(), 2);
+            let field =
+                |error: &PyDict| -> String { error.get_item("field").unwrap().extract().unwrap() };
+            assert_eq!(field(errors[0]), "price");
+            let error = errors[1];
+            assert_eq!(
+                error.get_item("index").unwrap().extract::<usize>().unwrap(),
+                0
+            );
+            assert_eq!(
+                error
+                    .get_item("field")
+                    .unwrap()
+                    .extract::<String>()
+                    .unwrap(),
+                "ask_ct_00"
+            );
+            assert_eq!(
+                error
+                    .get_item("expected_type")
+                    .unwrap()
+                    .extract::<String>()
+                    .unwrap(),
+                "u32"
+            );
+            assert!(error.get_item("actual_type").unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn test_writing_inferred_schema_from_python() {
+        pyo3::prepare_freethreaded_python();
+        let json_recs = read_json_records(Schema::Trades);
+        let output_buf = Python::with_gil(|py| {
+            let recs: Vec<_> = json_recs
+                .iter()
+                .map(|json_rec| json_to_py_dict(py, json_rec))
+                .collect();
+            let mock_file = MockPyFile::new();
+            let output_buf = mock_file.inner();
+            let mock_file = Py::new(py, mock_file).unwrap().into_py(py);
+            write_dbz_file(
+                py,
+                mock_file.extract(py).unwrap(),
+                None,
+                Some(DATASET.to_owned()),
+                Some(recs),
+                Some(STYPE.as_str()),
+                None,
+            )
+            .unwrap();
+            output_buf
+        });
+        let output_buf = output_buf.lock().unwrap().clone().into_inner();
+        let py_dbz = Dbz::new(Cursor::new(&output_buf)).unwrap();
+        assert_eq!(py_dbz.schema(), Schema::Trades);
+        assert_eq!(py_dbz.metadata().record_count as usize, json_recs.len());
+    }
+
+    #[test]
+    fn test_write_dbz_file_arguments_from_python() {
+        pyo3::prepare_freethreaded_python();
+        let json_recs = read_json_records(Schema::Trades);
+        Python::with_gil(|py| {
+            let recs: Vec<_> = json_recs
+                .iter()
+                .map(|json_rec| json_to_py_dict(py, json_rec))
+                .collect();
+            let locals = PyDict::new(py);
+            locals
+                .set_item(
+                    "write_dbz_file",
+                    wrap_pyfunction!(write_dbz_file, py).unwrap(),
+                )
+                .unwrap();
+            locals.set_item("records", recs).unwrap();
+            py.run("class TradeMsg: pass", None, Some(locals)).unwrap();
+            // calls `write_dbz_file` from Python with `args` and returns the schema written
+            let write = |args: &str| -> PyResult<Schema> {
+                let mock_file = MockPyFile::new();
+                let output_buf = mock_file.inner();
+                locals.set_item("file", Py::new(py, mock_file).unwrap())?;
+                py.run(&format!("write_dbz_file({args})"), None, Some(locals))?;
+                let output_buf = output_buf.lock().unwrap().clone().into_inner();
+                Ok(Dbz::new(Cursor::new(output_buf)).unwrap().schema())
+            };
+            let required =
+                "file=file, dataset=\"GLBX.MDP3\", records=records, stype=\"product_id\"";
+            assert_eq!(write(required).unwrap(), Schema::Trades);
+            assert_eq!(
+                write(&format!("{required}, record_class=TradeMsg")).unwrap(),
+                Schema::Trades
+            );
+            assert_eq!(
+                write(&format!("{required}, record_class=\"TradeMsg\"")).unwrap(),
+                Schema::Trades
+            );
+            assert_eq!(
+                write("file, \"trades\", \"GLBX.MDP3\", records, \"product_id\"").unwrap(),
+                Schema::Trades
+            );
+            let err = write(&format!(
+                "{required}, schema=\"trades\", record_class=TradeMsg"
+            ))
+            .unwrap_err();
+            assert!(err.is_instance_of::<PyValueError>(py));
+            let err = write("file=file, dataset=\"GLBX.MDP3\", records=records").unwrap_err();
+            assert!(err.is_instance_of::<PyTypeError>(py));
+            assert!(err.value(py).to_string().contains("'stype'"), "{err}");
+        });
+    }
+
+    /// Writes `records` as DBZ, converts them to dicts through JSON, and writes the
+    /// dicts with [`write_dbz_file`], returning the records decoded from the result.
+    fn round_trip_from_python<T>(schema: Schema, records: Vec<T>) -> Vec<T>
+    where
+        T: ConstTypeId + Clone,
+    {
+        pyo3::prepare_freethreaded_python();
+        let mut buffer = Cursor::new(Vec::new());
+        Metadata {
+            version: SCHEMA_VERSION,
+            dataset: DATASET.to_owned(),
+            schema,
+            start: 0,
+            end: 0,
+            limit: 0,
+            record_count: records.len() as u64,
+            compression: Compression::ZStd,
+            stype_in: STYPE,
+            stype_out: STYPE,
+            schema_definition: None,
+            symbols: vec![],
+            partial: vec![],
+            not_found: vec![],
+            mappings: vec![],
+            annotations: BTreeMap::new(),
+        }
+        .encode(&mut buffer)
+        .unwrap();
+        crate::write_dbz(&mut buffer, records.iter()).unwrap();
+        buffer.set_position(0);
+        let mut json = Vec::new();
+        Dbz::new(buffer)
+            .unwrap()
+            .write_to(
+                &mut json,
+                OutputEncoding::Json {
+                    should_pretty_print: false,
+                    should_output_array: false,
+                    should_output_iso_timestamps: false,
+                    should_output_decimal_prices: false,
+                },
+            )
+            .unwrap();
+        let json_recs = serde_json::Deserializer::from_slice(&json)
+            .into_iter()
+            .collect::<serde_json::Result<Vec<JsonObj>>>()
+            .unwrap();
+        let output_buf = Python::with_gil(|py| {
+            let recs: Vec<_> = json_recs
+                .iter()
+                .map(|json_rec| json_to_py_dict(py, json_rec))
+                .collect();
+            let mock_file = MockPyFile::new();
+            let output_buf = mock_file.inner();
+            let mock_file = Py::new(py, mock_file).unwrap().into_py(py);
+            write_dbz_file(
+                py,
+                mock_file.extract(py).unwrap(),
+                None,
+                Some(DATASET.to_owned()),
+                Some(recs),
+                Some(STYPE.as_str()),
+                None,
+            )
+            .unwrap();
+            output_buf
+        });
+        let output_buf = output_buf.lock().unwrap().clone().into_inner();
+        let dbz = Dbz::new(Cursor::new(output_buf)).unwrap();
+        assert_eq!(dbz.schema(), schema);
+        dbz.try_into_fallible_iter::<T>()
+            .unwrap()
+            .collect::<anyhow::Result<_>>()
+            .unwrap()
+    }
+
+    fn c_chars<const N: usize>(s: &str) -> [c_char; N] {
+        let mut chars = [0; N];
+        for (c, b) in chars.iter_mut().zip(s.bytes()) {
+            *c = b as c_char;
+        }
+        chars
+    }
+
+    #[test]
+    fn test_status_round_trip() {
+        let records = vec![StatusMsg {
+            hd: RecordHeader {
+                length: (mem::size_of::<StatusMsg>() / 4) as u8,
+                rtype: StatusMsg::TYPE_ID,
+                publisher_id: 1,
+                product_id: 5482,
+                ts_event: 1658441851000000000,
+            },
+            ts_recv: 1658441891000000000,
+            group: c_chars("ES"),
+            trading_status: 3,
+            halt_reason: 4,
+            trading_event: 5,
+        }];
+        assert_eq!(
+            round_trip_from_python(Schema::Status, records.clone()),
+            records
+        );
+    }
+
+    #[test]
+    fn test_definition_round_trip() {
+        let records = vec![SymDefMsg {
+            hd: RecordHeader {
+                length: (mem::size_of::<SymDefMsg>() / 4) as u8,
+                rtype: SymDefMsg::TYPE_ID,
+                publisher_id: 1,
+                product_id: 5482,
+                ts_event: 1658441851000000000,
+            },
+            ts_recv: 1658441891000000000,
+            min_price_increment: 100,
+            display_factor: 1000,
+            expiration: 1698450000000000000,
+            activation: 1697350000000000000,
+            high_limit_price: 1_000_000,
+            low_limit_price: -1_000_000,
+            max_price_variation: 0,
+            trading_reference_price: 500_000,
+            unit_of_measure_qty: 5,
+            min_price_increment_amount: 5,
+            price_ratio: 10,
+            inst_attrib_value: 10,
+            underlying_id: 256785,
+            cleared_volume: 0,
+            market_depth_implied: 0,
+            market_depth: 13,
+            market_segment_id: 0,
+            max_trade_vol: 10_000,
+            min_lot_size: 1,
+            min_lot_size_block: 1000,
+            min_lot_size_round_lot: 100,
+            min_trade_vol: 1,
+            open_interest_qty: 0,
+            contract_multiplier: 0,
+            decay_quantity: 0,
+            original_contract_size: 0,
+            related_security_id: 0,
+            trading_reference_date: 0,
+            appl_id: 0,
+            maturity_month_year: 0,
+            decay_start_date: 0,
+            chan: 4,
+            currency: c_chars("USD"),
+            settl_currency: c_chars("USD"),
+            secsubtype: [0; 6],
+            symbol: c_chars("ESH1"),
+            group: c_chars("ES"),
+            exchange: c_chars("XCME"),
+            asset: c_chars("ES"),
+            cfi: c_chars("FFIXSX"),
+            security_type: c_chars("FUT"),
+            unit_of_measure: c_chars("IPNT"),
+            underlying: [0; 21],
+            related: [0; 21],
+            match_algorithm: 'F' as c_char,
+            md_security_trading_status: 2,
+            main_fraction: 4,
+            price_display_format: 8,
+            settl_price_type: 9,
+            sub_fraction: 23,
+            underlying_product: 10,
+            security_update_action: SecurityUpdateAction::Add,
+            maturity_month_month: 8,
+            maturity_month_day: 9,
+            maturity_month_week: 11,
+            user_defined_instrument: 'N' as c_char,
+            contract_multiplier_unit: 0,
+            flow_schedule_type: 5,
+            tick_rule: 0,
+            _dummy: [0; 3],
+        }];
+        assert_eq!(
+            round_trip_from_python(Schema::Definition, records.clone()),
+            records
+        );
+    }
+
+    #[test]
+    fn test_filter_product_ids() {
+        pyo3::prepare_freethreaded_python();
+        let input = std::fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        let (record_count, output_buf) = Python::with_gil(|py| {
+            let mock_file = MockPyFile::new();
+            let output_buf = mock_file.inner();
+            let mock_file = Py::new(py, mock_file).unwrap().into_py(py);
+            let record_count = filter_product_ids(
+                py,
+                mock_file.extract(py).unwrap(),
+                PyBytes::new(py, &input),
+                vec![1],
+            )
+            .unwrap();
+            (record_count, output_buf)
+        });
+        assert_eq!(record_count, 0);
+        let output_buf = output_buf.lock().unwrap().clone().into_inner();
+        let dbz = Dbz::new(Cursor::new(&output_buf)).unwrap();
+        assert_eq!(dbz.schema(), Schema::Mbo);
+        assert_eq!(dbz.metadata().record_count, 0);
+    }
+
+    #[test]
+    fn test_get_range() {
+        pyo3::prepare_freethreaded_python();
+        const START: u64 = 1609160400000000000;
+        const END: u64 = 1609200000000000000;
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let paths: Vec<_> = ["a.dbz", "b.dbz"]
+            .into_iter()
+            .map(|file_name| {
+                let path = dir.join(file_name);
+                write_trades_with_range(&path, START, END);
+                path
+            })
+            .collect();
+        let manifest_path = dir.join("manifest.json");
+        Manifest::build(dir, &paths)
+            .unwrap()
+            .write_json(std::fs::File::create(&manifest_path).unwrap())
+            .unwrap();
+        let (record_count, output_buf) = Python::with_gil(|py| {
+            let mock_file = MockPyFile::new();
+            let output_buf = mock_file.inner();
+            let mock_file = Py::new(py, mock_file).unwrap().into_py(py);
+            let record_count = get_range(
+                py,
+                mock_file.extract(py).unwrap(),
+                manifest_path.to_str().unwrap(),
+                "trades",
+                START,
+                None,
+                Some(vec!["ESH1".to_owned()]),
+                Some("ts_event_sequence"),
+                None,
+            )
+            .unwrap();
+            (record_count, output_buf)
+        });
+        assert_eq!(record_count, 4);
+        let output_buf = output_buf.lock().unwrap().clone().into_inner();
+        let dbz = Dbz::new(Cursor::new(&output_buf)).unwrap();
+        assert_eq!(dbz.schema(), Schema::Trades);
+        assert_eq!(dbz.metadata().record_count, 4);
+        assert_eq!(dbz.into_record_iter().unwrap().count(), 4);
+    }
+
     macro_rules! test_writing_dbz_from_python {
         ($test_name:ident, $record_type:ident, $schema:expr) => {
             #[test]
@@ -618,6 +1875,9 @@ mod tests {
                         &mut writer,
                         OutputEncoding::Json {
                             should_pretty_print: false,
+                            should_output_array: false,
+                            should_output_iso_timestamps: false,
+                            should_output_decimal_prices: false,
                         },
                     )
                     .unwrap();
@@ -642,10 +1902,11 @@ mod tests {
                     write_dbz_file(
                         py,
                         mock_file.extract(py).unwrap(),
-                        $schema.as_str(),
-                        DATASET.to_owned(),
-                        recs,
-                        STYPE.as_str(),
+                        Some($schema.as_str()),
+                        Some(DATASET.to_owned()),
+                        Some(recs),
+                        Some(STYPE.as_str()),
+                        None,
                     )
                     .unwrap();
 
@@ -0,0 +1,798 @@
+//! Querying the records of a [`DbzDataset`] by time range and symbol.
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, BinaryHeap},
+    fs::File,
+    io::{self, BufReader, Write},
+    mem,
+    str::FromStr,
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread, vec,
+};
+
+use anyhow::anyhow;
+use databento_defs::enums::{Compression, SType, Schema};
+
+use crate::{
+    write::dbz::{new_manual_encoder, SCHEMA_VERSION},
+    DbzDataset, DbzRecordIter, Metadata, Record, SymbolMapping, WriterOptions,
+};
+
+/// The order records from different files are merged in. Different venues and
+/// datasets need different tie-breaking to reproduce the exchange's order.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum RecordOrder {
+    /// By `ts_event`.
+    #[default]
+    TsEvent,
+    /// By `ts_recv`, falling back to `ts_event` for records without one.
+    TsRecv,
+    /// By `ts_event`, then by `sequence` for records that have one.
+    TsEventSequence,
+    /// By a custom comparison.
+    Custom(fn(&Record, &Record) -> Ordering),
+}
+
+impl RecordOrder {
+    /// Compares `left` and `right` in this order.
+    pub fn compare(&self, left: &Record, right: &Record) -> Ordering {
+        match self {
+            RecordOrder::TsEvent => left.header().ts_event.cmp(&right.header().ts_event),
+            RecordOrder::TsRecv => {
+                let ts_recv = |rec: &Record| rec.ts_recv().unwrap_or(rec.header().ts_event);
+                ts_recv(left).cmp(&ts_recv(right))
+            }
+            RecordOrder::TsEventSequence => left
+                .header()
+                .ts_event
+                .cmp(&right.header().ts_event)
+                .then_with(|| left.sequence().cmp(&right.sequence())),
+            RecordOrder::Custom(compare) => compare(left, right),
+        }
+    }
+}
+
+impl FromStr for RecordOrder {
+    type Err = anyhow::Error;
+
+    /// Parses one of the built-in orders: `ts_event`, `ts_recv`, or
+    /// `ts_event_sequence`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ts_event" => Ok(RecordOrder::TsEvent),
+            "ts_recv" => Ok(RecordOrder::TsRecv),
+            "ts_event_sequence" => Ok(RecordOrder::TsEventSequence),
+            _ => Err(anyhow!(
+                "Unknown record order '{s}', expected ts_event, ts_recv, or ts_event_sequence"
+            )),
+        }
+    }
+}
+
+/// How records that compare equal in a [`RecordOrder`], e.g. records with the same
+/// `ts_event` when merging or sorting by `ts_event`, are ordered relative to each
+/// other.
+///
+/// Every transform orders ties deterministically, so the same inputs always produce
+/// the same output:
+/// - [`Dbz::write_sorted_to`](crate::Dbz::write_sorted_to) orders ties by the tie
+///   break, in memory and across the runs of an external sort alike.
+/// - [`merge`](crate::merge()) and [`DbzDataset::get_range`] order ties between files
+///   by the tie break, then by the order of the files, and keep the order of ties
+///   within a file.
+/// - Filters like [`Dbz::filter_range`](crate::Dbz::filter_range) never reorder
+///   records.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TieBreak {
+    /// Keep the order of the input: ties within a file keep their order in the
+    /// file, and ties between files are ordered by the order of the files.
+    #[default]
+    InputOrder,
+    /// By `product_id`, then by the encoded bytes of the record, so the output
+    /// doesn't depend on the order of the input records or files. When merging,
+    /// the ties within each file are expected to already be in this order, e.g. by
+    /// sorting each file with this tie break.
+    Canonical,
+}
+
+impl TieBreak {
+    /// Compares `left` and `right` when they compare equal in a [`RecordOrder`].
+    /// Returns [`Ordering::Equal`] for [`TieBreak::InputOrder`], leaving their order
+    /// to the position in the input.
+    pub fn compare(&self, left: &Record, right: &Record) -> Ordering {
+        match self {
+            TieBreak::InputOrder => Ordering::Equal,
+            TieBreak::Canonical => left
+                .header()
+                .product_id
+                .cmp(&right.header().product_id)
+                .then_with(|| left.as_bytes().cmp(right.as_bytes())),
+        }
+    }
+}
+
+impl FromStr for TieBreak {
+    type Err = anyhow::Error;
+
+    /// Parses `input` or `canonical`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "input" => Ok(TieBreak::InputOrder),
+            "canonical" => Ok(TieBreak::Canonical),
+            _ => Err(anyhow!(
+                "Unknown tie break '{s}', expected input or canonical"
+            )),
+        }
+    }
+}
+
+/// The most records decoded ahead for each file.
+pub(crate) const MAX_BATCH_SIZE: usize = 1024;
+/// An estimate of the memory used to read each file apart from its decoded records:
+/// the zstd decoding window and block buffer and the file's read buffer.
+const SOURCE_OVERHEAD: usize = (2 << 20) + (128 << 10) + (8 << 10);
+
+/// Options for [`DbzDataset::get_range_with_options`].
+#[derive(Clone, Debug, Default)]
+pub struct RangeOptions {
+    /// The order records from different files are merged in.
+    pub order: RecordOrder,
+    /// How records that compare equal in `order` are ordered.
+    pub tie_break: TieBreak,
+    /// An upper bound in bytes on the memory used for merging, or `None` for no
+    /// limit. Each file needs an estimated 2.1 MiB for decoding plus room for at
+    /// least two records: the batch being merged and the batch being decoded. Smaller
+    /// limits reduce the number of records decoded ahead for each file.
+    pub max_memory: Option<usize>,
+}
+
+impl RangeOptions {
+    /// Returns the number of records to decode ahead for each of `source_count`
+    /// files.
+    fn batch_size(&self, source_count: usize) -> anyhow::Result<usize> {
+        let (Some(max_memory), true) = (self.max_memory, source_count > 0) else {
+            return Ok(MAX_BATCH_SIZE);
+        };
+        // each file holds up to two batches: the one being merged and the one being
+        // decoded
+        let batch_size = (max_memory / source_count).saturating_sub(SOURCE_OVERHEAD)
+            / (2 * mem::size_of::<Record>());
+        if batch_size == 0 {
+            return Err(anyhow!(
+                "A max memory of {max_memory} bytes is too small to merge {source_count} files, which needs at least {} bytes",
+                source_count * (SOURCE_OVERHEAD + 2 * mem::size_of::<Record>())
+            ));
+        }
+        Ok(batch_size.min(MAX_BATCH_SIZE))
+    }
+}
+
+impl DbzDataset {
+    /// Returns the records of `schema` with a `ts_event` from `start` up to but
+    /// excluding `end` and a native symbol in `symbols`, merged across the files of
+    /// the dataset in `ts_event` order. Pass an empty `symbols` to return the records
+    /// of all symbols. Like the historical API's `timeseries.get_range`, but over
+    /// local files.
+    ///
+    /// Only the files whose time ranges overlap the query are opened. The records of
+    /// each file are expected to be in `ts_event` order. Each file is decoded on its
+    /// own thread, a batch of records at a time, and decoding waits while the merge
+    /// catches up, so memory use is bounded by the number of files.
+    ///
+    /// # Errors
+    /// This function returns an error if `schema` isn't the schema of the dataset or
+    /// is [`Schema::Statistics`], or if any of the files can't be opened. It will also
+    /// return an error if filtering by `symbols` and a file's metadata doesn't map
+    /// product IDs.
+    pub fn get_range(
+        &self,
+        schema: Schema,
+        symbols: &[&str],
+        start: u64,
+        end: u64,
+    ) -> anyhow::Result<DbzRangeIter> {
+        self.get_range_with_options(schema, symbols, start, end, &RangeOptions::default())
+    }
+
+    /// Like [`DbzDataset::get_range`], but merges the records of different files in
+    /// `options.order`, breaking ties with `options.tie_break`, within
+    /// `options.max_memory`. The records of each file are expected to already be in
+    /// `options.order`.
+    ///
+    /// # Errors
+    /// This function returns an error for the same reasons as
+    /// [`DbzDataset::get_range`]. It will also return an error if `options.max_memory`
+    /// is too small to merge the files in the time range.
+    pub fn get_range_with_options(
+        &self,
+        schema: Schema,
+        symbols: &[&str],
+        start: u64,
+        end: u64,
+        options: &RangeOptions,
+    ) -> anyhow::Result<DbzRangeIter> {
+        if schema != self.manifest().schema {
+            return Err(anyhow!(
+                "Can't get {schema} records from a dataset of {} files",
+                self.manifest().schema
+            ));
+        }
+        let files = self.plan(start, end);
+        let batch_size = options.batch_size(files.len())?;
+        let records = files
+            .into_iter()
+            .map(|file| {
+                let dbz = self.open(file)?;
+                let dbz = if symbols.is_empty() {
+                    dbz
+                } else {
+                    dbz.filter_symbols(symbols)?
+                };
+                dbz.into_record_iter()
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        DbzRangeIter::new(
+            records,
+            options.order,
+            options.tie_break,
+            batch_size,
+            start,
+            end,
+        )
+    }
+
+    /// Writes the records [`DbzDataset::get_range_with_options`] returns to `writer`
+    /// as DBZ, with metadata describing the query and the symbol mappings of the files
+    /// it spans. Returns the number of records written.
+    ///
+    /// # Errors
+    /// This function returns an error for the same reasons as
+    /// [`DbzDataset::get_range`]. It will also return an error if there's an issue
+    /// writing to `writer`.
+    pub fn write_range_dbz(
+        &self,
+        mut writer: impl io::Write + io::Seek,
+        schema: Schema,
+        symbols: &[&str],
+        start: u64,
+        end: u64,
+        options: &RangeOptions,
+    ) -> anyhow::Result<u64> {
+        let records = self.get_range_with_options(schema, symbols, start, end, options)?;
+        let metadata = self.range_metadata(schema, symbols, start, end)?;
+        metadata.encode(&mut writer)?;
+        let mut encoder = new_manual_encoder(&mut writer, &WriterOptions::default())?;
+        let mut record_count = 0;
+        for record in records {
+            encoder.write_all(record?.as_bytes())?;
+            record_count += 1;
+        }
+        encoder.finish()?;
+        Metadata::update_encoded(
+            &mut writer,
+            metadata.start,
+            metadata.end,
+            metadata.limit,
+            record_count,
+        )?;
+        writer.flush()?;
+        Ok(record_count)
+    }
+
+    /// Returns the metadata of a query, with the mappings of the files in its time
+    /// range merged.
+    fn range_metadata(
+        &self,
+        schema: Schema,
+        symbols: &[&str],
+        start: u64,
+        end: u64,
+    ) -> anyhow::Result<Metadata> {
+        let files = self.plan(start, end);
+        let mut stypes = None;
+        let mut mappings: BTreeMap<String, SymbolMapping> = BTreeMap::new();
+        let mut annotations = BTreeMap::new();
+        for file in files.iter() {
+            let metadata = Metadata::from_file(self.path(file))?;
+            stypes.get_or_insert((metadata.stype_in, metadata.stype_out));
+            merge_annotations(&mut annotations, &metadata.annotations);
+            for mapping in metadata.mappings.iter() {
+                if symbols.is_empty() || symbols.contains(&mapping.native.as_str()) {
+                    merge_mapping(&mut mappings, mapping);
+                }
+            }
+        }
+        let (stype_in, stype_out) = stypes.unwrap_or((SType::Native, SType::ProductId));
+        let files_end = files.iter().map(|file| file.end).max().unwrap_or(start);
+        Ok(Metadata {
+            version: SCHEMA_VERSION,
+            dataset: self.manifest().dataset.clone(),
+            schema,
+            start,
+            end: end.min(files_end),
+            limit: 0,
+            record_count: 0,
+            compression: Compression::ZStd,
+            stype_in,
+            stype_out,
+            schema_definition: None,
+            symbols: symbols.iter().map(|&s| s.to_owned()).collect(),
+            partial: Vec::new(),
+            not_found: Vec::new(),
+            mappings: mappings.into_values().collect(),
+            annotations,
+        })
+    }
+}
+
+/// Adds the annotations in `other` to `annotations`, keeping the existing value of
+/// any key in both.
+pub(crate) fn merge_annotations(
+    annotations: &mut BTreeMap<String, String>,
+    other: &BTreeMap<String, String>,
+) {
+    for (key, value) in other {
+        annotations
+            .entry(key.clone())
+            .or_insert_with(|| value.clone());
+    }
+}
+
+/// Adds the intervals of `mapping` to the mapping of the same native symbol in
+/// `mappings`, skipping duplicates.
+pub(crate) fn merge_mapping(
+    mappings: &mut BTreeMap<String, SymbolMapping>,
+    mapping: &SymbolMapping,
+) {
+    let merged = mappings
+        .entry(mapping.native.clone())
+        .or_insert_with(|| SymbolMapping {
+            native: mapping.native.clone(),
+            intervals: Vec::new(),
+        });
+    for interval in mapping.intervals.iter() {
+        if !merged.intervals.contains(interval) {
+            merged.intervals.push(interval.clone());
+        }
+    }
+}
+
+/// An iterator over the records of a [`DbzDataset`] in a time range, merged across
+/// files in a [`RecordOrder`]. Ties are broken by a [`TieBreak`], then by the order of
+/// the files in the manifest. This struct is created by the [`DbzDataset::get_range`] and
+/// [`DbzDataset::get_range_with_options`] methods.
+pub struct DbzRangeIter {
+    sources: Vec<RangeSource>,
+    /// The next matching record of each source.
+    heap: BinaryHeap<Reverse<Head>>,
+    order: RecordOrder,
+    tie_break: TieBreak,
+    /// An error reading the next record of a source, returned after the current head.
+    error: Option<anyhow::Error>,
+}
+
+/// The next record of a source, ordered by [`RecordOrder`], then by [`TieBreak`], and
+/// then by source.
+struct Head {
+    record: Record,
+    source: usize,
+    order: RecordOrder,
+    tie_break: TieBreak,
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.order
+            .compare(&self.record, &other.record)
+            .then_with(|| self.tie_break.compare(&self.record, &other.record))
+            .then(self.source.cmp(&other.source))
+    }
+}
+
+/// The matching records of a file, decoded on another thread.
+struct RangeSource {
+    /// The rest of the batch being merged.
+    batch: vec::IntoIter<Record>,
+    receiver: Receiver<anyhow::Result<Vec<Record>>>,
+}
+
+/// Sends the records of `records` with a `ts_event` from `start` up to but excluding
+/// `end` to `sender` in batches of `batch_size`, followed by the error that ended
+/// decoding, if any.
+fn decode_batches(
+    records: DbzRecordIter<BufReader<File>>,
+    sender: SyncSender<anyhow::Result<Vec<Record>>>,
+    batch_size: usize,
+    start: u64,
+    end: u64,
+) {
+    let mut batch = Vec::with_capacity(batch_size);
+    for record in records {
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => {
+                // an error after the last send means the merge was dropped
+                let _ = sender.send(Ok(batch)).and_then(|_| sender.send(Err(error)));
+                return;
+            }
+        };
+        let ts_event = record.header().ts_event;
+        if ts_event < start {
+            continue;
+        }
+        if ts_event >= end {
+            // the rest of the file is also past the end
+            break;
+        }
+        batch.push(record);
+        if batch.len() == batch_size {
+            let batch = mem::replace(&mut batch, Vec::with_capacity(batch_size));
+            if sender.send(Ok(batch)).is_err() {
+                // the merge was dropped
+                return;
+            }
+        }
+    }
+    if !batch.is_empty() {
+        let _ = sender.send(Ok(batch));
+    }
+}
+
+impl DbzRangeIter {
+    /// Merges the records of `records` with a `ts_event` from `start` up to but
+    /// excluding `end` in `order`, breaking ties with `tie_break`, decoding each on its
+    /// own thread `batch_size` records at a time.
+    pub(crate) fn new(
+        records: Vec<DbzRecordIter<BufReader<File>>>,
+        order: RecordOrder,
+        tie_break: TieBreak,
+        batch_size: usize,
+        start: u64,
+        end: u64,
+    ) -> anyhow::Result<Self> {
+        let sources = records
+            .into_iter()
+            .map(|records| {
+                // a rendezvous channel, so a file holds at most the batch being merged
+                // and the batch waiting to be sent
+                let (sender, receiver) = mpsc::sync_channel(0);
+                thread::spawn(move || decode_batches(records, sender, batch_size, start, end));
+                RangeSource {
+                    batch: Vec::new().into_iter(),
+                    receiver,
+                }
+            })
+            .collect();
+        let mut res = DbzRangeIter {
+            sources,
+            heap: BinaryHeap::new(),
+            order,
+            tie_break,
+            error: None,
+        };
+        for i in 0..res.sources.len() {
+            res.fill(i)?;
+        }
+        Ok(res)
+    }
+
+    /// Reads the next matching record of source `i` into its head, if there is one.
+    fn fill(&mut self, i: usize) -> anyhow::Result<()> {
+        let source = &mut self.sources[i];
+        loop {
+            if let Some(record) = source.batch.next() {
+                self.heap.push(Reverse(Head {
+                    record,
+                    source: i,
+                    order: self.order,
+                    tie_break: self.tie_break,
+                }));
+                return Ok(());
+            }
+            match source.receiver.recv() {
+                Ok(batch) => source.batch = batch?.into_iter(),
+                // the decoding thread is done
+                Err(_) => return Ok(()),
+            }
+        }
+    }
+}
+
+impl Iterator for DbzRangeIter {
+    type Item = anyhow::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.error.take() {
+            // stop after an error
+            self.heap.clear();
+            return Some(Err(error));
+        }
+        let Reverse(head) = self.heap.pop()?;
+        if let Err(error) = self.fill(head.source) {
+            self.error = Some(error);
+        }
+        Some(Ok(head.record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+    use crate::{manifest::tests::write_trades_with_range, Dbz, Manifest};
+
+    const START: u64 = 1609160400000000000;
+    const END: u64 = 1609200000000000000;
+    /// The `ts_event` of the first record of the trades test data.
+    const FIRST_TS_EVENT: u64 = 1609160400098821953;
+    /// The `ts_event` of the second record of the trades test data.
+    const SECOND_TS_EVENT: u64 = 1609160400107665963;
+
+    /// Returns a dataset of two files of trades in a temporary directory, which is
+    /// removed when the returned [`TempDir`] is dropped.
+    fn dataset() -> (DbzDataset, TempDir) {
+        let dir = tempdir().unwrap();
+        let paths: Vec<_> = ["a.dbz", "b.dbz"]
+            .into_iter()
+            .map(|file_name| {
+                let path = dir.path().join(file_name);
+                write_trades_with_range(&path, START, END);
+                path
+            })
+            .collect();
+        let manifest = Manifest::build(dir.path(), &paths).unwrap();
+        (DbzDataset::new(manifest, dir.path().to_owned()), dir)
+    }
+
+    fn ts_events(iter: DbzRangeIter) -> Vec<u64> {
+        iter.map(|record| record.unwrap().header().ts_event)
+            .collect()
+    }
+
+    #[test]
+    fn test_get_range_merges_files() {
+        let (target, _dir) = dataset();
+        let merged = ts_events(target.get_range(Schema::Trades, &[], START, END).unwrap());
+        assert_eq!(merged.len(), 4);
+        assert!(merged.windows(2).all(|w| w[0] <= w[1]), "{merged:?}");
+        assert_eq!(merged[2], SECOND_TS_EVENT);
+    }
+
+    #[test]
+    fn test_get_range_filters_time() {
+        let (target, _dir) = dataset();
+        let after = ts_events(
+            target
+                .get_range(Schema::Trades, &[], SECOND_TS_EVENT, END)
+                .unwrap(),
+        );
+        assert_eq!(after, vec![SECOND_TS_EVENT; 2]);
+        let before = ts_events(
+            target
+                .get_range(Schema::Trades, &[], START, SECOND_TS_EVENT)
+                .unwrap(),
+        );
+        assert_eq!(before.len(), 2);
+        assert!(before.iter().all(|&ts| ts < SECOND_TS_EVENT));
+    }
+
+    #[test]
+    fn test_get_range_filters_symbols() {
+        let (target, _dir) = dataset();
+        let iter = target
+            .get_range(Schema::Trades, &["ESH1"], START, END)
+            .unwrap();
+        assert_eq!(iter.count(), 4);
+        let iter = target
+            .get_range(Schema::Trades, &["NQH1"], START, END)
+            .unwrap();
+        assert_eq!(iter.count(), 0);
+    }
+
+    #[test]
+    fn test_write_range_dbz() {
+        let (target, _dir) = dataset();
+        let mut buffer = io::Cursor::new(Vec::new());
+        let record_count = target
+            .write_range_dbz(
+                &mut buffer,
+                Schema::Trades,
+                &["ESH1"],
+                START,
+                u64::MAX,
+                &RangeOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(record_count, 4);
+        buffer.set_position(0);
+        let dbz = Dbz::new(buffer).unwrap();
+        let metadata = dbz.metadata();
+        assert_eq!(metadata.record_count, 4);
+        assert_eq!((metadata.start, metadata.end), (START, END));
+        assert_eq!(metadata.symbols, vec!["ESH1"]);
+        assert_eq!(metadata.mappings.len(), 1);
+        assert_eq!(metadata.mappings[0].intervals.len(), 1);
+        let records = dbz
+            .into_record_iter()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        let expected = target
+            .get_range(Schema::Trades, &[], START, END)
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(records, expected);
+    }
+
+    #[test]
+    fn test_get_range_with_order() {
+        let (target, _dir) = dataset();
+        let by_ts_recv: Vec<_> = target
+            .get_range_with_options(
+                Schema::Trades,
+                &[],
+                START,
+                END,
+                &RangeOptions {
+                    order: RecordOrder::TsRecv,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .map(|record| record.unwrap().ts_recv().unwrap())
+            .collect();
+        assert_eq!(by_ts_recv.len(), 4);
+        assert!(
+            by_ts_recv.windows(2).all(|w| w[0] <= w[1]),
+            "{by_ts_recv:?}"
+        );
+        // the order is applied to the next record of each file, so with an order the
+        // files aren't in, each file's records stay together
+        fn reversed(left: &Record, right: &Record) -> Ordering {
+            right.header().ts_event.cmp(&left.header().ts_event)
+        }
+        let merged = ts_events(
+            target
+                .get_range_with_options(
+                    Schema::Trades,
+                    &[],
+                    START,
+                    END,
+                    &RangeOptions {
+                        order: RecordOrder::Custom(reversed),
+                        ..Default::default()
+                    },
+                )
+                .unwrap(),
+        );
+        assert_eq!(
+            merged,
+            vec![
+                FIRST_TS_EVENT,
+                SECOND_TS_EVENT,
+                FIRST_TS_EVENT,
+                SECOND_TS_EVENT
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_order_sequence() {
+        let records: Vec<_> = Dbz::from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../tests/data/test_data.trades.dbz"
+        ))
+        .unwrap()
+        .into_record_iter()
+        .unwrap()
+        .collect::<anyhow::Result<_>>()
+        .unwrap();
+        let mut tie = records[0].clone();
+        if let Record::Trades(rec) = &mut tie {
+            rec.sequence += 1;
+        }
+        assert_eq!(
+            RecordOrder::TsEvent.compare(&records[0], &tie),
+            Ordering::Equal
+        );
+        assert_eq!(
+            RecordOrder::TsEventSequence.compare(&records[0], &tie),
+            Ordering::Less
+        );
+        assert!("ts_event_sequence".parse::<RecordOrder>().is_ok());
+        assert!("sequence".parse::<RecordOrder>().is_err());
+    }
+
+    #[test]
+    fn test_tie_break() {
+        let records: Vec<_> = Dbz::from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../tests/data/test_data.trades.dbz"
+        ))
+        .unwrap()
+        .into_record_iter()
+        .unwrap()
+        .collect::<anyhow::Result<_>>()
+        .unwrap();
+        let mut tie = records[0].clone();
+        if let Record::Trades(rec) = &mut tie {
+            rec.hd.product_id -= 1;
+            rec.sequence += 1;
+        }
+        assert_eq!(
+            TieBreak::InputOrder.compare(&records[0], &tie),
+            Ordering::Equal
+        );
+        assert_eq!(
+            TieBreak::Canonical.compare(&records[0], &tie),
+            Ordering::Greater
+        );
+        assert_eq!(
+            TieBreak::Canonical.compare(&records[0], &records[0]),
+            Ordering::Equal
+        );
+        assert_eq!(
+            "canonical".parse::<TieBreak>().unwrap(),
+            TieBreak::Canonical
+        );
+        assert!("stable".parse::<TieBreak>().is_err());
+    }
+
+    #[test]
+    fn test_get_range_max_memory() {
+        let (target, _dir) = dataset();
+        let expected = ts_events(target.get_range(Schema::Trades, &[], START, END).unwrap());
+        // room for batches of a single record
+        let max_memory = 2 * (SOURCE_OVERHEAD + 2 * mem::size_of::<Record>());
+        let options = RangeOptions {
+            max_memory: Some(max_memory),
+            ..Default::default()
+        };
+        assert_eq!(options.batch_size(2).unwrap(), 1);
+        let merged = ts_events(
+            target
+                .get_range_with_options(Schema::Trades, &[], START, END, &options)
+                .unwrap(),
+        );
+        assert_eq!(merged, expected);
+        let options = RangeOptions {
+            max_memory: Some(max_memory - 1),
+            ..Default::default()
+        };
+        assert!(target
+            .get_range_with_options(Schema::Trades, &[], START, END, &options)
+            .is_err());
+    }
+
+    #[test]
+    fn test_get_range_dropped_early() {
+        let (target, _dir) = dataset();
+        let mut iter = target.get_range(Schema::Trades, &[], START, END).unwrap();
+        assert!(iter.next().is_some());
+        // dropping the iterator stops the decoding threads instead of blocking
+        drop(iter);
+    }
+
+    #[test]
+    fn test_get_range_wrong_schema() {
+        let (target, _dir) = dataset();
+        let res = target.get_range(Schema::Mbo, &[], START, END);
+        assert!(res.is_err());
+    }
+}
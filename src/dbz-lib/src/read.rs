@@ -1,9 +1,12 @@
 use std::{
+    collections::{BTreeMap, HashSet},
     fs::File,
     io::{self, BufReader, Read},
     marker::PhantomData,
     mem,
+    ops::Range,
     path::Path,
+    sync::Arc,
 };
 
 use anyhow::{anyhow, Context};
@@ -17,13 +20,24 @@ use databento_defs::{
     record::{transmute_record_bytes, ConstTypeId},
 };
 
-use crate::write::dbz::SCHEMA_VERSION;
+#[cfg(feature = "rayon")]
+use crate::DbzParIter;
+use crate::{write::dbz::SCHEMA_VERSION, Error, FieldDefinition, SchemaDefinition, SymbolResolver};
 
 /// Object for reading, parsing, and serializing a Databento Binary Encoding (DBZ) file.
 #[derive(Debug)]
 pub struct Dbz<R: io::BufRead> {
     reader: R,
     metadata: Metadata,
+    filter: RecordFilter,
+    size_hint_policy: SizeHintPolicy,
+    unknown_rtype_policy: UnknownRtypePolicy,
+    dictionary: Option<Arc<[u8]>>,
+    /// The number of decompressed bytes to skip before the first record.
+    skip_bytes: u64,
+    /// The number of records before the position of `reader`, e.g. in the Zstd
+    /// frames before the one it starts at.
+    records_before: u64,
 }
 
 /// Information about the data contained in a DBZ file.
@@ -49,6 +63,10 @@ pub struct Metadata {
     pub stype_in: SType,
     /// The output symbology type to map to.
     pub stype_out: SType,
+    /// The layout of the records, which makes the file self-describing so readers
+    /// can decode records of types they don't know. Most files don't have one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema_definition: Option<SchemaDefinition>,
     /// The original query input symbols from the request.
     pub symbols: Vec<String>,
     /// Symbols that did not resolve for _at least one day_ in the query time range.
@@ -57,6 +75,11 @@ pub struct Metadata {
     pub not_found: Vec<String>,
     /// Symbol mappings containing a native symbol and its mapping intervals.
     pub mappings: Vec<SymbolMapping>,
+    /// Free-form annotations about the provenance of the data, e.g. `source`,
+    /// `recorder-version`, or `notes`. They're encoded after the mappings, so readers
+    /// unaware of them ignore them, and files written before them have none.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
 }
 
 /// A native symbol and its symbol mappings for different time ranges within the query range.
@@ -101,15 +124,21 @@ impl Dbz<BufReader<File>> {
     /// This function will return an error if `path` doesn't exist. It will also return an error
     /// if it is unable to parse the metadata from the file.
     pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
-        let file = File::open(path.as_ref()).with_context(|| {
-            format!(
-                "Error opening dbz file at path '{}'",
-                path.as_ref().display()
-            )
-        })?;
+        let file = Error::open(path.as_ref())?;
         let reader = BufReader::new(file);
         Self::new(reader)
     }
+
+    /// Creates a new [`Dbz`] from the file at `path` like [`Dbz::from_file`], but also
+    /// accepts metadata from newer versions of DBZ, see [`Dbz::new_compatible`].
+    ///
+    /// # Errors
+    /// This function will return an error if `path` doesn't exist. It will also return an error
+    /// if it is unable to parse the metadata from the file.
+    pub fn from_file_compatible(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = Error::open(path.as_ref())?;
+        Self::new_compatible(BufReader::new(file))
+    }
 }
 
 // `BufRead` instead of `Read` because the [zstd::Decoder] works with `BufRead` so accepting
@@ -121,7 +150,75 @@ impl<R: io::BufRead> Dbz<R> {
     /// This function will return an error if it is unable to parse the metadata in `reader`.
     pub fn new(mut reader: R) -> anyhow::Result<Self> {
         let metadata = Metadata::read(&mut reader)?;
-        Ok(Self { reader, metadata })
+        Ok(Self::from_parts(reader, metadata))
+    }
+
+    /// Reads only the metadata from `reader`, leaving it positioned at the start of the
+    /// records, e.g. for indexing many files without constructing a [`Dbz`] for each.
+    /// No more than the metadata is read from `reader`.
+    ///
+    /// # Errors
+    /// This function will return an error if it is unable to parse the metadata in
+    /// `reader`.
+    pub fn peek_metadata(reader: &mut R) -> anyhow::Result<Metadata> {
+        Metadata::read(reader)
+    }
+
+    /// Creates a new [`Dbz`] from `reader` like [`Dbz::new`], but also accepts
+    /// metadata from newer versions of DBZ than this version of dbz, see
+    /// [`Metadata::from_file_compatible`]. Records are still decoded as the version
+    /// this version of dbz writes, so check [`Metadata::version`] before relying on
+    /// them.
+    ///
+    /// # Errors
+    /// This function will return an error if it is unable to parse the metadata in
+    /// `reader`.
+    pub fn new_compatible(mut reader: R) -> anyhow::Result<Self> {
+        let metadata = Metadata::read_compatible(&mut reader)?;
+        Ok(Self::from_parts(reader, metadata))
+    }
+
+    /// Creates a [`Dbz`] from `reader` positioned at the start of the records and the
+    /// `metadata` previously read from it.
+    pub(crate) fn from_parts(reader: R, metadata: Metadata) -> Self {
+        Self {
+            reader,
+            metadata,
+            filter: RecordFilter::default(),
+            size_hint_policy: SizeHintPolicy::default(),
+            unknown_rtype_policy: UnknownRtypePolicy::default(),
+            dictionary: None,
+            skip_bytes: 0,
+            records_before: 0,
+        }
+    }
+
+    /// Replaces the reader of the records with `reader`, keeping the metadata and
+    /// options, and returns the original, e.g. to read it on another thread.
+    pub(crate) fn replace_reader<R2: io::BufRead>(self, reader: R2) -> (Dbz<R2>, R) {
+        let dbz = Dbz {
+            reader,
+            metadata: self.metadata,
+            filter: self.filter,
+            size_hint_policy: self.size_hint_policy,
+            unknown_rtype_policy: self.unknown_rtype_policy,
+            dictionary: self.dictionary,
+            skip_bytes: self.skip_bytes,
+            records_before: self.records_before,
+        };
+        (dbz, self.reader)
+    }
+
+    /// Creates a [`Dbz`] from `reader` containing only a Zstd-compressed stream of
+    /// records of `schema` without the metadata that normally precedes it, e.g. to
+    /// recover the records of a partially written file where only the body survived.
+    ///
+    /// The metadata is filled in with placeholders: an empty dataset, a zero time
+    /// range and record count, and no symbology. Records are read until the data
+    /// ends, see [`SizeHintPolicy::Ignore`].
+    pub fn from_raw_body(reader: R, schema: Schema) -> Self {
+        let metadata = Metadata::placeholder(schema);
+        Self::from_parts(reader, metadata).with_size_hint_policy(SizeHintPolicy::Ignore)
     }
 
     /// Returns the [`Schema`] of the DBZ data. The schema also indicates the record type `T` for
@@ -142,7 +239,332 @@ impl<R: io::BufRead> Dbz<R> {
     /// This function will return an error if the zstd portion of the DBZ file
     /// was compressed in an unexpected manner.
     pub fn try_into_iter<T: ConstTypeId>(self) -> anyhow::Result<DbzStreamIter<R, T>> {
-        DbzStreamIter::new(self.reader, self.metadata)
+        let mut iter = DbzStreamIter::new(self.reader, self.metadata, self.dictionary.as_deref())?
+            .with_filter(self.filter)
+            .with_size_hint_policy(self.size_hint_policy)
+            .with_unknown_rtype_policy(self.unknown_rtype_policy);
+        iter.i = self.records_before as usize;
+        iter.bytes_before = self.records_before * mem::size_of::<T>() as u64;
+        iter.skip_bytes(self.skip_bytes)?;
+        Ok(iter)
+    }
+
+    /// Skips the first `skip_bytes` of the decompressed records when decoding, e.g. to
+    /// start at a record found in a [`RecordIndex`](crate::RecordIndex).
+    pub(crate) fn with_skip_bytes(mut self, skip_bytes: u64) -> Self {
+        self.skip_bytes = skip_bytes;
+        self
+    }
+
+    /// Sets the number of records before the position of the reader, which
+    /// iteration counts as decoded, e.g. after seeking to a later Zstd frame.
+    pub(crate) fn with_records_before(mut self, records_before: u64) -> Self {
+        self.records_before = records_before;
+        self
+    }
+
+    /// Returns the Zstd dictionary the records were compressed with, if set.
+    pub(crate) fn dictionary(&self) -> Option<&[u8]> {
+        self.dictionary.as_deref()
+    }
+
+    /// Sets the Zstd dictionary the records were compressed with, see
+    /// [`WriterOptions::dictionary`](crate::WriterOptions::dictionary). Decoding
+    /// records compressed with a dictionary fails without it.
+    pub fn with_dictionary(mut self, dictionary: impl Into<Arc<[u8]>>) -> Self {
+        self.dictionary = Some(dictionary.into());
+        self
+    }
+
+    /// Sets how far decoding trusts the `record_count` in the metadata. Applies to all
+    /// the ways of iterating and writing the records. See
+    /// [`DbzStreamIter::with_size_hint_policy`].
+    pub fn with_size_hint_policy(mut self, size_hint_policy: SizeHintPolicy) -> Self {
+        self.size_hint_policy = size_hint_policy;
+        self
+    }
+
+    /// Sets what decoding does with records whose rtype doesn't match the schema.
+    /// Applies to all the ways of iterating and writing the records except
+    /// `Dbz::par_iter` of the `rayon` feature, which always returns an error. See
+    /// [`DbzStreamIter::with_unknown_rtype_policy`].
+    pub fn with_unknown_rtype_policy(mut self, unknown_rtype_policy: UnknownRtypePolicy) -> Self {
+        self.unknown_rtype_policy = unknown_rtype_policy;
+        self
+    }
+
+    /// Skips the records with a `ts_event` before `start` or at or after `end` while
+    /// decoding, so a time window can be extracted from a large file without
+    /// converting and discarding the other records. Applies to all the ways of
+    /// iterating and writing the records.
+    ///
+    /// The metadata is left unchanged.
+    pub fn filter_range(mut self, start: u64, end: u64) -> Self {
+        self.filter.ts_event_range = Some(start..end);
+        self
+    }
+
+    /// Skips the records whose `product_id` isn't in `product_ids` while decoding, so
+    /// the records of some instruments can be extracted from a file of many. Like
+    /// [`Dbz::filter_range`], it applies to all the ways of iterating and writing the
+    /// records and can be combined with it.
+    ///
+    /// The metadata is left unchanged.
+    pub fn filter_product_ids(mut self, product_ids: impl IntoIterator<Item = u32>) -> Self {
+        self.filter.product_ids = Some(product_ids.into_iter().collect());
+        self
+    }
+
+    /// Skips the records that aren't of one of the native `symbols` while decoding.
+    /// Symbols are resolved to product IDs with the metadata's mappings on the date of
+    /// each record's `ts_event`, so a product ID that's reused for another symbol is
+    /// only kept while it maps to one of `symbols`. Symbols without mappings match no
+    /// records. Like [`Dbz::filter_range`], it applies to all the ways of iterating
+    /// and writing the records and can be combined with the other filters.
+    ///
+    /// The metadata is left unchanged.
+    ///
+    /// # Errors
+    /// This function returns an error if the metadata's mappings aren't to product IDs.
+    pub fn filter_symbols(mut self, symbols: &[&str]) -> anyhow::Result<Self> {
+        let mut metadata = self.metadata.clone();
+        metadata
+            .mappings
+            .retain(|mapping| symbols.contains(&mapping.native.as_str()));
+        self.filter.symbol_resolver = Some(SymbolResolver::from_metadata(&metadata)?);
+        Ok(self)
+    }
+
+    /// Skips the first `n` records while decoding, counting only records kept by the
+    /// other filters, e.g. to page through a file. Like [`Dbz::filter_range`], it
+    /// applies to all the ways of iterating and writing the records.
+    ///
+    /// The metadata is left unchanged.
+    pub fn skip_records(mut self, n: u64) -> Self {
+        self.filter.skip = n;
+        self
+    }
+
+    /// Stops decoding after `n` records, not counting records dropped by the other
+    /// filters or skipped with [`Dbz::skip_records`]. The rest of the data isn't
+    /// decompressed, so the first records of a large file can be read quickly. Like
+    /// [`Dbz::filter_range`], it applies to all the ways of iterating and writing the
+    /// records.
+    ///
+    /// The metadata is left unchanged.
+    pub fn take_records(mut self, n: u64) -> Self {
+        self.filter.limit = Some(n);
+        self
+    }
+
+    /// Try to decode the DBZ file into an iterator of owned records that returns an
+    /// error instead of ending early when the data is corrupt or truncated. See
+    /// [`DbzStreamIter::into_fallible`].
+    ///
+    /// # Errors
+    /// This function will return an error if the zstd portion of the DBZ file
+    /// was compressed in an unexpected manner.
+    pub fn try_into_fallible_iter<T: ConstTypeId + Clone>(
+        self,
+    ) -> anyhow::Result<DbzFallibleIter<R, T>> {
+        Ok(self.try_into_iter()?.into_fallible())
+    }
+
+    /// Try to decode the DBZ file into an iterator of [`Record`](crate::Record)s like
+    /// [`Dbz::into_record_iter`](crate::Dbz::into_record_iter), decompressing batches
+    /// of its Zstd frames on the threads of the rayon thread pool. The records are
+    /// returned in the same order. Records are read until the data ends.
+    ///
+    /// Only files whose records are split across several frames are decoded faster.
+    /// Each batch of frames is held in memory while it's decompressed, so a file with
+    /// a single large frame is better read with `into_record_iter`.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`].
+    #[cfg(feature = "rayon")]
+    pub fn par_iter(self) -> anyhow::Result<DbzParIter<R>> {
+        DbzParIter::new(
+            self.reader,
+            self.metadata,
+            self.filter,
+            self.dictionary,
+            self.skip_bytes,
+        )
+    }
+}
+
+/// How far a [`DbzStreamIter`] trusts the `record_count` in the [`Metadata`], which may
+/// be wrong, e.g. for a file that was truncated or written by a buggy producer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SizeHintPolicy {
+    /// Stop after `record_count` records and use it as the exact size hint.
+    #[default]
+    Trust,
+    /// Read records until the data ends and make no claims about the number of
+    /// remaining records.
+    Ignore,
+    /// Read records until the data ends. The size hint is based on `record_count`,
+    /// but never exceeds the number of records the zstd frame declares it contains, if
+    /// it declares its content size.
+    ClampToRemainingBytes,
+}
+
+/// What a [`DbzStreamIter`] does with a record whose rtype doesn't match the schema,
+/// e.g. one of a record type added after this version that's interleaved with the
+/// known ones.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownRtypePolicy {
+    /// End iteration with an error, see [`DbzStreamIter::error`].
+    #[default]
+    Error,
+    /// Skip the record and continue with the next one, which is found with the length
+    /// in the skipped record's header.
+    Skip,
+    /// End iteration at the record without an error, as if the data ended there.
+    Stop,
+}
+
+/// Counters of the work done decoding the records of a [`Dbz`], returned by
+/// [`DbzStreamIter::decode_stats`], e.g. for monitoring the health of decoding each
+/// file in a long-running service.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DecodeStats {
+    /// The number of bytes of compressed records read, not including the metadata.
+    pub compressed_bytes: u64,
+    /// The number of bytes of records decompressed, including skipped records.
+    pub decompressed_bytes: u64,
+    /// The number of records decoded, including those dropped by filters but not
+    /// those skipped without decoding, e.g. when seeking.
+    pub record_count: u64,
+    /// The number of decoded records dropped by filters.
+    pub filtered_count: u64,
+    /// The number of records skipped or stopped at for having an unknown rtype, see
+    /// [`UnknownRtypePolicy`].
+    pub unknown_rtype_count: u64,
+    /// The number of warnings logged, such as for corrupt or truncated data ending
+    /// iteration early.
+    pub warning_count: u64,
+}
+
+impl DecodeStats {
+    /// Returns the ratio of decompressed to compressed bytes, or `None` if no bytes
+    /// have been read.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        (self.compressed_bytes > 0)
+            .then(|| self.decompressed_bytes as f64 / self.compressed_bytes as f64)
+    }
+}
+
+/// Counts the bytes consumed from the reader it wraps.
+#[derive(Debug)]
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+    /// Whether reading from `inner` has failed, to tell a failure of the reader from
+    /// data that can't be decompressed.
+    has_failed: bool,
+}
+
+impl<R> CountingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            count: 0,
+            has_failed: false,
+        }
+    }
+}
+
+impl<R: io::Read> io::Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self
+            .inner
+            .read(buf)
+            .inspect_err(|_| self.has_failed = true)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: io::BufRead> io::BufRead for CountingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner
+            .fill_buf()
+            .inspect_err(|_| self.has_failed = true)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.count += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+/// The conditions records must meet to be returned by a [`DbzStreamIter`].
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RecordFilter {
+    /// The half-open range of `ts_event`s to keep.
+    pub(crate) ts_event_range: Option<Range<u64>>,
+    /// The product IDs to keep.
+    pub(crate) product_ids: Option<HashSet<u32>>,
+    /// The mappings of the symbols to keep.
+    symbol_resolver: Option<SymbolResolver>,
+    /// The number of records meeting the other conditions still to skip.
+    skip: u64,
+    /// The number of records still to keep, if limited.
+    limit: Option<u64>,
+}
+
+impl RecordFilter {
+    /// The offset of `product_id` in the record header.
+    const PRODUCT_ID_OFFSET: usize = 4;
+    /// The offset of `ts_event` in the record header.
+    const TS_EVENT_OFFSET: usize = 8;
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.ts_event_range.is_some()
+            || self.product_ids.is_some()
+            || self.symbol_resolver.is_some()
+            || self.skip > 0
+            || self.limit.is_some()
+    }
+
+    /// Returns `true` if the limit has been reached, so no more records will match.
+    pub(crate) fn is_exhausted(&self) -> bool {
+        self.limit == Some(0)
+    }
+
+    /// Returns `true` if the record in `buffer` meets the conditions. Records that
+    /// meet them are counted towards the records to skip and the limit.
+    pub(crate) fn matches(&mut self, buffer: &[u8]) -> bool {
+        if self.is_exhausted() {
+            return false;
+        }
+        let ts_event = u64::from_le_slice(&buffer[Self::TS_EVENT_OFFSET..]);
+        let product_id = u32::from_le_slice(&buffer[Self::PRODUCT_ID_OFFSET..]);
+        if let Some(range) = &self.ts_event_range {
+            if !range.contains(&ts_event) {
+                return false;
+            }
+        }
+        if let Some(product_ids) = &self.product_ids {
+            if !product_ids.contains(&product_id) {
+                return false;
+            }
+        }
+        if let Some(symbol_resolver) = &mut self.symbol_resolver {
+            if symbol_resolver.resolve(product_id, ts_event).is_none() {
+                return false;
+            }
+        }
+        if self.skip > 0 {
+            self.skip -= 1;
+            return false;
+        }
+        if let Some(limit) = &mut self.limit {
+            *limit -= 1;
+        }
+        true
     }
 }
 
@@ -154,41 +576,307 @@ pub struct DbzStreamIter<R: io::BufRead, T> {
     /// Reference to the underlying [`Dbz`] object.
     /// Buffered zstd decoder of the DBZ file, so each call to [`DbzStreamIter::next()`] doesn't result in a
     /// separate system call.
-    decoder: Decoder<'static, R>,
+    decoder: Decoder<'static, CountingReader<R>>,
     /// Number of elements that have been decoded. Used for [`Iterator::size_hint`].
-    i: usize,
+    pub(crate) i: usize,
+    /// The number of bytes of decompressed records before the position of the reader,
+    /// e.g. after seeking to a later Zstd frame.
+    bytes_before: u64,
+    /// Whether the iterator has run out of records.
+    is_done: bool,
+    /// How far to trust `metadata.record_count`.
+    size_hint_policy: SizeHintPolicy,
+    unknown_rtype_policy: UnknownRtypePolicy,
+    /// The number of records of each unknown rtype skipped or stopped at.
+    unknown_rtypes: BTreeMap<u8, u64>,
+    /// The number of records according to the zstd frame header, if it declares its
+    /// content size.
+    frame_record_count: Option<usize>,
+    /// The error that ended iteration early, if any.
+    error: Option<anyhow::Error>,
+    /// Records that don't match are skipped.
+    filter: RecordFilter,
     /// Reusable buffer for reading into.
     buffer: Vec<u8>,
+    /// Counters of the decoding so far, except `compressed_bytes`, which is counted
+    /// by the reader.
+    stats: DecodeStats,
     /// Required to associate [`DbzStreamIter`] with a `T`.
     _item: PhantomData<T>,
 }
 
 impl<R: io::BufRead, T> DbzStreamIter<R, T> {
-    pub(crate) fn new(reader: R, metadata: Metadata) -> anyhow::Result<Self> {
-        let decoder = Decoder::with_buffer(reader)?;
+    pub(crate) fn new(
+        mut reader: R,
+        metadata: Metadata,
+        dictionary: Option<&[u8]>,
+    ) -> anyhow::Result<Self> {
+        let content_size = zstd::zstd_safe::get_frame_content_size(reader.fill_buf()?);
+        // the largest values indicate an unknown size or an error
+        let frame_record_count =
+            (content_size < u64::MAX - 1).then(|| content_size as usize / mem::size_of::<T>());
+        let reader = CountingReader::new(reader);
+        let decoder = match dictionary {
+            Some(dictionary) => Decoder::with_dictionary(reader, dictionary)?,
+            None => Decoder::with_buffer(reader)?,
+        };
         Ok(DbzStreamIter {
             metadata,
             decoder,
             i: 0,
+            bytes_before: 0,
+            is_done: false,
+            size_hint_policy: SizeHintPolicy::default(),
+            unknown_rtype_policy: UnknownRtypePolicy::default(),
+            unknown_rtypes: BTreeMap::new(),
+            frame_record_count,
+            error: None,
+            filter: RecordFilter::default(),
             buffer: vec![0; mem::size_of::<T>()],
+            stats: DecodeStats::default(),
             _item: PhantomData {},
         })
     }
+
+    /// Returns the [`Schema`] of the records, which distinguishes schemas that share a
+    /// record type like [`Schema::Tbbo`] and [`Schema::Mbp1`].
+    pub fn schema(&self) -> Schema {
+        self.metadata.schema
+    }
+
+    /// Sets how far the iterator trusts the `record_count` in the [`Metadata`]. Should
+    /// be called before iterating.
+    pub fn with_size_hint_policy(mut self, size_hint_policy: SizeHintPolicy) -> Self {
+        self.size_hint_policy = size_hint_policy;
+        self
+    }
+
+    /// Sets what the iterator does with records whose rtype doesn't match the schema.
+    /// Should be called before iterating.
+    pub fn with_unknown_rtype_policy(mut self, unknown_rtype_policy: UnknownRtypePolicy) -> Self {
+        self.unknown_rtype_policy = unknown_rtype_policy;
+        self
+    }
+
+    pub(crate) fn with_filter(mut self, filter: RecordFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Returns the error that ended iteration before the end of the data, if any: an
+    /// I/O or decompression error, a truncated record, a record of a different type
+    /// than `T`, or, when trusting `record_count`, the data ending before it.
+    pub fn error(&self) -> Option<&anyhow::Error> {
+        self.error.as_ref()
+    }
+
+    /// Takes the error that ended iteration early, if any, so it can be returned with
+    /// its [`Error`] intact.
+    pub(crate) fn take_error(&mut self) -> Option<anyhow::Error> {
+        self.error.take()
+    }
+
+    /// Returns counters of the decoding so far, e.g. for reporting the health of
+    /// decoding each file to monitoring. They can be read after iteration ends.
+    pub fn decode_stats(&self) -> DecodeStats {
+        DecodeStats {
+            compressed_bytes: self.decoder.get_ref().count,
+            ..self.stats
+        }
+    }
+
+    /// Returns the unknown rtypes of the records skipped or stopped at so far with
+    /// [`UnknownRtypePolicy::Skip`] or [`UnknownRtypePolicy::Stop`], and the number of
+    /// records of each.
+    pub fn unknown_rtypes(&self) -> &BTreeMap<u8, u64> {
+        &self.unknown_rtypes
+    }
+
+    /// Converts the iterator into one of owned records that returns the error that
+    /// ended iteration, if any, as its last item.
+    pub fn into_fallible(self) -> DbzFallibleIter<R, T> {
+        DbzFallibleIter { inner: self }
+    }
+
+    /// Decompresses and discards the next `n` bytes, counting the records they
+    /// contained as decoded.
+    fn skip_bytes(&mut self, n: u64) -> anyhow::Result<()> {
+        if n == 0 {
+            return Ok(());
+        }
+        let skipped = io::copy(&mut (&mut self.decoder).take(n), &mut io::sink())
+            .map_err(|e| self.read_error(e, 0))
+            .with_context(|| format!("Failed to skip {n} bytes of records"))?;
+        if skipped < n {
+            return Err(anyhow!(
+                "Records ended after {skipped} of the {n} bytes to skip"
+            ));
+        }
+        self.i += (n / mem::size_of::<T>() as u64) as usize;
+        self.stats.decompressed_bytes += n;
+        Ok(())
+    }
+
+    /// Decompresses and discards the rest of the record in `buffer` with an unknown
+    /// rtype, whose length may differ from `T`'s.
+    fn skip_unknown_record(&mut self) -> anyhow::Result<()> {
+        // the length in the header is in 32-bit words
+        let len = self.buffer[0] as usize * 4;
+        let read = self.buffer.len() as u64;
+        let Some(rest) = len.checked_sub(self.buffer.len()) else {
+            let message = format!(
+                "Record {} has rtype {:#04x} and a length of {len} bytes, fewer than the {} \
+                read, so the records after it can't be found",
+                self.i,
+                self.buffer[1],
+                self.buffer.len()
+            );
+            return Err(self.decode_error(message, read).into());
+        };
+        let skipped = io::copy(&mut (&mut self.decoder).take(rest as u64), &mut io::sink())
+            .map_err(|e| self.read_error(e, read))
+            .with_context(|| format!("Failed to skip record {}", self.i))?;
+        if skipped < rest as u64 {
+            let message = format!(
+                "Record {} truncated after {} of {len} bytes",
+                self.i,
+                read + skipped
+            );
+            return Err(self.decode_error(message, read).into());
+        }
+        self.stats.decompressed_bytes += skipped;
+        Ok(())
+    }
+
+    /// Converts a failure reading from the decoder into an [`Error::Io`] if reading
+    /// the compressed data failed, otherwise into an [`Error::Decode`] for data that's
+    /// truncated or can't be decompressed. `read` is the number of bytes of the
+    /// current record already read.
+    fn read_error(&self, error: io::Error, read: u64) -> Error {
+        if self.decoder.get_ref().has_failed {
+            Error::Io {
+                path: None,
+                source: error,
+            }
+        } else {
+            self.decode_error(error.to_string(), read)
+        }
+    }
+
+    /// Returns an [`Error::Decode`] for the record at index `i`, of which `read` bytes
+    /// were already counted as decompressed.
+    fn decode_error(&self, message: String, read: u64) -> Error {
+        Error::Decode {
+            message,
+            record_index: self.i as u64,
+            byte_offset: self.bytes_before + self.stats.decompressed_bytes - read,
+        }
+    }
+
+    fn fail(&mut self, error: anyhow::Error) {
+        warn!("{error:?}");
+        self.stats.warning_count += 1;
+        self.error = Some(error);
+        self.is_done = true;
+    }
+}
+
+/// Reads a whole record into `buffer`. Returns `false` if the data ended before the
+/// start of the record.
+pub(crate) fn read_record(reader: &mut impl io::Read, buffer: &mut [u8]) -> io::Result<bool> {
+    let mut pos = 0;
+    while pos < buffer.len() {
+        match reader.read(&mut buffer[pos..]) {
+            Ok(0) if pos == 0 => return Ok(false),
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("record truncated after {pos} of {} bytes", buffer.len()),
+                ))
+            }
+            Ok(n) => pos += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
 }
 
 impl<R: io::BufRead, T: ConstTypeId> StreamingIterator for DbzStreamIter<R, T> {
     type Item = T;
 
     fn advance(&mut self) {
-        if let Err(e) = self.decoder.read_exact(&mut self.buffer) {
-            warn!("Failed to read from DBZ decoder: {e:?}");
-            self.i = self.metadata.record_count as usize + 1;
+        if self.is_done {
+            return;
+        }
+        let is_trusted = self.size_hint_policy == SizeHintPolicy::Trust;
+        loop {
+            // stop before decompressing any more once the limit is reached
+            if self.filter.is_exhausted()
+                || is_trusted && self.i >= self.metadata.record_count as usize
+            {
+                self.is_done = true;
+                return;
+            }
+            match read_record(&mut self.decoder, &mut self.buffer) {
+                Ok(true) => {}
+                // without a trusted `record_count`, running out of data is the expected
+                // way to finish
+                Ok(false) if !is_trusted => {
+                    self.is_done = true;
+                    return;
+                }
+                Ok(false) => {
+                    let message = format!(
+                        "DBZ data ended after {} records, expected {}",
+                        self.i, self.metadata.record_count
+                    );
+                    return self.fail(self.decode_error(message, 0).into());
+                }
+                Err(e) => {
+                    let e = anyhow::Error::new(self.read_error(e, 0))
+                        .context(format!("Failed to read record {} from DBZ decoder", self.i));
+                    return self.fail(e);
+                }
+            }
+            self.stats.decompressed_bytes += self.buffer.len() as u64;
+            self.stats.record_count += 1;
+            // the rtype follows the length in the header
+            let rtype = self.buffer[1];
+            if rtype != T::TYPE_ID {
+                if self.unknown_rtype_policy == UnknownRtypePolicy::Error {
+                    let message = format!(
+                        "Record {} has rtype {rtype:#04x}, expected {:#04x} for schema {}",
+                        self.i,
+                        T::TYPE_ID,
+                        self.metadata.schema.as_str()
+                    );
+                    let read = self.buffer.len() as u64;
+                    return self.fail(self.decode_error(message, read).into());
+                }
+                self.stats.unknown_rtype_count += 1;
+                *self.unknown_rtypes.entry(rtype).or_default() += 1;
+                if self.unknown_rtype_policy == UnknownRtypePolicy::Stop {
+                    debug!("Stopping at record {} with rtype {rtype:#04x}", self.i);
+                    self.is_done = true;
+                    return;
+                }
+                if let Err(e) = self.skip_unknown_record() {
+                    return self.fail(e);
+                }
+                self.i += 1;
+                continue;
+            }
+            self.i += 1;
+            if self.filter.matches(&self.buffer) {
+                return;
+            }
+            self.stats.filtered_count += 1;
         }
-        self.i += 1;
     }
 
     fn get(&self) -> Option<&Self::Item> {
-        if self.i > self.metadata.record_count as usize {
+        if self.is_done || self.i == 0 {
             return None;
         }
         // Safety: `buffer` is specifically sized to `T`
@@ -197,10 +885,70 @@ impl<R: io::BufRead, T: ConstTypeId> StreamingIterator for DbzStreamIter<R, T> {
 
     /// Returns the lower bound and upper bounds of remaining length of iterator.
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let remaining = self.metadata.record_count as usize - self.i;
-        // assumes `record_count` is accurate. If it is not, the program won't crash but
-        // performance will be suboptimal
-        (remaining, Some(remaining))
+        if self.is_done {
+            return (0, Some(0));
+        }
+        let remaining = (self.metadata.record_count as usize).saturating_sub(self.i);
+        let (lower, upper) = match self.size_hint_policy {
+            // If `record_count` is inaccurate, the program won't crash but performance
+            // will be suboptimal
+            SizeHintPolicy::Trust => (remaining, Some(remaining)),
+            SizeHintPolicy::Ignore => (0, None),
+            SizeHintPolicy::ClampToRemainingBytes => match self.frame_record_count {
+                Some(frame_record_count) => {
+                    let frame_remaining = frame_record_count.saturating_sub(self.i);
+                    (remaining.min(frame_remaining), Some(frame_remaining))
+                }
+                None => (remaining, None),
+            },
+        };
+        let upper = match self.filter.limit {
+            Some(limit) => Some(upper.unwrap_or(usize::MAX).min(limit as usize)),
+            None => upper,
+        };
+        // any of the remaining records may be filtered out
+        if self.filter.is_active() {
+            (0, upper)
+        } else {
+            (lower, upper)
+        }
+    }
+}
+
+/// An iterator over the owned records of a [`Dbz`] that returns an error instead of
+/// ending early when the data is corrupt or truncated. This struct is created by
+/// the [`Dbz::try_into_fallible_iter`] and [`DbzStreamIter::into_fallible`] methods.
+pub struct DbzFallibleIter<R: io::BufRead, T> {
+    inner: DbzStreamIter<R, T>,
+}
+
+impl<R: io::BufRead, T> DbzFallibleIter<R, T> {
+    /// Returns counters of the decoding so far, see [`DbzStreamIter::decode_stats`].
+    pub fn decode_stats(&self) -> DecodeStats {
+        self.inner.decode_stats()
+    }
+
+    /// Returns the unknown rtypes skipped or stopped at so far, see
+    /// [`DbzStreamIter::unknown_rtypes`].
+    pub fn unknown_rtypes(&self) -> &BTreeMap<u8, u64> {
+        self.inner.unknown_rtypes()
+    }
+}
+
+impl<R: io::BufRead, T: ConstTypeId + Clone> Iterator for DbzFallibleIter<R, T> {
+    type Item = anyhow::Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner.next() {
+            Some(record) => Some(Ok(record.clone())),
+            None => self.inner.take_error().map(Err),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.inner.size_hint();
+        // there may be an error after the records
+        (lower, upper.map(|upper| upper + 1))
     }
 }
 
@@ -216,6 +964,14 @@ impl FromLittleEndianSlice for u64 {
     }
 }
 
+impl FromLittleEndianSlice for i64 {
+    /// NOTE: assumes the length of `slice` is at least 8 bytes
+    fn from_le_slice(slice: &[u8]) -> Self {
+        let (bytes, _) = slice.split_at(mem::size_of::<Self>());
+        Self::from_le_bytes(bytes.try_into().unwrap())
+    }
+}
+
 impl FromLittleEndianSlice for i32 {
     /// NOTE: assumes the length of `slice` is at least 4 bytes
     fn from_le_slice(slice: &[u8]) -> Self {
@@ -240,54 +996,210 @@ impl FromLittleEndianSlice for u16 {
     }
 }
 
+/// The variable-length fields of the metadata.
+#[derive(Default)]
+struct VarFields {
+    schema_definition: Option<SchemaDefinition>,
+    symbols: Vec<String>,
+    partial: Vec<String>,
+    not_found: Vec<String>,
+    mappings: Vec<SymbolMapping>,
+    annotations: BTreeMap<String, String>,
+}
+
 impl Metadata {
+    /// Returns metadata of `schema` with placeholders for everything else: an empty
+    /// dataset, a zero time range and record count, and no symbology.
+    pub(crate) fn placeholder(schema: Schema) -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            dataset: String::new(),
+            schema,
+            start: 0,
+            end: 0,
+            limit: 0,
+            record_count: 0,
+            compression: Compression::ZStd,
+            stype_in: SType::ProductId,
+            stype_out: SType::ProductId,
+            schema_definition: None,
+            symbols: Vec::new(),
+            partial: Vec::new(),
+            not_found: Vec::new(),
+            mappings: Vec::new(),
+            annotations: BTreeMap::new(),
+        }
+    }
+
     const U32_SIZE: usize = mem::size_of::<u32>();
 
+    /// Reads only the metadata of the DBZ file at `path`, which is faster than
+    /// [`Dbz::from_file`] when the records aren't needed.
+    ///
+    /// # Errors
+    /// This function will return an error if `path` doesn't exist or it is unable to
+    /// parse the metadata from the file.
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let mut file = Error::open(path.as_ref())?;
+        Self::read(&mut file)
+    }
+
+    /// Reads only the metadata of the DBZ file at `path` like [`Metadata::from_file`],
+    /// but in a compatibility mode for files from newer versions of DBZ: the known
+    /// fixed-length fields are decoded, the reserved bytes and any schema definitions
+    /// and extensions are skipped, and problems decoding the variable-length fields
+    /// leave them empty instead of failing. The file's version is kept in
+    /// [`Metadata::version`]. Metadata of supported versions is decoded as usual.
+    ///
+    /// # Errors
+    /// This function will return an error if `path` doesn't exist or it is unable to
+    /// parse the fixed-length fields of the metadata from the file.
+    pub fn from_file_compatible(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let mut file = Error::open(path.as_ref())?;
+        Self::read_compatible(&mut file)
+    }
+
     pub(crate) fn read(reader: &mut impl io::Read) -> anyhow::Result<Self> {
-        let mut prelude_buffer = [0u8; 2 * mem::size_of::<i32>()];
+        Self::read_buffer(reader).and_then(Self::decode)
+    }
+
+    pub(crate) fn read_compatible(reader: &mut impl io::Read) -> anyhow::Result<Self> {
+        Self::read_buffer(reader).and_then(Self::decode_compatible)
+    }
+
+    /// Reads the metadata frame from `reader`, excluding the prelude.
+    fn read_buffer(reader: &mut impl io::Read) -> anyhow::Result<Vec<u8>> {
+        let mut prelude_buffer = [0u8; Self::PRELUDE_LEN];
         reader
             .read_exact(&mut prelude_buffer)
+            .map_err(|e| Self::read_error(e, "prelude"))
             .with_context(|| "Failed to read metadata prelude")?;
-        let magic = u32::from_le_slice(&prelude_buffer[..4]);
+        let frame_size = Self::frame_size(&prelude_buffer)?;
+        let mut metadata_buffer = vec![0u8; frame_size];
+        reader
+            .read_exact(&mut metadata_buffer)
+            .map_err(|e| Self::read_error(e, "frame"))
+            .with_context(|| "Failed to read metadata")?;
+        Ok(metadata_buffer)
+    }
+
+    /// Converts a failure reading `part` of the metadata into an
+    /// [`Error::InvalidMetadata`] if the data ended early, otherwise into an
+    /// [`Error::Io`].
+    fn read_error(error: io::Error, part: &str) -> Error {
+        if error.kind() == io::ErrorKind::UnexpectedEof {
+            Error::InvalidMetadata(format!("metadata {part} truncated"))
+        } else {
+            Error::Io {
+                path: None,
+                source: error,
+            }
+        }
+    }
+
+    /// The length of the prelude of the metadata: the Zstd magic number and the size of
+    /// the metadata frame.
+    pub(crate) const PRELUDE_LEN: usize = 2 * mem::size_of::<i32>();
+
+    /// Returns the size of the metadata frame following `prelude`.
+    pub(crate) fn frame_size(prelude: &[u8; Self::PRELUDE_LEN]) -> anyhow::Result<usize> {
+        let magic = u32::from_le_slice(&prelude[..4]);
         if !Self::ZSTD_MAGIC_RANGE.contains(&magic) {
-            return Err(anyhow!("Invalid metadata: no zstd magic number"));
+            return Err(Error::InvalidMetadata("no zstd magic number".to_owned()).into());
         }
-        let frame_size = u32::from_le_slice(&prelude_buffer[4..]);
+        let frame_size = u32::from_le_slice(&prelude[4..]);
         debug!("magic={magic}, frame_size={frame_size}");
         if (frame_size as usize) < Self::FIXED_METADATA_LEN {
-            return Err(anyhow!(
-                "Frame length cannot be shorter than the fixed metadata size"
-            ));
+            return Err(Error::InvalidMetadata(
+                "Frame length cannot be shorter than the fixed metadata size".to_owned(),
+            )
+            .into());
         }
+        Ok(frame_size as usize)
+    }
 
-        let mut metadata_buffer = vec![0u8; frame_size as usize];
-        reader
-            .read_exact(&mut metadata_buffer)
-            .with_context(|| "Failed to read metadata")?;
-        Self::decode(metadata_buffer)
+    pub(crate) fn decode(metadata_buffer: Vec<u8>) -> anyhow::Result<Self> {
+        Self::decode_with(metadata_buffer, false)
+    }
+
+    /// Decodes the metadata like [`Metadata::decode`], ignoring problems that newer
+    /// versions of DBZ could cause, see [`Metadata::from_file_compatible`].
+    pub(crate) fn decode_compatible(metadata_buffer: Vec<u8>) -> anyhow::Result<Self> {
+        Self::decode_with(metadata_buffer, true)
+    }
+
+    fn decode_with(metadata_buffer: Vec<u8>, is_compatible: bool) -> anyhow::Result<Self> {
+        let mut problems = Vec::new();
+        let metadata = Self::decode_checked(&metadata_buffer, &mut problems);
+        let version = metadata_buffer[Self::VERSION_CSTR_LEN - 1];
+        if is_compatible && version > SCHEMA_VERSION {
+            problems.retain(|(pos, error)| {
+                let is_from_newer_version =
+                    matches!(Error::find(error), Some(Error::UnsupportedVersion { .. }))
+                        || *pos >= Self::FIXED_METADATA_LEN;
+                if is_from_newer_version {
+                    warn!("Ignoring problem with metadata of version {version}: {error:#}");
+                }
+                !is_from_newer_version
+            });
+        }
+        if let Some((_, error)) = problems.into_iter().next() {
+            return Err(match Error::find(&error) {
+                Some(_) => error,
+                None => Error::InvalidMetadata(format!("{error:#}")).into(),
+            });
+        }
+        metadata
+            .ok_or_else(|| Error::InvalidMetadata("Failed to decode metadata".to_owned()).into())
     }
 
-    fn decode(metadata_buffer: Vec<u8>) -> anyhow::Result<Self> {
+    /// Decodes as much of the metadata in `metadata_buffer` as possible, adding every
+    /// problem found to `problems` with its offset in the buffer instead of stopping
+    /// at the first. Fields that can't be decoded are left empty, or `None` is
+    /// returned if they can't be empty.
+    pub(crate) fn decode_checked(
+        metadata_buffer: &[u8],
+        problems: &mut Vec<(usize, anyhow::Error)>,
+    ) -> Option<Self> {
         const U64_SIZE: usize = mem::size_of::<u64>();
+        fn check<T>(
+            problems: &mut Vec<(usize, anyhow::Error)>,
+            pos: usize,
+            res: anyhow::Result<T>,
+        ) -> Option<T> {
+            res.map_err(|e| problems.push((pos, e))).ok()
+        }
+
         let mut pos = 0;
         if &metadata_buffer[pos..pos + 3] != b"DBZ" {
-            return Err(anyhow!("Invalid version string"));
+            problems.push((pos, anyhow!("Invalid version string")));
         }
         // Interpret 4th character as an u8, not a char to allow for 254 versions (0 omitted)
-        let version = metadata_buffer[pos + 3] as u8;
+        let version = metadata_buffer[pos + 3];
         // assume not forwards compatible
         if version > SCHEMA_VERSION {
-            return Err(anyhow!("Can't read newer version of DBZ"));
+            problems.push((pos + 3, Error::UnsupportedVersion { version }.into()));
         }
         pos += Self::VERSION_CSTR_LEN;
-        let dataset = std::str::from_utf8(&metadata_buffer[pos..pos + Self::DATASET_CSTR_LEN])
-            .with_context(|| "Failed to read dataset from metadata")?
-            // remove null bytes
-            .trim_end_matches('\0')
-            .to_owned();
+        let dataset_buffer = &metadata_buffer[pos..pos + Self::DATASET_CSTR_LEN];
+        let dataset = check(
+            problems,
+            pos,
+            std::str::from_utf8(dataset_buffer)
+                .map(str::to_owned)
+                .with_context(|| "Failed to read dataset from metadata"),
+        )
+        .unwrap_or_else(|| String::from_utf8_lossy(dataset_buffer).into_owned())
+        // remove null bytes
+        .trim_end_matches('\0')
+        .to_owned();
         pos += Self::DATASET_CSTR_LEN;
-        let schema = Schema::try_from(u16::from_le_slice(&metadata_buffer[pos..]))
-            .with_context(|| format!("Failed to read schema: '{}'", metadata_buffer[pos]))?;
+        let schema = check(
+            problems,
+            pos,
+            Schema::try_from(u16::from_le_slice(&metadata_buffer[pos..]))
+                .with_context(|| format!("Failed to read schema: '{}'", metadata_buffer[pos])),
+        );
         pos += mem::size_of::<Schema>();
         let start = u64::from_le_slice(&metadata_buffer[pos..]);
         pos += U64_SIZE;
@@ -297,57 +1209,150 @@ impl Metadata {
         pos += U64_SIZE;
         let record_count = u64::from_le_slice(&metadata_buffer[pos..]);
         pos += U64_SIZE;
-        let compression = Compression::try_from(metadata_buffer[pos])
-            .with_context(|| format!("Failed to parse compression '{}'", metadata_buffer[pos]))?;
+        let compression = check(
+            problems,
+            pos,
+            Compression::try_from(metadata_buffer[pos])
+                .with_context(|| format!("Failed to parse compression '{}'", metadata_buffer[pos])),
+        );
         pos += mem::size_of::<Compression>();
-        let stype_in = SType::try_from(metadata_buffer[pos])
-            .with_context(|| format!("Failed to read stype_in: '{}'", metadata_buffer[pos]))?;
+        let stype_in = check(
+            problems,
+            pos,
+            SType::try_from(metadata_buffer[pos])
+                .with_context(|| format!("Failed to read stype_in: '{}'", metadata_buffer[pos])),
+        );
         pos += mem::size_of::<SType>();
-        let stype_out = SType::try_from(metadata_buffer[pos])
-            .with_context(|| format!("Failed to read stype_out: '{}'", metadata_buffer[pos]))?;
+        let stype_out = check(
+            problems,
+            pos,
+            SType::try_from(metadata_buffer[pos])
+                .with_context(|| format!("Failed to read stype_out: '{}'", metadata_buffer[pos])),
+        );
         pos += mem::size_of::<SType>();
         // skip reserved
         pos += Self::RESERVED_LEN;
-        // remaining metadata is compressed
-        let mut zstd_decoder = Decoder::new(&metadata_buffer[pos..])
+        // the variable-length fields follow one another, so they can't be decoded past
+        // the first problem
+        let mut var_fields = VarFields::default();
+        check(
+            problems,
+            pos,
+            Self::decode_var_fields(
+                &metadata_buffer[pos..],
+                version > SCHEMA_VERSION,
+                &mut var_fields,
+            ),
+        );
+
+        Some(Self {
+            version,
+            dataset,
+            schema: schema?,
+            stype_in: stype_in?,
+            stype_out: stype_out?,
+            start,
+            end,
+            limit,
+            compression: compression?,
+            record_count,
+            schema_definition: var_fields.schema_definition,
+            symbols: var_fields.symbols,
+            partial: var_fields.partial,
+            not_found: var_fields.not_found,
+            mappings: var_fields.mappings,
+            annotations: var_fields.annotations,
+        })
+    }
+
+    /// Decodes the Zstd-compressed variable-length fields in `buffer` into `fields`,
+    /// leaving the fields after a problem empty. Schema definitions, whose layout may
+    /// differ in newer versions of DBZ, are skipped if `should_skip_schema_definition`.
+    fn decode_var_fields(
+        buffer: &[u8],
+        should_skip_schema_definition: bool,
+        fields: &mut VarFields,
+    ) -> anyhow::Result<()> {
+        let mut zstd_decoder = Decoder::new(buffer)
             .with_context(|| "Failed to read zstd-zipped variable-length metadata".to_owned())?;
 
         // decompressed variable-length metadata buffer
-        let buffer_capacity = (metadata_buffer.len() - pos) * 3; // 3x is arbitrary
+        let buffer_capacity = buffer.len() * 3; // 3x is arbitrary
         let mut var_buffer = Vec::with_capacity(buffer_capacity);
         zstd_decoder.read_to_end(&mut var_buffer)?;
-        pos = 0;
-        let schema_definition_length = u32::from_le_slice(&var_buffer[pos..]);
-        if schema_definition_length != 0 {
+        let mut pos = 0;
+        if var_buffer.len() < Self::U32_SIZE {
+            return Err(anyhow!("Unexpected end of metadata buffer"));
+        }
+        let schema_definition_length = u32::from_le_slice(&var_buffer[pos..]) as usize;
+        pos += Self::U32_SIZE;
+        if pos + schema_definition_length > var_buffer.len() {
             return Err(anyhow!(
-                "This version of dbz can't parse schema definitions"
+                "Schema definition length ({schema_definition_length}) exceeds the {} remaining bytes of the metadata buffer",
+                var_buffer.len() - pos
             ));
         }
-        pos += Self::U32_SIZE + (schema_definition_length as usize);
-        let symbols = Self::decode_repeated_symbol_cstr(var_buffer.as_slice(), &mut pos)
+        if schema_definition_length != 0 && !should_skip_schema_definition {
+            fields.schema_definition = Some(
+                Self::decode_schema_definition(&var_buffer[pos..pos + schema_definition_length])
+                    .with_context(|| "Failed to parse schema definition")?,
+            );
+        }
+        pos += schema_definition_length;
+        fields.symbols = Self::decode_repeated_symbol_cstr(var_buffer.as_slice(), &mut pos)
             .with_context(|| "Failed to parse symbols")?;
-        let partial = Self::decode_repeated_symbol_cstr(var_buffer.as_slice(), &mut pos)
+        fields.partial = Self::decode_repeated_symbol_cstr(var_buffer.as_slice(), &mut pos)
             .with_context(|| "Failed to parse partial")?;
-        let not_found = Self::decode_repeated_symbol_cstr(var_buffer.as_slice(), &mut pos)
+        fields.not_found = Self::decode_repeated_symbol_cstr(var_buffer.as_slice(), &mut pos)
             .with_context(|| "Failed to parse not_found")?;
-        let mappings = Self::decode_symbol_mappings(var_buffer.as_slice(), &mut pos)?;
+        fields.mappings = Self::decode_symbol_mappings(var_buffer.as_slice(), &mut pos)?;
+        fields.annotations = Self::decode_annotations(var_buffer.as_slice(), &mut pos)
+            .with_context(|| "Failed to parse annotations")?;
+        Ok(())
+    }
 
-        Ok(Self {
-            version,
-            dataset,
-            schema,
-            stype_in,
-            stype_out,
-            start,
-            end,
-            limit,
-            compression,
-            record_count,
-            symbols,
-            partial,
-            not_found,
-            mappings,
-        })
+    /// Decodes the schema definition filling all of `buffer`.
+    fn decode_schema_definition(buffer: &[u8]) -> anyhow::Result<SchemaDefinition> {
+        const FIXED_SIZE: usize = 1 + 2 + Metadata::U32_SIZE;
+        if buffer.len() < FIXED_SIZE {
+            return Err(anyhow!("Unexpected end of schema definition"));
+        }
+        let rtype = buffer[0];
+        let record_size = u16::from_le_slice(&buffer[1..]);
+        let count = u32::from_le_slice(&buffer[3..]) as usize;
+        let mut pos = FIXED_SIZE;
+        let mut fields = Vec::new();
+        for i in 0..count {
+            let name = Self::decode_len_prefixed_str(buffer, &mut pos)
+                .with_context(|| format!("Failed to parse name of field at index {i}"))?;
+            if pos + 4 > buffer.len() {
+                return Err(anyhow!("Unexpected end of schema definition"));
+            }
+            let offset = u16::from_le_slice(&buffer[pos..]);
+            let size = u16::from_le_slice(&buffer[pos + 2..]);
+            pos += 4;
+            let type_name = Self::decode_len_prefixed_str(buffer, &mut pos)
+                .with_context(|| format!("Failed to parse type of field '{name}'"))?;
+            fields.push(FieldDefinition {
+                name,
+                offset,
+                size,
+                type_name,
+            });
+        }
+        if pos != buffer.len() {
+            return Err(anyhow!(
+                "Schema definition has {} unexpected trailing bytes",
+                buffer.len() - pos
+            ));
+        }
+        let definition = SchemaDefinition {
+            rtype,
+            record_size,
+            fields,
+        };
+        definition.validate()?;
+        Ok(definition)
     }
 
     fn decode_repeated_symbol_cstr(buffer: &[u8], pos: &mut usize) -> anyhow::Result<Vec<String>> {
@@ -390,6 +1395,50 @@ impl Metadata {
         Ok(res)
     }
 
+    /// Decodes the annotations following the mappings, if any. Metadata encoded before
+    /// annotations were added ends after the mappings.
+    fn decode_annotations(
+        buffer: &[u8],
+        pos: &mut usize,
+    ) -> anyhow::Result<BTreeMap<String, String>> {
+        let mut res = BTreeMap::new();
+        if *pos == buffer.len() {
+            return Ok(res);
+        }
+        if *pos + Self::U32_SIZE > buffer.len() {
+            return Err(anyhow!("Unexpected end of metadata buffer"));
+        }
+        let count = u32::from_le_slice(&buffer[*pos..]) as usize;
+        *pos += Self::U32_SIZE;
+        for i in 0..count {
+            let key = Self::decode_len_prefixed_str(buffer, pos)
+                .with_context(|| format!("Failed to parse key of annotation at index {i}"))?;
+            let value = Self::decode_len_prefixed_str(buffer, pos)
+                .with_context(|| format!("Failed to parse value of annotation '{key}'"))?;
+            res.insert(key, value);
+        }
+        Ok(res)
+    }
+
+    fn decode_len_prefixed_str(buffer: &[u8], pos: &mut usize) -> anyhow::Result<String> {
+        if *pos + Self::U32_SIZE > buffer.len() {
+            return Err(anyhow!("Unexpected end of metadata buffer"));
+        }
+        let len = u32::from_le_slice(&buffer[*pos..]) as usize;
+        *pos += Self::U32_SIZE;
+        if *pos + len > buffer.len() {
+            return Err(anyhow!(
+                "String length ({len}) exceeds the {} remaining bytes of the metadata buffer",
+                buffer.len() - *pos
+            ));
+        }
+        let res = std::str::from_utf8(&buffer[*pos..*pos + len])
+            .with_context(|| "String isn't valid UTF-8")?
+            .to_owned();
+        *pos += len;
+        Ok(res)
+    }
+
     fn decode_symbol_mapping(buffer: &[u8], pos: &mut usize) -> anyhow::Result<SymbolMapping> {
         const MIN_SYMBOL_MAPPING_ENCODED_SIZE: usize =
             Metadata::SYMBOL_CSTR_LEN + Metadata::U32_SIZE;
@@ -540,4 +1589,609 @@ mod tests {
         let res = Metadata::decode_iso8601(20100600);
         assert!(matches!(res, Err(e) if e.to_string().contains("a valid date")));
     }
+
+    /// Re-encodes the test trades, which contain 2 records, with `record_count` in the
+    /// metadata.
+    fn trades_with_record_count(
+        record_count: u64,
+        should_declare_size: bool,
+    ) -> Dbz<io::Cursor<Vec<u8>>> {
+        reencode_trades(record_count, should_declare_size, |_| {})
+    }
+
+    /// Re-encodes the test trades with `record_count` in the metadata after applying
+    /// `modify` to the decompressed records.
+    fn reencode_trades(
+        record_count: u64,
+        should_declare_size: bool,
+        modify: impl FnOnce(&mut Vec<u8>),
+    ) -> Dbz<io::Cursor<Vec<u8>>> {
+        let mut reader =
+            BufReader::new(File::open(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap());
+        let mut metadata = Metadata::read(&mut reader).unwrap();
+        let mut records = zstd::decode_all(reader).unwrap();
+        modify(&mut records);
+        metadata.record_count = record_count;
+        let mut buffer = io::Cursor::new(Vec::new());
+        metadata.encode(&mut buffer).unwrap();
+        let mut encoder = zstd::Encoder::new(&mut buffer, 0).unwrap();
+        if should_declare_size {
+            encoder
+                .set_pledged_src_size(Some(records.len() as u64))
+                .unwrap();
+        }
+        io::Write::write_all(&mut encoder, &records).unwrap();
+        encoder.finish().unwrap();
+        buffer.set_position(0);
+        Dbz::new(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_size_hint_policy_trust() {
+        let target = trades_with_record_count(1, false)
+            .try_into_iter::<TradeMsg>()
+            .unwrap();
+        assert_eq!(target.size_hint(), (1, Some(1)));
+        assert_eq!(target.count(), 1);
+        // running out of data before `record_count` doesn't underflow
+        let mut target = trades_with_record_count(5, false)
+            .try_into_iter::<TradeMsg>()
+            .unwrap();
+        assert_eq!(target.size_hint(), (5, Some(5)));
+        assert_eq!(target.by_ref().count(), 2);
+        assert_eq!(target.size_hint(), (0, Some(0)));
+    }
+
+    #[test]
+    fn test_filter_range() {
+        const FIRST_TS_EVENT: u64 = 1609160400098821953;
+        const SECOND_TS_EVENT: u64 = 1609160400107665963;
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz"))
+            .unwrap()
+            .filter_range(SECOND_TS_EVENT, u64::MAX)
+            .try_into_iter::<TradeMsg>()
+            .unwrap();
+        assert_eq!(target.size_hint(), (0, Some(2)));
+        let ts_events: Vec<_> = target
+            .into_fallible()
+            .map(|record| record.unwrap().hd.ts_event)
+            .collect();
+        assert_eq!(ts_events, vec![SECOND_TS_EVENT]);
+        // the end is exclusive
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz"))
+            .unwrap()
+            .filter_range(FIRST_TS_EVENT, SECOND_TS_EVENT)
+            .into_record_iter()
+            .unwrap();
+        let ts_events: Vec<_> = target
+            .map(|record| record.unwrap().header().ts_event)
+            .collect();
+        assert_eq!(ts_events, vec![FIRST_TS_EVENT]);
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz"))
+            .unwrap()
+            .filter_range(0, FIRST_TS_EVENT)
+            .try_into_iter::<TradeMsg>()
+            .unwrap();
+        assert_eq!(target.count(), 0);
+    }
+
+    #[test]
+    fn test_filter_product_ids() {
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz"))
+            .unwrap()
+            .filter_product_ids([5482])
+            .try_into_iter::<TickMsg>()
+            .unwrap();
+        assert_eq!(target.count(), 2);
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz"))
+            .unwrap()
+            .filter_product_ids([5482, 1])
+            .filter_range(0, 1)
+            .try_into_iter::<TickMsg>()
+            .unwrap();
+        assert_eq!(target.count(), 0);
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz"))
+            .unwrap()
+            .filter_product_ids([1])
+            .into_record_iter()
+            .unwrap();
+        assert_eq!(target.count(), 0);
+    }
+
+    #[test]
+    fn test_skip_and_take_records() {
+        let ts_events = |dbz: Dbz<_>| -> Vec<u64> {
+            dbz.into_record_iter()
+                .unwrap()
+                .map(|record| record.unwrap().header().ts_event)
+                .collect()
+        };
+        let all = ts_events(Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap());
+        assert_eq!(all.len(), 2);
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz"))
+            .unwrap()
+            .skip_records(1);
+        assert_eq!(ts_events(target), all[1..]);
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz"))
+            .unwrap()
+            .take_records(1)
+            .try_into_iter::<TickMsg>()
+            .unwrap();
+        assert_eq!(target.size_hint(), (0, Some(1)));
+        assert_eq!(target.count(), 1);
+        // skipping counts only the records kept by the other filters
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz"))
+            .unwrap()
+            .filter_range(all[1], u64::MAX)
+            .skip_records(1);
+        assert!(ts_events(target).is_empty());
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz"))
+            .unwrap()
+            .skip_records(5)
+            .take_records(1);
+        assert!(ts_events(target).is_empty());
+    }
+
+    #[test]
+    fn test_take_records_stops_decoding() {
+        let bytes = std::fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        let mut prelude = [0; Metadata::PRELUDE_LEN];
+        prelude.copy_from_slice(&bytes[..Metadata::PRELUDE_LEN]);
+        let metadata_len = Metadata::PRELUDE_LEN + Metadata::frame_size(&prelude).unwrap();
+        let records = zstd::decode_all(&bytes[metadata_len..]).unwrap();
+        // only the first of the 2 records in the metadata's `record_count`
+        let mut truncated = bytes[..metadata_len].to_vec();
+        truncated.extend(zstd::encode_all(&records[..mem::size_of::<TickMsg>()], 0).unwrap());
+        let res: anyhow::Result<Vec<_>> = Dbz::new(truncated.as_slice())
+            .unwrap()
+            .into_record_iter()
+            .unwrap()
+            .collect();
+        assert!(res.is_err());
+        let res: anyhow::Result<Vec<_>> = Dbz::new(truncated.as_slice())
+            .unwrap()
+            .take_records(1)
+            .into_record_iter()
+            .unwrap()
+            .collect();
+        assert_eq!(res.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_generic_zstd_skips_metadata() {
+        let bytes = std::fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        let metadata = Dbz::new(bytes.as_slice()).unwrap().metadata().clone();
+        // the metadata is a skippable frame, so the whole file decompresses to the records
+        let records = zstd::decode_all(bytes.as_slice()).unwrap();
+        assert_eq!(
+            records.len(),
+            metadata.record_count as usize * mem::size_of::<TickMsg>()
+        );
+    }
+
+    #[test]
+    fn test_decode_stats() {
+        let path = format!("{DBZ_PATH}/test_data.mbo.dbz");
+        let file_len = std::fs::metadata(&path).unwrap().len();
+        let metadata_len = {
+            let mut prelude = [0; Metadata::PRELUDE_LEN];
+            File::open(&path).unwrap().read_exact(&mut prelude).unwrap();
+            (Metadata::PRELUDE_LEN + Metadata::frame_size(&prelude).unwrap()) as u64
+        };
+        let mut target = Dbz::from_file(&path)
+            .unwrap()
+            .with_size_hint_policy(SizeHintPolicy::Ignore)
+            .filter_range(0, 1609160400000431665)
+            .into_record_iter()
+            .unwrap();
+        assert_eq!(target.decode_stats(), DecodeStats::default());
+        assert_eq!(target.by_ref().count(), 1);
+        let stats = target.decode_stats();
+        assert_eq!(stats.compressed_bytes, file_len - metadata_len);
+        assert_eq!(
+            stats.decompressed_bytes,
+            2 * mem::size_of::<TickMsg>() as u64
+        );
+        assert_eq!(stats.record_count, 2);
+        assert_eq!(stats.filtered_count, 1);
+        assert_eq!(stats.warning_count, 0);
+        assert!(stats.compression_ratio().unwrap() > 1.0);
+        // truncated data ends iteration with a warning
+        let bytes = std::fs::read(&path).unwrap();
+        let mut target = Dbz::new(&bytes[..bytes.len() - 10])
+            .unwrap()
+            .try_into_iter::<TickMsg>()
+            .unwrap();
+        while target.next().is_some() {}
+        assert_eq!(target.decode_stats().warning_count, 1);
+    }
+
+    #[test]
+    fn test_filter_symbols() {
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz"))
+            .unwrap()
+            .filter_symbols(&["ESH1"])
+            .unwrap()
+            .try_into_iter::<TradeMsg>()
+            .unwrap();
+        assert_eq!(target.count(), 2);
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz"))
+            .unwrap()
+            .filter_symbols(&["NQH1"])
+            .unwrap()
+            .into_record_iter()
+            .unwrap();
+        assert_eq!(target.count(), 0);
+        // ESH1 is only mapped to its product ID from 2020-12-28
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let mut metadata = target.metadata().clone();
+        for interval in metadata.mappings[0].intervals.iter_mut() {
+            interval.start_date = interval.end_date;
+        }
+        let target = Dbz { metadata, ..target }
+            .filter_symbols(&["ESH1"])
+            .unwrap()
+            .try_into_iter::<TradeMsg>()
+            .unwrap();
+        assert_eq!(target.count(), 0);
+    }
+
+    #[test]
+    fn test_peek_metadata() {
+        let path = format!("{DBZ_PATH}/test_data.trades.dbz");
+        let expected = Dbz::from_file(&path).unwrap().metadata().clone();
+        assert_eq!(Metadata::from_file(&path).unwrap(), expected);
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        let metadata = Dbz::peek_metadata(&mut reader).unwrap();
+        assert_eq!(metadata, expected);
+        // the reader is left at the records
+        let target = Dbz::from_parts(reader, metadata)
+            .try_into_iter::<TradeMsg>()
+            .unwrap();
+        assert_eq!(target.count(), 2);
+    }
+
+    #[test]
+    fn test_metadata_errors() {
+        let path = format!("{DBZ_PATH}/test_data.trades.dbz");
+        let bytes = std::fs::read(&path).unwrap();
+        let decode_with = |pos: usize, byte: u8| {
+            let mut bytes = bytes.clone();
+            bytes[pos] = byte;
+            Dbz::new(io::Cursor::new(bytes)).map(drop).unwrap_err()
+        };
+        let error = decode_with(Metadata::PRELUDE_LEN + 3, SCHEMA_VERSION + 1);
+        assert!(matches!(
+            Error::find(&error),
+            Some(Error::UnsupportedVersion { version }) if *version == SCHEMA_VERSION + 1
+        ));
+        let error = decode_with(0, 0);
+        assert!(matches!(
+            Error::find(&error),
+            Some(Error::InvalidMetadata(_))
+        ));
+        // the schema
+        let error = decode_with(Metadata::PRELUDE_LEN + 20, u8::MAX);
+        assert!(matches!(
+            Error::find(&error),
+            Some(Error::InvalidMetadata(msg)) if msg.contains("Failed to read schema")
+        ));
+        let error = Dbz::from_file(format!("{DBZ_PATH}/missing.dbz"))
+            .map(drop)
+            .unwrap_err();
+        assert!(matches!(Error::find(&error), Some(Error::Io { .. })));
+    }
+
+    #[test]
+    fn test_read_errors() {
+        /// A reader that fails after the bytes of `inner`.
+        struct FailingReader<'a> {
+            inner: &'a [u8],
+        }
+
+        impl io::Read for FailingReader<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                match self.inner.read(buf)? {
+                    0 => Err(io::Error::other("disconnected")),
+                    n => Ok(n),
+                }
+            }
+        }
+
+        let bytes = std::fs::read(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let mut prelude = [0; Metadata::PRELUDE_LEN];
+        prelude.copy_from_slice(&bytes[..Metadata::PRELUDE_LEN]);
+        let metadata_len = Metadata::PRELUDE_LEN + Metadata::frame_size(&prelude).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("truncated.dbz");
+        let first_error = |bytes: &[u8]| {
+            std::fs::write(&path, bytes).unwrap();
+            match Dbz::from_file(&path).and_then(|dbz| dbz.try_into_fallible_iter::<TradeMsg>()) {
+                Ok(mut iter) => iter.find_map(Result::err).unwrap(),
+                Err(error) => error,
+            }
+        };
+        // truncated in the prelude and the rest of the metadata
+        for len in [Metadata::PRELUDE_LEN / 2, metadata_len - 1] {
+            let error = first_error(&bytes[..len]);
+            assert!(
+                matches!(Error::find(&error), Some(Error::InvalidMetadata(_))),
+                "{error:#}"
+            );
+        }
+        // truncated in the records
+        let error = first_error(&bytes[..bytes.len() - 1]);
+        assert!(
+            matches!(Error::find(&error), Some(Error::Decode { .. })),
+            "{error:#}"
+        );
+        // a corrupted Zstd frame header
+        let mut corrupted = bytes.clone();
+        corrupted[metadata_len + 4] = u8::MAX;
+        let error = first_error(&corrupted);
+        assert!(
+            matches!(Error::find(&error), Some(Error::Decode { .. })),
+            "{error:#}"
+        );
+        // the reader fails in the records
+        let reader = BufReader::new(FailingReader {
+            inner: &bytes[..metadata_len + 8],
+        });
+        let error = Dbz::new(reader)
+            .unwrap()
+            .try_into_fallible_iter::<TradeMsg>()
+            .unwrap()
+            .find_map(Result::err)
+            .unwrap();
+        assert!(
+            matches!(Error::find(&error), Some(Error::Io { path: None, .. })),
+            "{error:#}"
+        );
+    }
+
+    /// Returns the encoded metadata of the test trades with `version` and with
+    /// `extension` added to the start and end of the variable-length fields, as a newer
+    /// version of DBZ could.
+    fn metadata_of_version(version: u8, extension: &[u8]) -> Vec<u8> {
+        let mut buffer = io::Cursor::new(Vec::new());
+        Metadata::from_file(format!("{DBZ_PATH}/test_data.trades.dbz"))
+            .unwrap()
+            .encode(&mut buffer)
+            .unwrap();
+        let mut bytes = buffer.into_inner();
+        bytes[Metadata::PRELUDE_LEN + 3] = version;
+        let var_start = Metadata::PRELUDE_LEN + Metadata::FIXED_METADATA_LEN;
+        let mut var_fields = zstd::decode_all(&bytes[var_start..]).unwrap();
+        // a schema definition
+        var_fields.splice(
+            ..Metadata::U32_SIZE,
+            (extension.len() as u32)
+                .to_le_bytes()
+                .into_iter()
+                .chain(extension.iter().copied()),
+        );
+        var_fields.extend_from_slice(extension);
+        bytes.truncate(var_start);
+        bytes.extend(zstd::encode_all(var_fields.as_slice(), 0).unwrap());
+        let frame_size = (bytes.len() - Metadata::PRELUDE_LEN) as u32;
+        bytes[4..Metadata::PRELUDE_LEN].copy_from_slice(&frame_size.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_read_compatible() {
+        let expected = Metadata::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let bytes = metadata_of_version(SCHEMA_VERSION + 1, b"future");
+        let error = Metadata::read(&mut bytes.as_slice()).unwrap_err();
+        assert!(matches!(
+            Error::find(&error),
+            Some(Error::UnsupportedVersion { .. })
+        ));
+        let metadata = Metadata::read_compatible(&mut bytes.as_slice()).unwrap();
+        assert_eq!(metadata.version, SCHEMA_VERSION + 1);
+        assert_eq!(
+            Metadata {
+                version: SCHEMA_VERSION,
+                ..metadata
+            },
+            expected
+        );
+        // supported versions are still checked
+        let bytes = metadata_of_version(SCHEMA_VERSION, b"future");
+        assert!(Metadata::read_compatible(&mut bytes.as_slice()).is_err());
+        let mut bytes = metadata_of_version(SCHEMA_VERSION + 1, &[]);
+        let records = std::fs::read(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let metadata_len =
+            Metadata::PRELUDE_LEN + u32::from_le_slice(&records[4..Metadata::PRELUDE_LEN]) as usize;
+        bytes.extend_from_slice(&records[metadata_len..]);
+        let target = Dbz::new_compatible(io::Cursor::new(bytes))
+            .unwrap()
+            .try_into_iter::<TradeMsg>()
+            .unwrap();
+        assert_eq!(target.count(), 2);
+    }
+
+    #[test]
+    fn test_from_raw_body() {
+        let path = format!("{DBZ_PATH}/test_data.trades.dbz");
+        let expected = Dbz::from_file(&path)
+            .unwrap()
+            .try_into_iter::<TradeMsg>()
+            .unwrap()
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        Metadata::read(&mut reader).unwrap();
+        let target = Dbz::from_raw_body(reader, Schema::Trades);
+        assert_eq!(target.schema(), Schema::Trades);
+        assert_eq!(target.metadata().record_count, 0);
+        let records = target
+            .try_into_iter::<TradeMsg>()
+            .unwrap()
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(records, expected);
+    }
+
+    #[test]
+    fn test_size_hint_policy_ignore() {
+        let target = trades_with_record_count(1, false)
+            .try_into_iter::<TradeMsg>()
+            .unwrap()
+            .with_size_hint_policy(SizeHintPolicy::Ignore);
+        assert_eq!(target.size_hint(), (0, None));
+        assert_eq!(target.count(), 2);
+    }
+
+    #[test]
+    fn test_size_hint_policy_clamp_to_remaining_bytes() {
+        let target = trades_with_record_count(5, true)
+            .try_into_iter::<TradeMsg>()
+            .unwrap()
+            .with_size_hint_policy(SizeHintPolicy::ClampToRemainingBytes);
+        assert_eq!(target.size_hint(), (2, Some(2)));
+        assert_eq!(target.count(), 2);
+        // without a declared frame size, only `record_count` is left to go on
+        let target = trades_with_record_count(5, false)
+            .try_into_iter::<TradeMsg>()
+            .unwrap()
+            .with_size_hint_policy(SizeHintPolicy::ClampToRemainingBytes);
+        assert_eq!(target.size_hint(), (5, None));
+        assert_eq!(target.count(), 2);
+    }
+
+    #[test]
+    fn test_fallible_iter() {
+        let target = trades_with_record_count(2, false)
+            .try_into_fallible_iter::<TradeMsg>()
+            .unwrap();
+        let records = target.collect::<anyhow::Result<Vec<_>>>().unwrap();
+        assert_eq!(records.len(), 2);
+    }
+
+    #[test]
+    fn test_fallible_iter_fewer_records_than_record_count() {
+        let mut target = trades_with_record_count(3, false)
+            .try_into_fallible_iter::<TradeMsg>()
+            .unwrap();
+        assert!(target.next().unwrap().is_ok());
+        assert!(target.next().unwrap().is_ok());
+        let err = target.next().unwrap().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("ended after 2 records, expected 3"));
+        assert!(target.next().is_none());
+    }
+
+    #[test]
+    fn test_fallible_iter_truncated_record() {
+        let target = reencode_trades(2, false, |records| {
+            records.truncate(records.len() - 1);
+        })
+        .try_into_fallible_iter::<TradeMsg>()
+        .unwrap();
+        let res: Vec<_> = target.collect();
+        assert_eq!(res.len(), 2);
+        assert!(res[0].is_ok());
+        let err = res[1].as_ref().unwrap_err();
+        assert!(
+            format!("{err:#}").contains("Failed to read record 1"),
+            "{err:#}"
+        );
+        let record_len = mem::size_of::<TradeMsg>() as u64;
+        assert!(
+            matches!(
+                Error::find(err),
+                Some(Error::Decode { record_index: 1, byte_offset, .. }) if *byte_offset == record_len
+            ),
+            "{err:?}"
+        );
+        // still truncated when not trusting `record_count`
+        let mut target = reencode_trades(2, false, |records| {
+            records.truncate(records.len() - 1);
+        })
+        .try_into_iter::<TradeMsg>()
+        .unwrap()
+        .with_size_hint_policy(SizeHintPolicy::Ignore);
+        assert_eq!(target.by_ref().count(), 1);
+        assert!(target.error().is_some());
+    }
+
+    #[test]
+    fn test_fallible_iter_wrong_rtype() {
+        let target = reencode_trades(2, false, |records| {
+            let record_len = mem::size_of::<TradeMsg>();
+            records[record_len + 1] = TickMsg::TYPE_ID;
+        })
+        .try_into_fallible_iter::<TradeMsg>()
+        .unwrap();
+        let res: Vec<_> = target.collect();
+        assert_eq!(res.len(), 2);
+        let err = res[1].as_ref().unwrap_err();
+        assert!(err.to_string().contains("Record 1 has rtype 0xa0"), "{err}");
+        // the position of the start of the record
+        let record_len = mem::size_of::<TradeMsg>() as u64;
+        assert!(
+            matches!(
+                Error::find(err),
+                Some(Error::Decode { record_index: 1, byte_offset, .. }) if *byte_offset == record_len
+            ),
+            "{err:?}"
+        );
+    }
+
+    /// Re-encodes the test trades with a longer record of an unknown rtype between them.
+    fn trades_with_unknown_record(
+        unknown_rtype_policy: UnknownRtypePolicy,
+    ) -> DbzStreamIter<io::Cursor<Vec<u8>>, TradeMsg> {
+        reencode_trades(3, false, |records| {
+            let record_len = mem::size_of::<TradeMsg>();
+            let mut unknown = vec![0; record_len + 16];
+            unknown[0] = (unknown.len() / 4) as u8;
+            unknown[1] = 0xEE;
+            records.splice(record_len..record_len, unknown);
+        })
+        .with_unknown_rtype_policy(unknown_rtype_policy)
+        .try_into_iter()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_unknown_rtype_policy() {
+        let mut target = trades_with_unknown_record(UnknownRtypePolicy::Error);
+        assert_eq!(target.by_ref().count(), 1);
+        assert!(target.error().unwrap().to_string().contains("rtype 0xee"));
+        assert!(target.unknown_rtypes().is_empty());
+
+        let mut target = trades_with_unknown_record(UnknownRtypePolicy::Skip);
+        assert_eq!(target.by_ref().count(), 2);
+        assert!(target.error().is_none());
+        assert_eq!(target.unknown_rtypes(), &BTreeMap::from([(0xEE, 1)]));
+        let stats = target.decode_stats();
+        assert_eq!(stats.record_count, 3);
+        assert_eq!(stats.unknown_rtype_count, 1);
+        assert_eq!(
+            stats.decompressed_bytes,
+            3 * mem::size_of::<TradeMsg>() as u64 + 16
+        );
+
+        let mut target = trades_with_unknown_record(UnknownRtypePolicy::Stop);
+        assert_eq!(target.by_ref().count(), 1);
+        assert!(target.error().is_none());
+        assert_eq!(target.unknown_rtypes(), &BTreeMap::from([(0xEE, 1)]));
+    }
+
+    #[test]
+    fn test_skip_unknown_rtype_too_short() {
+        let mut target = reencode_trades(2, false, |records| {
+            records[1] = 0xEE;
+            records[0] = 1;
+        })
+        .with_unknown_rtype_policy(UnknownRtypePolicy::Skip)
+        .try_into_iter::<TradeMsg>()
+        .unwrap();
+        assert_eq!(target.by_ref().count(), 0);
+        let err = target.error().unwrap().to_string();
+        assert!(err.contains("a length of 4 bytes"), "{err}");
+    }
 }
@@ -0,0 +1,360 @@
+//! Iteration over records whose type is only known at runtime.
+use std::{io, mem};
+
+use anyhow::anyhow;
+use databento_defs::{
+    enums::Schema,
+    record::{
+        transmute_record_bytes, ConstTypeId, Mbp10Msg, Mbp1Msg, OhlcvMsg, RecordHeader, StatusMsg,
+        SymDefMsg, TbboMsg, TickMsg, TradeMsg,
+    },
+};
+
+use crate::{
+    write::{dbz::as_u8_slice, write_json_object},
+    Dbz, DbzFallibleIter, DecodeStats,
+};
+
+/// A record of any schema, for handling records without knowing their type at
+/// compile time. The OHLCV schemas share one variant; the interval is in the
+/// metadata's `schema`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Record {
+    /// A record of [`Schema::Mbo`].
+    Mbo(TickMsg),
+    /// A record of [`Schema::Mbp1`].
+    Mbp1(Mbp1Msg),
+    /// A record of [`Schema::Mbp10`].
+    Mbp10(Mbp10Msg),
+    /// A record of [`Schema::Tbbo`].
+    Tbbo(TbboMsg),
+    /// A record of [`Schema::Trades`].
+    Trades(TradeMsg),
+    /// A record of any of the OHLCV schemas, like [`Schema::Ohlcv1D`].
+    Ohlcv(OhlcvMsg),
+    /// A record of [`Schema::Definition`].
+    Definition(SymDefMsg),
+    /// A record of [`Schema::Status`].
+    Status(StatusMsg),
+}
+
+impl Record {
+    /// Returns the header common to all records.
+    pub fn header(&self) -> &RecordHeader {
+        match self {
+            Record::Mbo(rec) => &rec.hd,
+            Record::Mbp1(rec) | Record::Tbbo(rec) => &rec.hd,
+            Record::Mbp10(rec) => &rec.hd,
+            Record::Trades(rec) => &rec.hd,
+            Record::Ohlcv(rec) => &rec.hd,
+            Record::Definition(rec) => &rec.hd,
+            Record::Status(rec) => &rec.hd,
+        }
+    }
+
+    /// Returns the capture-server-received timestamp of the record, if its type has
+    /// one.
+    pub fn ts_recv(&self) -> Option<u64> {
+        match self {
+            Record::Mbo(rec) => Some(rec.ts_recv),
+            Record::Mbp1(rec) | Record::Tbbo(rec) => Some(rec.ts_recv),
+            Record::Mbp10(rec) => Some(rec.ts_recv),
+            Record::Trades(rec) => Some(rec.ts_recv),
+            Record::Ohlcv(_) => None,
+            Record::Definition(rec) => Some(rec.ts_recv),
+            Record::Status(rec) => Some(rec.ts_recv),
+        }
+    }
+
+    /// Returns the venue's message sequence number of the record, if its type has
+    /// one.
+    pub fn sequence(&self) -> Option<u32> {
+        match self {
+            Record::Mbo(rec) => Some(rec.sequence),
+            Record::Mbp1(rec) | Record::Tbbo(rec) => Some(rec.sequence),
+            Record::Mbp10(rec) => Some(rec.sequence),
+            Record::Trades(rec) => Some(rec.sequence),
+            Record::Ohlcv(_) | Record::Definition(_) | Record::Status(_) => None,
+        }
+    }
+
+    /// Decodes a record of the type of `schema` from `bytes`, or returns `None` if
+    /// `bytes` has a different `rtype` or [`Schema::Statistics`] is passed.
+    ///
+    /// # Panics
+    /// This function panics if `bytes` is shorter than a record of `schema`.
+    pub(crate) fn from_bytes(schema: Schema, bytes: &[u8]) -> Option<Self> {
+        fn decode<T: ConstTypeId + Clone>(bytes: &[u8]) -> Option<T> {
+            // Safety: `transmute_record_bytes` checks the length of `bytes` and its rtype
+            unsafe { transmute_record_bytes::<T>(bytes) }.cloned()
+        }
+        let record = match schema {
+            Schema::Mbo => Record::Mbo(decode(bytes)?),
+            Schema::Mbp1 => Record::Mbp1(decode(bytes)?),
+            Schema::Mbp10 => Record::Mbp10(decode(bytes)?),
+            Schema::Tbbo => Record::Tbbo(decode(bytes)?),
+            Schema::Trades => Record::Trades(decode(bytes)?),
+            Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D => {
+                Record::Ohlcv(decode(bytes)?)
+            }
+            Schema::Definition => Record::Definition(decode(bytes)?),
+            Schema::Statistics => return None,
+            Schema::Status => Record::Status(decode(bytes)?),
+        };
+        Some(record)
+    }
+
+    /// Returns the record as a JSON object the same as it's output by
+    /// [`OutputEncoding::Json`](crate::OutputEncoding::Json) with the default options,
+    /// e.g. with the header nested under `hd` and timestamps as strings.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut buffer = Vec::new();
+        let res = match self {
+            Record::Mbo(rec) => write_json_object(&mut buffer, rec),
+            Record::Mbp1(rec) | Record::Tbbo(rec) => write_json_object(&mut buffer, rec),
+            Record::Mbp10(rec) => write_json_object(&mut buffer, rec),
+            Record::Trades(rec) => write_json_object(&mut buffer, rec),
+            Record::Ohlcv(rec) => write_json_object(&mut buffer, rec),
+            Record::Definition(rec) => write_json_object(&mut buffer, rec),
+            Record::Status(rec) => write_json_object(&mut buffer, rec),
+        };
+        // writing to a `Vec` can't fail and the JSON encoder writes valid JSON
+        res.and_then(|_| Ok(serde_json::from_slice(&buffer)?))
+            .expect("record to encode as valid JSON")
+    }
+
+    /// Returns the bytes of the record as they're encoded in DBZ.
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        // Safety: all record types are POD
+        unsafe {
+            match self {
+                Record::Mbo(rec) => as_u8_slice(rec),
+                Record::Mbp1(rec) | Record::Tbbo(rec) => as_u8_slice(rec),
+                Record::Mbp10(rec) => as_u8_slice(rec),
+                Record::Trades(rec) => as_u8_slice(rec),
+                Record::Ohlcv(rec) => as_u8_slice(rec),
+                Record::Definition(rec) => as_u8_slice(rec),
+                Record::Status(rec) => as_u8_slice(rec),
+            }
+        }
+    }
+}
+
+/// Returns the size in bytes of the records of `schema`, or `None` for
+/// [`Schema::Statistics`], which has no record type.
+pub(crate) fn record_size(schema: Schema) -> Option<usize> {
+    let size = match schema {
+        Schema::Mbo => mem::size_of::<TickMsg>(),
+        Schema::Mbp1 => mem::size_of::<Mbp1Msg>(),
+        Schema::Mbp10 => mem::size_of::<Mbp10Msg>(),
+        Schema::Tbbo => mem::size_of::<TbboMsg>(),
+        Schema::Trades => mem::size_of::<TradeMsg>(),
+        Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D => {
+            mem::size_of::<OhlcvMsg>()
+        }
+        Schema::Definition => mem::size_of::<SymDefMsg>(),
+        Schema::Statistics => return None,
+        Schema::Status => mem::size_of::<StatusMsg>(),
+    };
+    Some(size)
+}
+
+/// An iterator over the [`Record`]s of a [`Dbz`] of any schema. Like
+/// [`DbzFallibleIter`], it returns an error instead of ending early when the data is
+/// corrupt or truncated. This struct is created by the [`Dbz::into_record_iter`]
+/// method.
+pub struct DbzRecordIter<R: io::BufRead> {
+    inner: RecordIterInner<R>,
+}
+
+enum RecordIterInner<R: io::BufRead> {
+    Mbo(DbzFallibleIter<R, TickMsg>),
+    Mbp1(DbzFallibleIter<R, Mbp1Msg>),
+    Mbp10(DbzFallibleIter<R, Mbp10Msg>),
+    Tbbo(DbzFallibleIter<R, TbboMsg>),
+    Trades(DbzFallibleIter<R, TradeMsg>),
+    Ohlcv(DbzFallibleIter<R, OhlcvMsg>),
+    Definition(DbzFallibleIter<R, SymDefMsg>),
+    Status(DbzFallibleIter<R, StatusMsg>),
+}
+
+/// An iterator over the records of a [`Dbz`] of any schema as JSON objects, for
+/// generic tooling that doesn't need the record types. Like [`DbzRecordIter`], it
+/// returns an error instead of ending early when the data is corrupt or truncated.
+/// This struct is created by the [`Dbz::into_json_iter`] method.
+pub struct DbzJsonIter<R: io::BufRead> {
+    inner: DbzRecordIter<R>,
+}
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Try to decode the DBZ file into an iterator of its records as JSON objects, see
+    /// [`Record::to_json`].
+    ///
+    /// # Errors
+    /// This function returns an error under the same conditions as
+    /// [`Dbz::into_record_iter`].
+    pub fn into_json_iter(self) -> anyhow::Result<DbzJsonIter<R>> {
+        Ok(DbzJsonIter {
+            inner: self.into_record_iter()?,
+        })
+    }
+
+    /// Try to decode the DBZ file into an iterator of [`Record`]s of the type of
+    /// [`Dbz::schema()`], so the record type doesn't need to be known at compile
+    /// time.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`]. It will also return an error if the zstd portion of
+    /// the DBZ file was compressed in an unexpected manner.
+    pub fn into_record_iter(self) -> anyhow::Result<DbzRecordIter<R>> {
+        let inner = match self.schema() {
+            Schema::Mbo => RecordIterInner::Mbo(self.try_into_fallible_iter()?),
+            Schema::Mbp1 => RecordIterInner::Mbp1(self.try_into_fallible_iter()?),
+            Schema::Mbp10 => RecordIterInner::Mbp10(self.try_into_fallible_iter()?),
+            Schema::Tbbo => RecordIterInner::Tbbo(self.try_into_fallible_iter()?),
+            Schema::Trades => RecordIterInner::Trades(self.try_into_fallible_iter()?),
+            Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D => {
+                RecordIterInner::Ohlcv(self.try_into_fallible_iter()?)
+            }
+            Schema::Definition => RecordIterInner::Definition(self.try_into_fallible_iter()?),
+            Schema::Statistics => return Err(anyhow!("Not implemented for schema Statistics")),
+            Schema::Status => RecordIterInner::Status(self.try_into_fallible_iter()?),
+        };
+        Ok(DbzRecordIter { inner })
+    }
+}
+
+impl<R: io::BufRead> DbzRecordIter<R> {
+    /// Returns counters of the decoding so far, see
+    /// [`DbzStreamIter::decode_stats`](crate::DbzStreamIter::decode_stats).
+    pub fn decode_stats(&self) -> DecodeStats {
+        match &self.inner {
+            RecordIterInner::Mbo(iter) => iter.decode_stats(),
+            RecordIterInner::Mbp1(iter) => iter.decode_stats(),
+            RecordIterInner::Mbp10(iter) => iter.decode_stats(),
+            RecordIterInner::Tbbo(iter) => iter.decode_stats(),
+            RecordIterInner::Trades(iter) => iter.decode_stats(),
+            RecordIterInner::Ohlcv(iter) => iter.decode_stats(),
+            RecordIterInner::Definition(iter) => iter.decode_stats(),
+            RecordIterInner::Status(iter) => iter.decode_stats(),
+        }
+    }
+}
+
+impl<R: io::BufRead> Iterator for DbzRecordIter<R> {
+    type Item = anyhow::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let res = match &mut self.inner {
+            RecordIterInner::Mbo(iter) => iter.next()?.map(Record::Mbo),
+            RecordIterInner::Mbp1(iter) => iter.next()?.map(Record::Mbp1),
+            RecordIterInner::Mbp10(iter) => iter.next()?.map(Record::Mbp10),
+            RecordIterInner::Tbbo(iter) => iter.next()?.map(Record::Tbbo),
+            RecordIterInner::Trades(iter) => iter.next()?.map(Record::Trades),
+            RecordIterInner::Ohlcv(iter) => iter.next()?.map(Record::Ohlcv),
+            RecordIterInner::Definition(iter) => iter.next()?.map(Record::Definition),
+            RecordIterInner::Status(iter) => iter.next()?.map(Record::Status),
+        };
+        Some(res)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.inner {
+            RecordIterInner::Mbo(iter) => iter.size_hint(),
+            RecordIterInner::Mbp1(iter) => iter.size_hint(),
+            RecordIterInner::Mbp10(iter) => iter.size_hint(),
+            RecordIterInner::Tbbo(iter) => iter.size_hint(),
+            RecordIterInner::Trades(iter) => iter.size_hint(),
+            RecordIterInner::Ohlcv(iter) => iter.size_hint(),
+            RecordIterInner::Definition(iter) => iter.size_hint(),
+            RecordIterInner::Status(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl<R: io::BufRead> DbzJsonIter<R> {
+    /// Returns counters of the decoding so far, see
+    /// [`DbzStreamIter::decode_stats`](crate::DbzStreamIter::decode_stats).
+    pub fn decode_stats(&self) -> DecodeStats {
+        self.inner.decode_stats()
+    }
+}
+
+impl<R: io::BufRead> Iterator for DbzJsonIter<R> {
+    type Item = anyhow::Result<serde_json::Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.inner.next()?.map(|record| record.to_json()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OutputEncoding;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    #[test]
+    fn test_into_record_iter() {
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.tbbo.dbz"))
+            .unwrap()
+            .into_record_iter()
+            .unwrap();
+        let records = target.collect::<anyhow::Result<Vec<_>>>().unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(matches!(records[0], Record::Tbbo(_)));
+        assert_eq!(records[0].header().product_id, 5482);
+    }
+
+    #[test]
+    fn test_into_record_iter_ohlcv() {
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.ohlcv-1m.dbz"))
+            .unwrap()
+            .into_record_iter()
+            .unwrap();
+        let volumes: Vec<_> = target
+            .map(|record| match record.unwrap() {
+                Record::Ohlcv(ohlcv) => ohlcv.volume,
+                record => panic!("Unexpected record {record:?}"),
+            })
+            .collect();
+        assert_eq!(volumes, vec![353, 152]);
+    }
+
+    #[test]
+    fn test_into_json_iter() {
+        for schema in ["mbo", "mbp-10", "ohlcv-1h", "tbbo"] {
+            let path = format!("{DBZ_PATH}/test_data.{schema}.dbz");
+            let mut expected = Vec::new();
+            Dbz::from_file(&path)
+                .unwrap()
+                .write_to(
+                    &mut expected,
+                    OutputEncoding::Json {
+                        should_pretty_print: false,
+                        should_output_array: false,
+                        should_output_iso_timestamps: false,
+                        should_output_decimal_prices: false,
+                    },
+                )
+                .unwrap();
+            let expected = serde_json::Deserializer::from_slice(&expected)
+                .into_iter::<serde_json::Value>()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            let values = Dbz::from_file(&path)
+                .unwrap()
+                .into_json_iter()
+                .unwrap()
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(values, expected, "{schema}");
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Sampling of DBZ files into small fixtures with the same schema and metadata.
+use std::{collections::VecDeque, io};
+
+use anyhow::anyhow;
+use databento_defs::{
+    enums::Schema,
+    record::{ConstTypeId, Mbp10Msg, Mbp1Msg, OhlcvMsg, StatusMsg, SymDefMsg, TickMsg, TradeMsg},
+};
+use streaming_iterator::StreamingIterator;
+
+use crate::{write_dbz, Dbz, Metadata};
+
+/// Which records of a DBZ file to keep in a sample.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SampleOptions {
+    /// The number of records to keep from the start of the file.
+    pub head: usize,
+    /// The number of records to keep from the end of the file.
+    pub tail: usize,
+    /// The number of records to sample uniformly at random from between the head and
+    /// the tail.
+    pub reservoir: usize,
+    /// The seed for choosing the reservoir sample. The same seed and input always
+    /// produce the same sample.
+    pub seed: u64,
+}
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Writes a sample of the records as `options` describe in the DBZ format to
+    /// `writer`, for example to generate test data from a large file. The sampled
+    /// records keep their original order and the metadata, including the symbol
+    /// mappings, is kept as is except for `record_count`.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`]. It will also return an error if there's an issue
+    /// reading the records or writing the output to `writer`.
+    pub fn write_sample_to(
+        self,
+        writer: impl io::Write + io::Seek,
+        options: &SampleOptions,
+    ) -> anyhow::Result<()> {
+        match self.schema() {
+            Schema::Mbo => self.write_sample_of::<TickMsg>(writer, options),
+            Schema::Mbp1 | Schema::Tbbo => self.write_sample_of::<Mbp1Msg>(writer, options),
+            Schema::Mbp10 => self.write_sample_of::<Mbp10Msg>(writer, options),
+            Schema::Trades => self.write_sample_of::<TradeMsg>(writer, options),
+            Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D => {
+                self.write_sample_of::<OhlcvMsg>(writer, options)
+            }
+            Schema::Definition => self.write_sample_of::<SymDefMsg>(writer, options),
+            Schema::Statistics => Err(anyhow!("Not implemented for schema Statistics")),
+            Schema::Status => self.write_sample_of::<StatusMsg>(writer, options),
+        }
+    }
+
+    fn write_sample_of<T: ConstTypeId + Clone>(
+        self,
+        mut writer: impl io::Write + io::Seek,
+        options: &SampleOptions,
+    ) -> anyhow::Result<()> {
+        let metadata = self.metadata().clone();
+        let records = sample(self.try_into_iter::<T>()?, options);
+        metadata.encode(&mut writer)?;
+        write_dbz(&mut writer, records.iter())?;
+        Metadata::update_encoded(
+            writer,
+            metadata.start,
+            metadata.end,
+            metadata.limit,
+            records.len() as u64,
+        )
+    }
+}
+
+fn sample<T: Clone>(mut iter: impl StreamingIterator<Item = T>, options: &SampleOptions) -> Vec<T> {
+    let mut head = Vec::with_capacity(options.head);
+    let mut tail = VecDeque::with_capacity(options.tail);
+    // records evicted from `tail` are in the middle, tagged with their position so the
+    // sample can be put back in order
+    let mut reservoir: Vec<(usize, T)> = Vec::with_capacity(options.reservoir);
+    let mut middle_count = 0;
+    let mut rng = SplitMix64(options.seed);
+    while let Some(record) = iter.next() {
+        if head.len() < options.head {
+            head.push(record.clone());
+            continue;
+        }
+        tail.push_back(record.clone());
+        if tail.len() <= options.tail {
+            continue;
+        }
+        let middle = tail.pop_front().unwrap();
+        // Algorithm R: the n-th middle record replaces a random sampled one with
+        // probability reservoir / n
+        if reservoir.len() < options.reservoir {
+            reservoir.push((middle_count, middle));
+        } else {
+            let i = rng.below(middle_count as u64 + 1) as usize;
+            if i < options.reservoir {
+                reservoir[i] = (middle_count, middle);
+            }
+        }
+        middle_count += 1;
+    }
+    reservoir.sort_unstable_by_key(|(i, _)| *i);
+    head.into_iter()
+        .chain(reservoir.into_iter().map(|(_, record)| record))
+        .chain(tail)
+        .collect()
+}
+
+/// A small, seedable pseudorandom number generator. Cryptographic quality isn't needed
+/// for sampling.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `0..n`.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    use super::*;
+    use crate::write::test_data::VecStream;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    fn options(head: usize, tail: usize, reservoir: usize) -> SampleOptions {
+        SampleOptions {
+            head,
+            tail,
+            reservoir,
+            seed: 7,
+        }
+    }
+
+    #[test]
+    fn test_sample_head_and_tail() {
+        let target = sample(VecStream::new((0..10).collect()), &options(2, 3, 0));
+        assert_eq!(target, vec![0, 1, 7, 8, 9]);
+    }
+
+    #[test]
+    fn test_sample_reservoir() {
+        let target = sample(VecStream::new((0..100).collect()), &options(1, 1, 5));
+        assert_eq!(target.len(), 7);
+        assert_eq!(target[0], 0);
+        assert_eq!(target[6], 99);
+        assert!(target.windows(2).all(|pair| pair[0] < pair[1]));
+        // deterministic for a given seed
+        assert_eq!(
+            sample(VecStream::new((0..100).collect()), &options(1, 1, 5)),
+            target
+        );
+    }
+
+    #[test]
+    fn test_sample_fewer_records_than_requested() {
+        let target = sample(VecStream::new((0..4).collect()), &options(3, 3, 3));
+        assert_eq!(target, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_write_sample_to() {
+        let source = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        let metadata = source.metadata().clone();
+        let mut output = Cursor::new(Vec::new());
+        source
+            .write_sample_to(&mut output, &options(0, 1, 0))
+            .unwrap();
+        output.seek(SeekFrom::Start(0)).unwrap();
+        let target = Dbz::new(output).unwrap();
+        assert_eq!(target.metadata().record_count, 1);
+        assert_eq!(target.metadata().mappings, metadata.mappings);
+        assert_eq!(target.metadata().start, metadata.start);
+        let mut iter = target.try_into_iter::<TickMsg>().unwrap();
+        let last = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz"))
+            .unwrap()
+            .try_into_iter::<TickMsg>()
+            .unwrap()
+            // the file has 2 records
+            .nth(1)
+            .cloned()
+            .unwrap();
+        assert_eq!(iter.next(), Some(&last));
+        assert!(iter.next().is_none());
+    }
+}
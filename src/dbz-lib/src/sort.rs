@@ -0,0 +1,324 @@
+//! Sorting the records of DBZ files too large to sort in memory.
+use std::{
+    cmp,
+    fs::{self, File},
+    io::{self, BufWriter},
+    mem,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::Context;
+use databento_defs::enums::Compression;
+
+use crate::{
+    query::MAX_BATCH_SIZE, write::dbz::write_records, Dbz, DbzRangeIter, Metadata, Record,
+    RecordOrder, TieBreak,
+};
+
+/// The default memory budget for sorting, 1 GiB.
+const DEFAULT_MAX_MEMORY: usize = 1 << 30;
+
+/// Options for [`Dbz::write_sorted_to`].
+#[derive(Clone, Debug)]
+pub struct SortOptions {
+    /// The order to sort the records in.
+    pub order: RecordOrder,
+    /// How records that compare equal in `order` are ordered.
+    pub tie_break: TieBreak,
+    /// An upper bound in bytes on the memory used for holding records while sorting.
+    /// Records beyond it are sorted in runs that are written to `temp_dir` and then
+    /// merged. Merging also needs an estimated 2.1 MiB per run for decoding.
+    pub max_memory: usize,
+    /// The directory to write the sorted runs to, or `None` for the system's
+    /// temporary directory.
+    pub temp_dir: Option<PathBuf>,
+}
+
+impl Default for SortOptions {
+    fn default() -> Self {
+        Self {
+            order: RecordOrder::default(),
+            tie_break: TieBreak::default(),
+            max_memory: DEFAULT_MAX_MEMORY,
+            temp_dir: None,
+        }
+    }
+}
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Sorts the records in `options.order` and writes them in the DBZ format to
+    /// `writer`, e.g. to normalize an out-of-order capture. Records that compare
+    /// equal are ordered by `options.tie_break`, which by default keeps their original
+    /// order. Returns the number of records written.
+    ///
+    /// If the records don't fit in `options.max_memory`, they're sorted with an
+    /// external merge sort: each run of records that fits is sorted and written
+    /// compressed to a temporary file in `options.temp_dir`, then the runs are merged.
+    /// The temporary files are removed once sorting finishes or fails.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`](databento_defs::enums::Schema::Statistics) or a record
+    /// can't be decoded. It will also return an error if there's an issue writing the
+    /// temporary files or to `writer`.
+    pub fn write_sorted_to(
+        self,
+        writer: impl io::Write + io::Seek,
+        options: &SortOptions,
+    ) -> anyhow::Result<u64> {
+        let mut metadata = self.metadata().clone();
+        // the records are always compressed
+        metadata.compression = Compression::ZStd;
+        let run_len = (options.max_memory / mem::size_of::<Record>()).max(1);
+        let mut runs = SortRuns::new(options);
+        let mut run = Vec::new();
+        for record in self.into_record_iter()? {
+            run.push(record?);
+            if run.len() == run_len {
+                runs.write(&mut run, &metadata)?;
+            }
+        }
+        if runs.paths.is_empty() {
+            // everything fit in memory
+            run.sort_by(|left, right| options.compare(left, right));
+            return write_records(writer, &metadata, run.into_iter().map(Ok));
+        }
+        if !run.is_empty() {
+            runs.write(&mut run, &metadata)?;
+        }
+        drop(run);
+        let records = runs
+            .paths
+            .iter()
+            .map(|path| Dbz::from_file(path)?.into_record_iter())
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        // each run holds up to two batches: the one being merged and the one being
+        // decoded
+        let batch_size = (options.max_memory / records.len() / (2 * mem::size_of::<Record>()))
+            .clamp(1, MAX_BATCH_SIZE);
+        // runs are merged in the order they were written, so ties across runs also
+        // keep their original order
+        let records = DbzRangeIter::new(
+            records,
+            options.order,
+            options.tie_break,
+            batch_size,
+            0,
+            u64::MAX,
+        )?;
+        write_records(writer, &metadata, records)
+    }
+}
+
+impl SortOptions {
+    /// Compares `left` and `right` in `self.order`, then by `self.tie_break`.
+    fn compare(&self, left: &Record, right: &Record) -> cmp::Ordering {
+        self.order
+            .compare(left, right)
+            .then_with(|| self.tie_break.compare(left, right))
+    }
+}
+
+/// Distinguishes the runs of concurrent sorts in the same process.
+static SORT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// The temporary files of the sorted runs of an external sort, which are removed
+/// when dropped.
+struct SortRuns<'a> {
+    options: &'a SortOptions,
+    id: usize,
+    paths: Vec<PathBuf>,
+}
+
+impl<'a> SortRuns<'a> {
+    fn new(options: &'a SortOptions) -> Self {
+        Self {
+            options,
+            id: SORT_ID.fetch_add(1, Ordering::Relaxed),
+            paths: Vec::new(),
+        }
+    }
+
+    /// Sorts `run` and writes it to a new temporary file, leaving `run` empty.
+    fn write(&mut self, run: &mut Vec<Record>, metadata: &Metadata) -> anyhow::Result<()> {
+        run.sort_by(|left, right| self.options.compare(left, right));
+        let dir = self
+            .options
+            .temp_dir
+            .clone()
+            .unwrap_or_else(std::env::temp_dir);
+        let path = dir.join(format!(
+            "dbz-sort-{}-{}-{}.dbz",
+            std::process::id(),
+            self.id,
+            self.paths.len()
+        ));
+        let file = File::create(&path)
+            .with_context(|| format!("Unable to create sort run file '{}'", path.display()))?;
+        self.paths.push(path);
+        write_records(BufWriter::new(file), metadata, run.drain(..).map(Ok))?;
+        Ok(())
+    }
+}
+
+impl Drop for SortRuns<'_> {
+    fn drop(&mut self) {
+        for path in self.paths.iter() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    use databento_defs::record::TradeMsg;
+    use streaming_iterator::StreamingIterator;
+
+    use super::*;
+    use crate::DbzWriter;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    /// Returns trades in descending `ts_event` order, with pairs of equal `ts_event`s
+    /// distinguished by their `sequence`.
+    fn unsorted_trades() -> Cursor<Vec<u8>> {
+        let dbz = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let metadata = dbz.metadata().clone();
+        let template = dbz
+            .try_into_iter::<TradeMsg>()
+            .unwrap()
+            .next()
+            .unwrap()
+            .clone();
+        let mut writer = DbzWriter::new(Cursor::new(Vec::new()), &metadata).unwrap();
+        for i in 0..10 {
+            let mut record = template.clone();
+            record.hd.ts_event = metadata.start + (9 - i) / 2;
+            record.sequence = i as u32;
+            writer.write_record(&record).unwrap();
+        }
+        let mut buffer = writer.finish().unwrap();
+        buffer.seek(SeekFrom::Start(0)).unwrap();
+        buffer
+    }
+
+    fn assert_sorted(output: Cursor<Vec<u8>>) {
+        let records = Dbz::new(output)
+            .unwrap()
+            .try_into_fallible_iter::<TradeMsg>()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(records.len(), 10);
+        let order = records
+            .iter()
+            .map(|rec| (rec.hd.ts_event, rec.sequence))
+            .collect::<Vec<_>>();
+        let mut sorted = order.clone();
+        // the sequence is the original position, so equal `ts_event`s keep their
+        // original order
+        sorted.sort();
+        assert_eq!(order, sorted);
+    }
+
+    /// Returns trades with the same `ts_event` and the product IDs `product_ids`,
+    /// distinguished by their `sequence`, which is their position.
+    fn tied_trades(product_ids: &[u32]) -> Cursor<Vec<u8>> {
+        let dbz = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let metadata = dbz.metadata().clone();
+        let template = dbz
+            .try_into_iter::<TradeMsg>()
+            .unwrap()
+            .next()
+            .unwrap()
+            .clone();
+        let mut writer = DbzWriter::new(Cursor::new(Vec::new()), &metadata).unwrap();
+        for (i, product_id) in product_ids.iter().enumerate() {
+            let mut record = template.clone();
+            record.hd.product_id = *product_id;
+            record.sequence = i as u32;
+            writer.write_record(&record).unwrap();
+        }
+        let mut buffer = writer.finish().unwrap();
+        buffer.seek(SeekFrom::Start(0)).unwrap();
+        buffer
+    }
+
+    fn sorted_sequences(input: Cursor<Vec<u8>>, options: &SortOptions) -> Vec<u32> {
+        let mut output = Cursor::new(Vec::new());
+        Dbz::new(input)
+            .unwrap()
+            .write_sorted_to(&mut output, options)
+            .unwrap();
+        output.seek(SeekFrom::Start(0)).unwrap();
+        Dbz::new(output)
+            .unwrap()
+            .try_into_fallible_iter::<TradeMsg>()
+            .unwrap()
+            .map(|rec| rec.unwrap().sequence)
+            .collect()
+    }
+
+    #[test]
+    fn test_sort_tie_break() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        for max_memory in [DEFAULT_MAX_MEMORY, mem::size_of::<Record>()] {
+            let options = SortOptions {
+                max_memory,
+                temp_dir: Some(temp_dir.path().to_owned()),
+                ..Default::default()
+            };
+            assert_eq!(
+                sorted_sequences(tied_trades(&[3, 1, 2, 1]), &options),
+                vec![0, 1, 2, 3]
+            );
+            let options = SortOptions {
+                tie_break: TieBreak::Canonical,
+                ..options
+            };
+            // by product ID, then by sequence, the first differing byte
+            assert_eq!(
+                sorted_sequences(tied_trades(&[3, 1, 2, 1]), &options),
+                vec![1, 3, 2, 0]
+            );
+        }
+    }
+
+    #[test]
+    fn test_sort_in_memory() {
+        let mut output = Cursor::new(Vec::new());
+        let count = Dbz::new(unsorted_trades())
+            .unwrap()
+            .write_sorted_to(&mut output, &SortOptions::default())
+            .unwrap();
+        assert_eq!(count, 10);
+        output.seek(SeekFrom::Start(0)).unwrap();
+        assert_sorted(output);
+    }
+
+    #[test]
+    fn test_external_sort() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let options = SortOptions {
+            // runs of 3 records
+            max_memory: 3 * mem::size_of::<Record>(),
+            temp_dir: Some(temp_dir.path().to_owned()),
+            ..Default::default()
+        };
+        let mut output = Cursor::new(Vec::new());
+        let count = Dbz::new(unsorted_trades())
+            .unwrap()
+            .write_sorted_to(&mut output, &options)
+            .unwrap();
+        assert_eq!(count, 10);
+        output.seek(SeekFrom::Start(0)).unwrap();
+        let metadata = Dbz::new(output.clone()).unwrap().metadata().clone();
+        assert_eq!(metadata.record_count, 10);
+        assert_sorted(output);
+        // the runs were removed
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,750 @@
+//! A machine-readable description of the byte-level DBZ format, generated from the
+//! constants and record layouts used for encoding and decoding.
+use std::{any, io, mem};
+
+use anyhow::anyhow;
+
+use databento_defs::{
+    enums::{Compression, SType, Schema},
+    record::{
+        BidAskPair, ConstTypeId, Mbp10Msg, Mbp1Msg, OhlcvMsg, RecordHeader, StatusMsg, SymDefMsg,
+        TickMsg, TradeMsg,
+    },
+};
+use serde::Serialize;
+
+use crate::{write::dbz::SCHEMA_VERSION, Metadata};
+
+/// The byte-level layout of the DBZ format, for generating third-party
+/// implementations and validators. All integers are little-endian.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FormatSpec {
+    /// The DBZ schema version described. Version 1 is the only version.
+    pub version: u8,
+    /// The layout of the metadata.
+    pub metadata: MetadataSpec,
+    /// The record type of each schema, identified by its `rtype`.
+    pub schemas: Vec<SchemaSpec>,
+    /// The layouts of the records and the structs they contain.
+    pub structs: Vec<StructSpec>,
+    /// The values of `compression` in the metadata.
+    pub compressions: Vec<EnumValueSpec>,
+    /// The values of `stype_in` and `stype_out` in the metadata.
+    pub stypes: Vec<EnumValueSpec>,
+}
+
+/// The layout of the metadata at the start of a DBZ file, a Zstd skippable frame.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MetadataSpec {
+    /// The fields with fixed offsets from the start of the file.
+    pub fixed: Vec<FieldSpec>,
+    /// The fields of the Zstd-compressed part following the fixed fields, in order.
+    /// Their offsets depend on the preceding repeated fields.
+    pub variable: Vec<VariableFieldSpec>,
+    /// The layout of `schema_definition`, which fills the `schema_definition_length`
+    /// bytes after its length and is absent if that's 0.
+    pub schema_definition: Vec<VariableFieldSpec>,
+    /// The layout of each element of a schema definition's `fields`, whose offsets
+    /// are from the start of the record.
+    pub field_definition: Vec<VariableFieldSpec>,
+    /// The layout of each element of `mappings`.
+    pub mapping: Vec<VariableFieldSpec>,
+    /// The layout of each element of a mapping's `intervals`. Dates are encoded as
+    /// YYYYMMDD integers.
+    pub interval: Vec<FieldSpec>,
+    /// The layout of each element of `annotations`, UTF-8 strings prefixed by their
+    /// length in bytes. Metadata without annotations may end after `mappings`.
+    pub annotation: Vec<VariableFieldSpec>,
+}
+
+/// A field at a fixed offset.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FieldSpec {
+    /// The name of the field.
+    pub name: &'static str,
+    /// The offset in bytes from the start of the containing struct or file.
+    pub offset: usize,
+    /// The size of the field in bytes.
+    pub size: usize,
+    /// The Rust type of the field, with `c_char` as `i8`.
+    #[serde(rename = "type")]
+    pub type_name: String,
+}
+
+/// A field whose offset depends on the fields before it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct VariableFieldSpec {
+    /// The name of the field.
+    pub name: &'static str,
+    /// The Rust type of the field, or of each element if it's repeated.
+    #[serde(rename = "type")]
+    pub type_name: String,
+    /// The name of the field holding the number of elements, if the field is
+    /// repeated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<&'static str>,
+}
+
+/// How the records of a schema are encoded.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SchemaSpec {
+    /// The name of the schema, like `mbp-1`.
+    pub name: &'static str,
+    /// The value of the schema in the metadata.
+    pub value: u16,
+    /// The name of the record struct, or `None` if records of the schema can't be
+    /// decoded.
+    pub record: Option<&'static str>,
+    /// The `rtype` in the header of the records, or `None` if records of the schema
+    /// can't be decoded.
+    pub rtype: Option<u8>,
+}
+
+/// The layout of a `#[repr(C)]` struct.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StructSpec {
+    /// The name of the struct.
+    pub name: &'static str,
+    /// The size of the struct in bytes, including padding.
+    pub size: usize,
+    /// The fields of the struct in order of their offsets.
+    pub fields: Vec<FieldSpec>,
+}
+
+/// The name and value of a variant of an enum encoded in the metadata.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct EnumValueSpec {
+    /// The name of the variant.
+    pub name: &'static str,
+    /// The value of the variant in the metadata.
+    pub value: u8,
+}
+
+/// Returns the [`StructSpec`] of a struct from its field names.
+macro_rules! struct_spec {
+    ($struct:ident { $($field:ident),* $(,)? }) => {
+        StructSpec {
+            name: stringify!($struct),
+            size: mem::size_of::<$struct>(),
+            fields: vec![$(FieldSpec {
+                name: stringify!($field),
+                offset: mem::offset_of!($struct, $field),
+                size: field_size(|s: &$struct| &s.$field),
+                type_name: field_type_name(|s: &$struct| &s.$field),
+            }),*],
+        }
+    };
+}
+
+fn field_size<S, F>(_field: fn(&S) -> &F) -> usize {
+    mem::size_of::<F>()
+}
+
+fn field_type_name<S, F>(_field: fn(&S) -> &F) -> String {
+    type_name::<F>()
+}
+
+/// Returns the name of `T` without module paths, e.g. `[BidAskPair; 10]`.
+fn type_name<T>() -> String {
+    let mut rest = any::type_name::<T>();
+    let mut name = String::with_capacity(rest.len());
+    while let Some(i) = rest.find("::") {
+        let prefix = &rest[..i];
+        // drop the module, keeping any brackets before it
+        let module_start = prefix
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map_or(0, |j| j + 1);
+        name.push_str(&prefix[..module_start]);
+        rest = &rest[i + 2..];
+    }
+    name.push_str(rest);
+    name
+}
+
+impl FormatSpec {
+    /// Returns the description of the current DBZ version.
+    pub fn new() -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            metadata: MetadataSpec::new(),
+            schemas: SCHEMAS
+                .iter()
+                .map(|&schema| SchemaSpec::new(schema))
+                .collect(),
+            structs: vec![
+                struct_spec!(RecordHeader {
+                    length,
+                    rtype,
+                    publisher_id,
+                    product_id,
+                    ts_event,
+                }),
+                struct_spec!(BidAskPair {
+                    bid_px,
+                    ask_px,
+                    bid_sz,
+                    ask_sz,
+                    bid_ct,
+                    ask_ct,
+                }),
+                struct_spec!(TickMsg {
+                    hd,
+                    order_id,
+                    price,
+                    size,
+                    flags,
+                    channel_id,
+                    action,
+                    side,
+                    ts_recv,
+                    ts_in_delta,
+                    sequence,
+                }),
+                struct_spec!(TradeMsg {
+                    hd,
+                    price,
+                    size,
+                    action,
+                    side,
+                    flags,
+                    depth,
+                    ts_recv,
+                    ts_in_delta,
+                    sequence,
+                    booklevel,
+                }),
+                struct_spec!(Mbp1Msg {
+                    hd,
+                    price,
+                    size,
+                    action,
+                    side,
+                    flags,
+                    depth,
+                    ts_recv,
+                    ts_in_delta,
+                    sequence,
+                    booklevel,
+                }),
+                struct_spec!(Mbp10Msg {
+                    hd,
+                    price,
+                    size,
+                    action,
+                    side,
+                    flags,
+                    depth,
+                    ts_recv,
+                    ts_in_delta,
+                    sequence,
+                    booklevel,
+                }),
+                struct_spec!(OhlcvMsg {
+                    hd,
+                    open,
+                    high,
+                    low,
+                    close,
+                    volume,
+                }),
+                struct_spec!(StatusMsg {
+                    hd,
+                    ts_recv,
+                    group,
+                    trading_status,
+                    halt_reason,
+                    trading_event,
+                }),
+                struct_spec!(SymDefMsg {
+                    hd,
+                    ts_recv,
+                    min_price_increment,
+                    display_factor,
+                    expiration,
+                    activation,
+                    high_limit_price,
+                    low_limit_price,
+                    max_price_variation,
+                    trading_reference_price,
+                    unit_of_measure_qty,
+                    min_price_increment_amount,
+                    price_ratio,
+                    inst_attrib_value,
+                    underlying_id,
+                    cleared_volume,
+                    market_depth_implied,
+                    market_depth,
+                    market_segment_id,
+                    max_trade_vol,
+                    min_lot_size,
+                    min_lot_size_block,
+                    min_lot_size_round_lot,
+                    min_trade_vol,
+                    open_interest_qty,
+                    contract_multiplier,
+                    decay_quantity,
+                    original_contract_size,
+                    related_security_id,
+                    trading_reference_date,
+                    appl_id,
+                    maturity_month_year,
+                    decay_start_date,
+                    chan,
+                    currency,
+                    settl_currency,
+                    secsubtype,
+                    symbol,
+                    group,
+                    exchange,
+                    asset,
+                    cfi,
+                    security_type,
+                    unit_of_measure,
+                    underlying,
+                    related,
+                    match_algorithm,
+                    md_security_trading_status,
+                    main_fraction,
+                    price_display_format,
+                    settl_price_type,
+                    sub_fraction,
+                    underlying_product,
+                    security_update_action,
+                    maturity_month_month,
+                    maturity_month_day,
+                    maturity_month_week,
+                    user_defined_instrument,
+                    contract_multiplier_unit,
+                    flow_schedule_type,
+                    tick_rule,
+                    _dummy,
+                }),
+            ],
+            compressions: [Compression::None, Compression::ZStd]
+                .into_iter()
+                .map(|compression| EnumValueSpec {
+                    name: compression.as_str(),
+                    value: compression as u8,
+                })
+                .collect(),
+            stypes: [SType::ProductId, SType::Native, SType::Smart]
+                .into_iter()
+                .map(|stype| EnumValueSpec {
+                    name: stype.as_str(),
+                    value: stype as u8,
+                })
+                .collect(),
+        }
+    }
+
+    /// Writes the description as pretty-printed JSON to `writer`.
+    ///
+    /// # Errors
+    /// This function returns an error if there's an issue writing to `writer`.
+    pub fn write_json(&self, mut writer: impl io::Write) -> anyhow::Result<()> {
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Writes a C header declaring the record structs to `writer`, so C and C++ code
+    /// can cast the bytes of decompressed records to them. Each struct is followed by
+    /// static assertions of its size and field offsets, so a mismatched compiler
+    /// layout fails to compile rather than misreading records. The header also
+    /// defines the DBZ version, the value of each schema, and the `rtype` of each
+    /// record. Arrays of `i8`, which hold C strings, are declared as `char` arrays.
+    ///
+    /// # Errors
+    /// This function returns an error if there's an issue writing to `writer`.
+    pub fn write_c_header(&self, mut writer: impl io::Write) -> anyhow::Result<()> {
+        let guard = format!("DBZ_V{}_H", self.version);
+        writeln!(
+            writer,
+            "/* Record layouts of DBZ version {}, generated by `dbz spec --c-header`. */",
+            self.version
+        )?;
+        writeln!(writer, "#ifndef {guard}\n#define {guard}\n")?;
+        writeln!(writer, "#include <stddef.h>\n#include <stdint.h>\n")?;
+        writeln!(
+            writer,
+            "#ifdef __cplusplus\n\
+            #define DBZ_STATIC_ASSERT(cond, msg) static_assert(cond, msg)\n\
+            #else\n\
+            #define DBZ_STATIC_ASSERT(cond, msg) _Static_assert(cond, msg)\n\
+            #endif\n"
+        )?;
+        writeln!(writer, "#define DBZ_VERSION {}\n", self.version)?;
+        for schema in self.schemas.iter() {
+            writeln!(
+                writer,
+                "#define DBZ_SCHEMA_{} {}",
+                c_macro_name(schema.name),
+                schema.value
+            )?;
+        }
+        writeln!(writer)?;
+        let mut records = Vec::new();
+        for schema in self.schemas.iter() {
+            if let (Some(record), Some(rtype)) = (schema.record, schema.rtype) {
+                if !records.contains(&record) {
+                    writeln!(
+                        writer,
+                        "#define DBZ_RTYPE_{} {rtype:#04x}",
+                        c_macro_name(record)
+                    )?;
+                    records.push(record);
+                }
+            }
+        }
+        for spec in self.structs.iter() {
+            writeln!(writer, "\ntypedef struct {} {{", spec.name)?;
+            for field in spec.fields.iter() {
+                let (element, len) = parse_type_name(&field.type_name);
+                let c_type = if self.structs.iter().any(|spec| spec.name == element) {
+                    element.to_owned()
+                } else if element == "i8" && len.is_some() {
+                    "char".to_owned()
+                } else {
+                    c_type_name(element, field.size / len.unwrap_or(1).max(1))?
+                };
+                match len {
+                    Some(0) => writeln!(writer, "    /* {} has no elements */", field.name)?,
+                    Some(len) => writeln!(writer, "    {c_type} {}[{len}];", field.name)?,
+                    None => writeln!(writer, "    {c_type} {};", field.name)?,
+                }
+            }
+            writeln!(writer, "}} {};", spec.name)?;
+            writeln!(
+                writer,
+                "DBZ_STATIC_ASSERT(sizeof({0}) == {1}, \"{0} must be {1} bytes\");",
+                spec.name, spec.size
+            )?;
+            for field in spec.fields.iter().filter(|field| field.size > 0) {
+                writeln!(
+                    writer,
+                    "DBZ_STATIC_ASSERT(offsetof({0}, {1}) == {2}, \"{0}.{1} must be at offset {2}\");",
+                    spec.name, field.name, field.offset
+                )?;
+            }
+        }
+        writeln!(writer, "\n#endif /* {guard} */")?;
+        Ok(())
+    }
+}
+
+/// Splits a type name like `[i8; 4]` into the element type and the number of
+/// elements, or `None` if it's not an array.
+pub(crate) fn parse_type_name(type_name: &str) -> (&str, Option<usize>) {
+    type_name
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .and_then(|array| array.split_once("; "))
+        .and_then(|(element, len)| Some((element, Some(len.parse().ok()?))))
+        .unwrap_or((type_name, None))
+}
+
+/// Returns the C type of a primitive or enum field of `size` bytes.
+fn c_type_name(type_name: &str, size: usize) -> anyhow::Result<String> {
+    Ok(match type_name {
+        "i8" | "i16" | "i32" | "i64" => format!("int{}_t", &type_name[1..]),
+        "u8" | "u16" | "u32" | "u64" => format!("uint{}_t", &type_name[1..]),
+        // enums are encoded as unsigned integers of their size
+        _ if matches!(size, 1 | 2 | 4 | 8) => format!("uint{}_t /* {type_name} */", size * 8),
+        _ => return Err(anyhow!("No C type for {type_name} of {size} bytes")),
+    })
+}
+
+/// Converts a name like `Mbp1Msg` or `ohlcv-1s` to a C macro name like `MBP1_MSG` or
+/// `OHLCV_1S`.
+fn c_macro_name(name: &str) -> String {
+    let mut res = String::with_capacity(name.len() + 4);
+    let mut prev: Option<char> = None;
+    for c in name.chars() {
+        if c == '-' {
+            res.push('_');
+        } else {
+            if c.is_ascii_uppercase()
+                && prev.is_some_and(|prev| prev.is_ascii_lowercase() || prev.is_ascii_digit())
+            {
+                res.push('_');
+            }
+            res.push(c.to_ascii_uppercase());
+        }
+        prev = Some(c);
+    }
+    res
+}
+
+impl Default for FormatSpec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const SCHEMAS: [Schema; 12] = [
+    Schema::Mbo,
+    Schema::Mbp1,
+    Schema::Mbp10,
+    Schema::Tbbo,
+    Schema::Trades,
+    Schema::Ohlcv1S,
+    Schema::Ohlcv1M,
+    Schema::Ohlcv1H,
+    Schema::Ohlcv1D,
+    Schema::Definition,
+    Schema::Statistics,
+    Schema::Status,
+];
+
+impl SchemaSpec {
+    fn new(schema: Schema) -> Self {
+        fn record<T: ConstTypeId>() -> (Option<&'static str>, Option<u8>) {
+            let name = any::type_name::<T>();
+            (name.rsplit("::").next(), Some(T::TYPE_ID))
+        }
+
+        let (record, rtype) = match schema {
+            Schema::Mbo => record::<TickMsg>(),
+            Schema::Mbp1 | Schema::Tbbo => record::<Mbp1Msg>(),
+            Schema::Mbp10 => record::<Mbp10Msg>(),
+            Schema::Trades => record::<TradeMsg>(),
+            Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D => {
+                record::<OhlcvMsg>()
+            }
+            Schema::Definition => record::<SymDefMsg>(),
+            Schema::Statistics => (None, None),
+            Schema::Status => record::<StatusMsg>(),
+        };
+        Self {
+            name: schema.as_str(),
+            value: schema as u16,
+            record,
+            rtype,
+        }
+    }
+}
+
+impl MetadataSpec {
+    fn new() -> Self {
+        let symbol_cstr = format!("[i8; {}]", Metadata::SYMBOL_CSTR_LEN);
+        let repeated_symbols = |name, count| VariableFieldSpec {
+            name,
+            type_name: symbol_cstr.clone(),
+            count: Some(count),
+        };
+        let scalar_field = |name, type_name| VariableFieldSpec {
+            name,
+            type_name,
+            count: None,
+        };
+        let u32_field = |name| scalar_field(name, type_name::<u32>());
+        Self {
+            fixed: sequential_fields(&[
+                ("magic", 4, type_name::<u32>()),
+                ("frame_size", 4, type_name::<u32>()),
+                (
+                    "version",
+                    Metadata::VERSION_CSTR_LEN,
+                    format!("[i8; {}]", Metadata::VERSION_CSTR_LEN),
+                ),
+                (
+                    "dataset",
+                    Metadata::DATASET_CSTR_LEN,
+                    format!("[i8; {}]", Metadata::DATASET_CSTR_LEN),
+                ),
+                ("schema", 2, type_name::<u16>()),
+                ("start", 8, type_name::<u64>()),
+                ("end", 8, type_name::<u64>()),
+                ("limit", 8, type_name::<u64>()),
+                ("record_count", 8, type_name::<u64>()),
+                ("compression", 1, type_name::<u8>()),
+                ("stype_in", 1, type_name::<u8>()),
+                ("stype_out", 1, type_name::<u8>()),
+                (
+                    "reserved",
+                    Metadata::RESERVED_LEN,
+                    format!("[u8; {}]", Metadata::RESERVED_LEN),
+                ),
+            ]),
+            variable: vec![
+                u32_field("schema_definition_length"),
+                VariableFieldSpec {
+                    name: "schema_definition",
+                    type_name: "schema_definition".to_owned(),
+                    count: None,
+                },
+                u32_field("symbols_count"),
+                repeated_symbols("symbols", "symbols_count"),
+                u32_field("partial_count"),
+                repeated_symbols("partial", "partial_count"),
+                u32_field("not_found_count"),
+                repeated_symbols("not_found", "not_found_count"),
+                u32_field("mappings_count"),
+                VariableFieldSpec {
+                    name: "mappings",
+                    type_name: "mapping".to_owned(),
+                    count: Some("mappings_count"),
+                },
+                u32_field("annotations_count"),
+                VariableFieldSpec {
+                    name: "annotations",
+                    type_name: "annotation".to_owned(),
+                    count: Some("annotations_count"),
+                },
+            ],
+            schema_definition: vec![
+                scalar_field("rtype", type_name::<u8>()),
+                scalar_field("record_size", type_name::<u16>()),
+                u32_field("fields_count"),
+                VariableFieldSpec {
+                    name: "fields",
+                    type_name: "field_definition".to_owned(),
+                    count: Some("fields_count"),
+                },
+            ],
+            field_definition: vec![
+                u32_field("name_length"),
+                VariableFieldSpec {
+                    name: "name",
+                    type_name: type_name::<u8>(),
+                    count: Some("name_length"),
+                },
+                scalar_field("offset", type_name::<u16>()),
+                scalar_field("size", type_name::<u16>()),
+                u32_field("type_length"),
+                VariableFieldSpec {
+                    name: "type",
+                    type_name: type_name::<u8>(),
+                    count: Some("type_length"),
+                },
+            ],
+            mapping: vec![
+                VariableFieldSpec {
+                    name: "native",
+                    type_name: symbol_cstr.clone(),
+                    count: None,
+                },
+                u32_field("interval_count"),
+                VariableFieldSpec {
+                    name: "intervals",
+                    type_name: "interval".to_owned(),
+                    count: Some("interval_count"),
+                },
+            ],
+            interval: sequential_fields(&[
+                ("start_date", 4, type_name::<u32>()),
+                ("end_date", 4, type_name::<u32>()),
+                ("symbol", Metadata::SYMBOL_CSTR_LEN, symbol_cstr.clone()),
+            ]),
+            annotation: vec![
+                u32_field("key_length"),
+                VariableFieldSpec {
+                    name: "key",
+                    type_name: type_name::<u8>(),
+                    count: Some("key_length"),
+                },
+                u32_field("value_length"),
+                VariableFieldSpec {
+                    name: "value",
+                    type_name: type_name::<u8>(),
+                    count: Some("value_length"),
+                },
+            ],
+        }
+    }
+}
+
+/// Returns the specs of consecutive fields without padding from their names, sizes,
+/// and types.
+fn sequential_fields(fields: &[(&'static str, usize, String)]) -> Vec<FieldSpec> {
+    let mut offset = 0;
+    fields
+        .iter()
+        .map(|(name, size, type_name)| {
+            let field = FieldSpec {
+                name,
+                offset,
+                size: *size,
+                type_name: type_name.clone(),
+            };
+            offset += size;
+            field
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_metadata_len() {
+        let target = FormatSpec::new();
+        let last = target.metadata.fixed.last().unwrap();
+        assert_eq!(last.offset + last.size, 8 + Metadata::FIXED_METADATA_LEN);
+        let start = target
+            .metadata
+            .fixed
+            .iter()
+            .find(|field| field.name == "start")
+            .unwrap();
+        // matches the position updated by `Metadata::update_encoded`
+        assert_eq!(start.offset, 8 + 4 + Metadata::DATASET_CSTR_LEN + 2);
+    }
+
+    #[test]
+    fn test_structs_fully_described() {
+        for spec in FormatSpec::new().structs {
+            // none of the structs have padding, so each field starts where the
+            // previous one ends
+            let mut offset = 0;
+            for field in spec.fields.iter() {
+                assert_eq!(field.offset, offset, "{}.{}", spec.name, field.name);
+                offset += field.size;
+            }
+            assert_eq!(offset, spec.size, "{}", spec.name);
+        }
+    }
+
+    #[test]
+    fn test_schemas() {
+        let target = FormatSpec::new();
+        let mbo = &target.schemas[0];
+        assert_eq!(mbo.name, "mbo");
+        assert_eq!(mbo.record, Some("TickMsg"));
+        assert_eq!(mbo.rtype, Some(TickMsg::TYPE_ID));
+        assert!(target.schemas.iter().all(|schema| schema.record.is_none()
+            || target
+                .structs
+                .iter()
+                .any(|spec| Some(spec.name) == schema.record)));
+        let json = serde_json::to_string(&target).unwrap();
+        assert!(json.contains(r#"{"name":"ts_event","offset":8,"size":8,"type":"u64"}"#));
+        assert!(json.contains(r#""type":"[BidAskPair; 10]""#));
+    }
+
+    #[test]
+    fn test_c_header() {
+        let mut header = Vec::new();
+        FormatSpec::new().write_c_header(&mut header).unwrap();
+        let header = String::from_utf8(header).unwrap();
+        assert!(header.contains("#define DBZ_SCHEMA_OHLCV_1D 8\n"));
+        assert!(header.contains(&format!(
+            "#define DBZ_RTYPE_MBP10_MSG {:#04x}\n",
+            Mbp10Msg::TYPE_ID
+        )));
+        assert!(header.contains("    RecordHeader hd;\n"));
+        assert!(header.contains("    BidAskPair booklevel[10];\n"));
+        assert!(header.contains("    char symbol[22];\n"));
+        assert!(header.contains("    int8_t flags;\n"));
+        assert!(header.contains("DBZ_STATIC_ASSERT(sizeof(Mbp10Msg) == 368,"));
+        assert!(header.contains("DBZ_STATIC_ASSERT(offsetof(TickMsg, ts_recv) == 40,"));
+    }
+
+    #[test]
+    fn test_c_macro_name() {
+        assert_eq!(c_macro_name("Mbp1Msg"), "MBP1_MSG");
+        assert_eq!(c_macro_name("SymDefMsg"), "SYM_DEF_MSG");
+        assert_eq!(c_macro_name("ohlcv-1s"), "OHLCV_1S");
+    }
+}
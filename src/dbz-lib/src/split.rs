@@ -0,0 +1,633 @@
+//! Splitting of DBZ files into several smaller files.
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{self, Write},
+    panic,
+    path::Path,
+    thread,
+};
+
+use anyhow::anyhow;
+use databento_defs::enums::Compression;
+use zstd::Encoder;
+
+use crate::{
+    record::record_size, write::dbz::new_manual_encoder, Dbz, DbzWriter, Metadata, RecordIndex,
+    SymbolResolver, WriterOptions,
+};
+
+const NANOS_PER_HOUR: u64 = 3_600_000_000_000;
+const NANOS_PER_DAY: u64 = 24 * NANOS_PER_HOUR;
+
+/// How [`split`] divides the records of a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitBy {
+    /// One file per UTC day of `ts_event`.
+    Day,
+    /// One file per UTC hour of `ts_event`.
+    Hour,
+    /// Files of at most this many records.
+    RecordCount(u64),
+}
+
+impl SplitBy {
+    /// Returns the time bucket of `ts_event` as its half-open range, or `None` when
+    /// splitting by record count.
+    fn bucket(&self, ts_event: u64) -> Option<(u64, u64)> {
+        let width = match self {
+            SplitBy::Day => NANOS_PER_DAY,
+            SplitBy::Hour => NANOS_PER_HOUR,
+            SplitBy::RecordCount(_) => return None,
+        };
+        let start = ts_event - ts_event % width;
+        Some((start, start.saturating_add(width)))
+    }
+}
+
+/// Splits the records of the DBZ file at `input` into several DBZ files as `by`
+/// describes, e.g. to break up a large download into daily files. `output` is
+/// called with the metadata of each part before its records are written and
+/// returns the writer for the part. Returns the final metadata of each part.
+///
+/// The metadata of each part is that of the input apart from its `record_count`,
+/// `start`, and `end`. When splitting by time, `start` and `end` are the bounds of
+/// the part's day or hour, narrowed to the input's time range. When splitting by
+/// record count, they're the range of the part's `ts_event`s. Empty parts aren't
+/// written.
+///
+/// # Errors
+/// This function returns an error if `input` can't be read, `by` is
+/// [`SplitBy::RecordCount`] of 0, or when splitting by time, the records aren't in
+/// `ts_event` order. It will also return an error if `output` returns an error or
+/// there's an issue writing to one of the parts.
+pub fn split<W: io::Write + io::Seek>(
+    input: impl AsRef<Path>,
+    by: SplitBy,
+    mut output: impl FnMut(&Metadata) -> anyhow::Result<W>,
+) -> anyhow::Result<Vec<Metadata>> {
+    if by == SplitBy::RecordCount(0) {
+        return Err(anyhow!("Can't split into parts of 0 records"));
+    }
+    let dbz = Dbz::from_file(input)?;
+    let mut metadata = dbz.metadata().clone();
+    metadata.compression = Compression::ZStd;
+    metadata.record_count = 0;
+    let mut parts = Vec::new();
+    let mut part: Option<SplitPart<W>> = None;
+    for (i, record) in dbz.into_record_iter()?.enumerate() {
+        let record = record?;
+        let ts_event = record.header().ts_event;
+        let bucket = by.bucket(ts_event);
+        let is_new_part = match (&part, by) {
+            (None, _) => true,
+            (Some(part), SplitBy::RecordCount(max)) => part.metadata.record_count == max,
+            (Some(part), _) if bucket == part.bucket => false,
+            (Some(part), _) if ts_event < part.last_ts_event => {
+                return Err(anyhow!(
+                    "Record {i} is out of ts_event order. Sort the file before splitting it by time"
+                ));
+            }
+            (Some(_), _) => true,
+        };
+        if is_new_part {
+            if let Some(part) = part.take() {
+                parts.push(part.finish()?);
+            }
+            let mut part_metadata = metadata.clone();
+            let (start, end) = match bucket {
+                Some((start, end)) => (start.max(metadata.start), end.min(metadata.end)),
+                None => (ts_event, ts_event.saturating_add(1)),
+            };
+            part_metadata.start = start.min(ts_event);
+            part_metadata.end = end.max(ts_event.saturating_add(1));
+            part = Some(SplitPart::new(
+                output(&part_metadata)?,
+                part_metadata,
+                bucket,
+            )?);
+        }
+        let part = part.as_mut().expect("a part was created");
+        part.encoder.write_all(record.as_bytes())?;
+        part.metadata.record_count += 1;
+        part.metadata.end = part.metadata.end.max(ts_event.saturating_add(1));
+        part.last_ts_event = ts_event;
+    }
+    if let Some(part) = part {
+        parts.push(part.finish()?);
+    }
+    Ok(parts)
+}
+
+/// The records of one symbol in a file and the Zstd frames they're in, found by the
+/// first pass of [`split_by_symbol`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SymbolPartition {
+    /// The native symbol of the records, or their product ID if it isn't mapped to
+    /// one.
+    pub symbol: String,
+    /// The number of records of the symbol.
+    pub record_count: u64,
+    /// The indices of the frames in the file's [`RecordIndex`] with records of the
+    /// symbol, in ascending order. Files without an index are a single frame.
+    pub frames: Vec<usize>,
+}
+
+/// Finds the records of each symbol in the DBZ file at `input` and the Zstd frames
+/// they're in, using the frames of the index at [`RecordIndex::path_for`], if it
+/// exists. Symbols are resolved with the metadata's mappings on the date of each
+/// record's `ts_event`. Returns the partitions sorted by symbol.
+///
+/// # Errors
+/// This function returns an error if `input` or its index can't be read, the index
+/// doesn't match the file, or the mappings can't be resolved.
+pub fn plan_symbol_split(input: impl AsRef<Path>) -> anyhow::Result<Vec<SymbolPartition>> {
+    let input = input.as_ref();
+    let dbz = Dbz::from_file(input)?;
+    let frame_starts = frame_starts(input, dbz.metadata())?;
+    let mut resolver = SymbolResolver::from_metadata(dbz.metadata())?;
+    let mut partitions: HashMap<String, SymbolPartition> = HashMap::new();
+    let mut frame = 0;
+    for (i, record) in dbz.into_record_iter()?.enumerate() {
+        let record = record?;
+        let header = record.header();
+        while frame_starts
+            .get(frame + 1)
+            .is_some_and(|&start| i as u64 >= start)
+        {
+            frame += 1;
+        }
+        let symbol = resolve_symbol(&mut resolver, header.product_id, header.ts_event);
+        let partition = match partitions.get_mut(symbol.as_ref()) {
+            Some(partition) => partition,
+            None => partitions
+                .entry(symbol.clone().into_owned())
+                .or_insert_with(|| SymbolPartition {
+                    symbol: symbol.into_owned(),
+                    record_count: 0,
+                    frames: Vec::new(),
+                }),
+        };
+        partition.record_count += 1;
+        if partition.frames.last() != Some(&frame) {
+            partition.frames.push(frame);
+        }
+    }
+    let mut partitions = partitions.into_values().collect::<Vec<_>>();
+    partitions.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    Ok(partitions)
+}
+
+/// Splits the records of the DBZ file at `input` into one DBZ file per symbol on
+/// `threads` threads, e.g. to extract every instrument of a large multi-symbol
+/// download without a scan of the file per symbol. `output` is called with the
+/// metadata of each part before its records are written and returns the writer for
+/// the part. Returns the final metadata of each part, sorted by symbol.
+///
+/// The first pass, [`plan_symbol_split`], finds the frames of each symbol. The second
+/// divides the symbols between the threads, balancing their numbers of records, and
+/// each thread decodes only the frames with records of its symbols. Files with many
+/// frames and an index, e.g. written with
+/// [`WriterOptions::frame_record_count`](crate::WriterOptions::frame_record_count)
+/// and indexed with [`RecordIndex::build`], benefit the most. Otherwise, each thread
+/// decodes the whole file.
+///
+/// The metadata of each part is that of the input with only the part's symbol and
+/// its mappings, and the `record_count`, `start`, and `end` of the part's records.
+///
+/// # Errors
+/// This function returns an error under the same conditions as
+/// [`plan_symbol_split`]. It will also return an error if `output` returns an error
+/// or there's an issue writing to one of the parts.
+pub fn split_by_symbol<W: io::Write + io::Seek>(
+    input: impl AsRef<Path>,
+    threads: usize,
+    output: impl Fn(&Metadata) -> anyhow::Result<W> + Sync,
+) -> anyhow::Result<Vec<Metadata>> {
+    let input = input.as_ref();
+    let partitions = plan_symbol_split(input)?;
+    let metadata = Metadata::from_file(input)?;
+    let frame_starts = frame_starts(input, &metadata)?;
+    // assign the largest partitions first, each to the thread with the fewest records
+    let mut order = (0..partitions.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| std::cmp::Reverse(partitions[i].record_count));
+    let mut workers = vec![(0, Vec::new()); threads.clamp(1, partitions.len().max(1))];
+    for i in order {
+        let worker = workers
+            .iter_mut()
+            .min_by_key(|(record_count, _)| *record_count)
+            .expect("at least one worker");
+        worker.0 += partitions[i].record_count;
+        worker.1.push(i);
+    }
+    let (partitions, frame_starts, metadata, output) =
+        (&partitions, &frame_starts, &metadata, &output);
+    let mut parts = thread::scope(|scope| {
+        let handles = workers
+            .into_iter()
+            .map(|(_, assigned)| {
+                scope.spawn(move || {
+                    split_partitions(input, metadata, frame_starts, partitions, assigned, output)
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| panic::resume_unwind(panic))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+    })?
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+    parts.sort_by_key(|(i, _)| *i);
+    Ok(parts.into_iter().map(|(_, part)| part).collect())
+}
+
+/// Writes the records of the `assigned` partitions to their parts, decoding only the
+/// frames containing them. Returns the index and final metadata of each part.
+fn split_partitions<W: io::Write + io::Seek>(
+    input: &Path,
+    metadata: &Metadata,
+    frame_starts: &[u64],
+    partitions: &[SymbolPartition],
+    assigned: Vec<usize>,
+    output: &impl Fn(&Metadata) -> anyhow::Result<W>,
+) -> anyhow::Result<Vec<(usize, Metadata)>> {
+    let mut resolver = SymbolResolver::from_metadata(metadata)?;
+    let mut parts = HashMap::with_capacity(assigned.len());
+    for &i in assigned.iter() {
+        let symbol = partitions[i].symbol.as_str();
+        let mut part_metadata = metadata.clone();
+        part_metadata.compression = Compression::ZStd;
+        part_metadata.record_count = 0;
+        part_metadata.symbols = vec![symbol.to_owned()];
+        part_metadata.partial.clear();
+        part_metadata.not_found.clear();
+        part_metadata
+            .mappings
+            .retain(|mapping| mapping.native == symbol);
+        let writer = DbzWriter::new(output(&part_metadata)?, &part_metadata)?;
+        parts.insert(symbol, (i, writer, part_metadata, None));
+    }
+    let mut frames = assigned
+        .iter()
+        .flat_map(|&i| partitions[i].frames.iter().copied())
+        .collect::<Vec<_>>();
+    frames.sort_unstable();
+    frames.dedup();
+    for frame in frames {
+        let start = frame_starts[frame];
+        let dbz = Dbz::from_file_at_record(input, start as usize)?;
+        let dbz = match frame_starts.get(frame + 1) {
+            Some(&end) => dbz.take_records(end - start),
+            None => dbz,
+        };
+        for record in dbz.into_record_iter()? {
+            let record = record?;
+            let header = record.header();
+            let symbol = resolve_symbol(&mut resolver, header.product_id, header.ts_event);
+            if let Some((_, writer, _, bounds)) = parts.get_mut(symbol.as_ref()) {
+                writer.write_record_bytes(record.as_bytes())?;
+                let ts_event = header.ts_event;
+                *bounds = Some(match *bounds {
+                    Some((first, last)) => (ts_event.min(first), ts_event.max(last)),
+                    None => (ts_event, ts_event),
+                });
+            }
+        }
+    }
+    parts
+        .into_values()
+        .map(|(i, writer, mut part_metadata, bounds)| {
+            part_metadata.record_count = writer.record_count();
+            if let Some((first, last)) = bounds {
+                part_metadata.start = first;
+                part_metadata.end = last.saturating_add(1);
+            }
+            writer.finish()?;
+            Ok((i, part_metadata))
+        })
+        .collect()
+}
+
+/// Returns the index of the first record of each Zstd frame of the DBZ file at
+/// `path` from its index, or a single frame if it has none.
+fn frame_starts(path: &Path, metadata: &Metadata) -> anyhow::Result<Vec<u64>> {
+    let index_path = RecordIndex::path_for(path);
+    if !index_path.exists() {
+        return Ok(vec![0]);
+    }
+    let record_size = record_size(metadata.schema)
+        .ok_or_else(|| anyhow!("Not implemented for schema {}", metadata.schema.as_str()))?
+        as u64;
+    let index = RecordIndex::from_file(index_path)?;
+    // a record split between frames belongs to the frame it starts in
+    let mut starts = index
+        .frames()
+        .iter()
+        .map(|frame| frame.decompressed.div_ceil(record_size))
+        .collect::<Vec<_>>();
+    starts.dedup();
+    Ok(starts)
+}
+
+/// Returns the native symbol of `product_id` at `ts_event`, or the product ID if it
+/// isn't mapped.
+fn resolve_symbol(resolver: &mut SymbolResolver, product_id: u32, ts_event: u64) -> Cow<'_, str> {
+    match resolver.resolve(product_id, ts_event) {
+        Some(symbol) => Cow::Borrowed(symbol),
+        None => Cow::Owned(product_id.to_string()),
+    }
+}
+
+/// A part of a file being split.
+struct SplitPart<W: io::Write> {
+    encoder: Encoder<'static, W>,
+    metadata: Metadata,
+    /// The time bucket of the part, if splitting by time.
+    bucket: Option<(u64, u64)>,
+    last_ts_event: u64,
+}
+
+impl<W: io::Write + io::Seek> SplitPart<W> {
+    fn new(mut writer: W, metadata: Metadata, bucket: Option<(u64, u64)>) -> anyhow::Result<Self> {
+        metadata.encode(&mut writer)?;
+        Ok(Self {
+            encoder: new_manual_encoder(writer, &WriterOptions::default())?,
+            last_ts_event: metadata.start,
+            metadata,
+            bucket,
+        })
+    }
+
+    /// Finishes writing the part and updates its metadata.
+    fn finish(self) -> anyhow::Result<Metadata> {
+        let mut writer = self.encoder.finish()?;
+        Metadata::update_encoded(
+            &mut writer,
+            self.metadata.start,
+            self.metadata.end,
+            self.metadata.limit,
+            self.metadata.record_count,
+        )?;
+        writer.flush()?;
+        Ok(self.metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, path::PathBuf};
+
+    use databento_defs::record::TradeMsg;
+    use streaming_iterator::StreamingIterator;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{MappingInterval, SymbolMapping};
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+    /// 2020-12-28 23:00 UTC
+    const START: u64 = 1609196400000000000;
+
+    /// Writes 5 trades 30 minutes apart, starting at [`START`], to a temporary file in
+    /// `dir`.
+    fn write_trades(dir: &Path) -> PathBuf {
+        let dbz = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let mut metadata = dbz.metadata().clone();
+        let template = dbz
+            .try_into_iter::<TradeMsg>()
+            .unwrap()
+            .next()
+            .unwrap()
+            .clone();
+        metadata.start = START;
+        metadata.end = START + 3 * NANOS_PER_HOUR;
+        let path = dir.join("input.dbz");
+        let mut writer = DbzWriter::new(File::create(&path).unwrap(), &metadata).unwrap();
+        for i in 0..5 {
+            let mut record = template.clone();
+            record.hd.ts_event = START + i * NANOS_PER_HOUR / 2;
+            writer.write_record(&record).unwrap();
+        }
+        // `DbzWriter` narrows the range to the records
+        let mut file = writer.finish().unwrap();
+        Metadata::update_encoded(&mut file, metadata.start, metadata.end, 0, 5).unwrap();
+        path
+    }
+
+    /// Splits the trades from [`write_trades`] and returns the metadata and
+    /// `ts_event`s of each part read back from its file.
+    fn split_trades(by: SplitBy) -> Vec<(Metadata, Vec<u64>)> {
+        let dir = tempdir().unwrap();
+        let dir = dir.path();
+        let input = write_trades(dir);
+        let mut paths = Vec::new();
+        let parts = split(&input, by, |_| {
+            let path = dir.join(format!("part-{}.dbz", paths.len()));
+            let file = File::create(&path)?;
+            paths.push(path);
+            Ok(file)
+        })
+        .unwrap();
+        assert_eq!(parts.len(), paths.len());
+        let res = paths
+            .iter()
+            .map(|path| {
+                let dbz = Dbz::from_file(path).unwrap();
+                let metadata = dbz.metadata().clone();
+                let ts_events = dbz
+                    .try_into_fallible_iter::<TradeMsg>()
+                    .unwrap()
+                    .map(|record| record.unwrap().hd.ts_event)
+                    .collect();
+                (metadata, ts_events)
+            })
+            .collect::<Vec<_>>();
+        for ((metadata, _), part) in res.iter().zip(parts.iter()) {
+            assert_eq!(metadata, part);
+        }
+        res
+    }
+
+    #[test]
+    fn test_split_by_day() {
+        let parts = split_trades(SplitBy::Day);
+        assert_eq!(parts.len(), 2);
+        let (first, first_ts_events) = &parts[0];
+        assert_eq!(first.record_count, 2);
+        assert_eq!(first.start, START);
+        // midnight
+        assert_eq!(first.end, START + NANOS_PER_HOUR);
+        assert_eq!(first_ts_events, &[START, START + NANOS_PER_HOUR / 2]);
+        let (second, second_ts_events) = &parts[1];
+        assert_eq!(second.record_count, 3);
+        assert_eq!(second.start, START + NANOS_PER_HOUR);
+        assert_eq!(second.end, START + 3 * NANOS_PER_HOUR);
+        assert_eq!(second_ts_events.len(), 3);
+    }
+
+    #[test]
+    fn test_split_by_hour() {
+        let parts = split_trades(SplitBy::Hour);
+        assert_eq!(parts.len(), 3);
+        assert_eq!(
+            parts
+                .iter()
+                .map(|(metadata, _)| metadata.record_count)
+                .collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+        for (i, (metadata, _)) in parts.iter().enumerate() {
+            assert_eq!(metadata.start, START + i as u64 * NANOS_PER_HOUR);
+            assert_eq!(metadata.end, metadata.start + NANOS_PER_HOUR);
+        }
+    }
+
+    #[test]
+    fn test_split_by_record_count() {
+        let parts = split_trades(SplitBy::RecordCount(2));
+        assert_eq!(parts.len(), 3);
+        let (last, last_ts_events) = &parts[2];
+        assert_eq!(last.record_count, 1);
+        assert_eq!(last_ts_events, &[START + 2 * NANOS_PER_HOUR]);
+        // the range of the records
+        assert_eq!(last.start, START + 2 * NANOS_PER_HOUR);
+        assert_eq!(last.end, START + 2 * NANOS_PER_HOUR + 1);
+        let (first, _) = &parts[0];
+        assert_eq!(first.start, START);
+        assert_eq!(first.end, START + NANOS_PER_HOUR / 2 + 1);
+    }
+
+    /// Writes 12 trades, 8 alternating between ESH1 and ESM1 then 4 of an unmapped
+    /// product, in frames of 4 records to a temporary file in `dir`, and optionally
+    /// indexes it.
+    fn write_symbol_trades(dir: &Path, should_index: bool) -> PathBuf {
+        let path = write_trades(dir);
+        let dbz = Dbz::from_file(&path).unwrap();
+        let mut metadata = dbz.metadata().clone();
+        let template = dbz
+            .try_into_iter::<TradeMsg>()
+            .unwrap()
+            .next()
+            .unwrap()
+            .clone();
+        let interval = |symbol: &str| MappingInterval {
+            start_date: time::Date::from_calendar_date(2020, time::Month::December, 28).unwrap(),
+            end_date: time::Date::from_calendar_date(2020, time::Month::December, 31).unwrap(),
+            symbol: symbol.to_owned(),
+        };
+        metadata.symbols = vec!["ESH1".to_owned(), "ESM1".to_owned()];
+        metadata.mappings = vec![
+            SymbolMapping {
+                native: "ESH1".to_owned(),
+                intervals: vec![interval("5482")],
+            },
+            SymbolMapping {
+                native: "ESM1".to_owned(),
+                intervals: vec![interval("5483")],
+            },
+        ];
+        let options = WriterOptions {
+            frame_record_count: Some(4),
+            ..WriterOptions::default()
+        };
+        let mut writer =
+            DbzWriter::with_options(File::create(&path).unwrap(), &metadata, &options).unwrap();
+        for i in 0..12 {
+            let mut record = template.clone();
+            record.hd.product_id = if i < 8 { 5482 + i as u32 % 2 } else { 5484 };
+            record.hd.ts_event = START + i * NANOS_PER_HOUR / 2;
+            writer.write_record(&record).unwrap();
+        }
+        writer.finish().unwrap();
+        if should_index {
+            RecordIndex::build(&path)
+                .unwrap()
+                .encode(File::create(RecordIndex::path_for(&path)).unwrap())
+                .unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn test_plan_symbol_split() {
+        let dir = tempdir().unwrap();
+        let input = write_symbol_trades(dir.path(), true);
+        let plan = plan_symbol_split(&input).unwrap();
+        assert_eq!(
+            plan,
+            vec![
+                SymbolPartition {
+                    symbol: "5484".to_owned(),
+                    record_count: 4,
+                    frames: vec![2],
+                },
+                SymbolPartition {
+                    symbol: "ESH1".to_owned(),
+                    record_count: 4,
+                    frames: vec![0, 1],
+                },
+                SymbolPartition {
+                    symbol: "ESM1".to_owned(),
+                    record_count: 4,
+                    frames: vec![0, 1],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_by_symbol() {
+        for should_index in [false, true] {
+            let dir = tempdir().unwrap();
+            let dir = dir.path();
+            let input = write_symbol_trades(dir, should_index);
+            let parts = split_by_symbol(&input, 2, |metadata| {
+                Ok(File::create(
+                    dir.join(format!("{}.dbz", metadata.symbols[0])),
+                )?)
+            })
+            .unwrap();
+            assert_eq!(
+                parts
+                    .iter()
+                    .map(|part| part.symbols.clone())
+                    .collect::<Vec<_>>(),
+                vec![vec!["5484"], vec!["ESH1"], vec!["ESM1"]]
+            );
+            for part in parts.iter() {
+                let dbz = Dbz::from_file(dir.join(format!("{}.dbz", part.symbols[0]))).unwrap();
+                assert_eq!(dbz.metadata(), part);
+                assert_eq!(part.record_count, 4);
+                let product_ids = dbz
+                    .try_into_fallible_iter::<TradeMsg>()
+                    .unwrap()
+                    .map(|record| record.unwrap().hd.product_id)
+                    .collect::<Vec<_>>();
+                assert_eq!(product_ids.len(), 4);
+                assert!(product_ids.windows(2).all(|ids| ids[0] == ids[1]));
+            }
+            let esh1 = &parts[1];
+            assert_eq!(esh1.mappings.len(), 1);
+            assert_eq!(esh1.mappings[0].native, "ESH1");
+            assert_eq!(esh1.start, START);
+            assert_eq!(esh1.end, START + 3 * NANOS_PER_HOUR + 1);
+            assert!(parts[0].mappings.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_split_by_zero_records() {
+        let res = split(
+            format!("{DBZ_PATH}/test_data.trades.dbz"),
+            SplitBy::RecordCount(0),
+            |_| Ok(io::Cursor::new(Vec::new())),
+        );
+        assert!(res.is_err());
+    }
+}
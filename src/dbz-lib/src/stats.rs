@@ -0,0 +1,610 @@
+//! Summaries of the records in DBZ data.
+use std::{collections::BTreeMap, convert::Infallible, io, time::Duration};
+
+use anyhow::anyhow;
+use serde::Serialize;
+
+use crate::{
+    continuity::RecordSequence,
+    fields::{FieldValue, FieldVisitor, RecordHandler, Scope, VisitFields},
+    Dbz,
+};
+
+/// Record counts per time bucket of `ts_event`, returned by [`Dbz::histogram`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Histogram {
+    /// The duration of each bucket.
+    pub bucket_duration: Duration,
+    /// The buckets in order of `start` and then `product_id`. Buckets without records
+    /// between the start and end of the data are included with a `count` of 0.
+    pub buckets: Vec<HistogramBucket>,
+}
+
+/// The number of records in a single bucket of a [`Histogram`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HistogramBucket {
+    /// The start of the bucket as a UNIX timestamp in nanoseconds. Buckets are
+    /// aligned to multiples of the bucket duration since the UNIX epoch.
+    pub start: u64,
+    /// The product ID the records were counted for, if counted by product ID.
+    pub product_id: Option<u32>,
+    /// The number of records in the bucket.
+    pub count: u64,
+}
+
+/// Per-product message rates in fixed windows of `ts_event`, returned by
+/// [`Dbz::message_rates`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessageRates {
+    /// The duration of each window.
+    pub window: Duration,
+    /// The rates of each product, in order of `product_id`.
+    pub products: Vec<ProductRates>,
+}
+
+/// The distribution of the number of records per window for a single product.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ProductRates {
+    /// The product the rates are for.
+    pub product_id: u32,
+    /// The total number of records of the product.
+    pub record_count: u64,
+    /// The median number of records per window. Like the other percentiles, windows
+    /// without records for the product are included.
+    pub p50: u64,
+    /// The 90th percentile of the number of records per window.
+    pub p90: u64,
+    /// The 99th percentile of the number of records per window.
+    pub p99: u64,
+    /// The highest number of records in a window.
+    pub max: u64,
+    /// The windows with at least the burst threshold number of records, in order.
+    pub bursts: Vec<Burst>,
+}
+
+/// A window with an unusually high number of records for a product.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Burst {
+    /// The start of the window as a UNIX timestamp in nanoseconds.
+    pub start: u64,
+    /// The number of records of the product in the window.
+    pub count: u64,
+}
+
+/// How active a single product is, returned by [`Dbz::product_activity`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProductActivity {
+    /// The product the activity is for.
+    pub product_id: u32,
+    /// The number of records of the product.
+    pub record_count: u64,
+    /// The traded volume: the `size` of trades or the `volume` of OHLCV bars. Always
+    /// 0 for schemas without either.
+    pub volume: u64,
+    /// The `ts_event` of the first record of the product.
+    pub first_ts_event: u64,
+}
+
+/// An overview of the records in DBZ data, returned by [`Dbz::summary`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DataSummary {
+    /// The number of records read.
+    pub record_count: u64,
+    /// The `ts_event` of the first record, or `None` if there are no records.
+    pub first_ts_event: Option<u64>,
+    /// The `ts_event` of the last record, or `None` if there are no records.
+    pub last_ts_event: Option<u64>,
+    /// The number of distinct `product_id`s.
+    pub product_id_count: usize,
+    /// The traded volume of all products, like [`ProductActivity::volume`].
+    pub volume: u64,
+    /// The jumps in the `sequence` numbers of each publisher, in order. Always empty
+    /// for schemas without a `sequence`.
+    pub sequence_gaps: Vec<SequenceGap>,
+}
+
+/// A jump forward in the `sequence` numbers of a publisher, e.g. from dropped
+/// packets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct SequenceGap {
+    /// The index of the record after the gap.
+    pub record_index: u64,
+    /// The publisher whose `sequence` numbers jumped.
+    pub publisher_id: u16,
+    /// The `sequence` of the publisher's record before the gap.
+    pub last_sequence: u32,
+    /// The `sequence` of the record after the gap.
+    pub sequence: u32,
+}
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Counts the records in each `bucket_duration`-long period of `ts_event`, and
+    /// additionally by `product_id` if `should_count_by_product_id` is `true`.
+    ///
+    /// Empty buckets are filled in between the metadata's `start` and `end`, or the
+    /// first and last records if the range isn't set, so gaps in the data stand out.
+    ///
+    /// # Errors
+    /// This function returns an error if `bucket_duration` is zero or
+    /// [`Dbz::schema()`] is [`Schema::Statistics`](crate::Schema::Statistics). It will also
+    /// return an error if there's an issue reading the records.
+    pub fn histogram(
+        self,
+        bucket_duration: Duration,
+        should_count_by_product_id: bool,
+    ) -> anyhow::Result<Histogram> {
+        let bucket_len = bucket_duration.as_nanos() as u64;
+        if bucket_len == 0 {
+            return Err(anyhow!("Histogram bucket duration must be greater than 0"));
+        }
+        let mut counter = BucketCounter {
+            bucket_len,
+            should_count_by_product_id,
+            counts: BTreeMap::new(),
+        };
+        let (start, end) = (self.metadata().start, self.metadata().end);
+        self.handle_records(&mut counter)?;
+        Ok(counter.into_histogram(bucket_duration, (end > start).then_some((start, end))))
+    }
+
+    /// Counts the records of each product in each `window`-long period of `ts_event`
+    /// and summarizes the distribution of the counts, e.g. to find quote stuffing.
+    /// Windows with at least `burst_threshold` records are flagged as bursts.
+    ///
+    /// Like [`Dbz::histogram`], windows without records between the metadata's
+    /// `start` and `end`, or the first and last records if the range isn't set, count
+    /// towards the percentiles.
+    ///
+    /// # Errors
+    /// This function returns an error if `window` is zero or [`Dbz::schema()`] is
+    /// [`Schema::Statistics`](crate::Schema::Statistics). It will also return an error
+    /// if there's an issue reading the records.
+    pub fn message_rates(
+        self,
+        window: Duration,
+        burst_threshold: Option<u64>,
+    ) -> anyhow::Result<MessageRates> {
+        let bucket_len = window.as_nanos() as u64;
+        if bucket_len == 0 {
+            return Err(anyhow!("Message rate window must be greater than 0"));
+        }
+        let mut counter = BucketCounter {
+            bucket_len,
+            should_count_by_product_id: true,
+            counts: BTreeMap::new(),
+        };
+        let (start, end) = (self.metadata().start, self.metadata().end);
+        self.handle_records(&mut counter)?;
+        Ok(counter.into_message_rates(
+            window,
+            (end > start).then_some((start, end)),
+            burst_threshold,
+        ))
+    }
+
+    /// Returns the number of records and traded volume of each product, in order of
+    /// `product_id`.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`](crate::Schema::Statistics). It will also return an error if
+    /// there's an issue reading the records.
+    pub fn product_activity(self) -> anyhow::Result<Vec<ProductActivity>> {
+        let mut counter = ActivityCounter::default();
+        self.handle_records(&mut counter)?;
+        Ok(counter.activity.into_values().collect())
+    }
+
+    /// Summarizes the records: their count, time range, products, traded volume, and
+    /// gaps in their `sequence` numbers, as a quick sanity check of the data.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`](crate::Schema::Statistics). It will also return an error if
+    /// there's an issue reading the records.
+    pub fn summary(self) -> anyhow::Result<DataSummary> {
+        let mut summarizer = Summarizer::default();
+        self.handle_records(&mut summarizer)?;
+        Ok(summarizer.into_summary())
+    }
+}
+
+#[derive(Default)]
+struct Summarizer {
+    activity: ActivityCounter,
+    record_count: u64,
+    first_ts_event: Option<u64>,
+    last_ts_event: Option<u64>,
+    /// The last `sequence` of each publisher.
+    sequences: BTreeMap<u16, u32>,
+    sequence_gaps: Vec<SequenceGap>,
+}
+
+impl RecordHandler for Summarizer {
+    fn handle<T: VisitFields>(&mut self, record: &T) {
+        self.activity.handle(record);
+        let hd = record.header();
+        self.first_ts_event.get_or_insert(hd.ts_event);
+        self.last_ts_event = Some(hd.ts_event);
+        let mut sequence = RecordSequence(None);
+        // `RecordSequence` never fails
+        let _ = record.visit_fields(&mut sequence);
+        if let Some(sequence) = sequence.0 {
+            // several records can share the `sequence` of the message they're from
+            if let Some(last_sequence) = self.sequences.insert(hd.publisher_id, sequence) {
+                if sequence > last_sequence.wrapping_add(1) {
+                    self.sequence_gaps.push(SequenceGap {
+                        record_index: self.record_count,
+                        publisher_id: hd.publisher_id,
+                        last_sequence,
+                        sequence,
+                    });
+                }
+            }
+        }
+        self.record_count += 1;
+    }
+}
+
+impl Summarizer {
+    fn into_summary(self) -> DataSummary {
+        DataSummary {
+            record_count: self.record_count,
+            first_ts_event: self.first_ts_event,
+            last_ts_event: self.last_ts_event,
+            product_id_count: self.activity.activity.len(),
+            volume: self
+                .activity
+                .activity
+                .values()
+                .map(|activity| activity.volume)
+                .sum(),
+            sequence_gaps: self.sequence_gaps,
+        }
+    }
+}
+
+#[derive(Default)]
+struct ActivityCounter {
+    activity: BTreeMap<u32, ProductActivity>,
+}
+
+impl RecordHandler for ActivityCounter {
+    fn handle<T: VisitFields>(&mut self, record: &T) {
+        let hd = record.header();
+        let mut volume = RecordVolume::default();
+        // `RecordVolume` never fails
+        let _ = record.visit_fields(&mut volume);
+        let activity = self
+            .activity
+            .entry(hd.product_id)
+            .or_insert(ProductActivity {
+                product_id: hd.product_id,
+                record_count: 0,
+                volume: 0,
+                first_ts_event: hd.ts_event,
+            });
+        activity.record_count += 1;
+        // only trades count towards volume in schemas with an `action`
+        if volume.action.is_none_or(|action| action as u8 == b'T') {
+            activity.volume += volume.volume;
+        }
+    }
+}
+
+/// Picks out the `size` or `volume` and the `action` of a record.
+#[derive(Default)]
+struct RecordVolume {
+    volume: u64,
+    action: Option<i8>,
+}
+
+impl FieldVisitor for RecordVolume {
+    type Error = Infallible;
+
+    fn visit(
+        &mut self,
+        scope: Scope,
+        name: &'static str,
+        value: FieldValue,
+    ) -> Result<(), Infallible> {
+        match (scope, name, value) {
+            (Scope::Body, "size", FieldValue::U32(size)) => self.volume = size as u64,
+            (Scope::Body, "volume", FieldValue::U64(volume)) => self.volume = volume,
+            (Scope::Body, "action", FieldValue::Char(action)) => self.action = Some(action),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+struct BucketCounter {
+    bucket_len: u64,
+    should_count_by_product_id: bool,
+    counts: BTreeMap<(u64, Option<u32>), u64>,
+}
+
+impl RecordHandler for BucketCounter {
+    fn handle<T: VisitFields>(&mut self, record: &T) {
+        let hd = record.header();
+        let start = hd.ts_event - hd.ts_event % self.bucket_len;
+        let product_id = self.should_count_by_product_id.then_some(hd.product_id);
+        *self.counts.entry((start, product_id)).or_default() += 1;
+    }
+}
+
+impl BucketCounter {
+    /// Returns the starts of the first and last buckets covering `range` and the
+    /// counted records.
+    fn bucket_range(&self, range: Option<(u64, u64)>) -> Option<(u64, u64)> {
+        let bucket_of = |ts: u64| ts - ts % self.bucket_len;
+        let record_range = self
+            .counts
+            .keys()
+            .next()
+            .zip(self.counts.keys().next_back())
+            .map(|((first, _), (last, _))| (*first, *last));
+        match (range, record_range) {
+            // `end` is exclusive
+            (Some((start, end)), Some((first, last))) => {
+                Some((bucket_of(start).min(first), bucket_of(end - 1).max(last)))
+            }
+            (Some((start, end)), None) => Some((bucket_of(start), bucket_of(end - 1))),
+            (None, record_range) => record_range,
+        }
+    }
+
+    fn into_histogram(self, bucket_duration: Duration, range: Option<(u64, u64)>) -> Histogram {
+        let (first, last) = match self.bucket_range(range) {
+            Some(bucket_range) => bucket_range,
+            None => {
+                return Histogram {
+                    bucket_duration,
+                    buckets: Vec::new(),
+                }
+            }
+        };
+        let product_ids: Vec<Option<u32>> = if self.should_count_by_product_id {
+            let mut product_ids: Vec<_> = self.counts.keys().map(|(_, id)| *id).collect();
+            product_ids.sort_unstable();
+            product_ids.dedup();
+            product_ids
+        } else {
+            vec![None]
+        };
+        let mut buckets = Vec::new();
+        let mut start = first;
+        while start <= last {
+            for &product_id in product_ids.iter() {
+                buckets.push(HistogramBucket {
+                    start,
+                    product_id,
+                    count: self
+                        .counts
+                        .get(&(start, product_id))
+                        .copied()
+                        .unwrap_or_default(),
+                });
+            }
+            start += self.bucket_len;
+        }
+        Histogram {
+            bucket_duration,
+            buckets,
+        }
+    }
+
+    fn into_message_rates(
+        self,
+        window: Duration,
+        range: Option<(u64, u64)>,
+        burst_threshold: Option<u64>,
+    ) -> MessageRates {
+        let window_count = match self.bucket_range(range) {
+            Some((first, last)) => (last - first) / self.bucket_len + 1,
+            None => 0,
+        };
+        let mut by_product: BTreeMap<u32, Vec<(u64, u64)>> = BTreeMap::new();
+        for ((start, product_id), count) in self.counts {
+            by_product
+                .entry(product_id.unwrap_or_default())
+                .or_default()
+                .push((start, count));
+        }
+        let products = by_product
+            .into_iter()
+            .map(|(product_id, windows)| {
+                let bursts = windows
+                    .iter()
+                    .filter(|(_, count)| burst_threshold.is_some_and(|t| *count >= t))
+                    .map(|&(start, count)| Burst { start, count })
+                    .collect();
+                let mut counts: Vec<u64> = windows.iter().map(|(_, count)| *count).collect();
+                counts.sort_unstable();
+                // only windows with records are stored, the rest are implicitly 0
+                let empty_count = window_count.saturating_sub(counts.len() as u64);
+                let percentile = |p: u64| {
+                    // nearest-rank method
+                    let rank = (p * (empty_count + counts.len() as u64))
+                        .div_ceil(100)
+                        .max(1);
+                    if rank <= empty_count {
+                        0
+                    } else {
+                        counts[(rank - empty_count - 1) as usize]
+                    }
+                };
+                ProductRates {
+                    product_id,
+                    record_count: counts.iter().sum(),
+                    p50: percentile(50),
+                    p90: percentile(90),
+                    p99: percentile(99),
+                    max: counts.last().copied().unwrap_or_default(),
+                    bursts,
+                }
+            })
+            .collect();
+        MessageRates { window, products }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use databento_defs::record::TradeMsg;
+    use streaming_iterator::StreamingIterator;
+
+    use super::*;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    #[test]
+    fn test_histogram_fills_metadata_range() {
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        let (start, end) = (target.metadata().start, target.metadata().end);
+        let histogram = target.histogram(Duration::from_secs(3600), false).unwrap();
+        assert_eq!(histogram.buckets.first().unwrap().start, start);
+        assert_eq!(
+            histogram.buckets.len() as u64,
+            (end - start) / 3_600_000_000_000
+        );
+        assert_eq!(histogram.buckets.iter().map(|b| b.count).sum::<u64>(), 2);
+        assert_eq!(histogram.buckets[0].count, 2);
+        assert!(histogram.buckets[1..].iter().all(|b| b.count == 0));
+    }
+
+    #[test]
+    fn test_histogram_by_product_id() {
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let histogram = target.histogram(Duration::from_secs(86_400), true).unwrap();
+        assert_eq!(
+            histogram.buckets,
+            vec![HistogramBucket {
+                start: 1609113600000000000,
+                product_id: Some(5482),
+                count: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let mut iter = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz"))
+            .unwrap()
+            .try_into_iter::<TradeMsg>()
+            .unwrap();
+        let mut records = Vec::new();
+        while let Some(record) = iter.next() {
+            records.push(record.clone());
+        }
+        let summary = target.summary().unwrap();
+        assert_eq!(summary.record_count, 2);
+        assert_eq!(summary.first_ts_event, Some(records[0].hd.ts_event));
+        assert_eq!(summary.last_ts_event, Some(records[1].hd.ts_event));
+        assert_eq!(summary.product_id_count, 1);
+        assert_eq!(
+            summary.volume,
+            records.iter().map(|r| r.size as u64).sum::<u64>()
+        );
+
+        let mut summarizer = Summarizer::default();
+        for sequence in [10, 11, 11, 15, 16] {
+            let mut record = records[0].clone();
+            record.sequence = sequence;
+            summarizer.handle(&record);
+        }
+        assert_eq!(
+            summarizer.into_summary().sequence_gaps,
+            vec![SequenceGap {
+                record_index: 3,
+                publisher_id: records[0].hd.publisher_id,
+                last_sequence: 11,
+                sequence: 15,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_histogram_zero_duration() {
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        assert!(target.histogram(Duration::ZERO, false).is_err());
+    }
+
+    #[test]
+    fn test_empty_histogram() {
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.ohlcv-1d.dbz")).unwrap();
+        let histogram = target.histogram(Duration::from_secs(60), false).unwrap();
+        assert!(histogram.buckets.iter().all(|b| b.count == 0));
+    }
+
+    #[test]
+    fn test_message_rates() {
+        let mut counter = BucketCounter {
+            bucket_len: 10,
+            should_count_by_product_id: true,
+            counts: BTreeMap::new(),
+        };
+        // 10 windows, product 1 has records in 3 of them
+        for (start, count) in [(0, 1), (50, 20), (90, 2)] {
+            counter.counts.insert((start, Some(1)), count);
+        }
+        counter.counts.insert((30, Some(2)), 5);
+        let target = counter.into_message_rates(Duration::from_nanos(10), Some((0, 100)), Some(5));
+        assert_eq!(
+            target.products,
+            vec![
+                ProductRates {
+                    product_id: 1,
+                    record_count: 23,
+                    p50: 0,
+                    p90: 2,
+                    p99: 20,
+                    max: 20,
+                    bursts: vec![Burst {
+                        start: 50,
+                        count: 20
+                    }],
+                },
+                ProductRates {
+                    product_id: 2,
+                    record_count: 5,
+                    p50: 0,
+                    p90: 0,
+                    p99: 5,
+                    max: 5,
+                    bursts: vec![Burst {
+                        start: 30,
+                        count: 5
+                    }],
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_message_rates_from_file() {
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let rates = target.message_rates(Duration::from_secs(1), None).unwrap();
+        assert_eq!(rates.products.len(), 1);
+        assert_eq!(rates.products[0].product_id, 5482);
+        assert_eq!(rates.products[0].record_count, 2);
+        assert!(rates.products[0].bursts.is_empty());
+    }
+
+    #[test]
+    fn test_product_activity() {
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        assert_eq!(
+            target.product_activity().unwrap(),
+            vec![ProductActivity {
+                product_id: 5482,
+                record_count: 2,
+                volume: 26,
+                first_ts_event: 1609160400098821953
+            }]
+        );
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.ohlcv-1m.dbz")).unwrap();
+        assert_eq!(target.product_activity().unwrap()[0].volume, 353 + 152);
+    }
+}
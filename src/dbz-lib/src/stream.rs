@@ -0,0 +1,239 @@
+//! Decoding DBZ data pushed in chunks, e.g. as it arrives over a websocket or TCP
+//! connection, without first writing it to disk.
+use anyhow::{anyhow, Context};
+use zstd::stream::raw::{Decoder, InBuffer, Operation, OutBuffer};
+
+use crate::{record::record_size, Metadata, Record};
+
+/// How many bytes the buffer of decompressed records grows by at a time.
+const DECOMPRESS_CHUNK_LEN: usize = 1 << 16;
+
+/// A push-based decoder of DBZ data. Chunks of the data of any size are passed to
+/// [`DbzStreamDecoder::feed`] as they arrive, and the records that are complete are
+/// returned by [`DbzStreamDecoder::next_record`]. Partial records and metadata are
+/// buffered until the rest of their bytes is fed.
+///
+/// Decompressed records are buffered until they're returned, so call
+/// [`DbzStreamDecoder::next_record`] until it returns `None` after each chunk to keep
+/// memory use bounded.
+pub struct DbzStreamDecoder {
+    /// The bytes of the metadata received so far, until it's complete.
+    metadata_buffer: Vec<u8>,
+    metadata: Option<Metadata>,
+    /// The size of the record type of the schema, once the metadata is decoded.
+    record_size: usize,
+    decoder: Decoder<'static>,
+    /// Whether the zstd stream is between frames, i.e. it could end here.
+    is_frame_complete: bool,
+    /// Decompressed record bytes, starting at `pos`, that haven't been returned yet.
+    buffer: Vec<u8>,
+    pos: usize,
+    /// The number of records returned.
+    i: u64,
+}
+
+impl DbzStreamDecoder {
+    /// Creates a new [`DbzStreamDecoder`] expecting the start of DBZ data.
+    ///
+    /// # Errors
+    /// This function returns an error if the zstd decoder can't be created.
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(Self {
+            metadata_buffer: Vec::new(),
+            metadata: None,
+            record_size: 0,
+            decoder: Decoder::new().context("Failed to create zstd decoder")?,
+            is_frame_complete: true,
+            buffer: Vec::new(),
+            pos: 0,
+            i: 0,
+        })
+    }
+
+    /// Returns the metadata, or `None` if not all of it has been fed yet.
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Decodes the next chunk of DBZ data. The chunk doesn't need to align with the
+    /// metadata, records, or zstd frames.
+    ///
+    /// # Errors
+    /// This function returns an error if the metadata is invalid, its schema is
+    /// [`Schema::Statistics`](crate::Schema::Statistics), or the records can't be
+    /// decompressed.
+    pub fn feed(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        let bytes = if self.metadata.is_some() {
+            bytes
+        } else {
+            match self.feed_metadata(bytes)? {
+                Some(rest) => rest,
+                None => return Ok(()),
+            }
+        };
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        // drop the records already returned
+        self.buffer.drain(..self.pos);
+        self.pos = 0;
+        let mut input = InBuffer::around(bytes);
+        loop {
+            self.buffer.reserve(DECOMPRESS_CHUNK_LEN);
+            let capacity = self.buffer.capacity();
+            let len = self.buffer.len();
+            let mut output = OutBuffer::around_pos(&mut self.buffer, len);
+            let hint = self.decoder.run(&mut input, &mut output).with_context(|| {
+                format!("Failed to decompress DBZ records after record {}", self.i)
+            })?;
+            let is_output_full = output.pos() == capacity;
+            self.is_frame_complete = hint == 0;
+            // a full output may leave decompressed bytes in the decoder
+            if input.pos() == bytes.len() && !is_output_full {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Buffers `bytes` until the metadata is complete and decodes it. Returns the
+    /// bytes following the metadata, or `None` if it's still incomplete.
+    fn feed_metadata<'a>(&mut self, bytes: &'a [u8]) -> anyhow::Result<Option<&'a [u8]>> {
+        let prelude_len = Metadata::PRELUDE_LEN;
+        let mut consumed = 0;
+        if self.metadata_buffer.len() < prelude_len {
+            consumed = (prelude_len - self.metadata_buffer.len()).min(bytes.len());
+            self.metadata_buffer.extend_from_slice(&bytes[..consumed]);
+            if self.metadata_buffer.len() < prelude_len {
+                return Ok(None);
+            }
+        }
+        let mut prelude = [0; Metadata::PRELUDE_LEN];
+        prelude.copy_from_slice(&self.metadata_buffer[..prelude_len]);
+        let metadata_len = prelude_len + Metadata::frame_size(&prelude)?;
+        let rest = &bytes[consumed..];
+        let missing = (metadata_len - self.metadata_buffer.len()).min(rest.len());
+        self.metadata_buffer.extend_from_slice(&rest[..missing]);
+        if self.metadata_buffer.len() < metadata_len {
+            return Ok(None);
+        }
+        let metadata = Metadata::decode(self.metadata_buffer.split_off(prelude_len))?;
+        self.record_size = record_size(metadata.schema)
+            .ok_or_else(|| anyhow!("Not implemented for schema {}", metadata.schema.as_str()))?;
+        self.metadata = Some(metadata);
+        self.metadata_buffer = Vec::new();
+        Ok(Some(&rest[missing..]))
+    }
+
+    /// Returns the next complete record fed, or `None` if the rest of its bytes
+    /// haven't been fed yet.
+    ///
+    /// # Errors
+    /// This function returns an error if the record is of a different type than the
+    /// schema's.
+    pub fn next_record(&mut self) -> anyhow::Result<Option<Record>> {
+        let Some(metadata) = self.metadata.as_ref() else {
+            return Ok(None);
+        };
+        let Some(bytes) = self.buffer.get(self.pos..self.pos + self.record_size) else {
+            return Ok(None);
+        };
+        let record = Record::from_bytes(metadata.schema, bytes).ok_or_else(|| {
+            anyhow!(
+                "Record {} has rtype {:#04x}, which doesn't match schema {}",
+                self.i,
+                // the rtype follows the length in the header
+                bytes[1],
+                metadata.schema.as_str()
+            )
+        })?;
+        self.pos += self.record_size;
+        self.i += 1;
+        Ok(Some(record))
+    }
+
+    /// Checks that the data fed so far ends cleanly: after the metadata, at the end of
+    /// a zstd frame, and at the end of a record. Call it once the stream is closed.
+    ///
+    /// # Errors
+    /// This function returns an error if the data ended in the middle of the metadata,
+    /// a zstd frame, or a record.
+    pub fn finish(&self) -> anyhow::Result<()> {
+        if self.metadata.is_none() {
+            return Err(anyhow!(
+                "DBZ data ended after {} bytes of metadata",
+                self.metadata_buffer.len()
+            ));
+        }
+        if !self.is_frame_complete {
+            return Err(anyhow!(
+                "DBZ data ended in the middle of a zstd frame after record {}",
+                self.i
+            ));
+        }
+        let partial_len = (self.buffer.len() - self.pos) % self.record_size;
+        if partial_len > 0 {
+            return Err(anyhow!(
+                "DBZ data ended in the middle of a record: {partial_len} of {} bytes",
+                self.record_size
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Dbz;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    fn decode_in_chunks(bytes: &[u8], chunk_len: usize) -> (DbzStreamDecoder, Vec<Record>) {
+        let mut target = DbzStreamDecoder::new().unwrap();
+        let mut records = Vec::new();
+        for chunk in bytes.chunks(chunk_len) {
+            target.feed(chunk).unwrap();
+            while let Some(record) = target.next_record().unwrap() {
+                records.push(record);
+            }
+        }
+        (target, records)
+    }
+
+    #[test]
+    fn test_matches_sync_reader() {
+        for schema in ["mbo", "mbp-1", "mbp-10", "ohlcv-1s", "tbbo", "trades"] {
+            let path = format!("{DBZ_PATH}/test_data.{schema}.dbz");
+            let bytes = std::fs::read(&path).unwrap();
+            let expected = Dbz::from_file(&path).unwrap();
+            let expected_metadata = expected.metadata().clone();
+            let expected_records = expected
+                .into_record_iter()
+                .unwrap()
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap();
+            for chunk_len in [1, 7, 100, bytes.len()] {
+                let (target, records) = decode_in_chunks(&bytes, chunk_len);
+                target.finish().unwrap();
+                assert_eq!(target.metadata(), Some(&expected_metadata));
+                assert_eq!(
+                    records, expected_records,
+                    "{schema} in chunks of {chunk_len}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_incomplete() {
+        let bytes = std::fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        let (target, records) = decode_in_chunks(&bytes[..10], 3);
+        assert!(records.is_empty());
+        assert!(target.metadata().is_none());
+        assert!(target.finish().is_err());
+        let (target, _) = decode_in_chunks(&bytes[..bytes.len() - 10], 3);
+        assert!(target.metadata().is_some());
+        assert!(target.finish().is_err());
+    }
+}
@@ -0,0 +1,249 @@
+//! A language-agnostic conformance suite of fixture DBZ files and their expected
+//! decoded output, for checking that readers decode identically to this crate.
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use serde::Serialize;
+
+use crate::{Dbz, Metadata, OutputEncoding};
+
+/// The encoding of the expected output: compact JSON with integer timestamps and
+/// prices.
+const EXPECTED_ENCODING: OutputEncoding = OutputEncoding::Json {
+    should_pretty_print: false,
+    should_output_array: false,
+    should_output_iso_timestamps: false,
+    should_output_decimal_prices: false,
+};
+
+/// A case of a conformance suite: a fixture DBZ file and the files of its expected
+/// decoded output.
+///
+/// In a suite directory, the fixture `NAME.dbz` is expected to decode to the
+/// metadata in `NAME.metadata.json`, a JSON object, and the records in
+/// `NAME.records.json`, one JSON object per line. Timestamps are strings of integer
+/// UNIX nanoseconds and prices are integers in units of 1e-9, like the default JSON
+/// output of the CLI.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SuiteCase {
+    /// The name of the case, the file stem of the fixture.
+    pub name: String,
+    /// The path of the DBZ fixture.
+    pub fixture: PathBuf,
+    /// The path of the expected metadata, `NAME.metadata.json`.
+    pub expected_metadata: PathBuf,
+    /// The path of the expected records, `NAME.records.json`.
+    pub expected_records: PathBuf,
+}
+
+/// The result of checking one [`SuiteCase`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CaseResult {
+    /// The name of the case.
+    pub name: String,
+    /// The first difference from the expected output, or `None` if the case passed.
+    pub failure: Option<String>,
+}
+
+impl CaseResult {
+    /// Returns `true` if the fixture decoded to the expected output.
+    pub fn is_pass(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+impl SuiteCase {
+    fn new(dir: &Path, name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            fixture: dir.join(format!("{name}.dbz")),
+            expected_metadata: dir.join(format!("{name}.metadata.json")),
+            expected_records: dir.join(format!("{name}.records.json")),
+        }
+    }
+
+    /// Decodes the fixture and compares it to the expected output.
+    pub fn check(&self) -> CaseResult {
+        CaseResult {
+            name: self.name.clone(),
+            failure: self.compare().err().map(|e| format!("{e:#}")),
+        }
+    }
+
+    fn compare(&self) -> anyhow::Result<()> {
+        let (metadata, records) = decode(&self.fixture)?;
+        let expected_metadata = fs::read_to_string(&self.expected_metadata).with_context(|| {
+            format!(
+                "Unable to read expected metadata '{}'",
+                self.expected_metadata.display()
+            )
+        })?;
+        if parse_json(&metadata)? != parse_json(&expected_metadata)? {
+            return Err(anyhow!(
+                "metadata differs: expected {}, got {metadata}",
+                expected_metadata.trim_end()
+            ));
+        }
+        let expected_records = fs::read_to_string(&self.expected_records).with_context(|| {
+            format!(
+                "Unable to read expected records '{}'",
+                self.expected_records.display()
+            )
+        })?;
+        let mut expected_lines = expected_records.lines();
+        let mut lines = records.lines();
+        for i in 0.. {
+            match (expected_lines.next(), lines.next()) {
+                (None, None) => return Ok(()),
+                (Some(expected), Some(line)) => {
+                    if parse_json(line)? != parse_json(expected)? {
+                        return Err(anyhow!(
+                            "record {i} differs: expected {expected}, got {line}"
+                        ));
+                    }
+                }
+                (Some(_), None) => {
+                    return Err(anyhow!(
+                        "expected {} records, got {i}",
+                        expected_records.lines().count()
+                    ))
+                }
+                (None, Some(_)) => {
+                    return Err(anyhow!(
+                        "expected {i} records, got {}",
+                        records.lines().count()
+                    ))
+                }
+            }
+        }
+        unreachable!("the loop only ends by returning")
+    }
+
+    /// Overwrites the expected output with the output of decoding the fixture, e.g.
+    /// after adding a fixture.
+    ///
+    /// # Errors
+    /// This function returns an error if the fixture can't be decoded or there's an
+    /// issue writing the expected output.
+    pub fn write_expected(&self) -> anyhow::Result<()> {
+        let (metadata, records) = decode(&self.fixture)?;
+        for (path, contents) in [
+            (&self.expected_metadata, format!("{metadata}\n")),
+            (&self.expected_records, records),
+        ] {
+            let mut file = BufWriter::new(
+                File::create(path)
+                    .with_context(|| format!("Unable to create '{}'", path.display()))?,
+            );
+            file.write_all(contents.as_bytes())?;
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the cases of the conformance suite in `dir`, one per `.dbz` file, in order
+/// of name. See [`SuiteCase`] for the layout of the directory.
+///
+/// # Errors
+/// This function returns an error if `dir` can't be read or contains no fixtures.
+pub fn suite_cases(dir: impl AsRef<Path>) -> anyhow::Result<Vec<SuiteCase>> {
+    let dir = dir.as_ref();
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)
+        .with_context(|| format!("Unable to read suite directory '{}'", dir.display()))?
+    {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "dbz") {
+            if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+                names.push(stem.to_owned());
+            }
+        }
+    }
+    if names.is_empty() {
+        return Err(anyhow!("No .dbz fixtures in '{}'", dir.display()));
+    }
+    names.sort();
+    Ok(names.iter().map(|name| SuiteCase::new(dir, name)).collect())
+}
+
+/// Checks every case of the conformance suite in `dir`. Every case is checked even if
+/// an earlier one fails.
+///
+/// # Errors
+/// This function returns an error if the cases can't be listed, see
+/// [`suite_cases`].
+pub fn run_conformance_suite(dir: impl AsRef<Path>) -> anyhow::Result<Vec<CaseResult>> {
+    Ok(suite_cases(dir)?.iter().map(SuiteCase::check).collect())
+}
+
+/// Decodes the fixture at `path` into its expected metadata and records output.
+fn decode(path: &Path) -> anyhow::Result<(String, String)> {
+    let metadata = Metadata::from_file(path)?;
+    let mut metadata_json = Vec::new();
+    metadata.write_to(&mut metadata_json, EXPECTED_ENCODING)?;
+    let mut records_json = Vec::new();
+    Dbz::from_file(path)?.write_to(&mut records_json, EXPECTED_ENCODING)?;
+    Ok((
+        String::from_utf8(metadata_json)?.trim_end().to_owned(),
+        String::from_utf8(records_json)?,
+    ))
+}
+
+/// Parses `json` so outputs that differ only in whitespace or the order of keys
+/// compare equal.
+fn parse_json(json: &str) -> anyhow::Result<serde_json::Value> {
+    serde_json::from_str(json).with_context(|| format!("Invalid JSON: {json}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUITE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/conformance");
+
+    #[test]
+    fn test_suite_passes() {
+        let results = run_conformance_suite(SUITE_PATH).unwrap();
+        assert!(!results.is_empty());
+        for result in results {
+            assert!(result.is_pass(), "{result:?}");
+        }
+    }
+
+    #[test]
+    fn test_detects_differences() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        for ext in ["dbz", "metadata.json", "records.json"] {
+            fs::copy(
+                format!("{SUITE_PATH}/trades.{ext}"),
+                dir.join(format!("trades.{ext}")),
+            )
+            .unwrap();
+        }
+        let case = &suite_cases(dir).unwrap()[0];
+        let records = fs::read_to_string(&case.expected_records).unwrap();
+        fs::write(
+            &case.expected_records,
+            records.replacen("\"size\":5", "\"size\":6", 1),
+        )
+        .unwrap();
+        let changed = case.check();
+        fs::write(
+            &case.expected_records,
+            records.lines().next().unwrap().to_owned() + "\n",
+        )
+        .unwrap();
+        let missing = case.check();
+        case.write_expected().unwrap();
+        let rewritten = case.check();
+        assert!(changed.failure.unwrap().starts_with("record 0 differs"));
+        assert_eq!(missing.failure.unwrap(), "expected 1 records, got 2");
+        assert!(rewritten.is_pass());
+    }
+}
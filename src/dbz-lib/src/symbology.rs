@@ -0,0 +1,495 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, Context};
+use databento_defs::enums::SType;
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime};
+
+use crate::{adjust::parse_date, MappingInterval, Metadata, SymbolMapping};
+
+/// A lookup from a record's `product_id` and date to its native symbol, built from
+/// the symbol mappings in a DBZ file's [`Metadata`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SymbolMap {
+    intervals: HashMap<u32, Vec<ProductInterval>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ProductInterval {
+    start_date: Date,
+    end_date: Date,
+    native: String,
+}
+
+impl SymbolMap {
+    /// Builds a [`SymbolMap`] from the mappings in `metadata`.
+    ///
+    /// # Errors
+    /// This function returns an error if `metadata.stype_out` isn't
+    /// [`SType::ProductId`] or one of the mapped symbols isn't a valid product ID.
+    pub fn from_metadata(metadata: &Metadata) -> anyhow::Result<Self> {
+        if metadata.stype_out != SType::ProductId {
+            return Err(anyhow!(
+                "Can only map symbols of DBZ files with an stype_out of product_id, found {}",
+                metadata.stype_out.as_str()
+            ));
+        }
+        let mut intervals: HashMap<u32, Vec<ProductInterval>> = HashMap::new();
+        for mapping in metadata.mappings.iter() {
+            for interval in mapping.intervals.iter() {
+                // empty symbols indicate the native symbol didn't resolve for the interval
+                if interval.symbol.is_empty() {
+                    continue;
+                }
+                let product_id = interval.symbol.parse().with_context(|| {
+                    format!(
+                        "Failed to parse product ID '{}' mapped from '{}'",
+                        interval.symbol, mapping.native
+                    )
+                })?;
+                intervals
+                    .entry(product_id)
+                    .or_default()
+                    .push(ProductInterval {
+                        start_date: interval.start_date,
+                        end_date: interval.end_date,
+                        native: mapping.native.clone(),
+                    });
+            }
+        }
+        Ok(Self { intervals })
+    }
+
+    /// Returns the native symbol of `product_id` on `date`, if one is mapped. Mapping
+    /// intervals include their start date and exclude their end date.
+    pub fn get(&self, product_id: u32, date: Date) -> Option<&str> {
+        self.intervals.get(&product_id).and_then(|intervals| {
+            intervals
+                .iter()
+                .find(|i| i.start_date <= date && date < i.end_date)
+                .map(|i| i.native.as_str())
+        })
+    }
+
+    /// Returns the native symbol of `product_id` at the UTC date of `ts`, a UNIX
+    /// timestamp in nanoseconds, if one is mapped.
+    pub fn get_for_ts(&self, product_id: u32, ts: u64) -> Option<&str> {
+        let date = OffsetDateTime::from_unix_timestamp_nanos(ts as i128)
+            .ok()?
+            .date();
+        self.get(product_id, date)
+    }
+
+    /// Returns `true` if no product IDs are mapped.
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+}
+
+/// A memoized version of [`SymbolMap::get_for_ts`] for resolving the symbol of every
+/// record. It remembers the mapping interval each product ID last resolved to, so
+/// consecutive records of a product within the same interval resolve in constant
+/// time instead of searching its intervals.
+#[derive(Clone, Debug, Default)]
+pub struct SymbolResolver {
+    products: HashMap<u32, ResolvedProduct>,
+}
+
+#[derive(Clone, Debug)]
+struct ResolvedProduct {
+    intervals: Vec<TsInterval>,
+    /// The index of the interval of the last resolved timestamp.
+    last: usize,
+}
+
+/// A [`ProductInterval`] with its dates converted to UNIX nanosecond timestamps.
+#[derive(Clone, Debug)]
+struct TsInterval {
+    start: u64,
+    end: u64,
+    native: String,
+}
+
+impl TsInterval {
+    fn contains(&self, ts: u64) -> bool {
+        self.start <= ts && ts < self.end
+    }
+}
+
+impl SymbolResolver {
+    /// Builds a [`SymbolResolver`] from the mappings in `metadata`.
+    ///
+    /// # Errors
+    /// This function returns an error under the same conditions as
+    /// [`SymbolMap::from_metadata`].
+    pub fn from_metadata(metadata: &Metadata) -> anyhow::Result<Self> {
+        SymbolMap::from_metadata(metadata).map(Self::from)
+    }
+
+    /// Returns the native symbol of `product_id` at the UTC date of `ts`, a UNIX
+    /// timestamp in nanoseconds, if one is mapped.
+    pub fn resolve(&mut self, product_id: u32, ts: u64) -> Option<&str> {
+        let product = self.products.get_mut(&product_id)?;
+        if !product.intervals[product.last].contains(ts) {
+            product.last = product
+                .intervals
+                .iter()
+                .position(|interval| interval.contains(ts))?;
+        }
+        Some(product.intervals[product.last].native.as_str())
+    }
+}
+
+impl From<SymbolMap> for SymbolResolver {
+    fn from(map: SymbolMap) -> Self {
+        fn date_to_ts(date: Date) -> u64 {
+            // dates before the UNIX epoch can't contain any timestamps
+            date.midnight()
+                .assume_utc()
+                .unix_timestamp_nanos()
+                .clamp(0, u64::MAX as i128) as u64
+        }
+
+        let products = map
+            .intervals
+            .into_iter()
+            .map(|(product_id, intervals)| {
+                let intervals = intervals
+                    .into_iter()
+                    .map(|interval| TsInterval {
+                        start: date_to_ts(interval.start_date),
+                        end: date_to_ts(interval.end_date),
+                        native: interval.native,
+                    })
+                    .collect();
+                (product_id, ResolvedProduct { intervals, last: 0 })
+            })
+            .collect();
+        Self { products }
+    }
+}
+
+impl Metadata {
+    /// Returns a copy of the metadata with `stype_out` changed to `stype_out` and the
+    /// symbols of the mapping intervals rewritten accordingly. Mapping to
+    /// [`SType::Native`] makes each interval's symbol the native symbol it's keyed by.
+    ///
+    /// # Errors
+    /// This function returns an error if the mapping between the current `stype_out`
+    /// and `stype_out` isn't supported.
+    pub fn with_stype_out(&self, stype_out: SType) -> anyhow::Result<Self> {
+        let mut res = self.clone();
+        match (self.stype_out, stype_out) {
+            (current, target) if current == target => {}
+            (SType::ProductId, SType::Native) => {
+                res.stype_out = SType::Native;
+                for mapping in res.mappings.iter_mut() {
+                    for interval in mapping.intervals.iter_mut() {
+                        interval.symbol = mapping.native.clone();
+                    }
+                }
+            }
+            (current, target) => {
+                return Err(anyhow!(
+                    "Mapping symbols from {} to {} is unsupported",
+                    current.as_str(),
+                    target.as_str()
+                ))
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// The response of the Databento symbology API, as saved in `symbology.json`.
+#[derive(Debug, Serialize, Deserialize)]
+struct Symbology {
+    result: BTreeMap<String, Vec<SymbologyInterval>>,
+    #[serde(default)]
+    symbols: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stype_in: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stype_out: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start_date: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end_date: Option<String>,
+    #[serde(default)]
+    partial: Vec<String>,
+    #[serde(default)]
+    not_found: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SymbologyInterval {
+    d0: String,
+    d1: String,
+    s: String,
+}
+
+/// Parses the symbol mappings from `json`, the contents of a `symbology.json` file
+/// returned by the Databento symbology API, for example to attach them to a DBZ
+/// file's [`Metadata`] or compare them to its embedded mappings. The mappings are
+/// sorted by native symbol.
+///
+/// # Errors
+/// This function returns an error if `json` isn't valid symbology JSON or contains an
+/// invalid date.
+pub fn mappings_from_symbology_json(json: &str) -> anyhow::Result<Vec<SymbolMapping>> {
+    let symbology: Symbology =
+        serde_json::from_str(json).context("Failed to parse symbology JSON")?;
+    symbology
+        .result
+        .into_iter()
+        .map(|(native, intervals)| {
+            let intervals = intervals
+                .into_iter()
+                .map(|interval| {
+                    Ok(MappingInterval {
+                        start_date: parse_date(&interval.d0)?,
+                        end_date: parse_date(&interval.d1)?,
+                        symbol: interval.s,
+                    })
+                })
+                .collect::<anyhow::Result<_>>()
+                .with_context(|| format!("Invalid mapping interval for '{native}'"))?;
+            Ok(SymbolMapping { native, intervals })
+        })
+        .collect()
+}
+
+impl Metadata {
+    /// Returns the symbol mappings and symbology request parameters in the format
+    /// of a `symbology.json` file returned by the Databento symbology API.
+    pub fn to_symbology_json(&self) -> String {
+        let to_date = |ts: u64| {
+            OffsetDateTime::from_unix_timestamp_nanos(ts as i128)
+                .ok()
+                .map(|dt| dt.date().to_string())
+        };
+        let symbology = Symbology {
+            result: self
+                .mappings
+                .iter()
+                .map(|mapping| {
+                    let intervals = mapping
+                        .intervals
+                        .iter()
+                        .map(|interval| SymbologyInterval {
+                            d0: interval.start_date.to_string(),
+                            d1: interval.end_date.to_string(),
+                            s: interval.symbol.clone(),
+                        })
+                        .collect();
+                    (mapping.native.clone(), intervals)
+                })
+                .collect(),
+            symbols: self.symbols.clone(),
+            stype_in: Some(self.stype_in.as_str().to_owned()),
+            stype_out: Some(self.stype_out.as_str().to_owned()),
+            start_date: to_date(self.start),
+            end_date: to_date(self.end),
+            partial: self.partial.clone(),
+            not_found: self.not_found.clone(),
+        };
+        // only contains strings, so can't fail
+        serde_json::to_string(&symbology).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use databento_defs::enums::{Compression, Schema};
+
+    use super::*;
+
+    fn date(year: i32, month: u8, day: u8) -> Date {
+        Date::from_calendar_date(year, month.try_into().unwrap(), day).unwrap()
+    }
+
+    fn metadata_with_mappings(mappings: Vec<SymbolMapping>) -> Metadata {
+        Metadata {
+            version: 1,
+            dataset: "GLBX.MDP3".to_owned(),
+            schema: Schema::Trades,
+            start: 0,
+            end: 0,
+            limit: 0,
+            record_count: 0,
+            compression: Compression::ZStd,
+            stype_in: SType::Native,
+            stype_out: SType::ProductId,
+            schema_definition: None,
+            symbols: vec!["ESH1".to_owned()],
+            partial: vec![],
+            not_found: vec![],
+            mappings,
+            annotations: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_get() {
+        let metadata = metadata_with_mappings(vec![
+            SymbolMapping {
+                native: "ESH1".to_owned(),
+                intervals: vec![
+                    MappingInterval {
+                        start_date: date(2020, 12, 28),
+                        end_date: date(2020, 12, 30),
+                        symbol: "5482".to_owned(),
+                    },
+                    MappingInterval {
+                        start_date: date(2020, 12, 30),
+                        end_date: date(2020, 12, 31),
+                        symbol: "".to_owned(),
+                    },
+                ],
+            },
+            SymbolMapping {
+                native: "ESM1".to_owned(),
+                intervals: vec![MappingInterval {
+                    start_date: date(2020, 12, 28),
+                    end_date: date(2020, 12, 31),
+                    symbol: "5483".to_owned(),
+                }],
+            },
+        ]);
+        let target = SymbolMap::from_metadata(&metadata).unwrap();
+        assert_eq!(target.get(5482, date(2020, 12, 28)), Some("ESH1"));
+        assert_eq!(target.get(5482, date(2020, 12, 29)), Some("ESH1"));
+        assert_eq!(target.get(5482, date(2020, 12, 30)), None);
+        assert_eq!(target.get(5483, date(2020, 12, 30)), Some("ESM1"));
+        assert_eq!(target.get(1, date(2020, 12, 28)), None);
+        // 2020-12-28T09:00:00Z
+        assert_eq!(target.get_for_ts(5482, 1609146000000000000), Some("ESH1"));
+    }
+
+    #[test]
+    fn test_resolver_matches_map() {
+        let metadata = metadata_with_mappings(vec![
+            SymbolMapping {
+                native: "ESH1".to_owned(),
+                intervals: vec![
+                    MappingInterval {
+                        start_date: date(2020, 12, 28),
+                        end_date: date(2020, 12, 29),
+                        symbol: "5482".to_owned(),
+                    },
+                    MappingInterval {
+                        start_date: date(2020, 12, 30),
+                        end_date: date(2020, 12, 31),
+                        symbol: "5482".to_owned(),
+                    },
+                ],
+            },
+            SymbolMapping {
+                native: "ESM1".to_owned(),
+                intervals: vec![MappingInterval {
+                    start_date: date(2020, 12, 29),
+                    end_date: date(2020, 12, 30),
+                    symbol: "5482".to_owned(),
+                }],
+            },
+        ]);
+        let map = SymbolMap::from_metadata(&metadata).unwrap();
+        let mut target = SymbolResolver::from(map.clone());
+        // 2020-12-27T12:00:00Z, then every 6 hours to 2020-12-31T12:00:00Z, going
+        // back and forth between intervals
+        const HOUR: u64 = 3_600_000_000_000;
+        let timestamps = (0..17).map(|i| 1609070400000000000 + i * 6 * HOUR).chain([
+            1609200000000000000,
+            1609113600000000000,
+            1609300000000000000,
+        ]);
+        for ts in timestamps {
+            assert_eq!(target.resolve(5482, ts), map.get_for_ts(5482, ts), "{ts}");
+        }
+        assert_eq!(target.resolve(1, 1609200000000000000), None);
+    }
+
+    #[test]
+    fn test_from_metadata_requires_product_id() {
+        let mut metadata = metadata_with_mappings(vec![]);
+        metadata.stype_out = SType::Native;
+        assert!(SymbolMap::from_metadata(&metadata).is_err());
+    }
+
+    #[test]
+    fn test_with_stype_out_native() {
+        let metadata = metadata_with_mappings(vec![SymbolMapping {
+            native: "ESH1".to_owned(),
+            intervals: vec![MappingInterval {
+                start_date: date(2020, 12, 28),
+                end_date: date(2020, 12, 29),
+                symbol: "5482".to_owned(),
+            }],
+        }]);
+        let target = metadata.with_stype_out(SType::Native).unwrap();
+        assert_eq!(target.stype_out, SType::Native);
+        assert_eq!(target.mappings[0].intervals[0].symbol, "ESH1");
+        assert_eq!(metadata.with_stype_out(SType::ProductId).unwrap(), metadata);
+        assert!(metadata.with_stype_out(SType::Smart).is_err());
+    }
+
+    #[test]
+    fn test_mappings_from_symbology_json() {
+        let json = r#"{
+            "result": {
+                "ESM1": [{"d0": "2020-12-28", "d1": "2020-12-29", "s": "5483"}],
+                "ESH1": [
+                    {"d0": "2020-12-28", "d1": "2020-12-29", "s": "5482"},
+                    {"d0": "2020-12-29", "d1": "2020-12-30", "s": ""}
+                ]
+            },
+            "symbols": ["ESH1", "ESM1"],
+            "stype_in": "native",
+            "stype_out": "product_id",
+            "partial": [],
+            "not_found": [],
+            "message": "OK",
+            "status": 0
+        }"#;
+        let target = mappings_from_symbology_json(json).unwrap();
+        assert_eq!(target.len(), 2);
+        assert_eq!(target[0].native, "ESH1");
+        assert_eq!(
+            target[0].intervals[1],
+            MappingInterval {
+                start_date: date(2020, 12, 29),
+                end_date: date(2020, 12, 30),
+                symbol: "".to_owned(),
+            }
+        );
+        assert_eq!(target[1].intervals[0].symbol, "5483");
+    }
+
+    #[test]
+    fn test_mappings_from_symbology_json_invalid_date() {
+        let json = r#"{"result": {"ESH1": [{"d0": "2020-12", "d1": "2020-12-29", "s": "5482"}]}}"#;
+        assert!(mappings_from_symbology_json(json).is_err());
+    }
+
+    #[test]
+    fn test_symbology_json_round_trip() {
+        let mut metadata = metadata_with_mappings(vec![SymbolMapping {
+            native: "ESH1".to_owned(),
+            intervals: vec![MappingInterval {
+                start_date: date(2020, 12, 28),
+                end_date: date(2020, 12, 29),
+                symbol: "5482".to_owned(),
+            }],
+        }]);
+        // 2020-12-28T00:00:00Z
+        metadata.start = 1609113600000000000;
+        let json = metadata.to_symbology_json();
+        assert!(json.starts_with(
+            r#"{"result":{"ESH1":[{"d0":"2020-12-28","d1":"2020-12-29","s":"5482"}]},"symbols":["ESH1"],"stype_in":"native","stype_out":"product_id","start_date":"2020-12-28","end_date":"1970-01-01""#
+        ));
+        assert_eq!(
+            mappings_from_symbology_json(&json).unwrap(),
+            metadata.mappings
+        );
+    }
+}
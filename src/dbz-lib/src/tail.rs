@@ -0,0 +1,237 @@
+//! Reading the last records of a DBZ file without decompressing the Zstd frames
+//! before them.
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context};
+use zstd::Decoder;
+
+use crate::{record::record_size, Dbz, Metadata, RecordIndex};
+
+/// The magic number of a Zstd frame of compressed data.
+const ZSTD_FRAME_MAGIC: u32 = 0xFD2FB528;
+/// The length of a Zstd block header.
+const BLOCK_HEADER_LEN: usize = 3;
+/// The length of the content checksum at the end of a Zstd frame.
+const CONTENT_CHECKSUM_LEN: i64 = 4;
+
+impl Dbz<BufReader<File>> {
+    /// Creates a new [`Dbz`] from the file at `path` whose records start `count`
+    /// records before the end, e.g. to read the last records or the last timestamp
+    /// of a large file. The metadata is unchanged.
+    ///
+    /// When the index at [`RecordIndex::path_for`] exists, decoding starts from the
+    /// frame containing the first of the records, like [`Dbz::from_file_at_record`].
+    /// Otherwise, the Zstd frames of records are located from their headers without
+    /// being decompressed, and only the last frames holding the records are
+    /// decompressed. Files with a single frame of records, like those written without
+    /// [`WriterOptions::frame_record_count`](crate::WriterOptions::frame_record_count),
+    /// are decompressed in full.
+    ///
+    /// # Errors
+    /// This function returns an error if `path` can't be read, the index exists but
+    /// is invalid or doesn't match the file, or the records aren't valid Zstd frames.
+    /// It will also return an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`](databento_defs::enums::Schema::Statistics).
+    pub fn from_file_tail(path: impl AsRef<Path>, count: u64) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let index_path = RecordIndex::path_for(path);
+        if index_path.exists() {
+            let index = RecordIndex::from_file(&index_path)?;
+            let record = index.len().saturating_sub(count as usize);
+            return Self::from_file_at_record(path, record);
+        }
+        let mut file = File::open(path)
+            .with_context(|| format!("Error opening dbz file at path '{}'", path.display()))?;
+        let metadata = Metadata::read(&mut file)?;
+        let record_size = record_size(metadata.schema)
+            .ok_or_else(|| anyhow!("Not implemented for schema {}", metadata.schema.as_str()))?
+            as u64;
+        let mut reader = BufReader::new(file);
+        let frames = frame_positions(&mut reader)
+            .with_context(|| format!("Failed to locate the Zstd frames of '{}'", path.display()))?;
+        let mut start = reader.stream_position()?;
+        // the number of records in the frames from `start`
+        let mut frame_records = 0;
+        for &position in frames.iter().rev() {
+            if frame_records >= count {
+                break;
+            }
+            reader.seek(SeekFrom::Start(position))?;
+            let mut decoder = Decoder::with_buffer(&mut reader)?.single_frame();
+            let len = io::copy(&mut decoder, &mut io::sink())
+                .with_context(|| format!("Failed to decompress the frame at {position}"))?;
+            frame_records += len / record_size;
+            start = position;
+        }
+        reader.seek(SeekFrom::Start(start))?;
+        let records_before = metadata.record_count.saturating_sub(frame_records);
+        let skip_bytes = frame_records.saturating_sub(count) * record_size;
+        Ok(Self::from_parts(reader, metadata)
+            .with_records_before(records_before)
+            .with_skip_bytes(skip_bytes))
+    }
+}
+
+/// Returns the positions of the Zstd frames of records from the current position of
+/// `reader` to the end, skipping over their blocks and any skippable frames, like a
+/// checksum footer, without decompressing them.
+fn frame_positions(reader: &mut BufReader<File>) -> anyhow::Result<Vec<u64>> {
+    let mut positions = Vec::new();
+    loop {
+        let position = reader.stream_position()?;
+        let mut magic = [0; 4];
+        match reader.read_exact(&mut magic) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(positions),
+            Err(e) => return Err(e.into()),
+        }
+        let magic = u32::from_le_bytes(magic);
+        if Metadata::ZSTD_MAGIC_RANGE.contains(&magic) {
+            let size = read_le(reader, 4).context("Skippable frame truncated")?;
+            reader.seek_relative(size as i64)?;
+            continue;
+        }
+        if magic != ZSTD_FRAME_MAGIC {
+            return Err(anyhow!(
+                "Invalid Zstd magic number {magic:#010x} at offset {position}"
+            ));
+        }
+        skip_frame(reader).with_context(|| format!("Zstd frame at {position} truncated"))?;
+        positions.push(position);
+    }
+}
+
+/// Skips the rest of a Zstd frame after its magic number, using the sizes in its
+/// header and block headers.
+fn skip_frame(reader: &mut BufReader<File>) -> anyhow::Result<()> {
+    let descriptor = read_le(reader, 1)? as u8;
+    let content_size_flag = descriptor >> 6;
+    let is_single_segment = descriptor & (1 << 5) != 0;
+    let has_checksum = descriptor & (1 << 2) != 0;
+    let dict_id_len = [0, 1, 2, 4][(descriptor & 0b11) as usize];
+    let content_size_len = match content_size_flag {
+        0 => u8::from(is_single_segment) as i64,
+        flag => 1 << flag,
+    };
+    let window_descriptor_len = i64::from(!is_single_segment);
+    reader.seek_relative(window_descriptor_len + dict_id_len + content_size_len)?;
+    loop {
+        let block_header = read_le(reader, BLOCK_HEADER_LEN)?;
+        let is_last = block_header & 1 != 0;
+        let block_size = (block_header >> 3) as i64;
+        let compressed_len = match (block_header >> 1) & 0b11 {
+            // raw and compressed blocks
+            0 | 2 => block_size,
+            // an RLE block is a single repeated byte
+            1 => 1,
+            _ => return Err(anyhow!("Reserved Zstd block type")),
+        };
+        reader.seek_relative(compressed_len)?;
+        if is_last {
+            break;
+        }
+    }
+    if has_checksum {
+        reader.seek_relative(CONTENT_CHECKSUM_LEN)?;
+    }
+    Ok(())
+}
+
+/// Reads a little-endian unsigned integer of `len` bytes.
+fn read_le(reader: &mut impl Read, len: usize) -> io::Result<u64> {
+    let mut buffer = [0; 8];
+    reader.read_exact(&mut buffer[..len])?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use databento_defs::record::TradeMsg;
+    use streaming_iterator::StreamingIterator;
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+    use crate::{DbzWriter, WriterOptions};
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    /// Writes `count` trades with `ts_event`s 0, 1, ... to a temporary file in frames
+    /// of 2 records with a checksum footer.
+    fn write_trades(dir: &TempDir, count: u64) -> PathBuf {
+        let dbz = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let mut metadata = dbz.metadata().clone();
+        metadata.record_count = count;
+        let template = dbz
+            .try_into_iter::<TradeMsg>()
+            .unwrap()
+            .next()
+            .unwrap()
+            .clone();
+        let path = dir.path().join("trades.dbz");
+        let options = WriterOptions {
+            frame_record_count: Some(2),
+            should_write_checksum: true,
+            ..Default::default()
+        };
+        let mut writer =
+            DbzWriter::with_options(File::create(&path).unwrap(), &metadata, &options).unwrap();
+        for ts_event in 0..count {
+            let mut record = template.clone();
+            record.hd.ts_event = ts_event;
+            writer.write_record(&record).unwrap();
+        }
+        writer.finish().unwrap();
+        path
+    }
+
+    fn ts_events(dbz: Dbz<BufReader<File>>) -> Vec<u64> {
+        let mut iter = dbz.try_into_iter::<TradeMsg>().unwrap();
+        let mut ts_events = Vec::new();
+        while let Some(record) = iter.next() {
+            ts_events.push(record.hd.ts_event);
+        }
+        ts_events
+    }
+
+    #[test]
+    fn test_from_file_tail() {
+        let dir = tempdir().unwrap();
+        let path = write_trades(&dir, 5);
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        Metadata::read(&mut reader).unwrap();
+        // 3 frames of records and no frame for the footer
+        assert_eq!(frame_positions(&mut reader).unwrap().len(), 3);
+        for (count, expected) in [(0, vec![]), (3, vec![2, 3, 4]), (10, vec![0, 1, 2, 3, 4])] {
+            assert_eq!(
+                ts_events(Dbz::from_file_tail(&path, count).unwrap()),
+                expected
+            );
+        }
+        // with an index
+        let index_path = RecordIndex::path_for(&path);
+        RecordIndex::build(&path)
+            .unwrap()
+            .encode(File::create(&index_path).unwrap())
+            .unwrap();
+        assert_eq!(
+            ts_events(Dbz::from_file_tail(&path, 3).unwrap()),
+            vec![2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn test_from_file_tail_single_frame() {
+        let path = format!("{DBZ_PATH}/test_data.trades.dbz");
+        let expected = ts_events(Dbz::from_file(&path).unwrap());
+        assert_eq!(
+            ts_events(Dbz::from_file_tail(&path, 1).unwrap()),
+            &expected[1..]
+        );
+    }
+}
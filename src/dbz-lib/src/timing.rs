@@ -0,0 +1,286 @@
+//! Detection of timestamp problems introduced when capturing data.
+use std::{collections::BTreeMap, convert::Infallible, io, time::Duration};
+
+use serde::Serialize;
+
+use crate::{
+    fields::{FieldValue, FieldVisitor, RecordHandler, Scope, VisitFields},
+    Dbz,
+};
+
+/// Timestamp problems found in DBZ data, returned by [`Dbz::timing_report`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct TimingReport {
+    /// The problems found in the records of each publisher, in order of
+    /// `publisher_id`.
+    pub publishers: Vec<PublisherTiming>,
+}
+
+/// Timestamp problems found in the records of a single publisher.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PublisherTiming {
+    /// The publisher the problems were found for.
+    pub publisher_id: u16,
+    /// The number of records of the publisher.
+    pub record_count: u64,
+    /// Records with a `ts_recv` earlier than their `ts_event`, suggesting clock
+    /// skew between the capture server and the publisher.
+    pub recv_before_event: TimingIssues,
+    /// Records whose `ts_in_delta` exceeds the threshold.
+    pub ts_in_delta_outliers: TimingIssues,
+    /// Runs of consecutive records with a `ts_event` earlier than a preceding record
+    /// of the same publisher. Each run counts once and is identified by its first
+    /// record.
+    pub out_of_order_runs: TimingIssues,
+    /// The largest `ts_in_delta` of the records, if they have one.
+    pub max_ts_in_delta: Option<i32>,
+}
+
+/// The number of occurrences of a problem and the indices of the first few records
+/// it occurred in.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct TimingIssues {
+    /// The number of occurrences of the problem.
+    pub count: u64,
+    /// The indices of up to [`TimingIssues::MAX_RECORD_INDICES`] records, in order.
+    pub record_indices: Vec<u64>,
+}
+
+impl TimingIssues {
+    /// The maximum number of record indices kept as examples.
+    pub const MAX_RECORD_INDICES: usize = 10;
+
+    pub(crate) fn add(&mut self, index: u64) {
+        self.count += 1;
+        if self.record_indices.len() < Self::MAX_RECORD_INDICES {
+            self.record_indices.push(index);
+        }
+    }
+}
+
+impl TimingReport {
+    /// Returns `true` if no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.publishers.iter().all(|p| {
+            p.recv_before_event.count == 0
+                && p.ts_in_delta_outliers.count == 0
+                && p.out_of_order_runs.count == 0
+        })
+    }
+}
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Checks the timestamps of the records for signs of capture problems: records
+    /// received before the event occurred, `ts_in_delta` values greater than
+    /// `ts_in_delta_threshold`, and runs of records with decreasing `ts_event`. The
+    /// results are grouped by `publisher_id`.
+    ///
+    /// Records without `ts_recv` or `ts_in_delta` fields, such as OHLCV, are only
+    /// checked for ordering.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`](crate::Schema::Statistics). It will also return an
+    /// error if there's an issue reading the records.
+    pub fn timing_report(self, ts_in_delta_threshold: Duration) -> anyhow::Result<TimingReport> {
+        let mut checker = TimingChecker::new(ts_in_delta_threshold);
+        self.handle_records(&mut checker)?;
+        Ok(checker.into_report())
+    }
+}
+
+struct TimingChecker {
+    ts_in_delta_threshold: u128,
+    index: u64,
+    publishers: BTreeMap<u16, PublisherState>,
+}
+
+impl TimingChecker {
+    fn new(ts_in_delta_threshold: Duration) -> Self {
+        Self {
+            ts_in_delta_threshold: ts_in_delta_threshold.as_nanos(),
+            index: 0,
+            publishers: BTreeMap::new(),
+        }
+    }
+
+    fn into_report(self) -> TimingReport {
+        TimingReport {
+            publishers: self
+                .publishers
+                .into_values()
+                .map(|state| state.timing)
+                .collect(),
+        }
+    }
+}
+
+struct PublisherState {
+    timing: PublisherTiming,
+    max_ts_event: u64,
+    is_out_of_order: bool,
+}
+
+impl RecordHandler for TimingChecker {
+    fn handle<T: VisitFields>(&mut self, record: &T) {
+        let hd = record.header();
+        let mut times = RecordTimes::default();
+        // `RecordTimes` never fails
+        let _ = record.visit_fields(&mut times);
+        let state = self
+            .publishers
+            .entry(hd.publisher_id)
+            .or_insert_with(|| PublisherState {
+                timing: PublisherTiming {
+                    publisher_id: hd.publisher_id,
+                    ..Default::default()
+                },
+                max_ts_event: hd.ts_event,
+                is_out_of_order: false,
+            });
+        let timing = &mut state.timing;
+        timing.record_count += 1;
+        if matches!(times.ts_recv, Some(ts_recv) if ts_recv < hd.ts_event) {
+            timing.recv_before_event.add(self.index);
+        }
+        if let Some(ts_in_delta) = times.ts_in_delta {
+            if ts_in_delta.unsigned_abs() as u128 > self.ts_in_delta_threshold {
+                timing.ts_in_delta_outliers.add(self.index);
+            }
+            timing.max_ts_in_delta = timing.max_ts_in_delta.max(Some(ts_in_delta));
+        }
+        if hd.ts_event < state.max_ts_event {
+            if !state.is_out_of_order {
+                timing.out_of_order_runs.add(self.index);
+            }
+            state.is_out_of_order = true;
+        } else {
+            state.max_ts_event = hd.ts_event;
+            state.is_out_of_order = false;
+        }
+        self.index += 1;
+    }
+}
+
+/// Picks out the timestamps of a record that aren't part of the header.
+#[derive(Default)]
+struct RecordTimes {
+    ts_recv: Option<u64>,
+    ts_in_delta: Option<i32>,
+}
+
+impl FieldVisitor for RecordTimes {
+    type Error = Infallible;
+
+    fn visit(
+        &mut self,
+        scope: Scope,
+        name: &'static str,
+        value: FieldValue,
+    ) -> Result<(), Infallible> {
+        match (scope, name, value) {
+            (Scope::Body, "ts_recv", FieldValue::Timestamp(ts_recv)) => {
+                self.ts_recv = Some(ts_recv)
+            }
+            (Scope::Body, "ts_in_delta", FieldValue::I32(ts_in_delta)) => {
+                self.ts_in_delta = Some(ts_in_delta)
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use databento_defs::record::{RecordHeader, TradeMsg};
+
+    use super::*;
+    use crate::write::test_data::RECORD_HEADER;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    fn trade(publisher_id: u16, ts_event: u64, ts_recv: u64, ts_in_delta: i32) -> TradeMsg {
+        TradeMsg {
+            hd: RecordHeader {
+                publisher_id,
+                ts_event,
+                ..RECORD_HEADER
+            },
+            price: 5500,
+            size: 3,
+            action: 'T' as i8,
+            side: 'A' as i8,
+            flags: 0,
+            depth: 0,
+            ts_recv,
+            ts_in_delta,
+            sequence: 1,
+            booklevel: [],
+        }
+    }
+
+    fn check(records: &[TradeMsg]) -> TimingReport {
+        let mut checker = TimingChecker::new(Duration::from_millis(1));
+        for record in records {
+            checker.handle(record);
+        }
+        checker.into_report()
+    }
+
+    #[test]
+    fn test_timing_report_test_data() {
+        let target = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbp-10.dbz")).unwrap();
+        let report = target.timing_report(Duration::from_millis(1)).unwrap();
+        assert!(report.is_clean(), "{report:?}");
+        assert_eq!(report.publishers.len(), 1);
+        assert_eq!(report.publishers[0].record_count, 2);
+        assert!(report.publishers[0].max_ts_in_delta.is_some());
+    }
+
+    #[test]
+    fn test_recv_before_event_and_outliers() {
+        let report = check(&[
+            trade(1, 100, 150, 10),
+            trade(1, 200, 150, 10),
+            trade(1, 300, 350, 2_000_000),
+        ]);
+        let timing = &report.publishers[0];
+        assert_eq!(timing.recv_before_event.record_indices, vec![1]);
+        assert_eq!(timing.ts_in_delta_outliers.record_indices, vec![2]);
+        assert_eq!(timing.max_ts_in_delta, Some(2_000_000));
+        assert!(!report.is_clean());
+    }
+
+    #[test]
+    fn test_out_of_order_runs_by_publisher() {
+        let report = check(&[
+            trade(1, 100, 100, 0),
+            trade(2, 50, 50, 0),
+            trade(1, 90, 100, 0),
+            trade(1, 95, 100, 0),
+            trade(1, 110, 110, 0),
+            trade(1, 105, 110, 0),
+        ]);
+        assert_eq!(report.publishers.len(), 2);
+        let publisher_1 = &report.publishers[0];
+        assert_eq!(publisher_1.record_count, 5);
+        assert_eq!(publisher_1.out_of_order_runs.count, 2);
+        assert_eq!(publisher_1.out_of_order_runs.record_indices, vec![2, 5]);
+        // publisher 2's earlier timestamp doesn't affect publisher 1
+        assert_eq!(report.publishers[1].out_of_order_runs.count, 0);
+    }
+
+    #[test]
+    fn test_timing_issues_limits_record_indices() {
+        let mut issues = TimingIssues::default();
+        for i in 0..20 {
+            issues.add(i);
+        }
+        assert_eq!(issues.count, 20);
+        assert_eq!(
+            issues.record_indices.len(),
+            TimingIssues::MAX_RECORD_INDICES
+        );
+    }
+}
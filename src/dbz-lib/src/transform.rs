@@ -0,0 +1,749 @@
+//! Conversions of DBZ data from one schema to another and filters and rewrites of
+//! its records.
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::{self, Write},
+    mem,
+};
+
+use anyhow::anyhow;
+use databento_defs::{
+    enums::{SType, Schema},
+    record::{
+        ConstTypeId, Mbp10Msg, Mbp1Msg, OhlcvMsg, RecordHeader, StatusMsg, SymDefMsg, TbboMsg,
+        TickMsg, TradeMsg,
+    },
+};
+use streaming_iterator::StreamingIterator;
+
+use crate::{
+    fields::VisitFields, write::dbz::new_manual_encoder, write_dbz_stream, Dbz, Metadata, Record,
+    WriterOptions,
+};
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Converts MBP-10 data to MBP-1 and writes it in the DBZ format to `writer`,
+    /// keeping only the top book level of each record. Like MBP-1 data, only records
+    /// that changed the top of the book, i.e. with a `depth` of 0, and trades are
+    /// kept. The metadata's `schema` and `record_count` are rewritten to match.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] isn't
+    /// [`Schema::Mbp10`]. It will also return an error if there's an issue reading the
+    /// records or writing the output to `writer`.
+    pub fn write_mbp1_to(self, mut writer: impl io::Write + io::Seek) -> anyhow::Result<()> {
+        if self.schema() != Schema::Mbp10 {
+            return Err(anyhow!(
+                "Can only convert mbp-10 to mbp-1, found {}",
+                self.schema().as_str()
+            ));
+        }
+        let mut metadata = self.metadata().clone();
+        metadata.schema = Schema::Mbp1;
+        metadata.encode(&mut writer)?;
+        let mut record_count = 0;
+        let mut iter = self.try_into_iter::<Mbp10Msg>()?;
+        let records = (&mut iter)
+            .filter(|rec| rec.depth == 0 || rec.action as u8 == b'T')
+            .map(|rec| {
+                record_count += 1;
+                to_mbp1(rec)
+            });
+        write_dbz_stream(&mut writer, records)?;
+        if let Some(error) = iter.take_error() {
+            return Err(error);
+        }
+        Metadata::update_encoded(
+            writer,
+            metadata.start,
+            metadata.end,
+            metadata.limit,
+            record_count,
+        )
+    }
+
+    /// Writes only the records of the products in `product_ids` in the DBZ format to
+    /// `writer`. The metadata's `record_count` is rewritten to match and, if its
+    /// `stype_out` is [`SType::ProductId`], symbol mappings to other products are
+    /// dropped.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`]. It will also return an error if there's an issue reading
+    /// the records or writing the output to `writer`.
+    pub fn write_products_to(
+        self,
+        writer: impl io::Write + io::Seek,
+        product_ids: &HashSet<u32>,
+    ) -> anyhow::Result<()> {
+        match self.schema() {
+            Schema::Mbo => self.write_products_of::<TickMsg>(writer, product_ids),
+            Schema::Mbp1 | Schema::Tbbo => self.write_products_of::<Mbp1Msg>(writer, product_ids),
+            Schema::Mbp10 => self.write_products_of::<Mbp10Msg>(writer, product_ids),
+            Schema::Trades => self.write_products_of::<TradeMsg>(writer, product_ids),
+            Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D => {
+                self.write_products_of::<OhlcvMsg>(writer, product_ids)
+            }
+            Schema::Definition => self.write_products_of::<SymDefMsg>(writer, product_ids),
+            Schema::Statistics => Err(anyhow!("Not implemented for schema Statistics")),
+            Schema::Status => self.write_products_of::<StatusMsg>(writer, product_ids),
+        }
+    }
+
+    fn write_products_of<T: VisitFields>(
+        self,
+        mut writer: impl io::Write + io::Seek,
+        product_ids: &HashSet<u32>,
+    ) -> anyhow::Result<()> {
+        let mut metadata = self.metadata().clone();
+        if metadata.stype_out == SType::ProductId {
+            metadata.mappings.retain(|mapping| {
+                mapping.intervals.iter().any(|interval| {
+                    interval
+                        .symbol
+                        .parse()
+                        .is_ok_and(|id| product_ids.contains(&id))
+                })
+            });
+        }
+        metadata.encode(&mut writer)?;
+        let mut record_count = 0;
+        let mut iter = self.try_into_iter::<T>()?;
+        let records = (&mut iter)
+            .filter(|rec| product_ids.contains(&rec.header().product_id))
+            .inspect(|_| record_count += 1);
+        write_dbz_stream(&mut writer, records)?;
+        if let Some(error) = iter.take_error() {
+            return Err(error);
+        }
+        Metadata::update_encoded(
+            writer,
+            metadata.start,
+            metadata.end,
+            metadata.limit,
+            record_count,
+        )
+    }
+}
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Aggregates trades into OHLCV bars of the interval of `schema`, e.g.
+    /// [`Schema::Ohlcv1M`] for one-minute bars, and writes them in the DBZ format to
+    /// `writer`, so candles can be produced locally from trades instead of
+    /// downloaded. Returns the number of bars written.
+    ///
+    /// Like Databento's OHLCV data, each product has a bar for each interval with
+    /// at least one trade, with a `ts_event` of the start of the interval. Bars of the
+    /// same interval are ordered by product ID. The metadata's `schema` and
+    /// `record_count` are rewritten to match.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] isn't [`Schema::Trades`] or
+    /// [`Schema::Tbbo`], `schema` isn't an OHLCV schema, or the trades aren't in
+    /// `ts_event` order. It will also return an error if there's an issue reading the
+    /// records or writing the output to `writer`.
+    pub fn write_ohlcv_to(
+        self,
+        writer: impl io::Write + io::Seek,
+        schema: Schema,
+    ) -> anyhow::Result<u64> {
+        let interval = match schema {
+            Schema::Ohlcv1S => NANOS_PER_SECOND,
+            Schema::Ohlcv1M => 60 * NANOS_PER_SECOND,
+            Schema::Ohlcv1H => 60 * 60 * NANOS_PER_SECOND,
+            Schema::Ohlcv1D => 24 * 60 * 60 * NANOS_PER_SECOND,
+            _ => {
+                return Err(anyhow!(
+                    "Can only aggregate into an OHLCV schema, found {}",
+                    schema.as_str()
+                ))
+            }
+        };
+        match self.schema() {
+            Schema::Trades => self.write_ohlcv_of::<TradeMsg>(writer, schema, interval),
+            Schema::Tbbo => self.write_ohlcv_of::<TbboMsg>(writer, schema, interval),
+            other => Err(anyhow!(
+                "Can only aggregate trades or tbbo into OHLCV, found {}",
+                other.as_str()
+            )),
+        }
+    }
+
+    fn write_ohlcv_of<T: ConstTypeId + Trade>(
+        self,
+        mut writer: impl io::Write + io::Seek,
+        schema: Schema,
+        interval: u64,
+    ) -> anyhow::Result<u64> {
+        let mut metadata = self.metadata().clone();
+        metadata.schema = schema;
+        metadata.encode(&mut writer)?;
+        let mut encoder = new_manual_encoder(&mut writer, &WriterOptions::default())?;
+        let mut record_count = 0;
+        // the bars of the current interval by product ID
+        let mut bars = BTreeMap::<u32, OhlcvMsg>::new();
+        let mut bar_start = 0;
+        let mut i = 0;
+        let mut trades = self.try_into_iter::<T>()?;
+        while let Some(trade) = trades.next() {
+            let hd = trade.header();
+            let trade_bar_start = hd.ts_event - hd.ts_event % interval;
+            if trade_bar_start < bar_start {
+                return Err(anyhow!(
+                    "Trade {i} is out of ts_event order. Sort the file before aggregating it"
+                ));
+            }
+            if trade_bar_start > bar_start {
+                for bar in mem::take(&mut bars).into_values() {
+                    encoder.write_all(Record::Ohlcv(bar).as_bytes())?;
+                    record_count += 1;
+                }
+                bar_start = trade_bar_start;
+            }
+            let price = trade.price();
+            bars.entry(hd.product_id)
+                .and_modify(|bar| {
+                    bar.high = bar.high.max(price);
+                    bar.low = bar.low.min(price);
+                    bar.close = price;
+                    bar.volume += trade.size() as u64;
+                })
+                .or_insert_with(|| OhlcvMsg {
+                    hd: RecordHeader {
+                        length: (mem::size_of::<OhlcvMsg>() / 4) as u8,
+                        rtype: OhlcvMsg::TYPE_ID,
+                        ts_event: bar_start,
+                        ..hd.clone()
+                    },
+                    open: price,
+                    high: price,
+                    low: price,
+                    close: price,
+                    volume: trade.size() as u64,
+                });
+            i += 1;
+        }
+        if let Some(error) = trades.error() {
+            return Err(anyhow!("{error:#}"));
+        }
+        for bar in bars.into_values() {
+            encoder.write_all(Record::Ohlcv(bar).as_bytes())?;
+            record_count += 1;
+        }
+        encoder.finish()?;
+        Metadata::update_encoded(
+            &mut writer,
+            metadata.start,
+            metadata.end,
+            metadata.limit,
+            record_count,
+        )?;
+        writer.flush()?;
+        Ok(record_count)
+    }
+}
+
+/// How to rewrite the timestamps of records with [`Dbz::write_retimestamped_to`], e.g.
+/// to give a simulator a synthetic clock. The `ts_event` is replaced first, then all
+/// timestamps are shifted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Retimestamp {
+    /// The number of nanoseconds to add to every timestamp. Negative values shift
+    /// timestamps earlier.
+    pub shift: i64,
+    /// Replace each record's `ts_event` with its `ts_recv`, for records that have one.
+    pub should_use_ts_recv_as_ts_event: bool,
+}
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Rewrites the timestamps of the records as `retimestamp` describes and writes
+    /// them in the DBZ format to `writer`. The metadata's `start` and `end` are
+    /// shifted along with the records and widened to include every rewritten
+    /// `ts_event`.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`]. It will also return an error if there's an issue reading
+    /// the records or writing the output to `writer`.
+    pub fn write_retimestamped_to(
+        self,
+        writer: impl io::Write + io::Seek,
+        retimestamp: &Retimestamp,
+    ) -> anyhow::Result<()> {
+        match self.schema() {
+            Schema::Mbo => self.write_retimestamped_of::<TickMsg>(writer, retimestamp),
+            Schema::Mbp1 | Schema::Tbbo => {
+                self.write_retimestamped_of::<Mbp1Msg>(writer, retimestamp)
+            }
+            Schema::Mbp10 => self.write_retimestamped_of::<Mbp10Msg>(writer, retimestamp),
+            Schema::Trades => self.write_retimestamped_of::<TradeMsg>(writer, retimestamp),
+            Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D => {
+                self.write_retimestamped_of::<OhlcvMsg>(writer, retimestamp)
+            }
+            Schema::Definition => self.write_retimestamped_of::<SymDefMsg>(writer, retimestamp),
+            Schema::Statistics => Err(anyhow!("Not implemented for schema Statistics")),
+            Schema::Status => self.write_retimestamped_of::<StatusMsg>(writer, retimestamp),
+        }
+    }
+
+    fn write_retimestamped_of<T: VisitFields + Clone + Timestamps>(
+        self,
+        mut writer: impl io::Write + io::Seek,
+        retimestamp: &Retimestamp,
+    ) -> anyhow::Result<()> {
+        let metadata = self.metadata().clone();
+        let has_range = metadata.end > metadata.start;
+        let mut start = shift_ts(metadata.start, retimestamp.shift);
+        let mut end = shift_ts(metadata.end, retimestamp.shift);
+        metadata.encode(&mut writer)?;
+        let mut iter = self.try_into_iter::<T>()?;
+        let records = (&mut iter).map(|rec| {
+            let mut rec = rec.clone();
+            rec.retimestamp(retimestamp);
+            let ts_event = rec.header().ts_event;
+            if has_range {
+                start = start.min(ts_event);
+                end = end.max(ts_event.saturating_add(1));
+            }
+            rec
+        });
+        write_dbz_stream(&mut writer, records)?;
+        if let Some(error) = iter.take_error() {
+            return Err(error);
+        }
+        let (start, end) = if has_range {
+            (start, end)
+        } else {
+            (metadata.start, metadata.end)
+        };
+        Metadata::update_encoded(writer, start, end, metadata.limit, metadata.record_count)
+    }
+}
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Passes each record to `f` and writes the records it returns in the DBZ format
+    /// to `writer`, for custom transforms that modify or drop records. Records for
+    /// which `f` returns `None` are dropped. Returns the number of records written.
+    ///
+    /// The metadata's `record_count` is rewritten to match and, unless it has no time
+    /// range, its `start` and `end` are widened to include every rewritten
+    /// `ts_event`.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`] or `f` returns a record of a different type than it was
+    /// passed, which can't be encoded with the same schema. It will also return an
+    /// error if there's an issue reading the records or writing the output to
+    /// `writer`.
+    pub fn rewrite(
+        self,
+        mut writer: impl io::Write + io::Seek,
+        mut f: impl FnMut(Record) -> Option<Record>,
+    ) -> anyhow::Result<u64> {
+        let metadata = self.metadata().clone();
+        let has_range = metadata.end > metadata.start;
+        let (mut start, mut end) = (metadata.start, metadata.end);
+        let records = self.into_record_iter()?;
+        metadata.encode(&mut writer)?;
+        let mut encoder = new_manual_encoder(&mut writer, &WriterOptions::default())?;
+        let mut record_count = 0;
+        for (i, record) in records.enumerate() {
+            let record = record?;
+            let discriminant = mem::discriminant(&record);
+            let Some(record) = f(record) else {
+                continue;
+            };
+            if mem::discriminant(&record) != discriminant {
+                return Err(anyhow!(
+                    "Rewritten record {i} isn't of the type of schema {}",
+                    metadata.schema
+                ));
+            }
+            let ts_event = record.header().ts_event;
+            if has_range {
+                start = start.min(ts_event);
+                end = end.max(ts_event.saturating_add(1));
+            }
+            encoder.write_all(record.as_bytes())?;
+            record_count += 1;
+        }
+        encoder.finish()?;
+        Metadata::update_encoded(&mut writer, start, end, metadata.limit, record_count)?;
+        writer.flush()?;
+        Ok(record_count)
+    }
+}
+
+/// Shifts `ts` by `shift` nanoseconds, leaving unset timestamps alone.
+fn shift_ts(ts: u64, shift: i64) -> u64 {
+    if ts == 0 || ts == u64::MAX {
+        ts
+    } else {
+        ts.saturating_add_signed(shift)
+    }
+}
+
+/// Records of trades that can be aggregated into OHLCV bars.
+trait Trade {
+    fn header(&self) -> &RecordHeader;
+    fn price(&self) -> i64;
+    fn size(&self) -> u32;
+}
+
+macro_rules! impl_trade {
+    ($($record:ty),+) => {
+        $(
+            impl Trade for $record {
+                fn header(&self) -> &RecordHeader {
+                    &self.hd
+                }
+
+                fn price(&self) -> i64 {
+                    self.price
+                }
+
+                fn size(&self) -> u32 {
+                    self.size
+                }
+            }
+        )+
+    };
+}
+
+impl_trade!(TradeMsg, TbboMsg);
+
+/// Records whose timestamps can be rewritten.
+trait Timestamps {
+    fn retimestamp(&mut self, retimestamp: &Retimestamp);
+}
+
+macro_rules! impl_timestamps_with_ts_recv {
+    ($($record:ty),+) => {
+        $(
+            impl Timestamps for $record {
+                fn retimestamp(&mut self, retimestamp: &Retimestamp) {
+                    if retimestamp.should_use_ts_recv_as_ts_event {
+                        self.hd.ts_event = self.ts_recv;
+                    }
+                    self.hd.ts_event = shift_ts(self.hd.ts_event, retimestamp.shift);
+                    self.ts_recv = shift_ts(self.ts_recv, retimestamp.shift);
+                }
+            }
+        )+
+    };
+}
+
+impl_timestamps_with_ts_recv!(TickMsg, TradeMsg, Mbp1Msg, Mbp10Msg, SymDefMsg, StatusMsg);
+
+impl Timestamps for OhlcvMsg {
+    fn retimestamp(&mut self, retimestamp: &Retimestamp) {
+        self.hd.ts_event = shift_ts(self.hd.ts_event, retimestamp.shift);
+    }
+}
+
+pub(crate) fn to_mbp1(rec: &Mbp10Msg) -> Mbp1Msg {
+    let mut hd = rec.hd.clone();
+    hd.rtype = Mbp1Msg::TYPE_ID;
+    hd.length = (mem::size_of::<Mbp1Msg>() / 4) as u8;
+    Mbp1Msg {
+        hd,
+        price: rec.price,
+        size: rec.size,
+        action: rec.action,
+        side: rec.side,
+        flags: rec.flags,
+        depth: rec.depth,
+        ts_recv: rec.ts_recv,
+        ts_in_delta: rec.ts_in_delta,
+        sequence: rec.sequence,
+        booklevel: [rec.booklevel[0].clone()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    use databento_defs::record::{BidAskPair, RecordHeader};
+
+    use super::*;
+    use crate::{
+        write::test_data::{VecStream, BID_ASK, RECORD_HEADER},
+        DbzWriter,
+    };
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    fn mbp10(action: u8, depth: u8, top: BidAskPair) -> Mbp10Msg {
+        let mut booklevel = [BID_ASK; 10];
+        booklevel[0] = top;
+        Mbp10Msg {
+            hd: RecordHeader {
+                length: (mem::size_of::<Mbp10Msg>() / 4) as u8,
+                rtype: Mbp10Msg::TYPE_ID,
+                ..RECORD_HEADER
+            },
+            price: 5500,
+            size: 3,
+            action: action as i8,
+            side: b'B' as i8,
+            flags: 0,
+            depth,
+            ts_recv: 1658441891000000000,
+            ts_in_delta: 22_000,
+            sequence: 1_002_375,
+            booklevel,
+        }
+    }
+
+    #[test]
+    fn test_write_mbp1_to() {
+        let top = BidAskPair {
+            bid_px: 1,
+            ..BID_ASK
+        };
+        let records = vec![
+            mbp10(b'A', 0, top.clone()),
+            mbp10(b'C', 3, BID_ASK),
+            mbp10(b'T', 2, top.clone()),
+        ];
+        let source = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbp-10.dbz")).unwrap();
+        let mut metadata = source.metadata().clone();
+        metadata.record_count = records.len() as u64;
+        let mut input = Cursor::new(Vec::new());
+        metadata.encode(&mut input).unwrap();
+        write_dbz_stream(&mut input, VecStream::new(records.clone())).unwrap();
+        input.seek(SeekFrom::Start(0)).unwrap();
+
+        let mut output = Cursor::new(Vec::new());
+        Dbz::new(input).unwrap().write_mbp1_to(&mut output).unwrap();
+        output.seek(SeekFrom::Start(0)).unwrap();
+        let target = Dbz::new(output).unwrap();
+        assert_eq!(target.schema(), Schema::Mbp1);
+        assert_eq!(target.metadata().record_count, 2);
+        assert_eq!(target.metadata().mappings, metadata.mappings);
+        let mut iter = target.try_into_iter::<Mbp1Msg>().unwrap();
+        for exp in [&records[0], &records[2]] {
+            let rec = iter.next().unwrap();
+            assert_eq!(rec.hd.rtype, Mbp1Msg::TYPE_ID);
+            assert_eq!(rec.hd.length as usize * 4, mem::size_of::<Mbp1Msg>());
+            assert_eq!(rec.action, exp.action);
+            assert_eq!(rec.booklevel[0], top);
+        }
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_write_products_to() {
+        for (product_id, exp_count) in [(5482, 2), (1, 0)] {
+            let source = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+            let mut output = Cursor::new(Vec::new());
+            source
+                .write_products_to(&mut output, &HashSet::from([product_id]))
+                .unwrap();
+            output.seek(SeekFrom::Start(0)).unwrap();
+            let target = Dbz::new(output).unwrap();
+            assert_eq!(target.metadata().record_count, exp_count);
+            assert_eq!(target.metadata().mappings.is_empty(), exp_count == 0);
+            assert_eq!(
+                target.try_into_iter::<TradeMsg>().unwrap().count(),
+                exp_count as usize
+            );
+        }
+    }
+
+    #[test]
+    fn test_write_retimestamped_to() {
+        let source = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let metadata = source.metadata().clone();
+        let mut output = Cursor::new(Vec::new());
+        let retimestamp = Retimestamp {
+            shift: -1_000,
+            should_use_ts_recv_as_ts_event: true,
+        };
+        source
+            .write_retimestamped_to(&mut output, &retimestamp)
+            .unwrap();
+        output.seek(SeekFrom::Start(0)).unwrap();
+        let target = Dbz::new(output).unwrap();
+        assert_eq!(target.metadata().start, metadata.start - 1_000);
+        assert_eq!(target.metadata().end, metadata.end - 1_000);
+        assert_eq!(target.metadata().record_count, metadata.record_count);
+        let mut iter = target.try_into_iter::<TradeMsg>().unwrap();
+        let mut source_iter = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz"))
+            .unwrap()
+            .try_into_iter::<TradeMsg>()
+            .unwrap();
+        while let Some(rec) = iter.next() {
+            let exp = source_iter.next().unwrap();
+            assert_eq!(rec.ts_recv, exp.ts_recv - 1_000);
+            assert_eq!(rec.hd.ts_event, rec.ts_recv);
+            assert_eq!(rec.price, exp.price);
+        }
+        assert!(source_iter.next().is_none());
+    }
+
+    #[test]
+    fn test_retimestamp_ohlcv() {
+        let source = Dbz::from_file(format!("{DBZ_PATH}/test_data.ohlcv-1m.dbz")).unwrap();
+        let end = source.metadata().end;
+        let mut output = Cursor::new(Vec::new());
+        let retimestamp = Retimestamp {
+            shift: 86_400_000_000_000,
+            ..Default::default()
+        };
+        source
+            .write_retimestamped_to(&mut output, &retimestamp)
+            .unwrap();
+        output.seek(SeekFrom::Start(0)).unwrap();
+        let target = Dbz::new(output).unwrap();
+        assert_eq!(target.metadata().end, end + 86_400_000_000_000);
+        assert_eq!(shift_ts(0, 5), 0);
+        assert_eq!(shift_ts(u64::MAX, -5), u64::MAX);
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let source = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        let metadata = source.metadata().clone();
+        let mut output = Cursor::new(Vec::new());
+        let mut is_first = true;
+        let record_count = source
+            .rewrite(&mut output, |record| {
+                // drop the first record and scrub the order ID of the rest
+                if mem::take(&mut is_first) {
+                    return None;
+                }
+                let Record::Mbo(mut rec) = record else {
+                    panic!("expected an MBO record");
+                };
+                rec.order_id = 0;
+                rec.hd.ts_event = metadata.end;
+                Some(Record::Mbo(rec))
+            })
+            .unwrap();
+        assert_eq!(record_count, 1);
+        output.seek(SeekFrom::Start(0)).unwrap();
+        let target = Dbz::new(output).unwrap();
+        assert_eq!(target.metadata().record_count, 1);
+        assert_eq!(target.metadata().start, metadata.start);
+        assert_eq!(target.metadata().end, metadata.end + 1);
+        let records = target
+            .try_into_fallible_iter::<TickMsg>()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].order_id, 0);
+    }
+
+    #[test]
+    fn test_rewrite_to_other_type() {
+        let source = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbp-10.dbz")).unwrap();
+        let res = source.rewrite(Cursor::new(Vec::new()), |record| match record {
+            Record::Mbp10(rec) => Some(Record::Mbp1(to_mbp1(&rec))),
+            record => Some(record),
+        });
+        assert!(
+            matches!(res, Err(e) if e.to_string().contains("isn't of the type of schema mbp-10"))
+        );
+    }
+
+    #[test]
+    fn test_write_mbp1_to_requires_mbp10() {
+        let source = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbp-1.dbz")).unwrap();
+        let res = source.write_mbp1_to(Cursor::new(Vec::new()));
+        assert!(matches!(res, Err(e) if e.to_string().contains("Can only convert mbp-10")));
+    }
+
+    /// Writes trades of `(seconds after start, product_id, price, size)` to a buffer.
+    fn trades(trades: &[(u64, u32, i64, u32)]) -> Cursor<Vec<u8>> {
+        let dbz = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let metadata = dbz.metadata().clone();
+        let template = dbz
+            .try_into_iter::<TradeMsg>()
+            .unwrap()
+            .next()
+            .unwrap()
+            .clone();
+        let mut writer = DbzWriter::new(Cursor::new(Vec::new()), &metadata).unwrap();
+        for &(seconds, product_id, price, size) in trades {
+            let mut record = template.clone();
+            // the start is on a minute boundary
+            record.hd.ts_event = metadata.start + seconds * NANOS_PER_SECOND;
+            record.hd.product_id = product_id;
+            record.price = price;
+            record.size = size;
+            writer.write_record(&record).unwrap();
+        }
+        let mut buffer = writer.finish().unwrap();
+        buffer.seek(SeekFrom::Start(0)).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn test_write_ohlcv_to() {
+        let source = Dbz::new(trades(&[
+            (0, 2, 10, 1),
+            (10, 1, 5, 1),
+            (20, 2, 12, 2),
+            (30, 2, 8, 4),
+            (70, 2, 9, 3),
+        ]))
+        .unwrap();
+        let start = source.metadata().start;
+        let mut output = Cursor::new(Vec::new());
+        let record_count = source.write_ohlcv_to(&mut output, Schema::Ohlcv1M).unwrap();
+        assert_eq!(record_count, 3);
+        output.seek(SeekFrom::Start(0)).unwrap();
+        let target = Dbz::new(output).unwrap();
+        assert_eq!(target.schema(), Schema::Ohlcv1M);
+        assert_eq!(target.metadata().record_count, 3);
+        let bars = target
+            .try_into_fallible_iter::<OhlcvMsg>()
+            .unwrap()
+            .map(|bar| {
+                let bar = bar.unwrap();
+                assert_eq!(bar.hd.rtype, OhlcvMsg::TYPE_ID);
+                (
+                    (bar.hd.ts_event - start) / NANOS_PER_SECOND,
+                    bar.hd.product_id,
+                    [bar.open, bar.high, bar.low, bar.close],
+                    bar.volume,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            bars,
+            vec![
+                // ordered by product ID within each minute
+                (0, 1, [5, 5, 5, 5], 1),
+                (0, 2, [10, 12, 8, 8], 7),
+                (60, 2, [9, 9, 9, 9], 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_ohlcv_to_out_of_order() {
+        let source = Dbz::new(trades(&[(70, 1, 5, 1), (10, 1, 5, 1)])).unwrap();
+        let res = source.write_ohlcv_to(Cursor::new(Vec::new()), Schema::Ohlcv1M);
+        assert!(
+            matches!(res, Err(e) if e.to_string().contains("Trade 1 is out of ts_event order"))
+        );
+    }
+
+    #[test]
+    fn test_write_ohlcv_to_requires_ohlcv_schema() {
+        let source = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let res = source.write_ohlcv_to(Cursor::new(Vec::new()), Schema::Mbp1);
+        assert!(
+            matches!(res, Err(e) if e.to_string().contains("Can only aggregate into an OHLCV schema"))
+        );
+        let source = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        let res = source.write_ohlcv_to(Cursor::new(Vec::new()), Schema::Ohlcv1S);
+        assert!(matches!(res, Err(e) if e.to_string().contains("Can only aggregate trades")));
+    }
+}
@@ -1,26 +1,33 @@
-use std::{fmt, io};
+use std::io::{self, Write};
 
 use anyhow::Context;
-use serde::Serialize;
 use streaming_iterator::StreamingIterator;
 
-use databento_defs::record::ConstTypeId;
+use super::{SelectedFields, TextOptions};
+use crate::fields::{FieldValue, FieldVisitor, Scope, VisitFields};
 
 /// Incrementally serializes the contents of `iter` into CSV to `writer` so the
 /// contents of `iter` are not all buffered into memory at once.
-pub fn write_csv<T>(
+pub fn write_csv<T: VisitFields>(
     writer: impl io::Write,
     mut iter: impl StreamingIterator<Item = T>,
-) -> anyhow::Result<()>
-where
-    T: ConstTypeId + serialize::CsvSerialize + Serialize + fmt::Debug,
-{
+    options: &TextOptions,
+) -> anyhow::Result<()> {
     let mut csv_writer = csv::WriterBuilder::new()
         .has_headers(false) // need to write our own custom header
         .from_writer(writer);
-    csv_writer.write_record(T::HEADERS)?;
+    if !options.should_omit_csv_header {
+        csv_writer.write_record(options.headers::<T>())?;
+    }
+    let mut row = CsvRow {
+        csv_writer,
+        options,
+        buffer: Vec::new(),
+        selected: SelectedFields::default(),
+    };
+    let mut record_count = 0;
     while let Some(record) = iter.next() {
-        match record.serialize_to(&mut csv_writer) {
+        match row.write(record) {
             Err(e) => {
                 if matches!(e.kind(), csv::ErrorKind::Io(io_err) if io_err.kind() == io::ErrorKind::BrokenPipe) {
                     // closed pipe, should stop writing output
@@ -32,301 +39,78 @@ where
             r => r,
         }
         .with_context(|| format!("Failed to serialize {record:#?}"))?;
+        record_count += 1;
+        if options.should_flush(record_count) {
+            row.csv_writer.flush()?;
+        }
     }
-    csv_writer.flush()?;
+    row.csv_writer.flush()?;
     Ok(())
 }
 
-pub mod serialize {
-    use csv::Writer;
-    use databento_defs::record::{
-        Mbp10Msg, Mbp1Msg, OhlcvMsg, StatusMsg, SymDefMsg, TickMsg, TradeMsg,
-    };
-    use serde::Serialize;
-    use std::{fmt, io};
-
-    /// Because of the flat nature of CSVs, there are several limitations in the
-    /// Rust CSV serde serialization library. This trait helps work around them.
-    pub trait CsvSerialize: Serialize + fmt::Debug {
-        /// The CSV header needs to be defined in a flat struct (no nested structs)
-        /// in order to work correctly and the library doesn't support `#[serde(flatten)]`.
-        const HEADERS: &'static [&'static str];
+/// Writes records as CSV rows one field at a time.
+struct CsvRow<'a, W: io::Write> {
+    csv_writer: csv::Writer<W>,
+    options: &'a TextOptions,
+    /// Reused for formatting each field.
+    buffer: Vec<u8>,
+    selected: SelectedFields,
+}
 
-        /// Serialize the object to `csv_writer`. Allows custom behavior that would otherwise
-        /// cause a runtime error, e.g. serializing a struct with array field.
-        fn serialize_to<W: io::Write>(&self, csv_writer: &mut Writer<W>) -> csv::Result<()> {
-            csv_writer.serialize(self)
+impl<'a, W: io::Write> CsvRow<'a, W> {
+    fn write<T: VisitFields>(&mut self, record: &T) -> csv::Result<()> {
+        self.options.visit_record(record, self)?;
+        for (_, value) in self.selected.finish(self.options) {
+            self.csv_writer.write_field(value)?;
         }
+        // end of line
+        self.csv_writer.write_record(None::<&[u8]>)
     }
+}
 
-    impl CsvSerialize for TickMsg {
-        const HEADERS: &'static [&'static str] = &[
-            "rtype",
-            "publisher_id",
-            "product_id",
-            "ts_event",
-            "order_id",
-            "price",
-            "size",
-            "flags",
-            "channel_id",
-            "action",
-            "side",
-            "ts_recv",
-            "ts_in_delta",
-            "sequence",
-        ];
-    }
+impl<'a, W: io::Write> FieldVisitor for CsvRow<'a, W> {
+    type Error = csv::Error;
 
-    impl CsvSerialize for Mbp1Msg {
-        const HEADERS: &'static [&'static str] = &[
-            "rtype",
-            "publisher_id",
-            "product_id",
-            "ts_event",
-            "price",
-            "size",
-            "action",
-            "side",
-            "flags",
-            "depth",
-            "ts_recv",
-            "ts_in_delta",
-            "sequence",
-            "bid_px_00",
-            "ask_px_00",
-            "bid_sz_00",
-            "ask_sz_00",
-            "bid_ct_00",
-            "ask_ct_00",
-        ];
+    fn visit(&mut self, _scope: Scope, _name: &'static str, value: FieldValue) -> csv::Result<()> {
+        if let Some(buffer) = self.selected.next_buffer(self.options) {
+            write_value(buffer, value)?;
+        } else if self.options.selection.is_none() {
+            self.buffer.clear();
+            write_value(&mut self.buffer, value)?;
+            self.csv_writer.write_field(&self.buffer)?;
+        }
+        Ok(())
     }
+}
 
-    impl CsvSerialize for Mbp10Msg {
-        const HEADERS: &'static [&'static str] = &[
-            "rtype",
-            "publisher_id",
-            "product_id",
-            "ts_event",
-            "price",
-            "size",
-            "action",
-            "side",
-            "flags",
-            "depth",
-            "ts_recv",
-            "ts_in_delta",
-            "sequence",
-            "bid_px_00",
-            "ask_px_00",
-            "bid_sz_00",
-            "ask_sz_00",
-            "bid_ct_00",
-            "ask_ct_00",
-            "bid_px_01",
-            "ask_px_01",
-            "bid_sz_01",
-            "ask_sz_01",
-            "bid_ct_01",
-            "ask_ct_01",
-            "bid_px_02",
-            "ask_px_02",
-            "bid_sz_02",
-            "ask_sz_02",
-            "bid_ct_02",
-            "ask_ct_02",
-            "bid_px_03",
-            "ask_px_03",
-            "bid_sz_03",
-            "ask_sz_03",
-            "bid_ct_03",
-            "ask_ct_03",
-            "bid_px_04",
-            "ask_px_04",
-            "bid_sz_04",
-            "ask_sz_04",
-            "bid_ct_04",
-            "ask_ct_04",
-            "bid_px_05",
-            "ask_px_05",
-            "bid_sz_05",
-            "ask_sz_05",
-            "bid_ct_05",
-            "ask_ct_05",
-            "bid_px_06",
-            "ask_px_06",
-            "bid_sz_06",
-            "ask_sz_06",
-            "bid_ct_06",
-            "ask_ct_06",
-            "bid_px_07",
-            "ask_px_07",
-            "bid_sz_07",
-            "ask_sz_07",
-            "bid_ct_07",
-            "ask_ct_07",
-            "bid_px_08",
-            "ask_px_08",
-            "bid_sz_08",
-            "ask_sz_08",
-            "bid_ct_08",
-            "ask_ct_08",
-            "bid_px_09",
-            "ask_px_09",
-            "bid_sz_09",
-            "ask_sz_09",
-            "bid_ct_09",
-            "ask_ct_09",
-        ];
-
-        fn serialize_to<W: io::Write>(&self, csv_writer: &mut Writer<W>) -> csv::Result<()> {
-            csv_writer.write_field(self.hd.rtype.to_string())?;
-            csv_writer.write_field(self.hd.publisher_id.to_string())?;
-            csv_writer.write_field(self.hd.product_id.to_string())?;
-            csv_writer.write_field(self.hd.ts_event.to_string())?;
-            csv_writer.write_field(self.price.to_string())?;
-            csv_writer.write_field(self.size.to_string())?;
-            csv_writer.write_field(self.action.to_string())?;
-            csv_writer.write_field(self.side.to_string())?;
-            csv_writer.write_field(self.flags.to_string())?;
-            csv_writer.write_field(self.depth.to_string())?;
-            csv_writer.write_field(self.ts_recv.to_string())?;
-            csv_writer.write_field(self.ts_in_delta.to_string())?;
-            csv_writer.write_field(self.sequence.to_string())?;
-            for level in self.booklevel.iter() {
-                csv_writer.write_field(level.bid_px.to_string())?;
-                csv_writer.write_field(level.ask_px.to_string())?;
-                csv_writer.write_field(level.bid_sz.to_string())?;
-                csv_writer.write_field(level.ask_sz.to_string())?;
-                csv_writer.write_field(level.bid_ct.to_string())?;
-                csv_writer.write_field(level.ask_ct.to_string())?;
-            }
-            // end of line
-            csv_writer.write_record(None::<&[u8]>)?;
+fn write_value(buffer: &mut Vec<u8>, value: FieldValue) -> io::Result<()> {
+    match value {
+        FieldValue::I8(v) => write!(buffer, "{v}"),
+        FieldValue::U8(v) => write!(buffer, "{v}"),
+        FieldValue::I16(v) => write!(buffer, "{v}"),
+        FieldValue::U16(v) => write!(buffer, "{v}"),
+        FieldValue::I32(v) => write!(buffer, "{v}"),
+        FieldValue::U32(v) => write!(buffer, "{v}"),
+        FieldValue::I64(v) | FieldValue::Price(v) => write!(buffer, "{v}"),
+        FieldValue::U64(v) | FieldValue::Timestamp(v) => write!(buffer, "{v}"),
+        FieldValue::Char(v) => write!(buffer, "{v}"),
+        FieldValue::CStr(_) | FieldValue::Str(_) => {
+            // `as_str` is always `Some` for string values
+            buffer.extend_from_slice(value.as_str().unwrap_or_default().as_bytes());
             Ok(())
         }
-    }
-
-    impl CsvSerialize for TradeMsg {
-        const HEADERS: &'static [&'static str] = &[
-            "rtype",
-            "publisher_id",
-            "product_id",
-            "ts_event",
-            "price",
-            "size",
-            "action",
-            "side",
-            "flags",
-            "depth",
-            "ts_recv",
-            "ts_in_delta",
-            "sequence",
-        ];
-    }
-
-    impl CsvSerialize for OhlcvMsg {
-        const HEADERS: &'static [&'static str] = &[
-            "rtype",
-            "publisher_id",
-            "product_id",
-            "ts_event",
-            "open",
-            "high",
-            "low",
-            "close",
-            "volume",
-        ];
-    }
-
-    impl CsvSerialize for StatusMsg {
-        const HEADERS: &'static [&'static str] = &[
-            "rtype",
-            "publisher_id",
-            "product_id",
-            "ts_event",
-            "ts_recv",
-            "group",
-            "trading_status",
-            "halt_reason",
-            "trading_event",
-        ];
-    }
-
-    impl CsvSerialize for SymDefMsg {
-        const HEADERS: &'static [&'static str] = &[
-            "rtype",
-            "publisher_id",
-            "product_id",
-            "ts_event",
-            "ts_recv",
-            "min_price_increment",
-            "display_factor",
-            "expiration",
-            "activation",
-            "high_limit_price",
-            "low_limit_price",
-            "max_price_variation",
-            "trading_reference_price",
-            "unit_of_measure_qty",
-            "min_price_increment_amount",
-            "price_ratio",
-            "inst_attrib_value",
-            "underlying_id",
-            "cleared_volume",
-            "market_depth_implied",
-            "market_depth",
-            "market_segment_id",
-            "max_trade_vol",
-            "min_lot_size",
-            "min_lot_size_block",
-            "min_lot_size_round_lot",
-            "min_trade_vol",
-            "open_interest_qty",
-            "contract_multiplier",
-            "decay_quantity",
-            "original_contract_size",
-            "related_security_id",
-            "trading_reference_date",
-            "appl_id",
-            "maturity_month_year",
-            "decay_start_date",
-            "chan",
-            "currency",
-            "settl_currency",
-            "secsubtype",
-            "symbol",
-            "group",
-            "exchange",
-            "asset",
-            "cfi",
-            "security_type",
-            "unit_of_measure",
-            "underlying",
-            "related",
-            "match_algorithm",
-            "md_security_trading_status",
-            "main_fraction",
-            "price_display_format",
-            "settl_price_type",
-            "sub_fraction",
-            "underlying_product",
-            "security_update_action",
-            "maturity_month_month",
-            "maturity_month_day",
-            "maturity_month_week",
-            "user_defined_instrument",
-            "contract_multiplier_unit",
-            "flow_schedule_type",
-            "tick_rule",
-        ];
+        FieldValue::Null => Ok(()),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::write::test_data::{VecStream, BID_ASK, RECORD_HEADER};
+    use crate::{
+        fields::UNDEF_PRICE,
+        write::test_data::{VecStream, BID_ASK, RECORD_HEADER},
+        WriterOptions,
+    };
     use databento_defs::record::{
         Mbp10Msg, Mbp1Msg, OhlcvMsg, SecurityUpdateAction, StatusMsg, SymDefMsg, TickMsg, TradeMsg,
     };
@@ -363,7 +147,7 @@ mod tests {
         }];
         let mut buffer = Vec::new();
         let writer = BufWriter::new(&mut buffer);
-        write_csv(writer, VecStream::new(data)).unwrap();
+        write_csv(writer, VecStream::new(data), &TextOptions::default()).unwrap();
         let line = extract_2nd_line(buffer);
         assert_eq!(
             line,
@@ -388,7 +172,7 @@ mod tests {
         }];
         let mut buffer = Vec::new();
         let writer = BufWriter::new(&mut buffer);
-        write_csv(writer, VecStream::new(data)).unwrap();
+        write_csv(writer, VecStream::new(data), &TextOptions::default()).unwrap();
         let line = extract_2nd_line(buffer);
         assert_eq!(
             line,
@@ -415,7 +199,7 @@ mod tests {
         }];
         let mut buffer = Vec::new();
         let writer = BufWriter::new(&mut buffer);
-        write_csv(writer, VecStream::new(data)).unwrap();
+        write_csv(writer, VecStream::new(data), &TextOptions::default()).unwrap();
         let line = extract_2nd_line(buffer);
         assert_eq!(
             line,
@@ -423,6 +207,95 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_mbp10_write_csv_levels() {
+        let data = vec![Mbp10Msg {
+            hd: RECORD_HEADER,
+            price: 5500,
+            size: 3,
+            action: 'B' as i8,
+            side: 67,
+            flags: -128,
+            depth: 9,
+            ts_recv: 1658441891000000000,
+            ts_in_delta: 22_000,
+            sequence: 1_002_375,
+            booklevel: [BID_ASK; 10],
+        }];
+        let options = TextOptions {
+            levels: Some(2),
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        let writer = BufWriter::new(&mut buffer);
+        write_csv(writer, VecStream::new(data), &options).unwrap();
+        let output = String::from_utf8(buffer).expect("valid UTF-8");
+        let mut lines = output.lines();
+        assert!(lines.next().unwrap().ends_with(",sequence,bid_px_00,ask_px_00,bid_sz_00,ask_sz_00,bid_ct_00,ask_ct_00,bid_px_01,ask_px_01,bid_sz_01,ask_sz_01,bid_ct_01,ask_ct_01"));
+        assert_eq!(
+            lines.next().unwrap(),
+            format!("{HEADER_CSV},5500,3,66,67,-128,9,1658441891000000000,22000,1002375,{BID_ASK_CSV},{BID_ASK_CSV}")
+        );
+    }
+
+    #[test]
+    fn test_write_csv_selected_columns() {
+        let data = vec![Mbp1Msg {
+            hd: RECORD_HEADER,
+            price: 5500,
+            size: 3,
+            action: 'B' as i8,
+            side: 67,
+            flags: -128,
+            depth: 9,
+            ts_recv: 1658441891000000000,
+            ts_in_delta: 22_000,
+            sequence: 1_002_375,
+            booklevel: [BID_ASK; 1],
+        }];
+        let mut options = TextOptions::default();
+        let columns = ["price", "ts_event", "bid_px_00"].map(str::to_owned);
+        options.selection = Some(options.select::<Mbp1Msg>(&columns).unwrap());
+        let mut buffer = Vec::new();
+        let writer = BufWriter::new(&mut buffer);
+        write_csv(writer, VecStream::new(data), &options).unwrap();
+        let output = String::from_utf8(buffer).expect("valid UTF-8");
+        assert_eq!(
+            output,
+            "price,ts_event,bid_px_00\n5500,1658441851000000000,372000000000000\n"
+        );
+    }
+
+    #[test]
+    fn test_write_csv_without_header() {
+        let data = vec![OhlcvMsg {
+            hd: RECORD_HEADER,
+            open: 5000,
+            high: 8000,
+            low: 3000,
+            close: 6000,
+            volume: 55_000,
+        }];
+        let mut options = TextOptions {
+            should_omit_csv_header: true,
+            ..Default::default()
+        };
+        let columns = ["ts_event", "close"].map(str::to_owned);
+        options.selection = Some(options.select::<OhlcvMsg>(&columns).unwrap());
+        let mut buffer = Vec::new();
+        write_csv(&mut buffer, VecStream::new(data), &options).unwrap();
+        let output = String::from_utf8(buffer).expect("valid UTF-8");
+        assert_eq!(output, "1658441851000000000,6000\n");
+    }
+
+    #[test]
+    fn test_select_unknown_column() {
+        let options = TextOptions::default();
+        let columns = ["price", "bid_px_00"].map(str::to_owned);
+        let err = options.select::<TradeMsg>(&columns).unwrap_err();
+        assert!(err.to_string().contains("Unknown column 'bid_px_00'"));
+    }
+
     #[test]
     fn test_trade_write_csv() {
         let data = vec![TradeMsg {
@@ -440,7 +313,7 @@ mod tests {
         }];
         let mut buffer = Vec::new();
         let writer = BufWriter::new(&mut buffer);
-        write_csv(writer, VecStream::new(data)).unwrap();
+        write_csv(writer, VecStream::new(data), &TextOptions::default()).unwrap();
         let line = extract_2nd_line(buffer);
         assert_eq!(
             line,
@@ -448,6 +321,113 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_write_csv_chars() {
+        let data = vec![TradeMsg {
+            hd: RECORD_HEADER,
+            price: 5500,
+            size: 3,
+            action: 'T' as i8,
+            side: 0,
+            flags: -128,
+            depth: 9,
+            ts_recv: 1658441891000000000,
+            ts_in_delta: 22_000,
+            sequence: 1_002_375,
+            booklevel: [],
+        }];
+        let options = TextOptions {
+            should_output_chars: true,
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        write_csv(&mut buffer, VecStream::new(data), &options).unwrap();
+        let line = extract_2nd_line(buffer);
+        assert_eq!(
+            line,
+            format!("{HEADER_CSV},5500,3,T,0,-128,9,1658441891000000000,22000,1002375")
+        );
+    }
+
+    #[test]
+    fn test_write_csv_flush_interval() {
+        struct FlushCounter(usize);
+
+        impl io::Write for FlushCounter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                self.0 += 1;
+                Ok(())
+            }
+        }
+
+        let data = vec![
+            OhlcvMsg {
+                hd: RECORD_HEADER,
+                open: 5000,
+                high: 8000,
+                low: 3000,
+                close: 6000,
+                volume: 55_000,
+            };
+            5
+        ];
+        let options = TextOptions {
+            writer: WriterOptions {
+                flush_interval: Some(2),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut unflushed = FlushCounter(0);
+        write_csv(
+            &mut unflushed,
+            VecStream::new(data.clone()),
+            &TextOptions::default(),
+        )
+        .unwrap();
+        let mut flushed = FlushCounter(0);
+        write_csv(&mut flushed, VecStream::new(data), &options).unwrap();
+        // after records 2 and 4
+        assert_eq!(flushed.0, unflushed.0 + 2);
+    }
+
+    #[test]
+    fn test_write_csv_undef_price() {
+        let data = vec![OhlcvMsg {
+            hd: RECORD_HEADER,
+            open: 5000,
+            high: UNDEF_PRICE,
+            low: 3000,
+            close: 6000,
+            volume: 55_000,
+        }];
+        let mut buffer = Vec::new();
+        write_csv(
+            &mut buffer,
+            VecStream::new(data.clone()),
+            &TextOptions::default(),
+        )
+        .unwrap();
+        let line = extract_2nd_line(buffer);
+        assert_eq!(line, format!("{HEADER_CSV},5000,,3000,6000,55000"));
+
+        let options = TextOptions {
+            should_null_undef_prices: false,
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        write_csv(&mut buffer, VecStream::new(data), &options).unwrap();
+        let line = extract_2nd_line(buffer);
+        assert_eq!(
+            line,
+            format!("{HEADER_CSV},5000,{UNDEF_PRICE},3000,6000,55000")
+        );
+    }
+
     #[test]
     fn test_ohlcv_write_csv() {
         let data = vec![OhlcvMsg {
@@ -460,7 +440,7 @@ mod tests {
         }];
         let mut buffer = Vec::new();
         let writer = BufWriter::new(&mut buffer);
-        write_csv(writer, VecStream::new(data)).unwrap();
+        write_csv(writer, VecStream::new(data), &TextOptions::default()).unwrap();
         let line = extract_2nd_line(buffer);
         assert_eq!(line, format!("{HEADER_CSV},5000,8000,3000,6000,55000"));
     }
@@ -481,7 +461,7 @@ mod tests {
         }];
         let mut buffer = Vec::new();
         let writer = BufWriter::new(&mut buffer);
-        write_csv(writer, VecStream::new(data)).unwrap();
+        write_csv(writer, VecStream::new(data), &TextOptions::default()).unwrap();
         let line = extract_2nd_line(buffer);
         assert_eq!(
             line,
@@ -557,7 +537,7 @@ mod tests {
         }];
         let mut buffer = Vec::new();
         let writer = BufWriter::new(&mut buffer);
-        write_csv(writer, VecStream::new(data)).unwrap();
+        write_csv(writer, VecStream::new(data), &TextOptions::default()).unwrap();
         let line = extract_2nd_line(buffer);
         assert_eq!(line, format!("{HEADER_CSV},1658441891000000000,100,1000,1698450000000000000,1697350000000000000,1000000,-1000000,0,500000,5,5,10,10,256785,0,0,13,0,10000,1,1000,100,1,0,0,0,0,0,0,0,0,0,4,,USD,,,,,,,,,,,1,2,4,8,9,23,10,Invalid,8,9,11,1,0,5,0"));
     }
@@ -1,26 +1,48 @@
 use std::{
-    io::{self, SeekFrom, Write},
+    collections::BTreeMap,
+    io::{self, Seek, SeekFrom, Write},
     mem,
     ops::Range,
     slice,
 };
 
 use anyhow::{anyhow, Context};
-use databento_defs::record::ConstTypeId;
+use databento_defs::{enums::Compression, record::ConstTypeId};
 use streaming_iterator::StreamingIterator;
 use zstd::{stream::AutoFinishEncoder, Encoder};
 
-use crate::{read::SymbolMapping, Metadata};
+use crate::{
+    checksum::{ChecksumWriter, CHECKSUM_ALGORITHM, CHECKSUM_KEY},
+    read::{FromLittleEndianSlice, SymbolMapping},
+    Dbz, FrameOffset, Metadata, Record, RecordIndex, SchemaDefinition, WriterOptions,
+};
 
 pub(crate) const SCHEMA_VERSION: u8 = 1;
 
-/// Create a new Zstd encoder with default settings
-fn new_encoder<'a, W: io::Write>(writer: W) -> anyhow::Result<AutoFinishEncoder<'a, W>> {
-    pub(crate) const ZSTD_COMPRESSION_LEVEL: i32 = 0;
+/// Create a new Zstd encoder with the compression settings of `options`
+fn new_encoder<'a, W: io::Write>(
+    writer: W,
+    options: &WriterOptions,
+) -> anyhow::Result<AutoFinishEncoder<'a, W>> {
+    Ok(new_manual_encoder(writer, options)?.auto_finish())
+}
 
-    let mut encoder = Encoder::new(writer, ZSTD_COMPRESSION_LEVEL)?;
+/// Create a new Zstd encoder with the compression settings of `options` that must be
+/// finished explicitly, surfacing any error writing the end of the frame
+pub(crate) fn new_manual_encoder<'a, W: io::Write>(
+    writer: W,
+    options: &WriterOptions,
+) -> anyhow::Result<Encoder<'a, W>> {
+    let (level, n_threads) = options.zstd_settings();
+    let mut encoder = match options.dictionary.as_deref() {
+        Some(dictionary) => Encoder::with_dictionary(writer, level, dictionary)?,
+        None => Encoder::new(writer, level)?,
+    };
     encoder.include_checksum(true)?;
-    Ok(encoder.auto_finish())
+    if n_threads > 0 {
+        encoder.multithread(n_threads)?;
+    }
+    Ok(encoder)
 }
 
 impl Metadata {
@@ -31,6 +53,12 @@ impl Metadata {
     pub(crate) const FIXED_METADATA_LEN: usize = 96;
     pub(crate) const SYMBOL_CSTR_LEN: usize = 22;
 
+    /// Encodes the metadata in the DBZ format as a Zstd skippable frame to `writer`.
+    ///
+    /// # Errors
+    /// This function returns an error if the metadata can't be encoded, like when a
+    /// string is longer than its fixed-length field, or there's an issue writing to
+    /// `writer`.
     pub fn encode(&self, mut writer: impl io::Write + io::Seek) -> anyhow::Result<()> {
         writer.write_all(Self::ZSTD_MAGIC_RANGE.start.to_le_bytes().as_slice())?;
         // write placeholder frame size to filled in at the end
@@ -51,20 +79,7 @@ impl Metadata {
         writer.write_all(&[self.stype_out as u8])?;
         // padding
         writer.write_all(&[0; Self::RESERVED_LEN])?;
-        {
-            // remaining metadata is compressed
-            let mut zstd_encoder = new_encoder(&mut writer)?;
-            // schema_definition_length
-            zstd_encoder.write_all(0u32.to_le_bytes().as_slice())?;
-
-            Self::encode_repeated_symbol_cstr(&mut zstd_encoder, self.symbols.as_slice())
-                .with_context(|| "Failed to encode symbols")?;
-            Self::encode_repeated_symbol_cstr(&mut zstd_encoder, self.partial.as_slice())
-                .with_context(|| "Failed to encode partial")?;
-            Self::encode_repeated_symbol_cstr(&mut zstd_encoder, self.not_found.as_slice())
-                .with_context(|| "Failed to encode not_found")?;
-            Self::encode_symbol_mappings(&mut zstd_encoder, self.mappings.as_slice())?;
-        }
+        self.encode_compressed(&mut writer)?;
 
         let raw_size = writer.stream_position()?;
         // go back and update the size now that we know it
@@ -78,6 +93,46 @@ impl Metadata {
         Ok(())
     }
 
+    /// Returns the exact number of bytes [`Metadata::encode`] and
+    /// [`Metadata::encode_into`] will write for this metadata.
+    ///
+    /// # Errors
+    /// This function returns an error if the metadata can't be encoded, e.g. a symbol
+    /// is too long.
+    pub fn encoded_len(&self) -> anyhow::Result<usize> {
+        let mut counter = ByteCounter(0);
+        self.encode_compressed(&mut counter)?;
+        Ok(8 + Self::FIXED_METADATA_LEN + counter.0)
+    }
+
+    /// Encodes the metadata into the start of `buffer`, returning the number of bytes
+    /// written. Use [`Metadata::encoded_len`] to size `buffer`.
+    ///
+    /// # Errors
+    /// This function returns an error if `buffer` is too small or the metadata can't
+    /// be encoded.
+    pub fn encode_into(&self, buffer: &mut [u8]) -> anyhow::Result<usize> {
+        let capacity = buffer.len();
+        let mut cursor = io::Cursor::new(buffer);
+        self.encode(&mut cursor)
+            .map_err(|e| match e.downcast_ref::<io::Error>() {
+                Some(io_err) if io_err.kind() == io::ErrorKind::WriteZero => anyhow!(
+                    "Buffer of {capacity} bytes is too small to encode metadata, needs {}",
+                    self.encoded_len()
+                        .map_or_else(|_| "more".to_owned(), |len| len.to_string())
+                ),
+                _ => e,
+            })?;
+        Ok(cursor.position() as usize)
+    }
+
+    /// Updates the `start`, `end`, `limit`, and `record_count` of metadata that has
+    /// already been encoded at the start of `writer`, then seeks back to the end of
+    /// `writer`.
+    ///
+    /// # Errors
+    /// This function returns an error if there's an issue seeking in or writing to
+    /// `writer`.
     pub fn update_encoded(
         mut writer: impl io::Write + io::Seek,
         start: u64,
@@ -99,6 +154,89 @@ impl Metadata {
         Ok(())
     }
 
+    /// Replaces the symbology of the metadata encoded at the start of `file`: the
+    /// `symbols`, `partial`, `not_found`, and `mappings`, e.g. after resolving the
+    /// symbology of a recording once it's complete. The other fields are unchanged.
+    ///
+    /// The metadata is rewritten in place if it fits in the existing metadata frame,
+    /// with the rest of the frame filled by a Zstd skippable frame. Otherwise the frame
+    /// grows and the records are moved back to make room, which invalidates any
+    /// [`RecordIndex`] of the file.
+    ///
+    /// # Errors
+    /// This function returns an error if the existing metadata can't be decoded, the
+    /// new symbology can't be encoded, or there's an issue reading, writing, or
+    /// seeking `file`.
+    pub fn update_encoded_symbology(
+        mut file: impl io::Read + io::Write + io::Seek,
+        symbols: &[String],
+        partial: &[String],
+        not_found: &[String],
+        mappings: &[SymbolMapping],
+    ) -> anyhow::Result<()> {
+        file.seek(SeekFrom::Start(0))?;
+        let mut metadata =
+            Self::read(&mut file).with_context(|| "Failed to read metadata to update")?;
+        let frame_len = file.stream_position()?;
+        metadata.symbols = symbols.to_vec();
+        metadata.partial = partial.to_vec();
+        metadata.not_found = not_found.to_vec();
+        metadata.mappings = mappings.to_vec();
+        let mut encoded = io::Cursor::new(Vec::new());
+        metadata.encode(&mut encoded)?;
+        let mut encoded = encoded.into_inner();
+        let len = encoded.len() as u64;
+        let padded_len = if len == frame_len || len + Self::PRELUDE_LEN as u64 <= frame_len {
+            frame_len
+        } else {
+            // an empty skippable frame is the least padding possible
+            let grown_len = if len < frame_len {
+                len + Self::PRELUDE_LEN as u64
+            } else {
+                len
+            };
+            shift_tail(&mut file, frame_len, grown_len - frame_len)
+                .with_context(|| "Failed to move records to grow metadata")?;
+            grown_len
+        };
+        if padded_len > len {
+            let padding_len = (padded_len - len) as usize;
+            encoded.extend_from_slice(Self::ZSTD_MAGIC_RANGE.start.to_le_bytes().as_slice());
+            encoded.extend_from_slice(
+                ((padding_len - Self::PRELUDE_LEN) as u32)
+                    .to_le_bytes()
+                    .as_slice(),
+            );
+            encoded.resize(padded_len as usize, 0);
+            let frame_size = (padded_len as usize - Self::PRELUDE_LEN) as u32;
+            encoded[4..Self::PRELUDE_LEN].copy_from_slice(frame_size.to_le_bytes().as_slice());
+        }
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&encoded)?;
+        file.seek(SeekFrom::End(0))?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Encodes the zstd-compressed, variable-length part of the metadata.
+    fn encode_compressed(&self, writer: impl io::Write) -> anyhow::Result<()> {
+        let mut zstd_encoder = new_manual_encoder(writer, &WriterOptions::default())?;
+        Self::encode_schema_definition(&mut zstd_encoder, self.schema_definition.as_ref())
+            .with_context(|| "Failed to encode schema definition")?;
+
+        Self::encode_repeated_symbol_cstr(&mut zstd_encoder, self.symbols.as_slice())
+            .with_context(|| "Failed to encode symbols")?;
+        Self::encode_repeated_symbol_cstr(&mut zstd_encoder, self.partial.as_slice())
+            .with_context(|| "Failed to encode partial")?;
+        Self::encode_repeated_symbol_cstr(&mut zstd_encoder, self.not_found.as_slice())
+            .with_context(|| "Failed to encode not_found")?;
+        Self::encode_symbol_mappings(&mut zstd_encoder, self.mappings.as_slice())?;
+        Self::encode_annotations(&mut zstd_encoder, &self.annotations)
+            .with_context(|| "Failed to encode annotations")?;
+        zstd_encoder.finish()?;
+        Ok(())
+    }
+
     fn encode_range_and_counts(
         writer: &mut impl io::Write,
         start: u64,
@@ -113,6 +251,33 @@ impl Metadata {
         Ok(())
     }
 
+    /// Encodes `schema_definition` prefixed by its length, which is 0 if there's none.
+    fn encode_schema_definition(
+        writer: &mut impl io::Write,
+        schema_definition: Option<&SchemaDefinition>,
+    ) -> anyhow::Result<()> {
+        let mut buffer = Vec::new();
+        if let Some(definition) = schema_definition {
+            definition.validate()?;
+            buffer.push(definition.rtype);
+            buffer.extend_from_slice(definition.record_size.to_le_bytes().as_slice());
+            let count = u32::try_from(definition.fields.len())
+                .map_err(|_| anyhow!("Too many fields to encode"))?;
+            buffer.extend_from_slice(count.to_le_bytes().as_slice());
+            for field in definition.fields.iter() {
+                Self::encode_len_prefixed_str(&mut buffer, &field.name)?;
+                buffer.extend_from_slice(field.offset.to_le_bytes().as_slice());
+                buffer.extend_from_slice(field.size.to_le_bytes().as_slice());
+                Self::encode_len_prefixed_str(&mut buffer, &field.type_name)?;
+            }
+        }
+        let len = u32::try_from(buffer.len())
+            .map_err(|_| anyhow!("Schema definition is too long to encode"))?;
+        writer.write_all(len.to_le_bytes().as_slice())?;
+        writer.write_all(&buffer)?;
+        Ok(())
+    }
+
     fn encode_repeated_symbol_cstr(
         writer: &mut impl io::Write,
         symbols: &[String],
@@ -159,6 +324,26 @@ impl Metadata {
         Ok(())
     }
 
+    fn encode_annotations(
+        writer: &mut impl io::Write,
+        annotations: &BTreeMap<String, String>,
+    ) -> anyhow::Result<()> {
+        writer.write_all((annotations.len() as u32).to_le_bytes().as_slice())?;
+        for (key, value) in annotations {
+            Self::encode_len_prefixed_str(writer, key)?;
+            Self::encode_len_prefixed_str(writer, value)?;
+        }
+        Ok(())
+    }
+
+    fn encode_len_prefixed_str(writer: &mut impl io::Write, string: &str) -> anyhow::Result<()> {
+        let len = u32::try_from(string.len())
+            .map_err(|_| anyhow!("String of {} bytes is too long to encode", string.len()))?;
+        writer.write_all(len.to_le_bytes().as_slice())?;
+        writer.write_all(string.as_bytes())?;
+        Ok(())
+    }
+
     // Can't specify const generic with impl trait until Rust 1.63, see
     // https://github.com/rust-lang/rust/issues/83701
     fn encode_fixed_len_cstr<W: io::Write, const LEN: usize>(
@@ -192,20 +377,56 @@ impl Metadata {
     }
 }
 
-unsafe fn as_u8_slice<T: Sized>(data: &T) -> &[u8] {
+/// A writer that discards its input, only counting the bytes.
+struct ByteCounter(usize);
+
+impl io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub(crate) unsafe fn as_u8_slice<T: Sized>(data: &T) -> &[u8] {
     slice::from_raw_parts(data as *const T as *const u8, mem::size_of::<T>())
 }
 
 /// Incrementally serializes the records in `iter` in the DBZ format to `writer`.
+///
+/// # Errors
+/// This function returns an error if there's an issue compressing the records or
+/// writing to `writer`.
 pub fn write_dbz_stream<T>(
+    writer: impl io::Write,
+    stream: impl StreamingIterator<Item = T>,
+) -> anyhow::Result<()>
+where
+    T: ConstTypeId + Sized,
+{
+    write_dbz_stream_with_options(writer, stream, &WriterOptions::default())
+}
+
+/// Incrementally serializes the records in `stream` in the DBZ format to `writer`,
+/// flushing the compressed output every [`WriterOptions::flush_interval`] records.
+///
+/// # Errors
+/// This function returns an error if there's an issue compressing the records or
+/// writing to `writer`.
+pub fn write_dbz_stream_with_options<T>(
     writer: impl io::Write,
     mut stream: impl StreamingIterator<Item = T>,
+    options: &WriterOptions,
 ) -> anyhow::Result<()>
 where
     T: ConstTypeId + Sized,
 {
-    let mut encoder = new_encoder(writer)
+    let mut encoder = new_encoder(writer, options)
         .with_context(|| "Failed to create Zstd encoder for writing DBZ".to_owned())?;
+    let mut record_count = 0;
     while let Some(record) = stream.next() {
         let bytes = unsafe {
             // Safety: all records, types implementing `ConstTypeId` are POD
@@ -217,12 +438,23 @@ where
             r => r,
         }
         .with_context(|| "Failed to serialize {record:#?}")?;
+        record_count += 1;
+        if options.should_flush(record_count) {
+            // ends the current zstd block so the records so far can be decoded. zstd
+            // doesn't flush `writer` itself
+            encoder.flush()?;
+            encoder.get_mut().flush()?;
+        }
     }
     encoder.flush()?;
     Ok(())
 }
 
 /// Incrementally serializes the records in `iter` in the DBZ format to `writer`.
+///
+/// # Errors
+/// This function returns an error if there's an issue compressing the records or
+/// writing to `writer`.
 pub fn write_dbz<'a, T>(
     writer: impl io::Write,
     iter: impl Iterator<Item = &'a T>,
@@ -230,7 +462,24 @@ pub fn write_dbz<'a, T>(
 where
     T: 'a + ConstTypeId + Sized,
 {
-    let mut encoder = new_encoder(writer)
+    write_dbz_with_options(writer, iter, &WriterOptions::default())
+}
+
+/// Incrementally serializes the records in `iter` in the DBZ format to `writer`,
+/// compressing them with the level and number of threads of `options`.
+///
+/// # Errors
+/// This function returns an error if there's an issue compressing the records or
+/// writing to `writer`.
+pub fn write_dbz_with_options<'a, T>(
+    writer: impl io::Write,
+    iter: impl Iterator<Item = &'a T>,
+    options: &WriterOptions,
+) -> anyhow::Result<()>
+where
+    T: 'a + ConstTypeId + Sized,
+{
+    let mut encoder = new_encoder(writer, options)
         .with_context(|| "Failed to create Zstd encoder for writing DBZ".to_owned())?;
     for record in iter {
         let bytes = unsafe {
@@ -248,6 +497,281 @@ where
     Ok(())
 }
 
+/// Incrementally encodes records in the DBZ format, for writing records as they're
+/// generated rather than from an iterator. The `record_count`, `start`, and `end`
+/// of the metadata are filled in by [`DbzWriter::finish`].
+pub struct DbzWriter<W: io::Write + io::Seek> {
+    /// The encoder of the current Zstd frame. Only `None` if ending a frame failed.
+    encoder: Option<Encoder<'static, ChecksumWriter<W>>>,
+    options: WriterOptions,
+    limit: u64,
+    record_count: u64,
+    /// The lowest and highest `ts_event` of the records written so far.
+    ts_event_bounds: Option<(u64, u64)>,
+    /// The `start` and `end` of the original metadata, used when no records are
+    /// written.
+    range: (u64, u64),
+    /// The positions of the Zstd frames started so far.
+    frames: Vec<FrameOffset>,
+    /// The number of bytes of records written so far.
+    decompressed_len: u64,
+}
+
+impl<W: io::Write + io::Seek> DbzWriter<W> {
+    /// The offset of `ts_event` in the record header.
+    pub(crate) const TS_EVENT_OFFSET: usize = 8;
+
+    /// Creates a new writer, encoding `metadata` to `writer`. The records are always
+    /// Zstd-compressed, regardless of `metadata.compression`.
+    ///
+    /// # Errors
+    /// This function returns an error if the metadata can't be encoded or there's an
+    /// issue writing to `writer`.
+    pub fn new(writer: W, metadata: &Metadata) -> anyhow::Result<Self> {
+        Self::with_options(writer, metadata, &WriterOptions::default())
+    }
+
+    /// Creates a new writer like [`DbzWriter::new`] that compresses the records with
+    /// the level and number of threads of `options`, flushes every
+    /// [`WriterOptions::flush_interval`] records, and starts a new Zstd frame every
+    /// [`WriterOptions::frame_record_count`] records.
+    ///
+    /// # Errors
+    /// This function returns an error if the metadata can't be encoded or there's an
+    /// issue writing to `writer`.
+    pub fn with_options(
+        mut writer: W,
+        metadata: &Metadata,
+        options: &WriterOptions,
+    ) -> anyhow::Result<Self> {
+        let mut metadata = metadata.clone();
+        metadata.compression = Compression::ZStd;
+        if options.should_write_checksum {
+            metadata
+                .annotations
+                .insert(CHECKSUM_KEY.to_owned(), CHECKSUM_ALGORITHM.to_owned());
+        }
+        metadata.encode(&mut writer)?;
+        let records_start = writer.stream_position()?;
+        let writer = ChecksumWriter::new(writer, options.should_write_checksum);
+        let encoder = new_manual_encoder(writer, options)
+            .with_context(|| "Failed to create Zstd encoder for writing DBZ".to_owned())?;
+        Ok(Self {
+            encoder: Some(encoder),
+            options: options.clone(),
+            limit: metadata.limit,
+            record_count: 0,
+            ts_event_bounds: None,
+            range: (metadata.start, metadata.end),
+            frames: vec![FrameOffset {
+                compressed: records_start,
+                decompressed: 0,
+                ts_event: u64::MAX,
+            }],
+            decompressed_len: 0,
+        })
+    }
+
+    /// Returns the number of records written so far.
+    pub fn record_count(&self) -> u64 {
+        self.record_count
+    }
+
+    fn encoder(&mut self) -> anyhow::Result<&mut Encoder<'static, ChecksumWriter<W>>> {
+        self.encoder
+            .as_mut()
+            .ok_or_else(|| anyhow!("Can't write after failing to end a Zstd frame"))
+    }
+
+    /// Encodes `record`.
+    ///
+    /// # Errors
+    /// This function returns an error if there's an issue writing to the underlying
+    /// writer.
+    pub fn write_record<T: ConstTypeId + Sized>(&mut self, record: &T) -> anyhow::Result<()> {
+        let bytes = unsafe {
+            // Safety: all records, types implementing `ConstTypeId` are POD
+            as_u8_slice(record)
+        };
+        self.write_record_bytes(bytes)
+    }
+
+    /// Encodes the record encoded in `bytes`.
+    pub(crate) fn write_record_bytes(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        // the frame is ended before the next record so there's no empty last frame
+        let frame = *self.frames.last().expect("at least one frame");
+        if self.options.should_end_frame(self.record_count as usize)
+            && frame.decompressed < self.decompressed_len
+        {
+            self.end_frame()?;
+        }
+        self.encoder()?
+            .write_all(bytes)
+            .with_context(|| "Failed to serialize record".to_owned())?;
+        let ts_event = u64::from_le_slice(&bytes[Self::TS_EVENT_OFFSET..]);
+        self.ts_event_bounds = Some(match self.ts_event_bounds {
+            Some((first, last)) => (first.min(ts_event), last.max(ts_event)),
+            None => (ts_event, ts_event),
+        });
+        if let Some(frame) = self
+            .frames
+            .last_mut()
+            .filter(|frame| frame.decompressed == self.decompressed_len)
+        {
+            frame.ts_event = ts_event;
+        }
+        self.decompressed_len += bytes.len() as u64;
+        self.record_count += 1;
+        if self.options.should_flush(self.record_count as usize) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Finishes the current Zstd frame and starts a new one.
+    fn end_frame(&mut self) -> anyhow::Result<()> {
+        let encoder = self
+            .encoder
+            .take()
+            .ok_or_else(|| anyhow!("Can't write after failing to end a Zstd frame"))?;
+        let mut writer = encoder
+            .finish()
+            .with_context(|| format!("Failed to end Zstd frame {}", self.frames.len() - 1))?;
+        let compressed = writer.stream_position()?;
+        self.encoder = Some(
+            new_manual_encoder(writer, &self.options)
+                .with_context(|| "Failed to create Zstd encoder for writing DBZ".to_owned())?,
+        );
+        self.frames.push(FrameOffset {
+            compressed,
+            decompressed: self.decompressed_len,
+            ts_event: u64::MAX,
+        });
+        Ok(())
+    }
+
+    /// Ends the current Zstd block and flushes the underlying writer so the records
+    /// written so far can be decoded.
+    ///
+    /// # Errors
+    /// This function returns an error if there's an issue writing to the underlying
+    /// writer.
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        let encoder = self.encoder()?;
+        encoder.flush()?;
+        encoder.get_mut().flush()?;
+        Ok(())
+    }
+
+    /// Finishes the Zstd frame, writes the checksum footer if
+    /// [`WriterOptions::should_write_checksum`] is set, and updates the metadata with
+    /// the number of records written and the half-open range of their `ts_event`s. If
+    /// no records were written, `start` and `end` are unchanged. Returns the
+    /// underlying writer.
+    ///
+    /// # Errors
+    /// This function returns an error if there's an issue writing to or seeking the
+    /// underlying writer.
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        let encoder = self
+            .encoder
+            .take()
+            .ok_or_else(|| anyhow!("Can't finish after failing to end a Zstd frame"))?;
+        let mut writer = encoder.finish()?.finish()?;
+        let (start, end) = self
+            .ts_event_bounds
+            .map_or(self.range, |(first, last)| (first, last.saturating_add(1)));
+        Metadata::update_encoded(&mut writer, start, end, self.limit, self.record_count)?;
+        writer.flush()?;
+        Ok(writer)
+    }
+
+    /// Finishes writing like [`DbzWriter::finish`] and returns the [`RecordIndex`]
+    /// of the written file along with the underlying writer, so it can be saved
+    /// without decoding the file again. The index is most useful with
+    /// [`WriterOptions::frame_record_count`] set.
+    ///
+    /// # Errors
+    /// This function returns an error if there's an issue writing to or seeking the
+    /// underlying writer.
+    pub fn finish_with_index(mut self) -> anyhow::Result<(W, RecordIndex)> {
+        let frames = mem::take(&mut self.frames);
+        let record_count = self.record_count;
+        let mut writer = self.finish()?;
+        let dbz_len = writer.stream_position()?;
+        Ok((writer, RecordIndex::new(dbz_len, frames, record_count)))
+    }
+}
+
+/// Moves the bytes of `file` from `start` to its end back by `shift` bytes.
+fn shift_tail(
+    file: &mut (impl io::Read + io::Write + io::Seek),
+    start: u64,
+    shift: u64,
+) -> io::Result<()> {
+    const CHUNK_LEN: u64 = 1 << 16;
+    let mut buffer = vec![0; CHUNK_LEN as usize];
+    let mut end = file.seek(SeekFrom::End(0))?;
+    // copy from the end so no bytes are overwritten before they're moved
+    while end > start {
+        let chunk_start = end.saturating_sub(CHUNK_LEN).max(start);
+        let chunk = &mut buffer[..(end - chunk_start) as usize];
+        file.seek(SeekFrom::Start(chunk_start))?;
+        file.read_exact(chunk)?;
+        file.seek(SeekFrom::Start(chunk_start + shift))?;
+        file.write_all(chunk)?;
+        end = chunk_start;
+    }
+    Ok(())
+}
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Re-encodes the records in the DBZ format to `writer` after the metadata,
+    /// skipping the records excluded by [`Dbz::filter_range`] and
+    /// [`Dbz::filter_product_ids`]. The metadata is unchanged apart from the
+    /// `record_count`. Returns the number of records written.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`](databento_defs::enums::Schema::Statistics) or a record
+    /// can't be decoded. It will also return an error if there's an issue
+    /// writing to `writer`.
+    pub fn write_dbz_to(self, writer: impl io::Write + io::Seek) -> anyhow::Result<u64> {
+        let mut metadata = self.metadata().clone();
+        // the records are always compressed
+        metadata.compression = Compression::ZStd;
+        let records = self.into_record_iter()?;
+        write_records(writer, &metadata, records)
+    }
+}
+
+/// Writes `metadata` and `records` in the DBZ format to `writer`, then updates the
+/// `record_count` of the encoded metadata to the number of records written, which is
+/// returned.
+pub(crate) fn write_records(
+    mut writer: impl io::Write + io::Seek,
+    metadata: &Metadata,
+    records: impl IntoIterator<Item = anyhow::Result<Record>>,
+) -> anyhow::Result<u64> {
+    metadata.encode(&mut writer)?;
+    let mut encoder = new_manual_encoder(&mut writer, &WriterOptions::default())?;
+    let mut record_count = 0;
+    for record in records {
+        encoder.write_all(record?.as_bytes())?;
+        record_count += 1;
+    }
+    encoder.finish()?;
+    Metadata::update_encoded(
+        &mut writer,
+        metadata.start,
+        metadata.end,
+        metadata.limit,
+        record_count,
+    )?;
+    writer.flush()?;
+    Ok(record_count)
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -259,17 +783,164 @@ mod tests {
 
     use databento_defs::{
         enums::{Compression, SType, Schema},
-        record::{Mbp1Msg, OhlcvMsg, RecordHeader, StatusMsg, TickMsg, TradeMsg},
+        record::{Mbp10Msg, Mbp1Msg, OhlcvMsg, RecordHeader, StatusMsg, TickMsg, TradeMsg},
     };
 
     use crate::{
         read::{FromLittleEndianSlice, MappingInterval},
         write::test_data::{VecStream, BID_ASK, RECORD_HEADER},
-        DbzStreamIter,
+        Dbz, DbzStreamIter,
     };
 
     use super::*;
 
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    #[test]
+    fn test_write_dbz_to_filtered() {
+        let mut buffer = io::Cursor::new(Vec::new());
+        let record_count = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz"))
+            .unwrap()
+            .filter_product_ids([5482])
+            .write_dbz_to(&mut buffer)
+            .unwrap();
+        assert_eq!(record_count, 2);
+        buffer.set_position(0);
+        let expected = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        let target = Dbz::new(buffer).unwrap();
+        assert_eq!(target.metadata(), expected.metadata());
+        let records = target
+            .try_into_fallible_iter::<TickMsg>()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        let expected = expected
+            .try_into_fallible_iter::<TickMsg>()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(records, expected);
+
+        let mut buffer = io::Cursor::new(Vec::new());
+        let record_count = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz"))
+            .unwrap()
+            .filter_product_ids([1])
+            .write_dbz_to(&mut buffer)
+            .unwrap();
+        assert_eq!(record_count, 0);
+        buffer.set_position(0);
+        assert_eq!(Dbz::new(buffer).unwrap().metadata().record_count, 0);
+    }
+
+    #[test]
+    fn test_dbz_writer() {
+        let dbz = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let mut metadata = dbz.metadata().clone();
+        metadata.record_count = 0;
+        let records = dbz
+            .try_into_fallible_iter::<TradeMsg>()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        let mut target = DbzWriter::new(io::Cursor::new(Vec::new()), &metadata).unwrap();
+        for record in records.iter().rev() {
+            target.write_record(record).unwrap();
+            target.flush().unwrap();
+        }
+        assert_eq!(target.record_count(), 2);
+        let mut buffer = target.finish().unwrap();
+        buffer.set_position(0);
+        let res = Dbz::new(buffer).unwrap();
+        assert_eq!(res.metadata().record_count, 2);
+        assert_eq!(res.metadata().start, records[0].hd.ts_event);
+        assert_eq!(res.metadata().end, records[1].hd.ts_event + 1);
+        let mut res_records = res
+            .try_into_fallible_iter::<TradeMsg>()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        res_records.reverse();
+        assert_eq!(res_records, records);
+    }
+
+    #[test]
+    fn test_dbz_writer_with_options() {
+        let dbz = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbp-10.dbz")).unwrap();
+        let metadata = dbz.metadata().clone();
+        let records = dbz
+            .try_into_fallible_iter::<Mbp10Msg>()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        let options = WriterOptions {
+            compression_level: 19,
+            n_threads: 2,
+            ..Default::default()
+        };
+        let mut target =
+            DbzWriter::with_options(io::Cursor::new(Vec::new()), &metadata, &options).unwrap();
+        for record in records.iter() {
+            target.write_record(record).unwrap();
+        }
+        let mut buffer = target.finish().unwrap();
+        buffer.set_position(0);
+        let res_records = Dbz::new(buffer)
+            .unwrap()
+            .try_into_fallible_iter::<Mbp10Msg>()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(res_records, records);
+    }
+
+    #[test]
+    fn test_dbz_writer_deterministic() {
+        let dbz = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbp-10.dbz")).unwrap();
+        let metadata = dbz.metadata().clone();
+        let records = dbz
+            .try_into_fallible_iter::<Mbp10Msg>()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        let write = |options: &WriterOptions| {
+            let mut target =
+                DbzWriter::with_options(io::Cursor::new(Vec::new()), &metadata, options).unwrap();
+            for record in records.iter() {
+                target.write_record(record).unwrap();
+            }
+            target.finish().unwrap().into_inner()
+        };
+        let expected = write(&WriterOptions {
+            compression_level: WriterOptions::DETERMINISTIC_COMPRESSION_LEVEL,
+            ..Default::default()
+        });
+        for n_threads in [0, 2] {
+            let options = WriterOptions {
+                n_threads,
+                is_deterministic: true,
+                ..Default::default()
+            };
+            assert_eq!(write(&options), expected);
+        }
+    }
+
+    #[test]
+    fn test_dbz_writer_no_records() {
+        let metadata = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz"))
+            .unwrap()
+            .metadata()
+            .clone();
+        let mut buffer = DbzWriter::new(io::Cursor::new(Vec::new()), &metadata)
+            .unwrap()
+            .finish()
+            .unwrap();
+        buffer.set_position(0);
+        let res = Dbz::new(buffer).unwrap();
+        assert_eq!(res.metadata().record_count, 0);
+        assert_eq!(res.metadata().start, metadata.start);
+        assert_eq!(res.metadata().end, metadata.end);
+    }
+
     #[test]
     fn test_encode_decode_metadata_identity() {
         let mut extra = serde_json::Map::default();
@@ -288,6 +959,7 @@ mod tests {
             limit: 0,
             compression: Compression::ZStd,
             record_count: 14,
+            schema_definition: None,
             symbols: vec!["ES".to_owned(), "NG".to_owned()],
             partial: vec!["ESM2".to_owned()],
             not_found: vec!["QQQQQ".to_owned()],
@@ -330,6 +1002,13 @@ mod tests {
                     ],
                 },
             ],
+            annotations: BTreeMap::from([
+                ("source".to_owned(), "recorder-7".to_owned()),
+                (
+                    "notes".to_owned(),
+                    "Contains a gap from 14:02 to 14:05 \u{2014} see log".to_owned(),
+                ),
+            ]),
         };
         let mut buffer = Vec::new();
         let cursor = io::Cursor::new(&mut buffer);
@@ -340,6 +1019,81 @@ mod tests {
         assert_eq!(res, metadata);
     }
 
+    #[test]
+    fn test_encode_decode_schema_definition() {
+        let mut metadata = Metadata::placeholder(Schema::Mbp10);
+        metadata.schema_definition = Some(SchemaDefinition::for_schema(Schema::Mbp10).unwrap());
+        let mut buffer = Vec::new();
+        metadata.encode(io::Cursor::new(&mut buffer)).unwrap();
+        let res = Metadata::read(&mut &buffer[..]).unwrap();
+        assert_eq!(res, metadata);
+        // fields must lie within the record
+        let definition = metadata.schema_definition.as_mut().unwrap();
+        definition.fields[0].offset = definition.record_size;
+        assert!(metadata.encode(io::Cursor::new(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn test_update_encoded_symbology() {
+        let path = format!("{DBZ_PATH}/test_data.mbo.dbz");
+        let expected_records = Dbz::from_file(&path)
+            .unwrap()
+            .try_into_iter::<TickMsg>()
+            .unwrap()
+            .cloned()
+            .collect::<Vec<_>>();
+        let bytes = std::fs::read(&path).unwrap();
+        let mapping = |native: &str| SymbolMapping {
+            native: native.to_owned(),
+            intervals: vec![MappingInterval {
+                start_date: time::Date::from_calendar_date(2020, time::Month::December, 28)
+                    .unwrap(),
+                end_date: time::Date::from_calendar_date(2020, time::Month::December, 29).unwrap(),
+                symbol: "5482".to_owned(),
+            }],
+        };
+        // shrinking, growing, then shrinking into the grown frame
+        let cases = [
+            (vec![], vec![]),
+            (
+                (0..40).map(|i| format!("ES{i}")).collect(),
+                (0..40).map(|i| mapping(&format!("ES{i}"))).collect(),
+            ),
+            (vec!["ESH1".to_owned()], vec![mapping("ESH1")]),
+        ];
+        let mut file = io::Cursor::new(bytes);
+        for (symbols, mappings) in cases {
+            Metadata::update_encoded_symbology(&mut file, &symbols, &[], &[], &mappings).unwrap();
+            file.set_position(0);
+            let dbz = Dbz::new(&mut file).unwrap();
+            assert_eq!(dbz.metadata().symbols, symbols);
+            assert_eq!(dbz.metadata().mappings, mappings);
+            let records = dbz
+                .try_into_iter::<TickMsg>()
+                .unwrap()
+                .cloned()
+                .collect::<Vec<_>>();
+            assert_eq!(records, expected_records);
+        }
+    }
+
+    #[test]
+    fn test_encode_into() {
+        let metadata = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbp-10.dbz"))
+            .unwrap()
+            .metadata()
+            .clone();
+        let mut expected = Vec::new();
+        metadata.encode(io::Cursor::new(&mut expected)).unwrap();
+        let len = metadata.encoded_len().unwrap();
+        assert_eq!(len, expected.len());
+        let mut buffer = vec![0; len];
+        assert_eq!(metadata.encode_into(&mut buffer).unwrap(), len);
+        assert_eq!(buffer, expected);
+        let res = metadata.encode_into(&mut buffer[..len - 1]);
+        assert!(matches!(res, Err(e) if e.to_string().contains("too small")));
+    }
+
     #[test]
     fn test_encode_repeated_symbol_cstr() {
         let mut buffer = Vec::new();
@@ -398,10 +1152,12 @@ mod tests {
             limit: 0,
             record_count: 1_450_000,
             compression: Compression::ZStd,
+            schema_definition: None,
             symbols: vec![],
             partial: vec![],
             not_found: vec![],
             mappings: vec![],
+            annotations: BTreeMap::new(),
         };
         let mut buffer = Vec::new();
         let cursor = io::Cursor::new(&mut buffer);
@@ -447,10 +1203,12 @@ mod tests {
             compression: Compression::None,
             stype_in: SType::Native,
             stype_out: SType::ProductId,
+            schema_definition: None,
             symbols: vec![],
             partial: vec![],
             not_found: vec![],
             mappings: vec![],
+            annotations: BTreeMap::new(),
         };
         (buffer, metadata)
     }
@@ -461,7 +1219,7 @@ mod tests {
     {
         let (buffer, metadata) = encode_records_and_stub_metadata(schema, records.clone());
         let mut iter: DbzStreamIter<&[u8], T> =
-            DbzStreamIter::new(buffer.as_slice(), metadata).unwrap();
+            DbzStreamIter::new(buffer.as_slice(), metadata, None).unwrap();
         let mut res = Vec::new();
         while let Some(rec) = iter.next() {
             res.push(rec.to_owned());
@@ -649,6 +1407,55 @@ mod tests {
         assert_encode_decode_record_identity(Schema::Status, records);
     }
 
+    /// A writer that counts how many times it's flushed.
+    #[derive(Default)]
+    struct FlushCounter {
+        buffer: Vec<u8>,
+        flush_count: usize,
+    }
+
+    impl io::Write for FlushCounter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buffer.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flush_count += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_dbz_stream_flush_interval() {
+        let records = vec![
+            OhlcvMsg {
+                hd: RecordHeader {
+                    rtype: OhlcvMsg::TYPE_ID,
+                    ..RECORD_HEADER
+                },
+                open: 92500000000,
+                high: 95200000000,
+                low: 91200000000,
+                close: 91600000000,
+                volume: 6785,
+            };
+            4
+        ];
+        let mut unflushed = FlushCounter::default();
+        write_dbz_stream(&mut unflushed, VecStream::new(records.clone())).unwrap();
+        let mut flushed = FlushCounter::default();
+        let options = WriterOptions {
+            flush_interval: Some(2),
+            ..Default::default()
+        };
+        write_dbz_stream_with_options(&mut flushed, VecStream::new(records), &options).unwrap();
+        assert_eq!(flushed.flush_count, unflushed.flush_count + 2);
+        assert_eq!(
+            zstd::decode_all(flushed.buffer.as_slice()).unwrap(),
+            zstd::decode_all(unflushed.buffer.as_slice()).unwrap()
+        );
+    }
+
     #[test]
     fn test_decode_malformed_encoded_dbz() {
         let records = vec![
@@ -679,7 +1486,7 @@ mod tests {
         let (buffer, metadata) = encode_records_and_stub_metadata(wrong_schema, records);
         type WrongRecord = TickMsg;
         let mut iter: DbzStreamIter<&[u8], WrongRecord> =
-            DbzStreamIter::new(buffer.as_slice(), metadata).unwrap();
+            DbzStreamIter::new(buffer.as_slice(), metadata, None).unwrap();
         // check doesn't panic
         assert!(iter.next().is_none());
         assert!(iter.next().is_none());
@@ -0,0 +1,184 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use super::{OutputEncoding, OutputOptions};
+use crate::Dbz;
+
+/// An estimate of the size of the output and the time it takes to translate a
+/// [`Dbz`] to an [`OutputEncoding`], extrapolated from a sample of its records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutputEstimate {
+    /// The number of records in the DBZ according to its metadata.
+    pub record_count: u64,
+    /// The estimated number of records in the output, which is less than
+    /// `record_count` when the [`Dbz`] is filtered.
+    pub output_record_count: u64,
+    /// The number of records that were translated to produce the estimate.
+    pub sample_record_count: u64,
+    /// The size of the translated sample in bytes.
+    pub sample_size: u64,
+    /// The time it took to translate the sample.
+    pub sample_duration: Duration,
+    /// The estimated size of the full output in bytes.
+    pub size: u64,
+    /// The estimated time to translate all records.
+    pub duration: Duration,
+}
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Estimates the size and duration of translating the [`Dbz`] to `encoding` with
+    /// `options` by translating at most the first `sample_record_count` records
+    /// without keeping the output. Consumes the [`Dbz`] object.
+    ///
+    /// If the records end before the sample is full, the sample is the full output.
+    /// Otherwise the sample is extrapolated over the rest of the records in the
+    /// metadata's `record_count`, assuming filters like
+    /// [`Dbz::filter_product_ids`] keep the same share of the rest as of the records
+    /// decoded for the sample. Filters that skip or limit a number of records aren't
+    /// accounted for.
+    ///
+    /// # Errors
+    /// This function returns an error if the [`Dbz`] can't be translated to
+    /// `encoding` with `options` or a record of the sample can't be decoded, e.g.
+    /// because the data is truncated.
+    pub fn estimate_output(
+        self,
+        encoding: OutputEncoding,
+        options: &OutputOptions,
+        sample_record_count: usize,
+    ) -> anyhow::Result<OutputEstimate> {
+        let record_count = self.metadata().record_count;
+        let mut writer = CountingWriter::default();
+        let start = Instant::now();
+        let sample =
+            self.write_records_to(&mut writer, encoding, options, Some(sample_record_count))?;
+        let sample_duration = start.elapsed();
+        let decoded_count = sample.end - sample.start;
+        let (output_record_count, size, duration) =
+            if sample.count < sample_record_count as u64 || decoded_count == 0 {
+                (sample.count, writer.count, sample_duration)
+            } else {
+                // the records from the start of the sample to the end of the data
+                let scale = record_count.saturating_sub(sample.start).max(decoded_count) as f64
+                    / decoded_count as f64;
+                (
+                    (sample.count as f64 * scale).round() as u64,
+                    (writer.count as f64 * scale).round() as u64,
+                    sample_duration.mul_f64(scale),
+                )
+            };
+        Ok(OutputEstimate {
+            record_count,
+            output_record_count,
+            sample_record_count: sample.count,
+            sample_size: writer.count,
+            sample_duration,
+            size,
+            duration,
+        })
+    }
+}
+
+/// Discards all output while counting the bytes written.
+#[derive(Debug, Default)]
+struct CountingWriter {
+    count: u64,
+}
+
+impl io::Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.count += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    #[test]
+    fn test_estimate_matches_full_output() {
+        let path = format!("{DBZ_PATH}/test_data.mbp-10.dbz");
+        let mut output = Vec::new();
+        Dbz::from_file(&path)
+            .unwrap()
+            .write_to(&mut output, OutputEncoding::Csv)
+            .unwrap();
+        let estimate = Dbz::from_file(&path)
+            .unwrap()
+            .estimate_output(OutputEncoding::Csv, &OutputOptions::default(), 100)
+            .unwrap();
+        assert_eq!(estimate.record_count, 2);
+        assert_eq!(estimate.sample_record_count, 2);
+        assert_eq!(estimate.sample_size, output.len() as u64);
+        assert_eq!(estimate.size, output.len() as u64);
+    }
+
+    #[test]
+    fn test_estimate_extrapolates() {
+        let estimate = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz"))
+            .unwrap()
+            .estimate_output(
+                OutputEncoding::Json {
+                    should_pretty_print: false,
+                    should_output_array: false,
+                    should_output_iso_timestamps: false,
+                    should_output_decimal_prices: false,
+                },
+                &OutputOptions::default(),
+                1,
+            )
+            .unwrap();
+        assert_eq!(estimate.sample_record_count, 1);
+        assert_eq!(estimate.output_record_count, 2);
+        assert_eq!(estimate.size, estimate.sample_size * 2);
+    }
+
+    #[test]
+    fn test_estimate_filtered() {
+        let path = format!("{DBZ_PATH}/test_data.mbo.dbz");
+        // no matching records
+        let estimate = Dbz::from_file(&path)
+            .unwrap()
+            .filter_product_ids([1])
+            .estimate_output(OutputEncoding::Csv, &OutputOptions::default(), 100)
+            .unwrap();
+        assert_eq!(estimate.output_record_count, 0);
+        assert_eq!(estimate.sample_record_count, 0);
+        assert_eq!(estimate.size, estimate.sample_size);
+        // the last record
+        let mut output = Vec::new();
+        Dbz::from_file_tail(&path, 1)
+            .unwrap()
+            .write_to(&mut output, OutputEncoding::Csv)
+            .unwrap();
+        let estimate = Dbz::from_file_tail(&path, 1)
+            .unwrap()
+            .estimate_output(OutputEncoding::Csv, &OutputOptions::default(), 100)
+            .unwrap();
+        assert_eq!(estimate.output_record_count, 1);
+        assert_eq!(estimate.size, output.len() as u64);
+    }
+
+    #[test]
+    fn test_estimate_truncated() {
+        let bytes = std::fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        let error = Dbz::new(&bytes[..bytes.len() - 20])
+            .unwrap()
+            .estimate_output(OutputEncoding::Csv, &OutputOptions::default(), 100)
+            .unwrap_err();
+        assert!(
+            matches!(Error::find(&error), Some(Error::Decode { .. })),
+            "{error:#}"
+        );
+    }
+}
@@ -0,0 +1,493 @@
+use std::{
+    io, mem,
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc,
+    },
+    thread,
+};
+
+use anyhow::anyhow;
+use databento_defs::{
+    enums::Schema,
+    record::{
+        ConstTypeId, Mbp10Msg, Mbp1Msg, OhlcvMsg, StatusMsg, SymDefMsg, TbboMsg, TickMsg, TradeMsg,
+    },
+};
+use streaming_iterator::StreamingIterator;
+
+use super::{dbz::as_u8_slice, write_text, OutputEncoding, OutputOptions, TextOptions};
+use crate::{fields::VisitFields, read::RecordFilter, Dbz, DbzWriter, Metadata, WriterOptions};
+
+/// The number of decoded records passed to the outputs at a time.
+const BATCH_SIZE: usize = 1024;
+/// The number of batches an output can fall behind before decoding waits for it.
+const MAX_PENDING_BATCHES: usize = 4;
+
+/// One of the outputs of [`Dbz::write_fanout`].
+pub struct FanoutOutput<'a> {
+    sink: Sink<'a>,
+    /// The records to write, on top of any filters of the [`Dbz`].
+    filter: RecordFilter,
+}
+
+/// Where and how an output's records are encoded.
+enum Sink<'a> {
+    Text {
+        writer: Box<dyn io::Write + Send + 'a>,
+        encoding: OutputEncoding,
+        options: OutputOptions,
+    },
+    Dbz {
+        writer: Box<dyn WriteSeek + Send + 'a>,
+        options: WriterOptions,
+    },
+}
+
+/// A writer that can seek, like a DBZ output needs to fill in its metadata.
+trait WriteSeek: io::Write + io::Seek {}
+
+impl<W: io::Write + io::Seek> WriteSeek for W {}
+
+impl<'a> FanoutOutput<'a> {
+    /// Creates an output of the records in `encoding` with `options`, like its
+    /// timestamp format.
+    pub fn text(
+        writer: impl io::Write + Send + 'a,
+        encoding: OutputEncoding,
+        options: OutputOptions,
+    ) -> Self {
+        Self {
+            sink: Sink::Text {
+                writer: Box::new(writer),
+                encoding,
+                options,
+            },
+            filter: RecordFilter::default(),
+        }
+    }
+
+    /// Creates an output of the records in DBZ, with the input's metadata and
+    /// compressed with the level and number of threads of `options`. Like with
+    /// [`DbzWriter`], the `record_count`, `start`, and `end` of the metadata are those
+    /// of the records written.
+    pub fn dbz(writer: impl io::Write + io::Seek + Send + 'a, options: WriterOptions) -> Self {
+        Self {
+            sink: Sink::Dbz {
+                writer: Box::new(writer),
+                options,
+            },
+            filter: RecordFilter::default(),
+        }
+    }
+
+    /// Only writes the records with a `ts_event` at or after `start` and before
+    /// `end` to this output, e.g. to extract a time window alongside a full
+    /// conversion.
+    pub fn filter_range(mut self, start: u64, end: u64) -> Self {
+        self.filter.ts_event_range = Some(start..end);
+        self
+    }
+
+    /// Only writes the records of `product_ids` to this output. Like
+    /// [`FanoutOutput::filter_range`], it can be combined with the other filter.
+    pub fn filter_product_ids(mut self, product_ids: impl IntoIterator<Item = u32>) -> Self {
+        self.filter.product_ids = Some(product_ids.into_iter().collect());
+        self
+    }
+}
+
+/// A [`Sink`] whose options have been validated against the input.
+enum ResolvedSink<'a> {
+    Text {
+        writer: Box<dyn io::Write + Send + 'a>,
+        encoding: OutputEncoding,
+        options: TextOptions,
+    },
+    Dbz {
+        writer: Box<dyn WriteSeek + Send + 'a>,
+        options: WriterOptions,
+    },
+}
+
+impl<R: io::BufRead> Dbz<R> {
+    /// Decodes the records once and writes them to each of `outputs` with its own
+    /// encoding, options, and filters, so converting a file for several consumers
+    /// doesn't require a decoding pass per output. Each output is encoded on its own
+    /// thread.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`] or the options of an output are invalid for the DBZ's
+    /// metadata. It will also return an error if there's an issue writing to any of
+    /// the outputs, though the other outputs are still written in full.
+    pub fn write_fanout(self, outputs: Vec<FanoutOutput>) -> anyhow::Result<()> {
+        match self.schema() {
+            Schema::Mbo => self.write_fanout_of::<TickMsg>(outputs),
+            Schema::Mbp1 => self.write_fanout_of::<Mbp1Msg>(outputs),
+            Schema::Mbp10 => self.write_fanout_of::<Mbp10Msg>(outputs),
+            Schema::Tbbo => self.write_fanout_of::<TbboMsg>(outputs),
+            Schema::Trades => self.write_fanout_of::<TradeMsg>(outputs),
+            Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D => {
+                self.write_fanout_of::<OhlcvMsg>(outputs)
+            }
+            Schema::Definition => self.write_fanout_of::<SymDefMsg>(outputs),
+            Schema::Statistics => Err(anyhow!("Not implemented for schema Statistics")),
+            Schema::Status => self.write_fanout_of::<StatusMsg>(outputs),
+        }
+    }
+
+    fn write_fanout_of<T>(self, outputs: Vec<FanoutOutput>) -> anyhow::Result<()>
+    where
+        T: VisitFields + ConstTypeId + Clone + Send + Sync,
+    {
+        // validate all options before writing anything
+        let outputs = outputs
+            .into_iter()
+            .map(|output| {
+                let sink = match output.sink {
+                    Sink::Text {
+                        writer,
+                        encoding,
+                        options,
+                    } => ResolvedSink::Text {
+                        writer,
+                        encoding,
+                        options: TextOptions::new::<T>(&options, self.metadata())?,
+                    },
+                    Sink::Dbz { writer, options } => ResolvedSink::Dbz { writer, options },
+                };
+                Ok((sink, output.filter))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let metadata = self.metadata().clone();
+        let metadata = &metadata;
+        let mut iter = self.try_into_iter::<T>()?;
+        thread::scope(|scope| {
+            let mut senders = Vec::with_capacity(outputs.len());
+            let mut handles = Vec::with_capacity(outputs.len());
+            for (sink, filter) in outputs {
+                let (sender, receiver) = mpsc::sync_channel(MAX_PENDING_BATCHES);
+                senders.push(sender);
+                handles.push(scope.spawn(move || {
+                    let records = BatchStream::new(receiver, filter);
+                    match sink {
+                        ResolvedSink::Text {
+                            writer,
+                            encoding,
+                            options,
+                        } => write_text(writer, records, encoding, &options),
+                        ResolvedSink::Dbz { writer, options } => {
+                            write_dbz_output(writer, records, metadata, &options)
+                        }
+                    }
+                }));
+            }
+            let mut batch = Vec::with_capacity(BATCH_SIZE);
+            while let Some(record) = iter.next() {
+                batch.push(record.clone());
+                if batch.len() == BATCH_SIZE {
+                    let batch = mem::replace(&mut batch, Vec::with_capacity(BATCH_SIZE));
+                    if !send_batch(&senders, batch) {
+                        break;
+                    }
+                }
+            }
+            if !batch.is_empty() {
+                send_batch(&senders, batch);
+            }
+            // closes the channels, ending the outputs' iterators
+            drop(senders);
+            let mut res = Ok(());
+            for handle in handles {
+                let output_res = handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("Output thread panicked")));
+                if res.is_ok() {
+                    res = output_res;
+                }
+            }
+            res?;
+            iter.take_error().map_or(Ok(()), Err)
+        })
+    }
+}
+
+/// Writes `records` to `writer` as DBZ with `metadata`.
+fn write_dbz_output<T: ConstTypeId>(
+    writer: impl io::Write + io::Seek,
+    mut records: impl StreamingIterator<Item = T>,
+    metadata: &Metadata,
+    options: &WriterOptions,
+) -> anyhow::Result<()> {
+    let mut writer = DbzWriter::with_options(writer, metadata, options)?;
+    while let Some(record) = records.next() {
+        writer.write_record(record)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Sends `batch` to every output still receiving. Returns `false` if none are.
+fn send_batch<T>(senders: &[SyncSender<Arc<Vec<T>>>], batch: Vec<T>) -> bool {
+    let batch = Arc::new(batch);
+    senders
+        .iter()
+        // an output that stopped early, e.g. from an error, has dropped its receiver
+        .filter(|sender| sender.send(batch.clone()).is_ok())
+        .count()
+        > 0
+}
+
+/// A streaming iterator over batches of records received from the decoding thread,
+/// skipping those that don't match the output's filter.
+struct BatchStream<T> {
+    receiver: Receiver<Arc<Vec<T>>>,
+    filter: RecordFilter,
+    batch: Option<Arc<Vec<T>>>,
+    i: usize,
+}
+
+impl<T> BatchStream<T> {
+    fn new(receiver: Receiver<Arc<Vec<T>>>, filter: RecordFilter) -> Self {
+        Self {
+            receiver,
+            filter,
+            batch: None,
+            i: 0,
+        }
+    }
+
+    /// Moves to the next record of the batches, whether or not it matches the filter.
+    fn advance_unfiltered(&mut self) {
+        self.i += 1;
+        if self
+            .batch
+            .as_ref()
+            .is_some_and(|batch| self.i < batch.len())
+        {
+            return;
+        }
+        // batches are never empty
+        self.batch = self.receiver.recv().ok();
+        self.i = 0;
+    }
+}
+
+impl<T: ConstTypeId> StreamingIterator for BatchStream<T> {
+    type Item = T;
+
+    fn advance(&mut self) {
+        loop {
+            self.advance_unfiltered();
+            match self.batch.as_ref().and_then(|batch| batch.get(self.i)) {
+                Some(record) if self.filter.is_active() => {
+                    let bytes = unsafe {
+                        // Safety: all records, types implementing `ConstTypeId` are POD
+                        as_u8_slice(record)
+                    };
+                    if self.filter.matches(bytes) {
+                        return;
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn get(&self) -> Option<&T> {
+        self.batch.as_ref().and_then(|batch| batch.get(self.i))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::write::test_data::RECORD_HEADER;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+    const TRADES_TS_EVENT: u64 = 1609160400107665963;
+
+    fn ohlcv(product_id: u32) -> OhlcvMsg {
+        let mut hd = RECORD_HEADER;
+        hd.product_id = product_id;
+        OhlcvMsg {
+            hd,
+            open: 1,
+            high: 2,
+            low: 1,
+            close: 2,
+            volume: 10,
+        }
+    }
+
+    fn collect_product_ids(mut stream: BatchStream<OhlcvMsg>) -> Vec<u32> {
+        let mut res = Vec::new();
+        while let Some(record) = stream.next() {
+            res.push(record.hd.product_id);
+        }
+        assert!(stream.next().is_none());
+        res
+    }
+
+    #[test]
+    fn test_batch_stream() {
+        let (sender, receiver) = mpsc::sync_channel(3);
+        sender.send(Arc::new(vec![ohlcv(1), ohlcv(2)])).unwrap();
+        sender.send(Arc::new(vec![ohlcv(3)])).unwrap();
+        drop(sender);
+        let target = BatchStream::new(receiver, RecordFilter::default());
+        assert_eq!(collect_product_ids(target), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_batch_stream_filter() {
+        let (sender, receiver) = mpsc::sync_channel(3);
+        sender.send(Arc::new(vec![ohlcv(1), ohlcv(2)])).unwrap();
+        sender.send(Arc::new(vec![ohlcv(1)])).unwrap();
+        sender.send(Arc::new(vec![ohlcv(3), ohlcv(2)])).unwrap();
+        drop(sender);
+        let mut filter = RecordFilter::default();
+        filter.product_ids = Some([2, 3].into_iter().collect());
+        let target = BatchStream::new(receiver, filter);
+        assert_eq!(collect_product_ids(target), vec![2, 3, 2]);
+    }
+
+    #[test]
+    fn test_write_fanout() {
+        let path = format!("{DBZ_PATH}/test_data.mbp-10.dbz");
+        let mut expected_csv = Vec::new();
+        Dbz::from_file(&path)
+            .unwrap()
+            .write_to(&mut expected_csv, OutputEncoding::Csv)
+            .unwrap();
+        let mut csv = Vec::new();
+        let mut json = Vec::new();
+        Dbz::from_file(&path)
+            .unwrap()
+            .write_fanout(vec![
+                FanoutOutput::text(&mut csv, OutputEncoding::Csv, OutputOptions::default()),
+                FanoutOutput::text(
+                    &mut json,
+                    OutputEncoding::Json {
+                        should_pretty_print: false,
+                        should_output_array: false,
+                        should_output_iso_timestamps: false,
+                        should_output_decimal_prices: false,
+                    },
+                    OutputOptions {
+                        columns: Some(vec!["ts_event".to_owned(), "bid_px_00".to_owned()]),
+                        ..Default::default()
+                    },
+                ),
+            ])
+            .unwrap();
+        assert_eq!(csv, expected_csv);
+        let json = String::from_utf8(json).unwrap();
+        assert_eq!(json.lines().count(), 2);
+        assert!(json.starts_with("{\"ts_event\":"), "{json}");
+    }
+
+    #[test]
+    fn test_write_fanout_dbz() {
+        let path = format!("{DBZ_PATH}/test_data.mbo.dbz");
+        let mut dbz = Cursor::new(Vec::new());
+        let mut subset = Cursor::new(Vec::new());
+        let mut csv = Vec::new();
+        Dbz::from_file(&path)
+            .unwrap()
+            .write_fanout(vec![
+                FanoutOutput::dbz(&mut dbz, WriterOptions::default()),
+                FanoutOutput::dbz(&mut subset, WriterOptions::default()).filter_product_ids([5482]),
+                FanoutOutput::text(&mut csv, OutputEncoding::Csv, OutputOptions::default())
+                    .filter_product_ids([1]),
+            ])
+            .unwrap();
+        let expected: Vec<_> = Dbz::from_file(&path)
+            .unwrap()
+            .try_into_iter::<TickMsg>()
+            .unwrap()
+            .into_fallible()
+            .map(|record| record.unwrap().order_id)
+            .collect();
+        let target = Dbz::new(dbz.get_ref().as_slice()).unwrap();
+        assert_eq!(target.metadata().record_count, expected.len() as u64);
+        let order_ids: Vec<_> = target
+            .try_into_iter::<TickMsg>()
+            .unwrap()
+            .into_fallible()
+            .map(|record| record.unwrap().order_id)
+            .collect();
+        assert_eq!(order_ids, expected);
+        let target = Dbz::new(subset.get_ref().as_slice()).unwrap();
+        assert_eq!(target.metadata().record_count, 2);
+        assert!(target
+            .try_into_iter::<TickMsg>()
+            .unwrap()
+            .into_fallible()
+            .all(|record| record.unwrap().hd.product_id == 5482));
+        // only the header
+        assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    fn test_write_fanout_filter_range() {
+        let path = format!("{DBZ_PATH}/test_data.trades.dbz");
+        let mut all = Vec::new();
+        let mut subset = Vec::new();
+        Dbz::from_file(&path)
+            .unwrap()
+            .write_fanout(vec![
+                FanoutOutput::text(&mut all, OutputEncoding::Csv, OutputOptions::default()),
+                FanoutOutput::text(&mut subset, OutputEncoding::Csv, OutputOptions::default())
+                    .filter_range(TRADES_TS_EVENT, u64::MAX),
+            ])
+            .unwrap();
+        let mut expected = Vec::new();
+        Dbz::from_file(&path)
+            .unwrap()
+            .filter_range(TRADES_TS_EVENT, u64::MAX)
+            .write_to(&mut expected, OutputEncoding::Csv)
+            .unwrap();
+        assert_eq!(String::from_utf8(all).unwrap().lines().count(), 3);
+        assert_eq!(subset, expected);
+    }
+
+    #[test]
+    fn test_write_fanout_invalid_options() {
+        let mut csv = Vec::new();
+        let mut dbz = Cursor::new(Vec::new());
+        let res = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz"))
+            .unwrap()
+            .write_fanout(vec![
+                FanoutOutput::dbz(&mut dbz, WriterOptions::default()),
+                FanoutOutput::text(
+                    &mut csv,
+                    OutputEncoding::Csv,
+                    OutputOptions {
+                        columns: Some(vec!["bid_px_00".to_owned()]),
+                        ..Default::default()
+                    },
+                ),
+            ]);
+        assert!(res.is_err());
+        assert!(csv.is_empty());
+        assert!(dbz.get_ref().is_empty());
+    }
+
+    #[test]
+    fn test_write_fanout_truncated() {
+        let bytes = std::fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        let mut csv = Vec::new();
+        let res = Dbz::new(&bytes[..bytes.len() - 20])
+            .unwrap()
+            .write_fanout(vec![FanoutOutput::text(
+                &mut csv,
+                OutputEncoding::Csv,
+                OutputOptions::default(),
+            )]);
+        assert!(res.is_err());
+    }
+}
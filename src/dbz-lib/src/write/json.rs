@@ -1,40 +1,342 @@
-use std::{fmt, io};
+use std::io;
 
 use anyhow::Context;
 use serde::Serialize;
-use serde_json::ser::{Formatter, PrettyFormatter};
+use serde_json::ser::{CompactFormatter, Formatter, PrettyFormatter};
 use streaming_iterator::StreamingIterator;
 
-use databento_defs::record::ConstTypeId;
+use super::{SelectedFields, TextOptions};
+use crate::{
+    fields::{FieldValue, FieldVisitor, Scope, VisitFields},
+    Metadata, PriceDisplay,
+};
 
-use crate::Metadata;
+/// The options of [`OutputEncoding::Json`](super::OutputEncoding::Json) besides pretty
+/// printing.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonOptions {
+    pub should_output_array: bool,
+    pub should_output_iso_timestamps: bool,
+    pub should_output_decimal_prices: bool,
+}
 
-/// Incrementally serializes the contents of `iter` into NDJSON to `writer` so the
-/// contents of `iter` are not all buffered into memory at once.
-pub fn write_json<F: Clone + Formatter, T>(
+/// Incrementally serializes the contents of `iter` into NDJSON, or a single JSON array
+/// if `should_output_array` is `true`, to `writer` so the contents of `iter` are not all
+/// buffered into memory at once.
+pub fn write_json<F: Formatter, T: VisitFields>(
     mut writer: impl io::Write,
-    formatter: F,
+    mut formatter: F,
     mut iter: impl StreamingIterator<Item = T>,
-) -> anyhow::Result<()>
-where
-    T: ConstTypeId + Serialize + fmt::Debug,
-{
+    options: &TextOptions,
+    json_options: JsonOptions,
+) -> anyhow::Result<()> {
+    let should_output_array = json_options.should_output_array;
+    let mut values = ValueFormat::new(json_options, options);
+    let mut selected = SelectedFields::default();
+    let mut is_first = true;
+    let mut record_count = 0;
+    if should_output_array {
+        formatter.begin_array(&mut writer)?;
+    }
     while let Some(record) = iter.next() {
-        match record.serialize(&mut serde_json::Serializer::with_formatter(
+        match write_json_record(
             &mut writer,
-            formatter.clone(),
-        )) {
+            &mut formatter,
+            options,
+            &mut values,
+            &mut selected,
+            record,
+            should_output_array.then_some(is_first),
+        ) {
             // broken output, likely a closed pipe
-            Err(e) if e.is_io() => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
             r => r,
         }
         .with_context(|| format!("Failed to serialize {record:#?}"))?;
+        is_first = false;
+        record_count += 1;
+        if options.should_flush(record_count) {
+            writer.flush()?;
+        }
+    }
+    if should_output_array {
+        formatter.end_array(&mut writer)?;
         writer.write_all(b"\n")?;
     }
     writer.flush()?;
     Ok(())
 }
 
+/// Writes `record` as either an element of an array when `array_is_first` is `Some`
+/// or on its own line.
+fn write_json_record<W: io::Write, F: Formatter, T: VisitFields>(
+    writer: &mut W,
+    formatter: &mut F,
+    options: &TextOptions,
+    values: &mut ValueFormat,
+    selected: &mut SelectedFields,
+    record: &T,
+    array_is_first: Option<bool>,
+) -> io::Result<()> {
+    match array_is_first {
+        Some(is_first) => {
+            formatter.begin_array_value(&mut *writer, is_first)?;
+            JsonObject::write(writer, formatter, options, values, selected, record)?;
+            formatter.end_array_value(writer)
+        }
+        None => {
+            JsonObject::write(writer, formatter, options, values, selected, record)?;
+            writer.write_all(b"\n")
+        }
+    }
+}
+
+/// Writes `record` as a compact JSON object with the default options and no trailing
+/// newline, e.g. to nest it in another object.
+pub(crate) fn write_json_object<T: VisitFields>(
+    writer: &mut impl io::Write,
+    record: &T,
+) -> io::Result<()> {
+    JsonObject::write(
+        writer,
+        &mut CompactFormatter,
+        &TextOptions::default(),
+        &mut ValueFormat::new(JsonOptions::default(), &TextOptions::default()),
+        &mut SelectedFields::default(),
+        record,
+    )
+}
+
+/// Writes a record as a JSON object one field at a time, opening and closing the
+/// nested `hd` object and `booklevel` array as the [`Scope`] of the fields changes.
+/// When fields are selected, the object is flat instead.
+struct JsonObject<'a, W, F> {
+    writer: &'a mut W,
+    formatter: &'a mut F,
+    options: &'a TextOptions,
+    values: &'a mut ValueFormat,
+    selected: &'a mut SelectedFields,
+    scope: Scope,
+    /// Whether the next field is the first in the top-level object.
+    is_first_in_body: bool,
+    /// Whether the next field is the first in the current nested object.
+    is_first_in_scope: bool,
+}
+
+impl<'a, W: io::Write, F: Formatter> JsonObject<'a, W, F> {
+    fn write<T: VisitFields>(
+        writer: &'a mut W,
+        formatter: &'a mut F,
+        options: &'a TextOptions,
+        values: &'a mut ValueFormat,
+        selected: &'a mut SelectedFields,
+        record: &T,
+    ) -> io::Result<()> {
+        formatter.begin_object(writer)?;
+        let mut object = Self {
+            writer,
+            formatter,
+            options,
+            values,
+            selected,
+            scope: Scope::Body,
+            is_first_in_body: true,
+            is_first_in_scope: true,
+        };
+        options.visit_record(record, &mut object)?;
+        object.change_scope(Scope::Body)?;
+        for (i, (name, value)) in object.selected.finish(options).enumerate() {
+            object.formatter.begin_object_key(object.writer, i == 0)?;
+            write_str(object.writer, name)?;
+            object.formatter.end_object_key(object.writer)?;
+            object.formatter.begin_object_value(object.writer)?;
+            object.writer.write_all(value)?;
+            object.formatter.end_object_value(object.writer)?;
+        }
+        object.formatter.end_object(object.writer)
+    }
+
+    fn write_key(&mut self, key: &str, is_first: bool) -> io::Result<()> {
+        self.formatter.begin_object_key(self.writer, is_first)?;
+        write_str(self.writer, key)?;
+        self.formatter.end_object_key(self.writer)?;
+        self.formatter.begin_object_value(self.writer)
+    }
+
+    fn change_scope(&mut self, scope: Scope) -> io::Result<()> {
+        if scope == self.scope {
+            return Ok(());
+        }
+        // close the previous scope
+        match (self.scope, scope) {
+            (Scope::Body, _) => {}
+            (Scope::Header, _) => {
+                self.formatter.end_object(self.writer)?;
+                self.formatter.end_object_value(self.writer)?;
+            }
+            (Scope::Level(_), Scope::Level(_)) => {
+                self.formatter.end_object(self.writer)?;
+                self.formatter.end_array_value(self.writer)?;
+            }
+            (Scope::Level(_), _) => {
+                self.formatter.end_object(self.writer)?;
+                self.formatter.end_array_value(self.writer)?;
+                self.formatter.end_array(self.writer)?;
+                self.formatter.end_object_value(self.writer)?;
+            }
+        }
+        // open the new one
+        match (self.scope, scope) {
+            (_, Scope::Body) => {}
+            (_, Scope::Header) => {
+                self.write_key("hd", self.is_first_in_body)?;
+                self.is_first_in_body = false;
+                self.formatter.begin_object(self.writer)?;
+            }
+            (Scope::Level(_), Scope::Level(_)) => {
+                self.formatter.begin_array_value(self.writer, false)?;
+                self.formatter.begin_object(self.writer)?;
+            }
+            (_, Scope::Level(_)) => {
+                self.write_key("booklevel", self.is_first_in_body)?;
+                self.is_first_in_body = false;
+                self.formatter.begin_array(self.writer)?;
+                self.formatter.begin_array_value(self.writer, true)?;
+                self.formatter.begin_object(self.writer)?;
+            }
+        }
+        self.scope = scope;
+        self.is_first_in_scope = true;
+        Ok(())
+    }
+}
+
+impl<'a, W: io::Write, F: Formatter> FieldVisitor for JsonObject<'a, W, F> {
+    type Error = io::Error;
+
+    fn visit(&mut self, scope: Scope, name: &'static str, value: FieldValue) -> io::Result<()> {
+        if let Some(buffer) = self.selected.next_buffer(self.options) {
+            return self.values.write(buffer, self.formatter, value);
+        }
+        if self.options.selection.is_some() {
+            return Ok(());
+        }
+        self.change_scope(scope)?;
+        let is_first = if scope == Scope::Body {
+            std::mem::replace(&mut self.is_first_in_body, false)
+        } else {
+            std::mem::replace(&mut self.is_first_in_scope, false)
+        };
+        self.write_key(name, is_first)?;
+        self.values.write(self.writer, self.formatter, value)?;
+        self.formatter.end_object_value(self.writer)
+    }
+}
+
+/// Formats field values, optionally as human-readable strings.
+struct ValueFormat {
+    timestamps: Option<IsoTimestampFormatter>,
+    prices: Option<PriceDisplay>,
+}
+
+impl ValueFormat {
+    fn new(json_options: JsonOptions, options: &TextOptions) -> Self {
+        Self {
+            timestamps: json_options
+                .should_output_iso_timestamps
+                .then(IsoTimestampFormatter::default),
+            prices: json_options
+                .should_output_decimal_prices
+                .then_some(options.price_display),
+        }
+    }
+
+    fn write<W: io::Write + ?Sized, F: Formatter>(
+        &mut self,
+        writer: &mut W,
+        formatter: &mut F,
+        value: FieldValue,
+    ) -> io::Result<()> {
+        match value {
+            FieldValue::I8(v) => formatter.write_i8(writer, v),
+            FieldValue::U8(v) => formatter.write_u8(writer, v),
+            FieldValue::I16(v) => formatter.write_i16(writer, v),
+            FieldValue::U16(v) => formatter.write_u16(writer, v),
+            FieldValue::I32(v) => formatter.write_i32(writer, v),
+            FieldValue::U32(v) => formatter.write_u32(writer, v),
+            FieldValue::Price(v) if self.prices.is_some() => {
+                formatter.begin_string(writer)?;
+                self.prices.unwrap_or_default().write(writer, v)?;
+                formatter.end_string(writer)
+            }
+            FieldValue::I64(v) | FieldValue::Price(v) => formatter.write_i64(writer, v),
+            FieldValue::U64(v) => formatter.write_u64(writer, v),
+            FieldValue::Char(v) => formatter.write_i8(writer, v),
+            // quote large integers to avoid loss of precision when parsing
+            FieldValue::Timestamp(v) => {
+                formatter.begin_string(writer)?;
+                match &mut self.timestamps {
+                    Some(timestamps) => timestamps.write(writer, v)?,
+                    None => formatter.write_u64(writer, v)?,
+                }
+                formatter.end_string(writer)
+            }
+            FieldValue::CStr(_) | FieldValue::Str(_) => {
+                // `as_str` is always `Some` for string values
+                write_str(writer, value.as_str().unwrap_or_default())
+            }
+            FieldValue::Null => formatter.write_null(writer),
+        }
+    }
+}
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * NANOS_PER_SEC;
+
+/// Formats UNIX nanosecond timestamps as ISO 8601 UTC datetimes with nanosecond
+/// precision, e.g. `2020-12-28T13:00:00.000000000Z`. Records are mostly in time
+/// order, so the formatted date is cached and only the time of day is formatted
+/// for each timestamp.
+#[derive(Debug, Default)]
+struct IsoTimestampFormatter {
+    /// The number of days since the UNIX epoch of `date`.
+    day: Option<u64>,
+    date: String,
+}
+
+impl IsoTimestampFormatter {
+    fn write<W: io::Write + ?Sized>(&mut self, writer: &mut W, timestamp: u64) -> io::Result<()> {
+        let day = timestamp / NANOS_PER_DAY;
+        if self.day != Some(day) {
+            // the latest timestamp is in 2554, far from overflowing
+            let date = time::OffsetDateTime::UNIX_EPOCH.date() + time::Duration::days(day as i64);
+            self.date = format!(
+                "{:04}-{:02}-{:02}",
+                date.year(),
+                u8::from(date.month()),
+                date.day()
+            );
+            self.day = Some(day);
+        }
+        let nanos = timestamp % NANOS_PER_DAY;
+        let secs = nanos / NANOS_PER_SEC;
+        write!(
+            writer,
+            "{}T{:02}:{:02}:{:02}.{:09}Z",
+            self.date,
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60,
+            nanos % NANOS_PER_SEC
+        )
+    }
+}
+
+fn write_str<W: io::Write + ?Sized>(writer: &mut W, s: &str) -> io::Result<()> {
+    // serde_json handles escaping, strings are formatted the same regardless of the formatter
+    serde_json::to_writer(writer, s).map_err(io::Error::from)
+}
+
 /// Serializes `metadata` in JSON format to `writer`.
 pub fn write_json_metadata<F: Formatter>(
     mut writer: impl io::Write,
@@ -56,10 +358,11 @@ pub fn pretty_formatter() -> PrettyFormatter<'static> {
 
 #[cfg(test)]
 mod tests {
-    use std::{io::BufWriter, os::raw::c_char};
+    use std::{collections::BTreeMap, io::BufWriter, os::raw::c_char};
 
     use super::*;
     use crate::{
+        fields::UNDEF_PRICE,
         write::test_data::{VecStream, BID_ASK, RECORD_HEADER},
         MappingInterval, SymbolMapping,
     };
@@ -72,16 +375,26 @@ mod tests {
     };
     use serde_json::ser::CompactFormatter;
 
-    fn write_json_to_string<T>(vec: Vec<T>, should_pretty_print: bool) -> String
-    where
-        T: ConstTypeId + Serialize + fmt::Debug,
-    {
+    fn write_json_to_string<T: VisitFields>(vec: Vec<T>, should_pretty_print: bool) -> String {
         let mut buffer = Vec::new();
         let writer = BufWriter::new(&mut buffer);
+        let options = TextOptions::default();
         if should_pretty_print {
-            write_json(writer, pretty_formatter(), VecStream::new(vec))
+            write_json(
+                writer,
+                pretty_formatter(),
+                VecStream::new(vec),
+                &options,
+                JsonOptions::default(),
+            )
         } else {
-            write_json(writer, CompactFormatter, VecStream::new(vec))
+            write_json(
+                writer,
+                CompactFormatter,
+                VecStream::new(vec),
+                &options,
+                JsonOptions::default(),
+            )
         }
         .unwrap();
         String::from_utf8(buffer).expect("valid UTF-8")
@@ -183,6 +496,183 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_mbp10_write_json_levels() {
+        let data = vec![Mbp10Msg {
+            hd: RECORD_HEADER,
+            price: 5500,
+            size: 3,
+            action: 'B' as i8,
+            side: 67,
+            flags: -128,
+            depth: 9,
+            ts_recv: 1658441891000000000,
+            ts_in_delta: 22_000,
+            sequence: 1_002_375,
+            booklevel: [BID_ASK; 10],
+        }];
+        let options = TextOptions {
+            levels: Some(1),
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        let writer = BufWriter::new(&mut buffer);
+        write_json(
+            writer,
+            CompactFormatter,
+            VecStream::new(data),
+            &options,
+            JsonOptions::default(),
+        )
+        .unwrap();
+        let res = String::from_utf8(buffer).expect("valid UTF-8");
+
+        assert_eq!(
+            res,
+            format!(
+                "{{{HEADER_JSON},{},{}}}\n",
+                r#""price":5500,"size":3,"action":66,"side":67,"flags":-128,"depth":9,"ts_recv":"1658441891000000000","ts_in_delta":22000,"sequence":1002375"#,
+                format_args!("\"booklevel\":[{BID_ASK_JSON}]")
+            )
+        );
+    }
+
+    #[test]
+    fn test_pretty_write_json_matches_serde() {
+        let rec = Mbp10Msg {
+            hd: RECORD_HEADER,
+            price: 5500,
+            size: 3,
+            action: 'B' as i8,
+            side: 67,
+            flags: -128,
+            depth: 9,
+            ts_recv: 1658441891000000000,
+            ts_in_delta: 22_000,
+            sequence: 1_002_375,
+            booklevel: [BID_ASK; 10],
+        };
+        let mut expected = Vec::new();
+        rec.serialize(&mut serde_json::Serializer::with_formatter(
+            &mut expected,
+            pretty_formatter(),
+        ))
+        .unwrap();
+        expected.push(b'\n');
+        let res = write_json_to_string(vec![rec], true);
+
+        assert_eq!(res, String::from_utf8(expected).unwrap());
+    }
+
+    #[test]
+    fn test_write_json_selected_columns() {
+        let data = vec![TickMsg {
+            hd: RECORD_HEADER,
+            order_id: 16,
+            price: 5500,
+            size: 3,
+            flags: -128,
+            channel_id: 14,
+            action: 'B' as i8,
+            side: 67,
+            ts_recv: 1658441891000000000,
+            ts_in_delta: 22_000,
+            sequence: 1_002_375,
+        }];
+        let mut options = TextOptions::default();
+        let columns = ["ts_recv", "product_id", "size"].map(str::to_owned);
+        options.selection = Some(options.select::<TickMsg>(&columns).unwrap());
+        let mut buffer = Vec::new();
+        let writer = BufWriter::new(&mut buffer);
+        write_json(
+            writer,
+            CompactFormatter,
+            VecStream::new(data),
+            &options,
+            JsonOptions::default(),
+        )
+        .unwrap();
+        let res = String::from_utf8(buffer).expect("valid UTF-8");
+
+        assert_eq!(
+            res,
+            "{\"ts_recv\":\"1658441891000000000\",\"product_id\":323,\"size\":3}\n"
+        );
+    }
+
+    #[test]
+    fn test_write_json_array() {
+        let rec = OhlcvMsg {
+            hd: RECORD_HEADER,
+            open: 5000,
+            high: 8000,
+            low: 3000,
+            close: 6000,
+            volume: 55_000,
+        };
+        let data = vec![rec.clone(), rec];
+        for should_pretty_print in [false, true] {
+            let mut expected = Vec::new();
+            if should_pretty_print {
+                data.serialize(&mut serde_json::Serializer::with_formatter(
+                    &mut expected,
+                    pretty_formatter(),
+                ))
+            } else {
+                data.serialize(&mut serde_json::Serializer::new(&mut expected))
+            }
+            .unwrap();
+            expected.push(b'\n');
+            let mut buffer = Vec::new();
+            let writer = BufWriter::new(&mut buffer);
+            let options = TextOptions::default();
+            if should_pretty_print {
+                write_json(
+                    writer,
+                    pretty_formatter(),
+                    VecStream::new(data.clone()),
+                    &options,
+                    JsonOptions {
+                        should_output_array: true,
+                        ..Default::default()
+                    },
+                )
+            } else {
+                write_json(
+                    writer,
+                    CompactFormatter,
+                    VecStream::new(data.clone()),
+                    &options,
+                    JsonOptions {
+                        should_output_array: true,
+                        ..Default::default()
+                    },
+                )
+            }
+            .unwrap();
+
+            assert_eq!(buffer, expected);
+        }
+    }
+
+    #[test]
+    fn test_write_empty_json_array() {
+        let mut buffer = Vec::new();
+        let writer = BufWriter::new(&mut buffer);
+        write_json(
+            writer,
+            CompactFormatter,
+            VecStream::new(Vec::<TradeMsg>::new()),
+            &TextOptions::default(),
+            JsonOptions {
+                should_output_array: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(buffer, b"[]\n");
+    }
+
     #[test]
     fn test_trade_write_json() {
         let data = vec![TradeMsg {
@@ -209,6 +699,229 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_write_json_iso_timestamps_and_decimal_prices() {
+        let data = vec![TradeMsg {
+            hd: RECORD_HEADER,
+            price: 5_500_250_000_000,
+            size: 3,
+            action: 'B' as i8,
+            side: 67,
+            flags: -128,
+            depth: 9,
+            ts_recv: 1658441891000000025,
+            ts_in_delta: 22_000,
+            sequence: 1_002_375,
+            booklevel: [],
+        }];
+        let mut buffer = Vec::new();
+        write_json(
+            &mut buffer,
+            CompactFormatter,
+            VecStream::new(data),
+            &TextOptions::default(),
+            JsonOptions {
+                should_output_iso_timestamps: true,
+                should_output_decimal_prices: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            concat!(
+                r#"{"hd":{"rtype":4,"publisher_id":1,"product_id":323,"ts_event":"2022-07-21T22:17:31.000000000Z"},"#,
+                r#""price":"5500.250000000","size":3,"action":66,"side":67,"flags":-128,"depth":9,"#,
+                r#""ts_recv":"2022-07-21T22:18:11.000000025Z","ts_in_delta":22000,"sequence":1002375}"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_write_json_price_display() {
+        let data = vec![TradeMsg {
+            hd: RECORD_HEADER,
+            price: 5_500_250_000_000,
+            size: 3,
+            action: 'B' as i8,
+            side: 67,
+            flags: -128,
+            depth: 9,
+            ts_recv: 1658441891000000025,
+            ts_in_delta: 22_000,
+            sequence: 1_002_375,
+            booklevel: [],
+        }];
+        let options = TextOptions {
+            price_display: PriceDisplay {
+                factor_exponent: 2,
+                decimals: Some(0),
+            },
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        write_json(
+            &mut buffer,
+            CompactFormatter,
+            VecStream::new(data),
+            &options,
+            JsonOptions {
+                should_output_decimal_prices: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(String::from_utf8(buffer)
+            .unwrap()
+            .contains(r#""price":"550025","#));
+    }
+
+    #[test]
+    fn test_iso_timestamp_formatter() {
+        let mut target = IsoTimestampFormatter::default();
+        let mut format = |timestamp| {
+            let mut buffer = Vec::new();
+            target.write(&mut buffer, timestamp).unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+        assert_eq!(format(0), "1970-01-01T00:00:00.000000000Z");
+        assert_eq!(
+            format(1658441851000000000),
+            "2022-07-21T22:17:31.000000000Z"
+        );
+        // same day as the cached date
+        assert_eq!(
+            format(1658444400123456789),
+            "2022-07-21T23:00:00.123456789Z"
+        );
+        assert_eq!(
+            format(1658528251000000000),
+            "2022-07-22T22:17:31.000000000Z"
+        );
+        assert_eq!(format(u64::MAX), "2554-07-21T23:34:33.709551615Z");
+    }
+
+    #[test]
+    fn test_write_decimal_price() {
+        let format = |price| {
+            let mut buffer = Vec::new();
+            PriceDisplay::default().write(&mut buffer, price).unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+        assert_eq!(format(0), "0.000000000");
+        assert_eq!(format(5500), "0.000005500");
+        assert_eq!(format(-1_500_000_000), "-1.500000000");
+        assert_eq!(format(i64::MIN), "-9223372036.854775808");
+    }
+
+    #[test]
+    fn test_write_json_chars() {
+        let data = vec![TradeMsg {
+            hd: RECORD_HEADER,
+            price: 5500,
+            size: 3,
+            action: 'T' as i8,
+            side: 0,
+            flags: -128,
+            depth: 9,
+            ts_recv: 1658441891000000000,
+            ts_in_delta: 22_000,
+            sequence: 1_002_375,
+            booklevel: [],
+        }];
+        let options = TextOptions {
+            should_output_chars: true,
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        write_json(
+            &mut buffer,
+            CompactFormatter,
+            VecStream::new(data),
+            &options,
+            JsonOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            format!(
+                "{{{HEADER_JSON},{}}}\n",
+                r#""price":5500,"size":3,"action":"T","side":0,"flags":-128,"depth":9,"ts_recv":"1658441891000000000","ts_in_delta":22000,"sequence":1002375"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_write_json_chars_high_bit() {
+        let data = vec![TradeMsg {
+            hd: RECORD_HEADER,
+            price: 5500,
+            size: 3,
+            action: 'T' as i8,
+            // not ASCII, so output as an integer
+            side: 0xC3_u8 as i8,
+            flags: -128,
+            depth: 9,
+            ts_recv: 1658441891000000000,
+            ts_in_delta: 22_000,
+            sequence: 1_002_375,
+            booklevel: [],
+        }];
+        let options = TextOptions {
+            should_output_chars: true,
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        write_json(
+            &mut buffer,
+            CompactFormatter,
+            VecStream::new(data),
+            &options,
+            JsonOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            format!(
+                "{{{HEADER_JSON},{}}}\n",
+                r#""price":5500,"size":3,"action":"T","side":-61,"flags":-128,"depth":9,"ts_recv":"1658441891000000000","ts_in_delta":22000,"sequence":1002375"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_write_json_undef_price() {
+        let data = vec![OhlcvMsg {
+            hd: RECORD_HEADER,
+            open: UNDEF_PRICE,
+            high: 8000,
+            low: 3000,
+            close: 6000,
+            volume: 55_000,
+        }];
+        let options = TextOptions {
+            should_null_undef_prices: true,
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        write_json(
+            &mut buffer,
+            CompactFormatter,
+            VecStream::new(data),
+            &options,
+            JsonOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            format!(
+                "{{{HEADER_JSON},{}}}\n",
+                r#""open":null,"high":8000,"low":3000,"close":6000,"volume":55000"#,
+            )
+        );
+    }
+
     #[test]
     fn test_ohlcv_write_json() {
         let data = vec![OhlcvMsg {
@@ -355,6 +1068,7 @@ mod tests {
             compression: Compression::ZStd,
             stype_in: SType::ProductId,
             stype_out: SType::Native,
+            schema_definition: None,
             symbols: vec!["ESZ2".to_owned()],
             partial: Vec::new(),
             not_found: Vec::new(),
@@ -368,6 +1082,7 @@ mod tests {
                     symbol: "ESH2".to_owned(),
                 }],
             }],
+            annotations: BTreeMap::new(),
         };
         let res = write_json_metadata_to_string(&metadata, false);
         assert_eq!(
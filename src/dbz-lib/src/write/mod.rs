@@ -1,32 +1,397 @@
 mod csv;
 pub(crate) mod dbz;
+mod estimate;
+mod fanout;
 mod json;
+mod output;
+mod pipeline;
+mod preset;
+mod rotate;
 
-use std::{fmt, io};
+use std::{cell::RefCell, io, sync::Arc};
 
-use anyhow::anyhow;
+use anyhow::{anyhow, Context};
 use serde_json::ser::CompactFormatter;
+use streaming_iterator::StreamingIterator;
+use time::OffsetDateTime;
 
 use databento_defs::{
-    enums::Schema,
-    record::{
-        ConstTypeId, Mbp10Msg, Mbp1Msg, OhlcvMsg, StatusMsg, SymDefMsg, TbboMsg, TickMsg, TradeMsg,
-    },
+    enums::{SType, Schema},
+    record::{Mbp10Msg, Mbp1Msg, OhlcvMsg, StatusMsg, SymDefMsg, TbboMsg, TickMsg, TradeMsg},
 };
 
+pub(crate) use self::json::write_json_object;
 use self::{
-    csv::{serialize::CsvSerialize, write_csv},
-    json::{pretty_formatter, write_json, write_json_metadata},
+    csv::write_csv,
+    json::{pretty_formatter, write_json, write_json_metadata, JsonOptions},
+};
+pub use self::{
+    estimate::OutputEstimate,
+    fanout::FanoutOutput,
+    output::{OutputFile, WriterOptions},
+    pipeline::PipelineOptions,
+    preset::ColumnPreset,
+    rotate::{RotatingDbzWriter, RotationOptions},
+};
+use crate::{
+    adjust::{Adjuster, Adjustment},
+    fields::{FieldValue, FieldVisitor, Scope, VisitFields, LEVEL_FIELD_COUNT, UNDEF_PRICE},
+    symbology::SymbolResolver,
+    Dbz, Metadata, PriceDisplay,
 };
-use crate::{Dbz, Metadata};
 
 /// An encoding that DBZs can be translated to.
 #[derive(Clone, Copy, Debug)]
 pub enum OutputEncoding {
     /// Comma-separate values.
     Csv,
-    /// JavaScript object notation.
-    Json { should_pretty_print: bool },
+    /// JavaScript object notation. By default, one object per line (NDJSON).
+    Json {
+        /// Indent the JSON output to make it easier to read.
+        should_pretty_print: bool,
+        /// Output all records as a single JSON array instead of one per line.
+        should_output_array: bool,
+        /// Output timestamps such as `ts_event` as ISO 8601 strings with nanosecond
+        /// precision instead of integer UNIX nanoseconds.
+        should_output_iso_timestamps: bool,
+        /// Output prices as decimal strings instead of integers in units of 1e-9,
+        /// displayed according to the metadata's [`PriceDisplay`] annotations.
+        should_output_decimal_prices: bool,
+    },
+}
+
+/// How prices that aren't defined, i.e. equal to [`UNDEF_PRICE`], are output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UndefPrice {
+    /// Output them as `null` in JSON and an empty field in CSV, so they aren't
+    /// mistaken for real prices.
+    #[default]
+    Null,
+    /// Output the sentinel value as is.
+    Raw,
+}
+
+/// How single-character fields such as `action` and `side` are output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CharFormat {
+    /// Output them as the integer value of the character, e.g. `65` for `A`.
+    #[default]
+    Int,
+    /// Output them as one-character strings, e.g. `"A"`. Values that aren't ASCII
+    /// characters, such as 0, are still output as integers.
+    Char,
+}
+
+/// Options for translating DBZs to an [`OutputEncoding`] that apply to all encodings.
+#[derive(Clone, Debug, Default)]
+pub struct OutputOptions {
+    /// The symbology type to map records' `product_id` to using the metadata's
+    /// mappings. When [`SType::Native`], each record is output with a trailing
+    /// `symbol` field, which is empty or `null` for unmapped product IDs. For
+    /// metadata, `stype_out` and the mappings are rewritten.
+    pub map_stype: Option<SType>,
+    /// The fields to output and their order, using the CSV header names. `None`
+    /// outputs all fields. When set, JSON records are output as flat objects keyed
+    /// by the same names.
+    pub columns: Option<Vec<String>>,
+    /// The maximum number of book levels to output, starting from the top of the book.
+    /// `None` outputs all levels. Records with fewer levels are output unchanged.
+    pub levels: Option<usize>,
+    /// Adjusts the prices and sizes of records by their native symbol and date. The
+    /// symbols are looked up in the metadata's mappings.
+    pub adjuster: Option<Arc<dyn Adjuster>>,
+    /// How to output prices that aren't defined.
+    pub undef_price: UndefPrice,
+    /// How to output single-character fields.
+    pub char_format: CharFormat,
+    /// Whether to leave out the header row of CSV output, e.g. when appending to an
+    /// existing file.
+    pub should_omit_csv_header: bool,
+    /// How often to flush the output. Only [`WriterOptions::flush_interval`] applies to
+    /// the text encoders.
+    pub writer: WriterOptions,
+}
+
+/// [`OutputOptions`] resolved against a DBZ's metadata and record type.
+#[derive(Debug)]
+pub(crate) struct TextOptions {
+    /// Resolves the native symbols of records, memoized across calls to
+    /// [`TextOptions::visit_record`].
+    symbol_resolver: Option<RefCell<SymbolResolver>>,
+    should_output_symbol: bool,
+    selection: Option<Selection>,
+    levels: Option<usize>,
+    adjuster: Option<Arc<dyn Adjuster>>,
+    should_null_undef_prices: bool,
+    should_output_chars: bool,
+    should_omit_csv_header: bool,
+    writer: WriterOptions,
+    /// How to display decimal prices, from the metadata's annotations.
+    pub price_display: PriceDisplay,
+}
+
+/// A subset of fields to output in a specific order.
+#[derive(Debug)]
+struct Selection {
+    columns: Vec<&'static str>,
+    /// The output index of each visited field, in visit order, or `None` if it's
+    /// not selected.
+    indices: Vec<Option<usize>>,
+}
+
+impl Default for TextOptions {
+    /// The [`OutputOptions::default`] options, without metadata to resolve symbols or
+    /// annotations against.
+    fn default() -> Self {
+        let options = OutputOptions::default();
+        Self {
+            symbol_resolver: None,
+            should_output_symbol: false,
+            selection: None,
+            levels: options.levels,
+            adjuster: options.adjuster,
+            should_null_undef_prices: options.undef_price == UndefPrice::Null,
+            should_output_chars: options.char_format == CharFormat::Char,
+            should_omit_csv_header: options.should_omit_csv_header,
+            writer: options.writer,
+            price_display: PriceDisplay::default(),
+        }
+    }
+}
+
+impl TextOptions {
+    fn new<T: VisitFields>(options: &OutputOptions, metadata: &Metadata) -> anyhow::Result<Self> {
+        let should_output_symbol = match options.map_stype {
+            None | Some(SType::ProductId) => false,
+            Some(SType::Native) => true,
+            Some(SType::Smart) => {
+                return Err(anyhow!(
+                    "Mapping product IDs to smart symbols is unsupported"
+                ))
+            }
+        };
+        let symbol_resolver = if should_output_symbol {
+            Some(SymbolResolver::from_metadata(metadata)?)
+        } else if options.adjuster.is_some() {
+            Some(
+                SymbolResolver::from_metadata(metadata)
+                    .with_context(|| "Adjusting records requires their native symbols")?,
+            )
+        } else {
+            None
+        };
+        let mut res = Self {
+            symbol_resolver: symbol_resolver.map(RefCell::new),
+            should_output_symbol,
+            selection: None,
+            levels: options.levels,
+            adjuster: options.adjuster.clone(),
+            should_null_undef_prices: options.undef_price == UndefPrice::Null,
+            should_output_chars: options.char_format == CharFormat::Char,
+            should_omit_csv_header: options.should_omit_csv_header,
+            writer: options.writer.clone(),
+            price_display: PriceDisplay::from_metadata(metadata)?,
+        };
+        if let Some(columns) = &options.columns {
+            res.selection = Some(res.select::<T>(columns)?);
+        }
+        Ok(res)
+    }
+
+    fn select<T: VisitFields>(&self, columns: &[String]) -> anyhow::Result<Selection> {
+        let all_columns: Vec<_> = self.all_headers::<T>().collect();
+        let mut indices = vec![None; all_columns.len()];
+        let mut selected = Vec::with_capacity(columns.len());
+        for (i, column) in columns.iter().enumerate() {
+            let pos = all_columns
+                .iter()
+                .position(|c| c == column)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Unknown column '{column}', expected one of: {}",
+                        all_columns.join(", ")
+                    )
+                })?;
+            if indices[pos].is_some() {
+                return Err(anyhow!("Column '{column}' was selected more than once"));
+            }
+            indices[pos] = Some(i);
+            selected.push(all_columns[pos]);
+        }
+        if selected.is_empty() {
+            return Err(anyhow!("At least one column must be selected"));
+        }
+        Ok(Selection {
+            columns: selected,
+            indices,
+        })
+    }
+
+    fn all_headers<T: VisitFields>(&self) -> impl Iterator<Item = &'static str> {
+        let levels = self
+            .levels
+            .map_or(T::LEVEL_COUNT, |levels| levels.min(T::LEVEL_COUNT));
+        let len = T::HEADERS.len() - (T::LEVEL_COUNT - levels) * LEVEL_FIELD_COUNT;
+        T::HEADERS[..len]
+            .iter()
+            .copied()
+            .chain(self.should_output_symbol.then_some("symbol"))
+    }
+
+    /// Returns whether the output should be flushed after writing `record_count`
+    /// records.
+    pub fn should_flush(&self, record_count: usize) -> bool {
+        self.writer.should_flush(record_count)
+    }
+
+    /// Returns the names of the fields output for records of type `T`.
+    pub fn headers<T: VisitFields>(&self) -> Vec<&'static str> {
+        match &self.selection {
+            Some(selection) => selection.columns.clone(),
+            None => self.all_headers::<T>().collect(),
+        }
+    }
+
+    /// Visits the fields of `record` followed by any fields added by the options.
+    pub fn visit_record<T: VisitFields, V: FieldVisitor>(
+        &self,
+        record: &T,
+        visitor: &mut V,
+    ) -> Result<(), V::Error> {
+        let hd = record.header();
+        let mut symbol_resolver = self.symbol_resolver.as_ref().map(RefCell::borrow_mut);
+        let symbol = symbol_resolver
+            .as_mut()
+            .and_then(|resolver| resolver.resolve(hd.product_id, hd.ts_event));
+        let adjustment = self
+            .adjuster
+            .as_ref()
+            .zip(symbol)
+            .and_then(|(adjuster, symbol)| {
+                let date = OffsetDateTime::from_unix_timestamp_nanos(hd.ts_event as i128)
+                    .ok()?
+                    .date();
+                adjuster.adjustment(symbol, date)
+            });
+        let levels = self.levels.unwrap_or(usize::MAX);
+        if levels < T::LEVEL_COUNT
+            || adjustment.is_some()
+            || self.should_null_undef_prices
+            || self.should_output_chars
+        {
+            record.visit_fields(&mut RecordVisitor {
+                visitor,
+                levels,
+                adjustment,
+                should_null_undef_prices: self.should_null_undef_prices,
+                should_output_chars: self.should_output_chars,
+            })?;
+        } else {
+            record.visit_fields(visitor)?;
+        }
+        if self.should_output_symbol {
+            visitor.visit(
+                Scope::Body,
+                "symbol",
+                symbol.map_or(FieldValue::Null, FieldValue::Str),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Applies [`TextOptions`] to the fields of a record before passing them through to
+/// `visitor`: skipping those of book levels at or past `levels`, adjusting prices
+/// and sizes, replacing undefined prices with nulls, and converting characters to
+/// strings.
+struct RecordVisitor<'a, V> {
+    visitor: &'a mut V,
+    levels: usize,
+    adjustment: Option<Adjustment>,
+    should_null_undef_prices: bool,
+    should_output_chars: bool,
+}
+
+impl<'a, V: FieldVisitor> FieldVisitor for RecordVisitor<'a, V> {
+    type Error = V::Error;
+
+    fn visit(
+        &mut self,
+        scope: Scope,
+        name: &'static str,
+        value: FieldValue,
+    ) -> Result<(), V::Error> {
+        if matches!(scope, Scope::Level(i) if i >= self.levels) {
+            return Ok(());
+        }
+        match value {
+            FieldValue::Char(c) if self.should_output_chars && c != 0 && (c as u8).is_ascii() => {
+                let mut buffer = [0; 4];
+                let c = char::from(c as u8).encode_utf8(&mut buffer);
+                return self.visitor.visit(scope, name, FieldValue::Str(c));
+            }
+            _ => {}
+        }
+        let value = match (self.adjustment, value) {
+            (_, FieldValue::Price(UNDEF_PRICE)) if self.should_null_undef_prices => {
+                FieldValue::Null
+            }
+            (None, value) => value,
+            (Some(_), FieldValue::Price(UNDEF_PRICE)) => value,
+            (Some(adj), FieldValue::Price(px)) => {
+                FieldValue::Price((px as f64 * adj.price_multiplier).round() as i64)
+            }
+            (Some(adj), FieldValue::U32(sz)) if SIZE_FIELDS.contains(&name) => {
+                FieldValue::U32((sz as f64 * adj.size_multiplier).round() as u32)
+            }
+            (Some(adj), FieldValue::U64(sz)) if SIZE_FIELDS.contains(&name) => {
+                FieldValue::U64((sz as f64 * adj.size_multiplier).round() as u64)
+            }
+            (Some(_), value) => value,
+        };
+        self.visitor.visit(scope, name, value)
+    }
+}
+
+/// The names of fields scaled by [`Adjustment::size_multiplier`].
+const SIZE_FIELDS: &[&str] = &["size", "bid_sz", "ask_sz", "volume"];
+
+/// Buffers the encoded values of the selected fields of a record so they can be
+/// output in the selected order.
+#[derive(Debug, Default)]
+pub(crate) struct SelectedFields {
+    pos: usize,
+    values: Vec<Vec<u8>>,
+}
+
+impl SelectedFields {
+    /// Returns the cleared buffer for the next visited field if it's selected.
+    pub fn next_buffer(&mut self, options: &TextOptions) -> Option<&mut Vec<u8>> {
+        let selection = options.selection.as_ref()?;
+        let pos = self.pos;
+        self.pos += 1;
+        let index = selection.indices[pos]?;
+        if self.values.len() < selection.columns.len() {
+            self.values.resize(selection.columns.len(), Vec::new());
+        }
+        let buffer = &mut self.values[index];
+        buffer.clear();
+        Some(buffer)
+    }
+
+    /// Returns the selected column names and encoded values of the visited record in
+    /// output order and resets for the next record.
+    pub fn finish<'a>(
+        &'a mut self,
+        options: &'a TextOptions,
+    ) -> impl Iterator<Item = (&'static str, &'a [u8])> {
+        self.pos = 0;
+        options
+            .selection
+            .iter()
+            .flat_map(|selection| selection.columns.iter().copied())
+            .zip(self.values.iter().map(Vec::as_slice))
+    }
 }
 
 impl<R: io::BufRead> Dbz<R> {
@@ -36,39 +401,129 @@ impl<R: io::BufRead> Dbz<R> {
     /// # Errors
     /// This function returns an error if [`Dbz::schema()`] is
     /// [`Schema::Statistics`](databento_defs::enums::Schema::Statistics). It will also
-    /// return an error if there's an issue writing the output to `writer`.
+    /// return an error if a record can't be decoded or there's an issue writing the
+    /// output to `writer`.
     pub fn write_to(self, writer: impl io::Write, encoding: OutputEncoding) -> anyhow::Result<()> {
+        self.write_to_with_options(writer, encoding, &OutputOptions::default())
+    }
+
+    /// Streams the contents of the [`Dbz`] to `writer` encoding it using `encoding` and
+    /// `options`. Consumes the [`Dbz`] object.
+    ///
+    /// # Errors
+    /// This function returns an error if [`Dbz::schema()`] is
+    /// [`Schema::Statistics`](databento_defs::enums::Schema::Statistics) or `options`
+    /// are invalid for the DBZ's metadata. It will also return an error if a record
+    /// can't be decoded or there's an issue writing the output to `writer`.
+    pub fn write_to_with_options(
+        self,
+        writer: impl io::Write,
+        encoding: OutputEncoding,
+        options: &OutputOptions,
+    ) -> anyhow::Result<()> {
+        self.write_records_to(writer, encoding, options, None)?;
+        Ok(())
+    }
+
+    /// Writes at most `limit` records, or all if `None`.
+    pub(crate) fn write_records_to(
+        self,
+        writer: impl io::Write,
+        encoding: OutputEncoding,
+        options: &OutputOptions,
+        limit: Option<usize>,
+    ) -> anyhow::Result<WrittenRecords> {
         match self.schema() {
-            Schema::Mbo => self.write_with_tick_to::<TickMsg, _>(writer, encoding),
-            Schema::Mbp1 => self.write_with_tick_to::<Mbp1Msg, _>(writer, encoding),
-            Schema::Mbp10 => self.write_with_tick_to::<Mbp10Msg, _>(writer, encoding),
-            Schema::Tbbo => self.write_with_tick_to::<TbboMsg, _>(writer, encoding),
-            Schema::Trades => self.write_with_tick_to::<TradeMsg, _>(writer, encoding),
+            Schema::Mbo => self.write_with_tick_to::<TickMsg, _>(writer, encoding, options, limit),
+            Schema::Mbp1 => self.write_with_tick_to::<Mbp1Msg, _>(writer, encoding, options, limit),
+            Schema::Mbp10 => {
+                self.write_with_tick_to::<Mbp10Msg, _>(writer, encoding, options, limit)
+            }
+            Schema::Tbbo => self.write_with_tick_to::<TbboMsg, _>(writer, encoding, options, limit),
+            Schema::Trades => {
+                self.write_with_tick_to::<TradeMsg, _>(writer, encoding, options, limit)
+            }
             Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D => {
-                self.write_with_tick_to::<OhlcvMsg, _>(writer, encoding)
+                self.write_with_tick_to::<OhlcvMsg, _>(writer, encoding, options, limit)
+            }
+            Schema::Definition => {
+                self.write_with_tick_to::<SymDefMsg, _>(writer, encoding, options, limit)
             }
-            Schema::Definition => self.write_with_tick_to::<SymDefMsg, _>(writer, encoding),
             Schema::Statistics => Err(anyhow!("Not implemented for schema Statistics")),
-            Schema::Status => self.write_with_tick_to::<StatusMsg, _>(writer, encoding),
+            Schema::Status => {
+                self.write_with_tick_to::<StatusMsg, _>(writer, encoding, options, limit)
+            }
         }
     }
 
-    fn write_with_tick_to<T, W>(self, writer: W, encoding: OutputEncoding) -> anyhow::Result<()>
+    fn write_with_tick_to<T, W>(
+        self,
+        writer: W,
+        encoding: OutputEncoding,
+        options: &OutputOptions,
+        limit: Option<usize>,
+    ) -> anyhow::Result<WrittenRecords>
     where
-        T: ConstTypeId + CsvSerialize + fmt::Debug,
+        T: VisitFields,
         W: io::Write,
     {
-        let iter = self.try_into_iter::<T>()?;
-        match encoding {
-            OutputEncoding::Csv => write_csv(writer, iter),
-            OutputEncoding::Json {
-                should_pretty_print,
-            } => {
-                if should_pretty_print {
-                    write_json(writer, pretty_formatter(), iter)
-                } else {
-                    write_json(writer, CompactFormatter, iter)
-                }
+        let options = &TextOptions::new::<T>(options, self.metadata())?;
+        let mut iter = self.try_into_iter::<T>()?;
+        let start = iter.i as u64;
+        let mut count = 0;
+        write_text(
+            writer,
+            (&mut iter)
+                .take(limit.unwrap_or(usize::MAX))
+                .inspect(|_| count += 1),
+            encoding,
+            options,
+        )?;
+        if let Some(error) = iter.take_error() {
+            return Err(error);
+        }
+        Ok(WrittenRecords {
+            count,
+            start,
+            end: iter.i as u64,
+        })
+    }
+}
+
+/// The records written by [`Dbz::write_records_to`].
+pub(crate) struct WrittenRecords {
+    /// The number of records written.
+    pub count: u64,
+    /// The index of the first record decoded, after any records skipped from an index.
+    pub start: u64,
+    /// The index after the last record decoded, including records left out by filters.
+    pub end: u64,
+}
+
+/// Writes the records of `iter` to `writer` in `encoding`.
+fn write_text<T: VisitFields>(
+    writer: impl io::Write,
+    iter: impl StreamingIterator<Item = T>,
+    encoding: OutputEncoding,
+    options: &TextOptions,
+) -> anyhow::Result<()> {
+    match encoding {
+        OutputEncoding::Csv => write_csv(writer, iter, options),
+        OutputEncoding::Json {
+            should_pretty_print,
+            should_output_array,
+            should_output_iso_timestamps,
+            should_output_decimal_prices,
+        } => {
+            let json_options = JsonOptions {
+                should_output_array,
+                should_output_iso_timestamps,
+                should_output_decimal_prices,
+            };
+            if should_pretty_print {
+                write_json(writer, pretty_formatter(), iter, options, json_options)
+            } else {
+                write_json(writer, CompactFormatter, iter, options, json_options)
             }
         }
     }
@@ -83,7 +538,8 @@ impl Metadata {
     /// # Errors
     /// This function returns an error if [`Dbz::schema()`] is
     /// [`Schema::Statistics`](databento_defs::enums::Schema::Statistics). It will also
-    /// return an error if there's an issue writing the output to `writer`.
+    /// return an error if a record can't be decoded or there's an issue writing the
+    /// output to `writer`.
     pub fn write_to(&self, writer: impl io::Write, encoding: OutputEncoding) -> anyhow::Result<()> {
         match encoding {
             OutputEncoding::Csv => Err(anyhow!(
@@ -91,6 +547,7 @@ impl Metadata {
             )),
             OutputEncoding::Json {
                 should_pretty_print,
+                ..
             } => {
                 if should_pretty_print {
                     write_json_metadata(writer, pretty_formatter(), self)
@@ -100,10 +557,29 @@ impl Metadata {
             }
         }
     }
+
+    /// Writes the metadata to `writer` encoding it using `encoding` and `options`, if
+    /// supported.
+    ///
+    /// # Errors
+    /// This function returns an error if `encoding` is [`OutputEncoding::Csv`] or
+    /// `options` are invalid for the metadata. It will also return an error if there's
+    /// an issue writing the output to `writer`.
+    pub fn write_to_with_options(
+        &self,
+        writer: impl io::Write,
+        encoding: OutputEncoding,
+        options: &OutputOptions,
+    ) -> anyhow::Result<()> {
+        match options.map_stype {
+            Some(stype_out) => self.with_stype_out(stype_out)?.write_to(writer, encoding),
+            None => self.write_to(writer, encoding),
+        }
+    }
 }
 
 #[cfg(test)]
-mod test_data {
+pub(crate) mod test_data {
     use databento_defs::record::{BidAskPair, RecordHeader};
     use streaming_iterator::StreamingIterator;
 
@@ -151,3 +627,40 @@ mod test_data {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use databento_defs::record::TickMsg;
+
+    use crate::Error;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    #[test]
+    fn test_write_to_truncated() {
+        let bytes = std::fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        let mut csv = Vec::new();
+        let error = Dbz::new(Cursor::new(&bytes[..bytes.len() - 20]))
+            .unwrap()
+            .write_to(&mut csv, OutputEncoding::Csv)
+            .unwrap_err();
+        assert!(
+            matches!(Error::find(&error), Some(Error::Decode { .. })),
+            "{error:#}"
+        );
+    }
+
+    #[test]
+    fn test_text_options_default_matches_output_options() {
+        let dbz = Dbz::from_file(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        let resolved =
+            TextOptions::new::<TickMsg>(&OutputOptions::default(), dbz.metadata()).unwrap();
+        assert_eq!(
+            format!("{:?}", TextOptions::default()),
+            format!("{resolved:?}")
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    sync::Arc,
+};
+
+/// The default capacity of an [`OutputFile`]'s buffer, the same as [`BufWriter`]'s.
+const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Options for how output is buffered, compressed, and made durable. The defaults
+/// suit batch conversion, while recorders that must bound data loss can flush
+/// periodically and sync the file to disk once writing finishes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriterOptions {
+    /// The capacity in bytes of the buffer of an [`OutputFile`].
+    pub buffer_size: usize,
+    /// Flush the output after every `flush_interval` records. `None` or 0 only flushes
+    /// when the buffers are full and once writing finishes.
+    pub flush_interval: Option<usize>,
+    /// Whether [`OutputFile::finish`] syncs the contents of the file to disk.
+    pub should_sync_on_finish: bool,
+    /// The Zstd compression level of DBZ output, from 1 to 22 with higher levels
+    /// compressing more slowly to smaller files. 0 selects Zstd's default level and
+    /// other levels out of range are clamped.
+    pub compression_level: i32,
+    /// The number of Zstd worker threads compressing DBZ output in parallel with
+    /// writing. 0 compresses on the calling thread.
+    pub n_threads: u32,
+    /// A Zstd dictionary for compressing DBZ output, e.g. from
+    /// [`train_dict`](crate::train_dict), which greatly improves the compression of
+    /// small files. The same dictionary is required to decode the records, see
+    /// [`Dbz::with_dictionary`](crate::Dbz::with_dictionary).
+    pub dictionary: Option<Arc<[u8]>>,
+    /// End the Zstd frame of [`DbzWriter`](crate::DbzWriter) output and start a new
+    /// one every `frame_record_count` records, so readers with a
+    /// [`RecordIndex`](crate::RecordIndex) can start decoding from any frame. `None`
+    /// or 0 writes all the records in a single frame.
+    pub frame_record_count: Option<usize>,
+    /// Whether DBZ output must be byte-identical for identical metadata, records, and
+    /// options, e.g. so files can be content-addressed. This pins Zstd's default
+    /// level, which may change between Zstd releases, to
+    /// [`DETERMINISTIC_COMPRESSION_LEVEL`](Self::DETERMINISTIC_COMPRESSION_LEVEL) and
+    /// compresses on the calling thread regardless of `n_threads`, because
+    /// multithreaded compression splits the input into jobs differently.
+    pub is_deterministic: bool,
+    /// Whether [`DbzWriter`](crate::DbzWriter) ends the file with a footer holding
+    /// the CRC32 of the compressed records, which
+    /// [`Dbz::verify`](crate::Dbz::verify) checks to detect truncated or corrupted
+    /// files. The footer is a Zstd skippable frame, so readers unaware of it skip it.
+    pub should_write_checksum: bool,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        Self {
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            flush_interval: None,
+            should_sync_on_finish: false,
+            compression_level: 0,
+            n_threads: 0,
+            dictionary: None,
+            frame_record_count: None,
+            is_deterministic: false,
+            should_write_checksum: false,
+        }
+    }
+}
+
+impl WriterOptions {
+    /// The level of Zstd compression used in place of the default when
+    /// [`WriterOptions::is_deterministic`] is set.
+    pub const DETERMINISTIC_COMPRESSION_LEVEL: i32 = 3;
+
+    /// Returns whether the output should be flushed after writing `record_count`
+    /// records.
+    pub(crate) fn should_flush(&self, record_count: usize) -> bool {
+        self.flush_interval
+            .is_some_and(|interval| interval > 0 && record_count.is_multiple_of(interval))
+    }
+
+    /// Returns the Zstd compression level and number of worker threads for DBZ
+    /// output.
+    pub(crate) fn zstd_settings(&self) -> (i32, u32) {
+        if !self.is_deterministic {
+            (self.compression_level, self.n_threads)
+        } else if self.compression_level == 0 {
+            (Self::DETERMINISTIC_COMPRESSION_LEVEL, 0)
+        } else {
+            (self.compression_level, 0)
+        }
+    }
+
+    /// Returns whether the Zstd frame of DBZ output should end after writing
+    /// `record_count` records.
+    pub(crate) fn should_end_frame(&self, record_count: usize) -> bool {
+        self.frame_record_count
+            .is_some_and(|count| count > 0 && record_count.is_multiple_of(count))
+    }
+}
+
+/// A buffered file for writing output to with the buffer size and sync behavior of
+/// [`WriterOptions`].
+#[derive(Debug)]
+pub struct OutputFile {
+    writer: BufWriter<File>,
+    should_sync_on_finish: bool,
+}
+
+impl OutputFile {
+    /// Wraps `file` in a buffer of `options.buffer_size` bytes.
+    pub fn new(file: File, options: &WriterOptions) -> Self {
+        Self {
+            writer: BufWriter::with_capacity(options.buffer_size, file),
+            should_sync_on_finish: options.should_sync_on_finish,
+        }
+    }
+
+    /// Flushes the buffer and, if [`WriterOptions::should_sync_on_finish`] was set,
+    /// syncs the contents of the file to disk. Unlike dropping the [`OutputFile`],
+    /// this reports any errors.
+    ///
+    /// # Errors
+    /// This function returns an error if flushing or syncing fails.
+    pub fn finish(mut self) -> io::Result<File> {
+        self.writer.flush()?;
+        let file = self
+            .writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        if self.should_sync_on_finish {
+            file.sync_all()?;
+        }
+        Ok(file)
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl Seek for OutputFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.writer.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn test_should_flush() {
+        let options = WriterOptions {
+            flush_interval: Some(3),
+            ..Default::default()
+        };
+        let flushes: Vec<_> = (1..=7).filter(|&i| options.should_flush(i)).collect();
+        assert_eq!(flushes, vec![3, 6]);
+        assert!(!WriterOptions::default().should_flush(1));
+        let options = WriterOptions {
+            flush_interval: Some(0),
+            ..Default::default()
+        };
+        assert!(!options.should_flush(1));
+    }
+
+    #[test]
+    fn test_output_file_finish() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("output.txt");
+        let options = WriterOptions {
+            buffer_size: 4,
+            should_sync_on_finish: true,
+            ..Default::default()
+        };
+        let mut target = OutputFile::new(File::create(&path).unwrap(), &options);
+        target.write_all(b"ab").unwrap();
+        // still buffered
+        assert!(std::fs::read(&path).unwrap().is_empty());
+        target.finish().unwrap();
+        let mut contents = String::new();
+        File::open(&path)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "ab");
+    }
+}
@@ -0,0 +1,408 @@
+//! Converting DBZ to text in a pipeline of stages on their own threads, so large
+//! conversions use more than one core.
+use std::{
+    io::{self, Read},
+    mem,
+    num::NonZeroUsize,
+    panic,
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread,
+};
+
+use anyhow::anyhow;
+use databento_defs::{
+    enums::Schema,
+    record::{Mbp10Msg, Mbp1Msg, OhlcvMsg, StatusMsg, SymDefMsg, TbboMsg, TickMsg, TradeMsg},
+};
+use streaming_iterator::StreamingIterator;
+
+use super::{write_text, OutputEncoding, OutputOptions, TextOptions};
+use crate::{fields::VisitFields, Dbz};
+
+/// The number of compressed bytes read from the input at a time.
+const READ_CHUNK_LEN: u64 = 1 << 20;
+
+/// Options for [`Dbz::write_to_pipelined`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PipelineOptions {
+    /// The number of threads encoding batches of records in parallel. 0 converts on
+    /// the calling thread without a pipeline.
+    pub encoder_threads: usize,
+    /// The number of records decoded into each batch sent to an encoder.
+    pub batch_size: usize,
+    /// The number of chunks or batches each queue between stages holds, bounding the
+    /// memory used by the pipeline.
+    pub queue_depth: usize,
+}
+
+impl Default for PipelineOptions {
+    /// Encodes on every core except the two reading and decoding, or on the calling
+    /// thread when there's only one core.
+    fn default() -> Self {
+        let cores = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        Self {
+            encoder_threads: if cores == 1 {
+                0
+            } else {
+                cores.saturating_sub(2).max(1)
+            },
+            batch_size: 4096,
+            queue_depth: 4,
+        }
+    }
+}
+
+impl<R: io::BufRead + Send> Dbz<R> {
+    /// Like [`Dbz::write_to_with_options`], but converts in a pipeline of stages
+    /// connected by bounded queues: one thread reads the compressed input, one
+    /// decompresses and decodes it into batches of records, `pipeline.encoder_threads`
+    /// threads each encode every nth batch, and the calling thread writes the encoded
+    /// batches to `writer` in their original order. The output is identical to
+    /// [`Dbz::write_to_with_options`].
+    ///
+    /// JSON array output and output flushed every `options.writer.flush_interval`
+    /// records are converted on the calling thread, as are all conversions when
+    /// `pipeline.encoder_threads` is 0.
+    ///
+    /// # Errors
+    /// This function returns an error for the same reasons as
+    /// [`Dbz::write_to_with_options`]. It will also return an error if there's an
+    /// issue reading the input.
+    pub fn write_to_pipelined(
+        self,
+        writer: impl io::Write,
+        encoding: OutputEncoding,
+        options: &OutputOptions,
+        pipeline: &PipelineOptions,
+    ) -> anyhow::Result<()> {
+        let is_json_array = matches!(
+            encoding,
+            OutputEncoding::Json {
+                should_output_array: true,
+                ..
+            }
+        );
+        let is_flushed = options.writer.flush_interval.is_some_and(|i| i > 0);
+        if pipeline.encoder_threads == 0 || is_json_array || is_flushed {
+            return self.write_to_with_options(writer, encoding, options);
+        }
+        match self.schema() {
+            Schema::Mbo => self.write_pipelined_of::<TickMsg>(writer, encoding, options, pipeline),
+            Schema::Mbp1 => self.write_pipelined_of::<Mbp1Msg>(writer, encoding, options, pipeline),
+            Schema::Mbp10 => {
+                self.write_pipelined_of::<Mbp10Msg>(writer, encoding, options, pipeline)
+            }
+            Schema::Tbbo => self.write_pipelined_of::<TbboMsg>(writer, encoding, options, pipeline),
+            Schema::Trades => {
+                self.write_pipelined_of::<TradeMsg>(writer, encoding, options, pipeline)
+            }
+            Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D => {
+                self.write_pipelined_of::<OhlcvMsg>(writer, encoding, options, pipeline)
+            }
+            Schema::Definition => {
+                self.write_pipelined_of::<SymDefMsg>(writer, encoding, options, pipeline)
+            }
+            Schema::Statistics => Err(anyhow!("Not implemented for schema Statistics")),
+            Schema::Status => {
+                self.write_pipelined_of::<StatusMsg>(writer, encoding, options, pipeline)
+            }
+        }
+    }
+
+    fn write_pipelined_of<T: VisitFields + Clone + Send>(
+        self,
+        mut writer: impl io::Write,
+        encoding: OutputEncoding,
+        options: &OutputOptions,
+        pipeline: &PipelineOptions,
+    ) -> anyhow::Result<()> {
+        // resolve the options up front so invalid options fail before any output, and
+        // only the first batch has a CSV header
+        let mut first_options = Some(TextOptions::new::<T>(options, self.metadata())?);
+        let rest_options = OutputOptions {
+            should_omit_csv_header: true,
+            ..options.clone()
+        };
+        let encoders = (0..pipeline.encoder_threads)
+            .map(|i| {
+                let text_options = TextOptions::new::<T>(&rest_options, self.metadata())?;
+                let first_options = if i == 0 { first_options.take() } else { None };
+                Ok((first_options, text_options))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let (chunk_sender, chunk_receiver) = mpsc::sync_channel(pipeline.queue_depth);
+        let (dbz, reader) = self.replace_reader(ChunkReader::new(chunk_receiver));
+        thread::scope(|scope| {
+            scope.spawn(move || read_chunks(reader, chunk_sender));
+            let mut batch_senders = Vec::with_capacity(encoders.len());
+            let mut encoded_receivers = Vec::with_capacity(encoders.len());
+            for (first_options, text_options) in encoders {
+                let (batch_sender, batch_receiver) = mpsc::sync_channel(pipeline.queue_depth);
+                let (encoded_sender, encoded_receiver) = mpsc::sync_channel(pipeline.queue_depth);
+                scope.spawn(move || {
+                    encode_batches(
+                        batch_receiver,
+                        encoded_sender,
+                        encoding,
+                        first_options,
+                        text_options,
+                    )
+                });
+                batch_senders.push(batch_sender);
+                encoded_receivers.push(encoded_receiver);
+            }
+            let batch_size = pipeline.batch_size.max(1);
+            let decoder =
+                scope.spawn(move || decode_batches::<_, T>(dbz, batch_senders, batch_size));
+            let res = write_batches(&mut writer, &encoded_receivers);
+            // stop the other stages if writing ended early
+            drop(encoded_receivers);
+            let decoded = decoder
+                .join()
+                .unwrap_or_else(|panic| panic::resume_unwind(panic));
+            res.and(decoded)
+        })
+    }
+}
+
+/// Reads `reader` in chunks and sends them to `sender` until it ends, fails, or the
+/// pipeline stops.
+fn read_chunks(mut reader: impl io::Read, sender: SyncSender<io::Result<Vec<u8>>>) {
+    loop {
+        let mut chunk = Vec::new();
+        match (&mut reader).take(READ_CHUNK_LEN).read_to_end(&mut chunk) {
+            Ok(0) => return,
+            Ok(_) => {
+                if sender.send(Ok(chunk)).is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                let _ = sender.send(Err(e));
+                return;
+            }
+        }
+    }
+}
+
+/// Decodes the records of `dbz` into batches of `batch_size` and sends the nth batch
+/// to encoder n modulo the number of encoders, so the batches can be collected in
+/// order. Returns the error that ended decoding early, if any, after sending the
+/// records before it.
+fn decode_batches<R: io::BufRead, T: VisitFields + Clone>(
+    dbz: Dbz<R>,
+    senders: Vec<SyncSender<Vec<T>>>,
+    batch_size: usize,
+) -> anyhow::Result<()> {
+    let mut records = dbz.try_into_iter::<T>()?;
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut batch_count = 0;
+    while let Some(record) = records.next() {
+        batch.push(record.clone());
+        if batch.len() == batch_size {
+            let batch = mem::replace(&mut batch, Vec::with_capacity(batch_size));
+            if senders[batch_count % senders.len()].send(batch).is_err() {
+                // writing ended early
+                return Ok(());
+            }
+            batch_count += 1;
+        }
+    }
+    // the first batch is always sent, so CSV output has a header even without records
+    if !batch.is_empty() || batch_count == 0 {
+        let _ = senders[batch_count % senders.len()].send(batch);
+    }
+    records.take_error().map_or(Ok(()), Err)
+}
+
+/// Encodes each batch received with the options of the encoder, using
+/// `first_options` for its first batch if given.
+fn encode_batches<T: VisitFields>(
+    receiver: Receiver<Vec<T>>,
+    sender: SyncSender<anyhow::Result<Vec<u8>>>,
+    encoding: OutputEncoding,
+    mut first_options: Option<TextOptions>,
+    text_options: TextOptions,
+) {
+    for batch in receiver {
+        let options = first_options.as_ref().unwrap_or(&text_options);
+        let mut encoded = Vec::new();
+        let res = write_text(
+            &mut encoded,
+            streaming_iterator::convert(batch),
+            encoding,
+            options,
+        )
+        .map(|_| encoded);
+        first_options = None;
+        if sender.send(res).is_err() {
+            // writing ended early
+            return;
+        }
+    }
+}
+
+/// Writes the encoded batches to `writer` in order, taking the nth batch from encoder
+/// n modulo the number of encoders.
+fn write_batches(
+    writer: &mut impl io::Write,
+    receivers: &[Receiver<anyhow::Result<Vec<u8>>>],
+) -> anyhow::Result<()> {
+    for i in 0.. {
+        // an encoder without another batch means all the batches were written
+        let Ok(encoded) = receivers[i % receivers.len()].recv() else {
+            break;
+        };
+        match writer.write_all(&encoded?) {
+            // broken output, likely a closed pipe
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            r => r?,
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// The compressed input as it's received from the reading stage.
+struct ChunkReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl ChunkReader {
+    fn new(receiver: Receiver<io::Result<Vec<u8>>>) -> Self {
+        Self {
+            receiver,
+            chunk: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl io::Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = io::BufRead::fill_buf(self)?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        io::BufRead::consume(self, len);
+        Ok(len)
+    }
+}
+
+impl io::BufRead for ChunkReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos == self.chunk.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                // the input ended
+                Err(_) => break,
+            }
+        }
+        Ok(&self.chunk[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{write::CharFormat, Error};
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+
+    fn assert_matches_sequential(schema: &str, encoding: OutputEncoding, options: &OutputOptions) {
+        let path = format!("{DBZ_PATH}/test_data.{schema}.dbz");
+        let mut expected = Vec::new();
+        Dbz::from_file(&path)
+            .unwrap()
+            .write_to_with_options(&mut expected, encoding, options)
+            .unwrap();
+        for (encoder_threads, batch_size) in [(1, 1), (3, 1), (2, 4096)] {
+            let pipeline = PipelineOptions {
+                encoder_threads,
+                batch_size,
+                queue_depth: 1,
+            };
+            let mut output = Vec::new();
+            Dbz::from_file(&path)
+                .unwrap()
+                .write_to_pipelined(&mut output, encoding, options, &pipeline)
+                .unwrap();
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                String::from_utf8(expected.clone()).unwrap(),
+                "{schema} with {pipeline:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_matches_sequential() {
+        let json = OutputEncoding::Json {
+            should_pretty_print: false,
+            should_output_array: false,
+            should_output_iso_timestamps: true,
+            should_output_decimal_prices: true,
+        };
+        let options = OutputOptions {
+            char_format: CharFormat::Char,
+            ..Default::default()
+        };
+        for schema in ["mbo", "mbp-1", "mbp-10", "ohlcv-1s", "tbbo", "trades"] {
+            assert_matches_sequential(schema, OutputEncoding::Csv, &OutputOptions::default());
+            assert_matches_sequential(schema, json, &options);
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let path = format!("{DBZ_PATH}/test_data.mbo.dbz");
+        let mut output = Vec::new();
+        Dbz::from_file(path)
+            .unwrap()
+            .filter_range(0, 1)
+            .write_to_pipelined(
+                &mut output,
+                OutputEncoding::Csv,
+                &OutputOptions::default(),
+                &PipelineOptions {
+                    encoder_threads: 2,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        // only the header
+        assert!(output.starts_with("rtype,"));
+        assert_eq!(output.lines().count(), 1);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let bytes = std::fs::read(format!("{DBZ_PATH}/test_data.mbo.dbz")).unwrap();
+        let mut output = Vec::new();
+        let error = Dbz::new(&bytes[..bytes.len() - 20])
+            .unwrap()
+            .write_to_pipelined(
+                &mut output,
+                OutputEncoding::Csv,
+                &OutputOptions::default(),
+                &PipelineOptions {
+                    encoder_threads: 2,
+                    ..Default::default()
+                },
+            )
+            .unwrap_err();
+        assert!(
+            matches!(Error::find(&error), Some(Error::Decode { .. })),
+            "{error:#}"
+        );
+    }
+}
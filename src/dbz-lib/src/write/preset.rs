@@ -0,0 +1,204 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use databento_defs::{enums::Schema, record::Mbp10Msg};
+
+use crate::fields::{VisitFields, LEVEL_FIELD_COUNT};
+
+/// A curated selection of columns for a common use case, to use as
+/// [`OutputOptions::columns`](crate::OutputOptions::columns).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnPreset {
+    /// Timestamps, product ID, and the best bid and offer.
+    Quote,
+    /// Timestamps, product ID, and the price, size, and aggressor side of each trade.
+    TradeTape,
+    /// All fields except `rtype` and `publisher_id`, with timestamps first.
+    Research,
+}
+
+impl ColumnPreset {
+    /// Returns the string representation of the preset.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ColumnPreset::Quote => "quote",
+            ColumnPreset::TradeTape => "trade-tape",
+            ColumnPreset::Research => "research",
+        }
+    }
+
+    /// Returns the columns of the preset for records of `schema`.
+    ///
+    /// # Errors
+    /// This function returns an error if the preset isn't applicable to `schema`.
+    pub fn columns(&self, schema: Schema) -> anyhow::Result<Vec<String>> {
+        self.columns_with_levels(schema, None)
+    }
+
+    /// Returns the columns of the preset for records of `schema` with at most
+    /// `max_levels` book levels, to use along with
+    /// [`OutputOptions::levels`](crate::OutputOptions::levels).
+    ///
+    /// # Errors
+    /// This function returns an error if the preset isn't applicable to `schema`.
+    pub fn columns_with_levels(
+        &self,
+        schema: Schema,
+        max_levels: Option<usize>,
+    ) -> anyhow::Result<Vec<String>> {
+        const QUOTE: &[&str] = &[
+            "ts_recv",
+            "ts_event",
+            "product_id",
+            "bid_px_00",
+            "bid_sz_00",
+            "ask_px_00",
+            "ask_sz_00",
+        ];
+        const TRADE_TAPE: &[&str] = &["ts_recv", "ts_event", "product_id", "price", "size", "side"];
+        const RESEARCH_MBO: &[&str] = &[
+            "ts_recv",
+            "ts_event",
+            "ts_in_delta",
+            "product_id",
+            "order_id",
+            "action",
+            "side",
+            "price",
+            "size",
+            "flags",
+            "channel_id",
+            "sequence",
+        ];
+        const RESEARCH_MBP: &[&str] = &[
+            "ts_recv",
+            "ts_event",
+            "ts_in_delta",
+            "product_id",
+            "action",
+            "side",
+            "price",
+            "size",
+            "flags",
+            "depth",
+            "sequence",
+        ];
+        const RESEARCH_OHLCV: &[&str] = &[
+            "ts_event",
+            "product_id",
+            "open",
+            "high",
+            "low",
+            "close",
+            "volume",
+        ];
+        // book levels follow the common fields in the headers
+        let levels = |count: usize| {
+            let count = max_levels.map_or(count, |max| count.min(max));
+            let start = Mbp10Msg::HEADERS.len() - Mbp10Msg::LEVEL_COUNT * LEVEL_FIELD_COUNT;
+            Mbp10Msg::HEADERS[start..start + LEVEL_FIELD_COUNT * count].iter()
+        };
+
+        let columns: Vec<&str> = match (self, schema) {
+            (ColumnPreset::Quote, Schema::Mbp1 | Schema::Tbbo | Schema::Mbp10) => QUOTE.to_vec(),
+            (ColumnPreset::TradeTape, Schema::Trades | Schema::Tbbo) => TRADE_TAPE.to_vec(),
+            (ColumnPreset::Research, Schema::Mbo) => RESEARCH_MBO.to_vec(),
+            (ColumnPreset::Research, Schema::Trades) => RESEARCH_MBP.to_vec(),
+            (ColumnPreset::Research, Schema::Mbp1 | Schema::Tbbo) => {
+                RESEARCH_MBP.iter().chain(levels(1)).copied().collect()
+            }
+            (ColumnPreset::Research, Schema::Mbp10) => {
+                RESEARCH_MBP.iter().chain(levels(10)).copied().collect()
+            }
+            (
+                ColumnPreset::Research,
+                Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D,
+            ) => RESEARCH_OHLCV.to_vec(),
+            (preset, schema) => {
+                return Err(anyhow!(
+                    "The {} preset isn't available for schema {}",
+                    preset.as_str(),
+                    schema.as_str()
+                ))
+            }
+        };
+        Ok(columns.into_iter().map(str::to_owned).collect())
+    }
+}
+
+impl FromStr for ColumnPreset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "quote" => Ok(ColumnPreset::Quote),
+            "trade-tape" => Ok(ColumnPreset::TradeTape),
+            "research" => Ok(ColumnPreset::Research),
+            _ => Err(anyhow!("Unknown column preset '{s}'")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use databento_defs::record::{Mbp1Msg, OhlcvMsg, TickMsg, TradeMsg};
+
+    use super::*;
+
+    fn assert_valid<T: VisitFields>(preset: ColumnPreset, schema: Schema) {
+        for column in preset.columns(schema).unwrap() {
+            assert!(
+                T::HEADERS.contains(&column.as_str()),
+                "{column} isn't a column of {}",
+                schema.as_str()
+            );
+        }
+    }
+
+    #[test]
+    fn test_columns_are_valid() {
+        assert_valid::<Mbp1Msg>(ColumnPreset::Quote, Schema::Mbp1);
+        assert_valid::<Mbp10Msg>(ColumnPreset::Quote, Schema::Mbp10);
+        assert_valid::<TradeMsg>(ColumnPreset::TradeTape, Schema::Trades);
+        assert_valid::<Mbp1Msg>(ColumnPreset::TradeTape, Schema::Tbbo);
+        assert_valid::<TickMsg>(ColumnPreset::Research, Schema::Mbo);
+        assert_valid::<TradeMsg>(ColumnPreset::Research, Schema::Trades);
+        assert_valid::<Mbp1Msg>(ColumnPreset::Research, Schema::Mbp1);
+        assert_valid::<Mbp10Msg>(ColumnPreset::Research, Schema::Mbp10);
+        assert_valid::<OhlcvMsg>(ColumnPreset::Research, Schema::Ohlcv1D);
+    }
+
+    #[test]
+    fn test_research_mbp10_includes_all_levels() {
+        let columns = ColumnPreset::Research.columns(Schema::Mbp10).unwrap();
+        assert_eq!(columns.last().unwrap(), "ask_ct_09");
+        assert_eq!(columns.len(), 11 + 60);
+    }
+
+    #[test]
+    fn test_research_mbp10_with_levels() {
+        let columns = ColumnPreset::Research
+            .columns_with_levels(Schema::Mbp10, Some(2))
+            .unwrap();
+        assert_eq!(columns.last().unwrap(), "ask_ct_01");
+        assert_eq!(columns.len(), 11 + 12);
+    }
+
+    #[test]
+    fn test_unavailable_preset() {
+        assert!(ColumnPreset::Quote.columns(Schema::Trades).is_err());
+        assert!(ColumnPreset::TradeTape.columns(Schema::Mbo).is_err());
+    }
+
+    #[test]
+    fn test_from_str() {
+        for preset in [
+            ColumnPreset::Quote,
+            ColumnPreset::TradeTape,
+            ColumnPreset::Research,
+        ] {
+            assert_eq!(preset.as_str().parse::<ColumnPreset>().unwrap(), preset);
+        }
+        assert!("tape".parse::<ColumnPreset>().is_err());
+    }
+}
@@ -0,0 +1,317 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context;
+use databento_defs::record::ConstTypeId;
+use flate2::write::GzEncoder;
+
+use super::{
+    dbz::{as_u8_slice, DbzWriter},
+    OutputFile, WriterOptions,
+};
+use crate::{read::FromLittleEndianSlice, Metadata};
+
+/// When [`RotatingDbzWriter`] completes a file and starts the next one, and what's
+/// done with completed files.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RotationOptions {
+    /// Start a new file before a record would grow the records of the current one
+    /// past `max_file_size` bytes before compression. A file always holds at least
+    /// one record.
+    pub max_file_size: Option<u64>,
+    /// Start a new file for every `interval` of the records' `ts_event`s, aligned to
+    /// the UNIX epoch, e.g. 1 hour for hourly files. Records with a `ts_event` before
+    /// the interval of the current file are written to the current file.
+    pub interval: Option<Duration>,
+    /// Whether to gzip completed files, replacing each file with one of the same name
+    /// ending in `.gz`.
+    pub should_gzip: bool,
+    /// The options of the [`DbzWriter`] of each file.
+    pub writer_options: WriterOptions,
+}
+
+/// Writes records to a series of DBZ files, starting a new file once the current one
+/// reaches the size or time thresholds of [`RotationOptions`], for long-running
+/// capture services. The metadata of each file is finalized with its own record
+/// count and time range when it's completed.
+///
+/// Files are only created once there's a record to write to them. Records must be
+/// written in `ts_event` order for time-based rotation to be meaningful.
+pub struct RotatingDbzWriter<F> {
+    metadata: Metadata,
+    options: RotationOptions,
+    path: F,
+    current: Option<CurrentFile>,
+}
+
+struct CurrentFile {
+    writer: DbzWriter<OutputFile>,
+    path: PathBuf,
+    /// The number of bytes of records written before compression.
+    size: u64,
+    /// The index of the interval since the UNIX epoch, if rotating by time.
+    interval: Option<u64>,
+}
+
+impl<F> RotatingDbzWriter<F>
+where
+    F: FnMut(&Metadata) -> PathBuf,
+{
+    /// Creates a new writer of files with `metadata`. `path` is called with the
+    /// metadata of each new file to get its path, with `start` set to the start of
+    /// its interval if rotating by time, otherwise the `ts_event` of its first
+    /// record.
+    pub fn new(metadata: Metadata, options: RotationOptions, path: F) -> Self {
+        Self {
+            metadata,
+            options,
+            path,
+            current: None,
+        }
+    }
+
+    /// Encodes `record`, first completing the current file if `record` crosses one of
+    /// the thresholds. Returns the path of the completed file, if any.
+    ///
+    /// # Errors
+    /// This function returns an error if there's an issue completing the current file,
+    /// creating the next one, or writing to it.
+    pub fn write_record<T: ConstTypeId + Sized>(
+        &mut self,
+        record: &T,
+    ) -> anyhow::Result<Option<PathBuf>> {
+        let bytes = unsafe {
+            // Safety: all records, types implementing `ConstTypeId` are POD
+            as_u8_slice(record)
+        };
+        let ts_event = u64::from_le_slice(&bytes[DbzWriter::<OutputFile>::TS_EVENT_OFFSET..]);
+        let interval = self.interval_nanos().map(|nanos| ts_event / nanos);
+        let completed = match self.current {
+            Some(ref file) if self.should_rotate(file, bytes.len() as u64, interval) => {
+                self.rotate()?
+            }
+            _ => None,
+        };
+        if self.current.is_none() {
+            self.current = Some(self.create(ts_event, interval)?);
+        }
+        let file = self.current.as_mut().expect("created above");
+        file.writer.write_record_bytes(bytes)?;
+        file.size += bytes.len() as u64;
+        Ok(completed)
+    }
+
+    /// Flushes the current file so the records written so far can be decoded.
+    ///
+    /// # Errors
+    /// This function returns an error if there's an issue writing to the file.
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        match self.current {
+            Some(ref mut file) => file.writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Completes the current file regardless of the thresholds, so the next record
+    /// starts a new file. Returns the path of the completed file, or `None` if no
+    /// records were written since the last file was completed.
+    ///
+    /// # Errors
+    /// This function returns an error if there's an issue finalizing the metadata of
+    /// the file or gzipping it.
+    pub fn rotate(&mut self) -> anyhow::Result<Option<PathBuf>> {
+        let Some(file) = self.current.take() else {
+            return Ok(None);
+        };
+        file.writer
+            .finish()?
+            .finish()
+            .with_context(|| format!("Failed to finish {}", file.path.display()))?;
+        if self.options.should_gzip {
+            gzip(&file.path, &self.options.writer_options).map(Some)
+        } else {
+            Ok(Some(file.path))
+        }
+    }
+
+    /// Completes the current file like [`RotatingDbzWriter::rotate`]. Dropping the
+    /// writer without calling this leaves the metadata of the current file without
+    /// its record count and time range.
+    ///
+    /// # Errors
+    /// This function returns an error if there's an issue finalizing the metadata of
+    /// the file or gzipping it.
+    pub fn finish(mut self) -> anyhow::Result<Option<PathBuf>> {
+        self.rotate()
+    }
+
+    fn interval_nanos(&self) -> Option<u64> {
+        self.options
+            .interval
+            .map(|interval| (interval.as_nanos() as u64).max(1))
+    }
+
+    fn should_rotate(&self, file: &CurrentFile, record_len: u64, interval: Option<u64>) -> bool {
+        self.options
+            .max_file_size
+            .is_some_and(|max_size| file.size + record_len > max_size)
+            || interval > file.interval
+    }
+
+    fn create(&mut self, ts_event: u64, interval: Option<u64>) -> anyhow::Result<CurrentFile> {
+        let mut metadata = self.metadata.clone();
+        metadata.record_count = 0;
+        match (interval, self.interval_nanos()) {
+            (Some(interval), Some(nanos)) => {
+                metadata.start = interval * nanos;
+                metadata.end = metadata.start.saturating_add(nanos);
+            }
+            _ => metadata.start = ts_event,
+        }
+        let path = (self.path)(&metadata);
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create DBZ file at {}", path.display()))?;
+        let writer = DbzWriter::with_options(
+            OutputFile::new(file, &self.options.writer_options),
+            &metadata,
+            &self.options.writer_options,
+        )?;
+        Ok(CurrentFile {
+            writer,
+            path,
+            size: 0,
+            interval,
+        })
+    }
+}
+
+/// Replaces the file at `path` with a gzipped copy whose path ends in `.gz`, which is
+/// returned.
+fn gzip(path: &Path, options: &WriterOptions) -> anyhow::Result<PathBuf> {
+    let mut gz_path = OsString::from(path.as_os_str());
+    gz_path.push(".gz");
+    let gz_path = PathBuf::from(gz_path);
+    let mut input =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let output = File::create(&gz_path)
+        .with_context(|| format!("Failed to create {}", gz_path.display()))?;
+    let mut encoder = GzEncoder::new(
+        OutputFile::new(output, options),
+        flate2::Compression::default(),
+    );
+    io::copy(&mut input, &mut encoder)
+        .with_context(|| format!("Failed to gzip {}", path.display()))?;
+    encoder.flush()?;
+    encoder.finish()?.finish()?;
+    fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    Ok(gz_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use databento_defs::record::TradeMsg;
+    use flate2::read::GzDecoder;
+    use streaming_iterator::StreamingIterator;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::Dbz;
+
+    const DBZ_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/data");
+    const NANOS_PER_HOUR: u64 = 3_600_000_000_000;
+
+    fn trades(count: u64) -> (Metadata, Vec<TradeMsg>) {
+        let dbz = Dbz::from_file(format!("{DBZ_PATH}/test_data.trades.dbz")).unwrap();
+        let metadata = dbz.metadata().clone();
+        let template = dbz
+            .try_into_iter::<TradeMsg>()
+            .unwrap()
+            .next()
+            .unwrap()
+            .clone();
+        let records = (0..count)
+            .map(|i| {
+                let mut record = template.clone();
+                record.hd.ts_event = 10 * NANOS_PER_HOUR + i * NANOS_PER_HOUR / 2;
+                record
+            })
+            .collect();
+        (metadata, records)
+    }
+
+    fn write(dir: &Path, options: RotationOptions, records: &[TradeMsg]) -> Vec<PathBuf> {
+        let (metadata, _) = trades(0);
+        let mut index = 0;
+        let mut target = RotatingDbzWriter::new(metadata, options, |_: &Metadata| {
+            index += 1;
+            dir.join(format!("{index}.dbz"))
+        });
+        let mut completed = Vec::new();
+        for record in records {
+            completed.extend(target.write_record(record).unwrap());
+        }
+        completed.extend(target.finish().unwrap());
+        completed
+    }
+
+    #[test]
+    fn test_rotate_by_interval_and_size() {
+        let dir = tempdir().unwrap();
+        let (_, records) = trades(5);
+        let record_size = std::mem::size_of::<TradeMsg>() as u64;
+        let completed = write(
+            dir.path(),
+            RotationOptions {
+                max_file_size: Some(3 * record_size),
+                interval: Some(Duration::from_secs(3600 * 2)),
+                ..Default::default()
+            },
+            &records,
+        );
+        // records at hours 10, 10.5, 11, 11.5, and 12
+        let counts: Vec<_> = completed
+            .iter()
+            .map(|path| {
+                let dbz = Dbz::from_file(path).unwrap();
+                let metadata = dbz.metadata().clone();
+                assert_eq!(
+                    dbz.try_into_iter::<TradeMsg>().unwrap().count() as u64,
+                    metadata.record_count
+                );
+                (metadata.record_count, metadata.start / (NANOS_PER_HOUR / 2))
+            })
+            .collect();
+        assert_eq!(counts, vec![(3, 20), (1, 23), (1, 24)]);
+    }
+
+    #[test]
+    fn test_rotate_gzip() {
+        let dir = tempdir().unwrap();
+        let (_, records) = trades(3);
+        let completed = write(
+            dir.path(),
+            RotationOptions {
+                max_file_size: Some(1),
+                should_gzip: true,
+                ..Default::default()
+            },
+            &records,
+        );
+        assert_eq!(completed.len(), 3);
+        for (path, record) in completed.iter().zip(records.iter()) {
+            assert_eq!(path.extension().unwrap(), "gz");
+            assert!(!path.with_extension("").exists());
+            let reader = BufReader::new(GzDecoder::new(File::open(path).unwrap()));
+            let dbz = Dbz::new(reader).unwrap();
+            assert_eq!(dbz.metadata().start, record.hd.ts_event);
+            assert_eq!(dbz.metadata().record_count, 1);
+        }
+    }
+}
@@ -9,5 +9,10 @@ fn dbz_python(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
     m.add_wrapped(wrap_pyfunction!(dbz_lib::python::encode_metadata))?;
     m.add_wrapped(wrap_pyfunction!(dbz_lib::python::update_encoded_metadata))?;
     m.add_wrapped(wrap_pyfunction!(dbz_lib::python::write_dbz_file))?;
+    m.add_wrapped(wrap_pyfunction!(dbz_lib::python::validate_records))?;
+    m.add_wrapped(wrap_pyfunction!(dbz_lib::python::mappings_from_symbology))?;
+    m.add_wrapped(wrap_pyfunction!(dbz_lib::python::mappings_to_symbology))?;
+    m.add_wrapped(wrap_pyfunction!(dbz_lib::python::get_range))?;
+    m.add_wrapped(wrap_pyfunction!(dbz_lib::python::filter_product_ids))?;
     Ok(())
 }